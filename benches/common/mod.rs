@@ -0,0 +1,128 @@
+//! GeoJSON synthesized for the benchmarks, the same on every run, so they
+//! need no large fixture files in the repository.
+
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, Value};
+
+/// The size and complexity of the features of `feature_collection`.
+///
+/// * `features`: How many features the collection holds.
+/// * `polygons`: The polygons of the MultiPolygon of each feature.
+/// * `vertices`: The vertices of the outer ring of each polygon, which
+///   also has a hole of half as many.
+/// * `properties`: The properties of each feature, strings and numbers in
+///   turn, some holding commas and quotes to escape.
+/// * `seed`: Where the coordinates start from, for several distinct
+///   collections of the same shape.
+#[derive(Debug, Clone, Copy)]
+pub struct FixtureSpec {
+    pub features: usize,
+    pub polygons: usize,
+    pub vertices: usize,
+    pub properties: usize,
+    pub seed: u64,
+}
+
+impl Default for FixtureSpec {
+    fn default() -> Self {
+        Self {
+            features: 1_000,
+            polygons: 2,
+            vertices: 32,
+            properties: 8,
+            seed: 42,
+        }
+    }
+}
+
+/// A linear congruential generator, enough for coordinates looking random
+/// without a dependency.
+struct Lcg(u64);
+
+impl Lcg {
+    /// The next number, in [0, 1).
+    fn next(&mut self) -> f64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A closed ring of `vertices` vertices around `(x, y)`, with a jittered
+/// radius and coordinates of the precision of real data.
+fn ring(rng: &mut Lcg, x: f64, y: f64, radius: f64, vertices: usize) -> Vec<Vec<f64>> {
+    let mut ring: Vec<Vec<f64>> = (0..vertices)
+        .map(|i| {
+            let angle = i as f64 / vertices as f64 * std::f64::consts::TAU;
+            let radius = radius * (0.8 + 0.4 * rng.next());
+            vec![x + radius * angle.cos(), y + radius * angle.sin()]
+        })
+        .collect();
+    ring.push(ring[0].clone());
+    ring
+}
+
+/// A MultiPolygon of `spec.polygons` polygons with a hole each, as counties
+/// or parcels have.
+pub fn multipolygon(spec: &FixtureSpec, rng_seed: u64) -> Geometry {
+    let mut rng = Lcg(rng_seed);
+    let polygons = (0..spec.polygons)
+        .map(|_| {
+            let x = -180.0 + 360.0 * rng.next();
+            let y = -80.0 + 160.0 * rng.next();
+            vec![
+                ring(&mut rng, x, y, 0.5, spec.vertices.max(3)),
+                ring(&mut rng, x, y, 0.1, (spec.vertices / 2).max(3)),
+            ]
+        })
+        .collect();
+    Geometry::new(Value::MultiPolygon(polygons))
+}
+
+/// The features of `spec`, with their geometry and properties.
+pub fn features(spec: &FixtureSpec) -> Vec<Feature> {
+    (0..spec.features)
+        .map(|i| {
+            let mut properties = JsonObject::new();
+            for p in 0..spec.properties {
+                let value = if p % 2 == 0 {
+                    serde_json::json!(format!("value {}, \"{}\" of feature {}", p, p, i))
+                } else {
+                    serde_json::json!(i * spec.properties + p)
+                };
+                properties.insert(format!("property_{}", p), value);
+            }
+            Feature {
+                bbox: None,
+                geometry: Some(multipolygon(spec, spec.seed.wrapping_add(i as u64))),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            }
+        })
+        .collect()
+}
+
+/// The FeatureCollection of `spec`, as the text of a GeoJSON file.
+pub fn feature_collection(spec: &FixtureSpec) -> String {
+    FeatureCollection {
+        bbox: None,
+        features: features(spec),
+        foreign_members: None,
+    }
+    .to_string()
+}
+
+/// Write the FeatureCollection of `spec` to a file of the temp directory
+/// named after its shape, reused when it already exists, and return its path.
+pub fn fixture_file(spec: &FixtureSpec) -> String {
+    let path = std::env::temp_dir().join(format!(
+        "pgc_bench_{}f_{}p_{}v_{}props_{}.geojson",
+        spec.features, spec.polygons, spec.vertices, spec.properties, spec.seed
+    ));
+    if !path.exists() {
+        std::fs::write(&path, feature_collection(spec)).expect("the fixture should be written");
+    }
+    path.display().to_string()
+}
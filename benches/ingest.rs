@@ -0,0 +1,191 @@
+//! The steps of loading GeoJSON, to compare their speed before and after a
+//! change: `cargo bench --bench ingest`.
+//!
+//! The COPY into Postgres, and the same COPY split over 1, 2 and 4
+//! connections by `parallel_copy`, only run with `PGC_BENCH_CONFIG` naming
+//! the config file of a database it may create the `pgc_bench` table in.
+
+mod common;
+
+use common::FixtureSpec;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use postgres_connect_rust::{
+    feature_csv_line, for_each_geojson_feature_in, geometry_to_wkt, write_wkt, ConfigFile,
+    DatabaseQueriesWrite, IngestOptions, InsertGeoJsonOptions, LoadMode, PostgresQueriesWrite,
+};
+
+/// The table the COPY benchmark loads, replaced by every iteration.
+const BENCH_TABLE: &str = "pgc_bench";
+
+fn bench_geometry_to_wkt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("geometry_to_wkt");
+    for (label, polygons, vertices) in [
+        ("parcel", 1, 16),
+        ("county", 4, 256),
+        ("country", 20, 2_000),
+    ] {
+        let spec = FixtureSpec {
+            polygons,
+            vertices,
+            ..FixtureSpec::default()
+        };
+        let geometry = common::multipolygon(&spec, spec.seed);
+        // Both rings of each polygon, closed
+        let coordinates = polygons * (vertices + 1 + vertices / 2 + 1);
+        group.throughput(Throughput::Elements(coordinates as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(label),
+            &geometry,
+            |b, geometry| b.iter(|| geometry_to_wkt(black_box(geometry)).expect("valid geometry")),
+        );
+        // The same buffer for every geometry, as a load of many would
+        group.bench_with_input(
+            BenchmarkId::new("write_wkt", label),
+            &geometry,
+            |b, geometry| {
+                let mut wkt = String::new();
+                b.iter(|| {
+                    wkt.clear();
+                    write_wkt(black_box(geometry), None, &mut wkt).expect("valid geometry")
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_csv_lines(c: &mut Criterion) {
+    let spec = FixtureSpec::default();
+    let rows: Vec<(String, serde_json::Value, String)> = common::features(&spec)
+        .into_iter()
+        .enumerate()
+        .map(|(i, feature)| {
+            let wkt = geometry_to_wkt(feature.geometry.as_ref().expect("generated geometry"))
+                .expect("valid geometry");
+            let properties = serde_json::to_value(&feature.properties).expect("properties");
+            (format!("feature_{}", i), properties, wkt)
+        })
+        .collect();
+    let mut group = c.benchmark_group("csv_lines");
+    group.throughput(Throughput::Elements(rows.len() as u64));
+    group.bench_function("feature_csv_line", |b| {
+        b.iter(|| {
+            for (name, properties, wkt) in &rows {
+                black_box(feature_csv_line(
+                    name,
+                    properties,
+                    Some(wkt),
+                    Some(4326),
+                    None,
+                ));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_streaming_parse(c: &mut Criterion) {
+    let spec = FixtureSpec {
+        features: 100_000,
+        polygons: 1,
+        vertices: 8,
+        properties: 4,
+        ..FixtureSpec::default()
+    };
+    let collection = common::feature_collection(&spec);
+    let mut group = c.benchmark_group("streaming_parse");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(collection.len() as u64));
+    group.bench_function("100k_features", |b| {
+        b.iter(|| {
+            let mut count = 0;
+            for_each_geojson_feature_in(collection.as_bytes(), |_, feature| {
+                black_box(feature);
+                count += 1;
+                Ok(())
+            })
+            .expect("valid GeoJSON");
+            assert_eq!(count, spec.features);
+        })
+    });
+    group.finish();
+}
+
+fn bench_copy(c: &mut Criterion) {
+    let Ok(config_filename) = std::env::var("PGC_BENCH_CONFIG") else {
+        eprintln!("Skipped copy: set PGC_BENCH_CONFIG to a config file to run it");
+        return;
+    };
+    let config_file = ConfigFile::load(&config_filename)
+        .unwrap_or_else(|e| panic!("PGC_BENCH_CONFIG {} should load: {}", config_filename, e));
+    let write_queries = PostgresQueriesWrite::from_config_file(config_file).expect("the pool");
+    let runtime = tokio::runtime::Runtime::new().expect("a runtime");
+    let spec = FixtureSpec {
+        features: 10_000,
+        ..FixtureSpec::default()
+    };
+    let path = common::fixture_file(&spec);
+
+    let mut group = c.benchmark_group("copy");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(spec.features as u64));
+    group.bench_function("insert_geojson_10k", |b| {
+        b.iter(|| {
+            let options = InsertGeoJsonOptions::new()
+                .table(BENCH_TABLE)
+                .mode(LoadMode::Replace)
+                .build();
+            runtime
+                .block_on(write_queries.insert_geojson(&path, Some(options)))
+                .expect("insert_geojson")
+        })
+    });
+    group.finish();
+
+    // One file big enough for the COPY to be bound by the server, split
+    // over 1, 2 and 4 connections
+    let spec = FixtureSpec {
+        features: 100_000,
+        polygons: 1,
+        vertices: 16,
+        ..FixtureSpec::default()
+    };
+    let path = common::fixture_file(&spec);
+    let mut group = c.benchmark_group("parallel_copy");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(spec.features as u64));
+    for workers in [1, 2, 4] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(workers),
+            &workers,
+            |b, &workers| {
+                b.iter(|| {
+                    let options = InsertGeoJsonOptions::new()
+                        .table(BENCH_TABLE)
+                        .mode(LoadMode::Replace)
+                        .ingest_options(IngestOptions {
+                            parallel_copy: workers,
+                            ..IngestOptions::default()
+                        })
+                        .build();
+                    runtime
+                        .block_on(write_queries.insert_geojson(&path, Some(options)))
+                        .expect("insert_geojson")
+                })
+            },
+        );
+    }
+    group.finish();
+    runtime
+        .block_on(write_queries.drop(BENCH_TABLE))
+        .expect("drop the bench table");
+}
+
+criterion_group!(
+    benches,
+    bench_geometry_to_wkt,
+    bench_csv_lines,
+    bench_streaming_parse,
+    bench_copy
+);
+criterion_main!(benches);
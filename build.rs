@@ -0,0 +1,10 @@
+use std::error::Error as StdError;
+use vergen_gitcl::{Emitter, GitclBuilder};
+
+/// Set `VERGEN_GIT_SHA` to the commit pgc is built from, for `pgc info`.
+/// Out of a git checkout it is set to a placeholder, with a warning.
+fn main() -> Result<(), Box<dyn StdError>> {
+    let gitcl = GitclBuilder::default().sha(true).dirty(false).build()?;
+    Emitter::default().add_instructions(&gitcl)?.emit()?;
+    Ok(())
+}
@@ -0,0 +1,177 @@
+//! The operations most called from synchronous code, blocking until they
+//! are done, built with the `blocking` feature.
+//!
+//! The async methods of `PostgresQueriesRead` and `PostgresQueriesWrite`
+//! are the API of the crate, with all of its operations and options; these
+//! functions only run some of them on a current-thread runtime of their
+//! own, created by the first call, for the code that has no runtime. Each
+//! call connects with `config` and closes its connections when done, so
+//! code making many calls, or already running in a runtime, should use the
+//! async API instead: called from a runtime, these functions return a
+//! `Usage` error rather than blocking it.
+//!
+//! ```no_run
+//! use postgres_connect_rust::{blocking, Config, InsertGeoJsonOptions};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let config = Config::load("files/config.toml")?;
+//! let report = blocking::insert_geojson(
+//!     &config,
+//!     "data/parcels.geojson",
+//!     Some(InsertGeoJsonOptions::new().table("parcels").build()),
+//! )?;
+//! report.print_summary();
+//! println!("{:?}", blocking::list_tables(&config)?);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::read::config::Config;
+use crate::read::queries::{DatabaseQueriesRead, PostgresQueriesRead};
+use crate::read::reports::{ColumnInfo, ExportReport};
+use crate::utils::error::PgcError;
+use crate::write::options::{BackupOptions, ExportOptions, InsertGeoJsonOptions, RestoreOptions};
+use crate::write::queries::{DatabaseQueriesWrite, PostgresQueriesWrite};
+use crate::write::reports::{IngestError, IngestReport};
+use std::future::Future;
+use std::sync::OnceLock;
+use tokio::runtime::{Builder, Handle, Runtime};
+
+/// The runtime of the functions of this module, see `block_on`.
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// Run `future` to completion on the runtime of this module, creating it
+/// on the first call. Refused from a runtime, which blocking would stall,
+/// or panic when it is a current-thread one.
+fn block_on<F: Future>(future: F) -> Result<F::Output, PgcError> {
+    if Handle::try_current().is_ok() {
+        return Err(PgcError::Usage {
+            message: "❌ The functions of postgres_connect_rust::blocking cannot run in an async \
+                      runtime: await the methods of PostgresQueriesRead and PostgresQueriesWrite \
+                      instead"
+                .to_string(),
+        });
+    }
+    let runtime = match RUNTIME.get() {
+        Some(runtime) => runtime,
+        None => {
+            let runtime = Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| PgcError::Other(Box::new(e)))?;
+            // Another thread may have created one meanwhile, kept over this one
+            RUNTIME.get_or_init(|| runtime)
+        }
+    };
+    Ok(runtime.block_on(async {
+        let output = future.await;
+        // Lets the tasks of the connections dropped with the queries close them
+        tokio::task::yield_now().await;
+        output
+    }))
+}
+
+/// Run `operation` on the read queries of `config`.
+fn with_read_queries<T, F, Fut>(config: &Config, operation: F) -> Result<T, PgcError>
+where
+    F: FnOnce(PostgresQueriesRead) -> Fut,
+    Fut: Future<Output = Result<T, PgcError>>,
+{
+    let read_queries = PostgresQueriesRead::from_config(config.clone())?;
+    block_on(operation(read_queries))?
+}
+
+/// Run `operation` on the write queries of `config`.
+fn with_write_queries<T, F, Fut>(config: &Config, operation: F) -> Result<T, PgcError>
+where
+    F: FnOnce(PostgresQueriesWrite) -> Fut,
+    Fut: Future<Output = Result<T, PgcError>>,
+{
+    let write_queries = PostgresQueriesWrite::from_config(config.clone())?;
+    block_on(operation(write_queries))?
+}
+
+/// `PostgresQueriesWrite::insert_geojson`, blocking.
+// The error of the method, with the report of what was loaded
+#[allow(clippy::result_large_err)]
+pub fn insert_geojson(
+    config: &Config,
+    geojson_path: &str,
+    options: Option<InsertGeoJsonOptions>,
+) -> Result<IngestReport, IngestError> {
+    // Failing before loading anything, with an empty report
+    let failed = |source: PgcError| IngestError {
+        report: IngestReport::default(),
+        source,
+    };
+    let write_queries = PostgresQueriesWrite::from_config(config.clone()).map_err(failed)?;
+    block_on(async move { write_queries.insert_geojson(geojson_path, options).await })
+        .map_err(failed)?
+}
+
+/// `PostgresQueriesRead::list_tables`, blocking.
+pub fn list_tables(config: &Config) -> Result<Vec<String>, PgcError> {
+    with_read_queries(config, |read_queries| async move {
+        read_queries.list_tables(None).await
+    })
+}
+
+/// `PostgresQueriesRead::list_columns`, blocking.
+pub fn list_columns(config: &Config, table_name: &str) -> Result<Vec<ColumnInfo>, PgcError> {
+    with_read_queries(config, |read_queries| async move {
+        read_queries.list_columns(table_name).await
+    })
+}
+
+/// `PostgresQueriesRead::table_row_count`, blocking.
+pub fn table_row_count(config: &Config, table_name: &str) -> Result<i64, PgcError> {
+    with_read_queries(config, |read_queries| async move {
+        read_queries.table_row_count(table_name, None).await
+    })
+}
+
+/// `PostgresQueriesRead::export_geojson`, blocking.
+pub fn export_geojson(
+    config: &Config,
+    table_name: &str,
+    output_path: &str,
+    options: Option<ExportOptions>,
+) -> Result<ExportReport, PgcError> {
+    with_read_queries(config, |read_queries| async move {
+        read_queries
+            .export_geojson(table_name, output_path, options)
+            .await
+    })
+}
+
+/// `PostgresQueriesWrite::drop`, blocking.
+pub fn drop(config: &Config, table_name: &str) -> Result<(), PgcError> {
+    with_write_queries(config, |write_queries| async move {
+        write_queries.drop(table_name).await
+    })
+}
+
+/// `PostgresQueriesWrite::backup_database`, blocking. Returns the path of
+/// the backup.
+#[cfg(feature = "backup")]
+pub fn backup_database(
+    config: &Config,
+    output_dir: &str,
+    options: Option<BackupOptions>,
+) -> Result<String, PgcError> {
+    with_write_queries(config, |write_queries| async move {
+        write_queries.backup_database(output_dir, options).await
+    })
+}
+
+/// `PostgresQueriesWrite::restore_database`, blocking.
+#[cfg(feature = "backup")]
+pub fn restore_database(
+    config: &Config,
+    dump_file: &str,
+    options: Option<RestoreOptions>,
+) -> Result<(), PgcError> {
+    with_write_queries(config, |write_queries| async move {
+        write_queries.restore_database(dump_file, options).await
+    })
+}
@@ -0,0 +1,111 @@
+use super::Cli;
+use crate::read::args;
+use crate::read::config::Config;
+use crate::read::queries::{DatabaseQueriesRead, PostgresQueriesRead};
+use clap::{CommandFactory, ValueEnum};
+use clap_complete::env::{Bash, EnvCompleter, Fish, Zsh};
+use clap_complete::{CompleteEnv, CompletionCandidate};
+use std::error::Error as StdError;
+use std::ffi::OsStr;
+use std::time::Duration;
+
+/// Set by the completion scripts when they ask pgc for the completions of a
+/// command line.
+const COMPLETE_VAR: &str = "COMPLETE";
+
+/// How long completing a table name waits for the database.
+const TABLE_COMPLETION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Shells `pgc completions` writes a script for.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// When run by a completion script, print the completions of its command
+/// line and exit. Must run before anything is printed.
+pub fn complete_if_asked() {
+    CompleteEnv::with_factory(Cli::command)
+        .var(COMPLETE_VAR)
+        .complete();
+}
+
+/// Print the script registering the completions of pgc in `shell`, which
+/// asks this binary for them as the command line is typed.
+pub(super) fn print_script(shell: CompletionShell) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let completer = std::env::current_exe()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| "pgc".to_string());
+    let shell: &dyn EnvCompleter = match shell {
+        CompletionShell::Bash => &Bash,
+        CompletionShell::Zsh => &Zsh,
+        CompletionShell::Fish => &Fish,
+    };
+    shell.write_registration(
+        COMPLETE_VAR,
+        "pgc",
+        "pgc",
+        &completer,
+        &mut std::io::stdout().lock(),
+    )?;
+    Ok(())
+}
+
+/// The tables of the database configured on the command line being
+/// completed that start with `current`, from `list_tables`. None when the
+/// config does not load or the database cannot be reached in time, as a
+/// completion has nowhere to report it.
+pub(super) fn table_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current) = current.to_str() else {
+        return Vec::new();
+    };
+    let config_filename = config_argument().unwrap_or_else(args::default_config_filename);
+    let Ok(config) = Config::load(&config_filename) else {
+        return Vec::new();
+    };
+    // Completions are given before main awaits anything, and its runtime
+    // cannot be blocked on from within
+    let tables = std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .ok()?;
+        runtime.block_on(async {
+            tokio::time::timeout(TABLE_COMPLETION_TIMEOUT, async {
+                PostgresQueriesRead::from_config(config)
+                    .ok()?
+                    .list_tables(Some(true))
+                    .await
+                    .ok()
+            })
+            .await
+            .ok()
+            .flatten()
+        })
+    })
+    .join()
+    .ok()
+    .flatten()
+    .unwrap_or_default();
+    tables
+        .into_iter()
+        .filter(|table| table.starts_with(current))
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// The `--config` of the command line being completed, which the scripts
+/// pass after a `--`.
+fn config_argument() -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .enumerate()
+        .rev()
+        .find_map(|(i, arg)| match arg.strip_prefix("--config=") {
+            Some(config) => Some(config.to_string()),
+            None if arg == "--config" => args.get(i + 1).cloned(),
+            None => None,
+        })
+}
@@ -0,0 +1,1229 @@
+use crate::read::args;
+use crate::read::config::{self, Config, ConfigFile};
+use crate::read::db;
+use crate::read::doctor;
+use crate::read::info;
+use crate::read::queries::{DatabaseQueriesRead, PostgresQueriesRead};
+use crate::read::reports::CheckStatus;
+use crate::utils::error::{ErrorKind, PgcError};
+use crate::utils::interaction::{self, InteractionPolicy};
+use crate::utils::metrics::Metrics;
+use crate::utils::progress::{ObserverHandle, ReporterObserver};
+use crate::utils::reporter::{ConsoleReporter, Reporter};
+use crate::utils::{jobs, prompt};
+use crate::write::backup;
+use crate::write::dry_run::DryRun;
+use crate::write::options::{
+    BackupOptions, BackupScope, BoundingBox, CompressionMethod, CompressionSpec, DumpFormat,
+    ExportOptions, IngestOptions, InsertGeoJsonOptions, InsertStrategy, OnFeatureError,
+    PostRestoreOptions, PreClean, RestoreOptions, RetentionPolicy, WatchOptions,
+};
+use crate::write::plan;
+use crate::write::queries::{confirm_destruction, DatabaseQueriesWrite, PostgresQueriesWrite};
+use crate::write::validate;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap_complete::ArgValueCompleter;
+use deadpool_postgres::{Manager, Pool};
+use std::error::Error as StdError;
+use std::io::IsTerminal;
+use std::process::ExitCode;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio_postgres::NoTls;
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+mod completion;
+mod output;
+mod repl;
+mod signals;
+
+use completion::CompletionShell;
+use output::OutputFormat;
+
+pub use crate::utils::interaction::plain;
+pub use completion::complete_if_asked;
+pub use signals::Interrupts;
+
+/// Exit codes of pgc, see `exit_code`.
+const EXIT_CODES: &str = "Exit codes:
+  0    success
+  1    any other failure
+  2    invalid command line
+  3    config problem: a missing or invalid config or plan file, refused credentials
+  4    database unreachable, or the connection to it lost
+  5    a statement refused by the server
+  6    invalid input: a missing or invalid input file
+  7    failure of a client tool, like pg_dump
+  8    a step of `run` failed
+  9    a destructive command was not confirmed
+  10   `doctor` found a warning
+  11   `doctor` found a failure
+  130  cancelled with Ctrl-C or SIGTERM";
+
+/// Exit code of a `run` with a failed step.
+const EXIT_STEP_FAILED: u8 = 8;
+/// Exit code of a destructive command that was not confirmed.
+const EXIT_NOT_CONFIRMED: u8 = 9;
+/// Exit code of a `doctor` finding a warning, and no failure.
+const EXIT_DOCTOR_WARNING: u8 = 10;
+/// Exit code of a `doctor` finding a failure.
+const EXIT_DOCTOR_FAILURE: u8 = 11;
+/// Exit code of a command cancelled with Ctrl-C or SIGTERM.
+pub const EXIT_CANCELLED: u8 = 130;
+
+/// How long the check of `check_database` waits for the server.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Load GeoJSON into PostgreSQL/PostGIS and manage the database.
+#[derive(Debug, Parser)]
+#[command(name = "pgc", version, after_help = EXIT_CODES)]
+pub struct Cli {
+    /// Config file holding the connection, see files/config.toml.
+    /// Defaults to $PGC_CONFIG, or else files/config.toml next to the
+    /// pgc executable.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<String>,
+
+    /// How results are printed on stdout. Messages go to stderr.
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+
+    /// Log more: debug messages, and with -vv trace ones.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Only log warnings and errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Jobs of the operations running in parallel: tables dumped and
+    /// restored, and connections COPYing a file. Defaults to the `jobs` key
+    /// of the config, or one per CPU up to 4 (COPY defaults to 1).
+    #[arg(short, long, global = true, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: Option<u16>,
+
+    /// Run destructive commands without asking for confirmation.
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+
+    /// Never ask anything, as when run from cron: destructive commands need
+    /// --yes, the password is read from PGPASSWORD, progress is logged
+    /// rather than drawn, and the logs have neither color nor emoji.
+    /// Implied when stdin, stdout or stderr is not a terminal.
+    #[arg(long, global = true)]
+    pub non_interactive: bool,
+
+    /// As --yes. With `config init`, overwrite an existing file.
+    #[arg(long, global = true)]
+    pub force: bool,
+
+    /// Write the metrics of the run, like the features loaded and the
+    /// duration of backups, to this file in the Prometheus text format
+    /// once it ends, whether it failed or not.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub metrics_file: Option<String>,
+
+    /// Seconds a first Ctrl-C or SIGTERM waits for the command to stop,
+    /// rolling back what it did not commit and killing its client tools,
+    /// before exiting. A second one exits at once.
+    #[arg(long, global = true, value_name = "SECS", default_value_t = 10)]
+    pub shutdown_grace: u64,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// List the tables of the public schema.
+    #[command(alias = "ls")]
+    ListTables {
+        /// Also list the tables owned by PostGIS.
+        #[arg(long)]
+        all: bool,
+    },
+    /// List the columns of a table.
+    #[command(alias = "cols")]
+    ListColumns {
+        #[arg(add = ArgValueCompleter::new(completion::table_names))]
+        table: String,
+    },
+    /// Count the rows of a table.
+    #[command(alias = "count")]
+    RowCount {
+        #[arg(add = ArgValueCompleter::new(completion::table_names))]
+        table: String,
+        /// Also count the soft deleted rows.
+        #[arg(long)]
+        include_deleted: bool,
+    },
+    /// Report on the health of a table: rows, sizes, geometries, indexes,
+    /// maintenance and imports.
+    Stats {
+        #[arg(add = ArgValueCompleter::new(completion::table_names))]
+        table: String,
+    },
+    /// Suggest column types for the JSON properties of a table.
+    InferTypes {
+        #[arg(add = ArgValueCompleter::new(completion::table_names))]
+        table: String,
+        /// Rows sampled. Defaults to 1000.
+        #[arg(long)]
+        sample_size: Option<i64>,
+    },
+    /// List the files loaded by insert-geojson, newest first.
+    #[command(alias = "history")]
+    ImportHistory {
+        /// Only the files loaded into this table.
+        #[arg(long, add = ArgValueCompleter::new(completion::table_names))]
+        table: Option<String>,
+    },
+    /// Run SQL statements typed at a prompt, each ended by ;, until \q or Ctrl-D.
+    Sql {
+        /// Also run statements that change data or the schema, which are
+        /// refused otherwise.
+        #[arg(long)]
+        allow_writes: bool,
+    },
+    /// Load a GeoJSON file, or a directory of them, into a table.
+    #[command(alias = "ingest")]
+    InsertGeojson(InsertGeojsonArgs),
+    /// Load the GeoJSON files dropped into a directory as they arrive, until Ctrl-C.
+    Watch(WatchArgs),
+    /// Check a GeoJSON file without touching the database.
+    Validate { path: String },
+    /// Drop a table, after asking for confirmation.
+    Drop {
+        #[arg(add = ArgValueCompleter::new(completion::table_names))]
+        table: String,
+    },
+    /// Empty a table, after asking for confirmation.
+    Truncate {
+        #[arg(add = ArgValueCompleter::new(completion::table_names))]
+        table: String,
+        /// Also reset the sequences of the table.
+        #[arg(long)]
+        restart_identity: bool,
+        /// Also empty the tables referencing it.
+        #[arg(long)]
+        cascade: bool,
+    },
+    /// Drop every table of a schema, after asking for confirmation.
+    DropAll {
+        /// Schema to empty. Defaults to public.
+        #[arg(long)]
+        schema: Option<String>,
+        /// Tables to keep.
+        #[arg(long = "exclude", value_name = "TABLE", add = ArgValueCompleter::new(completion::table_names))]
+        exclude: Vec<String>,
+        /// Only list the tables that would be dropped.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Export a table to a GeoJSON or CSV file, or to a directory of vector
+    /// tiles, printing the features and bytes written.
+    Export(ExportArgs),
+    /// Back up the database with pg_dump into a directory.
+    Backup(BackupArgs),
+    /// List the dumps made by `backup` in a directory, newest first.
+    ListBackups { dir: String },
+    /// Restore a dump made by `backup`, after asking for confirmation.
+    Restore(RestoreArgs),
+    /// Run the steps of a plan file in order, see files/plan.toml. Exits with
+    /// 1 if a step failed.
+    Run {
+        plan: String,
+        /// Only plan the statements of the steps, with their paths resolved,
+        /// running the reads they need but changing nothing.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check the config, the database, PostGIS, the client tools and the
+    /// backup directory. Exits with 1 if a check failed, 2 if one warned.
+    Doctor,
+    /// Print the versions of pgc, the server, PostGIS and the client tools,
+    /// and the config file in use, for bug reports.
+    Info,
+    /// Write or check a config file.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Print the script completing pgc commands in a shell, table names
+    /// included, e.g. `source <(pgc completions bash)` in ~/.bashrc.
+    Completions {
+        #[arg(value_enum)]
+        shell: CompletionShell,
+    },
+    /// Refresh the collation version of a database.
+    FixCollation {
+        /// Database to refresh. Defaults to the configured one.
+        db_name: Option<String>,
+    },
+}
+
+impl Command {
+    /// Whether the command needs the config and the database, which
+    /// `check_database` checks first.
+    fn uses_database(&self) -> bool {
+        !matches!(
+            self,
+            Command::Validate { .. }
+                | Command::Doctor
+                | Command::Info
+                | Command::Config { .. }
+                | Command::Completions { .. }
+        )
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Write a starter config file, asking for the connection on a terminal.
+    /// The password is read from PGPASSWORD without one.
+    Init {
+        /// File to write. Defaults to --config, or its default.
+        #[arg(long, value_name = "FILE")]
+        path: Option<String>,
+        #[arg(long)]
+        host: Option<String>,
+        #[arg(long)]
+        port: Option<u16>,
+        #[arg(long)]
+        user: Option<String>,
+        #[arg(long)]
+        db_name: Option<String>,
+    },
+    /// Check that a config file loads, without connecting.
+    Check {
+        /// File to check. Defaults to --config, or its default.
+        #[arg(long, value_name = "FILE")]
+        path: Option<String>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct InsertGeojsonArgs {
+    /// GeoJSON file, directory of GeoJSON files, or - to read stdin (gzip or not).
+    pub path: String,
+    /// Table to load into. Defaults to the name of the file, required with -.
+    #[arg(long, value_name = "NAME", required_if_eq("path", "-"), add = ArgValueCompleter::new(completion::table_names))]
+    pub table: Option<String>,
+    /// Skip files already loaded into the table unchanged.
+    #[arg(long)]
+    pub skip_unchanged: bool,
+    #[command(flatten)]
+    pub ingest: IngestArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Directory to watch, with its subdirectories.
+    pub dir: String,
+    /// Table to load into. Defaults to the name of each file.
+    #[arg(long, value_name = "NAME", add = ArgValueCompleter::new(completion::table_names))]
+    pub table: Option<String>,
+    /// Extensions of the files loaded. Defaults to geojson and json.
+    #[arg(long = "extension", value_name = "EXT")]
+    pub extensions: Vec<String>,
+    /// Seconds a file must stay unchanged before it is loaded.
+    #[arg(long, value_name = "SECONDS")]
+    pub settle: Option<f64>,
+    /// Where files that fail to load are moved, relative to the directory.
+    #[arg(long, value_name = "DIR")]
+    pub failed_dir: Option<String>,
+    /// Also load the files already in the directory.
+    #[arg(long)]
+    pub existing: bool,
+    /// Reload files that were already loaded unchanged.
+    #[arg(long)]
+    pub reload: bool,
+    #[command(flatten)]
+    pub ingest: IngestArgs,
+}
+
+/// Flags of `insert-geojson` and `watch`, see `IngestOptions`.
+#[derive(Debug, Args)]
+pub struct IngestArgs {
+    /// How features are sent to the database.
+    #[arg(long, value_enum, default_value_t = StrategyArg::Copy)]
+    pub strategy: StrategyArg,
+    /// Features per INSERT with the batched strategy.
+    #[arg(long)]
+    pub batch_size: Option<usize>,
+    /// Leave out the features that cannot be converted rather than failing.
+    #[arg(long)]
+    pub skip_invalid: bool,
+    /// Load each member of a multi geometry as its own row.
+    #[arg(long)]
+    pub explode_multi: bool,
+    /// Round coordinates to this many decimals.
+    #[arg(long, value_name = "DECIMALS")]
+    pub precision: Option<u8>,
+    /// Name the features after this property instead of their id.
+    #[arg(long, value_name = "PROPERTY")]
+    pub name_property: Option<String>,
+    /// COPY each file over this many connections. Defaults to --jobs.
+    #[arg(long, value_name = "CONNECTIONS", value_parser = clap::value_parser!(u16).range(1..))]
+    pub parallel_copy: Option<u16>,
+    /// Do not ANALYZE the table once loaded.
+    #[arg(long)]
+    pub no_analyze: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct BackupArgs {
+    /// Directory the dump is written in. Defaults to `dir` of [backup].
+    pub dir: Option<String>,
+    #[arg(long, value_enum)]
+    pub format: Option<FormatArg>,
+    /// Only dump these tables.
+    #[arg(long = "table", value_name = "TABLE", add = ArgValueCompleter::new(completion::table_names))]
+    pub tables: Vec<String>,
+    /// Tables left out, instead of the PostGIS ones. Patterns with * or ?.
+    #[arg(long = "exclude-table", value_name = "TABLE")]
+    pub exclude_tables: Vec<String>,
+    /// Schemas left out. Patterns with * or ?.
+    #[arg(long = "exclude-schema", value_name = "SCHEMA")]
+    pub exclude_schemas: Vec<String>,
+    /// Dump the schema without the data.
+    #[arg(long, conflicts_with = "data_only")]
+    pub schema_only: bool,
+    /// Dump the data without the schema.
+    #[arg(long)]
+    pub data_only: bool,
+    /// Then prune the older backups, keeping this many.
+    #[arg(long, value_name = "COUNT")]
+    pub keep_last: Option<usize>,
+    /// With --keep-last, also keep backups younger than this many days.
+    #[arg(long, value_name = "DAYS", requires = "keep_last")]
+    pub keep_days: Option<u32>,
+    /// Check the dump and write its checksum.
+    #[arg(long)]
+    pub verify: bool,
+    /// Dump with the pg_dump of this container.
+    #[arg(long, value_name = "CONTAINER")]
+    pub docker: Option<String>,
+    /// Compression, as METHOD or METHOD:LEVEL: gzip, lz4, zstd or none.
+    #[arg(long, value_name = "METHOD[:LEVEL]", value_parser = parse_compression)]
+    pub compress: Option<CompressionSpec>,
+    /// Also dump the roles and tablespaces.
+    #[arg(long)]
+    pub globals: bool,
+    /// Kill pg_dump after this many seconds.
+    #[arg(long, value_name = "SECONDS")]
+    pub timeout: Option<u64>,
+    /// Name of the dump, with {db}, {label}, {date}, {time} and {format}.
+    #[arg(long, value_name = "TEMPLATE")]
+    pub filename_template: Option<String>,
+    /// Name the dump after the time in UTC.
+    #[arg(long)]
+    pub utc: bool,
+    /// Point latest_{db}.dump at the dump.
+    #[arg(long)]
+    pub latest: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RestoreArgs {
+    /// Dump to restore, or DIR/latest for the newest backup in DIR.
+    pub dump: String,
+    /// Restore with the pg_restore of this container.
+    #[arg(long, value_name = "CONTAINER")]
+    pub docker: Option<String>,
+    /// Only restore these tables.
+    #[arg(long = "table", value_name = "TABLE")]
+    pub tables: Vec<String>,
+    /// Which tables are dropped before the restore.
+    #[arg(long, value_enum, default_value_t = CleanArg::Dumped)]
+    pub clean: CleanArg,
+    /// Tables kept by --clean all.
+    #[arg(long = "exclude-table", value_name = "TABLE")]
+    pub exclude_tables: Vec<String>,
+    /// Restore a dump whose checksum does not match.
+    #[arg(long)]
+    pub ignore_checksum: bool,
+    /// Restore into this database rather than the configured one.
+    #[arg(long, value_name = "DB")]
+    pub target_db: Option<String>,
+    /// Create the target database first.
+    #[arg(long, requires = "target_db")]
+    pub create_db: bool,
+    /// With --create-db, drop the target database if it exists.
+    #[arg(long, requires = "create_db")]
+    pub overwrite: bool,
+    /// Roles and tablespaces dumped with `backup --globals`.
+    #[arg(long, value_name = "FILE")]
+    pub globals_file: Option<String>,
+    /// Extensions created before the schema, instead of those of the dump.
+    #[arg(long = "extension", value_name = "NAME")]
+    pub extensions: Vec<String>,
+    /// Kill pg_restore after this many seconds.
+    #[arg(long, value_name = "SECONDS")]
+    pub timeout: Option<u64>,
+    /// Do not ANALYZE the restored tables.
+    #[arg(long)]
+    pub no_analyze: bool,
+    /// Do not refresh the collation version of the database.
+    #[arg(long)]
+    pub no_refresh_collation: bool,
+    /// Do not rebuild the spatial indexes.
+    #[arg(long)]
+    pub no_reindex: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    #[arg(add = ArgValueCompleter::new(completion::table_names))]
+    pub table: String,
+    /// File written, or directory of the tiles with --format mvt.
+    pub path: String,
+    /// Defaults from the extension of the path: .geojson or .json, and .csv.
+    #[arg(long, value_enum)]
+    pub format: Option<ExportFormatArg>,
+    /// Only the features intersecting this box, in longitude/latitude.
+    /// Required with --format mvt.
+    #[arg(long, value_name = "MIN_X,MIN_Y,MAX_X,MAX_Y", value_parser = parse_bbox, required_if_eq("format", "mvt"))]
+    pub bbox: Option<BoundingBox>,
+    /// Only the rows this SQL condition holds for.
+    #[arg(long = "where", value_name = "CONDITION")]
+    pub filter: Option<String>,
+    /// Simplify the geometries with this tolerance, in the units of their SRID.
+    #[arg(long, value_name = "TOLERANCE")]
+    pub simplify: Option<f64>,
+    /// With --format mvt, the lowest zoom level exported.
+    #[arg(long, default_value_t = 0)]
+    pub min_zoom: u32,
+    /// With --format mvt, the highest zoom level exported.
+    #[arg(long, required_if_eq("format", "mvt"))]
+    pub max_zoom: Option<u32>,
+}
+
+/// What `export` writes.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormatArg {
+    Geojson,
+    Csv,
+    /// Mapbox vector tiles, in PATH/{z}/{x}/{y}.mvt.
+    Mvt,
+}
+
+/// See `InsertStrategy`.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum StrategyArg {
+    Copy,
+    Batched,
+}
+
+/// See `DumpFormat`.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FormatArg {
+    Custom,
+    Directory,
+    Plain,
+}
+
+/// See `PreClean`.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CleanArg {
+    /// Nothing.
+    None,
+    /// The tables the dump recreates.
+    Dumped,
+    /// Every table of the public schema.
+    All,
+}
+
+/// `METHOD` or `METHOD:LEVEL`, e.g. `zstd:3`.
+fn parse_compression(spec: &str) -> Result<CompressionSpec, String> {
+    let (method, level) = match spec.split_once(':') {
+        Some((method, level)) => (
+            method,
+            Some(
+                level
+                    .parse::<u32>()
+                    .map_err(|_| format!("invalid compression level: {}", level))?,
+            ),
+        ),
+        None => (spec, None),
+    };
+    let method = match method {
+        "gzip" => CompressionMethod::Gzip,
+        "lz4" => CompressionMethod::Lz4,
+        "zstd" => CompressionMethod::Zstd,
+        "none" => CompressionMethod::None,
+        _ => return Err(format!("unknown compression method: {}", method)),
+    };
+    Ok(CompressionSpec { method, level })
+}
+
+/// `MIN_X,MIN_Y,MAX_X,MAX_Y`, e.g. `5.9,47.2,10.5,55.1`.
+fn parse_bbox(bbox: &str) -> Result<BoundingBox, String> {
+    let corners = bbox
+        .split(',')
+        .map(|corner| corner.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|_| format!("invalid bounding box: {}", bbox))?;
+    let [min_x, min_y, max_x, max_y] = corners[..] else {
+        return Err(format!(
+            "invalid bounding box: {}, expected MIN_X,MIN_Y,MAX_X,MAX_Y",
+            bbox
+        ));
+    };
+    let bbox = BoundingBox {
+        min_x,
+        min_y,
+        max_x,
+        max_y,
+    };
+    bbox.validate()
+        .map_err(|e| e.trim_start_matches("❌ ").to_string())?;
+    Ok(bbox)
+}
+
+impl IngestArgs {
+    fn ingest_options(&self, skip_unchanged: bool) -> IngestOptions {
+        let defaults = IngestOptions::default();
+        IngestOptions {
+            analyze: !self.no_analyze,
+            on_feature_error: if self.skip_invalid {
+                OnFeatureError::Skip
+            } else {
+                OnFeatureError::Abort
+            },
+            insert_strategy: match self.strategy {
+                StrategyArg::Copy => InsertStrategy::Copy,
+                StrategyArg::Batched => InsertStrategy::Batched,
+            },
+            batch_size: self.batch_size.unwrap_or(defaults.batch_size),
+            skip_unchanged,
+            explode_multi: self.explode_multi,
+            coordinate_precision: self.precision,
+            parallel_copy: self
+                .parallel_copy
+                .map_or(defaults.parallel_copy, usize::from),
+            name_property: self.name_property.clone(),
+            ..defaults
+        }
+    }
+}
+
+impl InsertGeojsonArgs {
+    fn insert_options(&self) -> InsertGeoJsonOptions {
+        let mut ingest_options = self.ingest.ingest_options(self.skip_unchanged);
+        // A bar per file, drawn on a terminal
+        ingest_options.observer = Some(ObserverHandle::new(Arc::new(ReporterObserver::new(
+            reporter(),
+            "load",
+        ))));
+        InsertGeoJsonOptions {
+            table: self.table.clone(),
+            ingest_options,
+            ..Default::default()
+        }
+    }
+}
+
+impl ExportArgs {
+    /// `--format`, or the format the extension of the path stands for.
+    fn format(&self) -> Result<ExportFormatArg, Box<dyn StdError + Send + Sync>> {
+        if let Some(format) = self.format {
+            return Ok(format);
+        }
+        let extension = std::path::Path::new(&self.path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("geojson") | Some("json") => Ok(ExportFormatArg::Geojson),
+            Some("csv") => Ok(ExportFormatArg::Csv),
+            _ => Err(ErrorKind::Usage.wrap(format!(
+                "❌ Cannot tell the format of {} from its extension, pass --format",
+                self.path
+            ))),
+        }
+    }
+
+    fn export_options(&self) -> ExportOptions {
+        ExportOptions {
+            bbox: self.bbox,
+            filter: self.filter.clone(),
+            simplify: self.simplify,
+        }
+    }
+}
+
+impl WatchArgs {
+    fn watch_options(&self) -> WatchOptions {
+        let defaults = WatchOptions::default();
+        WatchOptions {
+            table_name: self.table.clone(),
+            extensions: if self.extensions.is_empty() {
+                defaults.extensions
+            } else {
+                self.extensions.clone()
+            },
+            settle_time: self
+                .settle
+                .map(Duration::from_secs_f64)
+                .unwrap_or(defaults.settle_time),
+            failed_dir: self.failed_dir.clone().unwrap_or(defaults.failed_dir),
+            load_existing: self.existing,
+            ingest_options: self.ingest.ingest_options(!self.reload),
+            ..defaults
+        }
+    }
+}
+
+impl BackupArgs {
+    fn backup_options(&self) -> BackupOptions {
+        let defaults = BackupOptions::default();
+        BackupOptions {
+            jobs: defaults.jobs,
+            format: self.format.map(|format| match format {
+                FormatArg::Custom => DumpFormat::Custom,
+                FormatArg::Directory => DumpFormat::Directory,
+                FormatArg::Plain => DumpFormat::Plain,
+            }),
+            tables: (!self.tables.is_empty()).then(|| self.tables.clone()),
+            exclude_tables: (!self.exclude_tables.is_empty()).then(|| self.exclude_tables.clone()),
+            exclude_schemas: self.exclude_schemas.clone(),
+            scope: if self.schema_only {
+                BackupScope::SchemaOnly
+            } else if self.data_only {
+                BackupScope::DataOnly
+            } else {
+                BackupScope::Full
+            },
+            retain: self.keep_last.map(|keep_last| RetentionPolicy {
+                keep_last,
+                keep_days: self.keep_days,
+            }),
+            verify: self.verify,
+            docker_container_name: self.docker.clone(),
+            compression: self.compress,
+            include_globals: self.globals,
+            timeout: self.timeout.map(Duration::from_secs),
+            filename_template: self.filename_template.clone(),
+            utc_timestamps: self.utc,
+            write_latest_pointer: self.latest,
+            observer: None,
+        }
+    }
+}
+
+impl RestoreArgs {
+    fn restore_options(&self) -> RestoreOptions {
+        let defaults = RestoreOptions::default();
+        RestoreOptions {
+            docker_container_name: self.docker.clone(),
+            jobs: defaults.jobs,
+            tables: (!self.tables.is_empty()).then(|| self.tables.clone()),
+            pre_clean: match self.clean {
+                CleanArg::None => PreClean::None,
+                CleanArg::Dumped => PreClean::DroppedTablesInDump,
+                CleanArg::All => PreClean::AllTables,
+            },
+            exclude_tables: self.exclude_tables.clone(),
+            force: self.ignore_checksum,
+            target_db: self.target_db.clone(),
+            create_db: self.create_db,
+            overwrite: self.overwrite,
+            globals_file: self.globals_file.clone(),
+            extensions: (!self.extensions.is_empty()).then(|| self.extensions.clone()),
+            timeout: self.timeout.map(Duration::from_secs),
+            post_restore: PostRestoreOptions {
+                analyze: !self.no_analyze,
+                refresh_collation: !self.no_refresh_collation,
+                reindex_spatial: !self.no_reindex,
+            },
+            ..defaults
+        }
+    }
+}
+
+/// Whether `command`, which destroys data, runs without asking for
+/// confirmation: with `--yes`, or with `--force` when stdin is not a
+/// terminal. Without a terminal to ask on, and without `--force`, it is an
+/// error.
+fn skip_confirmation(cli: &Cli, command: &str) -> Result<bool, Box<dyn StdError + Send + Sync>> {
+    if cli.yes || cli.force {
+        return Ok(true);
+    }
+    if prompt::is_interactive() {
+        return Ok(false);
+    }
+    Err(ErrorKind::Usage.wrap(format!(
+        "❌ {} needs confirmation, which is not asked when not interactive: pass --yes to run it without",
+        command
+    )))
+}
+
+/// The reporter of the queries pgc runs, writing to the terminal.
+pub(crate) fn reporter() -> Arc<dyn Reporter> {
+    static REPORTER: OnceLock<Arc<dyn Reporter>> = OnceLock::new();
+    REPORTER
+        .get_or_init(|| Arc::new(ConsoleReporter::default()))
+        .clone()
+}
+
+/// The metrics of the queries pgc runs, for `--metrics-file`.
+fn metrics() -> Arc<Metrics> {
+    static METRICS: OnceLock<Arc<Metrics>> = OnceLock::new();
+    METRICS.get_or_init(Arc::default).clone()
+}
+
+/// Ask to confirm `action` on `table` of the database of `config`, which is
+/// an error unless confirmed.
+async fn confirm_table(
+    action: &str,
+    table: &str,
+    config: &Config,
+) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let tables = [table.to_string()];
+    if !confirm_destruction(action, config, "public", &tables, reporter().as_ref()).await? {
+        return Err(
+            ErrorKind::NotConfirmed.wrap(format!("❌ {} was not confirmed, aborting", action))
+        );
+    }
+    Ok(())
+}
+
+/// Run `pgc config init` or `pgc config check`.
+fn config_command(
+    cli: &Cli,
+    command: &ConfigCommand,
+) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let (ConfigCommand::Init { path, .. } | ConfigCommand::Check { path }) = command;
+    let path = path.clone().unwrap_or_else(|| config_filename(cli));
+    match command {
+        ConfigCommand::Init {
+            host,
+            port,
+            user,
+            db_name,
+            ..
+        } => {
+            // Checked before asking anything
+            if std::path::Path::new(&path).exists() && !cli.force {
+                return Err(
+                    format!("❌ {} already exists: pass --force to overwrite it", path).into(),
+                );
+            }
+            let interactive = prompt::is_interactive();
+            let value = |given: &Option<String>, question: &str, default: &str| match given {
+                Some(value) => value.clone(),
+                None if interactive => prompt::ask(question, default),
+                None => default.to_string(),
+            };
+            let host = value(host, "Host:", "localhost");
+            let port = match port {
+                Some(port) => *port,
+                None => value(&None, "Port:", "5432")
+                    .parse()
+                    .map_err(|e| ErrorKind::Usage.wrap(format!("❌ Invalid port: {}", e)))?,
+            };
+            let user = value(user, "User:", "postgres");
+            let db_name = value(db_name, "Database:", "postgres");
+            let password = match std::env::var("PGPASSWORD") {
+                Ok(password) if !interactive => password,
+                _ if interactive => prompt::ask_secret("Password (not shown):"),
+                _ => {
+                    return Err(ErrorKind::Usage.wrap(
+                        "❌ The password cannot be asked when not interactive: set PGPASSWORD, empty for none",
+                    ))
+                }
+            };
+            let config = Config {
+                host,
+                port,
+                user,
+                password,
+                db_name,
+                pg_dump_path: None,
+                pg_restore_path: None,
+                jobs: None,
+                audit_log: None,
+                dialect: None,
+            };
+            config::write_starter_config(&path, &config, cli.force)?;
+            info!("✅ Wrote {}, readable by you only", path);
+        }
+        ConfigCommand::Check { .. } => {
+            let config = config::validate_file(&path)
+                .map_err(|e| ErrorKind::Config.wrap(format!("❌ {}: {}", path, e)))?
+                .config;
+            info!(
+                "✅ {} is valid: {}@{}:{}/{}",
+                path, config.user, config.host, config.port, config.db_name
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Log to stderr at the level `--verbose` and `--quiet` ask for, or as
+/// `RUST_LOG` says when it is set, e.g. `RUST_LOG=postgres_connect_rust::write=debug`. Colors
+/// and emoji are left out as the interaction policy says.
+fn init_logging(cli: &Cli) {
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => "warn",
+        (false, 0) => "info",
+        (false, 1) => "debug",
+        (false, _) => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,postgres_connect_rust={}", level)));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(|| interaction::PlainStderr)
+        .with_ansi(interaction::policy().color)
+        .with_target(false)
+        .init();
+}
+
+/// The config file of `cli`: `--config`, or else `args::default_config_filename`.
+fn config_filename(cli: &Cli) -> String {
+    cli.config
+        .clone()
+        .unwrap_or_else(args::default_config_filename)
+}
+
+/// Run the command of `cli`, with the config file it names, returning the
+/// exit code of a command that completed. `interrupts` stops it, see
+/// `Interrupts::listen`.
+///
+/// With `--verbose`, the versions of `info::collect` are printed after the
+/// error of a command that failed, for bug reports.
+pub async fn run(
+    cli: Cli,
+    interrupts: &Interrupts,
+) -> Result<ExitCode, Box<dyn StdError + Send + Sync>> {
+    interaction::set_policy(InteractionPolicy::detect(cli.non_interactive));
+    init_logging(&cli);
+    let config_filename = config_filename(&cli);
+    let jobs = match cli.jobs {
+        Some(jobs) => Some(jobs as usize),
+        None => Config::load(&config_filename)
+            .ok()
+            .and_then(|config| jobs::jobs_of(&config)),
+    };
+    if let Some(jobs) = jobs {
+        jobs::set_jobs(jobs);
+    }
+    let result = run_command(&cli, &config_filename, interrupts).await;
+    if let Some(metrics_file) = &cli.metrics_file {
+        match std::fs::write(metrics_file, metrics().to_prometheus_text()) {
+            Ok(()) => info!("📈 Metrics written to {}", metrics_file),
+            Err(e) => warn!("⚠️ Failed to write the metrics to {}: {}", metrics_file, e),
+        }
+    }
+    if let (Err(e), true) = (&result, cli.verbose > 0) {
+        let mut report = format!("{}\n\nVersions:", e);
+        for (name, value) in info::collect(&config_filename).await.entries() {
+            report.push_str(&format!("\n  {}: {}", name, value));
+        }
+        return Err(ErrorKind::of(e.as_ref()).wrap(report));
+    }
+    result
+}
+
+/// Stop the command on Ctrl-C or SIGTERM, giving it `--shutdown-grace` to,
+/// see `Interrupts::listen`.
+pub fn cancel_on_signals(cli: &Cli) -> Interrupts {
+    Interrupts::listen(Duration::from_secs(cli.shutdown_grace))
+}
+
+/// The exit code of a command that failed with `error`, see `EXIT_CODES`.
+pub fn exit_code(error: &(dyn StdError + 'static)) -> ExitCode {
+    ExitCode::from(match ErrorKind::of(error) {
+        ErrorKind::Other => 1,
+        ErrorKind::Usage => 2,
+        ErrorKind::Config => 3,
+        ErrorKind::Connection => 4,
+        ErrorKind::Sql => 5,
+        ErrorKind::InvalidInput => 6,
+        ErrorKind::Subprocess => 7,
+        ErrorKind::NotConfirmed => EXIT_NOT_CONFIRMED,
+        ErrorKind::Cancelled => EXIT_CANCELLED,
+    })
+}
+
+/// Read `config_filename` and fail unless the database it names answers,
+/// before running a command needing them.
+async fn check_database(
+    config_filename: &str,
+) -> Result<ConfigFile, Box<dyn StdError + Send + Sync>> {
+    let config_file = config::validate_file(config_filename)
+        .map_err(|e| ErrorKind::Config.wrap(format!("❌ {}: {}", config_filename, e)))?;
+    let config = &config_file.config;
+    match tokio::time::timeout(CONNECT_TIMEOUT, db::connect(config, &config.db_name)).await {
+        Ok(client) => client.map(|_| config_file.clone()).map_err(Into::into),
+        Err(_) => Err(ErrorKind::Connection.wrap(format!(
+            "❌ No answer from {}:{} within {:?}",
+            config.host, config.port, CONNECT_TIMEOUT
+        ))),
+    }
+}
+
+/// The queries of the commands, on a pool of connections to the database of
+/// `config_file`, reporting to the terminal and stopped by `interrupts`.
+/// The commands not using the database,
+/// see `Command::uses_database`, may have no config file: they get queries
+/// on a pool of the default settings, which they never connect with.
+fn queries(
+    config_file: Option<ConfigFile>,
+    interrupts: &Interrupts,
+) -> Result<(PostgresQueriesRead, PostgresQueriesWrite), PgcError> {
+    let write_queries = match config_file {
+        Some(config_file) => PostgresQueriesWrite::from_config_file(config_file)?,
+        None => {
+            let manager = Manager::new(tokio_postgres::Config::new(), NoTls);
+            PostgresQueriesWrite::new(Pool::builder(manager).build().map_err(|e| e.to_string())?)
+        }
+    }
+    .with_reporter(reporter())
+    .with_metrics(metrics())
+    .with_cancellation(interrupts.token());
+    let read_queries = PostgresQueriesRead::new(write_queries.pool().clone())
+        .with_reporter(reporter())
+        .with_metrics(metrics())
+        .with_cancellation(interrupts.token());
+    Ok((read_queries, write_queries))
+}
+
+async fn run_command(
+    cli: &Cli,
+    config_filename: &str,
+    interrupts: &Interrupts,
+) -> Result<ExitCode, Box<dyn StdError + Send + Sync>> {
+    let config_file = if cli.command.uses_database() {
+        Some(check_database(config_filename).await?)
+    } else {
+        None
+    };
+    let (read_queries, write_queries) = queries(config_file, interrupts)?;
+    // Set for the commands using the database, see `check_database`
+    let config_file = || {
+        write_queries
+            .config_file()
+            .ok_or_else(|| ErrorKind::Config.wrap(format!("❌ {} was not read", config_filename)))
+    };
+    let mut exit_code = ExitCode::SUCCESS;
+    match &cli.command {
+        Command::ListTables { all } => {
+            let tables = read_queries.list_tables(Some(!*all)).await?;
+            output::print(&output::TableList(tables), cli.output)?;
+        }
+        Command::ListColumns { table } => {
+            let columns = read_queries.list_columns(table).await?;
+            let columns = output::ColumnList {
+                table: table.clone(),
+                columns,
+            };
+            output::print(&columns, cli.output)?;
+        }
+        Command::RowCount {
+            table,
+            include_deleted,
+        } => {
+            let rows = read_queries
+                .table_row_count(table, Some(*include_deleted))
+                .await?;
+            let count = output::RowCount {
+                table: table.clone(),
+                rows,
+            };
+            output::print(&count, cli.output)?;
+        }
+        Command::InferTypes { table, sample_size } => {
+            let reports = read_queries
+                .infer_property_types(table, *sample_size)
+                .await?;
+            output::print(&output::PropertyTypes(reports), cli.output)?;
+        }
+        Command::ImportHistory { table } => {
+            let records = read_queries.import_history(table.as_deref()).await?;
+            output::print(&output::ImportHistory(records), cli.output)?;
+        }
+        Command::Sql { allow_writes } => {
+            repl::run(
+                &read_queries,
+                &config_file()?.config,
+                *allow_writes,
+                cli.output,
+                interrupts,
+            )
+            .await?
+        }
+        Command::InsertGeojson(insert) => {
+            let report = write_queries
+                .insert_geojson(&insert.path, Some(insert.insert_options()))
+                .await?;
+            output::print(&report, cli.output)?;
+        }
+        Command::Watch(watch) => {
+            info!("Ctrl-C stops watching");
+            let report = write_queries
+                .watch_directory(&watch.dir, Some(watch.watch_options()))
+                .await?;
+            output::print(&report, cli.output)?;
+        }
+        Command::Stats { table } => {
+            let stats = read_queries.table_stats(table).await?;
+            output::print(&stats, cli.output)?;
+        }
+        Command::Export(export) => match export.format()? {
+            ExportFormatArg::Geojson => {
+                let report = read_queries
+                    .export_geojson(&export.table, &export.path, Some(export.export_options()))
+                    .await?;
+                output::print(&report, cli.output)?;
+            }
+            ExportFormatArg::Csv => {
+                let report = read_queries
+                    .export_csv(&export.table, &export.path, Some(export.export_options()))
+                    .await?;
+                output::print(&report, cli.output)?;
+            }
+            ExportFormatArg::Mvt => {
+                if export.filter.is_some() || export.simplify.is_some() {
+                    return Err(ErrorKind::Usage.wrap(
+                        "❌ --where and --simplify do not apply to --format mvt, whose tiles are simplified to their zoom level",
+                    ));
+                }
+                let (Some(bbox), Some(max_zoom)) = (export.bbox, export.max_zoom) else {
+                    return Err(
+                        ErrorKind::Usage.wrap("❌ --format mvt needs --bbox and --max-zoom")
+                    );
+                };
+                let report = read_queries
+                    .export_mvt_range(
+                        &export.table,
+                        export.min_zoom,
+                        max_zoom,
+                        bbox,
+                        &export.path,
+                        &[],
+                    )
+                    .await?;
+                output::print(&report, cli.output)?;
+            }
+        },
+        Command::Validate { path } => {
+            validate::validate_geojson(path)?;
+        }
+        Command::Drop { table } => {
+            if !skip_confirmation(cli, "drop")? {
+                confirm_table(
+                    &format!("Drop table {}", table),
+                    table,
+                    &config_file()?.config,
+                )
+                .await?;
+            }
+            write_queries.drop(table).await?
+        }
+        Command::Truncate {
+            table,
+            restart_identity,
+            cascade,
+        } => {
+            if !skip_confirmation(cli, "truncate")? {
+                confirm_table(
+                    &format!("Empty table {}", table),
+                    table,
+                    &config_file()?.config,
+                )
+                .await?;
+            }
+            write_queries
+                .truncate(table, *restart_identity, *cascade)
+                .await?
+        }
+        Command::DropAll {
+            schema,
+            exclude,
+            dry_run,
+        } => {
+            // A dry run destroys nothing to confirm
+            let require_confirmation = !*dry_run && !skip_confirmation(cli, "drop-all")?;
+            let exclude: Vec<&str> = exclude.iter().map(String::as_str).collect();
+            write_queries
+                .drop_all_tables(schema.as_deref(), *dry_run, &exclude, require_confirmation)
+                .await?;
+        }
+        Command::Backup(backup) => {
+            let dir = match &backup.dir {
+                Some(dir) => dir.clone(),
+                None => config_file()?.backup.dir.clone().ok_or(
+                    "❌ No backup directory: pass one, or set `dir` in the [backup] section of the config",
+                )?,
+            };
+            let dump_file = write_queries
+                .backup_database(&dir, Some(backup.backup_options()))
+                .await?;
+            let db_name = &config_file()?.config.db_name;
+            let info = backup::written_backup_info(&dump_file, db_name)?;
+            output::print(&info, cli.output)?;
+        }
+        Command::ListBackups { dir } => {
+            let backups = write_queries.list_backups(dir).await?;
+            output::print(&output::BackupList(backups), cli.output)?;
+        }
+        Command::Restore(restore) => {
+            let options = RestoreOptions {
+                assume_yes: skip_confirmation(cli, "restore")?,
+                ..restore.restore_options()
+            };
+            write_queries
+                .restore_database(&restore.dump, Some(options))
+                .await?
+        }
+        Command::Run { plan, dry_run } => {
+            let mut plan = plan::load(plan).map_err(|e| ErrorKind::Config.wrap(e))?;
+            plan.backup_dir = config_file()?.backup.dir.clone();
+            let destroyed = plan.destroyed_tables();
+            if !*dry_run && !destroyed.is_empty() && !skip_confirmation(cli, "run")? {
+                let config = &config_file()?.config;
+                let action = format!("Run {}", plan.name);
+                if !confirm_destruction(&action, config, "public", &destroyed, reporter().as_ref())
+                    .await?
+                {
+                    return Err(ErrorKind::NotConfirmed
+                        .wrap(format!("❌ {} was not confirmed, aborting", action)));
+                }
+            }
+            let report = if *dry_run {
+                let planned = Arc::new(DryRun::new());
+                let planner = write_queries.clone().with_dry_run(planned.clone());
+                plan::run(&planner, &read_queries, &plan, Some(&planned)).await
+            } else {
+                plan::run(&write_queries, &read_queries, &plan, None).await
+            };
+            output::print(&report, cli.output)?;
+            if report.failed() {
+                exit_code = ExitCode::from(EXIT_STEP_FAILED);
+            }
+        }
+        Command::Doctor => {
+            let report = doctor::run_checks(config_filename).await;
+            output::print(&report, cli.output)?;
+            exit_code = match report.worst() {
+                CheckStatus::Pass => ExitCode::SUCCESS,
+                CheckStatus::Warn => ExitCode::from(EXIT_DOCTOR_WARNING),
+                CheckStatus::Fail => ExitCode::from(EXIT_DOCTOR_FAILURE),
+            };
+        }
+        Command::Info => output::print(&info::collect(config_filename).await, cli.output)?,
+        Command::Config { command } => config_command(cli, command)?,
+        Command::Completions { shell } => completion::print_script(*shell)?,
+        Command::FixCollation { db_name } => {
+            write_queries
+                .fix_collation_version(db_name.as_deref())
+                .await?;
+        }
+    }
+    Ok(exit_code)
+}
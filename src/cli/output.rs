@@ -0,0 +1,537 @@
+use crate::read::reports::{
+    CheckStatus, ColumnInfo, DoctorReport, ExportReport, ImportRecord, PropertyTypeReport,
+    StatementResult, TableStats, TileExport, VersionInfo,
+};
+use crate::utils::csv;
+use crate::write::backup;
+use crate::write::reports::{BackupInfo, IngestReport, PlanReport, StepStatus, WatchReport};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::error::Error as StdError;
+
+/// How the CLI prints the results of its commands, see `--output`.
+///
+/// * `Table`: box-drawn tables, for humans.
+/// * `Json`: one JSON document.
+/// * `Csv`: comma separated values, with a header line.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+/// A result of a command, printed in any `OutputFormat`: serialized as it is
+/// in JSON, and as `headers` and `rows` in a table or CSV.
+pub(super) trait Render: Serialize {
+    /// Line above the table, if any.
+    fn title(&self) -> Option<String> {
+        None
+    }
+    fn headers(&self) -> Vec<&str>;
+    fn rows(&self) -> Vec<Vec<String>>;
+}
+
+/// The tables of `list-tables`.
+#[derive(Serialize)]
+#[serde(transparent)]
+pub(super) struct TableList(pub Vec<String>);
+
+impl Render for TableList {
+    fn headers(&self) -> Vec<&str> {
+        vec!["table_name"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.0.iter().map(|table| vec![table.clone()]).collect()
+    }
+}
+
+/// The columns of `list-columns`.
+#[derive(Serialize)]
+pub(super) struct ColumnList {
+    pub table: String,
+    pub columns: Vec<ColumnInfo>,
+}
+
+impl Render for ColumnList {
+    fn title(&self) -> Option<String> {
+        Some(format!("Columns in '{}' table", self.table))
+    }
+
+    fn headers(&self) -> Vec<&str> {
+        vec!["column_name", "data_type"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.columns
+            .iter()
+            .map(|column| vec![column.column_name.clone(), column.data_type.clone()])
+            .collect()
+    }
+}
+
+/// The count of `row-count`.
+#[derive(Serialize)]
+pub(super) struct RowCount {
+    pub table: String,
+    pub rows: i64,
+}
+
+impl Render for RowCount {
+    fn headers(&self) -> Vec<&str> {
+        vec!["table", "rows"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.table.clone(), self.rows.to_string()]]
+    }
+}
+
+impl Render for IngestReport {
+    fn title(&self) -> Option<String> {
+        Some(format!("Loaded into '{}'", self.table))
+    }
+
+    fn headers(&self) -> Vec<&str> {
+        vec![
+            "path",
+            "features_loaded",
+            "rows_written",
+            "skipped",
+            "duplicates",
+            "unchanged",
+            "duration_ms",
+        ]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.files
+            .iter()
+            .map(|file| {
+                vec![
+                    file.path.clone(),
+                    file.features_loaded.to_string(),
+                    file.rows_written.to_string(),
+                    file.skipped.to_string(),
+                    file.duplicates.to_string(),
+                    file.unchanged.to_string(),
+                    file.duration_ms.to_string(),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Render for WatchReport {
+    fn headers(&self) -> Vec<&str> {
+        vec!["path", "status", "rows_written", "detail"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        let loaded = self.files.iter().map(|file| {
+            vec![
+                file.path.clone(),
+                if file.unchanged {
+                    "unchanged"
+                } else {
+                    "loaded"
+                }
+                .to_string(),
+                file.rows_written.to_string(),
+                String::new(),
+            ]
+        });
+        let failed = self.failed.iter().map(|failure| {
+            vec![
+                failure.path.clone(),
+                "failed".to_string(),
+                "0".to_string(),
+                match &failure.moved_to {
+                    Some(moved_to) => format!("moved to {}: {}", moved_to, failure.error),
+                    None => failure.error.clone(),
+                },
+            ]
+        });
+        loaded.chain(failed).collect()
+    }
+}
+
+impl Render for PlanReport {
+    fn title(&self) -> Option<String> {
+        Some(format!("Plan {}", self.plan))
+    }
+
+    fn headers(&self) -> Vec<&str> {
+        vec!["step", "action", "status", "duration_ms", "detail"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.steps
+            .iter()
+            .map(|step| {
+                let status = match step.status {
+                    StepStatus::Planned => "planned",
+                    StepStatus::Ok => "ok",
+                    StepStatus::Failed => "failed",
+                    StepStatus::Skipped => "skipped",
+                };
+                vec![
+                    step.step.to_string(),
+                    step.description.clone(),
+                    status.to_string(),
+                    step.duration_ms.to_string(),
+                    step.detail.clone(),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// Headers of the rows of `backup_row`.
+const BACKUP_HEADERS: [&str; 6] = ["backup", "db_name", "timestamp", "size", "format", "valid"];
+
+fn backup_row(backup_info: &BackupInfo) -> Vec<String> {
+    vec![
+        backup_info.path.clone(),
+        backup_info.db_name.clone(),
+        backup_info.timestamp.clone(),
+        backup::human_size(backup_info.size_bytes),
+        backup_info.format.pg_dump_name().to_string(),
+        if backup_info.valid { "yes" } else { "no" }.to_string(),
+    ]
+}
+
+impl Render for BackupInfo {
+    fn headers(&self) -> Vec<&str> {
+        BACKUP_HEADERS.to_vec()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![backup_row(self)]
+    }
+}
+
+/// The dumps of `list-backups`.
+#[derive(Serialize)]
+#[serde(transparent)]
+pub(super) struct BackupList(pub Vec<BackupInfo>);
+
+impl Render for BackupList {
+    fn headers(&self) -> Vec<&str> {
+        BACKUP_HEADERS.to_vec()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.0.iter().map(backup_row).collect()
+    }
+}
+
+/// The files of `import-history`.
+#[derive(Serialize)]
+#[serde(transparent)]
+pub(super) struct ImportHistory(pub Vec<ImportRecord>);
+
+impl Render for ImportHistory {
+    fn headers(&self) -> Vec<&str> {
+        vec!["imported_at", "source", "table", "features", "sha256"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.0
+            .iter()
+            .map(|record| {
+                vec![
+                    record.imported_at.clone(),
+                    record.source_path.clone(),
+                    record.table_name.clone(),
+                    record.feature_count.to_string(),
+                    record.sha256.chars().take(12).collect(),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// The property keys of `infer-types`.
+#[derive(Serialize)]
+#[serde(transparent)]
+pub(super) struct PropertyTypes(pub Vec<PropertyTypeReport>);
+
+impl Render for PropertyTypes {
+    fn headers(&self) -> Vec<&str> {
+        vec!["json_key", "suggested_type", "json_types"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.0
+            .iter()
+            .map(|report| {
+                let json_types = report
+                    .json_types
+                    .iter()
+                    .map(|(json_type, count)| format!("{}: {}", json_type, count))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                vec![
+                    report.json_key.clone(),
+                    report.suggested_type.sql_name().to_string(),
+                    json_types,
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Render for DoctorReport {
+    fn headers(&self) -> Vec<&str> {
+        vec!["check", "status", "detail", "hint"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.checks
+            .iter()
+            .map(|check| {
+                let status = match check.status {
+                    CheckStatus::Pass => "pass",
+                    CheckStatus::Warn => "warn",
+                    CheckStatus::Fail => "fail",
+                };
+                vec![
+                    check.name.clone(),
+                    status.to_string(),
+                    check.detail.clone(),
+                    check.hint.clone().unwrap_or_default(),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Render for VersionInfo {
+    fn headers(&self) -> Vec<&str> {
+        vec!["item", "value"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.entries()
+            .into_iter()
+            .map(|(name, value)| vec![name.to_string(), value])
+            .collect()
+    }
+}
+
+impl Render for ExportReport {
+    fn headers(&self) -> Vec<&str> {
+        vec!["table", "path", "features", "bytes"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            self.table_name.clone(),
+            self.output_path.clone(),
+            self.features.to_string(),
+            self.bytes.to_string(),
+        ]]
+    }
+}
+
+/// The features of the tiles are not counted by PostGIS.
+impl Render for TileExport {
+    fn headers(&self) -> Vec<&str> {
+        vec!["table", "dir", "tiles", "empty_skipped", "bytes"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            self.table_name.clone(),
+            self.output_dir.clone(),
+            self.tiles_written.to_string(),
+            self.empty_skipped.to_string(),
+            self.bytes.to_string(),
+        ]]
+    }
+}
+
+/// One row per item, so the table reads as a report. `--output json` has
+/// the sections as nested objects and lists.
+impl Render for TableStats {
+    fn title(&self) -> Option<String> {
+        Some(format!("Stats of '{}'", self.table_name))
+    }
+
+    fn headers(&self) -> Vec<&str> {
+        vec!["item", "value"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        let size = |bytes: i64| backup::human_size(bytes.max(0) as u64);
+        let never = |at: &Option<String>| at.clone().unwrap_or_else(|| "never".to_string());
+        let mut rows = vec![
+            (
+                "estimated_rows",
+                self.estimated_rows
+                    .map_or("unknown, never analyzed".to_string(), |rows| {
+                        rows.to_string()
+                    }),
+            ),
+            ("table_size", size(self.sizes.table_bytes)),
+            ("toast_size", size(self.sizes.toast_bytes)),
+            ("indexes_size", size(self.sizes.indexes_bytes)),
+            ("total_size", size(self.sizes.total_bytes)),
+        ];
+        if let Some(column) = &self.geometry_column {
+            rows.push(("geometry_column", column.clone()));
+            rows.push((
+                "srid",
+                self.srid
+                    .map_or("none".to_string(), |srid| srid.to_string()),
+            ));
+            let types: Vec<String> = self
+                .geometry_types
+                .iter()
+                .map(|types| {
+                    format!(
+                        "{}: {}",
+                        types.geometry_type.as_deref().unwrap_or("NULL"),
+                        types.count
+                    )
+                })
+                .collect();
+            rows.push(("geometry_types", types.join(", ")));
+            rows.push((
+                "extent",
+                self.extent.map_or("empty".to_string(), |extent| {
+                    format!(
+                        "{}, {}, {}, {}",
+                        extent.min_x, extent.min_y, extent.max_x, extent.max_y
+                    )
+                }),
+            ));
+            if let Some(validity) = &self.validity {
+                rows.push((
+                    "invalid_geometries",
+                    format!("{} of {} sampled", validity.invalid, validity.sampled),
+                ));
+            }
+        }
+        for index in &self.indexes {
+            rows.push(("index", format!("{} ({})", index.name, size(index.bytes))));
+        }
+        rows.push(("last_vacuum", never(&self.maintenance.last_vacuum)));
+        rows.push(("last_autovacuum", never(&self.maintenance.last_autovacuum)));
+        rows.push(("last_analyze", never(&self.maintenance.last_analyze)));
+        rows.push((
+            "last_autoanalyze",
+            never(&self.maintenance.last_autoanalyze),
+        ));
+        if let Some(dead_rows) = self.maintenance.dead_rows {
+            rows.push(("dead_rows", dead_rows.to_string()));
+        }
+        rows.push((
+            "imports",
+            match self.imports.first() {
+                Some(last) => format!(
+                    "{}, last {} from {}",
+                    self.imports.len(),
+                    last.imported_at,
+                    last.source_path
+                ),
+                None => "none recorded".to_string(),
+            },
+        ));
+        rows.into_iter()
+            .map(|(item, value)| vec![item.to_string(), value])
+            .collect()
+    }
+}
+
+/// NULL is printed as an empty cell, as psql does.
+impl Render for StatementResult {
+    fn headers(&self) -> Vec<&str> {
+        self.columns.iter().map(String::as_str).collect()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|value| value.clone().unwrap_or_default())
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Print `result` on stdout in `format`.
+pub(super) fn print<R: Render>(
+    result: &R,
+    format: OutputFormat,
+) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(result)?),
+        OutputFormat::Csv => {
+            let headers = result.headers();
+            println!("{}", headers.join(","));
+            for row in result.rows() {
+                let fields: Vec<String> = row.iter().map(|field| csv::field(field)).collect();
+                println!("{}", fields.join(","));
+            }
+        }
+        OutputFormat::Table => print_table(result.title(), &result.headers(), &result.rows()),
+    }
+    Ok(())
+}
+
+/// Print a box-drawn table, each column as wide as its widest cell.
+fn print_table(title: Option<String>, headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers
+        .iter()
+        .map(|header| header.chars().count())
+        .collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    // A title spans the columns, and widens the last one if longer
+    let inner = |widths: &[usize]| widths.iter().map(|width| width + 3).sum::<usize>() - 3;
+    if let Some(title) = &title {
+        let title_width = title.chars().count();
+        let spanned = inner(&widths);
+        if let (true, Some(last)) = (title_width > spanned, widths.last_mut()) {
+            *last += title_width - spanned;
+        }
+    }
+    let line = |left: &str, middle: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|width| "─".repeat(width + 2)).collect();
+        format!("{}{}{}", left, segments.join(middle), right)
+    };
+    let cells = |row: &[String]| {
+        let cells: Vec<String> = widths
+            .iter()
+            .zip(row)
+            .map(|(width, cell)| format!(" {:<width$} ", cell, width = width))
+            .collect();
+        format!("│{}│", cells.join("│"))
+    };
+
+    println!();
+    if let Some(title) = title {
+        let inner = inner(&widths);
+        println!("┌{}┐", "─".repeat(inner + 2));
+        println!("│ {:<inner$} │", title, inner = inner);
+        println!("{}", line("├", "┬", "┤"));
+    } else {
+        println!("{}", line("┌", "┬", "┐"));
+    }
+    let headers: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+    println!("{}", cells(&headers));
+    println!("{}", line("├", "┼", "┤"));
+    for row in rows {
+        println!("{}", cells(row));
+    }
+    println!("{}", line("└", "┴", "┘"));
+}
@@ -0,0 +1,246 @@
+use super::output::{self, OutputFormat};
+use super::Interrupts;
+use crate::read::config::Config;
+use crate::read::db;
+use crate::read::queries::{execute_statement, DatabaseQueriesRead, PostgresQueriesRead};
+use crate::utils::sql;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::error::Error as StdError;
+use std::path::PathBuf;
+use tokio_postgres::{Client, NoTls};
+use tracing::{error, info, warn};
+
+/// File in the home directory keeping the statements entered between sessions.
+const HISTORY_FILE: &str = ".pgc_history";
+
+/// First keywords of the statements run without `--allow-writes`. Each of
+/// them runs in a transaction of its own, begun read only and rolled back,
+/// which refuses the writes they can hide, like `WITH ... DELETE` or
+/// `EXPLAIN ANALYZE UPDATE`, even after a
+/// `set_config('default_transaction_read_only', 'off', false)`.
+const READ_KEYWORDS: [&str; 6] = ["select", "with", "explain", "show", "values", "table"];
+
+const HELP: &str = "End statements with ; they may span lines. Ctrl-C cancels the running statement, or drops the one being typed.
+  \\dt         list the tables of the public schema
+  \\d [TABLE]  list the columns of TABLE, or the tables
+  \\?          show this help
+  \\q          quit, as Ctrl-D does";
+
+/// Read statements from the terminal and run them on the database of
+/// `config` until `\q` or Ctrl-D, printing their rows in `format`, for
+/// `pgc sql`. The shortcuts run with `read_queries`.
+///
+/// Without `allow_writes`, only the statements reading data are run, each in
+/// a read only transaction. `interrupts` cancels the running one.
+pub(super) async fn run(
+    read_queries: &PostgresQueriesRead,
+    config: &Config,
+    allow_writes: bool,
+    format: OutputFormat,
+    interrupts: &Interrupts,
+) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let db_name = &config.db_name;
+    let client = db::connect(config, db_name).await?;
+    let mut editor = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(history) = &history {
+        // There is none on the first run
+        let _ = editor.load_history(history);
+    }
+    info!(
+        "✅ Connected to {}{}. \\? lists the shortcuts, \\q quits",
+        db_name,
+        if allow_writes { "" } else { ", read only" }
+    );
+
+    let mut buffer = String::new();
+    loop {
+        let prompt = if buffer.is_empty() {
+            format!("{}=> ", db_name)
+        } else {
+            format!("{}-> ", db_name)
+        };
+        // Reading the terminal blocks, which the connection task must not wait on
+        let line = match tokio::task::block_in_place(|| editor.readline(&prompt)) {
+            Ok(line) => line,
+            // Drops the statement being typed, as in psql
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if buffer.is_empty() && line.trim_start().starts_with('\\') {
+            let _ = editor.add_history_entry(line.trim());
+            match shortcut(read_queries, line.trim(), format).await {
+                Ok(true) => continue,
+                Ok(false) => break,
+                Err(e) => error!("❌ {}", e),
+            }
+            continue;
+        }
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer.push_str(&line);
+        let (statements, rest) = sql::split_statements(&buffer);
+        if statements.is_empty() {
+            if buffer.trim().is_empty() {
+                buffer.clear();
+            }
+            continue;
+        }
+        let _ = editor.add_history_entry(buffer[..buffer.len() - rest.len()].trim());
+        let rest = rest.trim_start().to_string();
+        for statement in statements {
+            if let Err(e) =
+                run_statement(&client, &statement, allow_writes, format, interrupts).await
+            {
+                error!("❌ {}", e);
+            }
+            if client.is_closed() {
+                return Err("❌ Lost the connection to the database".into());
+            }
+        }
+        buffer = rest;
+    }
+
+    if let Some(history) = &history {
+        if let Err(e) = editor.save_history(history) {
+            warn!(
+                "⚠️ Failed to save the history in {}: {}",
+                history.display(),
+                e
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `HISTORY_FILE` in the home directory, if there is one.
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Run `statement`, cancelling it on the server on Ctrl-C, and print what it returned.
+async fn run_statement(
+    client: &Client,
+    statement: &str,
+    allow_writes: bool,
+    format: OutputFormat,
+    interrupts: &Interrupts,
+) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let keyword = first_keyword(statement);
+    if !allow_writes && !keyword.is_empty() && !READ_KEYWORDS.contains(&keyword.as_str()) {
+        return Err(format!(
+            "{} statements are refused without --allow-writes",
+            keyword.to_uppercase()
+        )
+        .into());
+    }
+    // A Ctrl-C after the previous statement completed is not for this one
+    interrupts.reset();
+    let cancellation = interrupts.token();
+    if !allow_writes {
+        // The simple protocol runs every statement of the string, a COMMIT
+        // ending the read only transaction among them should `split_statements`
+        // have missed a `;`. Preparing it, over the extended protocol, refuses
+        // more than one
+        client.prepare(statement).await?;
+        client.batch_execute("BEGIN READ ONLY").await?;
+    }
+    let results: Result<_, Box<dyn StdError + Send + Sync>> = async {
+        let query = execute_statement(client, statement);
+        tokio::pin!(query);
+        Ok(tokio::select! {
+            results = &mut query => results,
+            () = cancellation.cancelled() => {
+                client.cancel_token().cancel_query(NoTls).await?;
+                interrupts.reset();
+                // Postgres answers the statement with the cancellation error
+                query.await
+            }
+        }?)
+    }
+    .await;
+    if !allow_writes {
+        // Nothing was written, and the settings it changed go with the transaction
+        client.batch_execute("ROLLBACK").await?;
+    }
+    for result in results? {
+        if result.columns.is_empty() {
+            info!("✅ {} rows affected", result.rows_affected);
+        } else {
+            output::print(&result, format)?;
+        }
+    }
+    Ok(())
+}
+
+/// The first keyword of `statement`, past comments and opening parentheses,
+/// in lowercase.
+fn first_keyword(statement: &str) -> String {
+    let mut rest = statement;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '(');
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.split_once('\n').map_or("", |(_, after)| after);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, after)| after);
+        } else {
+            break;
+        }
+    }
+    rest.chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Run the backslash command `line`, returning whether to go on reading.
+async fn shortcut(
+    read_queries: &PostgresQueriesRead,
+    line: &str,
+    format: OutputFormat,
+) -> Result<bool, Box<dyn StdError + Send + Sync>> {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("\\q"), None, None) => return Ok(false),
+        (Some("\\?"), None, None) => println!("{}", HELP),
+        (Some("\\dt") | Some("\\d"), None, None) => {
+            let tables = read_queries.list_tables(Some(true)).await?;
+            output::print(&output::TableList(tables), format)?;
+        }
+        (Some("\\d"), Some(table), None) => {
+            let columns = read_queries.list_columns(table).await?;
+            if columns.is_empty() {
+                return Err(format!("No table named {}", table).into());
+            }
+            let columns = output::ColumnList {
+                table: table.to_string(),
+                columns,
+            };
+            output::print(&columns, format)?;
+        }
+        _ => return Err(format!("Unknown command {}, \\? lists them", line).into()),
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_first_keyword_is_read_past_comments_and_parentheses() {
+        assert_eq!(first_keyword("SELECT 1"), "select");
+        assert_eq!(first_keyword("  (\n(Select 1) UNION (SELECT 2)"), "select");
+        assert_eq!(first_keyword("-- a comment\nDELETE FROM points"), "delete");
+        assert_eq!(first_keyword("/* a\n comment */ with d AS (...)"), "with");
+        assert_eq!(first_keyword("drop_me()"), "drop_me");
+        assert_eq!(first_keyword("-- only a comment"), "");
+        assert_eq!(first_keyword("/* not closed"), "");
+    }
+}
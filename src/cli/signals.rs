@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+/// The cancellation of the command `pgc` runs, given to its query structs
+/// and cancelled by the first Ctrl-C or SIGTERM, see `listen`. Built in
+/// `main`, the only place signals are listened to.
+#[derive(Clone)]
+pub struct Interrupts {
+    /// The token of the running command, replaced by `reset`.
+    tokens: Arc<watch::Sender<CancellationToken>>,
+}
+
+impl Interrupts {
+    /// Turn the first Ctrl-C or SIGTERM into cancelling `token`: the
+    /// operations stop at their next batch, rolling back what they did not
+    /// commit and killing their child processes, and the process exits with
+    /// 130 if it still runs `grace` later. A second signal exits at once.
+    /// `reset` ends the wait, for a process going on once the cancelled
+    /// operation stopped, as `pgc sql` does.
+    pub fn listen(grace: Duration) -> Self {
+        let interrupts = Self {
+            tokens: Arc::new(watch::channel(CancellationToken::new()).0),
+        };
+        let mut receiver = interrupts.tokens.subscribe();
+        tokio::spawn(async move {
+            let mut signals = match Signals::new() {
+                Ok(signals) => signals,
+                Err(e) => {
+                    warn!("⚠️ Failed to listen to Ctrl-C and SIGTERM: {}", e);
+                    return;
+                }
+            };
+            while signals.next().await {
+                warn!("🛑 Interrupted, stopping... (again to exit at once)");
+                receiver.borrow_and_update().cancel();
+                tokio::select! {
+                    _ = signals.next() => warn!("🛑 Interrupted again, exiting"),
+                    _ = tokio::time::sleep(grace) => {
+                        warn!("🛑 Still running {:?} after the interruption, exiting", grace)
+                    }
+                    _ = receiver.changed() => continue,
+                }
+                std::process::exit(130);
+            }
+        });
+        interrupts
+    }
+
+    /// The token stopping the command, for its query structs.
+    pub fn token(&self) -> CancellationToken {
+        self.tokens.borrow().clone()
+    }
+
+    /// Whether the command was interrupted, which exits with 130 even when
+    /// it then stopped without an error.
+    pub fn interrupted(&self) -> bool {
+        self.tokens.borrow().is_cancelled()
+    }
+
+    /// Replace a cancelled token with a new one, for an operation run again
+    /// once the cancelled one stopped, as the next statement of `pgc sql`.
+    pub(super) fn reset(&self) {
+        self.tokens.send_if_modified(|token| {
+            let cancelled = token.is_cancelled();
+            if cancelled {
+                *token = CancellationToken::new();
+            }
+            cancelled
+        });
+    }
+}
+
+/// Ctrl-C and SIGTERM, as `Interrupts::listen` waits on them. Only Ctrl-C
+/// off Unix.
+struct Signals {
+    #[cfg(unix)]
+    interrupt: Signal,
+    #[cfg(unix)]
+    terminate: Signal,
+}
+
+impl Signals {
+    #[cfg(unix)]
+    fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> std::io::Result<Self> {
+        Ok(Self {})
+    }
+
+    /// Wait for the next signal, false when none can come anymore.
+    #[cfg(unix)]
+    async fn next(&mut self) -> bool {
+        tokio::select! {
+            received = self.interrupt.recv() => received.is_some(),
+            received = self.terminate.recv() => received.is_some(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    async fn next(&mut self) -> bool {
+        tokio::signal::ctrl_c().await.is_ok()
+    }
+}
@@ -0,0 +1,110 @@
+//! Load GeoJSON files and rasters into PostGIS, query and export the tables,
+//! and back up and restore the database, as the `pgc` command line does.
+//!
+//! The connection is read from the `[config]` section of a config file,
+//! given explicitly: nothing below the command line reads its arguments or
+//! picks a file, see `default_config_filename` for the one of `pgc`. The
+//! operations are the methods of `PostgresQueriesRead` and
+//! `PostgresQueriesWrite`, built on a pool of connections, `from_config`,
+//! `from_config_file` with the optional sections too, or `new` with one to
+//! share, taking their options as structs like `InsertGeoJsonOptions` and
+//! returning reports like `IngestReport`, exported from the root of the
+//! crate with the rest of the API. What is shown to the person running
+//! them, like the progress of a restore, goes to the `Reporter` of the query
+//! structs, nothing by default, see `ConsoleReporter`. The long operations
+//! stop once the `CancellationToken` of the query structs is cancelled, see
+//! `PostgresQueriesWrite::with_cancellation`: nothing in the library listens
+//! to signals.
+//!
+//! ```no_run
+//! use postgres_connect_rust::{
+//!     ConfigFile, DatabaseQueriesRead, DatabaseQueriesWrite, InsertGeoJsonOptions,
+//!     PostgresQueriesRead, PostgresQueriesWrite,
+//! };
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let write_queries =
+//!     PostgresQueriesWrite::from_config_file(ConfigFile::load("files/config.toml")?)?;
+//! let report = write_queries
+//!     .insert_geojson(
+//!         "data/parcels.geojson",
+//!         Some(InsertGeoJsonOptions::new().table("parcels").build()),
+//!     )
+//!     .await?;
+//! report.print_summary();
+//! let read_queries = PostgresQueriesRead::new(write_queries.pool().clone());
+//! let rows = read_queries.table_row_count("parcels", None).await?;
+//! println!("{} rows", rows);
+//! # Ok(())
+//! # }
+//! ```
+
+mod read;
+mod utils;
+mod write;
+
+/// The operations most called from synchronous code, blocking.
+#[cfg(feature = "blocking")]
+pub mod blocking;
+
+/// Fakes of the query traits, for the tests of the code calling them.
+#[cfg(feature = "testing")]
+pub mod testing;
+
+/// The `pgc` command line, for the binary.
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod cli;
+
+pub use read::args::default_config_filename;
+pub use read::config::{
+    write_starter_config, BackupConfig, Config, ConfigFile, Hook, HooksConfig, MemoryTier,
+    RestoreMemoryConfig, TableDefaults,
+};
+pub use read::db::{connect, pool_of};
+pub use read::dialect::{Dialect, SqlDialect};
+pub use read::queries::{DatabaseQueriesRead, PostgresQueriesRead};
+pub use read::reports::{
+    CollationDependentIndex, CollationStatus, ColumnInfo, ExportReport, GeoColumnInfo,
+    GeometryTypeCount, ImportRecord, IndexInfo, MaintenanceInfo, PropertyTypeReport, ServerInfo,
+    TableSizes, TableStats, TileExport, ValiditySample,
+};
+pub use read::row_ext::{rows_to, rows_to_structs, FromRow, RowExt};
+pub use utils::audit::{AuditEntry, AuditLog, AuditOutcome};
+pub use utils::error::{ErrorKind, KindError, PgcError};
+pub use utils::metrics::{HistogramBucket, HistogramSnapshot, Metrics, MetricsSnapshot};
+pub use utils::progress::{
+    ObserverEvent, ObserverHandle, OperationReport, ProgressObserver, ReporterObserver,
+};
+pub use utils::reporter::{ConsoleReporter, NoopReporter, ProgressEvent, Reporter};
+pub use utils::sql::{split_statements, PgType};
+pub use write::dry_run::DryRun;
+pub use write::options::{
+    BackupOptions, BackupScope, BackupSink, BoundingBox, ColumnKind, CompressionMethod,
+    CompressionSpec, Dedupe, DumpFormat, ExportOptions, GeoTableOptions, GeometryType,
+    IngestOptions, InsertGeoJsonOptions, InsertGeoJsonOptionsBuilder, InsertStrategy, LoadMode,
+    OnFeatureError, PostRestoreOptions, PreClean, PrimaryKey, RasterMode, RasterOptions,
+    RestoreOptions, RetentionPolicy, WatchOptions,
+};
+pub use write::queries::{DatabaseQueriesWrite, PostgresQueriesWrite};
+pub use write::reports::{
+    BackupInfo, BackupVerification, CollationRefresh, DropOutcome, FileReport, IngestError,
+    IngestReport, LogicalBackup, LogicalTable, MaterializedColumn, RasterImport, SimplifiedTable,
+    StreamedBackup, ValidationReport, WatchFailure, WatchReport,
+};
+pub use write::source::{GeoJsonFeatures, GeoJsonFormat, GeoJsonSource, GeoJsonSummary};
+pub use write::utils::{
+    geometry_to_wkt, geometry_to_wkt_with_precision, parse_geojson_features, write_wkt,
+    FeatureFilter, PatchMode,
+};
+pub use write::validate::validate_geojson;
+
+/// The token stopping the operations of the query structs, see
+/// `PostgresQueriesWrite::with_cancellation`.
+pub use tokio_util::sync::CancellationToken;
+
+/// The steps of the ingest pipeline that the benchmarks of `benches/` time
+/// on their own, not part of the API.
+#[doc(hidden)]
+pub use write::utils::{feature_csv_line, for_each_geojson_feature_in};
@@ -1,23 +1,24 @@
-use std::env;
+use std::path::Path;
 
-pub struct Args {
-    pub config_filename: String,
-}
-
-impl Args {
-    pub fn new() -> Args {
-        let args: Vec<String> = env::args().collect();
+/// The environment variable naming the config file read when `--config`
+/// gives none.
+pub(crate) const CONFIG_ENV: &str = "PGC_CONFIG";
 
-        // Use the provided config filename or default to "files/config.toml"
-        if args.len() >= 2 {
-            Args {
-                config_filename: args[1].to_string(),
-            }
-        } else {
-            println!("⚠️ No config file specified, using default: files/config.toml");
-            Args {
-                config_filename: String::from("files/config.toml"),
-            }
-        }
+/// The config file read when none is given: `$PGC_CONFIG`, or else
+/// `files/config.toml` next to the executable, wherever it is run from.
+pub fn default_config_filename() -> String {
+    if let Some(config_filename) = std::env::var_os(CONFIG_ENV).filter(|value| !value.is_empty()) {
+        return config_filename.to_string_lossy().into_owned();
+    }
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    match exe_dir {
+        Some(exe_dir) => exe_dir
+            .join("files")
+            .join("config.toml")
+            .display()
+            .to_string(),
+        None => String::from("files/config.toml"),
     }
 }
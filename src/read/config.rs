@@ -1,29 +1,396 @@
+use crate::read::dialect::Dialect;
+use crate::utils::error::PgcError;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
+use std::error::Error as StdError;
 use std::fs;
-use toml;
+use std::io::Write;
 
+/// The `[config]` section of the config file.
+///
+/// * `pg_dump_path`, `pg_restore_path`: The `pg_dump` and `pg_restore`
+///   binaries used by `backup_database` and `restore_database`. Default to
+///   the ones found on `PATH`.
+/// * `jobs`: The number of jobs of the operations running in parallel, as
+///   `--jobs` sets it. Defaults to one per CPU, up to 4.
+/// * `audit_log`: A file the write queries append a JSON line to for each
+///   operation they complete or fail, see `AuditLog`. None by default.
+/// * `dialect`: The server the queries run on, `postgres` or `cockroach`,
+///   see `Dialect`. Detected from `version()` by default.
 #[allow(unused)]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub host: String,
     pub port: u16,
     pub user: String,
     pub password: String,
     pub db_name: String,
+    pub pg_dump_path: Option<String>,
+    pub pg_restore_path: Option<String>,
+    pub jobs: Option<usize>,
+    pub audit_log: Option<String>,
+    pub dialect: Option<Dialect>,
 }
 
 impl Config {
-    pub fn new(config_filename: String) -> Config {
-        // Read the TOML file
-        let config_data = fs::read_to_string(config_filename).expect("Unable to read file");
+    /// The `[config]` section of `config_filename`.
+    ///
+    /// ```no_run
+    /// let config = postgres_connect_rust::Config::load("files/config.toml")?;
+    /// println!("{}@{}:{}/{}", config.user, config.host, config.port, config.db_name);
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn load(config_filename: &str) -> Result<Config, String> {
+        config_section(&read_toml(config_filename)?, config_filename)
+    }
+}
+
+/// The `[config]` section of the parsed `config_filename`.
+fn config_section(toml: &toml::Value, config_filename: &str) -> Result<Config, String> {
+    toml.get("config")
+        .ok_or_else(|| format!("No [config] section in {}", config_filename))?
+        .clone()
+        .try_into()
+        .map_err(|e| format!("Unable to deserialize config: {}", e))
+}
+
+/// Every section of a config file: the connection of `[config]`, and the
+/// optional sections, their defaults when the file has none.
+///
+/// `PostgresQueriesWrite::from_config_file` reads its hooks, backup
+/// exclusions, table defaults and restore memory from it.
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    pub config: Config,
+    pub backup: BackupConfig,
+    pub hooks: HooksConfig,
+    pub table_defaults: TableDefaults,
+    pub restore_memory: RestoreMemoryConfig,
+}
+
+impl ConfigFile {
+    /// Read every section of `config_filename`, failing on a missing file,
+    /// a missing `[config]` section or an invalid one.
+    ///
+    /// ```no_run
+    /// let config_file = postgres_connect_rust::ConfigFile::load("files/config.toml")?;
+    /// println!("{} post_ingest hooks", config_file.hooks.post_ingest.len());
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn load(config_filename: &str) -> Result<ConfigFile, String> {
+        let toml = read_toml(config_filename)?;
+        Ok(ConfigFile {
+            config: config_section(&toml, config_filename)?,
+            backup: optional_section(&toml, "backup")?,
+            hooks: optional_section(&toml, "hooks")?,
+            table_defaults: optional_section(&toml, "table_defaults")?,
+            restore_memory: optional_section(&toml, "restore_memory")?,
+        })
+    }
+}
+
+impl From<Config> for ConfigFile {
+    /// The connection of `config`, with the defaults of the optional sections.
+    fn from(config: Config) -> Self {
+        ConfigFile {
+            config,
+            backup: BackupConfig::default(),
+            hooks: HooksConfig::default(),
+            table_defaults: TableDefaults::default(),
+            restore_memory: RestoreMemoryConfig::default(),
+        }
+    }
+}
+
+/// The optional sections of a config file written by `write_starter_config`,
+/// commented out, after its `[config]` section.
+const OPTIONAL_SECTIONS: &str = r#"# pg_dump_path = "/usr/lib/postgresql/16/bin/pg_dump"
+# pg_restore_path = "/usr/lib/postgresql/16/bin/pg_restore"
+# Jobs of the operations running in parallel, as --jobs sets them
+# jobs = 4
+# A JSON line per operation of the write queries, for auditing who dropped what
+# audit_log = "/var/log/pgc/audit.jsonl"
+# The server the queries run on, detected from version() when not given
+# dialect = "cockroach"
+
+# Every backup leaves out these tables and schemas, on top of the PostGIS
+# ones. `pgc backup` writes to `dir` when given no directory.
+# [backup]
+# exclude_tables = ["tiles_cache", "tmp_*"]
+# exclude_schemas = ["scratch*"]
+# dir = "/backups"
+
+# SQL snippets or shell commands run after loading a table, after a restore
+# and before dropping a table.
+# [hooks]
+# continue_on_hook_error = false
+# post_ingest = [
+#     { sql = "CREATE INDEX IF NOT EXISTS ON {table} ((properties->>'id'))" },
+#     { command = "echo \"loaded $PGC_TABLE into $PGC_DATABASE\"" },
+# ]
+# post_restore = [{ sql = "ANALYZE" }]
+# pre_drop = [{ command = "echo \"dropping $PGC_TABLE\"" }]
+
+# Memory of the pg_restore sessions, picked by the memory of the machine.
+# [restore_memory]
+# maintenance_work_mem = "2GB"
+# tiers = [
+#     { min_memory_gb = 0, work_mem = "64MB", maintenance_work_mem = "256MB" },
+#     { min_memory_gb = 32, work_mem = "256MB", maintenance_work_mem = "1GB" },
+# ]
+
+# Grants on every table created by insert_geojson.
+# [table_defaults]
+# grants = [["api_reader", "SELECT"]]
+"#;
+
+/// A config file connecting as `config`, with the optional sections commented out.
+pub(crate) fn starter_config(config: &Config) -> String {
+    // Quoted and escaped as TOML strings, as a password may hold anything
+    let quoted = |value: &str| toml::Value::String(value.to_string()).to_string();
+    format!(
+        "# Written by `pgc config init`. See files/config.toml in the repository\n\
+         # for every option.\n\
+         [config]\n\
+         host = {}\n\
+         port = {}\n\
+         user = {}\n\
+         password = {}\n\
+         db_name = {}\n\
+         {}",
+        quoted(&config.host),
+        config.port,
+        quoted(&config.user),
+        quoted(&config.password),
+        quoted(&config.db_name),
+        OPTIONAL_SECTIONS
+    )
+}
+
+/// Write `starter_config(config)` to `path`, readable by its owner only
+/// since it holds the password. An existing file is an error unless
+/// `overwrite`.
+pub fn write_starter_config(
+    path: &str,
+    config: &Config,
+    overwrite: bool,
+) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let path = std::path::Path::new(path);
+    if path.exists() && !overwrite {
+        return Err(PgcError::usage(format!(
+            "❌ {} already exists: pass --force to overwrite it",
+            path.display()
+        ))
+        .into());
+    }
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // The mode only applies to a new file
+    #[cfg(unix)]
+    fs::set_permissions(path, std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(starter_config(config).as_bytes())?;
+    Ok(())
+}
+
+/// A command run around an operation, written in the config as
+/// `{ sql = "..." }` or `{ command = "..." }`.
+///
+/// * `Sql`: A SQL snippet executed on a database connection. `{table}` and
+///   `{database}` are replaced by the quoted table and database names.
+/// * `Command`: A shell command, run by `sh -c`, or `cmd /C` on Windows. The
+///   table and database names are passed in the `PGC_TABLE` and
+///   `PGC_DATABASE` environment variables, and the hook stage in `PGC_HOOK`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "HookEntry")]
+pub enum Hook {
+    Sql(String),
+    Command(String),
+}
+
+/// A hook as written in the config file, before checking that it sets
+/// exactly one of its keys.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HookEntry {
+    sql: Option<String>,
+    command: Option<String>,
+}
+
+impl TryFrom<HookEntry> for Hook {
+    type Error = String;
+
+    fn try_from(entry: HookEntry) -> Result<Self, Self::Error> {
+        match (entry.sql, entry.command) {
+            (Some(snippet), None) => Ok(Hook::Sql(snippet)),
+            (None, Some(command)) => Ok(Hook::Command(command)),
+            _ => Err("a hook must set exactly one of `sql` or `command`".to_string()),
+        }
+    }
+}
+
+/// The `[hooks]` section of the config file.
+///
+/// * `post_ingest`: Run after `insert_geojson` loaded a table.
+/// * `post_restore`: Run after `restore_database` restored the database.
+/// * `pre_drop`: Run before `drop` drops a table.
+/// * `continue_on_hook_error`: If true, a failing hook is reported and the
+///   remaining hooks still run, without failing the operation. Defaults to false.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub post_ingest: Vec<Hook>,
+    pub post_restore: Vec<Hook>,
+    pub pre_drop: Vec<Hook>,
+    pub continue_on_hook_error: bool,
+}
+
+/// The `[backup]` section of the config file, the exclusions of every
+/// backup on top of those of `BackupOptions`.
+///
+/// * `exclude_tables`: tables left out of the dump, along with the tables
+///   owned by PostGIS, e.g. `exclude_tables = ["tiles_cache", "tmp_*"]`.
+/// * `exclude_schemas`: schemas left out of the dump, e.g.
+///   `exclude_schemas = ["scratch*"]`.
+/// * `dir`: directory `pgc backup` writes to when given none, whose free
+///   space `doctor` checks.
+///
+/// A name with a `*` or `?` is a pattern, as `pg_dump` reads them, and may be
+/// qualified by its schema: `staging.*`. Other names are matched as they are.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub exclude_tables: Vec<String>,
+    pub exclude_schemas: Vec<String>,
+    pub dir: Option<String>,
+}
+
+/// The `[table_defaults]` section of the config file, applied to the tables
+/// `create_geo_table` creates unless `GeoTableOptions` say otherwise.
+///
+/// * `grants`: `(role, privileges)` pairs granted on every new table, e.g.
+///   `grants = [["api_reader", "SELECT"]]`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TableDefaults {
+    pub grants: Vec<(String, String)>,
+}
+
+/// The memory settings `restore_database` uses from `min_memory_gb` of
+/// memory on.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MemoryTier {
+    pub min_memory_gb: u64,
+    pub work_mem: String,
+    pub maintenance_work_mem: String,
+}
 
-        // Parse the data into the struct
-        let config: toml::Value = toml::de::from_str(&config_data).expect("Unable to parse TOML");
+/// The `[restore_memory]` section of the config file: the `work_mem` and
+/// `maintenance_work_mem` of the sessions of `pg_restore`.
+///
+/// * `tiers`: Settings picked by the memory of the machine, from the tier
+///   with the highest `min_memory_gb` it reaches, e.g.
+///   `tiers = [{ min_memory_gb = 0, work_mem = "64MB", maintenance_work_mem = "128MB" }]`.
+///   Defaults to 128MB and 256MB, 256MB and 512MB from 32GB, and 512MB and
+///   1GB from 64GB.
+/// * `work_mem`, `maintenance_work_mem`: Used as they are, whatever the
+///   memory of the machine.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RestoreMemoryConfig {
+    pub tiers: Vec<MemoryTier>,
+    pub work_mem: Option<String>,
+    pub maintenance_work_mem: Option<String>,
+}
 
-        // Access the "person" section
-        let read_config: Config = toml::de::from_str(&config["config"].to_string())
-            .expect("\n\nUnable to deserialize config\n\n");
+impl Default for RestoreMemoryConfig {
+    fn default() -> Self {
+        let tier = |min_memory_gb, work_mem: &str, maintenance_work_mem: &str| MemoryTier {
+            min_memory_gb,
+            work_mem: work_mem.to_string(),
+            maintenance_work_mem: maintenance_work_mem.to_string(),
+        };
+        Self {
+            tiers: vec![
+                tier(0, "128MB", "256MB"),
+                tier(32, "256MB", "512MB"),
+                tier(64, "512MB", "1GB"),
+            ],
+            work_mem: None,
+            maintenance_work_mem: None,
+        }
+    }
+}
 
-        return read_config;
+impl RestoreMemoryConfig {
+    /// The `work_mem` and `maintenance_work_mem` for a machine with
+    /// `total_memory_kb` of memory, `None` for those left to the server.
+    pub fn settings(&self, total_memory_kb: u64) -> (Option<String>, Option<String>) {
+        let tier = self
+            .tiers
+            .iter()
+            .filter(|tier| total_memory_kb >= tier.min_memory_gb * 1024 * 1024)
+            .max_by_key(|tier| tier.min_memory_gb);
+        (
+            self.work_mem
+                .clone()
+                .or_else(|| tier.map(|tier| tier.work_mem.clone())),
+            self.maintenance_work_mem
+                .clone()
+                .or_else(|| tier.map(|tier| tier.maintenance_work_mem.clone())),
+        )
+    }
+}
+
+/// The config file, parsed.
+fn read_toml(config_filename: &str) -> Result<toml::Value, String> {
+    let config_data = fs::read_to_string(config_filename)
+        .map_err(|e| format!("Unable to read file {}: {}", config_filename, e))?;
+    toml::de::from_str(&config_data).map_err(|e| format!("Unable to parse TOML: {}", e))
+}
+
+/// Deserialize a section of the parsed config file, or its default if it is
+/// missing.
+fn optional_section<T: DeserializeOwned + Default>(
+    toml: &toml::Value,
+    section: &str,
+) -> Result<T, String> {
+    match toml.get(section) {
+        Some(value) => value
+            .clone()
+            .try_into()
+            .map_err(|e| format!("Unable to deserialize {}: {}", section, e)),
+        None => Ok(T::default()),
+    }
+}
+
+/// Check every section of the config file, as `doctor` does: the
+/// `[config]` section it must have, and the optional ones it may have.
+pub(crate) fn validate_file(config_filename: &str) -> Result<ConfigFile, String> {
+    let config_file = ConfigFile::load(config_filename)?;
+    let config = &config_file.config;
+    for (key, value) in [
+        ("host", &config.host),
+        ("user", &config.user),
+        ("db_name", &config.db_name),
+    ] {
+        if value.trim().is_empty() {
+            return Err(format!("`{}` of [config] is empty", key));
+        }
+    }
+    if config.port == 0 {
+        return Err("`port` of [config] is 0".to_string());
+    }
+    if config.jobs == Some(0) {
+        return Err("`jobs` of [config] must be at least 1".to_string());
     }
+    Ok(config_file)
 }
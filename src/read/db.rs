@@ -1,58 +1,112 @@
 use crate::read;
-use deadpool_postgres::{Manager, Pool, PoolError};
+use crate::utils::error::PgcError;
+use deadpool_postgres::{BuildError, Manager, Object, Pool, PoolError};
 use tokio_postgres::{Config, Error, NoTls};
+use tracing::error;
 
-pub async fn new(
-    pool: Option<bool>,
-) -> Result<(tokio_postgres::Client, Option<Pool>), Box<dyn std::error::Error>> {
-    let config = read::Read::config_data().config;
-    let use_pool = pool.unwrap_or(false);
-    println!("Using pool: {}", use_pool);
-
-    let connection_string = format!(
-        "host={} port={} user={} password={} dbname={}",
-        config.host, config.port, config.user, config.password, config.db_name
-    );
-    let (client, connection) = tokio_postgres::connect(&connection_string, NoTls)
-        .await
-        .expect("❌ Failed to connect to database!!");
-
-    println!("✅ Connected to database: {}", connection_string);
-    tokio::spawn(async move {
-        if let Err(e) = connection.await {
-            eprintln!("❌ connection error: {}", e);
-        }
-    });
+/// The settings of a connection to `db_name` with the credentials of
+/// `config`, built field by field, so passwords with spaces or quotes need
+/// no escaping.
+fn pg_config(config: &read::config::Config, db_name: &str) -> Config {
+    let mut pg_config = Config::new();
+    pg_config.host(&config.host);
+    pg_config.port(config.port);
+    pg_config.user(&config.user);
+    pg_config.password(&config.password);
+    pg_config.dbname(db_name);
+    pg_config
+}
 
-    if use_pool {
-        let mut cfg = Config::new();
-        cfg.host(&config.host);
-        cfg.user(&config.user);
-        cfg.password(&config.password);
-        cfg.dbname(&config.db_name);
-        let manager = Manager::new(cfg, NoTls);
-        let pool = Pool::builder(manager)
-            .max_size(16)
-            .build()
-            .expect("❌ Failed to build pool");
-        Ok((client, Some(pool)))
+/// The error of connecting to `db_name`. One the server answered, like a
+/// wrong password or a missing database, is a config problem, retrying
+/// does not help it.
+fn connect_error(db_name: &str, e: Error) -> PgcError {
+    let message = format!("❌ Failed to connect to database {}: {}", db_name, e);
+    if e.code().is_some() {
+        PgcError::Config { message }
     } else {
-        Ok((client, None))
+        PgcError::Connection {
+            message,
+            source: Some(e),
+        }
     }
 }
 
-pub async fn new_pool() -> Result<Pool, Box<dyn std::error::Error>> {
-    let config = read::Read::config_data().config;
-    let mut cfg = Config::new();
-    cfg.host(&config.host);
-    cfg.user(&config.user);
-    cfg.password(&config.password);
-    cfg.dbname(&config.db_name);
+/// The error of building a pool, which only an invalid config can cause.
+fn pool_error(e: BuildError) -> PgcError {
+    PgcError::Config {
+        message: format!("❌ Failed to build the connection pool: {}", e),
+    }
+}
 
-    let manager = Manager::new(cfg, NoTls);
-    let pool = Pool::builder(manager)
+/// A pool of up to 16 connections to the database of `config`. Nothing
+/// connects until a connection is checked out.
+///
+/// ```no_run
+/// use postgres_connect_rust::{pool_of, Config, PostgresQueriesRead};
+///
+/// # fn queries() -> Result<(), Box<dyn std::error::Error>> {
+/// let pool = pool_of(&Config::load("files/config.toml")?)?;
+/// let read_queries = PostgresQueriesRead::new(pool.clone());
+/// # Ok(())
+/// # }
+/// ```
+pub fn pool_of(config: &read::config::Config) -> Result<Pool, PgcError> {
+    let manager = Manager::new(pg_config(config, &config.db_name), NoTls);
+    Pool::builder(manager)
         .max_size(16) // Adjust based on your needs
-        .build()?;
+        .build()
+        .map_err(pool_error)
+}
+
+/// A connection of `pool`, failing as `connect` does when none can be made.
+pub(crate) async fn checkout(pool: &Pool) -> Result<Object, PgcError> {
+    pool.get().await.map_err(|e| match e {
+        PoolError::Backend(e) => connect_error("of the pool", e),
+        e => e.into(),
+    })
+}
 
-    Ok(pool)
+/// A client connected to `db_name` with the credentials of `config`, which
+/// may name another database. Fails instead of panicking, as the database
+/// may not exist.
+///
+/// ```no_run
+/// use postgres_connect_rust::{connect, Config};
+///
+/// # async fn query() -> Result<(), Box<dyn std::error::Error>> {
+/// let config = Config::load("files/config.toml")?;
+/// let client = connect(&config, "postgres_db").await?;
+/// let rows = client.query("SELECT name FROM parcels", &[]).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn connect(
+    config: &read::config::Config,
+    db_name: &str,
+) -> Result<tokio_postgres::Client, PgcError> {
+    connect_as(config, db_name, None).await
+}
+
+/// `connect`, with the `options` of the session, as `PGOPTIONS` would set
+/// them, e.g. `-c work_mem=256MB`.
+pub(crate) async fn connect_as(
+    config: &read::config::Config,
+    db_name: &str,
+    options: Option<&str>,
+) -> Result<tokio_postgres::Client, PgcError> {
+    let mut connect_config = pg_config(config, db_name);
+    if let Some(options) = options {
+        connect_config.options(options);
+    }
+    let (client, connection) = connect_config
+        .connect(NoTls)
+        .await
+        .map_err(|e| connect_error(db_name, e))?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            error!("❌ connection error: {}", e);
+        }
+    });
+    Ok(client)
 }
@@ -0,0 +1,294 @@
+use crate::read::row_ext::RowExt;
+use crate::utils::error::PgcError;
+use crate::utils::sql;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tokio_postgres::Client;
+
+/// The server speaking the Postgres protocol the queries run on, as the
+/// `dialect` of the config sets it, or else detected from `version()`.
+///
+/// * `Postgres`: PostgreSQL with PostGIS, the default.
+/// * `Cockroach`: CockroachDB, whose spatial types are built in, with
+///   inverted rather than GIN indexes and without the `pg_stat_*` views,
+///   `VACUUM`, collation versions, or `pg_dump` and `pg_restore`.
+///
+/// The statements that differ go through `Dialect::sql`; the operations that
+/// cannot work fail with `PgcError::UnsupportedOnDialect`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    #[default]
+    Postgres,
+    Cockroach,
+}
+
+impl Dialect {
+    /// The dialect of the server whose `SELECT version()` is `version`.
+    pub fn from_version(version: &str) -> Dialect {
+        if version.contains("CockroachDB") {
+            Dialect::Cockroach
+        } else {
+            Dialect::Postgres
+        }
+    }
+
+    /// The statements of this dialect.
+    pub fn sql(self) -> &'static dyn SqlDialect {
+        match self {
+            Dialect::Postgres => &PostgresSql,
+            Dialect::Cockroach => &CockroachSql,
+        }
+    }
+
+    /// Fail `operation` unless `supported` by this dialect.
+    pub fn require(
+        self,
+        operation: &str,
+        supported: fn(&dyn SqlDialect) -> bool,
+    ) -> Result<(), PgcError> {
+        if supported(self.sql()) {
+            Ok(())
+        } else {
+            Err(PgcError::UnsupportedOnDialect {
+                operation: operation.to_string(),
+                dialect: self,
+            })
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Dialect::Postgres => "PostgreSQL",
+            Dialect::Cockroach => "CockroachDB",
+        })
+    }
+}
+
+/// `configured`, or else the dialect of the server `client` is connected to.
+pub(crate) async fn resolve(
+    configured: Option<Dialect>,
+    client: &Client,
+) -> Result<Dialect, PgcError> {
+    match configured {
+        Some(dialect) => Ok(dialect),
+        None => Ok(Dialect::from_version(
+            &client
+                .query_one("SELECT version()", &[])
+                .await?
+                .try_get_string(0)?,
+        )),
+    }
+}
+
+/// The statements that differ between the dialects, those of PostgreSQL by
+/// default. Table names are given unquoted.
+pub trait SqlDialect: Send + Sync {
+    /// The statement installing PostGIS, `None` when spatial types are built in.
+    fn create_postgis(&self) -> Option<&'static str> {
+        Some("CREATE EXTENSION IF NOT EXISTS postgis;")
+    }
+
+    /// A query of one boolean, whether PostGIS is available.
+    fn postgis_check(&self) -> &'static str {
+        "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'postgis')"
+    }
+
+    /// The index `index` of the JSONB `column` of `table`, for `@>` filters.
+    fn json_index(&self, index: &str, table: &str, column: &str) -> Result<String, PgcError> {
+        Ok(format!(
+            "CREATE INDEX IF NOT EXISTS {} ON {} USING GIN ({});",
+            sql::ident(index)?,
+            sql::ident(table)?,
+            sql::ident(column)?
+        ))
+    }
+
+    /// The index `index` of the spatial `column` of `table`.
+    fn spatial_index(&self, index: &str, table: &str, column: &str) -> Result<String, PgcError> {
+        Ok(format!(
+            "CREATE INDEX IF NOT EXISTS {} ON {} USING GIST ({});",
+            sql::ident(index)?,
+            sql::ident(table)?,
+            sql::ident(column)?
+        ))
+    }
+
+    /// The tables of `tables` ($2) in `schema` ($1), with the rows the
+    /// statistics estimate they have, negative when unknown.
+    fn estimated_rows_query(&self) -> &'static str {
+        "SELECT c.relname::text, c.reltuples::bigint FROM pg_class c
+         JOIN pg_namespace n ON n.oid = c.relnamespace
+         WHERE n.nspname = $1 AND c.relname = ANY($2)"
+    }
+
+    /// The bytes of `table`, its TOAST and its indexes, their total, and the
+    /// rows the statistics estimate it has.
+    fn sizes_query(&self, table: &str) -> Result<String, PgcError> {
+        Ok(format!(
+            "SELECT pg_relation_size(c.oid), pg_table_size(c.oid) - pg_relation_size(c.oid),
+                pg_indexes_size(c.oid), pg_total_relation_size(c.oid), c.reltuples::bigint
+            FROM pg_class c WHERE c.oid = {}",
+            regclass(table)?
+        ))
+    }
+
+    /// The name, definition and bytes of the indexes of `table`, largest
+    /// first.
+    fn indexes_query(&self, table: &str) -> Result<String, PgcError> {
+        Ok(format!(
+            "SELECT i.indexrelid::regclass::text, pg_get_indexdef(i.indexrelid),
+                pg_relation_size(i.indexrelid)
+            FROM pg_index i WHERE i.indrelid = {}
+            ORDER BY 3 DESC, 1",
+            regclass(table)?
+        ))
+    }
+
+    /// When `table` was last vacuumed and analyzed, and its dead rows.
+    /// `None` without the statistics views.
+    fn maintenance_query(&self, table: &str) -> Result<Option<String>, PgcError> {
+        Ok(Some(format!(
+            "SELECT last_vacuum::text, last_autovacuum::text, last_analyze::text,
+                last_autoanalyze::text, n_dead_tup
+            FROM pg_stat_all_tables WHERE relid = {}",
+            regclass(table)?
+        )))
+    }
+
+    /// The count of the non NULL `geometry` of about `percent` of the rows
+    /// of `table`, picked at random, and of those that are invalid.
+    fn validity_query(
+        &self,
+        table: &str,
+        geometry: &str,
+        percent: f64,
+    ) -> Result<String, PgcError> {
+        Ok(format!(
+            "SELECT count(*), count(*) FILTER (WHERE NOT ST_IsValid({g}))
+            FROM {} t TABLESAMPLE BERNOULLI ({}) WHERE {g} IS NOT NULL",
+            sql::ident(table)?,
+            percent,
+            g = geometry
+        ))
+    }
+
+    /// Whether `pg_dump` and `pg_restore` can back up and restore it.
+    fn supports_pg_dump(&self) -> bool {
+        true
+    }
+
+    /// Whether it has `VACUUM`.
+    fn supports_vacuum(&self) -> bool {
+        true
+    }
+
+    /// Whether it records the versions of its collations.
+    fn supports_collation_versions(&self) -> bool {
+        true
+    }
+}
+
+/// The statements of PostgreSQL.
+struct PostgresSql;
+
+impl SqlDialect for PostgresSql {}
+
+/// The statements of CockroachDB.
+struct CockroachSql;
+
+/// The rows `validity` samples on CockroachDB, which has no `TABLESAMPLE`.
+const COCKROACH_SAMPLE_ROWS: u32 = 1000;
+
+impl SqlDialect for CockroachSql {
+    fn create_postgis(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn postgis_check(&self) -> &'static str {
+        "SELECT true"
+    }
+
+    fn json_index(&self, index: &str, table: &str, column: &str) -> Result<String, PgcError> {
+        Ok(format!(
+            "CREATE INVERTED INDEX IF NOT EXISTS {} ON {} ({});",
+            sql::ident(index)?,
+            sql::ident(table)?,
+            sql::ident(column)?
+        ))
+    }
+
+    fn estimated_rows_query(&self) -> &'static str {
+        "SELECT s.table_name::text, s.estimated_row_count::INT8
+         FROM crdb_internal.table_row_statistics s
+         JOIN crdb_internal.tables t ON t.table_id = s.table_id
+         WHERE t.database_name = current_database() AND t.schema_name = $1
+         AND s.table_name = ANY($2)"
+    }
+
+    /// The bytes of the ranges of the table, its indexes included, as the
+    /// size of the table and the total.
+    fn sizes_query(&self, table: &str) -> Result<String, PgcError> {
+        Ok(format!(
+            "SELECT r.bytes, 0::INT8, 0::INT8, r.bytes,
+                COALESCE((SELECT estimated_row_count::INT8
+                    FROM crdb_internal.table_row_statistics
+                    WHERE table_id = {}::oid::INT8), -1)
+            FROM (SELECT COALESCE(sum(range_size), 0)::INT8 AS bytes
+                FROM [SHOW RANGES FROM TABLE {} WITH DETAILS]) r",
+            regclass(table)?,
+            sql::ident(table)?
+        ))
+    }
+
+    /// Their sizes are not known, so by name.
+    fn indexes_query(&self, table: &str) -> Result<String, PgcError> {
+        Ok(format!(
+            "SELECT indexname::text, indexdef::text, 0::INT8 FROM pg_indexes
+            WHERE schemaname = current_schema() AND tablename = {}
+            ORDER BY 1",
+            sql::literal(table)
+        ))
+    }
+
+    fn maintenance_query(&self, _table: &str) -> Result<Option<String>, PgcError> {
+        Ok(None)
+    }
+
+    fn validity_query(
+        &self,
+        table: &str,
+        geometry: &str,
+        _percent: f64,
+    ) -> Result<String, PgcError> {
+        Ok(format!(
+            "SELECT count(*), count(*) FILTER (WHERE NOT ST_IsValid(g))
+            FROM (SELECT {g} AS g FROM {} t WHERE {g} IS NOT NULL ORDER BY random() LIMIT {})",
+            sql::ident(table)?,
+            COCKROACH_SAMPLE_ROWS,
+            g = geometry
+        ))
+    }
+
+    fn supports_pg_dump(&self) -> bool {
+        false
+    }
+
+    fn supports_vacuum(&self) -> bool {
+        false
+    }
+
+    fn supports_collation_versions(&self) -> bool {
+        false
+    }
+}
+
+/// The table as a `regclass` literal, resolved in the search path.
+pub(crate) fn regclass(table_name: &str) -> Result<String, PgcError> {
+    Ok(format!(
+        "{}::regclass",
+        sql::literal(&sql::ident(table_name)?)
+    ))
+}
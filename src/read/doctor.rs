@@ -0,0 +1,441 @@
+use crate::read::config::{self, Config};
+use crate::read::db;
+use crate::read::queries::server_info_of;
+use crate::read::reports::{Check, CheckStatus, DoctorReport, ServerInfo};
+use crate::read::row_ext::RowExt;
+use crate::utils::error::PgcError;
+use crate::utils::format::human_size;
+#[cfg(feature = "backup")]
+use crate::write::backup;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio_postgres::Client;
+use tracing::info;
+
+/// How long the connection check waits for the server.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Round trips slower than this are reported, as every statement pays them.
+const SLOW_ROUND_TRIP: Duration = Duration::from_millis(100);
+
+/// Schema the tables are created in.
+const SCHEMA: &str = "public";
+
+fn check(name: &str, status: CheckStatus, detail: String, hint: Option<&str>) -> Check {
+    Check {
+        name: name.to_string(),
+        status,
+        detail,
+        hint: hint.map(str::to_string),
+    }
+}
+
+fn pass(name: &str, detail: String) -> Check {
+    check(name, CheckStatus::Pass, detail, None)
+}
+
+/// An error as the detail of a check, which tells its status already.
+fn plain(e: impl std::fmt::Display) -> String {
+    e.to_string().trim_start_matches("❌ ").to_string()
+}
+
+/// Check what the operations of the crate rely on: the config file
+/// `config_filename`, the database and its extensions, the Postgres client
+/// tools and the backup directory, for `pgc doctor`.
+///
+/// Every check runs even after another failed, except those needing what
+/// failed: without a config only the client tools are checked, and without
+/// a connection neither the server nor the schema.
+pub(crate) async fn run_checks(config_filename: &str) -> DoctorReport {
+    info!("🩺 Checking the environment");
+    let mut report = DoctorReport::default();
+    let config_file = match config::validate_file(config_filename) {
+        Ok(config_file) => {
+            let config = &config_file.config;
+            report.checks.push(pass(
+                "config",
+                format!(
+                    "{}: {}@{}:{}/{}",
+                    config_filename, config.user, config.host, config.port, config.db_name
+                ),
+            ));
+            Some(config_file)
+        }
+        Err(e) => {
+            report.checks.push(check(
+                "config",
+                CheckStatus::Fail,
+                format!("{}: {}", config_filename, e),
+                Some("Pass --config FILE or set PGC_CONFIG, or fill in the [config] section of this file"),
+            ));
+            None
+        }
+    };
+    let config = config_file.as_ref().map(|config_file| &config_file.config);
+
+    let client = match &config {
+        Some(config) => connect(config, &mut report).await,
+        None => None,
+    };
+    let server = match &client {
+        Some(client) => server_version(client, &mut report).await,
+        None => None,
+    };
+    if let Some(client) = &client {
+        report.checks.extend(extensions(client).await);
+        report.checks.push(schema_privileges(client).await);
+    }
+    #[cfg(feature = "backup")]
+    {
+        let pg_dump_path = config
+            .as_ref()
+            .and_then(|config| config.pg_dump_path.clone());
+        let pg_restore_path = config
+            .as_ref()
+            .and_then(|config| config.pg_restore_path.clone());
+        report.checks.push(
+            client_tool(
+                "pg_dump",
+                pg_dump_path.as_deref(),
+                "pg_dump_path",
+                server.as_ref(),
+            )
+            .await,
+        );
+        report.checks.push(
+            client_tool(
+                "pg_restore",
+                pg_restore_path.as_deref(),
+                "pg_restore_path",
+                server.as_ref(),
+            )
+            .await,
+        );
+    }
+    if let Some(config_file) = &config_file {
+        if let Some(dir) = &config_file.backup.dir {
+            report.checks.push(backup_dir(dir, client.as_ref()).await);
+        }
+    }
+    report
+}
+
+/// Connect to the configured database, timing the connection and a round trip.
+async fn connect(config: &Config, report: &mut DoctorReport) -> Option<Client> {
+    let start = Instant::now();
+    let client = match tokio::time::timeout(CONNECT_TIMEOUT, db::connect(config, &config.db_name))
+        .await
+    {
+        Ok(Ok(client)) => client,
+        Ok(Err(e)) => {
+            report.checks.push(check(
+                "connection",
+                CheckStatus::Fail,
+                plain(e),
+                Some("Check that the server runs and accepts connections from here (pg_hba.conf), and the credentials of [config]"),
+            ));
+            return None;
+        }
+        Err(_) => {
+            report.checks.push(check(
+                "connection",
+                CheckStatus::Fail,
+                format!(
+                    "No answer from {}:{} within {:?}",
+                    config.host, config.port, CONNECT_TIMEOUT
+                ),
+                Some("Check the host and port of [config], and that no firewall drops the connection"),
+            ));
+            return None;
+        }
+    };
+    let connect_time = start.elapsed();
+    let start = Instant::now();
+    let round_trip = match client.simple_query("SELECT 1").await {
+        Ok(_) => start.elapsed(),
+        Err(e) => {
+            report.checks.push(check(
+                "connection",
+                CheckStatus::Fail,
+                format!("Connected, but SELECT 1 failed: {}", e),
+                None,
+            ));
+            return None;
+        }
+    };
+    let detail = format!(
+        "Connected in {:.0?}, round trip {:.1?}",
+        connect_time, round_trip
+    );
+    report.checks.push(if round_trip > SLOW_ROUND_TRIP {
+        check(
+            "connection",
+            CheckStatus::Warn,
+            detail,
+            Some("Slow round trips slow down batched inserts: run closer to the server, or use the COPY strategy"),
+        )
+    } else {
+        pass("connection", detail)
+    });
+    Some(client)
+}
+
+/// The version of the server, which needs `gen_random_uuid` (Postgres 13).
+async fn server_version(client: &Client, report: &mut DoctorReport) -> Option<ServerInfo> {
+    match server_info_of(client).await {
+        Ok(server) => {
+            let detail = format!("PostgreSQL {}", server.version);
+            report.checks.push(if server.major() < 13 {
+                check(
+                    "server",
+                    CheckStatus::Warn,
+                    detail,
+                    Some("Tables with a UUID key need gen_random_uuid, built in from PostgreSQL 13: upgrade, or CREATE EXTENSION pgcrypto"),
+                )
+            } else {
+                pass("server", detail)
+            });
+            Some(server)
+        }
+        Err(e) => {
+            report.checks.push(check(
+                "server",
+                CheckStatus::Fail,
+                format!("Failed to read the server version: {}", e),
+                None,
+            ));
+            None
+        }
+    }
+}
+
+/// PostGIS, which every geometry column needs, and `postgis_raster`, which
+/// only `insert_raster` does.
+async fn extensions(client: &Client) -> Vec<Check> {
+    let mut checks = Vec::new();
+    for (name, required, install_hint) in [
+        (
+            "postgis",
+            true,
+            "insert_geojson creates it when allowed to; otherwise have a superuser run CREATE EXTENSION postgis",
+        ),
+        (
+            "postgis_raster",
+            false,
+            "Only insert_raster needs it, and creates it when allowed to",
+        ),
+    ] {
+        let row = client
+            .query_opt(
+                "SELECT default_version, installed_version FROM pg_available_extensions WHERE name = $1",
+                &[&name],
+            )
+            .await;
+        checks.push(match row {
+            Ok(Some(row)) => {
+                let default_version: Option<String> = row.try_get_as(0).unwrap_or_default();
+                let installed_version: Option<String> = row.try_get_as(1).unwrap_or_default();
+                match installed_version {
+                    Some(version) => pass(name, format!("{} {} installed", name, version)),
+                    None => check(
+                        name,
+                        CheckStatus::Warn,
+                        format!(
+                            "{} {} is available, but not installed",
+                            name,
+                            default_version.unwrap_or_default()
+                        ),
+                        Some(install_hint),
+                    ),
+                }
+            }
+            Ok(None) => check(
+                name,
+                if required {
+                    CheckStatus::Fail
+                } else {
+                    CheckStatus::Warn
+                },
+                format!("{} is not available on the server", name),
+                Some("Install PostGIS on the server, e.g. the postgresql-<version>-postgis-3 package"),
+            ),
+            Err(e) => check(
+                name,
+                CheckStatus::Fail,
+                format!("Failed to read pg_available_extensions: {}", e),
+                None,
+            ),
+        });
+    }
+    checks
+}
+
+/// Whether the tables can be created in `SCHEMA`, on a server that is not
+/// a read only standby.
+async fn schema_privileges(client: &Client) -> Check {
+    let privileges = async {
+        let row = client
+            .query_one(
+                "SELECT has_schema_privilege($1, 'CREATE'), has_schema_privilege($1, 'USAGE'),
+                    pg_is_in_recovery(), current_user::text",
+                &[&SCHEMA],
+            )
+            .await?;
+        Ok::<_, PgcError>((
+            row.try_get_bool(0)?,
+            row.try_get_bool(1)?,
+            row.try_get_bool(2)?,
+            row.try_get_string(3)?,
+        ))
+    };
+    let (create, usage, in_recovery, user) = match privileges.await {
+        Ok(privileges) => privileges,
+        Err(e) => {
+            return check(
+                "schema",
+                CheckStatus::Fail,
+                format!("Failed to read the privileges on {}: {}", SCHEMA, e),
+                None,
+            )
+        }
+    };
+    if in_recovery {
+        return check(
+            "schema",
+            CheckStatus::Fail,
+            "The server is a read only standby".to_string(),
+            Some("Point [config] at the primary"),
+        );
+    }
+    if !create || !usage {
+        let hint = format!(
+            "As the owner of the schema: GRANT USAGE, CREATE ON SCHEMA {} TO {}",
+            SCHEMA, user
+        );
+        return check(
+            "schema",
+            CheckStatus::Fail,
+            format!("{} cannot create tables in {}", user, SCHEMA),
+            Some(&hint),
+        );
+    }
+    pass(
+        "schema",
+        format!("{} can create tables in {}", user, SCHEMA),
+    )
+}
+
+/// `name` at `configured_path` or on `PATH`, of a version that can dump `server`.
+#[cfg(feature = "backup")]
+async fn client_tool(
+    name: &str,
+    configured_path: Option<&str>,
+    config_key: &str,
+    server: Option<&ServerInfo>,
+) -> Check {
+    let tool = match backup::probe_client_tool(name, configured_path).await {
+        Ok(tool) => tool,
+        Err(e) => {
+            let hint = format!(
+                "Install the Postgres client tools, e.g. the postgresql-client package, or set `{}` in [config]",
+                config_key
+            );
+            return check(name, CheckStatus::Fail, plain(e), Some(&hint));
+        }
+    };
+    let detail = format!("{} {}", tool.path, tool.version);
+    match server {
+        Some(server) if tool.is_older_than(server) => {
+            let hint = format!(
+                "Install the client tools of PostgreSQL {} or newer, or set `{}` in [config]",
+                server.major(),
+                config_key
+            );
+            // pg_dump refuses to dump a newer server, pg_restore only fails
+            // on what its version does not know
+            let status = if name == "pg_dump" {
+                CheckStatus::Fail
+            } else {
+                CheckStatus::Warn
+            };
+            check(
+                name,
+                status,
+                format!("{}, older than the server ({})", detail, server.version),
+                Some(&hint),
+            )
+        }
+        _ => pass(name, detail),
+    }
+}
+
+/// The free space of the `[backup]` directory, against the size of the
+/// database when connected.
+async fn backup_dir(dir: &str, client: Option<&Client>) -> Check {
+    let path = Path::new(dir);
+    if !path.is_dir() {
+        return check(
+            "backup_dir",
+            CheckStatus::Fail,
+            format!("{} is not a directory", dir),
+            Some("Create it, or change `dir` in [backup]"),
+        );
+    }
+    let probe = path.join(".pgc_doctor");
+    if let Err(e) = std::fs::write(&probe, b"").and_then(|()| std::fs::remove_file(&probe)) {
+        return check(
+            "backup_dir",
+            CheckStatus::Fail,
+            format!("Cannot write in {}: {}", dir, e),
+            Some("Give the user running pgc write access to it"),
+        );
+    }
+    let available = match fs4::available_space(path) {
+        Ok(available) => available,
+        Err(e) => {
+            return check(
+                "backup_dir",
+                CheckStatus::Warn,
+                format!("Failed to read the free space of {}: {}", dir, e),
+                None,
+            )
+        }
+    };
+    let db_size = match client {
+        Some(client) => client
+            .query_one("SELECT pg_database_size(current_database())", &[])
+            .await
+            .ok()
+            .and_then(|row| row.try_get_i64(0).ok())
+            .map(|size| size as u64),
+        None => None,
+    };
+    match db_size {
+        // A compressed dump is usually much smaller than the database, a
+        // plain one is not
+        Some(db_size) if available < db_size => check(
+            "backup_dir",
+            CheckStatus::Warn,
+            format!(
+                "{} free in {}, less than the {} of the database",
+                human_size(available),
+                dir,
+                human_size(db_size)
+            ),
+            Some("Free up space, e.g. with backup --keep-last, or back up to another directory"),
+        ),
+        Some(db_size) => pass(
+            "backup_dir",
+            format!(
+                "{} free in {}, database is {}",
+                human_size(available),
+                dir,
+                human_size(db_size)
+            ),
+        ),
+        None => pass(
+            "backup_dir",
+            format!("{} free in {}", human_size(available), dir),
+        ),
+    }
+}
@@ -0,0 +1,89 @@
+use crate::read::config;
+use crate::read::db;
+use crate::read::queries::server_info_of;
+use crate::read::reports::VersionInfo;
+use crate::read::row_ext::RowExt;
+#[cfg(feature = "backup")]
+use crate::write::backup;
+use std::path::Path;
+use std::time::Duration;
+use tokio_postgres::Client;
+
+/// How long reading the server versions waits for it.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The versions of pgc, of the configured server and PostGIS, and of the
+/// client tools, for `pgc info` and the error reports of `--verbose`.
+///
+/// Never fails: what cannot be read, like the server when the config does
+/// not load or the database is down, is left out.
+pub(crate) async fn collect(config_filename: &str) -> VersionInfo {
+    let config_file = Path::new(config_filename)
+        .canonicalize()
+        .map(|path| path.display().to_string())
+        .unwrap_or(config_filename.to_string());
+    let mut info = VersionInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_sha: match env!("VERGEN_GIT_DIRTY") {
+            "true" => format!("{}-dirty", env!("VERGEN_GIT_SHA")),
+            _ => env!("VERGEN_GIT_SHA").to_string(),
+        },
+        build_profile: if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        }
+        .to_string(),
+        config_file,
+        ..VersionInfo::default()
+    };
+    let config = config::validate_file(config_filename)
+        .ok()
+        .map(|config_file| config_file.config);
+    if let Some(config) = &config {
+        info.connection = Some(format!(
+            "{}@{}:{}/{}",
+            config.user, config.host, config.port, config.db_name
+        ));
+        if let Ok(Ok(client)) =
+            tokio::time::timeout(CONNECT_TIMEOUT, db::connect(config, &config.db_name)).await
+        {
+            server_versions(&client, &mut info).await;
+        }
+    }
+    // Left out without the `backup` feature, which runs them
+    #[cfg(feature = "backup")]
+    {
+        let pg_dump_path = config
+            .as_ref()
+            .and_then(|config| config.pg_dump_path.clone());
+        let pg_restore_path = config.and_then(|config| config.pg_restore_path);
+        info.pg_dump_version = backup::probe_client_tool("pg_dump", pg_dump_path.as_deref())
+            .await
+            .ok()
+            .map(|tool| tool.version);
+        info.pg_restore_version =
+            backup::probe_client_tool("pg_restore", pg_restore_path.as_deref())
+                .await
+                .ok()
+                .map(|tool| tool.version);
+    }
+    info
+}
+
+/// The versions of the server and of the PostGIS installed in the database.
+async fn server_versions(client: &Client, info: &mut VersionInfo) {
+    info.server_version = server_info_of(client)
+        .await
+        .ok()
+        .map(|server| server.version);
+    info.postgis_version = client
+        .query_opt(
+            "SELECT extversion FROM pg_extension WHERE extname = 'postgis'",
+            &[],
+        )
+        .await
+        .ok()
+        .flatten()
+        .and_then(|row| row.try_get_string(0).ok());
+}
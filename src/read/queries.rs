@@ -1,28 +1,347 @@
+use super::config::Config;
 use super::db;
-use tokio_postgres::Error;
+use super::dialect::{self, Dialect};
+use super::reports::{
+    CollationDependentIndex, CollationStatus, ColumnInfo, ExportReport, GeoColumnInfo,
+    ImportRecord, PropertyTypeReport, ServerInfo, StatementResult, TableStats, TileExport,
+};
+use super::row_ext::{rows_to, rows_to_structs, RowExt};
+use super::stats;
+use crate::utils::error::{ErrorKind, PgcError};
+use crate::utils::format::human_size;
+use crate::utils::metrics::Metrics;
+use crate::utils::reporter::{NoopReporter, Reporter};
+use crate::utils::sql::PgType;
+use crate::utils::{cancel, csv, sql, suggest};
+use crate::write::manifest::IMPORTS_TABLE;
+use crate::write::options::{BoundingBox, ColumnKind, ExportOptions};
+use async_trait::async_trait;
+use deadpool_postgres::{Object, Pool};
+use futures::{TryFutureExt, TryStreamExt};
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::OnceCell;
+use tokio_postgres::{Error, SimpleQueryMessage};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 
-pub trait DatabaseQueriesRead {
-    async fn execute(&self, query: String) -> Result<Vec<tokio_postgres::row::Row>, Error>;
-    async fn list_columns(&self, table_name: &str) -> Result<(), Error>;
-    async fn list_tables(&self, only_user_tables: Option<bool>) -> Result<Vec<String>, Error>;
-    async fn table_row_count(&self, table_name: &str) -> Result<(), Error>;
-    async fn check_postgis_support(&self) -> Result<bool, Error>;
+/// The read operations, object safe so that a `Box<dyn DatabaseQueriesRead>`
+/// can hold `PostgresQueriesRead` or a fake, like `MockQueriesRead` of the
+/// `testing` feature. Their futures are `Send`, to be spawned on a
+/// multi-threaded runtime.
+#[async_trait]
+pub trait DatabaseQueriesRead: Send + Sync {
+    async fn execute(&self, query: String) -> Result<Vec<tokio_postgres::row::Row>, PgcError>;
+    async fn list_columns(&self, table_name: &str) -> Result<Vec<ColumnInfo>, PgcError>;
+    async fn list_tables(&self, only_user_tables: Option<bool>) -> Result<Vec<String>, PgcError>;
+    async fn list_tables_in_schema(
+        &self,
+        schema: &str,
+        only_user_tables: Option<bool>,
+    ) -> Result<Vec<String>, PgcError>;
+    async fn table_row_count(
+        &self,
+        table_name: &str,
+        include_deleted: Option<bool>,
+    ) -> Result<i64, PgcError>;
+    async fn check_postgis_support(&self) -> Result<bool, PgcError>;
+    async fn server_info(&self) -> Result<ServerInfo, PgcError>;
+    async fn collation_check(&self, db_name: &str) -> Result<CollationStatus, PgcError>;
+    async fn infer_property_types(
+        &self,
+        table_name: &str,
+        sample_size: Option<i64>,
+    ) -> Result<Vec<PropertyTypeReport>, PgcError>;
+    async fn collation_dependent_indexes(&self) -> Result<Vec<CollationDependentIndex>, PgcError>;
+    async fn import_history(&self, table_name: Option<&str>)
+        -> Result<Vec<ImportRecord>, PgcError>;
+    async fn geo_column_info(&self, table_name: &str) -> Result<Vec<GeoColumnInfo>, PgcError>;
+    async fn export_mvt(
+        &self,
+        table_name: &str,
+        z: u32,
+        x: u32,
+        y: u32,
+        output_path: Option<&str>,
+        properties: &[&str],
+    ) -> Result<Vec<u8>, PgcError>;
+    async fn export_mvt_range(
+        &self,
+        table_name: &str,
+        min_zoom: u32,
+        max_zoom: u32,
+        bbox: BoundingBox,
+        output_dir: &str,
+        properties: &[&str],
+    ) -> Result<TileExport, PgcError>;
+    async fn export_geojson(
+        &self,
+        table_name: &str,
+        output_path: &str,
+        options: Option<ExportOptions>,
+    ) -> Result<ExportReport, PgcError>;
+    async fn export_csv(
+        &self,
+        table_name: &str,
+        output_path: &str,
+        options: Option<ExportOptions>,
+    ) -> Result<ExportReport, PgcError>;
+    async fn table_stats(&self, table_name: &str) -> Result<TableStats, PgcError>;
 }
 
+/// Highest zoom level tiles are exported at.
+const MAX_TILE_ZOOM: u32 = 30;
+
+/// Extent of the tile coordinate space, and the buffer around it in the same units.
+const MVT_EXTENT: i32 = 4096;
+const MVT_BUFFER: i32 = 64;
+
+/// Latitude beyond which Web Mercator is undefined.
+const MAX_MERCATOR_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// Column of the tile containing a longitude at zoom level `z`.
+fn lon_to_tile_x(lon: f64, z: u32) -> u32 {
+    let n = 2f64.powi(z as i32);
+    (((lon + 180.0) / 360.0 * n).floor() as i64).clamp(0, n as i64 - 1) as u32
+}
+
+/// Row of the tile containing a latitude at zoom level `z`, counted from the north.
+fn lat_to_tile_y(lat: f64, z: u32) -> u32 {
+    let n = 2f64.powi(z as i32);
+    let lat = lat
+        .clamp(-MAX_MERCATOR_LATITUDE, MAX_MERCATOR_LATITUDE)
+        .to_radians();
+    let y = (1.0 - lat.tan().asinh() / std::f64::consts::PI) / 2.0 * n;
+    (y.floor() as i64).clamp(0, n as i64 - 1) as u32
+}
+
+/// Version of the server, as read by `server_info`.
+const SERVER_INFO_QUERY: &str = "SELECT current_setting('server_version'),
+    current_setting('server_version_num')::int";
+
+fn server_info_from(row: &tokio_postgres::Row) -> Result<ServerInfo, PgcError> {
+    Ok(ServerInfo {
+        version: row.try_get_string(0)?,
+        version_num: row.try_get_i32(1)?,
+    })
+}
+
+/// `server_info` on `client`, which prints nothing, unlike the connection
+/// `server_info` opens.
+pub(crate) async fn server_info_of(
+    client: &tokio_postgres::Client,
+) -> Result<ServerInfo, PgcError> {
+    let row = client.query_one(SERVER_INFO_QUERY, &[]).await?;
+    server_info_from(&row)
+}
+
+/// The number of rows of each of `tables` of `schema` the planner estimates
+/// from the statistics of the table, without counting them as
+/// `table_row_count` does. `None` for a table never analyzed or vacuumed, or
+/// missing. Prints nothing.
+pub(crate) async fn estimated_row_counts(
+    client: &tokio_postgres::Client,
+    dialect: Dialect,
+    schema: &str,
+    tables: &[String],
+) -> Result<Vec<(String, Option<i64>)>, PgcError> {
+    let rows = client
+        .query(dialect.sql().estimated_rows_query(), &[&schema, &tables])
+        .await?;
+    let estimates: BTreeMap<String, i64> = rows
+        .iter()
+        .map(|row| Ok((row.try_get_string(0)?, row.try_get_i64(1)?)))
+        .collect::<Result<_, PgcError>>()?;
+    Ok(tables
+        .iter()
+        .map(|table| {
+            let estimate = estimates.get(table).copied().filter(|rows| *rows >= 0);
+            (table.clone(), estimate)
+        })
+        .collect())
+}
+
+/// `import_history` on `client`, which prints nothing. Empty when no
+/// import was recorded yet.
+pub(crate) async fn import_history_of(
+    client: &tokio_postgres::Client,
+    table_name: Option<&str>,
+) -> Result<Vec<ImportRecord>, PgcError> {
+    let exists: bool = client
+        .query_one(
+            "SELECT to_regclass($1) IS NOT NULL",
+            &[&sql::ident(IMPORTS_TABLE)?],
+        )
+        .await?
+        .try_get_bool(0)?;
+    if !exists {
+        return Ok(Vec::new());
+    }
+    let condition = match table_name {
+        Some(table_name) => format!("WHERE table_name = {}", sql::literal(table_name)),
+        None => String::new(),
+    };
+    let query = format!(
+        "SELECT table_name, source_path, file_size, sha256, feature_count, srid, duration_ms,
+            crate_version, imported_at::text
+        FROM {} {} ORDER BY imported_at DESC, id DESC",
+        sql::ident(IMPORTS_TABLE)?,
+        condition
+    );
+    rows_to_structs(&client.query(&query, &[]).await?)
+}
+
+/// Run `statement` on `client` with the simple query protocol, which takes
+/// statements of any type and returns values of any type as text, one
+/// result per statement it holds. Prints nothing, for `pgc sql`.
+pub(crate) async fn execute_statement(
+    client: &tokio_postgres::Client,
+    statement: &str,
+) -> Result<Vec<StatementResult>, PgcError> {
+    let mut results = Vec::new();
+    let mut result = StatementResult::default();
+    for message in client.simple_query(statement).await? {
+        match message {
+            SimpleQueryMessage::RowDescription(columns) => {
+                result.columns = columns
+                    .iter()
+                    .map(|column| column.name().to_string())
+                    .collect();
+            }
+            SimpleQueryMessage::Row(row) => {
+                result.rows.push(
+                    (0..row.len())
+                        .map(|i| row.get(i).map(str::to_string))
+                        .collect(),
+                );
+            }
+            SimpleQueryMessage::CommandComplete(rows_affected) => {
+                result.rows_affected = rows_affected;
+                results.push(std::mem::take(&mut result));
+            }
+            _ => {}
+        }
+    }
+    Ok(results)
+}
+
+/// The read queries on the database of a pool, each checking out a
+/// connection of it, and showing what they report to `reporter`, nothing
+/// by default. The connections in use are recorded in `metrics`. The
+/// statements that differ between servers follow `dialect`, detected on the
+/// first query needing it unless given. The exports stop once
+/// `cancellation` is cancelled, never by default.
 #[derive(Clone)]
-pub struct PostgresQueriesRead;
+pub struct PostgresQueriesRead {
+    pool: Pool,
+    reporter: Arc<dyn Reporter>,
+    metrics: Arc<Metrics>,
+    dialect: Arc<OnceCell<Dialect>>,
+    cancellation: CancellationToken,
+}
+
+impl PostgresQueriesRead {
+    pub fn new(pool: Pool) -> Self {
+        Self {
+            pool,
+            reporter: Arc::new(NoopReporter),
+            metrics: Arc::new(Metrics::default()),
+            dialect: Arc::new(OnceCell::new()),
+            cancellation: CancellationToken::new(),
+        }
+    }
+
+    /// `new`, with a pool of connections to the database of `config`, see
+    /// `db::pool_of`, and its `dialect` if given.
+    pub fn from_config(config: Config) -> Result<Self, PgcError> {
+        let queries = Self::new(db::pool_of(&config)?);
+        Ok(match config.dialect {
+            Some(dialect) => queries.with_dialect(dialect),
+            None => queries,
+        })
+    }
 
+    /// Show what the queries report to `reporter`.
+    pub fn with_reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Where the queries show what they report.
+    pub fn reporter(&self) -> &Arc<dyn Reporter> {
+        &self.reporter
+    }
+
+    /// Record what the queries measure in `metrics`, to share it with
+    /// other query structs.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// What the queries measured.
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// Run the queries as on a server of `dialect`, rather than detecting it.
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = Arc::new(OnceCell::new_with(Some(dialect)));
+        self
+    }
+
+    /// Stop the exports once `cancellation` is cancelled.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// The token stopping the exports once cancelled.
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Share the dialect of other query structs, detected once for all.
+    pub(crate) fn with_dialect_cell(mut self, dialect: Arc<OnceCell<Dialect>>) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// The dialect of the server, detected from `version()` on the first call
+    /// unless given.
+    pub async fn dialect(&self) -> Result<Dialect, PgcError> {
+        self.dialect
+            .get_or_try_init(|| async { dialect::resolve(None, &**self.client().await?).await })
+            .await
+            .copied()
+    }
+
+    /// The pool the queries check their connections out of.
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    /// A connection of the pool.
+    async fn client(&self) -> Result<Object, PgcError> {
+        let client = db::checkout(&self.pool).await?;
+        self.metrics.record_pool(&self.pool);
+        Ok(client)
+    }
+}
+
+#[async_trait]
 impl DatabaseQueriesRead for PostgresQueriesRead {
-    async fn execute(&self, query: String) -> Result<Vec<tokio_postgres::row::Row>, Error> {
+    async fn execute(&self, query: String) -> Result<Vec<tokio_postgres::row::Row>, PgcError> {
         // Get database client
-        let (client, _) = db::new(None)
-            .await
-            .expect("❌ Failed to get database client");
+        let client = self.client().await?;
         // Execute the query without parameters
         let rows = client
             .query(&query, &[])
             .await
-            .expect(" ❌ Failed to query database!!\n");
+            .map_err(|e| PgcError::query(&query, e))?;
 
         // Collect all rows into a vector
         let mut result: Vec<tokio_postgres::row::Row> = Vec::new();
@@ -36,45 +355,16 @@ impl DatabaseQueriesRead for PostgresQueriesRead {
     /// List all columns in a table
     ///
     /// This function queries the database for all columns
-    /// in a table and prints them to the console.
-    async fn list_columns(&self, table_name: &str) -> Result<(), Error> {
-        eprintln!("⏳ Attempting to list columns in table: {}", table_name);
+    /// in a table and returns their names and data types.
+    async fn list_columns(&self, table_name: &str) -> Result<Vec<ColumnInfo>, PgcError> {
+        info!("⏳ Attempting to list columns in table: {}", table_name);
         let query = format!(
-        "SELECT column_name, data_type FROM information_schema.columns WHERE table_name = '{}';",
-        table_name
-    )
-        .to_string();
-        let rows = self.execute(query).await?;
-
-        // Collect all rows into a vector
-        let mut columns: Vec<(String, String)> = Vec::new();
-        for row in rows {
-            let column_name: String = row.get(0);
-            let data_type: String = row.get(1);
-            columns.push((column_name, data_type));
-        }
-
-        // Print table header
-
-        println!("\n┌{:─<30}{:─<21}┐", "", "");
-        println!(
-            "│ {:<28}{:<21} │",
-            format!("Columns in '{}' table", table_name),
-            ""
+            "SELECT column_name, data_type FROM information_schema.columns WHERE table_name = {};",
+            sql::literal(table_name)
         );
-        println!("├{:─<30}┬{:─<20}┤", "", "");
-        println!("│ {:<28} │ {:<18} │", "column_name", "data_type");
-        println!("├{:─<30}┼{:─<20}┤", "", "");
-
-        // Print table rows
-        for (col_name, data_type) in columns {
-            println!("│ {:<28} │ {:<18} │", col_name, data_type);
-        }
-
-        // Print table footer
-        println!("└{:─<30}┴{:─<20}┘", "", "");
-        eprintln!("✅ Successfully listed columns in table: {}", table_name);
-        Ok(())
+        let columns: Vec<ColumnInfo> = rows_to(&self.execute(query).await?)?;
+        info!("✅ Successfully listed columns in table: {}", table_name);
+        Ok(columns)
     }
 
     /// List all tables in the database
@@ -86,14 +376,26 @@ impl DatabaseQueriesRead for PostgresQueriesRead {
     ///
     /// Panics
     /// If the database query fails, this function will panic.
-    async fn list_tables(&self, only_user_tables: Option<bool>) -> Result<Vec<String>, Error> {
-        eprintln!("⏳ Attempting to list tables in database");
+    async fn list_tables(&self, only_user_tables: Option<bool>) -> Result<Vec<String>, PgcError> {
+        self.list_tables_in_schema("public", only_user_tables).await
+    }
+
+    /// List all tables in a given schema
+    ///
+    /// Same as `list_tables`, but for any schema instead of `public`.
+    async fn list_tables_in_schema(
+        &self,
+        schema: &str,
+        only_user_tables: Option<bool>,
+    ) -> Result<Vec<String>, PgcError> {
+        info!("⏳ Attempting to list tables in schema: {}", schema);
         let only_user_tables = only_user_tables.unwrap_or(true); // Default to true
         let query = if only_user_tables {
-            "
+            format!(
+                "
                 SELECT table_name 
                 FROM information_schema.tables 
-                WHERE table_schema = 'public' 
+                WHERE table_schema = {} 
                 AND table_type = 'BASE TABLE'
                 AND table_name NOT IN (
                     'geometry_columns',
@@ -101,76 +403,879 @@ impl DatabaseQueriesRead for PostgresQueriesRead {
                     'raster_columns',
                     'raster_overviews'
                 )
-            "
-            .to_string()
+            ",
+                sql::literal(schema)
+            )
         } else {
-            "SELECT table_name FROM information_schema.tables WHERE table_schema = 'public';"
-                .to_string()
+            format!(
+                "SELECT table_name FROM information_schema.tables WHERE table_schema = {};",
+                sql::literal(schema)
+            )
         };
         let rows = self.execute(query).await?;
 
         // Collect all rows into a vector
         let mut tables: Vec<String> = Vec::new();
         for row in rows {
-            let table_name: String = row.get(0);
-            tables.push(table_name);
+            tables.push(row.try_get_string(0)?);
         }
 
-        // Print table header
-        println!("\n┌{:─<30}┐", "");
-        println!("│ {:<28} │", "table_name");
-        println!("├{:─<30}┤", "");
-        // Print table rows
-        for table_name in &tables {
-            println!("│ {:<28} │", table_name);
-        }
-        // Print table footer
-        println!("└{:─<30}┘", "");
-        eprintln!("✅ Successfully listed tables");
+        info!("✅ Successfully listed tables");
         Ok(tables)
     }
 
     /// Get the row count for a given table
     ///
-    async fn table_row_count(&self, table_name: &str) -> Result<(), Error> {
-        eprintln!("⏳ Attempting to get row count for table: {}", table_name);
-        let query = format!("SELECT COUNT(*) FROM {} ", table_name).to_string();
-        let rows = self
-            .execute(query)
-            .await
-            .expect(format!("Failed to read table row count in table {} ", table_name).as_str());
+    /// Soft deleted rows are not counted unless `include_deleted` is true
+    /// (default false). Tables without a `deleted_at` column count every row.
+    async fn table_row_count(
+        &self,
+        table_name: &str,
+        include_deleted: Option<bool>,
+    ) -> Result<i64, PgcError> {
+        info!("⏳ Attempting to get row count for table: {}", table_name);
+        let include_deleted = include_deleted.unwrap_or(false);
+        let filter = if include_deleted || !self.has_deleted_at(table_name).await? {
+            ""
+        } else {
+            " WHERE deleted_at IS NULL"
+        };
+        let query = format!("SELECT COUNT(*) FROM {}{}", sql::ident(table_name)?, filter);
+        let rows = self.execute(query).await?;
 
         // Get the count from the first row, first column
-        let count: i64 = rows[0].get(0);
-
-        // Print table header
-        println!("\n┌{:─<30}┐", "");
-        println!("│ {:<28} │", format!("Row count for {}", table_name));
-        println!("├{:─<30}┤", "");
-        // Print row count
-        println!("│ {:<28} │", count);
-        // Print table footer
-        println!("└{:─<30}┘", "");
-        eprintln!("✅ Successfully got row count for table: {}", table_name);
-        Ok(())
+        let count = rows[0].try_get_i64(0)?;
+        info!("✅ Successfully got row count for table: {}", table_name);
+        Ok(count)
     }
-    async fn check_postgis_support(&self) -> Result<bool, Error> {
-        let query = "SELECT EXISTS (
-            SELECT 1 
-            FROM pg_extension 
-            WHERE extname = 'postgis'
-        )"
-        .to_string();
+    async fn check_postgis_support(&self) -> Result<bool, PgcError> {
+        let query = self.dialect().await?.sql().postgis_check().to_string();
 
         let rows = self.execute(query).await?;
-        let postgis_exists: bool = rows[0].get(0);
+        let postgis_exists = rows[0].try_get_bool(0)?;
 
         if postgis_exists {
-            println!("PostGIS is supported in the current database");
+            info!("PostGIS is supported in the current database");
         } else {
-            println!("PostGIS is NOT supported in the current database");
+            info!("PostGIS is NOT supported in the current database");
         }
 
         Ok(postgis_exists)
     }
+
+    /// Version of the database server
+    async fn server_info(&self) -> Result<ServerInfo, PgcError> {
+        let rows = self.execute(SERVER_INFO_QUERY.to_string()).await?;
+        server_info_from(&rows[0])
+    }
+
+    /// Compare the recorded and actual collation version of a database
+    ///
+    /// Requires Postgres 15 or newer, where collation versions of databases
+    /// are tracked.
+    async fn collation_check(&self, db_name: &str) -> Result<CollationStatus, PgcError> {
+        self.dialect()
+            .await?
+            .require("collation_check", |sql| sql.supports_collation_versions())?;
+        let query = format!(
+            "SELECT datcollversion, pg_database_collation_actual_version(oid)
+            FROM pg_database
+            WHERE datname = {}",
+            sql::literal(db_name)
+        );
+        let rows = self.execute(query).await?;
+        let (recorded_version, actual_version): (Option<String>, Option<String>) =
+            match rows.first() {
+                Some(row) => (row.try_get_as(0)?, row.try_get_as(1)?),
+                None => (None, None),
+            };
+        let mismatch = matches!(
+            (&recorded_version, &actual_version),
+            (Some(recorded), Some(actual)) if recorded != actual
+        );
+
+        info!(
+            "🔍 Collation version of {}: recorded {}, actual {}{}",
+            db_name,
+            recorded_version.as_deref().unwrap_or("unknown"),
+            actual_version.as_deref().unwrap_or("unknown"),
+            if mismatch { " ⚠️ mismatch" } else { "" }
+        );
+        Ok(CollationStatus {
+            db_name: db_name.to_string(),
+            recorded_version,
+            actual_version,
+            mismatch,
+        })
+    }
+
+    /// List the indexes in the current database that use a default or libc collation
+    ///
+    /// These are the objects that need a `REINDEX` after the collation library
+    /// changed. Dependencies on the default collation are not recorded in
+    /// `pg_depend`, so this looks at the collations of the index columns instead.
+    async fn collation_dependent_indexes(&self) -> Result<Vec<CollationDependentIndex>, PgcError> {
+        let query = "
+            SELECT DISTINCT s.indrelid::regclass::text, s.indexrelid::regclass::text
+            FROM (
+                SELECT indexrelid, indrelid, unnest(indcollation::oid[]) AS coll
+                FROM pg_index
+            ) s
+            JOIN pg_collation c ON c.oid = s.coll
+            JOIN pg_class i ON i.oid = s.indexrelid
+            JOIN pg_namespace n ON n.oid = i.relnamespace
+            WHERE c.collprovider IN ('d', 'c')
+            AND c.collname NOT IN ('C', 'POSIX')
+            AND n.nspname NOT IN ('pg_catalog', 'information_schema', 'pg_toast')
+            ORDER BY 1, 2
+        "
+        .to_string();
+        let rows = self.execute(query).await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(CollationDependentIndex {
+                    table_name: row.try_get_string(0)?,
+                    index_name: row.try_get_string(1)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Suggest column types for the JSON property keys of a table
+    ///
+    /// This samples `sample_size` rows (1000 by default), counts the JSON types
+    /// of the values per key and suggests a type for `materialize_properties`.
+    /// Keys with mixed types are suggested as `TEXT`.
+    async fn infer_property_types(
+        &self,
+        table_name: &str,
+        sample_size: Option<i64>,
+    ) -> Result<Vec<PropertyTypeReport>, PgcError> {
+        info!(
+            "⏳ Attempting to infer property types in table: {}",
+            table_name
+        );
+        let query = format!(
+            "SELECT p.key, jsonb_typeof(p.value), COUNT(*),
+                bool_and(jsonb_typeof(p.value) <> 'number' OR p.value::text ~ '^-?[0-9]+$'),
+                (array_agg(p.value #>> '{{}}'))[1:3]
+            FROM (SELECT properties FROM {} LIMIT {}) s, jsonb_each(s.properties) p
+            WHERE jsonb_typeof(p.value) <> 'null'
+            GROUP BY 1, 2
+            ORDER BY 1, 2",
+            sql::ident(table_name)?,
+            sample_size.unwrap_or(1000)
+        );
+        let rows = self.execute(query).await?;
+
+        let mut reports: Vec<PropertyTypeReport> = Vec::new();
+        let mut all_integers: BTreeMap<String, bool> = BTreeMap::new();
+        for row in rows {
+            let json_key = row.try_get_string(0)?;
+            let json_type = row.try_get_string(1)?;
+            let count = row.try_get_i64(2)?;
+            let integers = row.try_get_bool(3)?;
+            let samples: Vec<String> = row.try_get_as(4)?;
+
+            if reports
+                .last()
+                .map(|r| r.json_key != json_key)
+                .unwrap_or(true)
+            {
+                reports.push(PropertyTypeReport {
+                    json_key: json_key.clone(),
+                    json_types: BTreeMap::new(),
+                    sample_values: Vec::new(),
+                    suggested_type: PgType::Text,
+                });
+            }
+            let report = reports.last_mut().expect("report was just pushed");
+            report.json_types.insert(json_type, count);
+            report.sample_values.extend(samples);
+            *all_integers.entry(json_key).or_insert(true) &= integers;
+        }
+
+        for report in &mut reports {
+            let types: Vec<&str> = report.json_types.keys().map(String::as_str).collect();
+            report.suggested_type = match types.as_slice() {
+                ["number"] if all_integers[&report.json_key] => PgType::BigInt,
+                ["number"] => PgType::DoublePrecision,
+                ["boolean"] => PgType::Boolean,
+                ["object"] | ["array"] | ["array", "object"] => PgType::Jsonb,
+                _ => PgType::Text,
+            };
+        }
+
+        info!(
+            "✅ Successfully inferred property types in table: {}",
+            table_name
+        );
+        Ok(reports)
+    }
+
+    /// List the files loaded by `insert_geojson`, newest first
+    ///
+    /// This function reads the `_imports` table, for one table or for all of
+    /// them. Nothing has been recorded yet if the
+    /// table does not exist, in which case the list is empty.
+    async fn import_history(
+        &self,
+        table_name: Option<&str>,
+    ) -> Result<Vec<ImportRecord>, PgcError> {
+        info!("⏳ Attempting to list import history");
+        let client = self.client().await?;
+        let records = import_history_of(&client, table_name).await?;
+        if records.is_empty() {
+            info!("🤗 No imports recorded yet");
+        } else {
+            info!("✅ Listed {} imports", records.len());
+        }
+        Ok(records)
+    }
+
+    /// List the spatial columns of a table, geometry and geography alike
+    ///
+    /// PostGIS registers geometry columns in `geometry_columns` and geography
+    /// columns in `geography_columns`; both are read, in the current schema.
+    async fn geo_column_info(&self, table_name: &str) -> Result<Vec<GeoColumnInfo>, PgcError> {
+        info!(
+            "⏳ Attempting to list spatial columns of table: {}",
+            table_name
+        );
+        let query = format!(
+            "SELECT 'geometry', f_geometry_column::text, type::text, srid, coord_dimension
+            FROM geometry_columns
+            WHERE f_table_schema = current_schema() AND f_table_name = {table}
+            UNION ALL
+            SELECT 'geography', f_geography_column::text, type::text, srid, coord_dimension
+            FROM geography_columns
+            WHERE f_table_schema = current_schema() AND f_table_name = {table}",
+            table = sql::literal(table_name)
+        );
+        let columns: Vec<GeoColumnInfo> = self
+            .execute(query)
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(GeoColumnInfo {
+                    table_name: table_name.to_string(),
+                    column: row.try_get_string(1)?,
+                    kind: if row.try_get_as::<&str, _>(0)? == "geography" {
+                        ColumnKind::Geography
+                    } else {
+                        ColumnKind::Geometry
+                    },
+                    geometry_type: row.try_get_string(2)?,
+                    srid: row.try_get_as(3)?,
+                    dimensions: row.try_get_as(4)?,
+                })
+            })
+            .collect::<Result<_, PgcError>>()?;
+        for column in &columns {
+            info!(
+                "🌍 {}.{}: {}({}, {})",
+                table_name,
+                column.column,
+                column.kind,
+                column.geometry_type,
+                column.srid.unwrap_or(0)
+            );
+        }
+        Ok(columns)
+    }
+
+    /// Export one Mapbox vector tile of a table.
+    ///
+    /// The tile holds one layer named after the table, with the `name` of
+    /// every feature in the tile and the requested `properties` (as text).
+    /// Returns the protobuf bytes of the tile, and also writes them to
+    /// `output_path` if given. An empty tile is returned as no bytes and is
+    /// not written.
+    ///
+    /// Requires PostGIS 3.0 or later for `ST_TileEnvelope`.
+    async fn export_mvt(
+        &self,
+        table_name: &str,
+        z: u32,
+        x: u32,
+        y: u32,
+        output_path: Option<&str>,
+        properties: &[&str],
+    ) -> Result<Vec<u8>, PgcError> {
+        if z > MAX_TILE_ZOOM || (x as u64) >> z != 0 || (y as u64) >> z != 0 {
+            return Err(PgcError::usage(format!(
+                "❌ Invalid tile {}/{}/{}",
+                z, x, y
+            )));
+        }
+        let query = self.mvt_query(table_name, properties).await?;
+        let client = self.client().await?;
+        info!(
+            "⏳ Attempting to export tile {}/{}/{} of {}",
+            z, x, y, table_name
+        );
+        let tile: Option<Vec<u8>> = client
+            .query_one(&query, &[&(z as i32), &(x as i32), &(y as i32)])
+            .await?
+            .try_get_as(0)?;
+        let tile = tile.unwrap_or_default();
+        if tile.is_empty() {
+            info!("⏭️ Tile {}/{}/{} is empty", z, x, y);
+            return Ok(tile);
+        }
+        if let Some(output_path) = output_path {
+            std::fs::write(output_path, &tile).map_err(|e| PgcError::io(output_path, e))?;
+            info!(
+                "✅ Tile {}/{}/{} written to {} ({} bytes)",
+                z,
+                x,
+                y,
+                output_path,
+                tile.len()
+            );
+        }
+        Ok(tile)
+    }
+
+    /// Export every tile of a table covering a box, for a range of zoom levels.
+    ///
+    /// `bbox` is in longitude/latitude. The tiles are written to
+    /// `output_dir/{z}/{x}/{y}.mvt`, the layout static tile servers and map
+    /// libraries expect. Tiles without any feature are skipped. See
+    /// `export_mvt` for the content of a tile.
+    async fn export_mvt_range(
+        &self,
+        table_name: &str,
+        min_zoom: u32,
+        max_zoom: u32,
+        bbox: BoundingBox,
+        output_dir: &str,
+        properties: &[&str],
+    ) -> Result<TileExport, PgcError> {
+        bbox.validate().map_err(PgcError::usage)?;
+        if min_zoom > max_zoom || max_zoom > MAX_TILE_ZOOM {
+            return Err(PgcError::usage(format!(
+                "❌ Invalid zoom range {}..={}, zoom levels go up to {}",
+                min_zoom, max_zoom, MAX_TILE_ZOOM
+            )));
+        }
+        let query = self.mvt_query(table_name, properties).await?;
+        let client = self.client().await?;
+        let statement = client.prepare(&query).await?;
+        let mut report = TileExport {
+            table_name: table_name.to_string(),
+            output_dir: output_dir.to_string(),
+            ..Default::default()
+        };
+
+        for z in min_zoom..=max_zoom {
+            let (min_x, max_x) = (lon_to_tile_x(bbox.min_x, z), lon_to_tile_x(bbox.max_x, z));
+            let (min_y, max_y) = (lat_to_tile_y(bbox.max_y, z), lat_to_tile_y(bbox.min_y, z));
+            info!(
+                "⏳ Exporting zoom {}: {} tiles",
+                z,
+                (max_x - min_x + 1) as u64 * (max_y - min_y + 1) as u64
+            );
+            for x in min_x..=max_x {
+                for y in min_y..=max_y {
+                    let tile: Option<Vec<u8>> = client
+                        .query_one(&statement, &[&(z as i32), &(x as i32), &(y as i32)])
+                        .await?
+                        .try_get_as(0)?;
+                    let tile = tile.unwrap_or_default();
+                    if tile.is_empty() {
+                        report.empty_skipped += 1;
+                        continue;
+                    }
+                    let tile_dir = Path::new(output_dir)
+                        .join(z.to_string())
+                        .join(x.to_string());
+                    std::fs::create_dir_all(&tile_dir).map_err(|e| {
+                        format!("❌ Failed to create {}: {}", tile_dir.display(), e)
+                    })?;
+                    let tile_path = tile_dir.join(format!("{}.mvt", y));
+                    std::fs::write(&tile_path, &tile).map_err(|e| {
+                        format!("❌ Failed to write tile to {}: {}", tile_path.display(), e)
+                    })?;
+                    report.tiles_written += 1;
+                    report.bytes += tile.len() as u64;
+                }
+            }
+        }
+        info!(
+            "✅ Exported {} tiles of {} to {} ({} bytes, {} empty tiles skipped)",
+            report.tiles_written, table_name, output_dir, report.bytes, report.empty_skipped
+        );
+        Ok(report)
+    }
+
+    /// Export a table to a GeoJSON FeatureCollection at `output_path`.
+    ///
+    /// The features are streamed from the server to the file, which is
+    /// removed if the export fails. Their properties are the `properties`
+    /// column of the tables pgc loaded, and the other columns of any other
+    /// table. Geometries are written in longitude/latitude, as GeoJSON
+    /// requires.
+    async fn export_geojson(
+        &self,
+        table_name: &str,
+        output_path: &str,
+        options: Option<ExportOptions>,
+    ) -> Result<ExportReport, PgcError> {
+        self.export_rows(table_name, output_path, ExportFormat::GeoJson, options)
+            .await
+    }
+
+    /// Export a table to CSV at `output_path`, with a header line.
+    ///
+    /// Every column is written as text, the spatial column as WKT in
+    /// longitude/latitude. Streamed like `export_geojson`.
+    async fn export_csv(
+        &self,
+        table_name: &str,
+        output_path: &str,
+        options: Option<ExportOptions>,
+    ) -> Result<ExportReport, PgcError> {
+        self.export_rows(table_name, output_path, ExportFormat::Csv, options)
+            .await
+    }
+
+    /// Report on the health of a table in one go: its estimated rows and
+    /// sizes, the types, SRID, extent and sampled validity of its
+    /// geometries, its indexes, its last vacuum and analyze, and the files
+    /// loaded into it.
+    ///
+    /// The queries run concurrently on one connection. Those on the
+    /// geometries read the whole table, except the validity check.
+    async fn table_stats(&self, table_name: &str) -> Result<TableStats, PgcError> {
+        let column = self.find_spatial_column(table_name).await?;
+        let dialect = self.dialect().await?;
+        let client = self.client().await?;
+        info!(
+            "⏳ Attempting to collect the stats of table: {}",
+            table_name
+        );
+        let client = &client;
+        let geometry = column.as_ref().map(|column| column.expression.as_str());
+        let sizes_and_validity = async {
+            let (sizes, estimated_rows) = stats::sizes(client, dialect, table_name).await?;
+            let validity = match geometry {
+                Some(geometry) => Some(
+                    stats::validity(client, dialect, table_name, geometry, estimated_rows).await?,
+                ),
+                None => None,
+            };
+            Ok::<_, PgcError>((sizes, estimated_rows, validity))
+        };
+        let geometry_types = async {
+            match geometry {
+                Some(geometry) => stats::geometry_types(client, table_name, geometry).await,
+                None => Ok(Vec::new()),
+            }
+        };
+        let extent = async {
+            match geometry {
+                Some(geometry) => stats::extent(client, table_name, geometry).await,
+                None => Ok(None),
+            }
+        };
+        let (
+            (sizes, estimated_rows, validity),
+            geometry_types,
+            extent,
+            indexes,
+            maintenance,
+            imports,
+        ) = tokio::try_join!(
+            sizes_and_validity,
+            geometry_types,
+            extent,
+            stats::indexes(client, dialect, table_name),
+            stats::maintenance(client, dialect, table_name),
+            import_history_of(client, Some(table_name)),
+        )?;
+        info!("✅ Collected the stats of table: {}", table_name);
+        Ok(TableStats {
+            table_name: table_name.to_string(),
+            estimated_rows,
+            sizes,
+            geometry_column: column.as_ref().map(|column| column.name.clone()),
+            srid: column.as_ref().and_then(|column| column.declared_srid),
+            geometry_types,
+            extent,
+            validity,
+            indexes,
+            maintenance,
+            imports,
+        })
+    }
+}
+
+impl PostgresQueriesRead {
+    /// Query building one tile of a table, with `z`, `x` and `y` as `$1`, `$2` and `$3`.
+    ///
+    /// The tile envelope is transformed to the SRID of the geometry column,
+    /// so the column's spatial index is used to find the features in it.
+    async fn mvt_query(&self, table_name: &str, properties: &[&str]) -> Result<String, PgcError> {
+        let SpatialColumn {
+            expression: geometry,
+            srid,
+            ..
+        } = self.spatial_column(table_name).await?;
+        let property_columns: String = properties
+            .iter()
+            .map(|property| {
+                Ok(format!(
+                    ", t.properties->>{} AS {}",
+                    sql::literal(property),
+                    sql::ident(property)?
+                ))
+            })
+            .collect::<Result<_, PgcError>>()?;
+        Ok(format!(
+            "WITH bounds AS (SELECT ST_TileEnvelope($1, $2, $3) AS envelope),
+            tile AS (
+                SELECT ST_AsMVTGeom(ST_Transform({geometry}, 3857), bounds.envelope, {extent}, {buffer}, true) AS pgc_tile_geometry,
+                    t.name{property_columns}
+                FROM {table} t, bounds
+                WHERE {geometry} && ST_Transform(bounds.envelope, {srid})
+            )
+            SELECT ST_AsMVT(tile.*, {layer}, {extent}, 'pgc_tile_geometry') FROM tile",
+            geometry = geometry,
+            extent = MVT_EXTENT,
+            buffer = MVT_BUFFER,
+            property_columns = property_columns,
+            table = sql::ident(table_name)?,
+            srid = srid,
+            layer = sql::literal(table_name),
+        ))
+    }
+
+    /// `find_spatial_column`, failing on a table without one.
+    async fn spatial_column(&self, table_name: &str) -> Result<SpatialColumn, PgcError> {
+        let column = self.find_spatial_column(table_name).await?.ok_or_else(|| {
+            PgcError::usage(format!("❌ Table {} has no spatial column", table_name))
+        })?;
+        if column.declared_srid.is_none_or(|srid| srid <= 0) {
+            warn!(
+                "⚠️ {}.{} has no SRID, assuming 4326",
+                table_name, column.name
+            );
+        }
+        Ok(column)
+    }
+
+    /// The spatial column of a table the exports read: the one named
+    /// `geometry`, or else the first. Fails on a missing table, naming the
+    /// closest one.
+    async fn find_spatial_column(
+        &self,
+        table_name: &str,
+    ) -> Result<Option<SpatialColumn>, PgcError> {
+        self.require_table(table_name).await?;
+        let columns = self.geo_column_info(table_name).await?;
+        let Some(column) = columns
+            .iter()
+            .find(|column| column.column == "geometry")
+            .or(columns.first())
+        else {
+            return Ok(None);
+        };
+        let srid = column.srid.filter(|srid| *srid > 0).unwrap_or(4326);
+        let expression = match column.kind {
+            ColumnKind::Geometry => format!("t.{}", sql::ident(&column.column)?),
+            ColumnKind::Geography => format!("t.{}::geometry", sql::ident(&column.column)?),
+        };
+        Ok(Some(SpatialColumn {
+            name: column.column.clone(),
+            expression,
+            srid,
+            declared_srid: column.srid,
+        }))
+    }
+
+    /// Fail unless a table or view named `table_name` is in the current
+    /// schema, suggesting the closest name when one looks like a typo of it.
+    async fn require_table(&self, table_name: &str) -> Result<(), PgcError> {
+        let tables: Vec<String> = self
+            .execute(
+                "SELECT table_name::text FROM information_schema.tables
+                WHERE table_schema = current_schema()"
+                    .to_string(),
+            )
+            .await?
+            .iter()
+            .map(|row| row.try_get_string(0))
+            .collect::<Result<_, _>>()?;
+        if tables.iter().any(|table| table == table_name) {
+            return Ok(());
+        }
+        Err(PgcError::usage(
+            match suggest::closest(table_name, &tables) {
+                Some(closest) => format!(
+                    "❌ Table {} not found, did you mean {}?",
+                    table_name, closest
+                ),
+                None => format!("❌ Table {} not found", table_name),
+            },
+        ))
+    }
+
+    /// The query of `export_geojson` or `export_csv`: one row per feature,
+    /// one text column per field of the output.
+    ///
+    /// Geometries are simplified in their SRID, then transformed to
+    /// longitude/latitude. The box is transformed to the SRID of the column
+    /// instead, so its spatial index is used.
+    async fn export_query(
+        &self,
+        table_name: &str,
+        format: ExportFormat,
+        options: &ExportOptions,
+    ) -> Result<(Vec<String>, String), PgcError> {
+        let column = self.spatial_column(table_name).await?;
+        let mut geometry = column.expression.clone();
+        if let Some(tolerance) = options.simplify {
+            geometry = format!("ST_SimplifyPreserveTopology({}, {})", geometry, tolerance);
+        }
+        if column.srid != 4326 {
+            geometry = format!("ST_Transform({}, 4326)", geometry);
+        }
+        let mut conditions = Vec::new();
+        if let Some(bbox) = &options.bbox {
+            conditions.push(format!(
+                "{} && ST_Transform(ST_MakeEnvelope({}, {}, {}, {}, 4326), {})",
+                column.expression, bbox.min_x, bbox.min_y, bbox.max_x, bbox.max_y, column.srid
+            ));
+        }
+        if let Some(filter) = &options.filter {
+            conditions.push(format!("({})", filter));
+        }
+        let condition = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", conditions.join(" AND "))
+        };
+
+        let table_columns: Vec<(String, String)> = self
+            .execute(format!(
+                "SELECT column_name::text, data_type::text FROM information_schema.columns
+                WHERE table_schema = current_schema() AND table_name = {}
+                ORDER BY ordinal_position",
+                sql::literal(table_name)
+            ))
+            .await?
+            .iter()
+            .map(|row| Ok((row.try_get_string(0)?, row.try_get_string(1)?)))
+            .collect::<Result<_, PgcError>>()?;
+        let (headers, fields): (Vec<String>, Vec<String>) = match format {
+            ExportFormat::GeoJson => {
+                // The properties of the features loaded by pgc are in their
+                // own column, any other table has its columns as properties
+                let properties = if table_columns
+                    .iter()
+                    .any(|(name, data_type)| name == "properties" && data_type == "jsonb")
+                {
+                    "t.properties".to_string()
+                } else {
+                    format!("to_jsonb(t) - {}", sql::literal(&column.name))
+                };
+                let feature = format!(
+                    "json_build_object('type', 'Feature', 'geometry', ST_AsGeoJSON({})::json, 'properties', {})::text",
+                    geometry, properties
+                );
+                (Vec::new(), vec![feature])
+            }
+            ExportFormat::Csv => table_columns
+                .iter()
+                .map(|(name, _)| {
+                    let field = if *name == column.name {
+                        format!("ST_AsText({})", geometry)
+                    } else {
+                        format!("t.{}::text", sql::ident(name)?)
+                    };
+                    Ok((name.clone(), field))
+                })
+                .collect::<Result<Vec<_>, PgcError>>()?
+                .into_iter()
+                .unzip(),
+        };
+        let query = format!(
+            "SELECT {} FROM {} t{}",
+            fields.join(", "),
+            sql::ident(table_name)?,
+            condition
+        );
+        Ok((headers, query))
+    }
+
+    /// Stream the rows of `export_query` to `output_path`, removing what
+    /// was written of it on failure.
+    async fn export_rows(
+        &self,
+        table_name: &str,
+        output_path: &str,
+        format: ExportFormat,
+        options: Option<ExportOptions>,
+    ) -> Result<ExportReport, PgcError> {
+        let options = options.unwrap_or_default();
+        options.validate().map_err(PgcError::usage)?;
+        let (headers, query) = self.export_query(table_name, format, &options).await?;
+        let client = self.client().await?;
+        info!("⏳ Attempting to export {} to {}", table_name, output_path);
+        let file = tokio::fs::File::create(output_path)
+            .await
+            .map_err(|e| PgcError::io(output_path, e))?;
+        let mut writer = CountingWriter::new(tokio::io::BufWriter::new(file));
+        let result = write_export(
+            &client,
+            &query,
+            &headers,
+            format,
+            &mut writer,
+            &self.cancellation,
+        )
+        .await;
+        let features = match result {
+            Ok(features) => features,
+            Err(e) => {
+                drop(writer);
+                let _ = tokio::fs::remove_file(output_path).await;
+                let message = format!("❌ Failed to export {}: {}", table_name, e);
+                return Err(e.kind().wrap(message).into());
+            }
+        };
+        let report = ExportReport {
+            table_name: table_name.to_string(),
+            output_path: output_path.to_string(),
+            features,
+            bytes: writer.bytes,
+        };
+        info!(
+            "✅ Exported {} features of {} to {} ({})",
+            report.features,
+            table_name,
+            output_path,
+            human_size(report.bytes)
+        );
+        Ok(report)
+    }
+
+    /// Whether a table supports soft deletes, i.e. has a `deleted_at` column.
+    async fn has_deleted_at(&self, table_name: &str) -> Result<bool, PgcError> {
+        let rows = self
+            .execute(format!(
+                "SELECT EXISTS (
+                    SELECT 1 FROM information_schema.columns
+                    WHERE table_schema = current_schema() AND table_name = {}
+                    AND column_name = 'deleted_at'
+                )",
+                sql::literal(table_name)
+            ))
+            .await?;
+        rows[0].try_get_bool(0)
+    }
+}
+
+/// The spatial column of a table, see `spatial_column`.
+///
+/// * `name`: of the column.
+/// * `expression`: the column as a geometry, in a query on the table as `t`.
+/// * `srid`: of the column, 4326 when it has none.
+/// * `declared_srid`: of the column, as registered by PostGIS.
+struct SpatialColumn {
+    name: String,
+    expression: String,
+    srid: i32,
+    declared_srid: Option<i32>,
+}
+
+/// What `export_rows` writes.
+#[derive(Debug, Clone, Copy)]
+enum ExportFormat {
+    GeoJson,
+    Csv,
+}
+
+/// A writer counting the bytes written through it.
+struct CountingWriter<W> {
+    inner: W,
+    bytes: u64,
+}
+
+impl<W: AsyncWrite + Unpin> CountingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, bytes: 0 }
+    }
+
+    async fn write(&mut self, data: &str) -> std::io::Result<()> {
+        self.inner.write_all(data.as_bytes()).await?;
+        self.bytes += data.len() as u64;
+        Ok(())
+    }
+}
+
+/// Write the rows of `query` as a GeoJSON FeatureCollection or as CSV,
+/// streaming them from the server one at a time, and return how many
+/// there were. Stops with a `Cancelled` error once `cancellation` is.
+async fn write_export<W: AsyncWrite + Unpin>(
+    client: &tokio_postgres::Client,
+    query: &str,
+    headers: &[String],
+    format: ExportFormat,
+    writer: &mut CountingWriter<W>,
+    cancellation: &CancellationToken,
+) -> Result<u64, PgcError> {
+    let rows = client
+        .query_raw(query, std::iter::empty::<String>())
+        .await?;
+    tokio::pin!(rows);
+    match format {
+        ExportFormat::GeoJson => {
+            writer
+                .write("{\"type\":\"FeatureCollection\",\"features\":[")
+                .await?
+        }
+        ExportFormat::Csv => {
+            let headers: Vec<String> = headers.iter().map(|header| csv::field(header)).collect();
+            writer.write(&format!("{}\n", headers.join(","))).await?;
+        }
+    }
+    let mut features: u64 = 0;
+    while let Some(row) = rows.try_next().await? {
+        if features.is_multiple_of(cancel::CHECK_EVERY as u64) && cancellation.is_cancelled() {
+            return Err(PgcError::Cancelled {
+                message: "🛑 Interrupted".to_string(),
+            });
+        }
+        match format {
+            ExportFormat::GeoJson => {
+                let feature = row.try_get_string(0)?;
+                let separator = if features == 0 { "\n" } else { ",\n" };
+                writer.write(separator).await?;
+                writer.write(&feature).await?;
+            }
+            ExportFormat::Csv => {
+                // NULL is written as an empty field, an empty string quoted,
+                // as COPY does
+                let fields: Vec<String> = (0..row.len())
+                    .map(|i| {
+                        Ok(match row.try_get_as::<Option<&str>, _>(i)? {
+                            Some("") => "\"\"".to_string(),
+                            Some(value) => csv::field(value),
+                            None => String::new(),
+                        })
+                    })
+                    .collect::<Result<_, PgcError>>()?;
+                writer.write(&format!("{}\n", fields.join(","))).await?;
+            }
+        }
+        features += 1;
+    }
+    if let ExportFormat::GeoJson = format {
+        writer.write("\n]}\n").await?;
+    }
+    writer.inner.flush().await?;
+    Ok(features)
 }
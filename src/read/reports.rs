@@ -0,0 +1,268 @@
+use crate::utils::sql::PgType;
+use crate::write::options::{BoundingBox, ColumnKind};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A column of a table, as listed by `list_columns`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnInfo {
+    pub column_name: String,
+    pub data_type: String,
+}
+
+/// Collation version of a database, as recorded in the catalog and as
+/// provided by the operating system's collation library.
+///
+/// `mismatch` is true when both are known and differ, which is what makes
+/// Postgres print "database has a collation version mismatch".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollationStatus {
+    pub db_name: String,
+    pub recorded_version: Option<String>,
+    pub actual_version: Option<String>,
+    pub mismatch: bool,
+}
+
+/// An index that uses a libc/default collation and must be rebuilt after
+/// the collation library changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollationDependentIndex {
+    pub table_name: String,
+    pub index_name: String,
+}
+
+/// Types found for one JSON property key in a sample of a table's rows,
+/// with the column type it could be materialized into.
+///
+/// `json_types` counts the values per JSON type (`string`, `number`, ...).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PropertyTypeReport {
+    pub json_key: String,
+    pub json_types: BTreeMap<String, i64>,
+    pub sample_values: Vec<String>,
+    pub suggested_type: PgType,
+}
+
+/// What a statement run by `execute_statement` returned: the names of its
+/// columns and its rows, each value as the text Postgres prints it in and
+/// `None` for NULL. `rows_affected` counts the rows returned or changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct StatementResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Option<String>>>,
+    pub rows_affected: u64,
+}
+
+/// One file loaded by `insert_geojson`, as recorded in the `_imports` table.
+///
+/// `sha256` is the hex digest of the file's content, `duration_ms` the time
+/// the file took to parse and upload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRecord {
+    pub table_name: String,
+    pub source_path: String,
+    pub file_size: i64,
+    pub sha256: String,
+    pub feature_count: i64,
+    pub srid: Option<i32>,
+    pub duration_ms: i64,
+    pub crate_version: String,
+    pub imported_at: String,
+}
+
+/// A spatial column, as registered in `geometry_columns` or `geography_columns`.
+///
+/// `geometry_type` is the PostGIS type name, e.g. `POINT` or `GEOMETRY` when
+/// unconstrained, and `srid` is 0 for a geometry column without SRID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeoColumnInfo {
+    pub table_name: String,
+    pub column: String,
+    pub kind: ColumnKind,
+    pub geometry_type: String,
+    pub srid: Option<i32>,
+    pub dimensions: Option<i32>,
+}
+
+/// Result of `export_mvt_range`.
+///
+/// `empty_skipped` counts the tiles of the range without any feature, for
+/// which no file was written. `bytes` is the total size of the written tiles.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TileExport {
+    pub table_name: String,
+    pub output_dir: String,
+    pub tiles_written: u64,
+    pub empty_skipped: u64,
+    pub bytes: u64,
+}
+
+/// Result of `export_geojson` and `export_csv`: the features written to
+/// `output_path`, and its size.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportReport {
+    pub table_name: String,
+    pub output_path: String,
+    pub features: u64,
+    pub bytes: u64,
+}
+
+/// Version of the database server, as reported by `server_version` and
+/// `server_version_num`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub version: String,
+    pub version_num: i32,
+}
+
+impl ServerInfo {
+    /// Major version, e.g. 16 for 16.2. Before Postgres 10 the major
+    /// version had two parts, such as 9.6, counted here as 9.
+    pub fn major(&self) -> u32 {
+        (self.version_num / 10_000) as u32
+    }
+}
+
+/// Outcome of one check of `run_checks`, from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// One check of `run_checks`: what was found, and how to fix it when it
+/// did not pass.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+/// Result of `run_checks`, the checks in the order they ran.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    /// The worst status of the checks, `Pass` without any.
+    pub(crate) fn worst(&self) -> CheckStatus {
+        self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Pass)
+    }
+}
+
+/// Versions of pgc and of what it runs against, from `info::collect`, for
+/// bug reports. What could not be read is `None`.
+///
+/// * `version`: of the crate.
+/// * `git_sha`: commit the binary was built from, suffixed `-dirty` when
+///   built with uncommitted changes.
+/// * `build_profile`: `debug` or `release`.
+/// * `config_file`: as resolved from `--config`, canonicalized when it exists.
+/// * `connection`: `user@host:port/db_name` of the config, if it loads.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct VersionInfo {
+    pub version: String,
+    pub git_sha: String,
+    pub build_profile: String,
+    pub config_file: String,
+    pub connection: Option<String>,
+    pub server_version: Option<String>,
+    pub postgis_version: Option<String>,
+    pub pg_dump_version: Option<String>,
+    pub pg_restore_version: Option<String>,
+}
+
+impl VersionInfo {
+    /// The fields as name and value, `unknown` for those that could not be read.
+    pub(crate) fn entries(&self) -> Vec<(&'static str, String)> {
+        let known = |value: &Option<String>| value.clone().unwrap_or_else(|| "unknown".to_string());
+        vec![
+            ("version", self.version.clone()),
+            ("git_sha", self.git_sha.clone()),
+            ("build_profile", self.build_profile.clone()),
+            ("config_file", self.config_file.clone()),
+            ("connection", known(&self.connection)),
+            ("server_version", known(&self.server_version)),
+            ("postgis_version", known(&self.postgis_version)),
+            ("pg_dump_version", known(&self.pg_dump_version)),
+            ("pg_restore_version", known(&self.pg_restore_version)),
+        ]
+    }
+}
+
+/// Sizes of a table on disk, in bytes. `toast_bytes` holds its large
+/// values, like big geometries, stored out of its rows.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TableSizes {
+    pub table_bytes: i64,
+    pub toast_bytes: i64,
+    pub indexes_bytes: i64,
+    pub total_bytes: i64,
+}
+
+/// An index of a table, with its definition and size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexInfo {
+    pub name: String,
+    pub definition: String,
+    pub bytes: i64,
+}
+
+/// How many geometries of a table are of `geometry_type`, `None` counting
+/// the NULL ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeometryTypeCount {
+    pub geometry_type: Option<String>,
+    pub count: i64,
+}
+
+/// The geometries checked with `ST_IsValid` out of a random sample, and the
+/// invalid ones among them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValiditySample {
+    pub sampled: i64,
+    pub invalid: i64,
+}
+
+/// When a table was last vacuumed and analyzed, by hand or by autovacuum,
+/// and the dead rows a vacuum would reclaim. `None` when it never was.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceInfo {
+    pub last_vacuum: Option<String>,
+    pub last_autovacuum: Option<String>,
+    pub last_analyze: Option<String>,
+    pub last_autoanalyze: Option<String>,
+    pub dead_rows: Option<i64>,
+}
+
+/// Result of `table_stats`, in one document.
+///
+/// * `estimated_rows`: from the statistics of the planner, `None` before
+///   the first ANALYZE.
+/// * `geometry_column`, `srid`, `geometry_types`, `extent`, `validity`:
+///   of the spatial column `export_geojson` reads, empty without one. The
+///   extent is in the SRID of the column.
+/// * `imports`: the files loaded into the table, newest first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TableStats {
+    pub table_name: String,
+    pub estimated_rows: Option<i64>,
+    pub sizes: TableSizes,
+    pub geometry_column: Option<String>,
+    pub srid: Option<i32>,
+    pub geometry_types: Vec<GeometryTypeCount>,
+    pub extent: Option<BoundingBox>,
+    pub validity: Option<ValiditySample>,
+    pub indexes: Vec<IndexInfo>,
+    pub maintenance: MaintenanceInfo,
+    pub imports: Vec<ImportRecord>,
+}
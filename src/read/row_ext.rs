@@ -0,0 +1,204 @@
+//! Reading the rows of `DatabaseQueriesRead::execute` and of the clients of
+//! the crate without panicking on a column of another type.
+//!
+//! `Row::get` panics on a missing column, a NULL read as a value, or a type
+//! the column does not convert to. `RowExt::try_get_as` and the typed
+//! `try_get_*` helpers return a `PgcError::Row` naming the column and both
+//! types instead, `FromRow` builds the structs of the crate from a row, and
+//! `rows_to_structs` any struct deserializable from the columns:
+//!
+//! ```no_run
+//! use postgres_connect_rust::{rows_to_structs, RowExt};
+//! use postgres_connect_rust::{DatabaseQueriesRead, PostgresQueriesRead};
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Parcel {
+//!     name: String,
+//!     area: Option<f64>,
+//! }
+//!
+//! # async fn read(read_queries: PostgresQueriesRead) -> Result<(), postgres_connect_rust::PgcError> {
+//! let rows = read_queries
+//!     .execute("SELECT name, ST_Area(geometry) AS area FROM parcels".to_string())
+//!     .await?;
+//! let first_name = rows[0].try_get_string("name")?;
+//! let parcels: Vec<Parcel> = rows_to_structs(&rows)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::read::reports::ColumnInfo;
+use crate::utils::error::PgcError;
+use crate::write::utils::FeatureWithMeta;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::fmt;
+use tokio_postgres::row::RowIndex;
+use tokio_postgres::types::{FromSql, Type};
+use tokio_postgres::{Column, Row};
+
+/// The getters of `Row` returning an error rather than panicking.
+///
+/// A column is given by its index or its name, as to `Row::get`.
+pub trait RowExt {
+    /// The value of `column` as a `T`, an `Option` for a nullable one.
+    fn try_get_as<'a, T, I>(&'a self, column: I) -> Result<T, PgcError>
+    where
+        T: FromSql<'a>,
+        I: RowIndex + fmt::Display;
+
+    /// A `text`, `varchar` or `name` column.
+    fn try_get_string<I: RowIndex + fmt::Display>(&self, column: I) -> Result<String, PgcError> {
+        self.try_get_as(column)
+    }
+
+    /// A `bigint` column, like a `COUNT(*)`.
+    fn try_get_i64<I: RowIndex + fmt::Display>(&self, column: I) -> Result<i64, PgcError> {
+        self.try_get_as(column)
+    }
+
+    /// An `integer` column.
+    fn try_get_i32<I: RowIndex + fmt::Display>(&self, column: I) -> Result<i32, PgcError> {
+        self.try_get_as(column)
+    }
+
+    /// A `double precision` column.
+    fn try_get_f64<I: RowIndex + fmt::Display>(&self, column: I) -> Result<f64, PgcError> {
+        self.try_get_as(column)
+    }
+
+    /// A `boolean` column.
+    fn try_get_bool<I: RowIndex + fmt::Display>(&self, column: I) -> Result<bool, PgcError> {
+        self.try_get_as(column)
+    }
+
+    /// A `json` or `jsonb` column.
+    fn try_get_json<I: RowIndex + fmt::Display>(&self, column: I) -> Result<Value, PgcError> {
+        self.try_get_as(column)
+    }
+}
+
+impl RowExt for Row {
+    fn try_get_as<'a, T, I>(&'a self, column: I) -> Result<T, PgcError>
+    where
+        T: FromSql<'a>,
+        I: RowIndex + fmt::Display,
+    {
+        let name = column.to_string();
+        self.try_get(column).map_err(|e| PgcError::Row {
+            column: name.clone(),
+            expected: short_type_name::<T>(),
+            actual: column_of(self, &name).map(|column| column.type_().to_string()),
+            message: e.to_string(),
+        })
+    }
+}
+
+/// The column of `row` named `name`, or at the index `name` holds.
+fn column_of<'a>(row: &'a Row, name: &str) -> Option<&'a Column> {
+    match name.parse::<usize>() {
+        Ok(index) => row.columns().get(index),
+        Err(_) => row.columns().iter().find(|column| column.name() == name),
+    }
+}
+
+/// The name of `T` without the paths of its types, e.g. `Option<String>`.
+fn short_type_name<T: ?Sized>() -> String {
+    std::any::type_name::<T>()
+        .split_inclusive(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+        .map(|part| part.rsplit("::").next().unwrap_or(part))
+        .collect()
+}
+
+/// A value built from a row, by the names of its columns.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self, PgcError>;
+}
+
+/// Every row of `rows` as a `T`, failing on the first that is not one.
+pub fn rows_to<T: FromRow>(rows: &[Row]) -> Result<Vec<T>, PgcError> {
+    rows.iter().map(T::from_row).collect()
+}
+
+/// `column_name` and `data_type`, as `information_schema.columns` has them.
+impl FromRow for ColumnInfo {
+    fn from_row(row: &Row) -> Result<Self, PgcError> {
+        Ok(ColumnInfo {
+            column_name: row.try_get_string("column_name")?,
+            data_type: row.try_get_string("data_type")?,
+        })
+    }
+}
+
+/// `dataset_name`, `properties` and `geometry_wkt`, e.g. selected as
+/// `name AS dataset_name, properties, ST_AsText(geometry) AS geometry_wkt`.
+impl FromRow for FeatureWithMeta {
+    fn from_row(row: &Row) -> Result<Self, PgcError> {
+        Ok(FeatureWithMeta {
+            dataset_name: row.try_get_string("dataset_name")?,
+            properties: row.try_get_json("properties")?,
+            geometry_wkt: row.try_get_string("geometry_wkt")?,
+        })
+    }
+}
+
+/// Every row of `rows` deserialized as a `T`, from a JSON object of its
+/// columns by name, see `row_to_json`.
+pub fn rows_to_structs<T: DeserializeOwned>(rows: &[Row]) -> Result<Vec<T>, PgcError> {
+    rows.iter()
+        .map(|row| {
+            serde_json::from_value(Value::Object(row_to_json(row)?)).map_err(|e| PgcError::Row {
+                column: String::new(),
+                expected: short_type_name::<T>(),
+                actual: None,
+                message: e.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The columns of `row` as a JSON object, NULL as `null`. Booleans, numbers,
+/// strings, JSON and arrays of them convert, other types fail: cast
+/// them to `text` in the query.
+pub(crate) fn row_to_json(row: &Row) -> Result<Map<String, Value>, PgcError> {
+    let mut object = Map::new();
+    for (index, column) in row.columns().iter().enumerate() {
+        let value = match column.type_() {
+            &Type::BOOL => row.try_get_as::<Option<bool>, _>(index)?.map(Value::from),
+            &Type::INT2 => row.try_get_as::<Option<i16>, _>(index)?.map(Value::from),
+            &Type::INT4 => row.try_get_as::<Option<i32>, _>(index)?.map(Value::from),
+            &Type::INT8 => row.try_get_as::<Option<i64>, _>(index)?.map(Value::from),
+            &Type::FLOAT4 => row.try_get_as::<Option<f32>, _>(index)?.map(Value::from),
+            &Type::FLOAT8 => row.try_get_as::<Option<f64>, _>(index)?.map(Value::from),
+            &Type::TEXT | &Type::VARCHAR | &Type::BPCHAR | &Type::NAME => {
+                row.try_get_as::<Option<String>, _>(index)?.map(Value::from)
+            }
+            &Type::JSON | &Type::JSONB => row.try_get_as::<Option<Value>, _>(index)?,
+            &Type::BOOL_ARRAY => row
+                .try_get_as::<Option<Vec<bool>>, _>(index)?
+                .map(Value::from),
+            &Type::INT4_ARRAY => row
+                .try_get_as::<Option<Vec<i32>>, _>(index)?
+                .map(Value::from),
+            &Type::INT8_ARRAY => row
+                .try_get_as::<Option<Vec<i64>>, _>(index)?
+                .map(Value::from),
+            &Type::FLOAT8_ARRAY => row
+                .try_get_as::<Option<Vec<f64>>, _>(index)?
+                .map(Value::from),
+            &Type::TEXT_ARRAY | &Type::VARCHAR_ARRAY => row
+                .try_get_as::<Option<Vec<String>>, _>(index)?
+                .map(Value::from),
+            other => {
+                return Err(PgcError::Row {
+                    column: column.name().to_string(),
+                    expected: "a JSON value".to_string(),
+                    actual: Some(other.to_string()),
+                    message: format!("cast {} to text in the query", column.name()),
+                })
+            }
+        };
+        object.insert(column.name().to_string(), value.unwrap_or(Value::Null));
+    }
+    Ok(object)
+}
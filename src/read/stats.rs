@@ -0,0 +1,164 @@
+use crate::read::dialect::Dialect;
+use crate::read::reports::{
+    GeometryTypeCount, IndexInfo, MaintenanceInfo, TableSizes, ValiditySample,
+};
+use crate::read::row_ext::RowExt;
+use crate::utils::error::PgcError;
+use crate::utils::sql;
+use crate::write::options::BoundingBox;
+use tokio_postgres::Client;
+
+/// Geometries `validity` checks, out of a random sample of the table.
+const VALIDITY_SAMPLE: f64 = 1000.0;
+
+/// The sizes of a table, and the rows the planner estimates it has.
+pub(crate) async fn sizes(
+    client: &Client,
+    dialect: Dialect,
+    table_name: &str,
+) -> Result<(TableSizes, Option<i64>), PgcError> {
+    let row = client
+        .query_one(&dialect.sql().sizes_query(table_name)?, &[])
+        .await?;
+    let sizes = TableSizes {
+        table_bytes: row.try_get_i64(0)?,
+        toast_bytes: row.try_get_i64(1)?,
+        indexes_bytes: row.try_get_i64(2)?,
+        total_bytes: row.try_get_i64(3)?,
+    };
+    // -1 before the first ANALYZE or VACUUM
+    let estimated_rows = Some(row.try_get_i64(4)?).filter(|rows| *rows >= 0);
+    Ok((sizes, estimated_rows))
+}
+
+/// The indexes of a table, largest first.
+pub(crate) async fn indexes(
+    client: &Client,
+    dialect: Dialect,
+    table_name: &str,
+) -> Result<Vec<IndexInfo>, PgcError> {
+    let rows = client
+        .query(&dialect.sql().indexes_query(table_name)?, &[])
+        .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(IndexInfo {
+                name: row.try_get_string(0)?,
+                definition: row.try_get_string(1)?,
+                bytes: row.try_get_i64(2)?,
+            })
+        })
+        .collect()
+}
+
+/// When a table was last vacuumed and analyzed. Empty for a view, or on a
+/// dialect without the statistics views.
+pub(crate) async fn maintenance(
+    client: &Client,
+    dialect: Dialect,
+    table_name: &str,
+) -> Result<MaintenanceInfo, PgcError> {
+    let Some(query) = dialect.sql().maintenance_query(table_name)? else {
+        return Ok(MaintenanceInfo::default());
+    };
+    let row = client.query_opt(&query, &[]).await?;
+    Ok(match row {
+        Some(row) => MaintenanceInfo {
+            last_vacuum: row.try_get_as(0)?,
+            last_autovacuum: row.try_get_as(1)?,
+            last_analyze: row.try_get_as(2)?,
+            last_autoanalyze: row.try_get_as(3)?,
+            dead_rows: row.try_get_as(4)?,
+        },
+        None => MaintenanceInfo::default(),
+    })
+}
+
+/// How many geometries of each type `geometry` of a table holds, the most
+/// common first.
+pub(crate) async fn geometry_types(
+    client: &Client,
+    table_name: &str,
+    geometry: &str,
+) -> Result<Vec<GeometryTypeCount>, PgcError> {
+    let rows = client
+        .query(
+            &format!(
+                "SELECT GeometryType({}), count(*) FROM {} t GROUP BY 1 ORDER BY 2 DESC, 1",
+                geometry,
+                sql::ident(table_name)?
+            ),
+            &[],
+        )
+        .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(GeometryTypeCount {
+                geometry_type: row.try_get_as(0)?,
+                count: row.try_get_i64(1)?,
+            })
+        })
+        .collect()
+}
+
+/// The box containing `geometry` over a table, in its SRID. `None` when it
+/// has no geometry.
+pub(crate) async fn extent(
+    client: &Client,
+    table_name: &str,
+    geometry: &str,
+) -> Result<Option<BoundingBox>, PgcError> {
+    let row = client
+        .query_one(
+            &format!(
+                "SELECT min(ST_XMin({g})), min(ST_YMin({g})), max(ST_XMax({g})), max(ST_YMax({g}))
+                FROM {} t",
+                sql::ident(table_name)?,
+                g = geometry
+            ),
+            &[],
+        )
+        .await?;
+    let corners: (Option<f64>, Option<f64>, Option<f64>, Option<f64>) = (
+        row.try_get_as(0)?,
+        row.try_get_as(1)?,
+        row.try_get_as(2)?,
+        row.try_get_as(3)?,
+    );
+    Ok(match corners {
+        (Some(min_x), Some(min_y), Some(max_x), Some(max_y)) => Some(BoundingBox {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        }),
+        _ => None,
+    })
+}
+
+/// `ST_IsValid` over about `VALIDITY_SAMPLE` geometries of a table of
+/// `estimated_rows`, picked at random rather than read in full.
+pub(crate) async fn validity(
+    client: &Client,
+    dialect: Dialect,
+    table_name: &str,
+    geometry: &str,
+    estimated_rows: Option<i64>,
+) -> Result<ValiditySample, PgcError> {
+    let percent = match estimated_rows {
+        Some(rows) if rows as f64 > VALIDITY_SAMPLE => VALIDITY_SAMPLE * 100.0 / rows as f64,
+        _ => 100.0,
+    };
+    let row = client
+        .query_one(
+            &dialect
+                .sql()
+                .validity_query(table_name, geometry, percent)?,
+            &[],
+        )
+        .await?;
+    Ok(ValiditySample {
+        sampled: row.try_get_i64(0)?,
+        invalid: row.try_get_i64(1)?,
+    })
+}
@@ -0,0 +1,779 @@
+//! Fakes of the query traits, for testing the code calling them without a
+//! database. Built with the `testing` feature.
+//!
+//! ```
+//! use postgres_connect_rust::testing::MockQueriesWrite;
+//! use postgres_connect_rust::{DatabaseQueriesWrite, ErrorKind};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let queries = MockQueriesWrite::default();
+//! queries.fail("drop", ErrorKind::Sql, "permission denied");
+//! queries
+//!     .insert_geojson("data/parcels.geojson", None)
+//!     .await
+//!     .unwrap();
+//! assert!(queries.drop("parcels").await.is_err());
+//!
+//! let calls = queries.calls_to("insert_geojson");
+//! assert_eq!(calls[0].args[0], "data/parcels.geojson");
+//! # }
+//! ```
+
+use crate::read::queries::DatabaseQueriesRead;
+use crate::read::reports::{
+    CollationDependentIndex, CollationStatus, ColumnInfo, ExportReport, GeoColumnInfo,
+    ImportRecord, PropertyTypeReport, ServerInfo, TableStats, TileExport,
+};
+use crate::utils::error::{ErrorKind, PgcError};
+use crate::utils::sql::PgType;
+use crate::write::options::{
+    BackupOptions, BackupSink, BoundingBox, ExportOptions, GeoTableOptions, InsertGeoJsonOptions,
+    RasterOptions, RestoreOptions, WatchOptions,
+};
+use crate::write::queries::DatabaseQueriesWrite;
+use crate::write::reports::{
+    BackupInfo, BackupVerification, CollationRefresh, DropOutcome, IngestError, IngestReport,
+    LogicalBackup, MaterializedColumn, RasterImport, SimplifiedTable, StreamedBackup, WatchReport,
+};
+use crate::write::utils::{FeatureFilter, PatchMode};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncRead;
+use tokio_postgres::Client;
+
+/// A call a mock received: the name of the method, and its arguments in
+/// order, strings as they are and anything else formatted with `{:?}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockCall {
+    pub method: &'static str,
+    pub args: Vec<String>,
+}
+
+/// What a mock records and answers, by method name.
+#[derive(Default)]
+struct MockState {
+    calls: Mutex<Vec<MockCall>>,
+    results: Mutex<HashMap<&'static str, Arc<dyn Any + Send + Sync>>>,
+    failures: Mutex<HashMap<&'static str, (ErrorKind, String)>>,
+}
+
+impl MockState {
+    fn returns<T: Clone + Send + Sync + 'static>(&self, method: &'static str, value: T) {
+        if let Ok(mut results) = self.results.lock() {
+            results.insert(method, Arc::new(value));
+        }
+    }
+
+    fn fail(&self, method: &'static str, kind: ErrorKind, message: &str) {
+        if let Ok(mut failures) = self.failures.lock() {
+            failures.insert(method, (kind, message.to_string()));
+        }
+    }
+
+    fn calls(&self) -> Vec<MockCall> {
+        self.calls
+            .lock()
+            .map(|calls| calls.clone())
+            .unwrap_or_default()
+    }
+
+    /// Record the call, then answer it: with the error set by `fail`, the
+    /// value set by `returns`, or else `default`, an error when it is `None`.
+    fn call<T: Clone + 'static>(
+        &self,
+        method: &'static str,
+        args: Vec<String>,
+        default: Option<T>,
+    ) -> Result<T, PgcError> {
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.push(MockCall { method, args });
+        }
+        let failure = self
+            .failures
+            .lock()
+            .ok()
+            .and_then(|failures| failures.get(method).cloned());
+        if let Some((kind, message)) = failure {
+            return Err(kind.wrap(message).into());
+        }
+        let result = self
+            .results
+            .lock()
+            .ok()
+            .and_then(|results| results.get(method).cloned());
+        match result {
+            Some(result) => result.downcast_ref::<T>().cloned().ok_or_else(|| {
+                format!("❌ The result set for {} is not of its return type", method).into()
+            }),
+            None => default
+                .ok_or_else(|| format!("❌ No result set for {}, see `returns`", method).into()),
+        }
+    }
+}
+
+/// An argument as recorded in a `MockCall`.
+fn arg<T: Debug + ?Sized>(value: &T) -> String {
+    let formatted = format!("{:?}", value);
+    // Strings are recorded without their quotes
+    match formatted
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+    {
+        Some(string) => string.to_string(),
+        None => formatted,
+    }
+}
+
+/// A `DatabaseQueriesRead` recording its calls, answering them with what
+/// `returns` set, or else empty lists, zero and `None`. The methods
+/// returning a report, like `server_info`, fail without one.
+#[derive(Default)]
+pub struct MockQueriesRead {
+    state: MockState,
+}
+
+/// A `DatabaseQueriesWrite` recording its calls, answering them with what
+/// `returns` set, or else empty reports and lists. The methods returning a
+/// report without a default, like `create_simplified_table`, fail without
+/// one.
+#[derive(Default)]
+pub struct MockQueriesWrite {
+    state: MockState,
+}
+
+macro_rules! mock_setup {
+    ($mock:ty) => {
+        impl $mock {
+            /// Answer the calls to `method` with `value`, which must be of
+            /// its return type without the `Result`.
+            pub fn returns<T: Clone + Send + Sync + 'static>(
+                &self,
+                method: &'static str,
+                value: T,
+            ) -> &Self {
+                self.state.returns(method, value);
+                self
+            }
+
+            /// Fail the calls to `method` with `message`, of `kind`.
+            pub fn fail(&self, method: &'static str, kind: ErrorKind, message: &str) -> &Self {
+                self.state.fail(method, kind, message);
+                self
+            }
+
+            /// The calls received so far, in order.
+            pub fn calls(&self) -> Vec<MockCall> {
+                self.state.calls()
+            }
+
+            /// The calls to `method` received so far, in order.
+            pub fn calls_to(&self, method: &str) -> Vec<MockCall> {
+                self.calls()
+                    .into_iter()
+                    .filter(|call| call.method == method)
+                    .collect()
+            }
+        }
+    };
+}
+
+mock_setup!(MockQueriesRead);
+mock_setup!(MockQueriesWrite);
+
+impl MockQueriesRead {
+    /// Answer `list_tables` and `list_tables_in_schema` with `tables`.
+    pub fn with_tables(self, tables: &[&str]) -> Self {
+        let tables: Vec<String> = tables.iter().map(|table| table.to_string()).collect();
+        self.state.returns("list_tables", tables.clone());
+        self.state.returns("list_tables_in_schema", tables);
+        self
+    }
+
+    /// Answer `table_row_count` with `rows`.
+    pub fn with_row_count(self, rows: i64) -> Self {
+        self.state.returns("table_row_count", rows);
+        self
+    }
+}
+
+#[async_trait]
+impl DatabaseQueriesRead for MockQueriesRead {
+    async fn execute(&self, query: String) -> Result<Vec<tokio_postgres::row::Row>, PgcError> {
+        // Rows cannot be built outside of tokio_postgres
+        self.state.call("execute", vec![query], Some(Vec::new()))
+    }
+
+    async fn list_columns(&self, table_name: &str) -> Result<Vec<ColumnInfo>, PgcError> {
+        self.state
+            .call("list_columns", vec![arg(table_name)], Some(Vec::new()))
+    }
+
+    async fn list_tables(&self, only_user_tables: Option<bool>) -> Result<Vec<String>, PgcError> {
+        self.state.call(
+            "list_tables",
+            vec![arg(&only_user_tables)],
+            Some(Vec::new()),
+        )
+    }
+
+    async fn list_tables_in_schema(
+        &self,
+        schema: &str,
+        only_user_tables: Option<bool>,
+    ) -> Result<Vec<String>, PgcError> {
+        self.state.call(
+            "list_tables_in_schema",
+            vec![arg(schema), arg(&only_user_tables)],
+            Some(Vec::new()),
+        )
+    }
+
+    async fn table_row_count(
+        &self,
+        table_name: &str,
+        include_deleted: Option<bool>,
+    ) -> Result<i64, PgcError> {
+        self.state.call(
+            "table_row_count",
+            vec![arg(table_name), arg(&include_deleted)],
+            Some(0),
+        )
+    }
+
+    async fn check_postgis_support(&self) -> Result<bool, PgcError> {
+        self.state.call("check_postgis_support", vec![], Some(true))
+    }
+
+    async fn server_info(&self) -> Result<ServerInfo, PgcError> {
+        self.state.call("server_info", vec![], None)
+    }
+
+    async fn collation_check(&self, db_name: &str) -> Result<CollationStatus, PgcError> {
+        self.state.call("collation_check", vec![arg(db_name)], None)
+    }
+
+    async fn infer_property_types(
+        &self,
+        table_name: &str,
+        sample_size: Option<i64>,
+    ) -> Result<Vec<PropertyTypeReport>, PgcError> {
+        self.state.call(
+            "infer_property_types",
+            vec![arg(table_name), arg(&sample_size)],
+            Some(Vec::new()),
+        )
+    }
+
+    async fn collation_dependent_indexes(&self) -> Result<Vec<CollationDependentIndex>, PgcError> {
+        self.state
+            .call("collation_dependent_indexes", vec![], Some(Vec::new()))
+    }
+
+    async fn import_history(
+        &self,
+        table_name: Option<&str>,
+    ) -> Result<Vec<ImportRecord>, PgcError> {
+        self.state
+            .call("import_history", vec![arg(&table_name)], Some(Vec::new()))
+    }
+
+    async fn geo_column_info(&self, table_name: &str) -> Result<Vec<GeoColumnInfo>, PgcError> {
+        self.state
+            .call("geo_column_info", vec![arg(table_name)], Some(Vec::new()))
+    }
+
+    async fn export_mvt(
+        &self,
+        table_name: &str,
+        z: u32,
+        x: u32,
+        y: u32,
+        output_path: Option<&str>,
+        properties: &[&str],
+    ) -> Result<Vec<u8>, PgcError> {
+        self.state.call(
+            "export_mvt",
+            vec![
+                arg(table_name),
+                arg(&z),
+                arg(&x),
+                arg(&y),
+                arg(&output_path),
+                arg(properties),
+            ],
+            Some(Vec::new()),
+        )
+    }
+
+    async fn export_mvt_range(
+        &self,
+        table_name: &str,
+        min_zoom: u32,
+        max_zoom: u32,
+        bbox: BoundingBox,
+        output_dir: &str,
+        properties: &[&str],
+    ) -> Result<TileExport, PgcError> {
+        self.state.call(
+            "export_mvt_range",
+            vec![
+                arg(table_name),
+                arg(&min_zoom),
+                arg(&max_zoom),
+                arg(&bbox),
+                arg(output_dir),
+                arg(properties),
+            ],
+            Some(TileExport::default()),
+        )
+    }
+
+    async fn export_geojson(
+        &self,
+        table_name: &str,
+        output_path: &str,
+        options: Option<ExportOptions>,
+    ) -> Result<ExportReport, PgcError> {
+        self.state.call(
+            "export_geojson",
+            vec![arg(table_name), arg(output_path), arg(&options)],
+            Some(ExportReport::default()),
+        )
+    }
+
+    async fn export_csv(
+        &self,
+        table_name: &str,
+        output_path: &str,
+        options: Option<ExportOptions>,
+    ) -> Result<ExportReport, PgcError> {
+        self.state.call(
+            "export_csv",
+            vec![arg(table_name), arg(output_path), arg(&options)],
+            Some(ExportReport::default()),
+        )
+    }
+
+    async fn table_stats(&self, table_name: &str) -> Result<TableStats, PgcError> {
+        self.state.call(
+            "table_stats",
+            vec![arg(table_name)],
+            Some(TableStats::default()),
+        )
+    }
+}
+
+/// `IngestReport` for `table_name`, as the default answer of the methods
+/// loading GeoJSON.
+fn ingest_report(table_name: Option<&str>) -> IngestReport {
+    IngestReport {
+        table: table_name.unwrap_or_default().to_string(),
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl DatabaseQueriesWrite for MockQueriesWrite {
+    async fn execute(
+        &self,
+        query: String,
+        success_message: Option<&str>,
+        error_message: Option<&str>,
+    ) -> Result<(), PgcError> {
+        self.state.call(
+            "execute",
+            vec![query, arg(&success_message), arg(&error_message)],
+            Some(()),
+        )
+    }
+
+    async fn drop(&self, table_name: &str) -> Result<(), PgcError> {
+        self.state.call("drop", vec![arg(table_name)], Some(()))
+    }
+
+    async fn drop_many(
+        &self,
+        tables: &[&str],
+        cascade: bool,
+        if_exists: bool,
+    ) -> Result<Vec<(String, DropOutcome)>, PgcError> {
+        self.state.call(
+            "drop_many",
+            vec![arg(tables), arg(&cascade), arg(&if_exists)],
+            Some(Vec::new()),
+        )
+    }
+
+    async fn drop_all_tables(
+        &self,
+        schema: Option<&str>,
+        dry_run: bool,
+        exclude: &[&str],
+        require_confirmation: bool,
+    ) -> Result<Vec<String>, PgcError> {
+        self.state.call(
+            "drop_all_tables",
+            vec![
+                arg(&schema),
+                arg(&dry_run),
+                arg(exclude),
+                arg(&require_confirmation),
+            ],
+            Some(Vec::new()),
+        )
+    }
+
+    async fn create_schema(
+        &self,
+        schema_name: &str,
+        authorization: Option<&str>,
+    ) -> Result<(), PgcError> {
+        self.state.call(
+            "create_schema",
+            vec![arg(schema_name), arg(&authorization)],
+            Some(()),
+        )
+    }
+
+    async fn drop_schema(&self, schema_name: &str, cascade: bool) -> Result<(), PgcError> {
+        self.state.call(
+            "drop_schema",
+            vec![arg(schema_name), arg(&cascade)],
+            Some(()),
+        )
+    }
+
+    async fn truncate(
+        &self,
+        table_name: &str,
+        restart_identity: bool,
+        cascade: bool,
+    ) -> Result<(), PgcError> {
+        self.state.call(
+            "truncate",
+            vec![arg(table_name), arg(&restart_identity), arg(&cascade)],
+            Some(()),
+        )
+    }
+
+    async fn rename_table(&self, old_name: &str, new_name: &str) -> Result<(), PgcError> {
+        self.state
+            .call("rename_table", vec![arg(old_name), arg(new_name)], Some(()))
+    }
+
+    async fn atomic_swap(&self, staging_table: &str, target_table: &str) -> Result<(), PgcError> {
+        self.state.call(
+            "atomic_swap",
+            vec![arg(staging_table), arg(target_table)],
+            Some(()),
+        )
+    }
+
+    async fn fix_collation_version(
+        &self,
+        db_name: Option<&str>,
+    ) -> Result<CollationRefresh, PgcError> {
+        self.state
+            .call("fix_collation_version", vec![arg(&db_name)], None)
+    }
+
+    async fn ensure_postgis_extension(&self, _client: &Client) -> Result<(), PgcError> {
+        self.state
+            .call("ensure_postgis_extension", vec![], Some(()))
+    }
+
+    async fn create_geo_table(
+        &self,
+        _client: &Client,
+        table_name: &str,
+        options: &GeoTableOptions,
+    ) -> Result<(), PgcError> {
+        self.state.call(
+            "create_geo_table",
+            vec![arg(table_name), arg(options)],
+            Some(()),
+        )
+    }
+
+    async fn grant_on_table(
+        &self,
+        table_name: &str,
+        role: &str,
+        privileges: &str,
+    ) -> Result<(), PgcError> {
+        self.state.call(
+            "grant_on_table",
+            vec![arg(table_name), arg(role), arg(privileges)],
+            Some(()),
+        )
+    }
+
+    async fn insert_geojson(
+        &self,
+        geojson_path: &str,
+        options: Option<InsertGeoJsonOptions>,
+    ) -> Result<IngestReport, IngestError> {
+        let table_name = options.as_ref().and_then(|options| options.table.clone());
+        self.state
+            .call(
+                "insert_geojson",
+                vec![arg(geojson_path), arg(&options)],
+                Some(ingest_report(table_name.as_deref())),
+            )
+            .map_err(|source| IngestError {
+                report: ingest_report(table_name.as_deref()),
+                source,
+            })
+    }
+
+    async fn insert_geojson_from_reader(
+        &self,
+        _reader: Box<dyn AsyncRead + Send + Unpin>,
+        options: InsertGeoJsonOptions,
+    ) -> Result<IngestReport, IngestError> {
+        let table_name = options.table.clone();
+        self.state
+            .call(
+                "insert_geojson_from_reader",
+                vec![arg(&options)],
+                Some(ingest_report(table_name.as_deref())),
+            )
+            .map_err(|source| IngestError {
+                report: ingest_report(table_name.as_deref()),
+                source,
+            })
+    }
+
+    #[cfg(feature = "ingest")]
+    async fn watch_directory(
+        &self,
+        dir: &str,
+        options: Option<WatchOptions>,
+    ) -> Result<WatchReport, PgcError> {
+        self.state.call(
+            "watch_directory",
+            vec![arg(dir), arg(&options)],
+            Some(WatchReport::default()),
+        )
+    }
+
+    async fn delete_features(
+        &self,
+        table_name: &str,
+        filter: &FeatureFilter,
+        dry_run: bool,
+    ) -> Result<u64, PgcError> {
+        self.state.call(
+            "delete_features",
+            vec![arg(table_name), arg(filter), arg(&dry_run)],
+            Some(0),
+        )
+    }
+
+    async fn update_properties(
+        &self,
+        table_name: &str,
+        filter: &FeatureFilter,
+        patch: Value,
+        mode: PatchMode,
+        dry_run: bool,
+    ) -> Result<u64, PgcError> {
+        self.state.call(
+            "update_properties",
+            vec![
+                arg(table_name),
+                arg(filter),
+                patch.to_string(),
+                arg(&mode),
+                arg(&dry_run),
+            ],
+            Some(0),
+        )
+    }
+
+    async fn soft_delete_features(
+        &self,
+        table_name: &str,
+        filter: &FeatureFilter,
+    ) -> Result<u64, PgcError> {
+        self.state.call(
+            "soft_delete_features",
+            vec![arg(table_name), arg(filter)],
+            Some(0),
+        )
+    }
+
+    async fn purge_deleted(&self, table_name: &str, older_than: Duration) -> Result<u64, PgcError> {
+        self.state.call(
+            "purge_deleted",
+            vec![arg(table_name), arg(&older_than)],
+            Some(0),
+        )
+    }
+
+    async fn materialize_properties(
+        &self,
+        table_name: &str,
+        mapping: Vec<(String, String, PgType)>,
+        create_indexes: bool,
+    ) -> Result<Vec<MaterializedColumn>, PgcError> {
+        self.state.call(
+            "materialize_properties",
+            vec![arg(table_name), arg(&mapping), arg(&create_indexes)],
+            Some(Vec::new()),
+        )
+    }
+
+    async fn create_simplified_table(
+        &self,
+        source_table: &str,
+        target_table: &str,
+        tolerance: f64,
+    ) -> Result<SimplifiedTable, PgcError> {
+        self.state.call(
+            "create_simplified_table",
+            vec![arg(source_table), arg(target_table), arg(&tolerance)],
+            None,
+        )
+    }
+
+    async fn create_overview_tables(
+        &self,
+        source_table: &str,
+        tolerances: &[f64],
+    ) -> Result<Vec<String>, PgcError> {
+        self.state.call(
+            "create_overview_tables",
+            vec![arg(source_table), arg(tolerances)],
+            Some(Vec::new()),
+        )
+    }
+
+    async fn refresh_overviews(&self, source_table: &str) -> Result<Vec<String>, PgcError> {
+        self.state.call(
+            "refresh_overviews",
+            vec![arg(source_table)],
+            Some(Vec::new()),
+        )
+    }
+
+    async fn add_derived_geometry_columns(&self, table_name: &str) -> Result<u64, PgcError> {
+        self.state.call(
+            "add_derived_geometry_columns",
+            vec![arg(table_name)],
+            Some(0),
+        )
+    }
+
+    async fn insert_raster(
+        &self,
+        raster_path: &str,
+        table_name: &str,
+        options: Option<RasterOptions>,
+    ) -> Result<RasterImport, PgcError> {
+        self.state.call(
+            "insert_raster",
+            vec![arg(raster_path), arg(table_name), arg(&options)],
+            None,
+        )
+    }
+
+    async fn analyze(&self, table_name: &str) -> Result<(), PgcError> {
+        self.state.call("analyze", vec![arg(table_name)], Some(()))
+    }
+
+    async fn vacuum(&self, table_name: &str, full: bool, analyze: bool) -> Result<(), PgcError> {
+        self.state.call(
+            "vacuum",
+            vec![arg(table_name), arg(&full), arg(&analyze)],
+            Some(()),
+        )
+    }
+
+    #[cfg(feature = "backup")]
+    async fn backup_database(
+        &self,
+        output_dir: &str,
+        options: Option<BackupOptions>,
+    ) -> Result<String, PgcError> {
+        self.state.call(
+            "backup_database",
+            vec![arg(output_dir), arg(&options)],
+            Some(format!("{}/backup.dump", output_dir)),
+        )
+    }
+
+    #[cfg(feature = "backup")]
+    async fn stream_backup(
+        &self,
+        sink: BackupSink,
+        options: Option<BackupOptions>,
+    ) -> Result<StreamedBackup, PgcError> {
+        self.state
+            .call("stream_backup", vec![sink.to_string(), arg(&options)], None)
+    }
+
+    #[cfg(feature = "backup")]
+    async fn restore_database(
+        &self,
+        dump_file: &str,
+        options: Option<RestoreOptions>,
+    ) -> Result<(), PgcError> {
+        self.state.call(
+            "restore_database",
+            vec![arg(dump_file), arg(&options)],
+            Some(()),
+        )
+    }
+
+    #[cfg(feature = "backup")]
+    async fn prune_backups(
+        &self,
+        dir: &str,
+        keep_last: usize,
+        keep_days: Option<u32>,
+        utc_timestamps: bool,
+        dry_run: bool,
+    ) -> Result<Vec<String>, PgcError> {
+        self.state.call(
+            "prune_backups",
+            vec![
+                arg(dir),
+                arg(&keep_last),
+                arg(&keep_days),
+                arg(&utc_timestamps),
+                arg(&dry_run),
+            ],
+            Some(Vec::new()),
+        )
+    }
+
+    #[cfg(feature = "backup")]
+    async fn list_backups(&self, dir: &str) -> Result<Vec<BackupInfo>, PgcError> {
+        self.state
+            .call("list_backups", vec![arg(dir)], Some(Vec::new()))
+    }
+
+    #[cfg(feature = "backup")]
+    async fn verify_backup(&self, dump_file: &str) -> Result<BackupVerification, PgcError> {
+        self.state.call("verify_backup", vec![arg(dump_file)], None)
+    }
+
+    #[cfg(feature = "backup")]
+    async fn logical_backup(&self, output_dir: &str) -> Result<LogicalBackup, PgcError> {
+        self.state
+            .call("logical_backup", vec![arg(output_dir)], None)
+    }
+
+    #[cfg(feature = "backup")]
+    async fn logical_restore(&self, backup_dir: &str) -> Result<LogicalBackup, PgcError> {
+        self.state
+            .call("logical_restore", vec![arg(backup_dir)], None)
+    }
+}
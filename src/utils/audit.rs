@@ -0,0 +1,219 @@
+use crate::read::config::Config;
+use crate::utils::error::ErrorKind;
+use serde::Serialize;
+use std::error::Error as StdError;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::warn;
+
+/// The file the operations of the write queries are recorded in, a JSON
+/// line each, as the `audit_log` of the config file names it.
+///
+/// Each line is appended once the operation is done, whether it succeeded,
+/// failed or was cancelled, see `AuditEntry`. An operation stopped halfway,
+/// by dropping its future, is recorded as `interrupted`. Failing to write
+/// the file is logged as a warning and never fails the operation.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: String,
+    user: String,
+    database: String,
+    hostname: String,
+    /// Held while a line is written, so the lines of the operations running
+    /// at once are not interleaved.
+    lock: Mutex<()>,
+}
+
+/// A line of the audit log.
+///
+/// * `timestamp`: When the operation ended, in RFC 3339 and UTC.
+/// * `operation`: The method of `PostgresQueriesWrite`, e.g. `drop`.
+/// * `target`: The table, schema, file or directory it ran on.
+/// * `database`, `user`: The database and role of the config.
+/// * `options`: The options it ran with, e.g. `cascade=true`.
+/// * `outcome`: See `AuditOutcome`.
+/// * `error`: Why it failed or was cancelled.
+/// * `duration_ms`: How long it ran.
+/// * `rows`: The rows it loaded, deleted or updated, when it counts them.
+/// * `tables`: The tables it dropped, for the drops of several tables.
+/// * `hostname`: The machine it ran on.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub operation: String,
+    pub target: String,
+    pub database: String,
+    pub options: String,
+    pub outcome: AuditOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tables: Vec<String>,
+    pub user: String,
+    pub hostname: String,
+}
+
+/// How an audited operation ended.
+///
+/// * `Succeeded`: It completed.
+/// * `Failed`: It returned an error.
+/// * `Cancelled`: It was not confirmed, or was stopped by Ctrl-C.
+/// * `Interrupted`: Its future was dropped before it completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Succeeded,
+    Failed,
+    Cancelled,
+    Interrupted,
+}
+
+impl AuditLog {
+    /// The audit log appended to `path`, recording the user and database of
+    /// `config`.
+    pub fn new(path: &str, config: &Config) -> Self {
+        Self {
+            path: path.to_string(),
+            user: config.user.clone(),
+            database: config.db_name.clone(),
+            hostname: hostname(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// The file the log is appended to.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Append `entry` to the file, creating it if needed.
+    pub fn append(&self, entry: &AuditEntry) {
+        let mut line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("⚠️ Failed to write the audit log {}: {}", self.path, e);
+                return;
+            }
+        };
+        line.push('\n');
+        let _guard = self.lock.lock();
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(e) = written {
+            warn!("⚠️ Failed to write the audit log {}: {}", self.path, e);
+        }
+    }
+}
+
+/// An operation being audited, see `AuditLog`, appended to the log when
+/// finished, or as `interrupted` when dropped before. Records nothing
+/// without a log.
+pub(crate) struct AuditRecord {
+    log: Option<Arc<AuditLog>>,
+    operation: String,
+    target: String,
+    options: String,
+    rows: Option<u64>,
+    tables: Vec<String>,
+    started: Instant,
+    finished: bool,
+}
+
+impl AuditRecord {
+    /// `operation` starting on `target`, recorded in `log` if any.
+    pub(crate) fn start(log: Option<Arc<AuditLog>>, operation: &str, target: &str) -> Self {
+        Self {
+            log,
+            operation: operation.to_string(),
+            target: target.to_string(),
+            options: String::new(),
+            rows: None,
+            tables: Vec::new(),
+            started: Instant::now(),
+            finished: false,
+        }
+    }
+
+    /// The options the operation runs with, summed up.
+    pub(crate) fn options(mut self, options: impl Into<String>) -> Self {
+        self.options = options.into();
+        self
+    }
+
+    /// The rows the operation loaded, deleted or updated.
+    pub(crate) fn set_rows(&mut self, rows: u64) {
+        self.rows = Some(rows);
+    }
+
+    /// The tables the operation dropped.
+    pub(crate) fn set_tables(&mut self, tables: Vec<String>) {
+        self.tables = tables;
+    }
+
+    /// Append the operation to the log, with the outcome of `result`.
+    pub(crate) fn finish<T, E: StdError + 'static>(mut self, result: &Result<T, E>) {
+        let (outcome, error) = match result {
+            Ok(_) => (AuditOutcome::Succeeded, None),
+            Err(e)
+                if matches!(
+                    ErrorKind::of(e),
+                    ErrorKind::Cancelled | ErrorKind::NotConfirmed
+                ) =>
+            {
+                (AuditOutcome::Cancelled, Some(e.to_string()))
+            }
+            Err(e) => (AuditOutcome::Failed, Some(e.to_string())),
+        };
+        self.append(outcome, error);
+    }
+
+    fn append(&mut self, outcome: AuditOutcome, error: Option<String>) {
+        self.finished = true;
+        let Some(log) = &self.log else {
+            return;
+        };
+        log.append(&AuditEntry {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            operation: self.operation.clone(),
+            target: self.target.clone(),
+            database: log.database.clone(),
+            options: self.options.clone(),
+            outcome,
+            error,
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            rows: self.rows,
+            tables: std::mem::take(&mut self.tables),
+            user: log.user.clone(),
+            hostname: log.hostname.clone(),
+        });
+    }
+}
+
+impl Drop for AuditRecord {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.append(AuditOutcome::Interrupted, None);
+        }
+    }
+}
+
+/// The name of this machine, from the environment, or else the kernel on
+/// Linux, `unknown` when neither tells.
+fn hostname() -> String {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok())
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
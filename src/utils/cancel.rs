@@ -0,0 +1,4 @@
+/// Features or rows the loops over them handle between two checks of their
+/// `CancellationToken`, stopping at the first batch boundary after it is
+/// cancelled.
+pub(crate) const CHECK_EVERY: usize = 1000;
@@ -0,0 +1,8 @@
+/// `field` as a CSV field, quoted if it holds a comma, a quote or a line break.
+pub(crate) fn field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,481 @@
+use crate::read::dialect::Dialect;
+#[cfg(feature = "backup")]
+use crate::write::backup::{CommandInterrupted, Interruption};
+use deadpool_postgres::PoolError;
+use std::error::Error as StdError;
+use std::fmt;
+use std::path::PathBuf;
+use tokio_postgres::error::SqlState;
+
+/// The error of the public functions of the crate, telling what failed as
+/// the variant and the context to act on it as its fields. Displayed as the
+/// message `pgc` prints.
+///
+/// * `Usage`: the call asks for something that cannot be done.
+/// * `Config`: the config file, or a plan file, is missing or invalid.
+/// * `Connection`: the server could not be reached, or the connection was
+///   lost, with the error of the driver when there is one.
+/// * `Query`: the server refused a statement, `sql` when it is known.
+/// * `Io`: reading or writing a file, `path` when it is known.
+/// * `GeoJson`: a GeoJSON input is invalid, read from `path` when it is a
+///   file, at the feature `feature_index` when it is about one.
+/// * `Row`: a column of a row is missing, or does not convert to the type
+///   `expected` of the value read from it, its type being `actual`.
+/// * `InvalidInput`: another input, like a raster or a CSV file, holds
+///   invalid data.
+/// * `Subprocess`: `program`, like `pg_dump`, failed or timed out, with the
+///   last lines of its stderr.
+/// * `UnsupportedOnDialect`: `operation`, like `backup_database`, cannot
+///   work on the server of `dialect`, like CockroachDB.
+/// * `Cancelled`: stopped by Ctrl-C or SIGTERM.
+/// * `NotConfirmed`: a destructive operation was not confirmed.
+/// * `Other`: anything else, like a message or the error of a library.
+#[derive(Debug, thiserror::Error)]
+pub enum PgcError {
+    #[error("{message}")]
+    Usage { message: String },
+    #[error("{message}")]
+    Config { message: String },
+    #[error("{message}")]
+    Connection {
+        message: String,
+        #[source]
+        source: Option<tokio_postgres::Error>,
+    },
+    #[error("{}", query_message(sql, source))]
+    Query {
+        sql: String,
+        #[source]
+        source: tokio_postgres::Error,
+    },
+    #[error("{}", io_message(path, source))]
+    Io {
+        path: Option<PathBuf>,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{}", geojson_message(path, feature_index, message))]
+    GeoJson {
+        path: Option<String>,
+        feature_index: Option<usize>,
+        message: String,
+    },
+    #[error("{}", row_message(column, expected, actual, message))]
+    Row {
+        column: String,
+        expected: String,
+        actual: Option<String>,
+        message: String,
+    },
+    #[error("{message}")]
+    InvalidInput { message: String },
+    #[error("{message}")]
+    Subprocess {
+        program: String,
+        stderr_tail: Vec<String>,
+        message: String,
+    },
+    #[error("❌ {operation} is not supported on {dialect}")]
+    UnsupportedOnDialect { operation: String, dialect: Dialect },
+    #[error("{message}")]
+    Cancelled { message: String },
+    #[error("{message}")]
+    NotConfirmed { message: String },
+    #[error(transparent)]
+    Other(Box<dyn StdError + Send + Sync>),
+}
+
+impl PgcError {
+    /// `source`, the error of running `sql`.
+    pub fn query(sql: impl Into<String>, source: tokio_postgres::Error) -> Self {
+        PgcError::Query {
+            sql: sql.into(),
+            source,
+        }
+    }
+
+    /// `source`, the error of reading or writing `path`.
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        PgcError::Io {
+            path: Some(path.into()),
+            source,
+        }
+    }
+
+    /// A `Usage` error displayed as `message`.
+    pub fn usage(message: impl Into<String>) -> Self {
+        PgcError::Usage {
+            message: message.into(),
+        }
+    }
+
+    /// A `Config` error displayed as `message`.
+    pub fn config(message: impl Into<String>) -> Self {
+        PgcError::Config {
+            message: message.into(),
+        }
+    }
+
+    /// An `InvalidInput` error displayed as `message`.
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        PgcError::InvalidInput {
+            message: message.into(),
+        }
+    }
+
+    /// This error, of the feature at `feature_index` of `path`, when it is
+    /// a `GeoJson` one not placed yet. The feature it already names, if
+    /// any, is kept.
+    pub fn in_geojson(self, path: &str, feature_index: Option<usize>) -> Self {
+        match self {
+            PgcError::GeoJson {
+                path: None,
+                feature_index: index,
+                message,
+            } => PgcError::GeoJson {
+                path: Some(path.to_string()),
+                feature_index: index.or(feature_index),
+                message,
+            },
+            error => error,
+        }
+    }
+
+    /// The kind of this error, as `ErrorKind::of` tells it.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            PgcError::Usage { .. } => ErrorKind::Usage,
+            PgcError::Config { .. } => ErrorKind::Config,
+            PgcError::Connection { .. } => ErrorKind::Connection,
+            PgcError::Query { source, .. } => kind_of_source(source).unwrap_or(ErrorKind::Sql),
+            PgcError::Io { source, .. } => kind_of_source(source).unwrap_or(ErrorKind::Other),
+            PgcError::GeoJson { .. } | PgcError::InvalidInput { .. } => ErrorKind::InvalidInput,
+            PgcError::Row { .. } => ErrorKind::Sql,
+            PgcError::UnsupportedOnDialect { .. } => ErrorKind::Usage,
+            PgcError::Subprocess { .. } => ErrorKind::Subprocess,
+            PgcError::Cancelled { .. } => ErrorKind::Cancelled,
+            PgcError::NotConfirmed { .. } => ErrorKind::NotConfirmed,
+            PgcError::Other(error) => ErrorKind::of(error.as_ref()),
+        }
+    }
+}
+
+fn query_message(sql: &str, source: &tokio_postgres::Error) -> String {
+    let message = match source.as_db_error() {
+        Some(db_error) => format!("❌ {}", db_error),
+        None => format!("❌ {}", source),
+    };
+    if sql.is_empty() {
+        message
+    } else {
+        format!("{}\n  in: {}", message, sql)
+    }
+}
+
+fn io_message(path: &Option<PathBuf>, source: &std::io::Error) -> String {
+    match path {
+        Some(path) => format!("❌ {}: {}", path.display(), source),
+        None => format!("❌ {}", source),
+    }
+}
+
+fn geojson_message(path: &Option<String>, feature_index: &Option<usize>, message: &str) -> String {
+    let message = message.trim_start_matches("❌ ");
+    match (path, feature_index) {
+        (Some(path), Some(index)) => format!("❌ {}, feature {}: {}", path, index, message),
+        (Some(path), None) => format!("❌ {}: {}", path, message),
+        (None, Some(index)) => format!("❌ Feature {}: {}", index, message),
+        (None, None) => format!("❌ {}", message),
+    }
+}
+
+fn row_message(column: &str, expected: &str, actual: &Option<String>, message: &str) -> String {
+    match (column.is_empty(), actual) {
+        (true, _) => format!("❌ A row cannot be read as {}: {}", expected, message),
+        (false, Some(actual)) => format!(
+            "❌ Column {} of type {} cannot be read as {}: {}",
+            column, actual, expected, message
+        ),
+        (false, None) => format!(
+            "❌ No column {} to read as {}: {}",
+            column, expected, message
+        ),
+    }
+}
+
+impl From<tokio_postgres::Error> for PgcError {
+    fn from(error: tokio_postgres::Error) -> Self {
+        // Without a code, it never reached the server or lost it
+        if error.code().is_none() {
+            return PgcError::Connection {
+                message: format!("❌ {}", error),
+                source: Some(error),
+            };
+        }
+        PgcError::query(String::new(), error)
+    }
+}
+
+impl From<PoolError> for PgcError {
+    fn from(error: PoolError) -> Self {
+        match error {
+            PoolError::Backend(error) => error.into(),
+            error => PgcError::Connection {
+                message: format!("❌ Failed to get a connection from the pool: {}", error),
+                source: None,
+            },
+        }
+    }
+}
+
+impl From<std::io::Error> for PgcError {
+    fn from(source: std::io::Error) -> Self {
+        PgcError::Io { path: None, source }
+    }
+}
+
+impl From<geojson::Error> for PgcError {
+    fn from(error: geojson::Error) -> Self {
+        PgcError::GeoJson {
+            path: None,
+            feature_index: None,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for PgcError {
+    fn from(error: serde_json::Error) -> Self {
+        PgcError::GeoJson {
+            path: None,
+            feature_index: None,
+            message: error.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "backup")]
+impl From<CommandInterrupted> for PgcError {
+    fn from(error: CommandInterrupted) -> Self {
+        match error.interruption {
+            Interruption::Cancelled => PgcError::Cancelled {
+                message: error.to_string(),
+            },
+            Interruption::TimedOut(_) => PgcError::Subprocess {
+                message: error.to_string(),
+                program: error.what,
+                stderr_tail: error.stderr_tail,
+            },
+        }
+    }
+}
+
+impl From<tokio::task::JoinError> for PgcError {
+    fn from(error: tokio::task::JoinError) -> Self {
+        PgcError::Other(Box::new(error))
+    }
+}
+
+impl From<String> for PgcError {
+    fn from(message: String) -> Self {
+        PgcError::Other(message.into())
+    }
+}
+
+impl From<&str> for PgcError {
+    fn from(message: &str) -> Self {
+        PgcError::Other(message.into())
+    }
+}
+
+/// The errors of the functions not returning a `PgcError` yet, as the
+/// variant their kind tells.
+impl From<Box<dyn StdError + Send + Sync>> for PgcError {
+    fn from(error: Box<dyn StdError + Send + Sync>) -> Self {
+        let error = match error.downcast::<PgcError>() {
+            Ok(error) => return *error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<tokio_postgres::Error>() {
+            Ok(error) => return (*error).into(),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<std::io::Error>() {
+            Ok(error) => return (*error).into(),
+            Err(error) => error,
+        };
+        #[cfg(feature = "backup")]
+        let error = match error.downcast::<CommandInterrupted>() {
+            Ok(error) => return (*error).into(),
+            Err(error) => error,
+        };
+        let message = error.to_string();
+        match ErrorKind::of(error.as_ref()) {
+            ErrorKind::Usage => PgcError::Usage { message },
+            ErrorKind::Config => PgcError::Config { message },
+            ErrorKind::InvalidInput => PgcError::InvalidInput { message },
+            ErrorKind::Cancelled => PgcError::Cancelled { message },
+            ErrorKind::NotConfirmed => PgcError::NotConfirmed { message },
+            // Their source is still needed, to retry the transient ones
+            _ => PgcError::Other(error),
+        }
+    }
+}
+
+/// What a failure was about, for a caller deciding whether retrying it can
+/// help, see `ErrorKind::of`.
+///
+/// * `Usage`: the command line asks for something that cannot be done.
+/// * `Config`: the config file, or a plan file, is missing or invalid.
+/// * `Connection`: the server could not be reached, or the connection was lost.
+/// * `Sql`: the server refused a statement.
+/// * `InvalidInput`: an input file, like a GeoJSON one, holds invalid data.
+/// * `Subprocess`: a client tool, like `pg_dump`, failed or timed out.
+/// * `Cancelled`: stopped by Ctrl-C or SIGTERM.
+/// * `NotConfirmed`: a destructive operation was not confirmed.
+/// * `Other`: anything else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Usage,
+    Config,
+    Connection,
+    Sql,
+    InvalidInput,
+    Subprocess,
+    Cancelled,
+    NotConfirmed,
+    Other,
+}
+
+impl ErrorKind {
+    /// `error`, tagged as of this kind.
+    pub fn wrap(
+        self,
+        error: impl Into<Box<dyn StdError + Send + Sync>>,
+    ) -> Box<dyn StdError + Send + Sync> {
+        Box::new(KindError {
+            kind: self,
+            source: error.into(),
+        })
+    }
+
+    /// The kind of `error`: the tag of `wrap` closest to it in its chain of
+    /// sources, or else what the first error it can tell tells.
+    pub fn of(error: &(dyn StdError + 'static)) -> ErrorKind {
+        let mut current = Some(error);
+        while let Some(error) = current {
+            if let Some(error) = error.downcast_ref::<KindError>() {
+                return error.kind;
+            }
+            if let Some(kind) = kind_of_source(error) {
+                return kind;
+            }
+            current = error.source();
+        }
+        ErrorKind::Other
+    }
+}
+
+/// The kind of the errors of the libraries pgc uses, if it is one.
+fn kind_of_source(error: &(dyn StdError + 'static)) -> Option<ErrorKind> {
+    if let Some(error) = error.downcast_ref::<PgcError>() {
+        return Some(error.kind());
+    }
+    if let Some(error) = error.downcast_ref::<tokio_postgres::Error>() {
+        return Some(match error.code() {
+            Some(code) if *code == SqlState::QUERY_CANCELED => ErrorKind::Cancelled,
+            Some(_) => ErrorKind::Sql,
+            // Without a code, it never reached the server or lost it
+            None => ErrorKind::Connection,
+        });
+    }
+    #[cfg(feature = "backup")]
+    if let Some(error) = error.downcast_ref::<CommandInterrupted>() {
+        return Some(match error.interruption {
+            Interruption::Cancelled => ErrorKind::Cancelled,
+            Interruption::TimedOut(_) => ErrorKind::Subprocess,
+        });
+    }
+    if let Some(error) = error.downcast_ref::<std::io::Error>() {
+        use std::io::ErrorKind as Io;
+        return match error.kind() {
+            Io::ConnectionRefused
+            | Io::ConnectionReset
+            | Io::ConnectionAborted
+            | Io::NotConnected
+            | Io::HostUnreachable
+            | Io::NetworkUnreachable
+            | Io::TimedOut => Some(ErrorKind::Connection),
+            _ => None,
+        };
+    }
+    if error.is::<geojson::Error>() || error.is::<serde_json::Error>() {
+        return Some(ErrorKind::InvalidInput);
+    }
+    if error.is::<toml::de::Error>() {
+        return Some(ErrorKind::Config);
+    }
+    None
+}
+
+/// An error tagged with its kind by `ErrorKind::wrap`, displayed as it is.
+#[derive(Debug)]
+pub struct KindError {
+    pub kind: ErrorKind,
+    pub source: Box<dyn StdError + Send + Sync>,
+}
+
+impl fmt::Display for KindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl StdError for KindError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn errors_can_cross_threads() {
+        assert_send_sync::<PgcError>();
+        assert_send_sync::<KindError>();
+        let error = std::thread::spawn(|| PgcError::usage("❌ moved"))
+            .join()
+            .expect("the thread");
+        assert_eq!(error.to_string(), "❌ moved");
+    }
+
+    #[test]
+    fn boxed_errors_become_the_variant_of_their_kind() {
+        let cases: [(Box<dyn StdError + Send + Sync>, ErrorKind); 4] = [
+            (
+                ErrorKind::InvalidInput.wrap("❌ bad"),
+                ErrorKind::InvalidInput,
+            ),
+            (ErrorKind::Config.wrap("❌ bad"), ErrorKind::Config),
+            (
+                Box::new(std::io::Error::from(std::io::ErrorKind::ConnectionRefused)),
+                ErrorKind::Connection,
+            ),
+            ("❌ anything".into(), ErrorKind::Other),
+        ];
+        for (boxed, kind) in cases {
+            let error = PgcError::from(boxed);
+            assert_eq!(error.kind(), kind, "{:?}", error);
+        }
+        let error = PgcError::from(ErrorKind::InvalidInput.wrap("❌ bad"));
+        assert!(
+            matches!(error, PgcError::InvalidInput { .. }),
+            "{:?}",
+            error
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use std::borrow::Cow;
+use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
+
+/// What pgc may do with the person running it, see `set_policy`.
+///
+/// * `interactive`: questions and confirmations can be asked, and progress
+///   drawn as bars. Otherwise questions fail, destructive commands need
+///   `--yes`, and progress is logged every so often.
+/// * `color`: the logs are colored.
+/// * `emoji`: the logs and errors hold emoji, left out otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct InteractionPolicy {
+    pub interactive: bool,
+    pub color: bool,
+    pub emoji: bool,
+}
+
+impl InteractionPolicy {
+    /// The policy of a process run with `--non-interactive` or not:
+    /// interactive only when stdin, stdout and stderr are all terminals,
+    /// and colored unless `NO_COLOR` is set as well.
+    pub(crate) fn detect(non_interactive: bool) -> Self {
+        let interactive = !non_interactive
+            && io::stdin().is_terminal()
+            && io::stdout().is_terminal()
+            && io::stderr().is_terminal();
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        Self {
+            interactive,
+            color: interactive && !no_color,
+            emoji: interactive,
+        }
+    }
+}
+
+static POLICY: OnceLock<InteractionPolicy> = OnceLock::new();
+
+/// Interact as `policy` says rather than as detected. Only the first call
+/// counts, and only before anything asked for `policy`.
+pub(crate) fn set_policy(policy: InteractionPolicy) {
+    let _ = POLICY.set(policy);
+}
+
+/// The policy set with `set_policy`, or else detected without
+/// `--non-interactive`.
+pub(crate) fn policy() -> InteractionPolicy {
+    *POLICY.get_or_init(|| InteractionPolicy::detect(false))
+}
+
+/// Whether `c` is part of an emoji: a pictograph, a symbol of the ranges the
+/// messages use, or a variation selector making one of them an emoji.
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x2300..=0x23FF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0xFE0F | 0x1F000..=0x1FAFF
+    )
+}
+
+/// `message` without its emoji, and the spaces after them, when the policy
+/// leaves them out.
+pub fn plain(message: &str) -> Cow<'_, str> {
+    if policy().emoji || !message.chars().any(is_emoji) {
+        return Cow::Borrowed(message);
+    }
+    let mut plain = String::with_capacity(message.len());
+    let mut chars = message.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !is_emoji(c) {
+            plain.push(c);
+            continue;
+        }
+        while chars.peek().is_some_and(|c| is_emoji(*c) || *c == ' ') {
+            chars.next();
+        }
+    }
+    Cow::Owned(plain)
+}
+
+/// stderr, through `plain`, for the logs.
+pub(crate) struct PlainStderr;
+
+impl Write for PlainStderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A log line is written at once, so never split within a character
+        let text = String::from_utf8_lossy(buf);
+        io::stderr().write_all(plain(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
@@ -0,0 +1,78 @@
+use crate::read::config::Config;
+use std::sync::OnceLock;
+use tracing::warn;
+
+/// Most jobs used when neither `--jobs` nor the config says how many.
+const MAX_DEFAULT_JOBS: usize = 4;
+
+/// The jobs set with `set_jobs`, see `configured_jobs`.
+static JOBS: OnceLock<usize> = OnceLock::new();
+
+/// Run the operations with `jobs` jobs rather than one per CPU, as `--jobs`
+/// or the `jobs` key of [config] do, see `jobs_of`. Only the first call
+/// counts.
+pub(crate) fn set_jobs(jobs: usize) {
+    let _ = JOBS.set(jobs);
+}
+
+/// The jobs set with `set_jobs`, if any.
+pub(crate) fn configured_jobs() -> Option<usize> {
+    JOBS.get().copied()
+}
+
+/// The `jobs` key of the [config] of `config`, if it is set and valid.
+pub(crate) fn jobs_of(config: &Config) -> Option<usize> {
+    match config.jobs {
+        Some(0) => {
+            warn!("⚠️ `jobs` of [config] must be at least 1, ignoring it");
+            None
+        }
+        jobs => jobs,
+    }
+}
+
+/// How many jobs the operations running in parallel use unless their
+/// options say: `configured_jobs`, or else one per CPU up to
+/// `MAX_DEFAULT_JOBS`.
+pub(crate) fn default_jobs() -> usize {
+    configured_jobs().unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map_or(1, |cpus| cpus.get())
+            .min(MAX_DEFAULT_JOBS)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_jobs_of_the_config_are_at_least_one() {
+        let mut config = Config {
+            host: "localhost".to_string(),
+            port: 5432,
+            user: "postgres".to_string(),
+            password: String::new(),
+            db_name: "postgres".to_string(),
+            pg_dump_path: None,
+            pg_restore_path: None,
+            jobs: None,
+            audit_log: None,
+            dialect: None,
+        };
+        assert_eq!(jobs_of(&config), None);
+        config.jobs = Some(3);
+        assert_eq!(jobs_of(&config), Some(3));
+        config.jobs = Some(0);
+        assert_eq!(jobs_of(&config), None);
+    }
+
+    #[test]
+    fn the_default_is_one_job_per_cpu_up_to_four() {
+        // Nothing sets the jobs in these tests
+        assert_eq!(configured_jobs(), None);
+        let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+        assert_eq!(default_jobs(), cpus.min(MAX_DEFAULT_JOBS));
+        assert!((1..=MAX_DEFAULT_JOBS).contains(&default_jobs()));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::write::reports::FileReport;
+use deadpool_postgres::Pool;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Features loaded into a table, a counter.
+pub(crate) const FEATURES_LOADED: &str = "pgc_features_loaded_total";
+/// GeoJSON files and streams loaded, a counter.
+pub(crate) const FILES_PROCESSED: &str = "pgc_files_processed_total";
+/// Features read but not loaded: skipped, duplicates and clipped, a counter.
+pub(crate) const ROWS_DROPPED: &str = "pgc_rows_dropped_total";
+/// Backups written by `backup_database`, a counter.
+pub(crate) const BACKUPS: &str = "pgc_backups_total";
+/// Restores completed by `restore_database`, a counter.
+pub(crate) const RESTORES: &str = "pgc_restores_total";
+/// How long loading a file took, a histogram.
+pub(crate) const COPY_DURATION: &str = "pgc_copy_duration_seconds";
+/// The size of the files loaded, a histogram.
+pub(crate) const FILE_SIZE: &str = "pgc_file_size_bytes";
+/// How long a backup took, a histogram.
+pub(crate) const BACKUP_DURATION: &str = "pgc_backup_duration_seconds";
+/// How long a restore took, a histogram.
+pub(crate) const RESTORE_DURATION: &str = "pgc_restore_duration_seconds";
+/// Connections of the pool checked out when one last was, a gauge.
+pub(crate) const POOL_IN_USE: &str = "pgc_pool_in_use";
+
+/// The help line of each metric, for `to_prometheus_text`.
+const HELP: &[(&str, &str)] = &[
+    (FEATURES_LOADED, "Features loaded into a table"),
+    (FILES_PROCESSED, "GeoJSON files and streams loaded"),
+    (ROWS_DROPPED, "Features read but not loaded"),
+    (BACKUPS, "Backups written"),
+    (RESTORES, "Restores completed"),
+    (COPY_DURATION, "Time loading a file took"),
+    (FILE_SIZE, "Size of the files loaded"),
+    (BACKUP_DURATION, "Time a backup took"),
+    (RESTORE_DURATION, "Time a restore took"),
+    (POOL_IN_USE, "Connections of the pool in use"),
+];
+
+/// The upper bounds of the buckets of the histograms in seconds, from a
+/// tenth of a second to an hour.
+const DURATION_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0];
+
+/// The upper bounds of the buckets of the other histograms, in bytes from
+/// 1KB to 10GB.
+const SIZE_BUCKETS: &[f64] = &[1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10];
+
+/// What the query structs measured: their counters, histograms and gauges,
+/// named by the constants of this module.
+///
+/// The query structs share one, see `with_metrics`, and fill it in as they
+/// load files, back up and restore. `snapshot` reads it for another metrics
+/// system, and `to_prometheus_text` writes it as `pgc --metrics-file` does.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state: Mutex<MetricsSnapshot>,
+}
+
+/// A histogram: how many values fell at or under each bound, counted
+/// cumulatively as Prometheus does, with their count and sum.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HistogramSnapshot {
+    pub buckets: Vec<HistogramBucket>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+/// A bucket of a histogram: the values at or under `le`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistogramBucket {
+    pub le: f64,
+    pub count: u64,
+}
+
+/// The values of `Metrics` at a point in time, by metric name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    pub counters: BTreeMap<String, u64>,
+    pub histograms: BTreeMap<String, HistogramSnapshot>,
+    pub gauges: BTreeMap<String, f64>,
+}
+
+impl Metrics {
+    /// Add `by` to the counter `name`.
+    pub fn increment(&self, name: &str, by: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            *state.counters.entry(name.to_string()).or_default() += by;
+        }
+    }
+
+    /// Count `value` in the histogram `name`, with buckets in seconds when
+    /// the name ends in `_seconds`, and in bytes otherwise.
+    pub fn observe(&self, name: &str, value: f64) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let histogram = state.histograms.entry(name.to_string()).or_insert_with(|| {
+            let bounds = if name.ends_with("_seconds") {
+                DURATION_BUCKETS
+            } else {
+                SIZE_BUCKETS
+            };
+            HistogramSnapshot {
+                buckets: bounds
+                    .iter()
+                    .map(|&le| HistogramBucket { le, count: 0 })
+                    .collect(),
+                count: 0,
+                sum: 0.0,
+            }
+        });
+        for bucket in histogram
+            .buckets
+            .iter_mut()
+            .filter(|bucket| value <= bucket.le)
+        {
+            bucket.count += 1;
+        }
+        histogram.count += 1;
+        histogram.sum += value;
+    }
+
+    /// Set the gauge `name` to `value`.
+    pub fn set_gauge(&self, name: &str, value: f64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.gauges.insert(name.to_string(), value);
+        }
+    }
+
+    /// The metrics of a loaded file: the features loaded and dropped, how
+    /// long it took and its size.
+    pub fn record_file(&self, file_report: &FileReport) {
+        self.increment(FILES_PROCESSED, 1);
+        self.increment(FEATURES_LOADED, file_report.features_loaded);
+        self.increment(
+            ROWS_DROPPED,
+            file_report.skipped + file_report.duplicates + file_report.clipped,
+        );
+        self.observe(COPY_DURATION, file_report.duration_ms as f64 / 1000.0);
+        self.observe(FILE_SIZE, file_report.bytes as f64);
+    }
+
+    /// Set `POOL_IN_USE` to the connections of `pool` checked out now.
+    pub fn record_pool(&self, pool: &Pool) {
+        let status = pool.status();
+        self.set_gauge(
+            POOL_IN_USE,
+            status.size.saturating_sub(status.available) as f64,
+        );
+    }
+
+    /// The values of the metrics now.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.state
+            .lock()
+            .map(|state| state.clone())
+            .unwrap_or_default()
+    }
+
+    /// `MetricsSnapshot::to_prometheus_text` of the metrics now.
+    pub fn to_prometheus_text(&self) -> String {
+        self.snapshot().to_prometheus_text()
+    }
+}
+
+impl MetricsSnapshot {
+    /// The metrics in the text format of Prometheus, as its node exporter
+    /// reads them from a file.
+    pub fn to_prometheus_text(&self) -> String {
+        let mut text = String::new();
+        let header = |text: &mut String, name: &str, kind: &str| {
+            if let Some((_, help)) = HELP.iter().find(|(metric, _)| *metric == name) {
+                text.push_str(&format!("# HELP {} {}\n", name, help));
+            }
+            text.push_str(&format!("# TYPE {} {}\n", name, kind));
+        };
+        for (name, value) in &self.counters {
+            header(&mut text, name, "counter");
+            text.push_str(&format!("{} {}\n", name, value));
+        }
+        for (name, value) in &self.gauges {
+            header(&mut text, name, "gauge");
+            text.push_str(&format!("{} {}\n", name, value));
+        }
+        for (name, histogram) in &self.histograms {
+            header(&mut text, name, "histogram");
+            for bucket in &histogram.buckets {
+                text.push_str(&format!(
+                    "{}_bucket{{le=\"{}\"}} {}\n",
+                    name, bucket.le, bucket.count
+                ));
+            }
+            text.push_str(&format!(
+                "{}_bucket{{le=\"+Inf\"}} {}\n",
+                name, histogram.count
+            ));
+            text.push_str(&format!("{}_sum {}\n", name, histogram.sum));
+            text.push_str(&format!("{}_count {}\n", name, histogram.count));
+        }
+        text
+    }
+}
@@ -0,0 +1,13 @@
+pub(crate) mod audit;
+pub(crate) mod cancel;
+pub(crate) mod csv;
+pub(crate) mod error;
+pub(crate) mod format;
+pub(crate) mod interaction;
+pub(crate) mod jobs;
+pub(crate) mod metrics;
+pub(crate) mod progress;
+pub(crate) mod prompt;
+pub(crate) mod reporter;
+pub(crate) mod sql;
+pub(crate) mod suggest;
@@ -0,0 +1,305 @@
+//! How far the loads, backups and restores are, for the applications
+//! embedding the crate, like a web UI drawing a progress bar of a load it
+//! started.
+//!
+//! A `ProgressObserver` is registered on the `observer` of `IngestOptions`,
+//! `BackupOptions` or `RestoreOptions`, wrapped in an `ObserverHandle`:
+//!
+//! ```no_run
+//! use postgres_connect_rust::{InsertGeoJsonOptions, ObserverEvent, ProgressObserver};
+//! use std::sync::Arc;
+//!
+//! struct PrintObserver;
+//!
+//! impl ProgressObserver for PrintObserver {
+//!     fn on_event(&self, event: &ObserverEvent) {
+//!         if let ObserverEvent::FeaturesProcessed { count, .. } = event {
+//!             println!("{} features", count);
+//!         }
+//!     }
+//! }
+//!
+//! let options = InsertGeoJsonOptions::new()
+//!     .table("parcels")
+//!     .observer(Arc::new(PrintObserver))
+//!     .build();
+//! ```
+//!
+//! The events are sent through a bounded channel to a thread of the handle
+//! calling the observer, so a slow observer never holds the operation up:
+//! when the channel is full, the event is dropped and counted in
+//! `ObserverHandle::dropped`. `FeaturesProcessed` is sent at most every
+//! `PROGRESS_INTERVAL` or `PROGRESS_FEATURES` features, and once more when
+//! a file is done. `ReporterObserver` shows the events with a `Reporter`,
+//! as `pgc` draws its progress bars.
+
+use crate::utils::error::ErrorKind;
+use crate::utils::reporter::{ProgressEvent, Reporter};
+use crate::write::reports::IngestReport;
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// The least time between two `FeaturesProcessed` events.
+pub(crate) const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The features after which `FeaturesProcessed` is sent, however soon.
+pub(crate) const PROGRESS_FEATURES: u64 = 10_000;
+
+/// The events waiting for the observer before new ones are dropped.
+const OBSERVER_CAPACITY: usize = 64;
+
+/// What an operation observed with a `ProgressObserver` is doing.
+///
+/// * `FileStarted`: the file `path`, of `bytes` when known, is being
+///   loaded, or restored, or the dump `path` written by a backup.
+/// * `FeaturesProcessed`: `count` features of the file are processed, about
+///   `bytes` of it as read, or in proportion to the features of a stream.
+/// * `CopyFinished`: the rows of `path` are all in the database, `rows`
+///   of them when counted.
+/// * `IndexingStarted`: what follows the copy on `target` began: `ANALYZE`
+///   and the overviews of a load, the post restore steps of a restore.
+/// * `Completed`: the operation succeeded, see `OperationReport`.
+/// * `Failed`: the operation failed with `error`, of `kind`.
+///
+/// A load sends every one of them, a file after another; a restore all
+/// but `FeaturesProcessed`, a backup `FileStarted`, `CopyFinished` once
+/// `pg_dump` is done, and `Completed` or `Failed`.
+#[derive(Debug, Clone)]
+pub enum ObserverEvent {
+    FileStarted { path: String, bytes: Option<u64> },
+    FeaturesProcessed { count: u64, bytes: u64 },
+    CopyFinished { path: String, rows: Option<u64> },
+    IndexingStarted { target: String },
+    Completed { report: OperationReport },
+    Failed { error: String, kind: ErrorKind },
+}
+
+/// What an observed operation did, sent with `ObserverEvent::Completed`.
+///
+/// * `Ingest`: the report of a load.
+/// * `Backup`: the path of the dump.
+/// * `Restore`: the dump restored.
+#[derive(Debug, Clone)]
+pub enum OperationReport {
+    Ingest(Box<IngestReport>),
+    Backup { path: String },
+    Restore { dump_file: String },
+}
+
+/// Told how far an operation is, on a thread of its `ObserverHandle`.
+pub trait ProgressObserver: Send + Sync {
+    fn on_event(&self, event: &ObserverEvent);
+}
+
+/// A `ProgressObserver` registered on the options of an operation, see the
+/// module. Clones send to the same observer.
+#[derive(Clone)]
+pub struct ObserverHandle {
+    inner: Arc<HandleInner>,
+}
+
+struct HandleInner {
+    sender: SyncSender<ObserverEvent>,
+    dropped: AtomicU64,
+    throttle: Mutex<Throttle>,
+}
+
+/// When `FeaturesProcessed` was last sent, and for how many features of a
+/// file of `file_bytes`.
+struct Throttle {
+    sent_at: Instant,
+    sent_count: u64,
+    file_bytes: u64,
+}
+
+impl ObserverHandle {
+    /// `observer`, called on a thread of its own with the events of the
+    /// operations the handle is registered on.
+    pub fn new(observer: Arc<dyn ProgressObserver>) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<ObserverEvent>(OBSERVER_CAPACITY);
+        let spawned = std::thread::Builder::new()
+            .name("pgc-progress".to_string())
+            .spawn(move || {
+                // Ends once every clone of the handle is dropped
+                for event in receiver {
+                    observer.on_event(&event);
+                }
+            });
+        if let Err(e) = spawned {
+            warn!("⚠️ Failed to start the progress observer: {}", e);
+        }
+        Self {
+            inner: Arc::new(HandleInner {
+                sender,
+                dropped: AtomicU64::new(0),
+                throttle: Mutex::new(Throttle {
+                    sent_at: Instant::now(),
+                    sent_count: 0,
+                    file_bytes: 0,
+                }),
+            }),
+        }
+    }
+
+    /// The events dropped as the observer was behind.
+    pub fn dropped(&self) -> u64 {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Send `event`, or drop it when the observer is behind.
+    fn send(&self, event: ObserverEvent) {
+        match self.inner.sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// `path`, of `bytes`, started.
+    pub(crate) fn file_started(&self, path: &str, bytes: Option<u64>) {
+        if let Ok(mut throttle) = self.inner.throttle.lock() {
+            *throttle = Throttle {
+                sent_at: Instant::now(),
+                sent_count: 0,
+                file_bytes: bytes.unwrap_or_default(),
+            };
+        }
+        self.send(ObserverEvent::FileStarted {
+            path: path.to_string(),
+            bytes,
+        });
+    }
+
+    /// `count` of the `total` features of the file are processed, sent when
+    /// `PROGRESS_INTERVAL` or `PROGRESS_FEATURES` passed since the last
+    /// time, or whatever with `force`.
+    pub(crate) fn features_processed(&self, count: u64, total: u64, force: bool) {
+        self.send_features(count, force, |file_bytes| match total {
+            0 => file_bytes,
+            total => (file_bytes as f64 * count as f64 / total as f64) as u64,
+        });
+    }
+
+    /// `features_processed`, with `bytes` of the file read for them.
+    pub(crate) fn features_read(&self, count: u64, bytes: u64, force: bool) {
+        self.send_features(count, force, |_| bytes);
+    }
+
+    /// `FeaturesProcessed` for `count` features, and the `bytes` of the file
+    /// they are, when it is due.
+    fn send_features(&self, count: u64, force: bool, bytes: impl FnOnce(u64) -> u64) {
+        let Ok(mut throttle) = self.inner.throttle.lock() else {
+            return;
+        };
+        let due = throttle.sent_at.elapsed() >= PROGRESS_INTERVAL
+            || count.saturating_sub(throttle.sent_count) >= PROGRESS_FEATURES;
+        if count == throttle.sent_count || !(due || force) {
+            return;
+        }
+        throttle.sent_at = Instant::now();
+        throttle.sent_count = count;
+        let bytes = bytes(throttle.file_bytes);
+        drop(throttle);
+        self.send(ObserverEvent::FeaturesProcessed { count, bytes });
+    }
+
+    /// The rows of `path` are copied.
+    pub(crate) fn copy_finished(&self, path: &str, rows: Option<u64>) {
+        self.send(ObserverEvent::CopyFinished {
+            path: path.to_string(),
+            rows,
+        });
+    }
+
+    /// What follows the copy on `target` began.
+    pub(crate) fn indexing_started(&self, target: &str) {
+        self.send(ObserverEvent::IndexingStarted {
+            target: target.to_string(),
+        });
+    }
+}
+
+impl fmt::Debug for ObserverHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObserverHandle")
+            .field("dropped", &self.dropped())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Run `operation`, telling `observer` if any whether it completed, with
+/// the `OperationReport` of `report`, or failed.
+pub(crate) async fn observed<T, E, Fut>(
+    observer: Option<ObserverHandle>,
+    report: impl FnOnce(&T) -> OperationReport,
+    operation: Fut,
+) -> Result<T, E>
+where
+    E: StdError + 'static,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let result = operation.await;
+    if let Some(observer) = observer {
+        observer.send(match &result {
+            Ok(value) => ObserverEvent::Completed {
+                report: report(value),
+            },
+            Err(e) => ObserverEvent::Failed {
+                error: e.to_string(),
+                kind: ErrorKind::of(e),
+            },
+        });
+    }
+    result
+}
+
+/// Shows the events with a `Reporter` as the phase `phase`: a bar per file
+/// with `ConsoleReporter`, as `pgc` does.
+pub struct ReporterObserver {
+    reporter: Arc<dyn Reporter>,
+    phase: String,
+}
+
+impl ReporterObserver {
+    pub fn new(reporter: Arc<dyn Reporter>, phase: &str) -> Self {
+        Self {
+            reporter,
+            phase: phase.to_string(),
+        }
+    }
+}
+
+impl ProgressObserver for ReporterObserver {
+    fn on_event(&self, event: &ObserverEvent) {
+        let phase = self.phase.clone();
+        match event {
+            ObserverEvent::FileStarted { path, .. } => {
+                self.reporter.progress(ProgressEvent::Started {
+                    phase: phase.clone(),
+                    total: None,
+                });
+                self.reporter.progress(ProgressEvent::Message {
+                    phase,
+                    message: path.clone(),
+                });
+            }
+            ObserverEvent::FeaturesProcessed { count, .. } => {
+                self.reporter.progress(ProgressEvent::Advanced {
+                    phase,
+                    done: *count,
+                });
+            }
+            ObserverEvent::CopyFinished { .. } | ObserverEvent::Failed { .. } => {
+                self.reporter.progress(ProgressEvent::Finished { phase });
+            }
+            ObserverEvent::IndexingStarted { .. } | ObserverEvent::Completed { .. } => {}
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use crate::utils::interaction;
+use std::io::{self, BufRead, Write};
+
+/// Whether the process can interactively ask the user something, as the
+/// interaction policy says.
+pub(crate) fn is_interactive() -> bool {
+    interaction::policy().interactive
+}
+
+/// Ask a yes/no question on the terminal.
+///
+/// Only an explicit `y` or `yes` counts as confirmation; anything else,
+/// including an empty answer or a read error, is a no.
+pub(crate) fn confirm(question: &str) -> bool {
+    eprint!("❓ {} [y/N] ", question);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Ask for a value on the terminal, `default` if the answer is empty.
+pub(crate) fn ask(question: &str, default: &str) -> String {
+    eprint!("❓ {} [{}] ", question, default);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return default.to_string();
+    }
+    match answer.trim() {
+        "" => default.to_string(),
+        answer => answer.to_string(),
+    }
+}
+
+/// Ask for a secret on the terminal without echoing it, empty on a read error.
+#[cfg(feature = "cli")]
+pub(crate) fn ask_secret(question: &str) -> String {
+    rpassword::prompt_password(format!("❓ {} ", question)).unwrap_or_default()
+}
@@ -0,0 +1,215 @@
+use crate::utils::interaction;
+#[cfg(feature = "ingest")]
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// How often the progress of a phase is logged when it is not drawn, see
+/// `InteractionPolicy`.
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How far a long operation, like a restore, is, for `Reporter::progress`.
+///
+/// * `Started`: the phase `phase` began, with `total` items to do when known.
+/// * `Advanced`: `done` items of the phase are done.
+/// * `Message`: what the phase is doing now, e.g. the tables being copied.
+/// * `Finished`: the phase ended, or failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    Started { phase: String, total: Option<u64> },
+    Advanced { phase: String, done: u64 },
+    Message { phase: String, message: String },
+    Finished { phase: String },
+}
+
+/// Where the query structs show what the person running them should see:
+/// messages that are part of a question, tables and progress. Diagnostics
+/// are logged with `tracing` whatever the reporter.
+///
+/// `ConsoleReporter` writes them to the terminal, as `pgc` does, and
+/// `NoopReporter`, the default of the query structs, drops them.
+pub trait Reporter: Send + Sync {
+    fn info(&self, message: &str);
+    fn warn(&self, message: &str);
+    /// `rows`, each holding a value per header.
+    fn table(&self, headers: &[&str], rows: &[Vec<String>]);
+    fn progress(&self, event: ProgressEvent);
+}
+
+/// Drops everything it is given.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopReporter;
+
+impl Reporter for NoopReporter {
+    fn info(&self, _message: &str) {}
+    fn warn(&self, _message: &str) {}
+    fn table(&self, _headers: &[&str], _rows: &[Vec<String>]) {}
+    fn progress(&self, _event: ProgressEvent) {}
+}
+
+/// Writes to stderr, leaving stdout to the results: messages and tables as
+/// they are, without their emoji when the interaction policy says so, and
+/// progress as a bar per phase. When not interactive, or without the
+/// `ingest` feature drawing the bars, the progress is logged every
+/// `PROGRESS_LOG_INTERVAL` instead.
+#[derive(Default)]
+pub struct ConsoleReporter {
+    phases: Mutex<HashMap<String, PhaseProgress>>,
+}
+
+/// How far a phase is, its bar, and when its progress was last logged.
+struct PhaseProgress {
+    phase: String,
+    total: Option<u64>,
+    done: u64,
+    message: String,
+    started_at: Instant,
+    logged_at: Instant,
+    /// Whether the progress is drawn as a bar, rather than logged.
+    drawn: bool,
+    #[cfg(feature = "ingest")]
+    bar: ProgressBar,
+}
+
+impl PhaseProgress {
+    fn new(phase: &str, total: Option<u64>) -> Self {
+        Self {
+            phase: phase.to_string(),
+            total,
+            done: 0,
+            message: String::new(),
+            started_at: Instant::now(),
+            logged_at: Instant::now(),
+            drawn: cfg!(feature = "ingest") && interaction::policy().interactive,
+            #[cfg(feature = "ingest")]
+            bar: Self::bar(phase, total),
+        }
+    }
+
+    /// The bar of `phase`, hidden when not interactive.
+    #[cfg(feature = "ingest")]
+    fn bar(phase: &str, total: Option<u64>) -> ProgressBar {
+        let interactive = interaction::policy().interactive;
+        let bar = if interactive {
+            ProgressBar::no_length()
+        } else {
+            ProgressBar::hidden()
+        };
+        if let Some(total) = total {
+            bar.set_length(total);
+        }
+        if let Ok(style) = ProgressStyle::default_bar()
+            .template("{spinner} {prefix} [{bar:30}] {pos}/{len} items {elapsed} {wide_msg}")
+        {
+            bar.set_style(style.progress_chars("=> "));
+        }
+        bar.set_prefix(phase.to_string());
+        if interactive {
+            bar.enable_steady_tick(Duration::from_millis(200));
+        }
+        bar
+    }
+
+    fn advance(&mut self, done: u64) {
+        self.done = done;
+        #[cfg(feature = "ingest")]
+        self.bar.set_position(done);
+        self.log();
+    }
+
+    fn set_message(&mut self, message: String) {
+        #[cfg(feature = "ingest")]
+        self.bar.set_message(message.clone());
+        self.message = message;
+        self.log();
+    }
+
+    fn finish(self) {
+        #[cfg(feature = "ingest")]
+        self.bar.finish_and_clear();
+    }
+
+    /// Log the progress when it is not drawn and it was not logged for
+    /// `PROGRESS_LOG_INTERVAL`.
+    fn log(&mut self) {
+        if self.drawn || self.logged_at.elapsed() < PROGRESS_LOG_INTERVAL {
+            return;
+        }
+        self.logged_at = Instant::now();
+        let mut line = format!(
+            "⏳ {}: {}/{} items, {:.0?} elapsed",
+            self.phase,
+            self.done,
+            self.total.unwrap_or_default(),
+            self.started_at.elapsed()
+        );
+        if !self.message.is_empty() {
+            line.push_str(", ");
+            line.push_str(&self.message);
+        }
+        info!("{}", line);
+    }
+}
+
+impl Reporter for ConsoleReporter {
+    fn info(&self, message: &str) {
+        eprintln!("{}", interaction::plain(message));
+    }
+
+    fn warn(&self, message: &str) {
+        eprintln!("{}", interaction::plain(message));
+    }
+
+    fn table(&self, headers: &[&str], rows: &[Vec<String>]) {
+        let mut widths: Vec<usize> = headers
+            .iter()
+            .map(|header| header.chars().count())
+            .collect();
+        for row in rows {
+            for (width, value) in widths.iter_mut().zip(row) {
+                *width = (*width).max(value.chars().count());
+            }
+        }
+        let line = |values: Vec<&str>| {
+            let cells: Vec<String> = values
+                .iter()
+                .zip(&widths)
+                .map(|(value, width)| format!("{:<width$}", value, width = width))
+                .collect();
+            eprintln!("   {}", cells.join("  ").trim_end());
+        };
+        line(headers.to_vec());
+        for row in rows {
+            line(row.iter().map(String::as_str).collect());
+        }
+    }
+
+    fn progress(&self, event: ProgressEvent) {
+        let Ok(mut phases) = self.phases.lock() else {
+            return;
+        };
+        match event {
+            ProgressEvent::Started { phase, total } => {
+                let progress = PhaseProgress::new(&phase, total);
+                phases.insert(phase, progress);
+            }
+            ProgressEvent::Advanced { phase, done } => {
+                if let Some(progress) = phases.get_mut(&phase) {
+                    progress.advance(done);
+                }
+            }
+            ProgressEvent::Message { phase, message } => {
+                if let Some(progress) = phases.get_mut(&phase) {
+                    progress.set_message(message);
+                }
+            }
+            ProgressEvent::Finished { phase } => {
+                if let Some(progress) = phases.remove(&phase) {
+                    progress.finish();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use crate::utils::error::PgcError;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Quote a SQL identifier (table, column, schema, database, index name).
+///
+/// The name is wrapped in double quotes and any embedded double quote is
+/// doubled, so it is always treated as a single inert identifier by Postgres,
+/// whatever characters it contains. This also keeps names with uppercase
+/// letters or reserved words (e.g. `order`) working as written.
+///
+/// Fails with `PgcError::InvalidInput` if the name is empty or contains a
+/// NUL byte, neither of which Postgres accepts as an identifier.
+pub(crate) fn ident(name: &str) -> Result<String, PgcError> {
+    if name.is_empty() {
+        return Err(PgcError::invalid_input(
+            "❌ Invalid SQL identifier: name is empty",
+        ));
+    }
+    if name.contains('\0') {
+        return Err(PgcError::invalid_input(format!(
+            "❌ Invalid SQL identifier: {:?} contains a NUL byte",
+            name
+        )));
+    }
+    Ok(format!("\"{}\"", name.replace('"', "\"\"")))
+}
+
+/// Longest identifier Postgres keeps whole, in bytes: a longer one is
+/// truncated to it, which can name another table.
+pub(crate) const MAX_IDENTIFIER_BYTES: usize = 63;
+
+/// A name starting with `prefix` that no other call gives, in this process
+/// or another, for the tables and files an operation creates and drops
+/// itself. Short enough to be an identifier for a `prefix` of up to 20 bytes.
+pub(crate) fn unique_name(prefix: &str) -> String {
+    static NAMES: AtomicU64 = AtomicU64::new(0);
+    // Tells apart the processes of other hosts with the same id
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.subsec_nanos());
+    format!(
+        "{}_{}_{}_{:x}",
+        prefix,
+        std::process::id(),
+        NAMES.fetch_add(1, Ordering::Relaxed),
+        nanos
+    )
+}
+
+/// Quote a SQL string literal.
+///
+/// Single quotes are doubled. If the value contains a backslash, it is emitted
+/// as an escape string (`E'...'`) with the backslashes doubled, so the result
+/// is correct regardless of `standard_conforming_strings`.
+pub(crate) fn literal(value: &str) -> String {
+    let escaped = value.replace('\'', "''");
+    if escaped.contains('\\') {
+        format!("E'{}'", escaped.replace('\\', "\\\\"))
+    } else {
+        format!("'{}'", escaped)
+    }
+}
+
+/// Split the complete statements off `input`, those ended by a `;` outside
+/// quotes, comments and dollar quoted strings, returning them trimmed and
+/// without the `;`, and the rest of `input`, which is still being typed.
+pub fn split_statements(input: &str) -> (Vec<String>, &str) {
+    let bytes = input.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    // Every delimiter is ASCII, which no byte of a multi-byte character is
+    while i < bytes.len() {
+        match bytes[i] {
+            // A doubled quote closes and reopens, to the same effect
+            quote @ (b'\'' | b'"') => {
+                i = find(bytes, i + 1, &[quote]).map_or(bytes.len(), |end| end + 1);
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = find(bytes, i + 2, b"\n").map_or(bytes.len(), |end| end + 1);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = find(bytes, i + 2, b"*/").map_or(bytes.len(), |end| end + 2);
+            }
+            // A $ inside a name, like a$b$, is part of it
+            b'_' | b'a'..=b'z' | b'A'..=b'Z' | 0x80.. => {
+                i += bytes[i..]
+                    .iter()
+                    .position(|b| !is_identifier_byte(*b))
+                    .unwrap_or(bytes.len() - i);
+            }
+            b'$' => {
+                let tag_end = bytes[i + 1..]
+                    .iter()
+                    .position(|b| !(b.is_ascii_alphanumeric() || *b == b'_'))
+                    .map(|position| i + 1 + position);
+                match tag_end {
+                    Some(end) if bytes[end] == b'$' => {
+                        let tag = &bytes[i..=end];
+                        i = find(bytes, end + 1, tag)
+                            .map_or(bytes.len(), |close| close + tag.len());
+                    }
+                    // A parameter like $1
+                    _ => i += 1,
+                }
+            }
+            b';' => {
+                let statement = input[start..i].trim();
+                if !statement.is_empty() {
+                    statements.push(statement.to_string());
+                }
+                i += 1;
+                start = i;
+            }
+            _ => i += 1,
+        }
+    }
+    (statements, &input[start..])
+}
+
+/// Whether `byte` can be part of a name past its first character, any byte
+/// of a multi-byte character included.
+fn is_identifier_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$' || byte >= 0x80
+}
+
+/// Where `needle` is first found in `bytes` from `from` on.
+fn find(bytes: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    bytes
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| from + position)
+}
+
+/// Quote a schema-qualified name as `"schema"."name"`.
+pub(crate) fn qualified(schema: &str, name: &str) -> Result<String, PgcError> {
+    Ok(format!("{}.{}", ident(schema)?, ident(name)?))
+}
+
+/// Column types that JSON property values can be materialized into.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PgType {
+    Text,
+    Integer,
+    BigInt,
+    DoublePrecision,
+    Numeric,
+    Boolean,
+    Date,
+    Timestamptz,
+    Jsonb,
+}
+
+impl PgType {
+    /// The type as written in SQL.
+    pub fn sql_name(&self) -> &'static str {
+        match self {
+            PgType::Text => "TEXT",
+            PgType::Integer => "INTEGER",
+            PgType::BigInt => "BIGINT",
+            PgType::DoublePrecision => "DOUBLE PRECISION",
+            PgType::Numeric => "NUMERIC",
+            PgType::Boolean => "BOOLEAN",
+            PgType::Date => "DATE",
+            PgType::Timestamptz => "TIMESTAMPTZ",
+            PgType::Jsonb => "JSONB",
+        }
+    }
+
+    /// The type as `format_type` names it, e.g. in the catalog.
+    pub fn format_type_name(&self) -> &'static str {
+        match self {
+            PgType::Text => "text",
+            PgType::Integer => "integer",
+            PgType::BigInt => "bigint",
+            PgType::DoublePrecision => "double precision",
+            PgType::Numeric => "numeric",
+            PgType::Boolean => "boolean",
+            PgType::Date => "date",
+            PgType::Timestamptz => "timestamp with time zone",
+            PgType::Jsonb => "jsonb",
+        }
+    }
+
+    /// A regular expression (Postgres `~*` syntax) matching the text values
+    /// that can be cast to this type, or `None` if every value can.
+    pub fn castable_pattern(&self) -> Option<&'static str> {
+        match self {
+            PgType::Text | PgType::Jsonb => None,
+            PgType::Integer | PgType::BigInt => Some(r"^\s*[-+]?\d+\s*$"),
+            PgType::DoublePrecision | PgType::Numeric => {
+                Some(r"^\s*[-+]?(\d+\.?\d*|\.\d+)(e[-+]?\d+)?\s*$")
+            }
+            PgType::Boolean => Some(r"^\s*(true|false|t|f|yes|no|y|n|on|off|1|0)\s*$"),
+            PgType::Date => Some(r"^\s*\d{4}-\d{2}-\d{2}\s*$"),
+            PgType::Timestamptz => Some(
+                r"^\s*\d{4}-\d{2}-\d{2}([ t]\d{2}:\d{2}(:\d{2}(\.\d+)?)?)?\s*(z|[-+]\d{2}(:?\d{2})?)?\s*$",
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::error::ErrorKind;
+
+    #[test]
+    fn identifiers_are_quoted_whole() {
+        assert_eq!(ident("points").unwrap(), "\"points\"");
+        assert_eq!(ident("My Table").unwrap(), "\"My Table\"");
+        assert_eq!(ident("select").unwrap(), "\"select\"");
+        assert_eq!(
+            ident("foo\"; DROP TABLE bar; --").unwrap(),
+            "\"foo\"\"; DROP TABLE bar; --\""
+        );
+        assert_eq!(
+            qualified("public", "My Table").unwrap(),
+            "\"public\".\"My Table\""
+        );
+    }
+
+    #[test]
+    fn empty_and_nul_identifiers_are_invalid_input() {
+        for name in ["", "a\0b"] {
+            let error = ident(name).expect_err(name);
+            assert_eq!(error.kind(), ErrorKind::InvalidInput, "{}", error);
+        }
+        assert!(qualified("", "points").is_err());
+        assert!(qualified("public", "").is_err());
+    }
+
+    #[test]
+    fn unique_names_differ_and_fit_an_identifier() {
+        let first = unique_name("_pgc_parallel_copy");
+        let second = unique_name("_pgc_parallel_copy");
+        assert_ne!(first, second);
+        assert!(first.starts_with("_pgc_parallel_copy_"), "{}", first);
+        assert!(first.len() <= MAX_IDENTIFIER_BYTES, "{}", first);
+    }
+
+    #[test]
+    fn literals_double_their_quotes_and_backslashes() {
+        assert_eq!(literal("it's"), "'it''s'");
+        assert_eq!(literal("a\\b"), "E'a\\\\b'");
+    }
+
+    #[test]
+    fn statements_are_split_at_the_semicolons_outside_quotes() {
+        let (statements, rest) =
+            split_statements("SELECT ';' AS a; SELECT $tag$;$tag$, \"b;\" -- c;\n; SELECT");
+        assert_eq!(
+            statements,
+            ["SELECT ';' AS a", "SELECT $tag$;$tag$, \"b;\" -- c;"]
+        );
+        assert_eq!(rest, " SELECT");
+    }
+
+    #[test]
+    fn a_dollar_inside_a_name_does_not_open_a_dollar_quote() {
+        let (statements, rest) =
+            split_statements("SELECT 1 AS a$b$; COMMIT; DELETE FROM points; SELECT 1 AS c$b$;");
+        assert_eq!(
+            statements,
+            [
+                "SELECT 1 AS a$b$",
+                "COMMIT",
+                "DELETE FROM points",
+                "SELECT 1 AS c$b$"
+            ]
+        );
+        assert_eq!(rest, "");
+    }
+}
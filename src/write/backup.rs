@@ -0,0 +1,2917 @@
+use crate::read::config::{BackupConfig, Config};
+use crate::read::db;
+use crate::read::reports::ServerInfo;
+use crate::utils::error::{ErrorKind, PgcError};
+pub(crate) use crate::utils::format::human_size;
+use crate::utils::reporter::{ProgressEvent, Reporter};
+use crate::utils::sql;
+use crate::write::manifest;
+use crate::write::options::{
+    BackupOptions, CompressionMethod, CompressionSpec, DumpFormat, RestoreOptions,
+};
+use crate::write::reports::{BackupInfo, ExclusionMatch};
+use async_compression::tokio::write::GzipEncoder;
+use async_compression::Level;
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Tables owned by PostGIS, recreated with the extension rather than restored.
+pub(crate) const POSTGIS_TABLES: [&str; 4] = [
+    "geometry_columns",
+    "spatial_ref_sys",
+    "raster_columns",
+    "raster_overviews",
+];
+
+/// Format of the timestamp ending backup filenames.
+pub(crate) const TIMESTAMP_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+/// Extensions of the dumps `backup_database` writes, those ending with
+/// another one first.
+const DUMP_EXTENSIONS: [&str; 6] = ["sql.gz", "zst.dump", "lz4.dump", "dump", "dir", "sql"];
+
+/// The first `pg_dump` version taking a method in `--compress`.
+const COMPRESSION_METHODS_MAJOR: u32 = 16;
+
+/// Level plain dumps are gzipped at without a level in their `CompressionSpec`.
+pub(crate) const DEFAULT_GZIP_LEVEL: u32 = 6;
+
+/// First bytes of a gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Lines of stderr kept for the error of a failed command.
+const STDERR_TAIL_LINES: usize = 50;
+
+/// Up to this many selected tables are named in the backup filename.
+const NAMED_TABLES_MAX: usize = 3;
+
+/// A `pg_dump`/`pg_restore` invocation, kept as a program and a list of
+/// arguments so it is run without a shell. Names and paths are passed
+/// as they are, whatever characters they contain.
+///
+/// The password never appears in the arguments: it is passed to the child
+/// process only, in `PGPASSWORD`.
+#[derive(Debug, Clone)]
+pub(crate) struct PgCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    /// When `run_logged` kills the command, see `Deadline`.
+    pub deadline: Option<Deadline>,
+    /// Kills the command once cancelled, never by default.
+    pub cancellation: CancellationToken,
+}
+
+impl PgCommand {
+    pub(crate) fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            args: Vec::new(),
+            env: Vec::new(),
+            deadline: None,
+            cancellation: CancellationToken::new(),
+        }
+    }
+
+    pub(crate) fn deadline(mut self, deadline: Option<Deadline>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub(crate) fn cancellation(mut self, cancellation: &CancellationToken) -> Self {
+        self.cancellation = cancellation.clone();
+        self
+    }
+
+    pub(crate) fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub(crate) fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// The command ready to be spawned.
+    pub(crate) fn command(&self) -> tokio::process::Command {
+        let mut command = tokio::process::Command::new(&self.program);
+        command.args(&self.args);
+        for (key, value) in &self.env {
+            command.env(key, value);
+        }
+        command
+    }
+
+    /// The command as it would be typed in a shell, for the logs.
+    /// Environment variables are left out, they hold the password.
+    pub(crate) fn display(&self) -> String {
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// The end of the `timeout` of an operation, shared by the commands it
+/// runs one after the other.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Deadline {
+    pub at: tokio::time::Instant,
+    pub timeout: Duration,
+}
+
+impl Deadline {
+    pub(crate) fn after(timeout: Duration) -> Self {
+        Self {
+            at: tokio::time::Instant::now() + timeout,
+            timeout,
+        }
+    }
+}
+
+/// A `pg_dump` or `pg_restore` binary, with the version it reports.
+#[derive(Debug, Clone)]
+pub(crate) struct ClientTool {
+    pub path: String,
+    /// As printed by `--version`, e.g. `15.18 (Debian 15.18-0+deb12u1)`.
+    pub version: String,
+    pub major: u32,
+    /// The container the tool runs in through `docker exec`, if any.
+    pub docker_container: Option<String>,
+}
+
+impl ClientTool {
+    /// Whether the tool is of an older major version than the server.
+    /// `pg_dump` refuses to dump such a server.
+    pub(crate) fn is_older_than(&self, server: &ServerInfo) -> bool {
+        self.major < server.major()
+    }
+
+    /// The command running the tool, through `docker exec` in its container.
+    ///
+    /// The password and session options are handed over to the container
+    /// with `--env PGPASSWORD` and `--env PGOPTIONS`, which copy the
+    /// variables without putting their values on the command line.
+    pub(crate) fn command(&self) -> PgCommand {
+        match &self.docker_container {
+            Some(container) => PgCommand::new("docker")
+                .arg("exec")
+                .arg("--env")
+                .arg("PGPASSWORD")
+                .arg("--env")
+                .arg("PGOPTIONS")
+                .arg("-i")
+                .arg(container)
+                .arg(&self.path),
+            None => PgCommand::new(&self.path),
+        }
+    }
+}
+
+/// `name` as found in one of the directories of `PATH`, or `name` itself
+/// when it is on none of them.
+fn find_on_path(name: &str) -> String {
+    let file_name = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+    std::env::var_os("PATH")
+        .and_then(|paths| {
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(&file_name))
+                .find(|path| path.is_file())
+        })
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// The `name` binary, at `configured_path` if any, and its version.
+///
+/// Fails when it cannot be run, pointing at `config_key`.
+pub(crate) async fn client_tool(
+    name: &str,
+    configured_path: Option<&str>,
+    config_key: &str,
+) -> Result<ClientTool, Box<dyn StdError + Send + Sync>> {
+    probe_client_tool(name, configured_path).await.map_err(|e| {
+        ErrorKind::Subprocess.wrap(format!(
+            "{}. Install the Postgres client tools or set `{}` in the config",
+            e, config_key
+        ))
+    })
+}
+
+/// `client_tool`, failing with the error of running the binary alone.
+pub(crate) async fn probe_client_tool(
+    name: &str,
+    configured_path: Option<&str>,
+) -> Result<ClientTool, Box<dyn StdError + Send + Sync>> {
+    let path = match configured_path {
+        Some(path) => path.to_string(),
+        None => find_on_path(name),
+    };
+    let output = tokio::process::Command::new(&path)
+        .arg("--version")
+        .output()
+        .await
+        .map_err(|e| {
+            ErrorKind::Subprocess.wrap(format!("❌ Cannot run {} ({}): {}", name, path, e))
+        })?;
+    tool_version(path, &output.stdout, None)
+}
+
+/// The `name` binary of the `container` Docker container, and its version.
+///
+/// Fails with the error of `docker` itself when the container cannot be
+/// run in, such as `No such container`.
+pub(crate) async fn docker_client_tool(
+    name: &str,
+    container: &str,
+) -> Result<ClientTool, Box<dyn StdError + Send + Sync>> {
+    let output = match tokio::process::Command::new("docker")
+        .arg("exec")
+        .arg(container)
+        .arg(name)
+        .arg("--version")
+        .output()
+        .await
+    {
+        Ok(output) => output,
+        Err(e) => return Err(ErrorKind::Subprocess.wrap(format!("❌ Cannot run docker: {}", e))),
+    };
+    if !output.status.success() {
+        return Err(ErrorKind::Subprocess.wrap(format!(
+            "❌ Cannot run {} in container {}: {}",
+            name,
+            container,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    tool_version(name.to_string(), &output.stdout, Some(container))
+}
+
+/// The tool at `path` from its `--version` output.
+fn tool_version(
+    path: String,
+    version_output: &[u8],
+    docker_container: Option<&str>,
+) -> Result<ClientTool, Box<dyn StdError + Send + Sync>> {
+    // e.g. "pg_dump (PostgreSQL) 15.18 (Debian 15.18-0+deb12u1)"
+    let stdout = String::from_utf8_lossy(version_output);
+    let version = stdout
+        .trim()
+        .split_once(") ")
+        .map(|(_, version)| version.to_string())
+        .ok_or_else(|| {
+            ErrorKind::Subprocess.wrap(format!(
+                "❌ Unexpected {} --version output: {}",
+                path,
+                stdout.trim()
+            ))
+        })?;
+    let major = version
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|major| major.parse().ok())
+        .ok_or_else(|| {
+            ErrorKind::Subprocess.wrap(format!("❌ Unexpected {} version: {}", path, version))
+        })?;
+    Ok(ClientTool {
+        path,
+        version,
+        major,
+        docker_container: docker_container.map(str::to_string),
+    })
+}
+
+/// Quote an argument for display in a POSIX shell.
+#[cfg(not(windows))]
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Quote an argument for display in PowerShell, where paths hold `\`.
+#[cfg(windows)]
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./\\=:,@%+".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "''"))
+    }
+}
+
+/// A value quoted for a libpq connection string.
+///
+/// The database name is passed as `--dbname=dbname='...'`: a bare name
+/// containing `=` or starting with `postgresql://` would otherwise be read
+/// as a connection string of its own.
+fn conninfo_value(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// `tool` with the connection arguments shared by `pg_dump` and
+/// `pg_restore`.
+///
+/// In a Docker container, the tool connects to the server of the container
+/// through its socket: the host and port of the config are those published
+/// on the host, which may not be reachable from inside the container.
+fn connection_args(tool: &ClientTool, config: &Config) -> PgCommand {
+    let mut command = tool.command();
+    if tool.docker_container.is_none() {
+        command = command
+            .arg(format!("--host={}", config.host))
+            .arg(format!("--port={}", config.port));
+    }
+    command
+        .arg(format!("--username={}", config.user))
+        .arg(format!(
+            "--dbname=dbname={}",
+            conninfo_value(&config.db_name)
+        ))
+}
+
+/// A dump named the way `backup_database` names them, found in a directory.
+#[derive(Debug, Clone)]
+pub(crate) struct BackupFile {
+    pub path: std::path::PathBuf,
+    /// The filename without its timestamp and extension, such as
+    /// `backup_mydb` or `backup_mydb_schema`. Backups of the same series
+    /// only differ by their timestamp.
+    pub series: String,
+    pub timestamp: chrono::NaiveDateTime,
+}
+
+/// Parse a filename such as `backup_mydb_roads_2025-03-25_14-57-49.dump`,
+/// for the backups of `db_name` if given, of any database otherwise.
+fn parse_backup_name(
+    file_name: &str,
+    db_name: Option<&str>,
+) -> Option<(String, chrono::NaiveDateTime)> {
+    let stem = DUMP_EXTENSIONS.iter().find_map(|extension| {
+        file_name
+            .strip_suffix(extension)
+            .and_then(|stem| stem.strip_suffix('.'))
+    })?;
+    // "_2025-03-25_14-57-49"
+    let timestamp_len = "_0000-00-00_00-00-00".len();
+    if stem.len() <= timestamp_len || !stem.is_char_boundary(stem.len() - timestamp_len) {
+        return None;
+    }
+    let (series, timestamp) = stem.split_at(stem.len() - timestamp_len);
+    let timestamp =
+        chrono::NaiveDateTime::parse_from_str(&timestamp[1..], TIMESTAMP_FORMAT).ok()?;
+    let prefix = match db_name {
+        Some(db_name) => format!("backup_{}", db_name),
+        None => "backup_".to_string(),
+    };
+    let rest = series.strip_prefix(&prefix)?;
+    if db_name.is_some() && !rest.is_empty() && !rest.starts_with('_') {
+        // The backup of another database whose name starts with `db_name`
+        return None;
+    }
+    Some((series.to_string(), timestamp))
+}
+
+/// The backups of `db_name`, or of any database, in `dir`, newest first.
+/// Entries not named the way `backup_database` names dumps are left out.
+pub(crate) fn backup_files(
+    dir: &str,
+    db_name: Option<&str>,
+) -> Result<Vec<BackupFile>, std::io::Error> {
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        if let Some((series, timestamp)) = parse_backup_name(file_name, db_name) {
+            backups.push(BackupFile {
+                path: entry.path(),
+                series,
+                timestamp,
+            });
+        }
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.timestamp));
+    Ok(backups)
+}
+
+/// The `filename_template` of the dumps `backup_files` finds, see
+/// `BackupOptions`.
+pub(crate) const DEFAULT_FILENAME_TEMPLATE: &str = "backup_{db}{label}_{date}_{time}";
+
+/// The time a backup is named after, in UTC or local time.
+pub(crate) fn backup_time(utc: bool) -> chrono::NaiveDateTime {
+    if utc {
+        chrono::Utc::now().naive_utc()
+    } else {
+        chrono::Local::now().naive_local()
+    }
+}
+
+/// The name of a dump, without its extension, from `template` and its
+/// tokens: `{db}`, `{label}`, `{date}`, `{time}` and `{format}`.
+///
+/// Fails on other tokens, and on names that are not a plain file name.
+pub(crate) fn backup_file_name(
+    template: &str,
+    db_name: &str,
+    label: &str,
+    time: chrono::NaiveDateTime,
+    format: DumpFormat,
+) -> Result<String, Box<dyn StdError + Send + Sync>> {
+    let name = template
+        .replace("{db}", db_name)
+        .replace("{label}", label)
+        .replace("{date}", &time.format("%Y-%m-%d").to_string())
+        .replace("{time}", &time.format("%H-%M-%S").to_string())
+        .replace("{format}", format.pg_dump_name());
+    if let Some(start) = name.find('{') {
+        let token = name[start..]
+            .split_inclusive('}')
+            .next()
+            .unwrap_or_default();
+        return Err(ErrorKind::Config.wrap(format!(
+            "❌ Unknown token {} in filename_template {}, use {{db}}, {{label}}, {{date}}, {{time}} or {{format}}",
+            token, template
+        )));
+    }
+    if name.is_empty() || name.contains(std::path::is_separator) || name == "." || name == ".." {
+        return Err(ErrorKind::Config.wrap(format!(
+            "❌ filename_template {} does not name a file: {:?}",
+            template, name
+        )));
+    }
+    Ok(name)
+}
+
+/// The symlink to the newest backup of `db_name` in `dir`, and the JSON file
+/// written instead where symlinks cannot be made.
+pub(crate) fn latest_pointer_paths(
+    dir: &str,
+    db_name: &str,
+) -> (std::path::PathBuf, std::path::PathBuf) {
+    let dir = Path::new(dir);
+    (
+        dir.join(format!("latest_{}.dump", db_name)),
+        dir.join(format!("latest_{}.json", db_name)),
+    )
+}
+
+/// Point the `latest_{db_name}` pointer of `dir` at `dump_file`, returning
+/// the pointer.
+///
+/// The pointer is replaced with a rename, so a reader sees the old or the
+/// new one. It names the dump relative to `dir`, which can be moved.
+pub(crate) fn write_latest_pointer(
+    dir: &str,
+    db_name: &str,
+    dump_file: &str,
+) -> Result<String, std::io::Error> {
+    let (link, json) = latest_pointer_paths(dir, db_name);
+    let target = Path::new(dump_file.trim_end_matches(std::path::is_separator))
+        .file_name()
+        .map(std::path::PathBuf::from)
+        .unwrap_or_default();
+    let tmp = Path::new(dir).join(format!(".latest_{}.{}.tmp", db_name, std::process::id()));
+
+    #[cfg(unix)]
+    if std::os::unix::fs::symlink(&target, &tmp).is_ok() {
+        if let Err(e) = std::fs::rename(&tmp, &link) {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(e);
+        }
+        remove_if_exists(&json)?;
+        return Ok(link.display().to_string());
+    }
+
+    let pointer = serde_json::json!({
+        "path": target.display().to_string(),
+        "updated_at": chrono::Local::now().to_rfc3339(),
+    });
+    std::fs::write(&tmp, format!("{:#}\n", pointer))?;
+    if let Err(e) = std::fs::rename(&tmp, &json) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    remove_if_exists(&link)?;
+    Ok(json.display().to_string())
+}
+
+/// Remove the file at `path`, if there is one.
+fn remove_if_exists(path: &Path) -> Result<(), std::io::Error> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The dump the `latest_{db_name}` pointer of `dir` names, if there is a
+/// pointer and the dump still exists.
+pub(crate) fn read_latest_pointer(
+    dir: &str,
+    db_name: &str,
+) -> Result<Option<String>, Box<dyn StdError + Send + Sync>> {
+    let (link, json) = latest_pointer_paths(dir, db_name);
+    let target = if std::fs::symlink_metadata(&link).is_ok() {
+        std::fs::read_link(&link)?
+    } else if json.is_file() {
+        let pointer: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json)?)?;
+        match pointer["path"].as_str() {
+            Some(path) => std::path::PathBuf::from(path),
+            None => {
+                return Err(
+                    ErrorKind::InvalidInput.wrap(format!("❌ {} has no path", json.display()))
+                )
+            }
+        }
+    } else {
+        return Ok(None);
+    };
+    let dump = Path::new(dir).join(target);
+    Ok(dump.exists().then(|| dump.display().to_string()))
+}
+
+/// Path of the file holding the SHA-256 of a dump.
+pub(crate) fn checksum_path(dump_file: &str) -> String {
+    format!(
+        "{}.sha256",
+        dump_file.trim_end_matches(std::path::is_separator)
+    )
+}
+
+/// Hex SHA-256 of a dump. For a directory dump, the digest of the name and
+/// digest of each of its files, in name order.
+pub(crate) fn dump_checksum(path: &Path) -> Result<String, Box<dyn StdError + Send + Sync>> {
+    if !path.is_dir() {
+        return manifest::hash_file(&path.to_string_lossy());
+    }
+    let mut files: Vec<_> = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    files.sort();
+    let mut hasher = Sha256::new();
+    for file in files {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        hasher.update(format!("{}  {}\n", dump_checksum(&file)?, name).as_bytes());
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Write the SHA-256 of a dump to its checksum file, in the format of
+/// `sha256sum`.
+pub(crate) fn write_checksum(dump_file: &str, sha256: &str) -> Result<String, std::io::Error> {
+    let path = checksum_path(dump_file);
+    let name = Path::new(dump_file.trim_end_matches(std::path::is_separator))
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    std::fs::write(&path, format!("{}  {}\n", sha256, name))?;
+    Ok(path)
+}
+
+/// The SHA-256 stored in the checksum file of a dump, if it has one.
+pub(crate) fn read_checksum(dump_file: &str) -> Result<Option<String>, std::io::Error> {
+    match std::fs::read_to_string(checksum_path(dump_file)) {
+        Ok(content) => Ok(content.split_whitespace().next().map(str::to_string)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Size of a dump, whether a file or a directory.
+pub(crate) fn dump_size(path: &Path) -> Result<u64, std::io::Error> {
+    if !path.is_dir() {
+        return Ok(std::fs::metadata(path)?.len());
+    }
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        size += dump_size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+/// The `BackupInfo` of the dump `backup_database` just wrote of `db_name`
+/// at `dump_path`, timestamped with when it was last written.
+pub(crate) fn written_backup_info(
+    dump_path: &str,
+    db_name: &str,
+) -> Result<BackupInfo, std::io::Error> {
+    let path = Path::new(dump_path);
+    let written: chrono::DateTime<chrono::Local> = std::fs::metadata(path)?.modified()?.into();
+    Ok(BackupInfo {
+        path: dump_path.to_string(),
+        db_name: db_name.to_string(),
+        timestamp: written.format("%Y-%m-%d %H:%M:%S").to_string(),
+        size_bytes: dump_size(path)?,
+        format: dump_format_of(dump_path)?,
+        valid: true,
+    })
+}
+
+/// Delete what a killed command wrote of a dump, file or directory, if
+/// anything.
+pub(crate) fn remove_partial(path: &str) -> Result<(), std::io::Error> {
+    let path = Path::new(path);
+    let removed = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+    match removed {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Delete a dump, whether a file or a directory, with the files kept next
+/// to it, such as its logs.
+pub(crate) fn remove_backup(backup: &BackupFile) -> Result<(), std::io::Error> {
+    if backup.path.is_dir() {
+        std::fs::remove_dir_all(&backup.path)?;
+    } else {
+        std::fs::remove_file(&backup.path)?;
+    }
+    let Some(file_name) = backup.path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(());
+    };
+    let sidecar_prefix = format!("{}.", file_name);
+    if let Some(dir) = backup.path.parent() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let is_sidecar = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with(&sidecar_prefix));
+            if is_sidecar && entry.path().is_file() {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A `pg_dump --table` pattern matching exactly `table_name`: quoted, so
+/// that `*`, `?`, `.` and upper case letters are taken as they are.
+fn table_pattern(table_name: &str) -> String {
+    format!("\"{}\"", table_name.replace('"', "\"\""))
+}
+
+/// `name` as an exclusion of `pg_dump`: a pattern as `pg_dump` reads them if
+/// it has a `*` or `?`, its exact name otherwise.
+fn exclusion_pattern(name: &str) -> String {
+    if name.contains(['*', '?']) {
+        name.to_string()
+    } else {
+        table_pattern(name)
+    }
+}
+
+/// Add the exclusions of the `[backup]` config section to those of
+/// `options`, the PostGIS tables unless `exclude_tables` is set.
+pub(crate) fn merge_exclusions(options: &mut BackupOptions, backup_config: &BackupConfig) {
+    let mut exclude_tables = options
+        .exclude_tables
+        .clone()
+        .unwrap_or_else(|| POSTGIS_TABLES.iter().map(|t| t.to_string()).collect());
+    for (merged, configured) in [
+        (&mut exclude_tables, &backup_config.exclude_tables),
+        (&mut options.exclude_schemas, &backup_config.exclude_schemas),
+    ] {
+        for name in configured {
+            if !merged.contains(name) {
+                merged.push(name.clone());
+            }
+        }
+    }
+    options.exclude_tables = Some(exclude_tables);
+}
+
+/// The parts of the `pg_dump` pattern `pattern` between its unquoted dots,
+/// as anchored regular expressions: unquoted letters are folded to lower
+/// case, `*` and `?` match any characters and any one, and the rest matches
+/// as it is.
+fn pattern_regexes(pattern: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().expect("parts start with one");
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                part.push('"');
+            }
+            '"' => quoted = !quoted,
+            '*' if !quoted => part.push_str(".*"),
+            '?' if !quoted => part.push('.'),
+            '.' if !quoted => parts.push(String::new()),
+            c => {
+                let c = if quoted { c } else { c.to_ascii_lowercase() };
+                if "\\^$.|?*+()[]{}".contains(c) {
+                    part.push('\\');
+                }
+                part.push(c);
+            }
+        }
+    }
+    parts
+        .into_iter()
+        .map(|part| format!("^({})$", part))
+        .collect()
+}
+
+/// What each of the exclusions of `options` matches in the database of
+/// `client`, as `pg_dump` matches them: a table name without its schema only
+/// matches the tables on the search path.
+pub(crate) async fn exclusion_matches(
+    client: &tokio_postgres::Client,
+    options: &BackupOptions,
+) -> Result<Vec<ExclusionMatch>, Box<dyn StdError + Send + Sync>> {
+    let mut matches = Vec::new();
+    for table in options.exclude_tables.iter().flatten() {
+        let regexes = pattern_regexes(&exclusion_pattern(table));
+        let (schema_regex, table_regex) = match regexes.as_slice() {
+            [.., schema, table] => (Some(schema), table),
+            [table] => (None, table),
+            [] => unreachable!("a pattern has at least one part"),
+        };
+        let rows = client
+            .query(
+                "SELECT n.nspname, c.relname FROM pg_class c
+                 JOIN pg_namespace n ON n.oid = c.relnamespace
+                 WHERE c.relkind IN ('r', 'p', 'S', 'v', 'm', 'f')
+                   AND c.relname ~ $1
+                   AND CASE WHEN $2::text IS NULL THEN pg_table_is_visible(c.oid)
+                            ELSE n.nspname ~ $2 END
+                 ORDER BY 1, 2",
+                &[table_regex, &schema_regex],
+            )
+            .await?;
+        matches.push(ExclusionMatch {
+            pattern: table.clone(),
+            schema: false,
+            default: POSTGIS_TABLES.contains(&table.as_str()),
+            matched: rows
+                .iter()
+                .map(|row| format!("{}.{}", row.get::<_, String>(0), row.get::<_, String>(1)))
+                .collect(),
+        });
+    }
+    for schema in &options.exclude_schemas {
+        let regexes = pattern_regexes(&exclusion_pattern(schema));
+        let rows = client
+            .query(
+                "SELECT nspname FROM pg_namespace WHERE nspname ~ $1 ORDER BY 1",
+                &[regexes.last().expect("a pattern has at least one part")],
+            )
+            .await?;
+        matches.push(ExclusionMatch {
+            pattern: schema.clone(),
+            schema: true,
+            default: false,
+            matched: rows.iter().map(|row| row.get(0)).collect(),
+        });
+    }
+    Ok(matches)
+}
+
+/// Lines reporting `matches`, with a warning for each configured exclusion
+/// matching nothing, likely a typo.
+pub(crate) fn exclusion_report(matches: &[ExclusionMatch]) -> Vec<String> {
+    matches
+        .iter()
+        .filter(|exclusion| !(exclusion.default && exclusion.matched.is_empty()))
+        .map(|exclusion| {
+            let kind = if exclusion.schema { "schema" } else { "table" };
+            if exclusion.matched.is_empty() {
+                format!(
+                    "⚠️ Excluded {} {} matches nothing, check its spelling",
+                    kind, exclusion.pattern
+                )
+            } else {
+                format!(
+                    "🚫 Excluded {} {}: {}",
+                    kind,
+                    exclusion.pattern,
+                    exclusion.matched.join(", ")
+                )
+            }
+        })
+        .collect()
+}
+
+/// Log a line of `exclusion_report`, its warnings as warnings.
+pub(crate) fn log_exclusion(line: &str) {
+    if line.starts_with('⚠') {
+        warn!("{}", line);
+    } else {
+        info!("{}", line);
+    }
+}
+
+/// Part of the backup filename naming the tables of a partial backup: their
+/// names when there are a few of them, their number otherwise.
+pub(crate) fn tables_label(tables: &[String]) -> String {
+    if tables.len() > NAMED_TABLES_MAX {
+        return format!("{}tables", tables.len());
+    }
+    tables
+        .iter()
+        .map(|table| {
+            table
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || c == '-' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// `pg_dump` of the configured database into `output_file`, a directory
+/// for `DumpFormat::Directory` and a file otherwise, or to stdout without
+/// `output_file`.
+///
+/// `--jobs` is only passed for the directory format, the only one `pg_dump`
+/// accepts it with.
+pub(crate) fn pg_dump_command(
+    pg_dump: &ClientTool,
+    config: &Config,
+    output_file: Option<&str>,
+    options: &BackupOptions,
+) -> PgCommand {
+    let format = options.effective_format();
+    let mut command = connection_args(pg_dump, config);
+    if format == DumpFormat::Directory {
+        command = command.arg(format!("--jobs={}", options.jobs));
+    }
+    command = command
+        .arg(format!("--format={}", format.pg_dump_name()))
+        .arg("--no-privileges")
+        .arg("--no-owner");
+    // Plain dumps are gzipped while written, see `run_logged_to`
+    if let Some(compress) = options
+        .compression
+        .filter(|_| format != DumpFormat::Plain)
+        .and_then(|spec| compress_arg(spec, pg_dump))
+    {
+        command = command.arg(compress);
+    }
+    if let Some(flag) = options.scope.pg_dump_flag() {
+        command = command.arg(flag);
+    }
+    for table in options.tables.iter().flatten() {
+        command = command.arg(format!("--table={}", table_pattern(table)));
+    }
+    match &options.exclude_tables {
+        Some(exclude_tables) => {
+            for table in exclude_tables {
+                command = command.arg(format!("--exclude-table={}", exclusion_pattern(table)));
+            }
+        }
+        None => {
+            for table in POSTGIS_TABLES {
+                command = command.arg(format!("--exclude-table={}", table_pattern(table)));
+            }
+        }
+    }
+    for schema in &options.exclude_schemas {
+        command = command.arg(format!("--exclude-schema={}", exclusion_pattern(schema)));
+    }
+    if let Some(output_file) = output_file {
+        command = command.arg(format!("--file={}", output_file));
+    }
+    command.env("PGPASSWORD", &config.password)
+}
+
+/// First bytes of a `pg_dump` archive in custom format.
+const CUSTOM_DUMP_MAGIC: &[u8] = b"PGDMP";
+
+/// Format of the dump at `dump_path`: a directory dump holds a `toc.dat`,
+/// a custom dump starts with `PGDMP`, anything else is taken for plain SQL.
+pub(crate) fn dump_format_of(dump_path: &str) -> Result<DumpFormat, std::io::Error> {
+    let path = Path::new(dump_path);
+    if path.is_dir() {
+        return if path.join("toc.dat").is_file() {
+            Ok(DumpFormat::Directory)
+        } else {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is a directory but not a pg_dump directory dump",
+                    dump_path
+                ),
+            ))
+        };
+    }
+    let mut magic = [0u8; CUSTOM_DUMP_MAGIC.len()];
+    let mut file = std::fs::File::open(path)?;
+    let read = file.read(&mut magic)?;
+    if read == magic.len() && magic == CUSTOM_DUMP_MAGIC {
+        Ok(DumpFormat::Custom)
+    } else {
+        Ok(DumpFormat::Plain)
+    }
+}
+
+/// TOC entry descriptions made of several words, longest first, so an entry
+/// line can be split between its description and its tag.
+const MULTI_WORD_DESCS: [&str; 10] = [
+    "MATERIALIZED VIEW DATA",
+    "SEQUENCE OWNED BY",
+    "LARGE OBJECT",
+    "MATERIALIZED VIEW",
+    "FK CONSTRAINT",
+    "SEQUENCE SET",
+    "TABLE DATA",
+    "DEFAULT ACL",
+    "BLOB DATA",
+    "BLOBS",
+];
+
+/// An entry of the table of contents of a dump, as listed by `pg_restore --list`.
+#[derive(Debug, Clone)]
+pub(crate) struct TocEntry {
+    pub dump_id: i32,
+    /// Kind of object, such as `TABLE`, `TABLE DATA` or `INDEX`.
+    pub desc: String,
+    /// `-` for objects outside of any schema.
+    pub schema: String,
+    /// The name of the object, prefixed with its table for constraints,
+    /// triggers and the like.
+    pub tag: String,
+}
+
+/// Parse a `pg_restore --list` line such as
+/// `3673; 0 21954 TABLE DATA public ta postgres`.
+fn parse_toc_line(line: &str) -> Option<TocEntry> {
+    let (dump_id, rest) = line.split_once(';')?;
+    let dump_id = dump_id.trim().parse().ok()?;
+    // Skip the catalog table oid and the object oid
+    let mut fields = rest.trim_start().splitn(3, ' ');
+    fields.next()?;
+    fields.next()?;
+    let rest = fields.next()?;
+    let desc = MULTI_WORD_DESCS
+        .iter()
+        .find(|desc| rest.starts_with(*desc) && rest[desc.len()..].starts_with(' '))
+        .map(|desc| desc.to_string())
+        .or_else(|| rest.split(' ').next().map(str::to_string))?;
+    let rest = rest[desc.len()..].trim();
+    let (schema, rest) = rest.split_once(' ')?;
+    // The owner comes last, empty for objects without one
+    let tag = match rest.rsplit_once(' ') {
+        Some((tag, _owner)) => tag,
+        None => rest,
+    };
+    Some(TocEntry {
+        dump_id,
+        desc,
+        schema: schema.to_string(),
+        tag: tag.to_string(),
+    })
+}
+
+/// The output of `pg_restore --list` for a custom or directory dump, failing
+/// when `pg_restore` cannot read the dump.
+///
+/// Read with the local `pg_restore`, which does not connect to any database
+/// to list a dump.
+pub(crate) async fn dump_listing(
+    pg_restore: &ClientTool,
+    dump_file: &str,
+) -> Result<String, Box<dyn StdError + Send + Sync>> {
+    let output = pg_restore
+        .command()
+        .arg("--list")
+        .arg(dump_file)
+        .command()
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(ErrorKind::Subprocess.wrap(format!(
+            "❌ Failed to list {}: {}",
+            dump_file,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The database a dump was made of, from the `;     dbname: mydb` line of
+/// its listing.
+pub(crate) fn listed_db_name(listing: &str) -> Option<String> {
+    listing
+        .lines()
+        .take_while(|line| line.starts_with(';'))
+        .find_map(|line| line.trim_start_matches(';').trim().strip_prefix("dbname: "))
+        .map(str::to_string)
+}
+
+/// The table of contents of a custom or directory dump.
+pub(crate) async fn dump_toc(
+    pg_restore: &ClientTool,
+    dump_file: &str,
+) -> Result<Vec<TocEntry>, Box<dyn StdError + Send + Sync>> {
+    Ok(dump_listing(pg_restore, dump_file)
+        .await?
+        .lines()
+        .filter(|line| !line.starts_with(';') && !line.trim().is_empty())
+        .filter_map(parse_toc_line)
+        .collect())
+}
+
+/// The identifiers of a possibly qualified name at the start of `text`, such
+/// as `public."T b".id`, unquoted.
+fn qualified_name_parts(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut chars = text.trim_start().chars().peekable();
+    loop {
+        let mut part = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                if c == '"' {
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                part.push(c);
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || ".(;,".contains(c) {
+                    break;
+                }
+                part.push(c);
+                chars.next();
+            }
+        }
+        if part.is_empty() {
+            break;
+        }
+        parts.push(part);
+        if chars.peek() != Some(&'.') {
+            break;
+        }
+        chars.next();
+    }
+    parts
+}
+
+/// The table named after the first occurrence of `keyword` in `sql`,
+/// skipping `ONLY`.
+fn table_after(sql: &str, keyword: &str) -> Option<String> {
+    let (_, rest) = sql.split_once(keyword)?;
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix("ONLY ").unwrap_or(rest);
+    let parts = qualified_name_parts(rest);
+    // `schema.table`, or `schema.table.column` after OWNED BY
+    match parts.len() {
+        1 => parts.into_iter().next(),
+        _ => parts.into_iter().nth(1),
+    }
+}
+
+/// The SQL of the schema of a custom or directory dump, which `pg_restore`
+/// writes without connecting.
+async fn dump_schema_sql(
+    pg_restore: &ClientTool,
+    dump_file: &str,
+) -> Result<String, Box<dyn StdError + Send + Sync>> {
+    let output = pg_restore
+        .command()
+        .arg("--schema-only")
+        .arg("--file=-")
+        .arg(dump_file)
+        .command()
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(ErrorKind::Subprocess.wrap(format!(
+            "❌ Failed to read the schema of {}: {}",
+            dump_file,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Extensions providing the types a dump may use without creating the
+/// extension, as a dump of selected tables does.
+const EXTENSION_TYPES: [(&str, &str); 8] = [
+    ("geometry", "postgis"),
+    ("geography", "postgis"),
+    ("box2d", "postgis"),
+    ("box3d", "postgis"),
+    ("raster", "postgis_raster"),
+    ("topogeometry", "postgis_topology"),
+    ("hstore", "hstore"),
+    ("citext", "citext"),
+];
+
+/// An extension the schema of a dump needs, in `schema` if the dump
+/// creates it there.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DumpExtension {
+    pub name: String,
+    pub schema: Option<String>,
+}
+
+/// Whether `sql` uses the type `type_name`, qualified as `pg_dump` writes
+/// it or not.
+fn uses_type(sql: &str, type_name: &str) -> bool {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    sql.match_indices(type_name).any(|(idx, _)| {
+        let before = sql[..idx].chars().next_back();
+        let after = sql[idx + type_name.len()..].chars().next();
+        before.is_some_and(|c| c == '.' || c == ' ' || c == '(') && !after.is_some_and(is_ident)
+    })
+}
+
+/// The extensions `sql`, the schema of a dump, creates, then those of
+/// `EXTENSION_TYPES` whose types it uses, `plpgsql` aside.
+pub(crate) fn schema_extensions(sql: &str) -> Vec<DumpExtension> {
+    let mut extensions: Vec<DumpExtension> = Vec::new();
+    for line in sql.lines() {
+        // e.g. `CREATE EXTENSION IF NOT EXISTS postgis WITH SCHEMA public;`
+        let Some(rest) = line.strip_prefix("CREATE EXTENSION IF NOT EXISTS ") else {
+            continue;
+        };
+        let Some(name) = qualified_name_parts(rest).into_iter().next() else {
+            continue;
+        };
+        let schema = rest
+            .split_once(" WITH SCHEMA ")
+            .and_then(|(_, schema)| qualified_name_parts(schema).into_iter().next());
+        if name != "plpgsql" && !extensions.iter().any(|extension| extension.name == name) {
+            extensions.push(DumpExtension { name, schema });
+        }
+    }
+    for (type_name, extension) in EXTENSION_TYPES {
+        if !extensions.iter().any(|known| known.name == extension) && uses_type(sql, type_name) {
+            extensions.push(DumpExtension {
+                name: extension.to_string(),
+                schema: None,
+            });
+        }
+    }
+    extensions
+}
+
+/// The extensions the schema of a dump needs, see `schema_extensions`.
+pub(crate) async fn dump_extensions(
+    pg_restore: &ClientTool,
+    dump_file: &str,
+) -> Result<Vec<DumpExtension>, Box<dyn StdError + Send + Sync>> {
+    Ok(schema_extensions(
+        &dump_schema_sql(pg_restore, dump_file).await?,
+    ))
+}
+
+/// Create the `extensions` the database of `client` does not have yet, with
+/// the extensions they require, returning the names of those created.
+///
+/// The schema of an extension is created first when missing, as the
+/// schema phase would only create it after the types it holds are needed.
+pub(crate) async fn create_extensions(
+    client: &tokio_postgres::Client,
+    extensions: &[DumpExtension],
+) -> Result<Vec<String>, Box<dyn StdError + Send + Sync>> {
+    let mut created = Vec::new();
+    for extension in extensions {
+        let exists: bool = client
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = $1)",
+                &[&extension.name],
+            )
+            .await?
+            .get(0);
+        if exists {
+            continue;
+        }
+        let mut statement = format!(
+            "CREATE EXTENSION IF NOT EXISTS {}",
+            sql::ident(&extension.name)?
+        );
+        if let Some(schema) = &extension.schema {
+            statement = format!(
+                "CREATE SCHEMA IF NOT EXISTS {schema}; {statement} WITH SCHEMA {schema}",
+                schema = sql::ident(schema)?
+            );
+        }
+        if let Err(e) = client
+            .batch_execute(&format!("{} CASCADE", statement))
+            .await
+        {
+            return Err(format!(
+                "❌ Failed to create extension {}: {}. It must be installed on the server, and created by a superuser or the database owner",
+                extension.name, e
+            )
+            .into());
+        }
+        created.push(extension.name.clone());
+    }
+    Ok(created)
+}
+
+/// Path of the file holding the versions of the extensions of the database
+/// a dump was made of.
+pub(crate) fn extensions_path(dump_file: &str) -> String {
+    format!(
+        "{}.extensions",
+        dump_file.trim_end_matches(std::path::is_separator)
+    )
+}
+
+/// The extensions of the database of `client` and their versions.
+pub(crate) async fn extension_versions(
+    client: &tokio_postgres::Client,
+) -> Result<Vec<(String, String)>, tokio_postgres::Error> {
+    Ok(client
+        .query(
+            "SELECT extname::text, extversion FROM pg_extension ORDER BY extname",
+            &[],
+        )
+        .await?
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect())
+}
+
+/// Write the extension versions of a dump to its extensions file, one
+/// `name version` per line.
+pub(crate) fn write_extension_versions(
+    dump_file: &str,
+    versions: &[(String, String)],
+) -> Result<String, std::io::Error> {
+    let path = extensions_path(dump_file);
+    let content: String = versions
+        .iter()
+        .map(|(name, version)| format!("{} {}\n", name, version))
+        .collect();
+    std::fs::write(&path, content)?;
+    Ok(path)
+}
+
+/// The extension versions stored next to a dump, if it has them.
+pub(crate) fn read_extension_versions(
+    dump_file: &str,
+) -> Result<Option<Vec<(String, String)>>, std::io::Error> {
+    match std::fs::read_to_string(extensions_path(dump_file)) {
+        Ok(content) => Ok(Some(
+            content
+                .lines()
+                .filter_map(|line| line.split_once(' '))
+                .map(|(name, version)| (name.to_string(), version.to_string()))
+                .collect(),
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The table each object of the schema of a dump belongs to, keyed by
+/// description, schema and tag as in its table of contents.
+///
+/// `pg_restore --list` does not tell which table an index or a sequence
+/// belongs to, so this reads it from the SQL of the schema, which
+/// `pg_restore` writes without connecting.
+async fn object_tables(
+    pg_restore: &ClientTool,
+    dump_file: &str,
+) -> Result<
+    std::collections::HashMap<(String, String, String), String>,
+    Box<dyn StdError + Send + Sync>,
+> {
+    let sql = dump_schema_sql(pg_restore, dump_file).await?;
+
+    let mut tables = std::collections::HashMap::new();
+    // Each object is introduced by `-- Name: ta_v; Type: INDEX; Schema: public; Owner: postgres`
+    for block in sql.split("\n-- Name: ").skip(1) {
+        let (header, body) = block.split_once('\n').unwrap_or((block, ""));
+        let mut fields = header.split("; ");
+        let (Some(name), Some(desc), Some(schema)) = (
+            fields.next(),
+            fields.next().and_then(|f| f.strip_prefix("Type: ")),
+            fields.next().and_then(|f| f.strip_prefix("Schema: ")),
+        ) else {
+            continue;
+        };
+        let table = match desc {
+            "TABLE" => Some(name.to_string()),
+            "SEQUENCE OWNED BY" => table_after(body, "OWNED BY "),
+            // Identity sequences are created along with their column
+            "SEQUENCE" => body
+                .contains("ADD GENERATED")
+                .then(|| table_after(body, "ALTER TABLE "))
+                .flatten(),
+            "INDEX" | "TRIGGER" | "POLICY" | "RULE" => table_after(body, " ON "),
+            _ => table_after(body, "ALTER TABLE "),
+        };
+        if let Some(table) = table {
+            tables.insert(
+                (desc.to_string(), schema.to_string(), name.to_string()),
+                table,
+            );
+        }
+    }
+    // A serial sequence is created apart from its OWNED BY, which names the table
+    let owned_sequences: Vec<_> = tables
+        .iter()
+        .filter(|((desc, _, _), _)| desc == "SEQUENCE OWNED BY")
+        .map(|((_, schema, name), table)| {
+            (
+                ("SEQUENCE".to_string(), schema.clone(), name.clone()),
+                table.clone(),
+            )
+        })
+        .collect();
+    tables.extend(owned_sequences);
+    Ok(tables)
+}
+
+/// Write the entries of the table of contents of `dump_file` belonging to
+/// `tables` to `list_path`, for `pg_restore --use-list`: the tables with
+/// their data, sequences, defaults, constraints, indexes and triggers.
+/// Foreign keys of other tables pointing at them are left out.
+///
+/// Returns the dump ids of the entries written.
+pub(crate) async fn write_table_list(
+    pg_restore: &ClientTool,
+    dump_file: &str,
+    tables: &[String],
+    list_path: &str,
+) -> Result<Vec<i32>, Box<dyn StdError + Send + Sync>> {
+    let listing = dump_listing(pg_restore, dump_file).await?;
+    let object_tables = object_tables(pg_restore, dump_file).await?;
+    // Sequence values are data, listed apart from the sequences themselves
+    let sequence_tables: std::collections::HashMap<(String, String), String> = object_tables
+        .iter()
+        .filter(|((desc, _, _), _)| desc.starts_with("SEQUENCE"))
+        .map(|((_, schema, name), table)| ((schema.clone(), name.clone()), table.clone()))
+        .collect();
+
+    let mut list = String::new();
+    let mut entries = Vec::new();
+    for line in listing.lines() {
+        let Some(entry) = parse_toc_line(line) else {
+            continue;
+        };
+        let table = match entry.desc.as_str() {
+            "TABLE" | "TABLE DATA" => Some(entry.tag.clone()),
+            "SEQUENCE SET" => sequence_tables
+                .get(&(entry.schema.clone(), entry.tag.clone()))
+                .cloned(),
+            _ => object_tables
+                .get(&(entry.desc.clone(), entry.schema.clone(), entry.tag.clone()))
+                .cloned(),
+        };
+        if table.is_some_and(|table| tables.contains(&table)) {
+            list.push_str(line);
+            list.push('\n');
+            entries.push(entry.dump_id);
+        }
+    }
+    std::fs::write(list_path, list)?;
+    Ok(entries)
+}
+
+/// A file removed when dropped, for the temporary files of a restore.
+pub(crate) struct TempFile(pub String);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A file or directory copied into a Docker container, removed from it
+/// when dropped.
+pub(crate) struct DockerFile {
+    pub container: String,
+    /// The path of the copy inside the container.
+    pub path: String,
+}
+
+impl DockerFile {
+    /// A path in `/tmp` of `container` named after `host_path`, for a copy
+    /// of it. Another for each call, so that operations copying the same
+    /// file at the same time never remove each other's copy.
+    pub(crate) fn new(container: &str, host_path: &str) -> Self {
+        let file_name = Path::new(host_path.trim_end_matches(std::path::is_separator))
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "dump".to_string());
+        Self {
+            container: container.to_string(),
+            path: format!("/tmp/{}_{}", sql::unique_name("pgc"), file_name),
+        }
+    }
+
+    /// Copy `host_path` into `/tmp` of `container` with `docker cp`.
+    pub(crate) async fn copy_in(
+        container: &str,
+        host_path: &str,
+    ) -> Result<Self, Box<dyn StdError + Send + Sync>> {
+        let docker_file = Self::new(container, host_path);
+        docker_cp(
+            host_path,
+            &format!("{}:{}", container, docker_file.path),
+            container,
+        )
+        .await?;
+        Ok(docker_file)
+    }
+
+    /// Copy the file out of the container to `host_path` with `docker cp`.
+    pub(crate) async fn copy_out(
+        &self,
+        host_path: &str,
+    ) -> Result<(), Box<dyn StdError + Send + Sync>> {
+        docker_cp(
+            &format!("{}:{}", self.container, self.path),
+            host_path,
+            &self.container,
+        )
+        .await
+    }
+}
+
+/// `docker cp` from `source` to `destination`, one of them in `container`.
+async fn docker_cp(
+    source: &str,
+    destination: &str,
+    container: &str,
+) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let output = tokio::process::Command::new("docker")
+        .arg("cp")
+        .arg(source)
+        .arg(destination)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(ErrorKind::Subprocess.wrap(format!(
+            "❌ Failed to copy {} to {} with container {}: {}",
+            source,
+            destination,
+            container,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+impl Drop for DockerFile {
+    fn drop(&mut self) {
+        let removed = std::process::Command::new("docker")
+            .arg("exec")
+            .arg(&self.container)
+            .arg("rm")
+            .arg("-rf")
+            .arg(&self.path)
+            .output()
+            .is_ok_and(|output| output.status.success());
+        if !removed {
+            warn!(
+                "⚠️ Failed to remove {} from container {}",
+                self.path, self.container
+            );
+        }
+    }
+}
+
+/// First line of a plain SQL dump.
+const PLAIN_DUMP_HEADER: &str = "--\n-- PostgreSQL database dump";
+
+/// Whether `path` starts the way `pg_dump` starts plain SQL dumps, once
+/// gunzipped if it is gzipped.
+pub(crate) fn is_plain_dump(path: &Path) -> bool {
+    let mut header = [0u8; PLAIN_DUMP_HEADER.len()];
+    let read_header = |header: &mut [u8]| -> std::io::Result<()> {
+        let mut magic = [0u8; GZIP_MAGIC.len()];
+        std::fs::File::open(path)?.read_exact(&mut magic)?;
+        let file = std::fs::File::open(path)?;
+        if magic == GZIP_MAGIC {
+            flate2::read::GzDecoder::new(file).read_exact(header)
+        } else {
+            std::io::BufReader::new(file).read_exact(header)
+        }
+    };
+    read_header(&mut header).is_ok_and(|_| header == PLAIN_DUMP_HEADER.as_bytes())
+}
+
+/// `spec` if `pg_dump` supports it. `pg_dump` before 16 only compresses
+/// with gzip, as does `backup_database` for plain dumps: other methods
+/// fall back to gzip, with a warning.
+pub(crate) fn supported_compression(
+    spec: CompressionSpec,
+    format: DumpFormat,
+    pg_dump: &ClientTool,
+) -> CompressionSpec {
+    let gzip_only = format == DumpFormat::Plain || pg_dump.major < COMPRESSION_METHODS_MAJOR;
+    if !gzip_only
+        || matches!(
+            spec.method,
+            CompressionMethod::Gzip | CompressionMethod::None
+        )
+    {
+        return spec;
+    }
+    // gzip levels go up to 9
+    let level = spec.level.map(|level| level.min(9));
+    if format == DumpFormat::Plain {
+        warn!(
+            "⚠️ Plain dumps are only compressed with gzip, using gzip instead of {}",
+            spec.method.pg_dump_name()
+        );
+    } else {
+        warn!(
+            "⚠️ pg_dump {} cannot compress with {}, it needs pg_dump {} or newer. Using gzip instead",
+            pg_dump.version,
+            spec.method.pg_dump_name(),
+            COMPRESSION_METHODS_MAJOR
+        );
+    }
+    CompressionSpec {
+        method: CompressionMethod::Gzip,
+        level,
+    }
+}
+
+/// The `--compress` argument of `pg_dump` for `spec`, if one is needed.
+fn compress_arg(spec: CompressionSpec, pg_dump: &ClientTool) -> Option<String> {
+    if pg_dump.major >= COMPRESSION_METHODS_MAJOR {
+        let method = spec.method.pg_dump_name();
+        return Some(match spec.level {
+            Some(level) => format!("--compress={}:{}", method, level),
+            None => format!("--compress={}", method),
+        });
+    }
+    // Older versions only take a gzip level, 0 for none
+    match (spec.method, spec.level) {
+        (CompressionMethod::None, _) => Some("--compress=0".to_string()),
+        (_, Some(level)) => Some(format!("--compress={}", level.min(9))),
+        (_, None) => None,
+    }
+}
+
+/// The two passes of a restore: the schema first, then the data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RestorePhase {
+    Schema,
+    Data,
+}
+
+/// Kinds of entries of a table of contents restored with the data.
+const DATA_DESCS: [&str; 3] = ["TABLE DATA", "SEQUENCE SET", "BLOBS"];
+
+impl RestorePhase {
+    /// Whether the phase restores `entry`.
+    pub(crate) fn restores(&self, entry: &TocEntry) -> bool {
+        DATA_DESCS.contains(&entry.desc.as_str()) == (*self == RestorePhase::Data)
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            RestorePhase::Schema => "schema",
+            RestorePhase::Data => "data",
+        }
+    }
+}
+
+/// `pg_restore` of one phase of `dump_file` into the database of `config`.
+///
+/// With `use_list`, only the entries of this list file are restored, see
+/// `write_table_list`.
+///
+/// With a `pg_restore` of a Docker container, `dump_file` and `use_list`
+/// are paths inside the container, see `DockerFile`.
+pub(crate) fn pg_restore_command(
+    pg_restore: &ClientTool,
+    config: &Config,
+    dump_file: &str,
+    options: &RestoreOptions,
+    phase: RestorePhase,
+    use_list: Option<&str>,
+) -> PgCommand {
+    let mut command = connection_args(pg_restore, config)
+        .arg(format!("--jobs={}", options.jobs))
+        .arg("--verbose");
+    command = match phase {
+        RestorePhase::Schema => command
+            .arg("--schema-only")
+            .arg("--clean")
+            .arg("--if-exists"),
+        RestorePhase::Data => command.arg("--data-only").arg("--disable-triggers"),
+    };
+    if let Some(use_list) = use_list {
+        command = command.arg(format!("--use-list={}", use_list));
+    }
+    command
+        .arg("--no-acl")
+        .arg("--no-comments")
+        .arg(dump_file)
+        .env("PGPASSWORD", &config.password)
+}
+
+/// `pg_dumpall` to go with `pg_dump`: the one of its container, or the one
+/// in the directory of `pg_dump_path` when that is configured.
+pub(crate) async fn pg_dumpall_tool(
+    pg_dump: &ClientTool,
+    config: &Config,
+) -> Result<ClientTool, Box<dyn StdError + Send + Sync>> {
+    if let Some(container) = &pg_dump.docker_container {
+        return docker_client_tool("pg_dumpall", container).await;
+    }
+    let sibling = config.pg_dump_path.as_ref().map(|pg_dump_path| {
+        Path::new(pg_dump_path)
+            .with_file_name(format!("pg_dumpall{}", std::env::consts::EXE_SUFFIX))
+            .display()
+            .to_string()
+    });
+    client_tool("pg_dumpall", sibling.as_deref(), "pg_dump_path").await
+}
+
+/// `pg_dumpall --globals-only`, writing the roles and tablespaces of the
+/// server to stdout.
+pub(crate) fn pg_dumpall_globals_command(pg_dumpall: &ClientTool, config: &Config) -> PgCommand {
+    connection_args(pg_dumpall, config)
+        .arg("--globals-only")
+        .env("PGPASSWORD", &config.password)
+}
+
+/// Dump the globals of the server to `globals_file` with the `pg_dumpall`
+/// of `pg_dump`, appending its stderr to `log_path`. Killed at `deadline`
+/// or once `cancellation` is cancelled, it leaves no `globals_file`.
+pub(crate) async fn dump_globals(
+    pg_dump: &ClientTool,
+    config: &Config,
+    globals_file: &str,
+    log_path: &str,
+    deadline: Option<Deadline>,
+    cancellation: &CancellationToken,
+) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let pg_dumpall = pg_dumpall_tool(pg_dump, config).await?;
+    let command = pg_dumpall_globals_command(&pg_dumpall, config)
+        .deadline(deadline)
+        .cancellation(cancellation);
+    let stdout_file = StdoutFile {
+        path: globals_file,
+        gzip_level: None,
+    };
+    let run = run_logged_to(&command, log_path, Some(stdout_file)).await?;
+    if !run.status.success() {
+        if run.interruption.is_some() {
+            remove_partial(globals_file)?;
+        }
+        return Err(run.failure("pg_dumpall", log_path).into());
+    }
+    Ok(())
+}
+
+/// Attributes of a role only a superuser may set, even to turn them off.
+const SUPERUSER_ROLE_ATTRIBUTES: [&str; 6] = [
+    "SUPERUSER",
+    "NOSUPERUSER",
+    "REPLICATION",
+    "NOREPLICATION",
+    "BYPASSRLS",
+    "NOBYPASSRLS",
+];
+
+/// What `globals_statements` did with the statements of a globals file.
+#[derive(Debug, Default)]
+pub(crate) struct GlobalsStatements {
+    pub statements: Vec<String>,
+    /// The statements left out, as labelled by `statement_label`.
+    pub skipped: Vec<String>,
+}
+
+/// The first words of `statement`, naming what it does without the
+/// password hashes of `ALTER ROLE`.
+pub(crate) fn statement_label(statement: &str) -> String {
+    statement
+        .trim_end_matches(';')
+        .split_whitespace()
+        .take(3)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The role named at the start of `rest`, quoted or not.
+fn role_name(rest: &str) -> &str {
+    let rest = rest.trim_start();
+    let end = if rest.starts_with('"') {
+        // `""` is a quote inside the name
+        let mut quotes = 0;
+        rest.char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    quotes += 1;
+                }
+                quotes % 2 == 0 && (c == ' ' || c == ';')
+            })
+            .map_or(rest.len(), |(idx, _)| idx)
+    } else {
+        rest.find([' ', ';']).unwrap_or(rest.len())
+    };
+    &rest[..end]
+}
+
+/// The statements of a `pg_dumpall --globals-only` file, one per line or
+/// ending on the line with the final `;`, without its comments and `psql`
+/// meta-commands.
+///
+/// For a role that is not `superuser`, leaves out what it would be refused:
+/// the tablespaces, the superuser roles, and the attributes and `GRANTED BY`
+/// clauses only a superuser may set.
+pub(crate) fn globals_statements(sql: &str, superuser: bool) -> GlobalsStatements {
+    let mut all = Vec::new();
+    let mut current = String::new();
+    for line in sql.lines() {
+        if current.is_empty()
+            && (line.trim().is_empty() || line.starts_with("--") || line.starts_with('\\'))
+        {
+            continue;
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+        if line.trim_end().ends_with(';') {
+            all.push(std::mem::take(&mut current));
+        }
+    }
+    if superuser {
+        return GlobalsStatements {
+            statements: all,
+            skipped: Vec::new(),
+        };
+    }
+
+    let superuser_roles: HashSet<&str> = all
+        .iter()
+        .filter_map(|statement| statement.strip_prefix("ALTER ROLE "))
+        .filter(|rest| {
+            rest.split_whitespace()
+                .any(|word| word.trim_end_matches(';') == "SUPERUSER")
+        })
+        .map(role_name)
+        .collect();
+    let mut globals = GlobalsStatements::default();
+    for statement in &all {
+        let role = statement
+            .strip_prefix("CREATE ROLE ")
+            .or_else(|| statement.strip_prefix("ALTER ROLE "))
+            .map(role_name);
+        if statement.contains(" TABLESPACE ")
+            && (statement.starts_with("CREATE ") || statement.starts_with("ALTER "))
+            || role.is_some_and(|role| superuser_roles.contains(role))
+        {
+            globals.skipped.push(statement_label(statement));
+            continue;
+        }
+        let statement = if statement.starts_with("ALTER ROLE ") && statement.contains(" WITH ") {
+            let words: Vec<&str> = statement
+                .trim_end_matches(';')
+                .split(' ')
+                .filter(|word| !SUPERUSER_ROLE_ATTRIBUTES.contains(word))
+                .collect();
+            format!("{};", words.join(" "))
+        } else if statement.starts_with("GRANT ") {
+            match statement.find(" GRANTED BY ") {
+                Some(idx) => format!("{};", &statement[..idx]),
+                None => statement.clone(),
+            }
+        } else {
+            statement.clone()
+        };
+        globals.statements.push(statement);
+    }
+    globals
+}
+
+/// How many statements of a globals file `apply_globals` ran.
+#[derive(Debug, Default)]
+pub(crate) struct GlobalsApplied {
+    pub applied: usize,
+    /// Creating an object that already exists, kept as it is.
+    pub existing: usize,
+    pub skipped: Vec<String>,
+}
+
+/// Run the statements of the `pg_dumpall --globals-only` file
+/// `globals_file` on `client` one by one, see `globals_statements`.
+///
+/// A role or tablespace that already exists is not an error, any other
+/// failing statement is.
+pub(crate) async fn apply_globals(
+    client: &tokio_postgres::Client,
+    globals_file: &str,
+) -> Result<GlobalsApplied, Box<dyn StdError + Send + Sync>> {
+    let sql = std::fs::read_to_string(globals_file).map_err(|e| PgcError::io(globals_file, e))?;
+    let superuser: bool = client
+        .query_one(
+            "SELECT rolsuper FROM pg_roles WHERE rolname = current_user",
+            &[],
+        )
+        .await?
+        .get(0);
+    let globals = globals_statements(&sql, superuser);
+    let mut applied = GlobalsApplied {
+        skipped: globals.skipped,
+        ..Default::default()
+    };
+    for statement in &globals.statements {
+        match client.batch_execute(statement).await {
+            Ok(()) => applied.applied += 1,
+            Err(e) if e.code() == Some(&tokio_postgres::error::SqlState::DUPLICATE_OBJECT) => {
+                applied.existing += 1
+            }
+            Err(e) => {
+                return Err(format!(
+                    "❌ Failed to apply {} from {}: {}",
+                    statement_label(statement),
+                    globals_file,
+                    e
+                )
+                .into());
+            }
+        }
+    }
+    Ok(applied)
+}
+
+/// Why `run_logged` killed a command.
+///
+/// * `TimedOut`: it was still running at its `Deadline`, after this timeout.
+/// * `Cancelled`: its `cancellation` was cancelled, e.g. on Ctrl-C.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Interruption {
+    TimedOut(Duration),
+    Cancelled,
+}
+
+/// A finished `run_logged` command.
+#[derive(Debug)]
+pub(crate) struct CommandRun {
+    pub status: ExitStatus,
+    /// The last lines the command wrote to stderr.
+    pub stderr_tail: Vec<String>,
+    /// Set when the command was killed before it finished.
+    pub interruption: Option<Interruption>,
+    pub ran_for: Duration,
+}
+
+impl CommandRun {
+    /// The error for a failed run of `what`, with the last lines of stderr
+    /// and the log holding all of them. A `CommandInterrupted` for a killed
+    /// command.
+    pub(crate) fn failure(&self, what: &str, log_path: &str) -> PgcError {
+        if let Some(interruption) = self.interruption {
+            return CommandInterrupted {
+                what: what.to_string(),
+                interruption,
+                ran_for: self.ran_for,
+                stderr_tail: self.stderr_tail.clone(),
+                log_path: log_path.to_string(),
+            }
+            .into();
+        }
+        let mut message = format!("❌ {} failed ({})", what, self.status);
+        message.push_str(&stderr_context(&self.stderr_tail, log_path));
+        PgcError::Subprocess {
+            program: what.to_string(),
+            stderr_tail: self.stderr_tail.clone(),
+            message,
+        }
+    }
+}
+
+/// The last lines of stderr and the log holding all of them, ending the
+/// error of a command.
+fn stderr_context(stderr_tail: &[String], log_path: &str) -> String {
+    let mut context = String::new();
+    if !stderr_tail.is_empty() {
+        context.push_str(&format!(
+            ", last lines of stderr:\n{}",
+            stderr_tail.join("\n")
+        ));
+    }
+    context.push_str(&format!("\nFull log: {}", log_path));
+    context
+}
+
+/// The error of a command `run_logged` killed, telling a timeout from a
+/// cancellation.
+#[derive(Debug)]
+pub(crate) struct CommandInterrupted {
+    pub what: String,
+    pub interruption: Interruption,
+    pub ran_for: Duration,
+    pub stderr_tail: Vec<String>,
+    pub log_path: String,
+}
+
+impl fmt::Display for CommandInterrupted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.interruption {
+            Interruption::TimedOut(timeout) => write!(
+                f,
+                "❌ {} timed out after {:.2?} (timeout {:.2?}), killed",
+                self.what, self.ran_for, timeout
+            )?,
+            Interruption::Cancelled => write!(
+                f,
+                "❌ {} cancelled after {:.2?}, killed",
+                self.what, self.ran_for
+            )?,
+        }
+        write!(f, "{}", stderr_context(&self.stderr_tail, &self.log_path))
+    }
+}
+
+impl StdError for CommandInterrupted {}
+
+/// Run `command`, echoing its stderr line by line while appending it to
+/// `log_path`, after a header naming the command.
+///
+/// `pg_dump` and `pg_restore` only write errors and warnings to stderr, so
+/// the log next to a dump holds what went wrong with it.
+pub(crate) async fn run_logged(
+    command: &PgCommand,
+    log_path: &str,
+) -> Result<CommandRun, std::io::Error> {
+    run_logged_to(command, log_path, None).await
+}
+
+/// The file the stdout of a command is written to, gzipped at
+/// `gzip_level` if set.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StdoutFile<'a> {
+    pub path: &'a str,
+    pub gzip_level: Option<u32>,
+}
+
+/// `run_logged`, writing the stdout of `command` to `stdout_file` if given.
+pub(crate) async fn run_logged_to(
+    command: &PgCommand,
+    log_path: &str,
+    stdout_file: Option<StdoutFile<'_>>,
+) -> Result<CommandRun, std::io::Error> {
+    run_logged_with(command, log_path, stdout_file, |_| true).await
+}
+
+/// `run_logged_to`, only echoing and keeping the lines of stderr for which
+/// `on_line` returns true. All of them are still logged.
+pub(crate) async fn run_logged_with(
+    command: &PgCommand,
+    log_path: &str,
+    stdout_file: Option<StdoutFile<'_>>,
+    on_line: impl FnMut(&str) -> bool,
+) -> Result<CommandRun, std::io::Error> {
+    match stdout_file {
+        Some(StdoutFile {
+            path,
+            gzip_level: Some(level),
+        }) => {
+            let file = tokio::fs::File::create(path).await?;
+            let encoder = GzipEncoder::with_quality(file, Level::Precise(level as i32));
+            let (run, _) = run_logged_into(command, log_path, encoder, on_line).await?;
+            Ok(run)
+        }
+        Some(StdoutFile {
+            path,
+            gzip_level: None,
+        }) => {
+            let log = open_log(command, log_path).await?;
+            let mut child = command.command();
+            child.stdout(std::fs::File::create(path)?);
+            let child = in_own_group(&mut child).stderr(Stdio::piped()).spawn()?;
+            wait_logged(child, log, command.deadline, &command.cancellation, on_line).await
+        }
+        None => {
+            let log = open_log(command, log_path).await?;
+            let child = in_own_group(&mut command.command())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            wait_logged(child, log, command.deadline, &command.cancellation, on_line).await
+        }
+    }
+}
+
+/// `run_logged_with`, copying the stdout of `command` into `stdout` as it
+/// comes, and shutting it down at the end. A slow writer slows `command`
+/// down rather than having its output pile up in memory.
+///
+/// Returns `stdout` with the run, to read what it kept about the copy.
+pub(crate) async fn run_logged_into<W>(
+    command: &PgCommand,
+    log_path: &str,
+    mut stdout: W,
+    on_line: impl FnMut(&str) -> bool,
+) -> Result<(CommandRun, W), std::io::Error>
+where
+    W: tokio::io::AsyncWrite + Send + Unpin + 'static,
+{
+    let log = open_log(command, log_path).await?;
+    let mut child = in_own_group(&mut command.command())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Copied while stderr is read, the command would block on a full pipe otherwise
+    let mut child_stdout = child.stdout.take().expect("stdout of the command is piped");
+    let copy = tokio::spawn(async move {
+        tokio::io::copy(&mut child_stdout, &mut stdout).await?;
+        stdout.shutdown().await?;
+        Ok::<_, std::io::Error>(stdout)
+    });
+    let run = wait_logged(child, log, command.deadline, &command.cancellation, on_line).await?;
+    let stdout = copy.await.map_err(std::io::Error::other)??;
+    Ok((run, stdout))
+}
+
+/// The log of `run_logged`, opened for appending after a header naming
+/// `command`.
+async fn open_log(command: &PgCommand, log_path: &str) -> Result<tokio::fs::File, std::io::Error> {
+    let mut log = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .await?;
+    log.write_all(
+        format!(
+            "# {} {}\n",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            command.display()
+        )
+        .as_bytes(),
+    )
+    .await?;
+    Ok(log)
+}
+
+/// Start the command in a process group of its own, which `kill_group`
+/// kills as a whole.
+fn in_own_group(command: &mut tokio::process::Command) -> &mut tokio::process::Command {
+    #[cfg(unix)]
+    command.process_group(0);
+    command
+}
+
+/// Kill `child` with the processes it started, such as the workers of a
+/// parallel `pg_restore`.
+///
+/// `docker exec` does not pass the signal on: the tool keeps running in its
+/// container until the server ends its session.
+async fn kill_group(child: &mut tokio::process::Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        let _ = tokio::process::Command::new("kill")
+            .arg("-KILL")
+            .arg("--")
+            .arg(format!("-{}", pid))
+            .status()
+            .await;
+    }
+    let _ = child.kill().await;
+}
+
+/// Read the stderr of `child` into `log` until it exits, see `run_logged_with`.
+///
+/// The child is killed when `deadline` passes or `cancellation` is
+/// cancelled first.
+async fn wait_logged(
+    mut child: tokio::process::Child,
+    mut log: tokio::fs::File,
+    deadline: Option<Deadline>,
+    cancellation: &CancellationToken,
+    mut on_line: impl FnMut(&str) -> bool,
+) -> Result<CommandRun, std::io::Error> {
+    let start = std::time::Instant::now();
+    let stderr = child.stderr.take().expect("stderr of the command is piped");
+    let mut lines = BufReader::new(stderr).lines();
+    let mut stderr_tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+    let read_stderr = async {
+        while let Some(line) = lines.next_line().await? {
+            log.write_all(format!("{}\n", line).as_bytes()).await?;
+            if !on_line(&line) {
+                continue;
+            }
+            info!("{}", line);
+            if stderr_tail.len() == STDERR_TAIL_LINES {
+                stderr_tail.pop_front();
+            }
+            stderr_tail.push_back(line);
+        }
+        Ok::<_, std::io::Error>(())
+    };
+    let expired = async {
+        match deadline {
+            Some(deadline) => {
+                tokio::time::sleep_until(deadline.at).await;
+                deadline.timeout
+            }
+            None => std::future::pending().await,
+        }
+    };
+    let interruption = tokio::select! {
+        read = read_stderr => {
+            read?;
+            None
+        }
+        timeout = expired => Some(Interruption::TimedOut(timeout)),
+        _ = cancellation.cancelled() => Some(Interruption::Cancelled),
+    };
+    if let Some(interruption) = interruption {
+        kill_group(&mut child).await;
+        log.write_all(format!("# killed: {:?}\n", interruption).as_bytes())
+            .await?;
+    }
+    log.flush().await?;
+
+    Ok(CommandRun {
+        status: child.wait().await?,
+        stderr_tail: stderr_tail.into(),
+        interruption,
+        ran_for: start.elapsed(),
+    })
+}
+
+/// A writer computing the SHA-256 and size of what goes through it to
+/// `inner`, for dumps that are never on disk to be checksummed.
+pub(crate) struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl<W> HashingWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            bytes: 0,
+        }
+    }
+
+    /// The hex SHA-256 and the number of bytes written so far.
+    pub(crate) fn finish(self) -> (String, u64) {
+        let sha256 = self
+            .hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        (sha256, self.bytes)
+    }
+}
+
+impl<W: tokio::io::AsyncWrite + Unpin> tokio::io::AsyncWrite for HashingWriter<W> {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<Result<usize, std::io::Error>> {
+        let this = &mut *self;
+        let poll = std::pin::Pin::new(&mut this.inner).poll_write(cx, buf);
+        // Only what `inner` took, the rest is written again
+        if let std::task::Poll::Ready(Ok(written)) = poll {
+            this.hasher.update(&buf[..written]);
+            this.bytes += written as u64;
+        }
+        poll
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), std::io::Error>> {
+        std::pin::Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), std::io::Error>> {
+        std::pin::Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// How often `watch_copy_progress` asks the server how far the copies are.
+const COPY_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Progress of a phase of a restore, following the items
+/// `pg_restore --verbose` reports on stderr, reported to `reporter`.
+pub(crate) struct RestoreProgress {
+    reporter: Arc<dyn Reporter>,
+    phase: &'static str,
+    /// The entries of the table of contents the phase restores.
+    entries: Vec<TocEntry>,
+    done: HashSet<i32>,
+    /// Whether `pg_restore` entered its parallel loop, where items are only
+    /// done once reported as finished.
+    parallel: bool,
+}
+
+impl RestoreProgress {
+    pub(crate) fn new(
+        phase: RestorePhase,
+        entries: Vec<TocEntry>,
+        reporter: Arc<dyn Reporter>,
+    ) -> Self {
+        reporter.progress(ProgressEvent::Started {
+            phase: phase.name().to_string(),
+            total: Some(entries.len() as u64),
+        });
+        Self {
+            reporter,
+            phase: phase.name(),
+            entries,
+            done: HashSet::new(),
+            parallel: false,
+        }
+    }
+
+    /// Follow a line of the stderr of `pg_restore --verbose`. Returns
+    /// whether it is worth showing: errors and warnings, not the verbose
+    /// `processing item 3673 TABLE DATA ta` lines moving the bar.
+    pub(crate) fn line(&mut self, line: &str) -> bool {
+        let Some(message) = line.strip_prefix("pg_restore: ") else {
+            // The `Command was:` of an error
+            return true;
+        };
+        let reported = [
+            "error:",
+            "warning:",
+            "hint:",
+            "from TOC entry",
+            "while PROCESSING",
+        ];
+        if reported.iter().any(|prefix| message.starts_with(prefix)) {
+            return true;
+        }
+        if message == "entering main parallel loop" {
+            self.parallel = true;
+        }
+
+        let item = ["processing item ", "launching item ", "finished item "]
+            .iter()
+            .find_map(|prefix| message.strip_prefix(prefix).map(|rest| (*prefix, rest)));
+        let entry = match item {
+            Some((_, rest)) => rest
+                .split(' ')
+                .next()
+                .and_then(|dump_id| dump_id.parse::<i32>().ok())
+                .and_then(|dump_id| self.entries.iter().find(|entry| entry.dump_id == dump_id)),
+            // Without --jobs the data is reported as
+            // `processing data for table "public.ta"` and `executing SEQUENCE SET ta_id_seq`
+            None => {
+                if let Some(table) = message.strip_prefix("processing data for table ") {
+                    let (schema, tag) = table.trim_matches('"').split_once('.').unwrap_or(("", ""));
+                    self.entries.iter().find(|entry| {
+                        entry.desc == "TABLE DATA" && entry.schema == schema && entry.tag == tag
+                    })
+                } else if let Some(tag) = message.strip_prefix("executing SEQUENCE SET ") {
+                    self.entries
+                        .iter()
+                        .find(|entry| entry.desc == "SEQUENCE SET" && entry.tag == tag)
+                } else {
+                    None
+                }
+            }
+        };
+        if let Some(entry) = entry {
+            self.reporter.progress(ProgressEvent::Message {
+                phase: self.phase.to_string(),
+                message: format!("{} {}", entry.desc, entry.tag),
+            });
+            // In the parallel loop, an item is done once finished rather than when started
+            let done = match item {
+                Some((prefix, _)) => prefix != "launching item ",
+                None => !self.parallel,
+            };
+            if done && self.done.insert(entry.dump_id) {
+                self.reporter.progress(ProgressEvent::Advanced {
+                    phase: self.phase.to_string(),
+                    done: self.done.len() as u64,
+                });
+            }
+        }
+        false
+    }
+
+    /// Report the phase as finished.
+    pub(crate) fn finish(&self) {
+        self.reporter.progress(ProgressEvent::Finished {
+            phase: self.phase.to_string(),
+        });
+    }
+}
+
+/// Report the rows copied so far into the tables being restored in the
+/// database of `config`, from `pg_stat_progress_copy`, as messages of the
+/// data phase until aborted.
+pub(crate) fn watch_copy_progress(
+    reporter: Arc<dyn Reporter>,
+    config: Config,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let Ok(client) = db::connect_as(&config, &config.db_name, None).await else {
+            return;
+        };
+        loop {
+            tokio::time::sleep(COPY_PROGRESS_INTERVAL).await;
+            let Ok(rows) = client
+                .query(
+                    "SELECT relid::regclass::text, tuples_processed FROM pg_stat_progress_copy WHERE datname = current_database()",
+                    &[],
+                )
+                .await
+            else {
+                continue;
+            };
+            let copying: Vec<String> = rows
+                .iter()
+                .map(|row| {
+                    format!(
+                        "{} ({} rows)",
+                        row.get::<_, String>(0),
+                        row.get::<_, i64>(1)
+                    )
+                })
+                .collect();
+            if !copying.is_empty() {
+                reporter.progress(ProgressEvent::Message {
+                    phase: RestorePhase::Data.name().to_string(),
+                    message: format!("copying {}", copying.join(", ")),
+                });
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A connection whose names would break a command line built for a
+    /// shell.
+    fn config() -> Config {
+        Config {
+            host: "db.example.com".to_string(),
+            port: 5433,
+            user: "pgc user".to_string(),
+            password: "s3cr'et $HOME".to_string(),
+            db_name: "my db=x".to_string(),
+            pg_dump_path: None,
+            pg_restore_path: None,
+            jobs: None,
+            audit_log: None,
+            dialect: None,
+        }
+    }
+
+    fn tool(path: &str, major: u32) -> ClientTool {
+        ClientTool {
+            path: path.to_string(),
+            version: format!("{}.2", major),
+            major,
+            docker_container: None,
+        }
+    }
+
+    #[test]
+    fn pg_dump_gets_its_names_as_arguments_and_its_password_in_the_environment() {
+        let options = BackupOptions {
+            jobs: 1,
+            tables: Some(vec!["My \"Table\"".to_string()]),
+            exclude_tables: Some(Vec::new()),
+            ..Default::default()
+        };
+        let command = pg_dump_command(
+            &tool("/usr/bin/pg_dump", 16),
+            &config(),
+            Some("/backups/my dump.dump"),
+            &options,
+        );
+        assert_eq!(command.program, "/usr/bin/pg_dump");
+        assert_eq!(
+            command.args,
+            [
+                "--host=db.example.com",
+                "--port=5433",
+                "--username=pgc user",
+                "--dbname=dbname='my db=x'",
+                "--format=custom",
+                "--no-privileges",
+                "--no-owner",
+                "--table=\"My \"\"Table\"\"\"",
+                "--file=/backups/my dump.dump",
+            ]
+        );
+        assert_eq!(
+            command.env,
+            [("PGPASSWORD".to_string(), "s3cr'et $HOME".to_string())]
+        );
+        assert!(!command.display().contains("s3cr"), "{}", command.display());
+    }
+
+    #[test]
+    fn pg_dump_excludes_the_postgis_tables_unless_told_otherwise() {
+        let pg_dump = tool("pg_dump", 16);
+        let excluded = |options: &BackupOptions| -> Vec<String> {
+            pg_dump_command(&pg_dump, &config(), None, options)
+                .args
+                .into_iter()
+                .filter(|arg| arg.starts_with("--exclude-"))
+                .collect()
+        };
+        assert_eq!(
+            excluded(&BackupOptions::default()),
+            POSTGIS_TABLES
+                .iter()
+                .map(|table| format!("--exclude-table=\"{}\"", table))
+                .collect::<Vec<_>>()
+        );
+        let options = BackupOptions {
+            exclude_tables: Some(vec!["logs_*".to_string(), "Audit".to_string()]),
+            exclude_schemas: vec!["staging".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            excluded(&options),
+            [
+                "--exclude-table=logs_*",
+                "--exclude-table=\"Audit\"",
+                "--exclude-schema=\"staging\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn pg_dump_is_given_jobs_only_for_directory_dumps() {
+        let pg_dump = tool("pg_dump", 16);
+        let jobs = |format: Option<DumpFormat>| {
+            let options = BackupOptions {
+                jobs: 4,
+                format,
+                ..Default::default()
+            };
+            pg_dump_command(&pg_dump, &config(), Some("out"), &options)
+                .args
+                .into_iter()
+                .filter(|arg| arg.starts_with("--jobs=") || arg.starts_with("--format="))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(jobs(None), ["--jobs=4", "--format=directory"]);
+        assert_eq!(jobs(Some(DumpFormat::Custom)), ["--format=custom"]);
+    }
+
+    #[test]
+    fn compression_is_spelled_for_the_version_of_pg_dump() {
+        let zstd = CompressionSpec {
+            method: CompressionMethod::Zstd,
+            level: Some(3),
+        };
+        let none = CompressionSpec {
+            method: CompressionMethod::None,
+            level: None,
+        };
+        assert_eq!(
+            compress_arg(zstd, &tool("pg_dump", 16)).as_deref(),
+            Some("--compress=zstd:3")
+        );
+        assert_eq!(
+            compress_arg(none, &tool("pg_dump", 16)).as_deref(),
+            Some("--compress=none")
+        );
+        assert_eq!(
+            compress_arg(zstd, &tool("pg_dump", 15)).as_deref(),
+            Some("--compress=3")
+        );
+        assert_eq!(
+            compress_arg(none, &tool("pg_dump", 15)).as_deref(),
+            Some("--compress=0")
+        );
+    }
+
+    #[test]
+    fn pg_restore_restores_the_schema_then_the_data() {
+        let pg_restore = tool("/usr/bin/pg_restore", 16);
+        let options = RestoreOptions {
+            jobs: 2,
+            ..Default::default()
+        };
+        let schema = pg_restore_command(
+            &pg_restore,
+            &config(),
+            "/backups/my dump.dump",
+            &options,
+            RestorePhase::Schema,
+            None,
+        );
+        assert_eq!(
+            schema.args[4..],
+            [
+                "--jobs=2",
+                "--verbose",
+                "--schema-only",
+                "--clean",
+                "--if-exists",
+                "--no-acl",
+                "--no-comments",
+                "/backups/my dump.dump",
+            ]
+        );
+        let data = pg_restore_command(
+            &pg_restore,
+            &config(),
+            "/backups/my dump.dump",
+            &options,
+            RestorePhase::Data,
+            Some("/tmp/tables.list"),
+        );
+        assert_eq!(
+            data.args[4..],
+            [
+                "--jobs=2",
+                "--verbose",
+                "--data-only",
+                "--disable-triggers",
+                "--use-list=/tmp/tables.list",
+                "--no-acl",
+                "--no-comments",
+                "/backups/my dump.dump",
+            ]
+        );
+        assert_eq!(data.env.len(), 1);
+        assert_eq!(data.env[0].0, "PGPASSWORD");
+    }
+
+    #[test]
+    fn tools_of_a_container_run_through_docker_exec_on_its_socket() {
+        let pg_dump = ClientTool {
+            docker_container: Some("postgis".to_string()),
+            ..tool("pg_dump", 16)
+        };
+        let command = pg_dump_command(&pg_dump, &config(), None, &BackupOptions::default());
+        assert_eq!(command.program, "docker");
+        assert_eq!(
+            command.args[..10],
+            [
+                "exec",
+                "--env",
+                "PGPASSWORD",
+                "--env",
+                "PGOPTIONS",
+                "-i",
+                "postgis",
+                "pg_dump",
+                "--username=pgc user",
+                "--dbname=dbname='my db=x'",
+            ]
+        );
+        assert!(!command.args.iter().any(|arg| arg.starts_with("--host=")));
+    }
+
+    #[test]
+    fn the_database_name_is_quoted_as_a_conninfo_value() {
+        assert_eq!(conninfo_value("plain"), "'plain'");
+        assert_eq!(
+            conninfo_value("postgresql://host/db"),
+            "'postgresql://host/db'"
+        );
+        assert_eq!(conninfo_value("it's\\"), "'it\\'s\\\\'");
+    }
+
+    /// Keeps the progress it is given, to compare.
+    #[derive(Default)]
+    struct CapturingReporter {
+        events: std::sync::Mutex<Vec<ProgressEvent>>,
+    }
+
+    impl CapturingReporter {
+        fn take(&self) -> Vec<ProgressEvent> {
+            std::mem::take(&mut self.events.lock().unwrap())
+        }
+    }
+
+    impl Reporter for CapturingReporter {
+        fn info(&self, _message: &str) {}
+        fn warn(&self, _message: &str) {}
+        fn table(&self, _headers: &[&str], _rows: &[Vec<String>]) {}
+        fn progress(&self, event: ProgressEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    fn toc_entry(dump_id: i32, desc: &str, tag: &str) -> TocEntry {
+        TocEntry {
+            dump_id,
+            desc: desc.to_string(),
+            schema: "public".to_string(),
+            tag: tag.to_string(),
+        }
+    }
+
+    #[test]
+    fn restore_progress_reports_each_entry_once_as_pg_restore_goes() {
+        let reporter = Arc::new(CapturingReporter::default());
+        let entries = vec![
+            toc_entry(1, "TABLE DATA", "points"),
+            toc_entry(2, "SEQUENCE SET", "points_id_seq"),
+            toc_entry(3, "TABLE DATA", "shapes"),
+        ];
+        let mut progress = RestoreProgress::new(RestorePhase::Data, entries, reporter.clone());
+        let phase = || "data".to_string();
+        let message = |message: &str| ProgressEvent::Message {
+            phase: phase(),
+            message: message.to_string(),
+        };
+        let advanced = |done| ProgressEvent::Advanced {
+            phase: phase(),
+            done,
+        };
+
+        assert!(!progress.line("pg_restore: processing data for table \"public.points\""));
+        assert!(!progress.line("pg_restore: executing SEQUENCE SET points_id_seq"));
+        assert!(progress.line("pg_restore: error: could not execute query"));
+        assert!(progress.line("Command was: COPY public.shapes FROM stdin;"));
+        assert!(!progress.line("pg_restore: processing data for table \"public.points\""));
+        assert_eq!(
+            reporter.take(),
+            [
+                ProgressEvent::Started {
+                    phase: phase(),
+                    total: Some(3),
+                },
+                message("TABLE DATA points"),
+                advanced(1),
+                message("SEQUENCE SET points_id_seq"),
+                advanced(2),
+                message("TABLE DATA points"),
+            ]
+        );
+
+        // In the parallel loop, launched items are not done yet
+        assert!(!progress.line("pg_restore: entering main parallel loop"));
+        assert!(!progress.line("pg_restore: launching item 3 TABLE DATA public shapes postgres"));
+        assert!(!progress.line("pg_restore: finished item 3 TABLE DATA public shapes postgres"));
+        progress.finish();
+        assert_eq!(
+            reporter.take(),
+            [
+                message("TABLE DATA shapes"),
+                message("TABLE DATA shapes"),
+                advanced(3),
+                ProgressEvent::Finished { phase: phase() },
+            ]
+        );
+    }
+
+    /// The program and arguments `command` spawns, as the OS gets them.
+    fn spawned(command: &PgCommand) -> (String, Vec<String>) {
+        let command = command.command();
+        let command = command.as_std();
+        (
+            command.get_program().to_string_lossy().into_owned(),
+            command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+        )
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn commands_are_spawned_and_logged_with_posix_paths() {
+        let command = pg_dump_command(
+            &tool("/usr/lib/postgresql/16/bin/pg_dump", 16),
+            &config(),
+            Some("/backups/it's.dump"),
+            &BackupOptions::default(),
+        );
+        let (program, args) = spawned(&command);
+        assert_eq!(program, "/usr/lib/postgresql/16/bin/pg_dump");
+        assert_eq!(args.last().unwrap(), "--file=/backups/it's.dump");
+        assert!(command
+            .display()
+            .starts_with("/usr/lib/postgresql/16/bin/pg_dump --host=db.example.com"));
+        assert!(
+            command
+                .display()
+                .ends_with(" '--file=/backups/it'\\''s.dump'"),
+            "{}",
+            command.display()
+        );
+        assert_eq!(checksum_path("/backups/db.dir/"), "/backups/db.dir.sha256");
+        assert!(DockerFile::new("postgis", "/backups/db.dir/")
+            .path
+            .ends_with("_db.dir"));
+        assert_ne!(
+            DockerFile::new("postgis", "/backups/db.dump").path,
+            DockerFile::new("postgis", "/backups/db.dump").path
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_cancelled_command_is_killed_and_only_its_own() {
+        let log = std::env::temp_dir().join(format!("pgc_cancelled_{}.log", std::process::id()));
+        let log = log.to_str().unwrap();
+        let cancellation = CancellationToken::new();
+        let command = PgCommand::new("sleep")
+            .arg("30")
+            .cancellation(&cancellation);
+        let other = PgCommand::new("true");
+        cancellation.cancel();
+        let run = run_logged(&command, log).await.unwrap();
+        assert_eq!(run.interruption, Some(Interruption::Cancelled));
+        assert!(run.ran_for < Duration::from_secs(30));
+        let run = run_logged(&other, log).await.unwrap();
+        assert_eq!(run.interruption, None);
+        assert!(run.status.success());
+        std::fs::remove_file(log).ok();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn commands_are_spawned_and_logged_with_windows_paths() {
+        let command = pg_dump_command(
+            &tool(r"C:\Program Files\PostgreSQL\16\bin\pg_dump.exe", 16),
+            &config(),
+            Some(r"C:\backups\it's.dump"),
+            &BackupOptions::default(),
+        );
+        let (program, args) = spawned(&command);
+        assert_eq!(program, r"C:\Program Files\PostgreSQL\16\bin\pg_dump.exe");
+        assert_eq!(args.last().unwrap(), r"--file=C:\backups\it's.dump");
+        assert!(command.display().starts_with(
+            r"'C:\Program Files\PostgreSQL\16\bin\pg_dump.exe' --host=db.example.com"
+        ));
+        assert!(
+            command
+                .display()
+                .ends_with(r" '--file=C:\backups\it''s.dump'"),
+            "{}",
+            command.display()
+        );
+        assert_eq!(
+            shell_quote(r"C:\pg\bin\pg_dump.exe"),
+            r"C:\pg\bin\pg_dump.exe"
+        );
+        assert_eq!(
+            checksum_path(r"C:\backups\db.dir\"),
+            r"C:\backups\db.dir.sha256"
+        );
+        assert!(DockerFile::new("postgis", r"C:\backups\db.dir\")
+            .path
+            .ends_with("_db.dir"));
+    }
+
+    #[test]
+    fn backups_are_named_from_the_template_and_found_by_that_name() {
+        let time = chrono::NaiveDate::from_ymd_opt(2025, 3, 25)
+            .unwrap()
+            .and_hms_opt(14, 57, 49)
+            .unwrap();
+        let name = backup_file_name(
+            DEFAULT_FILENAME_TEMPLATE,
+            "mydb",
+            "_roads",
+            time,
+            DumpFormat::Custom,
+        )
+        .unwrap();
+        assert_eq!(name, "backup_mydb_roads_2025-03-25_14-57-49");
+        assert_eq!(
+            backup_file_name(
+                "{db}-{format}-{date}",
+                "mydb",
+                "",
+                time,
+                DumpFormat::Directory
+            )
+            .unwrap(),
+            "mydb-directory-2025-03-25"
+        );
+        for template in ["{db}_{month}", "{db}/{date}", "{label}", ".."] {
+            let error = backup_file_name(template, "mydb", "", time, DumpFormat::Custom)
+                .expect_err(template);
+            assert!(error.to_string().contains(template), "{}", error);
+        }
+
+        let file_name = format!("{}.dump", name);
+        assert_eq!(
+            parse_backup_name(&file_name, Some("mydb")),
+            Some(("backup_mydb_roads".to_string(), time))
+        );
+        assert!(parse_backup_name(&file_name, None).is_some());
+        // Another database whose name starts with `my`
+        assert_eq!(parse_backup_name(&file_name, Some("my")), None);
+        assert_eq!(
+            parse_backup_name("backup_mydb_2025-03-25_14-57-49.sql.gz", Some("mydb")),
+            Some(("backup_mydb".to_string(), time))
+        );
+        for file_name in [
+            "backup_mydb_2025-03-25_14-57-49.txt",
+            "backup_mydb_2025-13-25_14-57-49.dump",
+            "latest_mydb.dump",
+            "_2025-03-25_14-57-49.dump",
+        ] {
+            assert_eq!(
+                parse_backup_name(file_name, Some("mydb")),
+                None,
+                "{}",
+                file_name
+            );
+        }
+    }
+
+    #[test]
+    fn partial_backups_are_labelled_by_their_tables_or_their_number() {
+        assert_eq!(tables_label(&["roads".to_string()]), "roads");
+        assert_eq!(
+            tables_label(&["public.roads".to_string(), "My Table".to_string()]),
+            "public_roads-My_Table"
+        );
+        let tables: Vec<String> = (0..=NAMED_TABLES_MAX).map(|i| format!("t{}", i)).collect();
+        assert_eq!(tables_label(&tables), format!("{}tables", tables.len()));
+    }
+
+    #[test]
+    fn the_latest_pointer_names_the_newest_dump_relative_to_its_directory() {
+        let dir = std::env::temp_dir().join(format!("pgc_latest_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_str = dir.to_str().unwrap();
+        assert_eq!(read_latest_pointer(dir_str, "mydb").unwrap(), None);
+
+        for name in ["backup_mydb_1.dump", "backup_mydb_2.dump"] {
+            let dump = dir.join(name);
+            std::fs::write(&dump, name).unwrap();
+            write_latest_pointer(dir_str, "mydb", dump.to_str().unwrap()).unwrap();
+            assert_eq!(
+                read_latest_pointer(dir_str, "mydb").unwrap(),
+                Some(dump.display().to_string())
+            );
+        }
+        let (link, json) = latest_pointer_paths(dir_str, "mydb");
+        assert_eq!(
+            std::fs::read_link(&link).unwrap(),
+            Path::new("backup_mydb_2.dump")
+        );
+        // A dump removed since is not the latest
+        std::fs::remove_file(dir.join("backup_mydb_2.dump")).unwrap();
+        assert_eq!(read_latest_pointer(dir_str, "mydb").unwrap(), None);
+
+        // The JSON pointer of filesystems without symlinks
+        std::fs::remove_file(&link).unwrap();
+        std::fs::write(&json, r#"{"path": "backup_mydb_1.dump"}"#).unwrap();
+        assert_eq!(
+            read_latest_pointer(dir_str, "mydb").unwrap(),
+            Some(dir.join("backup_mydb_1.dump").display().to_string())
+        );
+        std::fs::write(&json, "{}").unwrap();
+        assert!(read_latest_pointer(dir_str, "mydb").is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
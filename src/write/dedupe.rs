@@ -0,0 +1,288 @@
+use crate::utils::sql;
+use crate::write::options::Dedupe;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::error::Error as StdError;
+use std::hash::{Hash, Hasher};
+use tokio_postgres::Client;
+use tracing::info;
+
+/// Names kept in memory before they are spilled to a temporary table.
+const MAX_NAMES_IN_MEMORY: usize = 1_000_000;
+
+/// Size of the bloom filter in front of the spilled names (64M bits = 8MB).
+const BLOOM_BITS: usize = 64 * 1024 * 1024;
+
+/// Number of hash functions of the bloom filter.
+const BLOOM_HASHES: u64 = 7;
+
+/// Session-local table the seen names are spilled to.
+const SPILL_TABLE: &str = "_pgc_dedupe_seen";
+
+/// A plain bloom filter over feature names.
+///
+/// It answers "definitely not seen" without a database round trip, so only
+/// the few names that may have been seen are looked up in the spill table.
+struct Bloom {
+    bits: Vec<u64>,
+}
+
+impl Bloom {
+    fn new() -> Self {
+        Self {
+            bits: vec![0; BLOOM_BITS / 64],
+        }
+    }
+
+    fn positions(name: &str) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        let h1 = hasher.finish();
+        0xdeadbeef_u64.hash(&mut hasher);
+        let h2 = hasher.finish() | 1;
+        (0..BLOOM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) as usize) % BLOOM_BITS)
+    }
+
+    fn insert(&mut self, name: &str) {
+        for position in Self::positions(name) {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+
+    fn may_contain(&self, name: &str) -> bool {
+        Self::positions(name).all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
+
+/// The set of names already loaded during the current ingest.
+///
+/// Names are kept in a `HashSet` until there are too many of them, then
+/// spilled to a temporary table on the ingest connection, with a bloom filter
+/// in front of it. Memory use stays bounded however many features are loaded.
+struct SeenNames {
+    memory: HashSet<String>,
+    bloom: Option<Bloom>,
+}
+
+impl SeenNames {
+    fn new() -> Self {
+        Self {
+            memory: HashSet::new(),
+            bloom: None,
+        }
+    }
+
+    /// Returns the subset of `names` that were seen before.
+    async fn seen(
+        &self,
+        client: &Client,
+        names: &HashSet<&str>,
+    ) -> Result<HashSet<String>, Box<dyn StdError + Send + Sync>> {
+        let mut seen: HashSet<String> = names
+            .iter()
+            .filter(|name| self.memory.contains(**name))
+            .map(|name| name.to_string())
+            .collect();
+
+        if let Some(bloom) = &self.bloom {
+            let candidates: Vec<String> = names
+                .iter()
+                .filter(|name| !seen.contains(**name) && bloom.may_contain(name))
+                .map(|name| name.to_string())
+                .collect();
+            if !candidates.is_empty() {
+                let rows = client
+                    .query(
+                        &format!(
+                            "SELECT name FROM {} WHERE name = ANY($1)",
+                            sql::ident(SPILL_TABLE)?
+                        ),
+                        &[&candidates],
+                    )
+                    .await?;
+                seen.extend(rows.iter().map(|row| row.get::<_, String>(0)));
+            }
+        }
+
+        Ok(seen)
+    }
+
+    async fn insert(
+        &mut self,
+        client: &Client,
+        names: Vec<String>,
+    ) -> Result<(), Box<dyn StdError + Send + Sync>> {
+        self.memory.extend(names);
+        if self.memory.len() > MAX_NAMES_IN_MEMORY {
+            self.spill(client).await?;
+        }
+        Ok(())
+    }
+
+    /// Moves the in-memory names to the spill table.
+    async fn spill(&mut self, client: &Client) -> Result<(), Box<dyn StdError + Send + Sync>> {
+        if self.bloom.is_none() {
+            info!(
+                "💾 More than {} feature names seen, spilling them to a temporary table",
+                MAX_NAMES_IN_MEMORY
+            );
+            client
+                .batch_execute(&format!(
+                    "CREATE TEMP TABLE IF NOT EXISTS {} (name TEXT PRIMARY KEY)",
+                    sql::ident(SPILL_TABLE)?
+                ))
+                .await?;
+            self.bloom = Some(Bloom::new());
+        }
+        let bloom = self.bloom.as_mut().expect("bloom filter was just created");
+        let names: Vec<String> = self.memory.drain().collect();
+        for name in &names {
+            bloom.insert(name);
+        }
+        client
+            .execute(
+                &format!(
+                    "INSERT INTO {} SELECT unnest($1::text[]) ON CONFLICT DO NOTHING",
+                    sql::ident(SPILL_TABLE)?
+                ),
+                &[&names],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Tracks feature names across all files of one ingest to resolve duplicates
+/// according to the `Dedupe` strategy.
+///
+/// Call `prepare_file` with a file's feature names before uploading it, and
+/// `commit_file` once its COPY completed.
+pub(crate) struct DedupeState {
+    pub strategy: Dedupe,
+    pub duplicates: u64,
+    seen: SeenNames,
+    suffix_counts: HashMap<String, u32>,
+}
+
+impl DedupeState {
+    pub(crate) fn new(strategy: Dedupe) -> Self {
+        Self {
+            strategy,
+            duplicates: 0,
+            seen: SeenNames::new(),
+            suffix_counts: HashMap::new(),
+        }
+    }
+
+    /// Decides, for each feature of a file, under which name it is loaded.
+    ///
+    /// Returns one entry per name in `names`: `Some(name)` to load the feature
+    /// under that name, `None` to drop it as a duplicate. With `LastWins`,
+    /// rows loaded from earlier files under a name that appears again are
+    /// deleted from the table here, so the new feature can replace them.
+    pub(crate) async fn prepare_file(
+        &mut self,
+        client: &Client,
+        table_name: &str,
+        names: Vec<String>,
+    ) -> Result<Vec<Option<String>>, Box<dyn StdError + Send + Sync>> {
+        let resolved: Vec<Option<String>> = match self.strategy {
+            Dedupe::None => names.into_iter().map(Some).collect(),
+            Dedupe::FirstWins => {
+                let seen = self
+                    .seen
+                    .seen(client, &names.iter().map(String::as_str).collect())
+                    .await?;
+                let mut in_file: HashSet<String> = HashSet::new();
+                names
+                    .into_iter()
+                    .map(|name| {
+                        if seen.contains(&name) || !in_file.insert(name.clone()) {
+                            None
+                        } else {
+                            Some(name)
+                        }
+                    })
+                    .collect()
+            }
+            Dedupe::LastWins => {
+                let seen = self
+                    .seen
+                    .seen(client, &names.iter().map(String::as_str).collect())
+                    .await?;
+                if !seen.is_empty() {
+                    let replaced: Vec<String> = seen.into_iter().collect();
+                    let deleted = client
+                        .execute(
+                            &format!(
+                                "DELETE FROM {} WHERE name = ANY($1)",
+                                sql::ident(table_name)?
+                            ),
+                            &[&replaced],
+                        )
+                        .await?;
+                    info!(
+                        "🧹 Replacing {} features loaded from earlier files",
+                        deleted
+                    );
+                    self.duplicates += replaced.len() as u64;
+                }
+                let mut last_index: HashMap<&str, usize> = HashMap::new();
+                for (idx, name) in names.iter().enumerate() {
+                    last_index.insert(name.as_str(), idx);
+                }
+                names
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, name)| {
+                        if last_index[name.as_str()] == idx {
+                            Some(name.clone())
+                        } else {
+                            None
+                        }
+                    })
+                    .collect()
+            }
+            Dedupe::Suffix => names
+                .into_iter()
+                .map(|name| {
+                    let count = self.suffix_counts.entry(name.clone()).or_insert(0);
+                    *count += 1;
+                    if *count == 1 {
+                        return Some(name);
+                    }
+                    self.duplicates += 1;
+                    // Skip suffixes that collide with names present in the data
+                    let mut suffix = *count;
+                    loop {
+                        let candidate = format!("{}_{}", name, suffix);
+                        if !self.suffix_counts.contains_key(&candidate) {
+                            self.suffix_counts.insert(candidate.clone(), 1);
+                            self.suffix_counts.insert(name.clone(), suffix);
+                            return Some(candidate);
+                        }
+                        suffix += 1;
+                    }
+                })
+                .collect(),
+        };
+
+        if matches!(self.strategy, Dedupe::FirstWins | Dedupe::LastWins) {
+            let dropped = resolved.iter().filter(|name| name.is_none()).count();
+            self.duplicates += dropped as u64;
+        }
+        Ok(resolved)
+    }
+
+    /// Records the names of a file whose upload completed.
+    pub(crate) async fn commit_file(
+        &mut self,
+        client: &Client,
+        loaded_names: Vec<String>,
+    ) -> Result<(), Box<dyn StdError + Send + Sync>> {
+        if matches!(self.strategy, Dedupe::FirstWins | Dedupe::LastWins) {
+            self.seen.insert(client, loaded_names).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+//! Running the write queries without changing the database, to review what
+//! they would do, like the statements of a plan file before it is run.
+//!
+//! With a `DryRun` set by `PostgresQueriesWrite::with_dry_run`, `execute`,
+//! `drop`, `drop_many`, `drop_all_tables`, `create_schema`, `drop_schema`,
+//! `truncate`, `rename_table`, `atomic_swap`, `delete_features`,
+//! `soft_delete_features`, `purge_deleted`, `update_properties`,
+//! `create_geo_table`, `grant_on_table`, `materialize_properties`,
+//! `create_simplified_table`, `create_overview_tables`, `refresh_overviews`,
+//! `add_derived_geometry_columns`, `analyze`, `vacuum` and
+//! `fix_collation_version` record the statements they would run instead of
+//! running them, and so do the `pre_drop` and `post_ingest` hooks.
+//! `insert_geojson` plans the `DROP` of a staging table left behind, `CREATE
+//! EXTENSION postgis` when it is missing, the table, its indexes and grants,
+//! the `COPY` of every file, without reading the features, and the swap of
+//! `LoadMode::Replace`. The reads, like whether a table exists or how many
+//! rows a filter matches, still run, so the counts those return are the rows
+//! they would change:
+//!
+//! ```no_run
+//! use postgres_connect_rust::{Config, DatabaseQueriesWrite, DryRun, PostgresQueriesWrite};
+//! use std::sync::Arc;
+//!
+//! # async fn review() -> Result<(), Box<dyn std::error::Error>> {
+//! let dry_run = Arc::new(DryRun::new());
+//! let write_queries = PostgresQueriesWrite::from_config(Config::load("files/config.toml")?)?
+//!     .with_dry_run(dry_run.clone());
+//! write_queries.truncate("parcels", true, false).await?;
+//! for statement in dry_run.statements() {
+//!     println!("{}", statement);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Parameters are left as `$1` placeholders, followed by a comment with their
+//! values. A dry run changes nothing, so it is not recorded in the audit log.
+
+use std::sync::Mutex;
+use tracing::info;
+
+/// The statements planned by the write queries of a dry run, in the order
+/// they would have run. Shared by the query structs it is set on.
+#[derive(Debug, Default)]
+pub struct DryRun {
+    statements: Mutex<Vec<String>>,
+}
+
+impl DryRun {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `statement` as the next one that would run.
+    pub(crate) fn plan(&self, statement: String) {
+        info!("🔍 Would run: {}", statement);
+        if let Ok(mut statements) = self.statements.lock() {
+            statements.push(statement);
+        }
+    }
+
+    /// The statements planned so far.
+    pub fn statements(&self) -> Vec<String> {
+        self.statements
+            .lock()
+            .map(|statements| statements.clone())
+            .unwrap_or_default()
+    }
+
+    /// The statements planned so far, forgetting them, to plan what comes
+    /// next on its own.
+    pub fn take(&self) -> Vec<String> {
+        self.statements
+            .lock()
+            .map(|mut statements| std::mem::take(&mut *statements))
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,193 @@
+use crate::read::config::{Hook, HooksConfig};
+use crate::read::db;
+use crate::utils::error::{ErrorKind, PgcError};
+use crate::utils::sql;
+use deadpool_postgres::{Object, Pool};
+use std::error::Error as StdError;
+use std::fmt;
+use tracing::{error, info, warn};
+
+/// When a group of hooks from the `[hooks]` config section runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum HookStage {
+    PostIngest,
+    PostRestore,
+    PreDrop,
+}
+
+impl fmt::Display for HookStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HookStage::PostIngest => write!(f, "post_ingest"),
+            HookStage::PostRestore => write!(f, "post_restore"),
+            HookStage::PreDrop => write!(f, "pre_drop"),
+        }
+    }
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Hook::Sql(snippet) => write!(f, "sql: {}", snippet),
+            Hook::Command(command) => write!(f, "command: {}", command),
+        }
+    }
+}
+
+/// `command` run by the shell of the platform: `sh -c`, or `cmd /C` on
+/// Windows.
+fn shell_command(command: &str) -> tokio::process::Command {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut shell_command = tokio::process::Command::new(shell);
+    shell_command.arg(flag).arg(command);
+    shell_command
+}
+
+/// The hooks of `hooks_config` for `stage`.
+fn hooks_of(hooks_config: &HooksConfig, stage: HookStage) -> &[Hook] {
+    match stage {
+        HookStage::PostIngest => &hooks_config.post_ingest,
+        HookStage::PostRestore => &hooks_config.post_restore,
+        HookStage::PreDrop => &hooks_config.pre_drop,
+    }
+}
+
+/// The SQL hook `snippet`, its `{database}` and `{table}` replaced.
+fn hook_query(snippet: &str, db_name: &str, table_name: Option<&str>) -> Result<String, PgcError> {
+    let mut query = snippet.replace("{database}", &sql::ident(db_name)?);
+    if let Some(table_name) = table_name {
+        query = query.replace("{table}", &sql::ident(table_name)?);
+    }
+    Ok(query)
+}
+
+/// What the hooks of `hooks_config` for `stage` would run, for a dry run:
+/// the SQL hooks as run, the commands as a comment.
+pub(crate) fn planned_hooks(
+    hooks_config: &HooksConfig,
+    db_name: &str,
+    stage: HookStage,
+    table_name: Option<&str>,
+) -> Result<Vec<String>, PgcError> {
+    hooks_of(hooks_config, stage)
+        .iter()
+        .map(|hook| match hook {
+            Hook::Sql(snippet) => hook_query(snippet, db_name, table_name),
+            Hook::Command(command) => Ok(format!("-- {} hook command: {}", stage, command)),
+        })
+        .collect()
+}
+
+/// Run the hooks of `hooks_config` for `stage`, in order.
+///
+/// SQL hooks share one connection of `pool`, to `db_name`, checked out when
+/// the first of them runs.
+/// `table_name` is the table the operation worked on, if any.
+///
+/// Returns the failures of the hooks that failed when `continue_on_hook_error`
+/// is set, so the caller can report them as warnings. Otherwise the first
+/// failing hook stops the remaining ones and is returned as the error, naming
+/// the stage and position of the hook.
+pub(crate) async fn run_hooks(
+    pool: &Pool,
+    hooks_config: &HooksConfig,
+    db_name: &str,
+    stage: HookStage,
+    table_name: Option<&str>,
+) -> Result<Vec<String>, Box<dyn StdError + Send + Sync>> {
+    let hooks = hooks_of(hooks_config, stage);
+    if hooks.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut client: Option<Object> = None;
+    let mut failures = Vec::new();
+    for (idx, hook) in hooks.iter().enumerate() {
+        info!(
+            "🪝 Running {} hook {}/{}: {}",
+            stage,
+            idx + 1,
+            hooks.len(),
+            hook
+        );
+        let result: Result<(), Box<dyn StdError + Send + Sync>> = match hook {
+            Hook::Sql(snippet) => {
+                let query = hook_query(snippet, db_name, table_name)?;
+                if client.is_none() {
+                    client = Some(db::checkout(pool).await?);
+                }
+                let client = client.as_ref().expect("hook client was just connected");
+                client.batch_execute(&query).await.map_err(Into::into)
+            }
+            Hook::Command(command) => {
+                let status = shell_command(command)
+                    .env("PGC_HOOK", stage.to_string())
+                    .env("PGC_DATABASE", db_name)
+                    .env("PGC_TABLE", table_name.unwrap_or(""))
+                    .status()
+                    .await;
+                match status {
+                    Ok(status) if status.success() => Ok(()),
+                    Ok(status) => {
+                        Err(ErrorKind::Subprocess.wrap(format!("exited with {}", status)))
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            }
+        };
+
+        if let Err(e) = result {
+            let failure = format!(
+                "{} hook {}/{} ({}) failed: {}",
+                stage,
+                idx + 1,
+                hooks.len(),
+                hook,
+                e
+            );
+            if !hooks_config.continue_on_hook_error {
+                error!("❌ {}", failure);
+                return Err(ErrorKind::of(e.as_ref()).wrap(format!("❌ {}", failure)));
+            }
+            warn!("⚠️ {}, continuing", failure);
+            failures.push(failure);
+        }
+    }
+    Ok(failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The program and arguments of `command`.
+    fn spawned(command: &tokio::process::Command) -> Vec<String> {
+        let command = command.as_std();
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn command_hooks_run_in_sh() {
+        assert_eq!(
+            spawned(&shell_command("vacuumdb --analyze \"$PGC_DATABASE\"")),
+            ["sh", "-c", "vacuumdb --analyze \"$PGC_DATABASE\""]
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn command_hooks_run_in_cmd() {
+        assert_eq!(
+            spawned(&shell_command("vacuumdb --analyze %PGC_DATABASE%")),
+            ["cmd", "/C", "vacuumdb --analyze %PGC_DATABASE%"]
+        );
+    }
+}
@@ -0,0 +1,489 @@
+use crate::read::db;
+use crate::utils::error::PgcError;
+use crate::utils::sql;
+use crate::write::backup::POSTGIS_TABLES;
+use crate::write::reports::{LogicalBackup, LogicalTable};
+use bytes::Bytes;
+use chrono::Local;
+use deadpool_postgres::Pool;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::{SinkExt, StreamExt};
+use std::error::Error as StdError;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read as _, Write};
+use std::path::Path;
+use tokio_postgres::{Error, IsolationLevel, Transaction};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+/// Statements recreating the tables, written in the backup directory.
+pub(crate) const SCHEMA_FILE: &str = "schema.sql";
+
+/// Description of the backup and its tables, see `LogicalBackup`.
+pub(crate) const MANIFEST_FILE: &str = "manifest.json";
+
+/// Line of `schema.sql` separating the statements run before the data is
+/// loaded (extensions, functions, tables) from those run after (constraints,
+/// indexes, triggers, sequence values), which are faster to build once.
+const POST_DATA_MARKER: &str = "-- pgc:post-data";
+
+/// Bytes read from a data file per chunk sent to `COPY`.
+const RESTORE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The statements recreating one table, by the stage they run in.
+#[derive(Default)]
+struct TableSchema {
+    functions: Vec<String>,
+    sequences: Vec<String>,
+    table: String,
+    constraints: Vec<String>,
+    foreign_keys: Vec<String>,
+    indexes: Vec<String>,
+    triggers: Vec<String>,
+    finalize: Vec<String>,
+    copy_columns: Vec<String>,
+}
+
+/// Reads the definition of a table from the catalog.
+///
+/// Covers what the tables of this crate use: column types with their
+/// modifiers (e.g. `geometry(Point,4326)`), defaults, identity, serial and
+/// generated columns, constraints, indexes, triggers with their functions,
+/// `UNLOGGED` and the table comment.
+async fn table_schema(
+    transaction: &Transaction<'_>,
+    table_name: &str,
+) -> Result<TableSchema, PgcError> {
+    let regclass = sql::ident(table_name)?;
+    let table = sql::ident(table_name)?;
+    let mut schema = TableSchema::default();
+
+    let columns = transaction
+        .query(
+            "SELECT a.attname::text, format_type(a.atttypid, a.atttypmod), a.attnotnull,
+                pg_get_expr(d.adbin, d.adrelid), a.attidentity::text, a.attgenerated::text,
+                pg_get_serial_sequence($1, a.attname)
+            FROM pg_attribute a
+            LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+            WHERE a.attrelid = to_regclass($1) AND a.attnum > 0 AND NOT a.attisdropped
+            ORDER BY a.attnum",
+            &[&regclass],
+        )
+        .await?;
+    let mut definitions = Vec::new();
+    for row in &columns {
+        let name: String = row.get(0);
+        let column_type: String = row.get(1);
+        let not_null: bool = row.get(2);
+        let default: Option<String> = row.get(3);
+        let identity: String = row.get(4);
+        let generated: String = row.get(5);
+        let sequence: Option<String> = row.get(6);
+
+        let mut definition = format!("{} {}", sql::ident(&name)?, column_type);
+        match (generated.as_str(), identity.as_str(), &default) {
+            ("s", _, Some(expression)) => {
+                definition.push_str(&format!(" GENERATED ALWAYS AS ({}) STORED", expression))
+            }
+            (_, "a", _) => definition.push_str(" GENERATED ALWAYS AS IDENTITY"),
+            (_, "d", _) => definition.push_str(" GENERATED BY DEFAULT AS IDENTITY"),
+            (_, _, Some(expression)) => definition.push_str(&format!(" DEFAULT {}", expression)),
+            _ => {}
+        }
+        if not_null {
+            definition.push_str(" NOT NULL");
+        }
+        definitions.push(definition);
+        // Generated columns are computed again on restore, COPY refuses them
+        if generated != "s" {
+            schema.copy_columns.push(name.clone());
+        }
+
+        if let Some(sequence) = sequence {
+            // Serial columns reference their sequence by name, identity columns own theirs
+            if identity.is_empty() {
+                schema
+                    .sequences
+                    .push(format!("CREATE SEQUENCE IF NOT EXISTS {};", sequence));
+                schema.finalize.push(format!(
+                    "ALTER SEQUENCE {} OWNED BY {}.{};",
+                    sequence,
+                    table,
+                    sql::ident(&name)?
+                ));
+            }
+            schema.finalize.push(format!(
+                "SELECT setval({}, COALESCE(MAX({column}), 0) + 1, false) FROM {};",
+                sql::literal(&sequence),
+                table,
+                column = sql::ident(&name)?
+            ));
+        }
+    }
+
+    let relation = transaction
+        .query_one(
+            "SELECT relpersistence::text, obj_description(oid, 'pg_class')
+            FROM pg_class WHERE oid = to_regclass($1)",
+            &[&regclass],
+        )
+        .await?;
+    let unlogged = relation.get::<_, String>(0) == "u";
+    schema.table = format!(
+        "CREATE {}TABLE {} (\n    {}\n);",
+        if unlogged { "UNLOGGED " } else { "" },
+        table,
+        definitions.join(",\n    ")
+    );
+    if let Some(comment) = relation.get::<_, Option<String>>(1) {
+        schema.finalize.push(format!(
+            "COMMENT ON TABLE {} IS {};",
+            table,
+            sql::literal(&comment)
+        ));
+    }
+
+    for row in transaction
+        .query(
+            "SELECT conname::text, pg_get_constraintdef(oid), contype = 'f'
+            FROM pg_constraint WHERE conrelid = to_regclass($1)
+            ORDER BY conname",
+            &[&regclass],
+        )
+        .await?
+    {
+        let statement = format!(
+            "ALTER TABLE {} ADD CONSTRAINT {} {};",
+            table,
+            sql::ident(row.get(0))?,
+            row.get::<_, String>(1)
+        );
+        if row.get(2) {
+            schema.foreign_keys.push(statement);
+        } else {
+            schema.constraints.push(statement);
+        }
+    }
+
+    // Indexes backing a constraint are created with it
+    for row in transaction
+        .query(
+            "SELECT pg_get_indexdef(i.indexrelid)
+            FROM pg_index i
+            WHERE i.indrelid = to_regclass($1)
+            AND NOT EXISTS (
+                SELECT 1 FROM pg_constraint c
+                WHERE c.conrelid = i.indrelid AND c.conindid = i.indexrelid
+                AND c.contype IN ('p', 'u', 'x')
+            )
+            ORDER BY i.indexrelid",
+            &[&regclass],
+        )
+        .await?
+    {
+        schema.indexes.push(format!("{};", row.get::<_, String>(0)));
+    }
+
+    for row in transaction
+        .query(
+            "SELECT pg_get_triggerdef(t.oid), pg_get_functiondef(t.tgfoid),
+                EXISTS (SELECT 1 FROM pg_depend d WHERE d.objid = t.tgfoid AND d.deptype = 'e')
+            FROM pg_trigger t
+            WHERE t.tgrelid = to_regclass($1) AND NOT t.tgisinternal
+            ORDER BY t.tgname",
+            &[&regclass],
+        )
+        .await?
+    {
+        schema
+            .triggers
+            .push(format!("{};", row.get::<_, String>(0)));
+        // Functions of an extension are recreated with it
+        if !row.get::<_, bool>(2) {
+            schema
+                .functions
+                .push(format!("{};", row.get::<_, String>(1)));
+        }
+    }
+    Ok(schema)
+}
+
+/// The whole `schema.sql` of a backup.
+fn schema_sql(extensions: &[String], tables: &[TableSchema]) -> Result<String, PgcError> {
+    let mut statements: Vec<String> = extensions
+        .iter()
+        .map(|extension| {
+            Ok(format!(
+                "CREATE EXTENSION IF NOT EXISTS {} CASCADE;",
+                sql::ident(extension)?
+            ))
+        })
+        .collect::<Result<_, PgcError>>()?;
+    let mut functions: Vec<&String> = Vec::new();
+    for function in tables.iter().flat_map(|table| &table.functions) {
+        if !functions.contains(&function) {
+            functions.push(function);
+        }
+    }
+    statements.extend(functions.into_iter().cloned());
+    statements.extend(tables.iter().flat_map(|table| table.sequences.clone()));
+    statements.extend(tables.iter().map(|table| table.table.clone()));
+    statements.push(POST_DATA_MARKER.to_string());
+    statements.extend(tables.iter().flat_map(|table| table.constraints.clone()));
+    statements.extend(tables.iter().flat_map(|table| table.indexes.clone()));
+    statements.extend(tables.iter().flat_map(|table| table.foreign_keys.clone()));
+    statements.extend(tables.iter().flat_map(|table| table.triggers.clone()));
+    statements.extend(tables.iter().flat_map(|table| table.finalize.clone()));
+    Ok(statements.join("\n\n") + "\n")
+}
+
+/// Name of the data file of a table, kept to safe characters.
+fn data_file_name(index: usize, table_name: &str) -> String {
+    let safe: String = table_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("data/{:04}_{}.csv.gz", index, safe)
+}
+
+/// Back up the tables of the current schema of `db_name`, the database of
+/// `pool`, without `pg_dump`.
+///
+/// Everything is read in one `REPEATABLE READ` transaction, so the backup is
+/// a consistent snapshot. The data of each table is streamed with
+/// `COPY ... TO STDOUT (FORMAT csv)` into a gzip file, and `schema.sql` is
+/// generated from the catalog. Once `cancellation` is cancelled, the
+/// backup stops before its next table, removing what it wrote.
+pub(crate) async fn backup(
+    pool: &Pool,
+    db_name: &str,
+    output_dir: &str,
+    cancellation: &CancellationToken,
+) -> Result<LogicalBackup, Box<dyn StdError + Send + Sync>> {
+    let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let backup_dir = Path::new(output_dir).join(format!("logical_{}_{}", db_name, timestamp));
+    std::fs::create_dir_all(backup_dir.join("data")).map_err(|e| PgcError::io(&backup_dir, e))?;
+
+    let mut client = db::checkout(pool).await?;
+    let transaction = client
+        .build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()
+        .await?;
+
+    let excluded: Vec<String> = POSTGIS_TABLES.iter().map(|t| t.to_string()).collect();
+    let table_names: Vec<String> = transaction
+        .query(
+            "SELECT relname::text FROM pg_class
+            WHERE relnamespace = current_schema()::regnamespace AND relkind = 'r'
+            AND NOT (relname = ANY($1))
+            ORDER BY relname",
+            &[&excluded],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    let extensions: Vec<String> = transaction
+        .query(
+            "SELECT extname::text FROM pg_extension WHERE extname <> 'plpgsql' ORDER BY oid",
+            &[],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    info!(
+        "🔄 Attempting a logical backup of {} tables of {} to {}",
+        table_names.len(),
+        db_name,
+        backup_dir.display()
+    );
+
+    let mut schemas = Vec::new();
+    let mut tables = Vec::new();
+    for (index, table_name) in table_names.iter().enumerate() {
+        if cancellation.is_cancelled() {
+            // Partial, it could pass for a backup of fewer tables
+            std::fs::remove_dir_all(&backup_dir)?;
+            info!("🗑️ Removed the partial backup {}", backup_dir.display());
+            return Err(Box::new(PgcError::Cancelled {
+                message: "🛑 The logical backup was interrupted".to_string(),
+            }));
+        }
+        let schema = table_schema(&transaction, table_name).await?;
+        let file = data_file_name(index, table_name);
+        let data_path = backup_dir.join(&file);
+        let mut encoder = GzEncoder::new(
+            BufWriter::new(File::create(&data_path).map_err(|e| PgcError::io(&data_path, e))?),
+            Compression::default(),
+        );
+        if !schema.copy_columns.is_empty() {
+            let columns: Vec<String> = schema
+                .copy_columns
+                .iter()
+                .map(|c| sql::ident(c))
+                .collect::<Result<_, _>>()?;
+            let stream = transaction
+                .copy_out(&format!(
+                    "COPY {} ({}) TO STDOUT (FORMAT csv)",
+                    sql::ident(table_name)?,
+                    columns.join(", ")
+                ))
+                .await?;
+            futures::pin_mut!(stream);
+            while let Some(chunk) = stream.next().await {
+                encoder.write_all(&chunk?)?;
+            }
+        }
+        encoder.finish()?.flush()?;
+        let rows: i64 = transaction
+            .query_one(
+                &format!("SELECT COUNT(*) FROM {}", sql::ident(table_name)?),
+                &[],
+            )
+            .await?
+            .get(0);
+        info!("✅ {}: {} rows", table_name, rows);
+        tables.push(LogicalTable {
+            name: table_name.clone(),
+            columns: schema.copy_columns.clone(),
+            file,
+            rows: rows as u64,
+        });
+        schemas.push(schema);
+    }
+    transaction.commit().await?;
+
+    std::fs::write(
+        backup_dir.join(SCHEMA_FILE),
+        schema_sql(&extensions, &schemas)?,
+    )?;
+    let backup = LogicalBackup {
+        path: backup_dir.display().to_string(),
+        db_name: db_name.to_string(),
+        created_at: timestamp,
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        tables,
+    };
+    serde_json::to_writer_pretty(
+        BufWriter::new(File::create(backup_dir.join(MANIFEST_FILE))?),
+        &backup,
+    )?;
+    info!(
+        "✅ Logical backup of {} written to {}",
+        backup.db_name, backup.path
+    );
+    Ok(backup)
+}
+
+/// Restore a backup made by `backup` into the database of `pool`.
+///
+/// Runs in one transaction: the tables are created, their data is loaded
+/// back with `COPY`, then constraints, indexes and triggers are created and
+/// sequences set past the restored values. The row count of every table is
+/// checked against the manifest. None of the tables may exist yet. Once
+/// `cancellation` is cancelled, the restore stops before its next table,
+/// rolled back.
+pub(crate) async fn restore(
+    pool: &Pool,
+    backup_dir: &str,
+    cancellation: &CancellationToken,
+) -> Result<LogicalBackup, Box<dyn StdError + Send + Sync>> {
+    let backup_path = Path::new(backup_dir);
+    let manifest_path = backup_path.join(MANIFEST_FILE);
+    let backup: LogicalBackup = serde_json::from_reader(BufReader::new(
+        File::open(&manifest_path).map_err(|e| PgcError::io(&manifest_path, e))?,
+    ))?;
+    let schema = std::fs::read_to_string(backup_path.join(SCHEMA_FILE))?;
+    let (pre_data, post_data) = schema.split_once(POST_DATA_MARKER).ok_or_else(|| {
+        PgcError::invalid_input(format!(
+            "❌ {} has no {} line",
+            SCHEMA_FILE, POST_DATA_MARKER
+        ))
+    })?;
+
+    let mut client = db::checkout(pool).await?;
+    let names: Vec<String> = backup
+        .tables
+        .iter()
+        .map(|t| sql::ident(&t.name))
+        .collect::<Result<_, _>>()?;
+    let existing: Vec<String> = client
+        .query(
+            "SELECT name FROM unnest($1::text[]) AS name WHERE to_regclass(name) IS NOT NULL",
+            &[&names],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    if !existing.is_empty() {
+        return Err(PgcError::usage(format!(
+            "❌ Cannot restore {}, these tables already exist: {}. Drop them first",
+            backup_dir,
+            existing.join(", ")
+        ))
+        .into());
+    }
+
+    info!(
+        "🔄 Attempting to restore {} tables from {}",
+        backup.tables.len(),
+        backup_dir
+    );
+    let transaction = client.transaction().await?;
+    transaction.batch_execute(pre_data).await?;
+    for table in &backup.tables {
+        // Dropping the transaction rolls back the tables restored so far
+        if cancellation.is_cancelled() {
+            return Err(Box::new(PgcError::Cancelled {
+                message: "🛑 The logical restore was interrupted, rolled back".to_string(),
+            }));
+        }
+        if table.columns.is_empty() {
+            continue;
+        }
+        let data_path = backup_path.join(&table.file);
+        let mut decoder = GzDecoder::new(BufReader::new(
+            File::open(&data_path).map_err(|e| PgcError::io(&data_path, e))?,
+        ));
+        let columns: Vec<String> = table
+            .columns
+            .iter()
+            .map(|c| sql::ident(c))
+            .collect::<Result<_, _>>()?;
+        let sink = transaction
+            .copy_in(&format!(
+                "COPY {} ({}) FROM STDIN (FORMAT csv)",
+                sql::ident(&table.name)?,
+                columns.join(", ")
+            ))
+            .await?;
+        futures::pin_mut!(sink);
+        let mut buffer = vec![0; RESTORE_CHUNK_SIZE];
+        loop {
+            let read = decoder.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            sink.send(Bytes::copy_from_slice(&buffer[..read])).await?;
+        }
+        let rows = sink.as_mut().finish().await?;
+        if rows != table.rows {
+            return Err(format!(
+                "❌ Restored {} rows into {}, but the backup has {}",
+                rows, table.name, table.rows
+            )
+            .into());
+        }
+        info!("✅ {}: {} rows", table.name, rows);
+    }
+    transaction.batch_execute(post_data).await?;
+    transaction.commit().await?;
+    info!("✅ Logical backup {} restored", backup_dir);
+    Ok(backup)
+}
@@ -0,0 +1,211 @@
+use crate::read::reports::ImportRecord;
+use crate::utils::error::PgcError;
+use crate::utils::sql;
+use sha2::{Digest, Sha256};
+use std::error::Error as StdError;
+use std::fs::File;
+use std::io::Read;
+use tokio_postgres::Client;
+
+/// Table every `insert_geojson` load is recorded in.
+pub(crate) const IMPORTS_TABLE: &str = "_imports";
+
+/// Hex SHA-256 digest of a file, read in chunks so large files are not
+/// loaded into memory.
+pub(crate) fn hash_file(path: &str) -> Result<String, Box<dyn StdError + Send + Sync>> {
+    let mut file = File::open(path).map_err(|e| PgcError::io(path, e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex_digest(hasher))
+}
+
+fn hex_digest(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Hashes and counts the bytes read through it, for streams that cannot be
+/// read twice like the files `hash_file` hashes.
+pub(crate) struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Bytes read and their hex SHA-256 digest.
+    pub(crate) fn finish(self) -> (u64, String) {
+        (self.bytes, hex_digest(self.hasher))
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buffer)?;
+        self.hasher.update(&buffer[..read]);
+        self.bytes += read as u64;
+        Ok(read)
+    }
+}
+
+/// Describes a file about to be loaded, before its features are counted.
+pub(crate) fn describe_file(
+    table_name: &str,
+    path: &str,
+    srid: Option<i32>,
+) -> Result<ImportRecord, Box<dyn StdError + Send + Sync>> {
+    let source_path = std::fs::canonicalize(path)
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| path.to_string());
+    Ok(ImportRecord {
+        table_name: table_name.to_string(),
+        source_path,
+        file_size: std::fs::metadata(path)?.len() as i64,
+        sha256: hash_file(path)?,
+        feature_count: 0,
+        srid,
+        duration_ms: 0,
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        imported_at: String::new(),
+    })
+}
+
+/// Describes a stream read into `table_name`, named `source` as it has no
+/// path, from its size and digest taken while reading it.
+pub(crate) fn describe_stream(
+    table_name: &str,
+    source: &str,
+    srid: Option<i32>,
+    file_size: u64,
+    sha256: String,
+) -> ImportRecord {
+    ImportRecord {
+        table_name: table_name.to_string(),
+        source_path: source.to_string(),
+        file_size: file_size as i64,
+        sha256,
+        feature_count: 0,
+        srid,
+        duration_ms: 0,
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        imported_at: String::new(),
+    }
+}
+
+/// Appends a load to the `_imports` table, creating it if needed, and stamps
+/// the same information as the comment of the loaded table.
+pub(crate) async fn record_import(
+    client: &Client,
+    record: &ImportRecord,
+) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    client
+        .batch_execute(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                id BIGSERIAL PRIMARY KEY,
+                table_name TEXT NOT NULL,
+                source_path TEXT NOT NULL,
+                file_size BIGINT NOT NULL,
+                sha256 TEXT NOT NULL,
+                feature_count BIGINT NOT NULL,
+                srid INTEGER,
+                duration_ms BIGINT NOT NULL,
+                crate_version TEXT NOT NULL,
+                imported_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+            sql::ident(IMPORTS_TABLE)?
+        ))
+        .await?;
+    let row = client
+        .query_one(
+            &format!(
+                "INSERT INTO {} (table_name, source_path, file_size, sha256, feature_count, srid, duration_ms, crate_version)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                RETURNING imported_at::text",
+                sql::ident(IMPORTS_TABLE)?
+            ),
+            &[
+                &record.table_name,
+                &record.source_path,
+                &record.file_size,
+                &record.sha256,
+                &record.feature_count,
+                &record.srid,
+                &record.duration_ms,
+                &record.crate_version,
+            ],
+        )
+        .await?;
+    let imported_at: String = row.get(0);
+
+    let comment = format!(
+        "Imported from {} (sha256 {}, {} bytes, {} features, SRID {}) in {} ms at {} by postgres-connect-rust {}",
+        record.source_path,
+        record.sha256,
+        record.file_size,
+        record.feature_count,
+        record
+            .srid
+            .map(|srid| srid.to_string())
+            .unwrap_or_else(|| "unset".to_string()),
+        record.duration_ms,
+        imported_at,
+        record.crate_version
+    );
+    client
+        .batch_execute(&format!(
+            "COMMENT ON TABLE {} IS {}",
+            sql::ident(&record.table_name)?,
+            sql::literal(&comment)
+        ))
+        .await?;
+    Ok(())
+}
+
+/// SHA-256 of the last recorded import of `source_path` into `table_name`,
+/// or `None` if it was never recorded. `source_path` is canonicalized the
+/// same way `describe_file` does.
+pub(crate) async fn last_import_hash(
+    client: &Client,
+    table_name: &str,
+    source_path: &str,
+) -> Result<Option<String>, Box<dyn StdError + Send + Sync>> {
+    let exists: bool = client
+        .query_one(
+            "SELECT to_regclass($1) IS NOT NULL",
+            &[&sql::ident(IMPORTS_TABLE)?],
+        )
+        .await?
+        .get(0);
+    if !exists {
+        return Ok(None);
+    }
+    let row = client
+        .query_opt(
+            &format!(
+                "SELECT sha256 FROM {} WHERE table_name = $1 AND source_path = $2
+                ORDER BY imported_at DESC, id DESC LIMIT 1",
+                sql::ident(IMPORTS_TABLE)?
+            ),
+            &[&table_name, &source_path],
+        )
+        .await?;
+    Ok(row.map(|row| row.get(0)))
+}
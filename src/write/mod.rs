@@ -1,3 +1,18 @@
 use crate::read::queries::PostgresQueriesRead;
-pub mod queries;
-pub mod utils;
\ No newline at end of file
+#[cfg(feature = "backup")]
+pub(crate) mod backup;
+pub(crate) mod dedupe;
+pub(crate) mod dry_run;
+pub(crate) mod hooks;
+#[cfg(feature = "backup")]
+pub(crate) mod logical;
+pub(crate) mod manifest;
+pub(crate) mod options;
+pub(crate) mod plan;
+pub(crate) mod queries;
+pub(crate) mod reports;
+pub(crate) mod source;
+pub(crate) mod utils;
+pub(crate) mod validate;
+#[cfg(feature = "ingest")]
+pub(crate) mod watch;
@@ -0,0 +1,1245 @@
+use crate::utils::jobs;
+use crate::utils::progress::{ObserverHandle, ProgressObserver};
+use crate::utils::sql::PgType;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// PostGIS geometry subtypes a geo table column can be constrained to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GeometryType {
+    Geometry,
+    Point,
+    MultiPoint,
+    LineString,
+    MultiLineString,
+    Polygon,
+    MultiPolygon,
+    GeometryCollection,
+}
+
+impl fmt::Display for GeometryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GeometryType::Geometry => "Geometry",
+            GeometryType::Point => "Point",
+            GeometryType::MultiPoint => "MultiPoint",
+            GeometryType::LineString => "LineString",
+            GeometryType::MultiLineString => "MultiLineString",
+            GeometryType::Polygon => "Polygon",
+            GeometryType::MultiPolygon => "MultiPolygon",
+            GeometryType::GeometryCollection => "GeometryCollection",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Kind of the spatial column of a geo table.
+///
+/// * `Geometry`: planar coordinates in any SRID.
+/// * `Geography`: longitude/latitude on the WGS 84 spheroid (SRID 4326), so
+///   distances, lengths and areas are in meters.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ColumnKind {
+    Geometry,
+    Geography,
+}
+
+impl fmt::Display for ColumnKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnKind::Geometry => write!(f, "geometry"),
+            ColumnKind::Geography => write!(f, "geography"),
+        }
+    }
+}
+
+/// Primary key of a geo table.
+///
+/// * `Uuid`: an `id UUID` column filled by `gen_random_uuid()`, `name` being `UNIQUE`.
+/// * `NameAsPk`: `name` itself is the primary key, there is no `id` column.
+/// * `SerialId`: an `id BIGINT` identity column, `name` being `UNIQUE`.
+/// * `Property(key, type)`: the value of property `key`, loaded into a column of
+///   that name and type during ingest, `name` being `UNIQUE`. Features missing the
+///   property cannot be loaded. Not usable with `explode_multi`, whose rows share
+///   their feature's properties.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PrimaryKey {
+    Uuid,
+    NameAsPk,
+    SerialId,
+    Property(String, PgType),
+}
+
+impl fmt::Display for PrimaryKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrimaryKey::Uuid => write!(f, "id UUID"),
+            PrimaryKey::NameAsPk => write!(f, "name"),
+            PrimaryKey::SerialId => write!(f, "id BIGINT identity"),
+            PrimaryKey::Property(key, pg_type) => write!(f, "{} {}", key, pg_type.sql_name()),
+        }
+    }
+}
+
+impl PrimaryKey {
+    /// The property loaded into the primary key column, with its type.
+    pub fn property_column(&self) -> Option<(&str, PgType)> {
+        match self {
+            PrimaryKey::Property(key, pg_type) => Some((key.as_str(), *pg_type)),
+            _ => None,
+        }
+    }
+}
+
+/// The only SRID a geography column accepts.
+pub(crate) const GEOGRAPHY_SRID: i32 = 4326;
+
+/// Options controlling the table `create_geo_table` creates.
+///
+/// The defaults reproduce the original table layout: an unconstrained
+/// `GEOMETRY` column, `name VARCHAR(512)` and a GIN index on `properties`.
+///
+/// * `column_kind`: see `ColumnKind`. The column is named `geometry` either way.
+/// * `geometry_type`: constrain the geometry column, e.g. `GEOMETRY(MultiPolygon, 4326)`.
+/// * `srid`: SRID of the geometry column. Uploaded geometries are tagged with it.
+///   A geography column is always 4326.
+/// * `name_column_length`: maximum length of `name`; `None` makes it `TEXT`.
+/// * `primary_key`: see `PrimaryKey`.
+/// * `with_gin_properties_index`: create a GIN index on `properties`.
+/// * `with_gist_geometry_index`: create a GiST index on `geometry`.
+/// * `unlogged`: create an `UNLOGGED` table, which is much faster to load but
+///   not crash-safe. Meant for staging tables.
+/// * `with_bbox_column`: add a `bbox` polygon column holding the bounding box of
+///   `geometry`, computed by the database.
+/// * `with_centroid_column`: add a `centroid` point column holding
+///   `ST_PointOnSurface(geometry)`, computed by the database.
+/// * `with_updated_at`: add an `updated_at` column, set on insert and bumped by a
+///   trigger whenever an update changes `properties` or `geometry`. Also added to
+///   an existing table.
+/// * `with_deleted_at`: add a `deleted_at` column, enabling `soft_delete_features`.
+///   Also added to an existing table.
+/// * `grants`: `(role, privileges)` pairs granted on the table once it is created,
+///   e.g. `("api_reader", "SELECT")`. `None` applies the `grants` of the
+///   `[table_defaults]` config section, `Some(vec![])` grants nothing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeoTableOptions {
+    pub column_kind: ColumnKind,
+    pub geometry_type: Option<GeometryType>,
+    pub srid: Option<i32>,
+    pub name_column_length: Option<u32>,
+    pub primary_key: PrimaryKey,
+    pub with_gin_properties_index: bool,
+    pub with_gist_geometry_index: bool,
+    pub unlogged: bool,
+    pub with_bbox_column: bool,
+    pub with_centroid_column: bool,
+    pub with_updated_at: bool,
+    pub with_deleted_at: bool,
+    pub grants: Option<Vec<(String, String)>>,
+}
+
+impl Default for GeoTableOptions {
+    fn default() -> Self {
+        Self {
+            column_kind: ColumnKind::Geometry,
+            geometry_type: None,
+            srid: None,
+            name_column_length: Some(512),
+            primary_key: PrimaryKey::Uuid,
+            with_gin_properties_index: true,
+            with_gist_geometry_index: false,
+            unlogged: false,
+            with_bbox_column: false,
+            with_centroid_column: false,
+            with_updated_at: false,
+            with_deleted_at: false,
+            grants: None,
+        }
+    }
+}
+
+impl GeoTableOptions {
+    /// Checks the options against what the column kind accepts, before anything is created.
+    pub fn validate(&self) -> Result<(), String> {
+        if let PrimaryKey::Property(key, _) = &self.primary_key {
+            if [
+                "name",
+                "properties",
+                "geometry",
+                "created_at",
+                "bbox",
+                "centroid",
+            ]
+            .contains(&key.as_str())
+            {
+                return Err(format!(
+                    "❌ Property '{}' cannot be the primary key, a column of that name already exists. Use PrimaryKey::NameAsPk for name",
+                    key
+                ));
+            }
+        }
+        if self.column_kind == ColumnKind::Geography {
+            if let Some(srid) = self.srid.filter(|srid| *srid != GEOGRAPHY_SRID) {
+                return Err(format!(
+                    "❌ A geography column only accepts SRID {}, got {}. Reproject the data or use ColumnKind::Geometry",
+                    GEOGRAPHY_SRID, srid
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The SRID uploaded geometries are tagged with. Geography is always 4326.
+    pub fn effective_srid(&self) -> Option<i32> {
+        match self.column_kind {
+            ColumnKind::Geometry => self.srid,
+            ColumnKind::Geography => Some(GEOGRAPHY_SRID),
+        }
+    }
+
+    /// SQL type of the geometry column, e.g. `GEOMETRY(MultiPolygon, 4326)`
+    /// or `GEOGRAPHY(Point, 4326)`.
+    pub fn geometry_column_type(&self) -> String {
+        if self.column_kind == ColumnKind::Geography {
+            return format!(
+                "GEOGRAPHY({}, {})",
+                self.geometry_type.unwrap_or(GeometryType::Geometry),
+                GEOGRAPHY_SRID
+            );
+        }
+        match (self.geometry_type, self.srid) {
+            (Some(geometry_type), Some(srid)) => format!("GEOMETRY({}, {})", geometry_type, srid),
+            (None, Some(srid)) => format!("GEOMETRY(Geometry, {})", srid),
+            (Some(geometry_type), None) => format!("GEOMETRY({})", geometry_type),
+            (None, None) => "GEOMETRY".to_string(),
+        }
+    }
+
+    /// SQL type of the bbox column, e.g. `GEOMETRY(Polygon, 4326)`.
+    pub fn bbox_column_type(&self) -> String {
+        match self.effective_srid() {
+            Some(srid) => format!("GEOMETRY(Polygon, {})", srid),
+            None => "GEOMETRY(Polygon)".to_string(),
+        }
+    }
+
+    /// SQL type of the centroid column, e.g. `GEOMETRY(Point, 4326)`.
+    pub fn centroid_column_type(&self) -> String {
+        match self.effective_srid() {
+            Some(srid) => format!("GEOMETRY(Point, {})", srid),
+            None => "GEOMETRY(Point)".to_string(),
+        }
+    }
+
+    /// SQL type of the name column, e.g. `VARCHAR(512)` or `TEXT`.
+    pub fn name_column_type(&self) -> String {
+        match self.name_column_length {
+            Some(length) => format!("VARCHAR({})", length),
+            None => "TEXT".to_string(),
+        }
+    }
+}
+
+/// What to do when a single feature cannot be converted for upload.
+///
+/// * `Abort`: stop the ingest and return the error. Nothing from the file is loaded.
+/// * `Skip`: leave the feature out, record it in the `.errors.jsonl` sidecar
+///   file next to the input, or in the warnings of the report when it is a
+///   stream, and carry on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OnFeatureError {
+    Abort,
+    Skip,
+}
+
+/// How features sharing the same name are handled during an ingest.
+///
+/// Duplicates are detected within a file and across all files of a directory
+/// ingest, which would otherwise break the `UNIQUE` constraint on `name`.
+///
+/// * `None`: load everything as is.
+/// * `FirstWins`: keep the first feature with a given name, drop later ones.
+/// * `LastWins`: keep the last feature with a given name, replacing earlier ones.
+/// * `Suffix`: keep all of them, renaming duplicates to `name_2`, `name_3`, …
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Dedupe {
+    None,
+    FirstWins,
+    LastWins,
+    Suffix,
+}
+
+/// How features are sent to the database.
+///
+/// * `Copy`: stream them with `COPY ... FROM STDIN`. The fastest way, and the
+///   whole file is loaded or nothing is.
+/// * `Batched`: multi-row `INSERT ... VALUES` statements with bind parameters,
+///   for servers and poolers that break `COPY FROM STDIN` (e.g. pgbouncer in
+///   transaction pooling mode). Slower, and a failing file keeps the batches
+///   already committed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InsertStrategy {
+    Copy,
+    Batched,
+}
+
+/// An axis-aligned box, in the coordinates of the data.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl BoundingBox {
+    /// Checks the box is well formed.
+    ///
+    /// A box whose `min_x` is greater than its `max_x` would cross the
+    /// antimeridian, which is not supported: split it into two boxes and
+    /// load twice instead.
+    pub fn validate(&self) -> Result<(), String> {
+        let corners = [self.min_x, self.min_y, self.max_x, self.max_y];
+        if corners.iter().any(|c| !c.is_finite()) {
+            return Err(format!(
+                "❌ Invalid bounding box {:?}: coordinates must be finite",
+                corners
+            ));
+        }
+        if self.min_x > self.max_x {
+            return Err(format!(
+                "❌ Bounding box {:?} has min_x > max_x. Boxes crossing the antimeridian are not supported, split it in two",
+                corners
+            ));
+        }
+        if self.min_y > self.max_y {
+            return Err(format!(
+                "❌ Invalid bounding box {:?}: min_y > max_y",
+                corners
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the two boxes share at least one point.
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min_x <= other.max_x
+            && other.min_x <= self.max_x
+            && self.min_y <= other.max_y
+            && other.min_y <= self.max_y
+    }
+
+    /// Grows the box to contain a position.
+    pub fn extend(&mut self, x: f64, y: f64) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+}
+
+/// Options controlling how `insert_geojson` loads features.
+///
+/// * `analyze`: run `ANALYZE` on the table after a successful load.
+/// * `on_feature_error`: see `OnFeatureError`.
+/// * `max_skip_ratio`: with `OnFeatureError::Skip`, fail the load (and roll
+///   back its COPY) if more than this fraction of the features was skipped.
+/// * `dedupe`: see `Dedupe`.
+/// * `insert_strategy`: see `InsertStrategy`.
+/// * `batch_size`: features per `INSERT` statement with `InsertStrategy::Batched`.
+///   Capped so a statement stays under the 65535 bind parameter limit.
+/// * `commit_every`: with `InsertStrategy::Batched`, commit after this many statements.
+/// * `max_retries`: with `InsertStrategy::Batched`, how many times a batch failing with
+///   a transient error (broken connection, serialization failure, cancelled statement)
+///   is retried on a new connection, resuming from the last commit. Other errors are
+///   never retried. A COPY is all or nothing, and is not retried.
+/// * `retry_backoff_ms`: delay before the first retry, doubled on every further attempt.
+/// * `flatten_properties`: turn nested property objects into dotted keys,
+///   e.g. `admin.name_en`. Arrays are left intact.
+/// * `normalize_keys`: lowercase property keys and replace spaces with underscores.
+///   Keys that collide afterwards are reported, and the first one is kept.
+/// * `record_import`: record every loaded file (path, size, SHA-256, feature count, ...)
+///   in the `_imports` table and as the comment of the table, see `import_history`.
+/// * `skip_unchanged`: skip files whose SHA-256 matches their last recorded import
+///   into the same table. Files are still loaded if the table no longer exists.
+/// * `force`: load every file even with `skip_unchanged`.
+/// * `explode_multi`: load each member of a MultiPoint, MultiLineString or MultiPolygon
+///   as its own row, named `name_1`, `name_2`, … and with the feature's properties.
+///   Allows loading into a `Point`, `LineString` or `Polygon` constrained column.
+/// * `coordinate_precision`: round coordinates to this many decimals, e.g. 6 (~10cm),
+///   removing the consecutive duplicate vertices that rounding produces.
+/// * `parallel_copy`: with `InsertStrategy::Copy`, split each file round-robin across
+///   this many pooled connections, each COPYing into its own `UNLOGGED` staging table.
+///   The staging tables are merged into the table in one transaction and the row count
+///   is verified. Worth it for huge files, where one COPY is bound by one server core.
+///   `1` uses a single COPY. Defaults to `--jobs` or the `jobs` key of [config], and
+///   to `1` without them.
+/// * `clip_bbox`: only load features whose bounding box, computed from their coordinates,
+///   intersects this box. Features without geometry are left out as well.
+/// * `clip_exact`: with `clip_bbox`, also cut the loaded geometries to the box with
+///   `ST_Intersection`, through a staging table. Features with nothing left inside the box
+///   are dropped. Requires `InsertStrategy::Copy`.
+/// * `refresh_overviews`: refresh the overviews of the table made by `create_overview_tables`
+///   after a load that changed it.
+/// * `name_property`: name the features after this property, e.g. `GID_2`, instead of their
+///   id. Those without it are named after their id, or `unknown_<index>` without one.
+/// * `observer`: told how far the load is, see `ProgressObserver`. Not serialized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IngestOptions {
+    pub analyze: bool,
+    pub on_feature_error: OnFeatureError,
+    pub max_skip_ratio: f64,
+    pub dedupe: Dedupe,
+    pub insert_strategy: InsertStrategy,
+    pub batch_size: usize,
+    pub commit_every: usize,
+    pub max_retries: u32,
+    pub retry_backoff_ms: u64,
+    pub flatten_properties: bool,
+    pub normalize_keys: bool,
+    pub record_import: bool,
+    pub skip_unchanged: bool,
+    pub force: bool,
+    pub explode_multi: bool,
+    pub coordinate_precision: Option<u8>,
+    pub parallel_copy: usize,
+    pub clip_bbox: Option<BoundingBox>,
+    pub clip_exact: bool,
+    pub refresh_overviews: bool,
+    pub name_property: Option<String>,
+    #[serde(skip)]
+    pub observer: Option<ObserverHandle>,
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        Self {
+            analyze: true,
+            on_feature_error: OnFeatureError::Abort,
+            max_skip_ratio: 0.01,
+            dedupe: Dedupe::None,
+            insert_strategy: InsertStrategy::Copy,
+            batch_size: 1000,
+            commit_every: 10,
+            max_retries: 3,
+            retry_backoff_ms: 500,
+            flatten_properties: false,
+            normalize_keys: false,
+            record_import: true,
+            skip_unchanged: false,
+            force: false,
+            explode_multi: false,
+            coordinate_precision: None,
+            parallel_copy: jobs::configured_jobs().unwrap_or(1),
+            clip_bbox: None,
+            clip_exact: false,
+            refresh_overviews: false,
+            name_property: None,
+            observer: None,
+        }
+    }
+}
+
+impl IngestOptions {
+    /// Checks the options are consistent, before anything is loaded.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(clip_bbox) = &self.clip_bbox {
+            clip_bbox.validate()?;
+        }
+        if self.parallel_copy < 1 {
+            return Err("❌ parallel_copy must be at least 1".to_string());
+        }
+        if self.clip_exact && self.insert_strategy != InsertStrategy::Copy {
+            return Err("❌ clip_exact requires InsertStrategy::Copy".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// How `insert_geojson` treats the table it loads into.
+///
+/// * `Append`: load into the table as it is, creating it if missing.
+/// * `Replace`: load into a staging table, swapped in for the table once
+///   every file is loaded, so it only holds what is loaded. A load that
+///   fails leaves the table as it was.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadMode {
+    #[default]
+    Append,
+    Replace,
+}
+
+/// Options of `insert_geojson`, built with `InsertGeoJsonOptions::new()`.
+/// Every field defaults to loading into a table named after the file, as
+/// it is, with the default `GeoTableOptions` and `IngestOptions`.
+///
+/// * `table`: table the features are loaded into. `None` names it after the
+///   file or directory.
+/// * `mode`: see `LoadMode`.
+/// * `table_options`: the table created when it does not exist, see
+///   `GeoTableOptions`.
+/// * `ingest_options`: how the features are loaded, see `IngestOptions`.
+///
+/// ```
+/// use postgres_connect_rust::{InsertGeoJsonOptions, LoadMode};
+///
+/// let options = InsertGeoJsonOptions::new()
+///     .table("geo_data")
+///     .mode(LoadMode::Replace)
+///     .srid(4326)
+///     .name_from_property("GID_2")
+///     .build();
+/// assert_eq!(options.table.as_deref(), Some("geo_data"));
+/// assert_eq!(options.table_options.srid, Some(4326));
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InsertGeoJsonOptions {
+    pub table: Option<String>,
+    pub mode: LoadMode,
+    pub table_options: GeoTableOptions,
+    pub ingest_options: IngestOptions,
+}
+
+impl InsertGeoJsonOptions {
+    /// A builder of the options, starting from the defaults.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> InsertGeoJsonOptionsBuilder {
+        InsertGeoJsonOptionsBuilder::default()
+    }
+}
+
+/// Sets the fields of `InsertGeoJsonOptions` one by one, see
+/// `InsertGeoJsonOptions::new`.
+#[derive(Debug, Clone, Default)]
+pub struct InsertGeoJsonOptionsBuilder {
+    options: InsertGeoJsonOptions,
+}
+
+impl InsertGeoJsonOptionsBuilder {
+    pub fn table(mut self, table: impl Into<String>) -> Self {
+        self.options.table = Some(table.into());
+        self
+    }
+
+    pub fn mode(mut self, mode: LoadMode) -> Self {
+        self.options.mode = mode;
+        self
+    }
+
+    /// All the options of the table, replacing those set before.
+    pub fn table_options(mut self, table_options: GeoTableOptions) -> Self {
+        self.options.table_options = table_options;
+        self
+    }
+
+    /// All the options of the load, replacing those set before.
+    pub fn ingest_options(mut self, ingest_options: IngestOptions) -> Self {
+        self.options.ingest_options = ingest_options;
+        self
+    }
+
+    pub fn srid(mut self, srid: i32) -> Self {
+        self.options.table_options.srid = Some(srid);
+        self
+    }
+
+    pub fn geometry_type(mut self, geometry_type: GeometryType) -> Self {
+        self.options.table_options.geometry_type = Some(geometry_type);
+        self
+    }
+
+    pub fn primary_key(mut self, primary_key: PrimaryKey) -> Self {
+        self.options.table_options.primary_key = primary_key;
+        self
+    }
+
+    /// Create a GiST index on `geometry`, and a GIN index on `properties`,
+    /// or not.
+    pub fn indexes(mut self, gist_geometry: bool, gin_properties: bool) -> Self {
+        self.options.table_options.with_gist_geometry_index = gist_geometry;
+        self.options.table_options.with_gin_properties_index = gin_properties;
+        self
+    }
+
+    /// Name the features after `property`, see `IngestOptions::name_property`.
+    pub fn name_from_property(mut self, property: impl Into<String>) -> Self {
+        self.options.ingest_options.name_property = Some(property.into());
+        self
+    }
+
+    /// Tell `observer` how far the load is, see `IngestOptions::observer`.
+    pub fn observer(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.options.ingest_options.observer = Some(ObserverHandle::new(observer));
+        self
+    }
+
+    pub fn dedupe(mut self, dedupe: Dedupe) -> Self {
+        self.options.ingest_options.dedupe = dedupe;
+        self
+    }
+
+    pub fn insert_strategy(mut self, insert_strategy: InsertStrategy) -> Self {
+        self.options.ingest_options.insert_strategy = insert_strategy;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.options.ingest_options.batch_size = batch_size;
+        self
+    }
+
+    pub fn on_feature_error(mut self, on_feature_error: OnFeatureError) -> Self {
+        self.options.ingest_options.on_feature_error = on_feature_error;
+        self
+    }
+
+    pub fn analyze(mut self, analyze: bool) -> Self {
+        self.options.ingest_options.analyze = analyze;
+        self
+    }
+
+    pub fn skip_unchanged(mut self, skip_unchanged: bool) -> Self {
+        self.options.ingest_options.skip_unchanged = skip_unchanged;
+        self
+    }
+
+    pub fn build(self) -> InsertGeoJsonOptions {
+        self.options
+    }
+}
+
+/// Options for `watch_directory`.
+///
+/// * `table_name`: table every file is loaded into. `None` loads each file into
+///   a table named after it, as `insert_geojson` does.
+/// * `extensions`: extensions of the files picked up, compared case insensitively.
+///   Defaults to `geojson` and `json`.
+/// * `settle_time`: how long a file must keep the same size and modification
+///   time before it is loaded, so files still being written or synced are not
+///   loaded half way. Defaults to 2 seconds.
+/// * `poll_interval`: how often the waiting files are checked. Defaults to 500ms.
+/// * `failed_dir`: where files that fail to load are moved, so they are not
+///   retried on every change. Relative to the watched directory, which is not
+///   watched below it. Defaults to `failed`.
+/// * `load_existing`: also load the files already in the directory on start.
+///   With `skip_unchanged`, those loaded earlier are skipped.
+/// * `table_options`, `ingest_options`: passed to `insert_geojson` for every file.
+///   `skip_unchanged` is on by default, so touching a file does not reload it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchOptions {
+    pub table_name: Option<String>,
+    pub extensions: Vec<String>,
+    pub settle_time: Duration,
+    pub poll_interval: Duration,
+    pub failed_dir: String,
+    pub load_existing: bool,
+    pub table_options: GeoTableOptions,
+    pub ingest_options: IngestOptions,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            table_name: None,
+            extensions: vec!["geojson".to_string(), "json".to_string()],
+            settle_time: Duration::from_secs(2),
+            poll_interval: Duration::from_millis(500),
+            failed_dir: "failed".to_string(),
+            load_existing: false,
+            table_options: GeoTableOptions::default(),
+            ingest_options: IngestOptions {
+                skip_unchanged: true,
+                ..IngestOptions::default()
+            },
+        }
+    }
+}
+
+/// Options of `export_geojson` and `export_csv`.
+///
+/// * `bbox`: only export the features intersecting this box, in
+///   longitude/latitude.
+/// * `filter`: only export the rows this SQL condition holds for, e.g.
+///   `properties->>'kind' = 'park'`. It is run as is.
+/// * `simplify`: simplify the geometries with this tolerance, in the units
+///   of their SRID, keeping their topology.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportOptions {
+    pub bbox: Option<BoundingBox>,
+    pub filter: Option<String>,
+    pub simplify: Option<f64>,
+}
+
+impl ExportOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(bbox) = &self.bbox {
+            bbox.validate()?;
+        }
+        match self.simplify {
+            Some(tolerance) if !tolerance.is_finite() || tolerance < 0.0 => Err(format!(
+                "❌ Invalid simplify tolerance {}: must be 0 or more",
+                tolerance
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How `insert_raster` treats the target table.
+///
+/// * `Create`: create the table, failing if it exists (`raster2pgsql -c`).
+/// * `Append`: add the tiles to an existing table (`-a`).
+/// * `Replace`: drop the table and create it again (`-d`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RasterMode {
+    Create,
+    Append,
+    Replace,
+}
+
+/// Options for `insert_raster`, mapped to `raster2pgsql` flags.
+///
+/// * `mode`: see `RasterMode`.
+/// * `tile_size`: cut the raster into tiles of `(width, height)` pixels (`-t`).
+///   `None` loads each file as a single row, only sensible for small rasters.
+/// * `srid`: SRID of the raster (`-s`). `None` reads it from the file.
+/// * `column`: name of the raster column (`-f`), `rast` by default.
+/// * `with_constraints`: add the raster constraints (`-C`), which is what fills
+///   in `raster_columns` with the SRID, scale, block size and band count.
+/// * `with_index`: create a GiST index on the convex hull of the tiles (`-I`).
+/// * `pad_tiles`: pad the right and bottom tiles so all tiles have the same size
+///   (`-P`), required for the regular blocking constraint.
+/// * `with_filename`: add a `filename` column with the source file of each tile (`-F`).
+/// * `vacuum_analyze`: vacuum and analyze the table afterwards (`-M`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RasterOptions {
+    pub mode: RasterMode,
+    pub tile_size: Option<(u32, u32)>,
+    pub srid: Option<i32>,
+    pub column: Option<String>,
+    pub with_constraints: bool,
+    pub with_index: bool,
+    pub pad_tiles: bool,
+    pub with_filename: bool,
+    pub vacuum_analyze: bool,
+}
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        Self {
+            mode: RasterMode::Create,
+            tile_size: Some((256, 256)),
+            srid: None,
+            column: None,
+            with_constraints: true,
+            with_index: true,
+            pad_tiles: false,
+            with_filename: false,
+            vacuum_analyze: false,
+        }
+    }
+}
+
+impl RasterOptions {
+    /// The `raster2pgsql` arguments preceding the input files and the table name.
+    pub fn raster2pgsql_args(&self) -> Vec<String> {
+        let mut args = vec![match self.mode {
+            RasterMode::Create => "-c".to_string(),
+            RasterMode::Append => "-a".to_string(),
+            RasterMode::Replace => "-d".to_string(),
+        }];
+        if let Some((width, height)) = self.tile_size {
+            args.push("-t".to_string());
+            args.push(format!("{}x{}", width, height));
+        }
+        if let Some(srid) = self.srid {
+            args.push("-s".to_string());
+            args.push(srid.to_string());
+        }
+        if let Some(column) = &self.column {
+            args.push("-f".to_string());
+            args.push(column.clone());
+        }
+        for (enabled, flag) in [
+            (self.with_constraints, "-C"),
+            (self.with_index, "-I"),
+            (self.pad_tiles, "-P"),
+            (self.with_filename, "-F"),
+            (self.vacuum_analyze, "-M"),
+        ] {
+            if enabled {
+                args.push(flag.to_string());
+            }
+        }
+        // Quote identifiers, so the table and column keep their case
+        args.push("-q".to_string());
+        args
+    }
+}
+
+/// Output format of `backup_database`, see `pg_dump --format`.
+///
+/// * `Custom`: a single compressed `.dump` file. Dumped by one process only.
+/// * `Directory`: a `.dir` directory with one file per table, the only format
+///   `pg_dump` can dump with several jobs.
+/// * `Plain`: a `.sql` script, restored with `psql` rather than `restore_database`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DumpFormat {
+    Custom,
+    Directory,
+    Plain,
+}
+
+impl DumpFormat {
+    /// The `pg_dump --format` value.
+    pub fn pg_dump_name(&self) -> &'static str {
+        match self {
+            DumpFormat::Custom => "custom",
+            DumpFormat::Directory => "directory",
+            DumpFormat::Plain => "plain",
+        }
+    }
+
+    /// Extension of the dump written in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DumpFormat::Custom => "dump",
+            DumpFormat::Directory => "dir",
+            DumpFormat::Plain => "sql",
+        }
+    }
+}
+
+/// Compression method of a dump, see `pg_dump --compress`.
+///
+/// * `Gzip`: the default of custom and directory dumps.
+/// * `Lz4`, `Zstd`: need `pg_dump` 16 or newer.
+/// * `None`: no compression.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CompressionMethod {
+    Gzip,
+    Lz4,
+    Zstd,
+    None,
+}
+
+impl CompressionMethod {
+    /// The method as `pg_dump --compress` names it.
+    pub fn pg_dump_name(&self) -> &'static str {
+        match self {
+            CompressionMethod::Gzip => "gzip",
+            CompressionMethod::Lz4 => "lz4",
+            CompressionMethod::Zstd => "zstd",
+            CompressionMethod::None => "none",
+        }
+    }
+}
+
+/// How `backup_database` compresses a dump: `method` at `level`, or at the
+/// default level of the method without one.
+///
+/// Plain SQL dumps are gzipped by `backup_database` itself, into `.sql.gz`
+/// files, so that old `pg_dump` versions produce them too.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CompressionSpec {
+    pub method: CompressionMethod,
+    pub level: Option<u32>,
+}
+
+/// Which backups `prune_backups` keeps.
+///
+/// * `keep_last`: the newest backups kept whatever their age.
+/// * `keep_days`: backups younger than this many days are kept too.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub keep_last: usize,
+    pub keep_days: Option<u32>,
+}
+
+/// What `backup_database` dumps.
+///
+/// * `Full`: the schema and the data.
+/// * `SchemaOnly`: the tables, indexes and other objects, without any rows.
+/// * `DataOnly`: the rows, to be restored into existing tables.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BackupScope {
+    Full,
+    SchemaOnly,
+    DataOnly,
+}
+
+impl BackupScope {
+    /// The `pg_dump` flag dumping this scope, if any.
+    pub fn pg_dump_flag(&self) -> Option<&'static str> {
+        match self {
+            BackupScope::Full => None,
+            BackupScope::SchemaOnly => Some("--schema-only"),
+            BackupScope::DataOnly => Some("--data-only"),
+        }
+    }
+
+    /// Part of the backup filename naming a partial scope, if any.
+    pub fn label(&self) -> Option<&'static str> {
+        match self {
+            BackupScope::Full => None,
+            BackupScope::SchemaOnly => Some("schema"),
+            BackupScope::DataOnly => Some("data"),
+        }
+    }
+}
+
+/// Options controlling how `backup_database` dumps the database.
+///
+/// * `jobs`: number of tables dumped in parallel. Defaults to `--jobs` or
+///   the `jobs` key of [config], or else one per CPU up to 4.
+/// * `format`: see `DumpFormat`. Defaults to `Directory` with more than one
+///   job, since other formats are dumped by one process, and `Custom` otherwise.
+/// * `tables`: only dump these tables. Defaults to all of them.
+/// * `exclude_tables`: tables left out of the dump. Defaults to the tables
+///   owned by PostGIS, which are recreated with the extension. The
+///   `exclude_tables` of the `[backup]` config section are left out as well.
+/// * `exclude_schemas`: schemas left out of the dump, with the
+///   `exclude_schemas` of the `[backup]` config section. Defaults to none.
+/// * `scope`: see `BackupScope`. Defaults to `Full`.
+/// * `retain`: prune the older backups of the database in the output
+///   directory once the backup succeeded, see `prune_backups`. Defaults to
+///   keeping all of them.
+/// * `verify`: once dumped, check that `pg_restore` can list the dump and
+///   that it holds the expected tables, and write its SHA-256 next to it as
+///   `{dump}.sha256`. Defaults to false.
+/// * `docker_container_name`: dump with the `pg_dump` of this container
+///   rather than the local one, connected to the server of the container.
+///   The dump is streamed back to the host, or copied out of the container
+///   for the directory format. Checking it with `verify` still needs a local
+///   `pg_restore`.
+/// * `compression`: see `CompressionSpec`. Defaults to the compression of
+///   `pg_dump`: gzip for custom and directory dumps, none for plain ones.
+/// * `include_globals`: also dump the roles and tablespaces of the server,
+///   which `pg_dump` leaves out, with `pg_dumpall --globals-only` into
+///   `globals_{timestamp}.sql` next to the dump, for `RestoreOptions::globals_file`.
+///   Defaults to false.
+/// * `timeout`: kill `pg_dump` once the backup has run this long, and remove
+///   what it wrote. Defaults to none. Ctrl-C kills it the same way.
+/// * `filename_template`: name of the dump, before its extension, with the
+///   tokens `{db}`, `{label}` (the tables and scope), `{date}`, `{time}` and
+///   `{format}`. Defaults to `backup_{db}{label}_{date}_{time}`; dumps named
+///   otherwise are not listed, pruned or found as `latest`, but through
+///   `write_latest_pointer`.
+/// * `utc_timestamps`: name the dump after the time in UTC rather than local
+///   time, so backups made from several time zones sort in order. Defaults to
+///   false.
+/// * `write_latest_pointer`: once backed up and verified, point
+///   `latest_{db}.dump` in the output directory at the dump, a symlink or,
+///   where symlinks cannot be made, `latest_{db}.json`. `restore_database`
+///   restores it as `latest`. Defaults to false.
+/// * `observer`: told how far the backup is, see `ProgressObserver`. Not
+///   serialized.
+///
+/// Table names in `tables` are matched as they are, not as `pg_dump`
+/// patterns. Exclusions with a `*` or `?` are patterns, e.g. `tmp_*` or
+/// `staging.*`, and the others exact names.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupOptions {
+    pub jobs: i32,
+    pub format: Option<DumpFormat>,
+    pub tables: Option<Vec<String>>,
+    pub exclude_tables: Option<Vec<String>>,
+    pub exclude_schemas: Vec<String>,
+    pub scope: BackupScope,
+    pub retain: Option<RetentionPolicy>,
+    pub verify: bool,
+    pub docker_container_name: Option<String>,
+    pub compression: Option<CompressionSpec>,
+    pub include_globals: bool,
+    pub timeout: Option<Duration>,
+    pub filename_template: Option<String>,
+    pub utc_timestamps: bool,
+    pub write_latest_pointer: bool,
+    #[serde(skip)]
+    pub observer: Option<ObserverHandle>,
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        Self {
+            jobs: jobs::default_jobs() as i32,
+            format: None,
+            tables: None,
+            exclude_tables: None,
+            exclude_schemas: Vec::new(),
+            scope: BackupScope::Full,
+            retain: None,
+            verify: false,
+            docker_container_name: None,
+            compression: None,
+            include_globals: false,
+            timeout: None,
+            filename_template: None,
+            utc_timestamps: false,
+            write_latest_pointer: false,
+            observer: None,
+        }
+    }
+}
+
+impl BackupOptions {
+    /// Extension of the dump, telling its compression apart for plain SQL
+    /// and custom dumps: `sql.gz`, `zst.dump` or `lz4.dump`.
+    pub fn extension(&self) -> &'static str {
+        let format = self.effective_format();
+        match (format, self.compression.map(|spec| spec.method)) {
+            (DumpFormat::Plain, Some(CompressionMethod::Gzip)) => "sql.gz",
+            (DumpFormat::Custom, Some(CompressionMethod::Zstd)) => "zst.dump",
+            (DumpFormat::Custom, Some(CompressionMethod::Lz4)) => "lz4.dump",
+            _ => format.extension(),
+        }
+    }
+
+    /// The format the dump is written in.
+    pub fn effective_format(&self) -> DumpFormat {
+        self.format.unwrap_or(if self.jobs > 1 {
+            DumpFormat::Directory
+        } else {
+            DumpFormat::Custom
+        })
+    }
+}
+
+/// Where `stream_backup` writes the dump.
+///
+/// * `File`: a local file at this path.
+/// * `Stdout`: the stdout of the process, to pipe the dump elsewhere.
+/// * `Writer`: any writer, e.g. an upload to object storage or the stdin of
+///   `ssh`. It is shut down once the dump is written.
+pub enum BackupSink {
+    File(String),
+    Stdout,
+    Writer(Box<dyn tokio::io::AsyncWrite + Send + Unpin>),
+}
+
+impl fmt::Display for BackupSink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackupSink::File(path) => write!(f, "{}", path),
+            BackupSink::Stdout => write!(f, "stdout"),
+            BackupSink::Writer(_) => write!(f, "the writer"),
+        }
+    }
+}
+
+/// Which tables `restore_database` drops before restoring a dump.
+///
+/// * `None`: none, `pg_restore --clean --if-exists` replaces the objects of
+///   the dump as it restores them.
+/// * `DroppedTablesInDump`: the tables the dump recreates, each in its own
+///   schema, as listed in its table of contents, with the objects depending
+///   on them.
+/// * `AllTables`: every table of the `public` schema but `exclude_tables`,
+///   whether the dump holds them or not.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PreClean {
+    None,
+    DroppedTablesInDump,
+    AllTables,
+}
+
+/// Maintenance `restore_database` runs once the data is restored. A failing
+/// step is reported as a warning, the restore still succeeds.
+///
+/// * `analyze`: `ANALYZE` the restored tables, which have no statistics yet.
+///   Defaults to true.
+/// * `refresh_collation`: refresh the collation version of the database, see
+///   `fix_collation_version`, for dumps made with another collation library.
+///   Defaults to true.
+/// * `reindex_spatial`: rebuild the GiST and SP-GiST indexes of the restored
+///   tables. Defaults to true.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostRestoreOptions {
+    pub analyze: bool,
+    pub refresh_collation: bool,
+    pub reindex_spatial: bool,
+}
+
+impl Default for PostRestoreOptions {
+    fn default() -> Self {
+        Self {
+            analyze: true,
+            refresh_collation: true,
+            reindex_spatial: true,
+        }
+    }
+}
+
+/// Options controlling how `restore_database` restores a dump.
+///
+/// * `docker_container_name`: restore with the `pg_restore` of this
+///   container rather than the local one. The dump is copied into the
+///   container for the restore, which connects to the server of the
+///   container.
+/// * `jobs`: number of tables restored in parallel. Defaults to `--jobs` or
+///   the `jobs` key of [config], or else one per CPU up to 4.
+/// * `tables`: only restore these tables, with their sequences, indexes,
+///   constraints and triggers. The other tables of the database are left
+///   alone. Defaults to the whole dump.
+/// * `pre_clean`: see `PreClean`. Defaults to `DroppedTablesInDump`; dropping
+///   all tables must be asked for.
+/// * `exclude_tables`: tables of the database kept by `PreClean::AllTables`.
+/// * `assume_yes`: drop the existing tables without asking for confirmation.
+/// * `force`: restore a dump whose `{dump}.sha256` does not match it. Without
+///   it, such a dump is refused as corrupted.
+/// * `target_db`: restore into this database rather than the configured one,
+///   which is then left alone: nothing is dropped from it.
+/// * `create_db`: create `target_db` first, with the PostGIS extension.
+/// * `overwrite`: drop `target_db` first when `create_db` finds it already
+///   exists. Without it, an existing database is an error.
+/// * `globals_file`: apply this `globals_{timestamp}.sql`, written by
+///   `BackupOptions::include_globals`, before the schema, so the roles the
+///   dump grants to exist. Roles that already exist are kept as they are,
+///   and a role that is not superuser skips what only a superuser may do.
+/// * `extensions`: the extensions created before the schema, if missing.
+///   Defaults to those the dump creates or whose types it uses, such as
+///   `postgis` for `geometry` columns.
+/// * `timeout`: kill `pg_restore` once the restore has run this long, over
+///   both phases, e.g. when it waits on a lock held by an idle session.
+///   Defaults to none. Ctrl-C kills it the same way.
+/// * `post_restore`: see `PostRestoreOptions`.
+/// * `observer`: told how far the restore is, see `ProgressObserver`. Not
+///   serialized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreOptions {
+    pub docker_container_name: Option<String>,
+    pub jobs: i32,
+    pub tables: Option<Vec<String>>,
+    pub pre_clean: PreClean,
+    pub exclude_tables: Vec<String>,
+    pub assume_yes: bool,
+    pub force: bool,
+    pub target_db: Option<String>,
+    pub create_db: bool,
+    pub overwrite: bool,
+    pub globals_file: Option<String>,
+    pub extensions: Option<Vec<String>>,
+    pub timeout: Option<Duration>,
+    pub post_restore: PostRestoreOptions,
+    #[serde(skip)]
+    pub observer: Option<ObserverHandle>,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        Self {
+            docker_container_name: None,
+            jobs: jobs::default_jobs() as i32,
+            tables: None,
+            pre_clean: PreClean::DroppedTablesInDump,
+            exclude_tables: Vec::new(),
+            assume_yes: false,
+            force: false,
+            target_db: None,
+            create_db: false,
+            overwrite: false,
+            globals_file: None,
+            extensions: None,
+            timeout: None,
+            post_restore: PostRestoreOptions::default(),
+            observer: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `options` as JSON, the fields of the options being compared that way
+    /// as they are not all `PartialEq`.
+    fn json(options: &InsertGeoJsonOptions) -> serde_json::Value {
+        serde_json::to_value(options).expect("serialize the options")
+    }
+
+    #[test]
+    fn the_builder_starts_from_the_defaults() {
+        let options = InsertGeoJsonOptions::new().build();
+        assert_eq!(json(&options), json(&InsertGeoJsonOptions::default()));
+        assert_eq!(options.table, None);
+        assert_eq!(options.mode, LoadMode::Append);
+    }
+
+    #[test]
+    fn the_builder_sets_the_table_and_load_options() {
+        let options = InsertGeoJsonOptions::new()
+            .table("geo_data")
+            .mode(LoadMode::Replace)
+            .srid(4326)
+            .geometry_type(GeometryType::Point)
+            .primary_key(PrimaryKey::SerialId)
+            .indexes(false, true)
+            .name_from_property("GID_2")
+            .dedupe(Dedupe::LastWins)
+            .insert_strategy(InsertStrategy::Batched)
+            .batch_size(50)
+            .on_feature_error(OnFeatureError::Skip)
+            .analyze(false)
+            .skip_unchanged(true)
+            .build();
+        assert_eq!(options.table.as_deref(), Some("geo_data"));
+        assert_eq!(options.mode, LoadMode::Replace);
+        let table = &options.table_options;
+        assert_eq!(table.srid, Some(4326));
+        assert_eq!(table.geometry_type, Some(GeometryType::Point));
+        assert_eq!(table.primary_key, PrimaryKey::SerialId);
+        assert!(!table.with_gist_geometry_index);
+        assert!(table.with_gin_properties_index);
+        let ingest = &options.ingest_options;
+        assert_eq!(ingest.name_property.as_deref(), Some("GID_2"));
+        assert_eq!(ingest.dedupe, Dedupe::LastWins);
+        assert_eq!(ingest.insert_strategy, InsertStrategy::Batched);
+        assert_eq!(ingest.batch_size, 50);
+        assert_eq!(ingest.on_feature_error, OnFeatureError::Skip);
+        assert!(!ingest.analyze);
+        assert!(ingest.skip_unchanged);
+        // The other fields keep their defaults
+        assert_eq!(ingest.commit_every, IngestOptions::default().commit_every);
+        assert_eq!(table.unlogged, GeoTableOptions::default().unlogged);
+    }
+
+    #[test]
+    fn the_whole_table_and_load_options_replace_those_set_before() {
+        let options = InsertGeoJsonOptions::new()
+            .srid(3857)
+            .name_from_property("id")
+            .table_options(GeoTableOptions::default())
+            .ingest_options(IngestOptions::default())
+            .batch_size(10)
+            .build();
+        assert_eq!(options.table_options.srid, None);
+        assert_eq!(options.ingest_options.name_property, None);
+        assert_eq!(options.ingest_options.batch_size, 10);
+    }
+
+    #[test]
+    fn options_are_read_declaratively_with_defaults_for_the_missing_fields() {
+        let options: InsertGeoJsonOptions = toml::from_str(
+            "table = \"parcels\"\nmode = \"replace\"\n\n\
+             [table_options]\nsrid = 3857\n\n\
+             [ingest_options]\nbatch_size = 200\n",
+        )
+        .expect("parse the options");
+        let expected = InsertGeoJsonOptions::new()
+            .table("parcels")
+            .mode(LoadMode::Replace)
+            .srid(3857)
+            .batch_size(200)
+            .build();
+        assert_eq!(json(&options), json(&expected));
+        let round_trip: InsertGeoJsonOptions =
+            serde_json::from_value(json(&expected)).expect("deserialize the options");
+        assert_eq!(json(&round_trip), json(&expected));
+    }
+}
@@ -0,0 +1,577 @@
+use crate::read::queries::DatabaseQueriesRead;
+use crate::utils::error::ErrorKind;
+use crate::write::dry_run::DryRun;
+use crate::write::options::{
+    BackupOptions, DumpFormat, IngestOptions, InsertGeoJsonOptions, LoadMode, OnFeatureError,
+    RetentionPolicy,
+};
+use crate::write::queries::DatabaseQueriesWrite;
+use crate::write::reports::{PlanReport, StepReport, StepStatus};
+use serde::Deserialize;
+use std::error::Error as StdError;
+use std::fmt;
+use std::path::Path;
+use std::time::Instant;
+use tracing::{error, info};
+
+/// Format of the dump of a `backup` step, see `DumpFormat`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PlanDumpFormat {
+    Custom,
+    Directory,
+    Plain,
+}
+
+/// What a step of a plan does, named by its `action` key, each running the
+/// trait method of the same name.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum PlanAction {
+    Drop {
+        table: String,
+    },
+    Truncate {
+        table: String,
+        #[serde(default)]
+        restart_identity: bool,
+        #[serde(default)]
+        cascade: bool,
+    },
+    InsertGeojson {
+        path: String,
+        table: Option<String>,
+        #[serde(default)]
+        mode: LoadMode,
+        #[serde(default)]
+        skip_unchanged: bool,
+        #[serde(default)]
+        skip_invalid: bool,
+    },
+    /// A statement run with `execute`, e.g. `CREATE INDEX`.
+    Sql {
+        sql: String,
+    },
+    Analyze {
+        table: String,
+    },
+    Vacuum {
+        table: String,
+        #[serde(default)]
+        full: bool,
+        #[serde(default)]
+        analyze: bool,
+    },
+    RefreshOverviews {
+        table: String,
+    },
+    /// `backup_database` into `dir`, or the `dir` of `[backup]`.
+    Backup {
+        dir: Option<String>,
+        format: Option<PlanDumpFormat>,
+        keep_last: Option<usize>,
+        #[serde(default)]
+        verify: bool,
+    },
+}
+
+impl fmt::Display for PlanAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanAction::Drop { table } => write!(f, "drop {}", table),
+            PlanAction::Truncate { table, .. } => write!(f, "truncate {}", table),
+            PlanAction::InsertGeojson {
+                path, table, mode, ..
+            } => {
+                write!(f, "insert_geojson {}", path)?;
+                if let Some(table) = table {
+                    write!(f, " into {}", table)?;
+                }
+                if *mode == LoadMode::Replace {
+                    write!(f, ", replacing it")?;
+                }
+                Ok(())
+            }
+            PlanAction::Sql { sql } => {
+                let sql = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+                if sql.chars().count() > 60 {
+                    write!(f, "sql {}...", sql.chars().take(57).collect::<String>())
+                } else {
+                    write!(f, "sql {}", sql)
+                }
+            }
+            PlanAction::Analyze { table } => write!(f, "analyze {}", table),
+            PlanAction::Vacuum { table, full, .. } => {
+                write!(f, "vacuum {}{}", if *full { "full " } else { "" }, table)
+            }
+            PlanAction::RefreshOverviews { table } => write!(f, "refresh_overviews {}", table),
+            PlanAction::Backup { dir, .. } => match dir {
+                Some(dir) => write!(f, "backup into {}", dir),
+                None => write!(f, "backup into the [backup] dir"),
+            },
+        }
+    }
+}
+
+impl PlanAction {
+    /// The table the step drops or empties, if it does.
+    pub(crate) fn destroyed_table(&self) -> Option<&str> {
+        match self {
+            PlanAction::Drop { table } | PlanAction::Truncate { table, .. } => Some(table),
+            PlanAction::InsertGeojson {
+                table: Some(table),
+                mode: LoadMode::Replace,
+                ..
+            } => Some(table),
+            _ => None,
+        }
+    }
+}
+
+/// A step of a plan, and whether the steps after it still run if it fails.
+#[derive(Debug, Clone)]
+pub(crate) struct PlanStep {
+    pub action: PlanAction,
+    pub continue_on_error: bool,
+}
+
+/// Steps run in order by `run`, as read from a plan file by `load`.
+///
+/// A plan file is TOML, with one `[[steps]]` table per step naming its
+/// `action` beside the arguments of the trait method it runs. Each step may
+/// set `continue_on_error`, defaulting to the top level key of the same
+/// name, itself `false`:
+///
+/// ```toml
+/// continue_on_error = false
+///
+/// [[steps]]
+/// action = "drop"
+/// table = "parcels_staging"
+///
+/// [[steps]]
+/// action = "insert_geojson"
+/// path = "data/parcels"
+/// table = "parcels"
+/// mode = "replace"
+///
+/// [[steps]]
+/// action = "sql"
+/// sql = "CREATE INDEX IF NOT EXISTS parcels_name ON parcels (name)"
+/// continue_on_error = true
+/// ```
+///
+/// The `backup` steps without a `dir` write to `backup_dir`, which `load`
+/// leaves unset for the caller to fill in, as `pgc run` does with the `dir`
+/// of the `[backup]` section of the config.
+#[derive(Debug, Clone)]
+pub(crate) struct Plan {
+    pub name: String,
+    pub steps: Vec<PlanStep>,
+    pub backup_dir: Option<String>,
+}
+
+impl Plan {
+    /// The tables the plan drops or empties, in step order.
+    pub(crate) fn destroyed_tables(&self) -> Vec<String> {
+        self.steps
+            .iter()
+            .filter_map(|step| step.action.destroyed_table())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Read the plan file at `path`, checking every step before any runs.
+///
+/// Relative paths of the steps are relative to the directory of the plan
+/// file, wherever it is run from.
+pub(crate) fn load(path: &str) -> Result<Plan, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("❌ Failed to read the plan {}: {}", path, e))?;
+    let mut plan: toml::value::Table = toml::from_str(&text)
+        .map_err(|e| format!("❌ Failed to parse the plan {}: {}", path, e))?;
+    let continue_by_default = take_bool(&mut plan, "continue_on_error")
+        .map_err(|e| format!("❌ {}: {}", path, e))?
+        .unwrap_or(false);
+    let steps = match plan.remove("steps") {
+        Some(toml::Value::Array(steps)) => steps,
+        Some(_) => return Err(format!("❌ {}: `steps` must be an array of tables", path)),
+        None => return Err(format!("❌ {} has no [[steps]]", path)),
+    };
+    if let Some(key) = plan.keys().next() {
+        return Err(format!("❌ {}: unknown key `{}`", path, key));
+    }
+
+    let base = Path::new(path).parent().unwrap_or(Path::new(""));
+    let mut plan = Plan {
+        name: path.to_string(),
+        steps: Vec::new(),
+        backup_dir: None,
+    };
+    for (i, step) in steps.into_iter().enumerate() {
+        let invalid = |e: String| format!("❌ {}, step {}: {}", path, i + 1, e);
+        let mut step = match step {
+            toml::Value::Table(step) => step,
+            _ => return Err(invalid("a step must be a table".to_string())),
+        };
+        let continue_on_error = take_bool(&mut step, "continue_on_error")
+            .map_err(invalid)?
+            .unwrap_or(continue_by_default);
+        let mut action: PlanAction = toml::Value::Table(step)
+            .try_into()
+            .map_err(|e: toml::de::Error| invalid(e.to_string()))?;
+        match &mut action {
+            PlanAction::InsertGeojson {
+                path, table, mode, ..
+            } => {
+                if *mode == LoadMode::Replace && table.is_none() {
+                    return Err(invalid("mode = \"replace\" needs a table".to_string()));
+                }
+                resolve(base, path);
+            }
+            PlanAction::Backup { dir: Some(dir), .. } => resolve(base, dir),
+            _ => {}
+        }
+        plan.steps.push(PlanStep {
+            action,
+            continue_on_error,
+        });
+    }
+    Ok(plan)
+}
+
+/// Remove the boolean `key` from `table`.
+fn take_bool(table: &mut toml::value::Table, key: &str) -> Result<Option<bool>, String> {
+    match table.remove(key) {
+        Some(toml::Value::Boolean(value)) => Ok(Some(value)),
+        Some(_) => Err(format!("`{}` must be true or false", key)),
+        None => Ok(None),
+    }
+}
+
+/// Make the relative `path` relative to `base` instead.
+fn resolve(base: &Path, path: &mut String) {
+    if Path::new(path.as_str()).is_relative() && path != "-" {
+        *path = base.join(path.as_str()).display().to_string();
+    }
+}
+
+/// Run the steps of `plan` in order, each timed, until one fails without
+/// `continue_on_error`, which skips those after it.
+///
+/// With `dry_run`, the `DryRun` set on `write_queries` by
+/// `PostgresQueriesWrite::with_dry_run`, the steps are run to plan their
+/// statements, each step `Planned` with them as its detail. The `sql`
+/// steps are planned as written, and the `backup` steps are only listed.
+pub(crate) async fn run<W, R>(
+    write_queries: &W,
+    read_queries: &R,
+    plan: &Plan,
+    dry_run: Option<&DryRun>,
+) -> PlanReport
+where
+    W: DatabaseQueriesWrite,
+    R: DatabaseQueriesRead,
+{
+    let mut report = PlanReport {
+        plan: plan.name.clone(),
+        steps: Vec::new(),
+    };
+    let mut stopped = false;
+    for (i, step) in plan.steps.iter().enumerate() {
+        let mut step_report = StepReport {
+            step: i + 1,
+            description: step.action.to_string(),
+            status: StepStatus::Planned,
+            duration_ms: 0,
+            detail: if step.continue_on_error {
+                "continue on error".to_string()
+            } else {
+                String::new()
+            },
+        };
+        if stopped {
+            step_report.status = StepStatus::Skipped;
+            step_report.detail = "an earlier step failed".to_string();
+            report.steps.push(step_report);
+            continue;
+        }
+
+        info!(
+            "▶️ Step {}/{}: {}",
+            i + 1,
+            plan.steps.len(),
+            step_report.description
+        );
+        let start = Instant::now();
+        let result = match (dry_run, &step.action) {
+            (Some(dry_run), PlanAction::Sql { sql }) => {
+                dry_run.plan(sql.clone());
+                Ok(String::new())
+            }
+            (Some(_), PlanAction::Backup { .. }) => Ok("not run in a dry run".to_string()),
+            _ => {
+                run_step(
+                    write_queries,
+                    read_queries,
+                    &step.action,
+                    plan.backup_dir.as_deref(),
+                )
+                .await
+            }
+        };
+        step_report.duration_ms = start.elapsed().as_millis() as u64;
+        let statements = dry_run.map(DryRun::take).unwrap_or_default();
+        match result {
+            Ok(detail) if dry_run.is_some() => {
+                if !statements.is_empty() {
+                    step_report.detail = statements.join("; ");
+                } else if !detail.is_empty() {
+                    step_report.detail = detail;
+                }
+            }
+            Ok(detail) => {
+                info!(
+                    "✅ Step {} done in {:.1}s",
+                    i + 1,
+                    start.elapsed().as_secs_f64()
+                );
+                step_report.status = StepStatus::Ok;
+                step_report.detail = detail;
+            }
+            Err(e) => {
+                error!("❌ Step {} failed: {}", i + 1, e);
+                step_report.status = StepStatus::Failed;
+                step_report.detail = e.to_string();
+                stopped = !step.continue_on_error;
+            }
+        }
+        report.steps.push(step_report);
+    }
+    if dry_run.is_none() {
+        report.print_summary();
+    }
+    report
+}
+
+/// Run `action` with its trait method, returning what it did. A backup
+/// without a `dir` writes to `backup_dir`.
+async fn run_step<W, R>(
+    write_queries: &W,
+    read_queries: &R,
+    action: &PlanAction,
+    backup_dir: Option<&str>,
+) -> Result<String, Box<dyn StdError + Send + Sync>>
+where
+    W: DatabaseQueriesWrite,
+    R: DatabaseQueriesRead,
+{
+    match action {
+        PlanAction::Drop { table } => {
+            write_queries.drop(table).await?;
+            Ok("dropped".to_string())
+        }
+        PlanAction::Truncate {
+            table,
+            restart_identity,
+            cascade,
+        } => {
+            write_queries
+                .truncate(table, *restart_identity, *cascade)
+                .await?;
+            Ok("emptied".to_string())
+        }
+        PlanAction::InsertGeojson {
+            path,
+            table,
+            mode,
+            skip_unchanged,
+            skip_invalid,
+        } => {
+            let ingest_options = IngestOptions {
+                skip_unchanged: *skip_unchanged,
+                on_feature_error: if *skip_invalid {
+                    OnFeatureError::Skip
+                } else {
+                    OnFeatureError::Abort
+                },
+                ..Default::default()
+            };
+            let options = InsertGeoJsonOptions {
+                table: table.clone(),
+                mode: *mode,
+                ingest_options,
+                ..Default::default()
+            };
+            let report = write_queries.insert_geojson(path, Some(options)).await?;
+            let rows: u64 = report.files.iter().map(|file| file.rows_written).sum();
+            Ok(format!(
+                "{} rows from {} files into {}",
+                rows,
+                report.files.len(),
+                report.table
+            ))
+        }
+        PlanAction::Sql { sql } => {
+            let rows = read_queries.execute(sql.clone()).await?;
+            Ok(format!("{} rows returned", rows.len()))
+        }
+        PlanAction::Analyze { table } => {
+            write_queries.analyze(table).await?;
+            Ok("analyzed".to_string())
+        }
+        PlanAction::Vacuum {
+            table,
+            full,
+            analyze,
+        } => {
+            write_queries.vacuum(table, *full, *analyze).await?;
+            Ok("vacuumed".to_string())
+        }
+        PlanAction::RefreshOverviews { table } => {
+            let overviews = write_queries.refresh_overviews(table).await?;
+            Ok(format!("refreshed {}", overviews.join(", ")))
+        }
+        #[cfg(feature = "backup")]
+        PlanAction::Backup {
+            dir,
+            format,
+            keep_last,
+            verify,
+        } => {
+            let dir = match dir {
+                Some(dir) => dir.clone(),
+                None => backup_dir
+                    .map(str::to_string)
+                    .ok_or_else(|| {
+                        ErrorKind::Config.wrap(
+                            "❌ No backup directory: set `dir` in the step, or in the [backup] section of the config",
+                        )
+                    })?,
+            };
+            let options = BackupOptions {
+                format: format.map(|format| match format {
+                    PlanDumpFormat::Custom => DumpFormat::Custom,
+                    PlanDumpFormat::Directory => DumpFormat::Directory,
+                    PlanDumpFormat::Plain => DumpFormat::Plain,
+                }),
+                retain: keep_last.map(|keep_last| RetentionPolicy {
+                    keep_last,
+                    keep_days: None,
+                }),
+                verify: *verify,
+                ..Default::default()
+            };
+            let dump_file = write_queries.backup_database(&dir, Some(options)).await?;
+            Ok(dump_file)
+        }
+        #[cfg(not(feature = "backup"))]
+        PlanAction::Backup { .. } => {
+            Err(ErrorKind::Usage.wrap("❌ Backup steps need pgc built with the `backup` feature"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The plan `text`, written to a file of the temporary directory.
+    fn plan_file(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("pgc_plan_{}_{}", std::process::id(), name));
+        std::fs::write(&path, text).expect("write the plan");
+        path.display().to_string()
+    }
+
+    #[test]
+    fn the_example_plan_is_read_with_its_paths_relative_to_it() {
+        let plan = load("files/plan.toml").expect("load files/plan.toml");
+        let steps: Vec<(String, bool)> = plan
+            .steps
+            .iter()
+            .map(|step| (step.action.to_string(), step.continue_on_error))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                ("drop parcels_staging".to_string(), false),
+                (
+                    "insert_geojson files/../data/parcels into parcels, replacing it".to_string(),
+                    false
+                ),
+                // Shortened to 60 characters
+                (
+                    "sql CREATE INDEX IF NOT EXISTS parcels_name_idx ON parcels (n...".to_string(),
+                    false
+                ),
+                ("backup into the [backup] dir".to_string(), true),
+            ]
+        );
+        assert_eq!(plan.destroyed_tables(), ["parcels_staging", "parcels"]);
+        assert_eq!(plan.backup_dir, None);
+    }
+
+    #[test]
+    fn steps_continue_on_error_as_the_plan_says_unless_they_say() {
+        let path = plan_file(
+            "continue.toml",
+            "continue_on_error = true\n\n\
+             [[steps]]\naction = \"truncate\"\ntable = \"a\"\n\n\
+             [[steps]]\naction = \"insert_geojson\"\npath = \"-\"\ntable = \"b\"\n\
+             continue_on_error = false\n\n\
+             [[steps]]\naction = \"backup\"\ndir = \"/backups\"\n",
+        );
+        let plan = load(&path).expect("load");
+        let continues: Vec<bool> = plan
+            .steps
+            .iter()
+            .map(|step| step.continue_on_error)
+            .collect();
+        assert_eq!(continues, [true, false, true]);
+        // Appending, the table is kept
+        assert_eq!(plan.destroyed_tables(), ["a"]);
+        assert_eq!(plan.steps[1].action.to_string(), "insert_geojson - into b");
+        assert_eq!(plan.steps[2].action.to_string(), "backup into /backups");
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn invalid_plans_fail_to_load_before_any_step_runs() {
+        let cases = [
+            ("no_steps", "continue_on_error = true\n", "has no [[steps]]"),
+            (
+                "unknown_key",
+                "retries = 2\n[[steps]]\naction = \"drop\"\ntable = \"a\"\n",
+                "unknown key `retries`",
+            ),
+            (
+                "not_bool",
+                "[[steps]]\naction = \"drop\"\ntable = \"a\"\ncontinue_on_error = \"yes\"\n",
+                "step 1: `continue_on_error` must be true or false",
+            ),
+            (
+                "unknown_action",
+                "[[steps]]\naction = \"drop\"\ntable = \"a\"\n[[steps]]\naction = \"explode\"\n",
+                "step 2: unknown variant `explode`",
+            ),
+            (
+                "unknown_field",
+                "[[steps]]\naction = \"drop\"\ntable = \"a\"\ncascade = true\n",
+                "unknown field `cascade`",
+            ),
+            (
+                "replace",
+                "[[steps]]\naction = \"insert_geojson\"\npath = \"x\"\nmode = \"replace\"\n",
+                "mode = \"replace\" needs a table",
+            ),
+        ];
+        for (name, text, message) in cases {
+            let path = plan_file(name, text);
+            let error = load(&path).expect_err(name);
+            assert!(error.contains(message), "{}: {}", name, error);
+            assert!(error.contains(&path), "{}", error);
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
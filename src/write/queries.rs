@@ -1,480 +1,5002 @@
+use super::super::read::config::{Config, ConfigFile};
 use super::super::read::db;
-use super::super::read::queries::DatabaseQueriesRead;
-use super::super::read::Read;
+use super::super::read::dialect::{self, Dialect};
+use super::super::read::queries::{DatabaseQueriesRead, PostgresQueriesRead};
+use super::super::read::reports::ImportRecord;
+use crate::utils::audit::{AuditLog, AuditRecord};
+use crate::utils::error::{ErrorKind, PgcError};
+use crate::utils::metrics::{self, Metrics};
+use crate::utils::progress::{observed, OperationReport};
+use crate::utils::reporter::{NoopReporter, Reporter};
+use crate::utils::sql::PgType;
+use crate::utils::{cancel, prompt, sql};
+#[cfg(feature = "backup")]
+use crate::write::backup::{self, RestorePhase};
+use crate::write::dedupe::DedupeState;
+use crate::write::dry_run::DryRun;
+use crate::write::hooks::{self, HookStage};
+#[cfg(feature = "backup")]
+use crate::write::logical;
+use crate::write::manifest;
+use crate::write::options::{
+    BackupOptions, BackupSink, ColumnKind, CompressionMethod, CompressionSpec, Dedupe, DumpFormat,
+    GeoTableOptions, IngestOptions, InsertGeoJsonOptions, InsertStrategy, LoadMode,
+    PostRestoreOptions, PreClean, PrimaryKey, RasterOptions, RestoreOptions, WatchOptions,
+};
+use crate::write::reports::{
+    BackupInfo, BackupVerification, CollationRefresh, DropOutcome, FileReport, IngestError,
+    IngestReport, LogicalBackup, MaterializedColumn, RasterImport, SimplifiedTable, StreamedBackup,
+    WatchFailure, WatchReport,
+};
 use crate::write::utils::{
-    convert_path, custom_unwrap_or, get_all_file_paths, process_and_upload_file, GeoJSONFile,
+    convert_path, custom_unwrap_or, get_all_file_paths, get_geojson_file_paths,
+    get_raster_file_paths, planned_upload, process_and_upload_file, process_and_upload_stream,
+    FeatureFilter, IngestConnection, PatchMode,
 };
+#[cfg(feature = "ingest")]
+use crate::write::watch;
+use async_trait::async_trait;
 use chrono::Local;
+use deadpool_postgres::{Object, Pool};
 use serde_json::{Deserializer, Value};
+use std::collections::HashMap;
 use std::error::Error as StdError;
 use std::fs::File;
+use std::future::Future;
 use std::io::{BufReader, BufWriter, Write};
-use std::process::Command;
-use std::time::Instant;
-use sys_info;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt};
+use tokio::sync::OnceCell;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
 use tokio_postgres::{Client, Error};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, instrument, warn};
 
-pub trait DatabaseQueriesWrite {
+/// The write operations, object safe so that a `Box<dyn DatabaseQueriesWrite>`
+/// can hold `PostgresQueriesWrite` or a fake, like `MockQueriesWrite` of
+/// the `testing` feature. Their futures are `Send`, to be spawned on a
+/// multi-threaded runtime.
+///
+/// ```no_run
+/// use postgres_connect_rust::DatabaseQueriesWrite;
+///
+/// fn load_later(queries: Box<dyn DatabaseQueriesWrite>) -> tokio::task::JoinHandle<()> {
+///     tokio::spawn(async move {
+///         let _ = queries.insert_geojson("data/parcels.geojson", None).await;
+///     })
+/// }
+/// ```
+#[async_trait]
+pub trait DatabaseQueriesWrite: Send + Sync {
     async fn execute(
         &self,
         query: String,
         success_message: Option<&str>,
         error_message: Option<&str>,
-    );
+    ) -> Result<(), PgcError>;
+
+    async fn drop(&self, table_name: &str) -> Result<(), PgcError>;
+
+    async fn drop_many(
+        &self,
+        tables: &[&str],
+        cascade: bool,
+        if_exists: bool,
+    ) -> Result<Vec<(String, DropOutcome)>, PgcError>;
+
+    async fn drop_all_tables(
+        &self,
+        schema: Option<&str>,
+        dry_run: bool,
+        exclude: &[&str],
+        require_confirmation: bool,
+    ) -> Result<Vec<String>, PgcError>;
 
-    async fn drop(&self, table_name: &str) -> Result<(), Box<dyn StdError>>;
+    async fn create_schema(
+        &self,
+        schema_name: &str,
+        authorization: Option<&str>,
+    ) -> Result<(), PgcError>;
+
+    async fn drop_schema(&self, schema_name: &str, cascade: bool) -> Result<(), PgcError>;
+
+    async fn truncate(
+        &self,
+        table_name: &str,
+        restart_identity: bool,
+        cascade: bool,
+    ) -> Result<(), PgcError>;
+
+    async fn rename_table(&self, old_name: &str, new_name: &str) -> Result<(), PgcError>;
+
+    async fn atomic_swap(&self, staging_table: &str, target_table: &str) -> Result<(), PgcError>;
+
+    async fn fix_collation_version(
+        &self,
+        db_name: Option<&str>,
+    ) -> Result<CollationRefresh, PgcError>;
 
-    async fn drop_all_tables(&self) -> Result<(), Box<dyn std::error::Error>>;
+    async fn ensure_postgis_extension(&self, client: &Client) -> Result<(), PgcError>;
 
-    async fn fix_collation_version(&self, table_name: &str);
+    async fn create_geo_table(
+        &self,
+        client: &Client,
+        table_name: &str,
+        options: &GeoTableOptions,
+    ) -> Result<(), PgcError>;
 
-    async fn create_geo_table(&self, client: &Client, table_name: &str) -> Result<(), Error>;
+    async fn grant_on_table(
+        &self,
+        table_name: &str,
+        role: &str,
+        privileges: &str,
+    ) -> Result<(), PgcError>;
 
     async fn insert_geojson(
         &self,
         geojson_path: &str,
-        table_name: Option<&str>,
-    ) -> Result<(), Box<dyn StdError>>;
+        options: Option<InsertGeoJsonOptions>,
+    ) -> Result<IngestReport, IngestError>;
+
+    async fn insert_geojson_from_reader(
+        &self,
+        reader: Box<dyn AsyncRead + Send + Unpin>,
+        options: InsertGeoJsonOptions,
+    ) -> Result<IngestReport, IngestError>;
+
+    #[cfg(feature = "ingest")]
+    async fn watch_directory(
+        &self,
+        dir: &str,
+        options: Option<WatchOptions>,
+    ) -> Result<WatchReport, PgcError>;
+
+    async fn delete_features(
+        &self,
+        table_name: &str,
+        filter: &FeatureFilter,
+        dry_run: bool,
+    ) -> Result<u64, PgcError>;
+
+    async fn update_properties(
+        &self,
+        table_name: &str,
+        filter: &FeatureFilter,
+        patch: Value,
+        mode: PatchMode,
+        dry_run: bool,
+    ) -> Result<u64, PgcError>;
+
+    async fn soft_delete_features(
+        &self,
+        table_name: &str,
+        filter: &FeatureFilter,
+    ) -> Result<u64, PgcError>;
+
+    async fn purge_deleted(&self, table_name: &str, older_than: Duration) -> Result<u64, PgcError>;
+
+    async fn materialize_properties(
+        &self,
+        table_name: &str,
+        mapping: Vec<(String, String, PgType)>,
+        create_indexes: bool,
+    ) -> Result<Vec<MaterializedColumn>, PgcError>;
 
+    async fn create_simplified_table(
+        &self,
+        source_table: &str,
+        target_table: &str,
+        tolerance: f64,
+    ) -> Result<SimplifiedTable, PgcError>;
+
+    async fn create_overview_tables(
+        &self,
+        source_table: &str,
+        tolerances: &[f64],
+    ) -> Result<Vec<String>, PgcError>;
+
+    async fn refresh_overviews(&self, source_table: &str) -> Result<Vec<String>, PgcError>;
+
+    async fn add_derived_geometry_columns(&self, table_name: &str) -> Result<u64, PgcError>;
+
+    async fn insert_raster(
+        &self,
+        raster_path: &str,
+        table_name: &str,
+        options: Option<RasterOptions>,
+    ) -> Result<RasterImport, PgcError>;
+
+    async fn analyze(&self, table_name: &str) -> Result<(), PgcError>;
+
+    async fn vacuum(&self, table_name: &str, full: bool, analyze: bool) -> Result<(), PgcError>;
+
+    #[cfg(feature = "backup")]
     async fn backup_database(
         &self,
         output_dir: &str,
-        no_of_jobs: Option<i32>,
-    ) -> Result<(), Box<dyn std::error::Error>>;
+        options: Option<BackupOptions>,
+    ) -> Result<String, PgcError>;
+
+    #[cfg(feature = "backup")]
+    async fn stream_backup(
+        &self,
+        sink: BackupSink,
+        options: Option<BackupOptions>,
+    ) -> Result<StreamedBackup, PgcError>;
 
+    #[cfg(feature = "backup")]
     async fn restore_database(
         &self,
         dump_file: &str,
-        docker_container_name: Option<&str>,
-        no_of_jobs: Option<i32>,
-    ) -> Result<(), Box<dyn std::error::Error>>;
+        options: Option<RestoreOptions>,
+    ) -> Result<(), PgcError>;
+
+    #[cfg(feature = "backup")]
+    async fn prune_backups(
+        &self,
+        dir: &str,
+        keep_last: usize,
+        keep_days: Option<u32>,
+        utc_timestamps: bool,
+        dry_run: bool,
+    ) -> Result<Vec<String>, PgcError>;
+
+    #[cfg(feature = "backup")]
+    async fn list_backups(&self, dir: &str) -> Result<Vec<BackupInfo>, PgcError>;
+
+    #[cfg(feature = "backup")]
+    async fn verify_backup(&self, dump_file: &str) -> Result<BackupVerification, PgcError>;
+
+    #[cfg(feature = "backup")]
+    async fn logical_backup(&self, output_dir: &str) -> Result<LogicalBackup, PgcError>;
+
+    #[cfg(feature = "backup")]
+    async fn logical_restore(&self, backup_dir: &str) -> Result<LogicalBackup, PgcError>;
 }
 
-pub struct PostgresQueriesWrite;
+/// Database connected to for creating and dropping other databases.
+#[cfg(feature = "backup")]
+const MAINTENANCE_DB: &str = "postgres";
 
-impl DatabaseQueriesWrite for PostgresQueriesWrite {
-    /// Execute a SQL query.
-    ///
-    /// This function will attempt to execute a SQL query
-    /// in the database. If the query fails, an error message will be printed.
-    async fn execute(
-        &self,
-        query: String,
-        success_message: Option<&str>,
-        error_message: Option<&str>,
-    ) {
-        let read_queries = super::super::read::queries::PostgresQueriesRead;
-        // read_queries.execute(query).await;
+/// Create the database `restore_database` restores into, dropping it first
+/// with `overwrite`, then create the PostGIS extension in it so geometry
+/// columns can be restored.
+#[cfg(feature = "backup")]
+async fn create_restore_database(
+    config: &Config,
+    target_db: &str,
+    overwrite: bool,
+) -> Result<(), PgcError> {
+    if target_db == config.db_name {
+        return Err(PgcError::usage(format!(
+            "❌ {} is the configured database, restore into it without `create_db`",
+            target_db
+        )));
+    }
+    let client = db::connect_as(config, MAINTENANCE_DB, None).await?;
+    let exists = client
+        .query_opt(
+            "SELECT 1 FROM pg_database WHERE datname = $1",
+            &[&target_db],
+        )
+        .await?
+        .is_some();
+    if exists {
+        if !overwrite {
+            return Err(PgcError::usage(format!(
+                "❌ Database {} already exists, set `overwrite` to drop and recreate it",
+                target_db
+            )));
+        }
+        info!("🔄 Dropping existing database {}", target_db);
+        client
+            .batch_execute(&format!(
+                "DROP DATABASE {} WITH (FORCE)",
+                sql::ident(target_db)?
+            ))
+            .await?;
+    }
+    // Owned by the current role, which creates it
+    info!("🔄 Creating database {}", target_db);
+    client
+        .batch_execute(&format!(
+            "CREATE DATABASE {} TEMPLATE template0",
+            sql::ident(target_db)?
+        ))
+        .await?;
 
-        match read_queries.execute(query).await {
-            Ok(_) => println!(
-                "{}",
-                success_message.unwrap_or("✅  Query executed successfully")
-            ),
-            Err(e) => eprintln!(
-                "{}\n\n❌  Error executing query: {}",
-                error_message.unwrap_or("Error executing query"),
-                e
+    let target_client = db::connect_as(config, target_db, None).await?;
+    target_client
+        .batch_execute("CREATE EXTENSION IF NOT EXISTS postgis CASCADE")
+        .await
+        .map_err(|e| format!("❌ Failed to create PostGIS in {}: {}", target_db, e))?;
+    info!("✅ Database {} created with PostGIS", target_db);
+    Ok(())
+}
+
+/// Run the `post_restore` steps of `restore_database` on `db_name`, for the
+/// restored `tables` or the whole database, returning how long each took.
+///
+/// `ANALYZE` and `REINDEX` go through a connection to `db_name`, which may
+/// not be the configured database. A failing step is only a warning.
+#[cfg(feature = "backup")]
+async fn run_post_restore(
+    write_queries: &PostgresQueriesWrite,
+    db_name: &str,
+    tables: Option<&[String]>,
+    post_restore: &PostRestoreOptions,
+) -> Vec<(&'static str, Duration)> {
+    let mut durations = Vec::new();
+    if post_restore.analyze {
+        let start = Instant::now();
+        info!("🔄 Analyzing the restored tables");
+        let analyzed: Result<(), PgcError> = async {
+            let query = analyze_statement(tables)?;
+            let client = db::connect_as(&write_queries.config()?, db_name, None).await?;
+            Ok(client.batch_execute(&query).await?)
+        }
+        .await;
+        match analyzed {
+            Ok(()) => info!("✅ Restored tables analyzed in {:.2?}", start.elapsed()),
+            Err(e) => warn!("⚠️ Failed to analyze the restored tables: {}", e),
+        }
+        durations.push(("analyze", start.elapsed()));
+    }
+    if post_restore.refresh_collation {
+        let start = Instant::now();
+        if let Err(e) = write_queries.fix_collation_version(Some(db_name)).await {
+            warn!("⚠️ Failed to refresh the collation version: {}", e);
+        }
+        durations.push(("collation", start.elapsed()));
+    }
+    if post_restore.reindex_spatial {
+        let start = Instant::now();
+        let reindexed = match write_queries.config() {
+            Ok(config) => reindex_spatial(&config, db_name, tables).await,
+            Err(e) => Err(e),
+        };
+        match reindexed {
+            Ok(0) => info!("📋 No spatial index to rebuild"),
+            Ok(count) => info!(
+                "✅ Rebuilt {} spatial indexes in {:.2?}",
+                count,
+                start.elapsed()
             ),
+            Err(e) => warn!("⚠️ Failed to rebuild the spatial indexes: {}", e),
         }
+        durations.push(("spatial reindex", start.elapsed()));
     }
+    durations
+}
 
-    /// Drop a table in the database.
-    ///
-    /// This function will attempt to drop a table
-    /// in the database. If the table does not exist,
-    /// the function will silently exit.
-    async fn drop(&self, table_name: &str) -> Result<(), Box<dyn StdError>> {
-        let query = format!("DROP TABLE IF EXISTS {} CASCADE", table_name).to_string();
-        let read_queries = super::super::read::queries::PostgresQueriesRead;
+/// `ANALYZE` of `tables`, or of the whole database.
+#[cfg(feature = "backup")]
+fn analyze_statement(tables: Option<&[String]>) -> Result<String, PgcError> {
+    Ok(match tables {
+        Some(tables) => format!(
+            "ANALYZE {}",
+            tables
+                .iter()
+                .map(|table| sql::ident(table))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(", ")
+        ),
+        None => "ANALYZE".to_string(),
+    })
+}
 
-        println!("🔄 Attempting to drop table: {}", table_name);
-        match read_queries.execute(query).await {
-            Ok(_) => {
-                println!("✅ {} table dropped successfully", table_name);
-                Ok(())
-            }
-            Err(e) => {
-                eprintln!("❌ Failed to drop table: {}", e);
-                Err(Box::new(e))
-            }
+/// Rebuild the GiST and SP-GiST indexes of `tables`, or of every table, in
+/// `db_name`, returning how many there were.
+#[cfg(feature = "backup")]
+async fn reindex_spatial(
+    config: &Config,
+    db_name: &str,
+    tables: Option<&[String]>,
+) -> Result<usize, PgcError> {
+    let client = db::connect_as(config, db_name, None).await?;
+    let tables: Option<Vec<String>> = tables.map(<[String]>::to_vec);
+    let indexes: Vec<String> = client
+        .query(
+            "SELECT format('%I.%I', n.nspname, i.relname)
+            FROM pg_index x
+            JOIN pg_class i ON i.oid = x.indexrelid
+            JOIN pg_class t ON t.oid = x.indrelid
+            JOIN pg_namespace n ON n.oid = i.relnamespace
+            JOIN pg_am am ON am.oid = i.relam
+            WHERE am.amname IN ('gist', 'spgist')
+            AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+            AND ($1::text[] IS NULL OR t.relname = ANY($1))
+            ORDER BY 1",
+            &[&tables],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    for index in &indexes {
+        info!("🔄 Rebuilding spatial index {}", index);
+        client
+            .batch_execute(&format!("REINDEX INDEX {}", index))
+            .await?;
+    }
+    Ok(indexes.len())
+}
+
+/// `rows` with thousands separators, e.g. `40,000,000`.
+fn thousands(rows: i64) -> String {
+    let digits = rows.unsigned_abs().to_string();
+    let mut grouped = String::new();
+    for (idx, digit) in digits.chars().enumerate() {
+        if idx > 0 && (digits.len() - idx).is_multiple_of(3) {
+            grouped.push(',');
         }
+        grouped.push(digit);
     }
+    if rows < 0 {
+        grouped.insert(0, '-');
+    }
+    grouped
+}
 
-    async fn drop_all_tables(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let read_queries = super::super::read::queries::PostgresQueriesRead;
-        // let query = "DROP SCHEMA public CASCADE; CREATE SCHEMA public;";
+/// Ask the user to confirm `action`, e.g. `Drop 3 tables`, on `tables` of
+/// `schema` in database `db_name`. The question names the server and lists
+/// each table with its estimated number of rows, see `estimated_row_counts`,
+/// so that the size of what goes is seen before it does.
+///
+/// Returns whether the user confirmed.
+pub(crate) async fn confirm_destruction(
+    action: &str,
+    config: &Config,
+    schema: &str,
+    tables: &[String],
+    reporter: &dyn Reporter,
+) -> Result<bool, PgcError> {
+    let db_name = &config.db_name;
+    let client = db::connect_as(config, db_name, None).await?;
+    let dialect = dialect::resolve(config.dialect, &client).await?;
+    let estimates =
+        super::super::read::queries::estimated_row_counts(&client, dialect, schema, tables).await?;
+    // Part of the question, shown whatever the log level
+    reporter.warn(&format!(
+        "⚠️ {} in database {} on {}:{}:",
+        action, db_name, config.host, config.port
+    ));
+    let rows: Vec<Vec<String>> = estimates
+        .iter()
+        .map(|(table, rows)| {
+            vec![
+                format!("{}.{}", schema, table),
+                match rows {
+                    Some(rows) => format!("~{}", thousands(*rows)),
+                    None => "unknown, never analyzed".to_string(),
+                },
+            ]
+        })
+        .collect();
+    reporter.table(&["table", "estimated rows"], &rows);
+    let total: i64 = estimates.iter().filter_map(|(_, rows)| *rows).sum();
+    Ok(prompt::confirm(&format!(
+        "{} (~{} rows in all)?",
+        action,
+        thousands(total)
+    )))
+}
 
-        let tables = read_queries.list_tables(Some(true)).await?;
+/// Rows updated per statement when backfilling materialized or derived columns
+const BACKFILL_BATCH_SIZE: i64 = 10_000;
 
-        if tables.is_empty() || tables.len() == 0 {
-            println!("🤗 No tables to drop");
-            return Ok(());
-        }
+/// SQL expression for the bounding box of `geometry`. Built from the extent
+/// rather than `ST_Envelope`, so it is a polygon even for points and lines.
+fn bbox_expression(geometry: &str, srid: Option<i32>) -> String {
+    format!(
+        "ST_MakeEnvelope(ST_XMin({g}), ST_YMin({g}), ST_XMax({g}), ST_YMax({g}), {srid})",
+        g = geometry,
+        srid = srid
+            .map(|srid| srid.to_string())
+            .unwrap_or_else(|| format!("ST_SRID({})", geometry))
+    )
+}
 
-        println!("Found {} tables to drop", tables.len());
-        println!("🔄 Attempting to drop all tables");
+/// `column` as a planar geometry, cast from geography if need be, for the
+/// functions that only take geometries.
+fn planar_expression(column: &str, kind: ColumnKind) -> String {
+    match kind {
+        ColumnKind::Geometry => column.to_string(),
+        ColumnKind::Geography => format!("{}::geometry", column),
+    }
+}
 
-        let drop_futures: Vec<_> = tables
-            .into_iter()
-            .map(|table_name| {
-                // let read_queries = read_queries.clone(); // Clone for each closure
-                let read_queries = super::super::read::queries::PostgresQueriesRead;
+/// Statements adding `updated_at` to a table and the trigger maintaining it.
+///
+/// All of them can be run again on a table already having them. The trigger
+/// only bumps the timestamp when `properties` or `geometry` actually changed,
+/// so re-applying identical values leaves it alone.
+fn updated_at_statements(table_name: &str) -> Result<Vec<String>, PgcError> {
+    let trigger_name = sql::ident(&format!("{}_set_updated_at", table_name))?;
+    Ok(vec![
+        format!(
+            "ALTER TABLE {} ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ DEFAULT NOW();",
+            sql::ident(table_name)?
+        ),
+        "CREATE OR REPLACE FUNCTION set_updated_at() RETURNS trigger AS $$
+        BEGIN
+            IF NEW.properties IS DISTINCT FROM OLD.properties
+                OR NEW.geometry::text IS DISTINCT FROM OLD.geometry::text THEN
+                NEW.updated_at := NOW();
+            END IF;
+            RETURN NEW;
+        END
+        $$ LANGUAGE plpgsql;"
+            .to_string(),
+        format!(
+            "DROP TRIGGER IF EXISTS {} ON {};",
+            trigger_name,
+            sql::ident(table_name)?
+        ),
+        format!(
+            "CREATE TRIGGER {} BEFORE UPDATE ON {}
+            FOR EACH ROW EXECUTE PROCEDURE set_updated_at();",
+            trigger_name,
+            sql::ident(table_name)?
+        ),
+    ])
+}
 
-                let drop_query = format!("DROP TABLE {} CASCADE", table_name);
+/// Whether a table of the current schema has a column.
+async fn has_column(client: &Client, table_name: &str, column: &str) -> Result<bool, Error> {
+    Ok(client
+        .query_one(
+            "SELECT EXISTS (
+                SELECT 1 FROM information_schema.columns
+                WHERE table_schema = current_schema() AND table_name = $1 AND column_name = $2
+            )",
+            &[&table_name, &column],
+        )
+        .await?
+        .get(0))
+}
 
-                println!("🔄 Scheduling drop for table: {}", table_name);
-                async move {
-                    match read_queries.execute(drop_query).await {
-                        Ok(_) => println!("✅ Dropped table: {:?}", &table_name),
-                        Err(e) => println!("❌ Failed to drop table {:?}: {}", &table_name, e),
-                    }
-                }
-            })
-            .collect();
+/// The condition and parameters of `filter` on a table of the current schema.
+///
+/// A `BBox` filter needs the SRID of the `geometry` column, looked up once in
+/// `geometry_columns` or `geography_columns`; a column declared without one
+/// (SRID 0) leaves the SRID of every row to be used.
+async fn filter_sql(
+    client: &Client,
+    table_name: &str,
+    filter: &FeatureFilter,
+) -> Result<(String, Vec<Box<dyn ToSql + Sync + Send>>), Error> {
+    let srid = match filter {
+        FeatureFilter::BBox { .. } => client
+            .query_opt(
+                "SELECT srid FROM geometry_columns
+                WHERE f_table_schema = current_schema() AND f_table_name = $1
+                    AND f_geometry_column = 'geometry'
+                UNION ALL
+                SELECT srid FROM geography_columns
+                WHERE f_table_schema = current_schema() AND f_table_name = $1
+                    AND f_geography_column = 'geometry'",
+                &[&table_name],
+            )
+            .await?
+            .and_then(|row| row.get::<_, Option<i32>>(0))
+            .filter(|srid| *srid > 0),
+        _ => None,
+    };
+    Ok(filter.to_sql(srid))
+}
 
-        futures::future::join_all(drop_futures).await;
-        println!("✅ All tables dropped successfully");
+/// Name of the overview of `source_table` at zoom level `level`.
+fn overview_name(source_table: &str, level: usize) -> String {
+    format!("{}_z{}", source_table, level)
+}
 
-        Ok(())
+/// Overviews of `source_table` in the current schema, by increasing zoom level.
+async fn overview_names(client: &Client, source_table: &str) -> Result<Vec<String>, Error> {
+    let prefix = format!("{}_z", source_table);
+    let mut overviews: Vec<(usize, String)> = client
+        .query(
+            "SELECT matviewname::text FROM pg_matviews WHERE schemaname = current_schema()",
+            &[],
+        )
+        .await?
+        .iter()
+        .filter_map(|row| {
+            let name: String = row.get(0);
+            let level = name.strip_prefix(&prefix)?.parse().ok()?;
+            Some((level, name))
+        })
+        .collect();
+    overviews.sort();
+    Ok(overviews.into_iter().map(|(_, name)| name).collect())
+}
+
+/// Table privileges `GRANT` accepts.
+const TABLE_PRIVILEGES: [&str; 9] = [
+    "SELECT",
+    "INSERT",
+    "UPDATE",
+    "DELETE",
+    "TRUNCATE",
+    "REFERENCES",
+    "TRIGGER",
+    "ALL",
+    "ALL PRIVILEGES",
+];
+
+/// `GRANT` statement for a comma separated list of table privileges.
+/// Privileges are checked against `TABLE_PRIVILEGES`, since they can't be quoted.
+fn grant_statement(table_name: &str, role: &str, privileges: &str) -> Result<String, PgcError> {
+    let privileges: Vec<String> = privileges
+        .split(',')
+        .map(|privilege| privilege.split_whitespace().collect::<Vec<_>>().join(" "))
+        .map(|privilege| privilege.to_uppercase())
+        .collect();
+    if let Some(invalid) = privileges
+        .iter()
+        .find(|privilege| !TABLE_PRIVILEGES.contains(&privilege.as_str()))
+    {
+        return Err(PgcError::usage(format!(
+            "❌ Invalid privilege '{}' for {}, expected one of {}",
+            invalid,
+            role,
+            TABLE_PRIVILEGES.join(", ")
+        )));
     }
+    let grantee = if role.eq_ignore_ascii_case("public") {
+        "PUBLIC".to_string()
+    } else {
+        sql::ident(role)?
+    };
+    Ok(format!(
+        "GRANT {} ON TABLE {} TO {}",
+        privileges.join(", "),
+        sql::ident(table_name)?,
+        grantee
+    ))
+}
 
-    /// Resolve version mismatch error
-    ///WARNING:  database "postgres_db" has a collation version mismatch
-    ///DETAIL:  The database was created using collation version 2.36, but the operating system provides version 2.31.
-    ///HINT:  Rebuild all objects in this database that use the default collation and run ALTER DATABASE postgres_db REFRESH COLLATION VERSION, or build PostgreSQL with the right library version.
-    async fn fix_collation_version(&self, table_name: &str) {
-        let query = format!("ALTER DATABASE {} REFRESH COLLATION VERSION", table_name).to_string();
-        self.execute(
-            query,
-            Some("✅ Collation version fixed successfully"),
-            Some("❌ Failed to fix collation version"),
+/// Grant privileges on a table, naming the grant in the error if it fails.
+async fn apply_grant(
+    client: &Client,
+    table_name: &str,
+    role: &str,
+    privileges: &str,
+) -> Result<(), PgcError> {
+    let statement = grant_statement(table_name, role, privileges)?;
+    match client.batch_execute(&statement).await {
+        Ok(()) => {
+            info!("🔑 Granted {} on {} to {}", privileges, table_name, role);
+            Ok(())
+        }
+        Err(e) => {
+            error!("❌ Failed to apply `{}`: {}", statement, e);
+            Err(PgcError::query(statement, e))
+        }
+    }
+}
+
+/// Primary key columns of an existing table, with their types as `format_type` writes them.
+async fn primary_key_columns(
+    client: &Client,
+    table_name: &str,
+) -> Result<Vec<(String, String)>, PgcError> {
+    let rows = client
+        .query(
+            "SELECT a.attname::text, format_type(a.atttypid, a.atttypmod)
+            FROM pg_index i
+            JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey)
+            WHERE i.indrelid = to_regclass($1) AND i.indisprimary
+            ORDER BY a.attnum",
+            &[&sql::ident(table_name)?],
         )
-        .await;
+        .await?;
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+/// Whether existing primary key columns are those `primary_key` creates.
+fn primary_key_matches(primary_key: &PrimaryKey, columns: &[(String, String)]) -> bool {
+    let [(column, column_type)] = columns else {
+        return false;
+    };
+    match primary_key {
+        PrimaryKey::Uuid => column == "id" && column_type == "uuid",
+        PrimaryKey::NameAsPk => column == "name",
+        PrimaryKey::SerialId => column == "id" && column_type == "bigint",
+        PrimaryKey::Property(key, pg_type) => {
+            column == key && column_type.eq_ignore_ascii_case(pg_type.format_type_name())
+        }
     }
+}
 
-    async fn create_geo_table(&self, client: &Client, table_name: &str) -> Result<(), Error> {
-        println!("⏳ Attempting to create table: {}", table_name);
-        client
-            .batch_execute("CREATE EXTENSION IF NOT EXISTS postgis;")
-            .await?;
+/// SQL expression for a point guaranteed to lie on `geometry`.
+fn centroid_expression(geometry: &str) -> String {
+    format!("ST_PointOnSurface({})", geometry)
+}
 
-        client
-            .batch_execute(&format!(
-                "CREATE TABLE IF NOT EXISTS {} (
-                id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-                name VARCHAR(512) NOT NULL UNIQUE,
-                properties JSONB NOT NULL,
-                geometry GEOMETRY,
-                created_at TIMESTAMPTZ DEFAULT NOW()
-            );
-            CREATE INDEX {}_properties_idx ON {} USING GIN (properties);",
-                table_name, table_name, table_name
-            ))
-            .await?;
-        println!("✅ Table {} created successfully", table_name);
-        Ok(())
+/// Statements creating the trigger that keeps `bbox` and/or `centroid` up to
+/// date, used where generated columns can't be.
+fn derived_geometry_trigger(
+    table_name: &str,
+    bbox: bool,
+    centroid: bool,
+    srid: Option<i32>,
+    kind: ColumnKind,
+) -> Result<Vec<String>, PgcError> {
+    let function_name = sql::ident(&format!("{}_derived_geometry", table_name))?;
+    let geometry = planar_expression("NEW.geometry", kind);
+    let mut assignments = Vec::new();
+    if bbox {
+        assignments.push(format!("NEW.bbox := {};", bbox_expression(&geometry, srid)));
     }
+    if centroid {
+        assignments.push(format!(
+            "NEW.centroid := {};",
+            centroid_expression(&geometry)
+        ));
+    }
+    Ok(vec![
+        format!(
+            "CREATE OR REPLACE FUNCTION {}() RETURNS trigger AS $$
+            BEGIN
+                {}
+                RETURN NEW;
+            END
+            $$ LANGUAGE plpgsql;",
+            function_name,
+            assignments.join("\n                ")
+        ),
+        format!(
+            "DROP TRIGGER IF EXISTS {} ON {};",
+            function_name,
+            sql::ident(table_name)?
+        ),
+        format!(
+            "CREATE TRIGGER {} BEFORE INSERT OR UPDATE OF geometry ON {}
+            FOR EACH ROW EXECUTE PROCEDURE {}();",
+            function_name,
+            sql::ident(table_name)?,
+            function_name
+        ),
+    ])
+}
 
-    async fn backup_database(
-        &self,
-        output_dir: &str,
-        no_of_jobs: Option<i32>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+/// How `insert_geojson_from_reader` names the stream it reads.
+const STREAM_SOURCE: &str = "stdin";
+
+/// Checks the options of a load into `table_name` are consistent, before
+/// anything is loaded.
+fn check_ingest_options(
+    table_name: &str,
+    table_options: &GeoTableOptions,
+    ingest_options: &IngestOptions,
+) -> Result<(), String> {
+    if table_name.len() > sql::MAX_IDENTIFIER_BYTES {
+        return Err(format!(
+            "❌ {} is longer than the {} bytes Postgres keeps of a name",
+            table_name,
+            sql::MAX_IDENTIFIER_BYTES
+        ));
+    }
+    table_options
+        .validate()
+        .and_then(|()| ingest_options.validate())
+        .and_then(|()| match table_options.primary_key {
+            PrimaryKey::Property(_, _) if ingest_options.explode_multi => Err(
+                "❌ explode_multi cannot be used with PrimaryKey::Property, the rows of a feature \
+                would share its key"
+                    .to_string(),
+            ),
+            _ => Ok(()),
+        })
+}
 
-        let db_config = Read::config_data().config;
-        let no_of_jobs = no_of_jobs.unwrap_or(4);
+/// Get `table_name` ready for a load in `mode`: PostGIS, the table itself if
+/// missing, and a primary key matching the table options. Returns whether the
+/// table existed before.
+async fn prepare_ingest_table(
+    queries: &PostgresQueriesWrite,
+    connection: &IngestConnection,
+    table_name: &str,
+    mode: LoadMode,
+    table_options: &GeoTableOptions,
+) -> Result<bool, PgcError> {
+    // PostGIS is required for the geometry column, so there is no point going on without it
+    queries
+        .ensure_postgis_extension(connection.client())
+        .await?;
 
-        let output_file = format!(
-            "{}/backup_{}_{}.dump",
-            output_dir, db_config.db_name, timestamp
-        );
-        println!("🔄 Attempting to backup {} database", &db_config.db_name);
-        println!("🕒 Backup timestamp: {}", timestamp);
-
-        // Set password in environment variable
-        std::env::set_var("PGPASSWORD", &db_config.password);
-
-        let command = format!(
-            "pg_dump \
-            --host={} --port={} --username={} --dbname={} \
-            --jobs={} \
-            --format=custom \
-            --no-privileges \
-            --no-owner \
-            --exclude-table='geometry_columns' \
-            --exclude-table='spatial_ref_sys' \
-            --exclude-table='raster_columns' \
-            --exclude-table='raster_overviews' \
-            --file={}",
-            db_config.host,
-            db_config.port,
-            db_config.user,
-            db_config.db_name,
-            no_of_jobs,
-            output_file
+    // Checked before creating the table: unchanged files are reloaded into a table dropped since
+    let table_existed: bool = connection
+        .client()
+        .query_one(
+            "SELECT to_regclass($1) IS NOT NULL",
+            &[&sql::ident(table_name)?],
+        )
+        .await?
+        .get(0);
+
+    // Create table if it doesn't exist, with its grants. Failing to is the error of the load, not
+    // a primary key mismatch found next
+    queries
+        .create_geo_table(connection.client(), table_name, table_options)
+        .await?;
+    // A dry run did not drop the table to replace nor create the missing one, there is no key to
+    // check
+    if queries.dry_run.is_some() && (mode == LoadMode::Replace || !table_existed) {
+        return Ok(false);
+    }
+    // An existing table keeps its primary key, which the rows must match
+    let key_columns = primary_key_columns(connection.client(), table_name).await?;
+    if !primary_key_matches(&table_options.primary_key, &key_columns) {
+        let existing: Vec<String> = key_columns
+            .iter()
+            .map(|(column, column_type)| format!("{} {}", column, column_type))
+            .collect();
+        return Err(PgcError::usage(format!(
+            "❌ {} has primary key ({}), but the table options ask for {}. Load with a matching \
+            GeoTableOptions::primary_key or into a new table",
+            table_name,
+            existing.join(", "),
+            table_options.primary_key
+        )));
+    }
+    Ok(table_existed)
+}
+
+/// The table a load in `mode` writes into: `table_name` itself when it
+/// appends, a staging table `finish_replace` swaps into it when it replaces,
+/// so a load that fails leaves `table_name` as it was. The staging table is
+/// named for this load alone, it can be no table of the user's nor of
+/// another load.
+fn load_table_name(table_name: &str, mode: LoadMode) -> String {
+    match mode {
+        LoadMode::Append => table_name.to_string(),
+        LoadMode::Replace => sql::unique_name("_pgc_replace"),
+    }
+}
+
+/// Drop the staging table of a replacing load, `load_table`, after a load
+/// that failed.
+async fn drop_staging_table(
+    queries: &PostgresQueriesWrite,
+    connection: &IngestConnection,
+    load_table: &str,
+) -> Result<(), PgcError> {
+    let statement = format!("DROP TABLE IF EXISTS {} CASCADE", sql::ident(load_table)?);
+    if queries.planned(statement.as_str()) {
+        return Ok(());
+    }
+    connection
+        .client()
+        .batch_execute(&statement)
+        .await
+        .map_err(|e| PgcError::query(statement, e))
+}
+
+/// Swap the staging table `load_table` of a load in `mode` into
+/// `table_name`, once every file is loaded. Appending loads write into
+/// `table_name` directly, there is nothing to swap.
+async fn finish_replace(
+    queries: &PostgresQueriesWrite,
+    load_table: &str,
+    table_name: &str,
+    mode: LoadMode,
+) -> Result<(), PgcError> {
+    if mode == LoadMode::Append {
+        return Ok(());
+    }
+    queries.atomic_swap(load_table, table_name).await
+}
+
+/// Record a loaded file in the `_imports` table. Failing to is a warning of
+/// the file, as its features are loaded already.
+async fn record_loaded_file(
+    client: &Client,
+    mut record: ImportRecord,
+    file_report: &mut FileReport,
+) {
+    record.feature_count = file_report.features_loaded as i64;
+    record.duration_ms = file_report.duration_ms as i64;
+    if let Err(e) = manifest::record_import(client, &record).await {
+        warn!(
+            "⚠️ Failed to record the import of {} in {}: {}",
+            file_report.path,
+            manifest::IMPORTS_TABLE,
+            e
         );
+        file_report.warnings.push(format!(
+            "Failed to record the import in {}: {}",
+            manifest::IMPORTS_TABLE,
+            e
+        ));
+    }
+}
+
+/// What follows a load that changed `table_name`: `ANALYZE` and refreshing
+/// its overviews, as the options ask, then the `post_ingest` hooks.
+async fn finish_ingest(
+    queries: &PostgresQueriesWrite,
+    table_name: &str,
+    ingest_options: &IngestOptions,
+    report: &mut IngestReport,
+) -> Result<(), PgcError> {
+    let changed = report.files.iter().any(|file| !file.unchanged);
+    if let (Some(observer), true) = (&ingest_options.observer, changed) {
+        if ingest_options.analyze || ingest_options.refresh_overviews {
+            observer.indexing_started(table_name);
+        }
+    }
+    if ingest_options.analyze && changed {
+        queries.analyze(table_name).await?;
+    }
+    if ingest_options.refresh_overviews && changed {
+        queries.refresh_overviews(table_name).await?;
+    }
+    report.warnings.extend(
+        queries
+            .run_hooks(HookStage::PostIngest, Some(table_name))
+            .await?,
+    );
+    Ok(())
+}
 
-        println!("💻 Executing command: {}", command);
-        println!("⏳ Running pg_dump...");
+/// Where the features of a load come from.
+///
+/// * `Path`: the GeoJSON file at a path, or those of a directory.
+/// * `Stream`: a GeoJSON stream, named `STREAM_SOURCE`.
+enum LoadSource<'a> {
+    Path(&'a str),
+    Stream(Box<dyn AsyncRead + Send + Unpin>),
+}
 
-        match tokio::process::Command::new("sh")
-            .arg("-c")
-            .arg(command)
-            .status()
-            .await
-        {
-            Ok(status) if status.success() => {
-                println!(
-                    "✅ Database '{}' backed up to {}",
-                    db_config.db_name, output_file
-                );
-                Ok(())
+/// Load the features of `source` into `table_name` in `mode`, the load of
+/// `insert_geojson` and `insert_geojson_from_reader`: the table is prepared,
+/// the features uploaded, swapped into the table when they replace it, and
+/// the load finished with `finish_ingest`. The files loaded before a failure
+/// stay in the report returned with the error.
+async fn load_geojson(
+    queries: &PostgresQueriesWrite,
+    source: LoadSource<'_>,
+    table_name: &str,
+    mode: LoadMode,
+    table_options: &GeoTableOptions,
+    ingest_options: &IngestOptions,
+) -> Result<IngestReport, IngestError> {
+    let mut report = IngestReport {
+        table: table_name.to_string(),
+        ..Default::default()
+    };
+    if let Err(e) = check_ingest_options(table_name, table_options, ingest_options) {
+        return Err(IngestError {
+            report,
+            source: PgcError::usage(e),
+        });
+    }
+    if ingest_options.insert_strategy == InsertStrategy::Copy {
+        info!(
+            "⚙️  COPY connections per file: {}",
+            ingest_options.parallel_copy
+        );
+    }
+
+    let result: Result<(), PgcError> = async {
+        // Resolved before the table is touched, a bad path changes nothing
+        let file_paths = match &source {
+            LoadSource::Path(path) => {
+                let file_paths = get_geojson_file_paths(path).await?;
+                if file_paths.is_empty() {
+                    return Err(PgcError::invalid_input(format!(
+                        "❌ No GeoJSON files found in {}",
+                        path
+                    )));
+                }
+                file_paths
             }
-            Ok(_) => {
-                eprintln!("❌ Failed to backup database '{}'", db_config.db_name);
-                Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Database backup failed",
-                )))
+            LoadSource::Stream(_) => Vec::new(),
+        };
+        let pool = queries.pool.clone();
+        let mut connection = IngestConnection::new(pool).await?;
+        queries.metrics.record_pool(&queries.pool);
+        let load_table = load_table_name(table_name, mode);
+        let loaded = async {
+            let table_existed =
+                prepare_ingest_table(queries, &connection, &load_table, mode, table_options)
+                    .await?;
+            match source {
+                LoadSource::Path(_) => {
+                    load_files(
+                        queries,
+                        &mut connection,
+                        &file_paths,
+                        table_name,
+                        &load_table,
+                        table_existed,
+                        table_options,
+                        ingest_options,
+                        &mut report,
+                    )
+                    .await?
+                }
+                LoadSource::Stream(reader) => {
+                    load_stream(
+                        queries,
+                        &mut connection,
+                        reader,
+                        table_name,
+                        &load_table,
+                        table_options,
+                        ingest_options,
+                        &mut report,
+                    )
+                    .await?
+                }
             }
-            Err(e) => {
-                eprintln!(
-                    "❌ Error backing up database '{}': {}",
-                    db_config.db_name, e
+            finish_replace(queries, &load_table, table_name, mode).await
+        }
+        .await;
+        if let (Err(_), LoadMode::Replace) = (&loaded, mode) {
+            if let Err(e) = drop_staging_table(queries, &connection, &load_table).await {
+                warn!("⚠️ Failed to drop the staging table {}: {}", load_table, e);
+            }
+        }
+        loaded?;
+
+        finish_ingest(queries, table_name, ingest_options, &mut report).await
+    }
+    .await;
+
+    report.print_summary();
+    match result {
+        Ok(()) => Ok(report),
+        Err(source) => Err(IngestError { report, source }),
+    }
+}
+
+/// Upload the GeoJSON files `file_paths` into `load_table`, the table of a
+/// load into `table_name`, which existed before it when `table_existed`.
+/// Each file loaded is added to `report`.
+#[allow(clippy::too_many_arguments)]
+async fn load_files(
+    queries: &PostgresQueriesWrite,
+    connection: &mut IngestConnection,
+    file_paths: &[String],
+    table_name: &str,
+    load_table: &str,
+    table_existed: bool,
+    table_options: &GeoTableOptions,
+    ingest_options: &IngestOptions,
+    report: &mut IngestReport,
+) -> Result<(), PgcError> {
+    let skip_unchanged = ingest_options.skip_unchanged && !ingest_options.force;
+
+    let mut dedupe_state = DedupeState::new(ingest_options.dedupe);
+    for file_path in file_paths {
+        let record = if ingest_options.record_import || skip_unchanged {
+            Some(manifest::describe_file(
+                table_name,
+                file_path,
+                table_options.effective_srid(),
+            )?)
+        } else {
+            None
+        };
+        if let (true, Some(record)) = (skip_unchanged, &record) {
+            let last_hash =
+                manifest::last_import_hash(connection.client(), table_name, &record.source_path)
+                    .await?;
+            if last_hash.as_deref() == Some(record.sha256.as_str()) {
+                if table_existed {
+                    info!(
+                        "⏭️ {} is unchanged since its last import, skipping",
+                        file_path
+                    );
+                    report.files.push(FileReport {
+                        path: file_path.clone(),
+                        bytes: record.file_size as u64,
+                        unchanged: true,
+                        ..Default::default()
+                    });
+                    continue;
+                }
+                info!(
+                    "🔄 {} is unchanged, but {} was dropped since its last import, reloading",
+                    file_path, table_name
                 );
-                Err(Box::new(e))
             }
         }
+        if queries.planned(planned_upload(
+            load_table,
+            table_options,
+            ingest_options,
+            file_path,
+        )?) {
+            report.files.push(FileReport {
+                path: file_path.clone(),
+                bytes: std::fs::metadata(file_path)
+                    .map(|metadata| metadata.len())
+                    .unwrap_or_default(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let mut file_report = process_and_upload_file(
+            connection,
+            file_path,
+            load_table,
+            table_options,
+            ingest_options,
+            &mut dedupe_state,
+            &queries.cancellation,
+        )
+        .await?;
+        if let (true, Some(record)) = (ingest_options.record_import, record) {
+            record_loaded_file(connection.client(), record, &mut file_report).await;
+        }
+        report.warnings.extend(
+            file_report
+                .warnings
+                .iter()
+                .map(|warning| format!("{}: {}", file_path, warning)),
+        );
+        queries.metrics.record_file(&file_report);
+        report.files.push(file_report);
+    }
+    if ingest_options.dedupe != Dedupe::None {
+        info!(
+            "🧹 {} duplicate feature names encountered, strategy applied: {:?}",
+            dedupe_state.duplicates, ingest_options.dedupe
+        );
     }
+    Ok(())
+}
 
-    async fn restore_database(
-        &self,
-        dump_file: &str,
-        docker_container_name: Option<&str>,
-        no_of_jobs: Option<i32>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let start_time = Instant::now();
-        let db_config = Read::config_data().config;
-        let no_of_jobs = no_of_jobs.unwrap_or(4);
-
-        println!(
-            "🔄 Attempting to restore database unto {}",
-            &db_config.db_name
+/// Upload the GeoJSON stream `reader`, named `STREAM_SOURCE`, into
+/// `load_table`, the table of a load into `table_name`, and add it to
+/// `report`.
+#[allow(clippy::too_many_arguments)]
+async fn load_stream(
+    queries: &PostgresQueriesWrite,
+    connection: &mut IngestConnection,
+    reader: Box<dyn AsyncRead + Send + Unpin>,
+    table_name: &str,
+    load_table: &str,
+    table_options: &GeoTableOptions,
+    ingest_options: &IngestOptions,
+    report: &mut IngestReport,
+) -> Result<(), PgcError> {
+    if queries.planned(planned_upload(
+        load_table,
+        table_options,
+        ingest_options,
+        STREAM_SOURCE,
+    )?) {
+        report.files.push(FileReport {
+            path: STREAM_SOURCE.to_string(),
+            ..Default::default()
+        });
+        return Ok(());
+    }
+    let mut dedupe_state = DedupeState::new(ingest_options.dedupe);
+    let (mut file_report, digest) = process_and_upload_stream(
+        connection,
+        reader,
+        STREAM_SOURCE,
+        load_table,
+        table_options,
+        ingest_options,
+        &mut dedupe_state,
+        &queries.cancellation,
+    )
+    .await?;
+    if ingest_options.record_import {
+        let record = manifest::describe_stream(
+            table_name,
+            STREAM_SOURCE,
+            table_options.effective_srid(),
+            digest.bytes,
+            digest.sha256,
         );
+        record_loaded_file(connection.client(), record, &mut file_report).await;
+    }
+    report.warnings.extend(
+        file_report
+            .warnings
+            .iter()
+            .map(|warning| format!("{}: {}", STREAM_SOURCE, warning)),
+    );
+    queries.metrics.record_file(&file_report);
+    report.files.push(file_report);
+    Ok(())
+}
 
-        let _ = self.drop_all_tables().await?;
+/// The report of a load, for its observer.
+fn ingest_report(report: &IngestReport) -> OperationReport {
+    OperationReport::Ingest(Box::new(report.clone()))
+}
 
-        // Detect system memory and set appropriate values
-        let total_memory = sys_info::mem_info()
-            .map(|info| info.total)
-            .unwrap_or(16 * 1024 * 1024); // Default to 16GB if detection fails
+/// The options of the loads of `insert_geojson`, for the audit log.
+fn audit_load(options: Option<&InsertGeoJsonOptions>) -> String {
+    let options = options.cloned().unwrap_or_default();
+    format!(
+        "table={} mode={:?}",
+        options.table.as_deref().unwrap_or("(file name)"),
+        options.mode
+    )
+}
 
-        let work_mem = if total_memory >= 64 * 1024 * 1024 {
-            // 64GB
-            "512MB"
-        } else if total_memory >= 32 * 1024 * 1024 {
-            // 32GB
-            "256MB"
-        } else {
-            // 16GB or less
-            "128MB"
-        };
+/// A filter of the rows to delete or update, for the audit log.
+fn audit_filter(filter: &FeatureFilter, dry_run: bool) -> String {
+    format!("{} dry_run={}", planned_filter(filter), dry_run)
+}
 
-        let maintenance_work_mem = if total_memory >= 64 * 1024 * 1024 {
-            // 64GB
-            "1GB"
-        } else if total_memory >= 32 * 1024 * 1024 {
-            // 32GB
-            "512MB"
-        } else {
-            // 16GB or less
-            "256MB"
-        };
+/// The values of the parameters of `filter`, in the comment of a planned
+/// statement.
+fn planned_filter(filter: &FeatureFilter) -> String {
+    format!(
+        "filter={}",
+        serde_json::to_string(filter).unwrap_or_default()
+    )
+}
 
-        // Set password in environment variable
-        std::env::set_var("PGPASSWORD", &db_config.password);
-        std::env::set_var("PGWORKMEM", work_mem);
-        std::env::set_var("PGMAINTENANCE_WORK_MEM", maintenance_work_mem);
-
-        println!("💾 Detected memory: {}KB", total_memory);
-        println!("⚙️  Using WORK_MEM: {}", work_mem);
-        println!("⚙️  Using MAINTENANCE_WORK_MEM: {}", maintenance_work_mem);
-        println!("⚙️  Total number of jobs: {}", &no_of_jobs);
-        // Step 1: Restore schema only
-        println!("📊 Step 1: Restoring schema...");
-
-        let mut schema_command: String = String::new();
-
-        if let Some(container) = docker_container_name {
-            println!("⚙️ Docker container specified: {}", container);
-            schema_command = format!(
-                "docker exec -i {} pg_restore \
-                    --host={} --port={} --username={} --dbname={} \
-                    --jobs={} \
-                    --schema-only \
-                    --clean \
-                    --if-exists \
-                    --no-acl \
-                    --no-comments \
-                    {}",
-                container,
-                db_config.host,
-                db_config.port,
-                db_config.user,
-                db_config.db_name,
-                no_of_jobs,
-                dump_file
-            );
-        } else {
-            println!("⚙️ No Docker container specified");
-            schema_command = format!(
-                "pg_restore \
-                    --host={} --port={} --username={} --dbname={} \
-                    --jobs={} \
-                    --schema-only \
-                    --clean \
-                    --if-exists \
-                    --no-acl \
-                    --no-comments \
-                    {}",
-                db_config.host,
-                db_config.port,
-                db_config.user,
-                db_config.db_name,
-                no_of_jobs,
-                dump_file
-            );
-        }
+/// The options of a restore, its pre-clean first, for the audit log.
+#[cfg(feature = "backup")]
+fn audit_restore(options: Option<&RestoreOptions>) -> String {
+    let options = options.cloned().unwrap_or_default();
+    format!(
+        "pre_clean={:?} target_db={} tables={:?} exclude_tables={:?}",
+        options.pre_clean,
+        options.target_db.as_deref().unwrap_or("(configured)"),
+        options.tables,
+        options.exclude_tables
+    )
+}
 
-        match tokio::process::Command::new("sh")
-            .arg("-c")
-            .arg(&schema_command)
-            .status()
-            .await
-        {
-            Ok(status) if status.success() => {
-                println!("✅ Schema restored successfully");
-            }
-            _ => {
-                return Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "❌ Schema restore failed",
-                )));
-            }
-        }
-
-        // Step 2: Restore data only
-        println!("\n\n📊 Phase 2: Restoring data...");
-        let data_command = format!(
-            "pg_restore \
-                --host={} --port={} --username={} --dbname={} \
-                --jobs={} \
-                --data-only \
-                --disable-triggers \
-                --no-acl \
-                --no-comments \
-                {}",
-            db_config.host,
-            db_config.port,
-            db_config.user,
-            db_config.db_name,
-            no_of_jobs,
-            dump_file
-        );
+/// The write queries on the database of a pool, each checking out a
+/// connection of it, and showing what they report, like the progress of a
+/// restore, to `reporter`, nothing by default.
+///
+/// Backups, restores and the statements run on another database connect
+/// with the settings of `config_file`, which also holds the hooks, backup
+/// exclusions, table defaults and restore memory, see `from_config_file`.
+/// Built with `new`, they have none: only the queries going through the
+/// pool work, without hooks.
+///
+/// The files loaded, backups and restores are measured in `metrics`, and
+/// the operations changing the database recorded in `audit_log`, when the
+/// config names one. With a `dry_run`, the destructive ones are planned
+/// rather than run, see `write::dry_run`.
+///
+/// The long operations stop at their next batch once `cancellation` is
+/// cancelled, rolling back what they did not commit and killing the client
+/// tools they run. It is never cancelled by default, and nothing here
+/// listens to signals: give each operation a token of its own with
+/// `with_cancellation` on a clone.
+#[derive(Clone)]
+pub struct PostgresQueriesWrite {
+    pool: Pool,
+    config_file: Option<Arc<ConfigFile>>,
+    reporter: Arc<dyn Reporter>,
+    metrics: Arc<Metrics>,
+    audit_log: Option<Arc<AuditLog>>,
+    dialect: Arc<OnceCell<Dialect>>,
+    dry_run: Option<Arc<DryRun>>,
+    cancellation: CancellationToken,
+}
 
-        println!("⏳ Running pg_restore...");
-        match tokio::process::Command::new("sh")
-            .arg("-c")
-            .arg(&data_command)
-            .status()
-            .await
-        {
-            Ok(status) if status.success() => {
-                let duration = start_time.elapsed();
-                println!(
-                    "✅ Database '{}' restored from {} in {:.2?}",
-                    db_config.db_name, dump_file, duration
-                );
-                Ok(())
-            }
-            Ok(_) => {
-                let duration = start_time.elapsed();
-                eprintln!(
-                    "❌ Failed to restore database '{}' after {:.2?}",
-                    db_config.db_name, duration
-                );
-                Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Database restore failed",
-                )))
-            }
-            Err(e) => {
-                let duration = start_time.elapsed();
-                eprintln!(
-                    "❌ Error restoring database '{}' after {:.2?}: {}",
-                    db_config.db_name, duration, e
-                );
-                Err(Box::new(e))
-            }
+impl PostgresQueriesWrite {
+    pub fn new(pool: Pool) -> Self {
+        Self {
+            pool,
+            config_file: None,
+            reporter: Arc::new(NoopReporter),
+            metrics: Arc::new(Metrics::default()),
+            audit_log: None,
+            dialect: Arc::new(OnceCell::new()),
+            dry_run: None,
+            cancellation: CancellationToken::new(),
         }
     }
 
-    /// Uploads a GeoJSON file to the database.
+    /// `new`, with a pool of connections to the database of `config`, see
+    /// `db::pool_of`, and its settings for what does not go through it.
+    pub fn from_config(config: Config) -> Result<Self, PgcError> {
+        Self::from_config_file(ConfigFile::from(config))
+    }
+
+    /// `from_config`, with the optional sections of `config_file` too.
+    ///
+    /// ```no_run
+    /// use postgres_connect_rust::{ConfigFile, PostgresQueriesWrite};
+    ///
+    /// # fn queries() -> Result<(), Box<dyn std::error::Error>> {
+    /// let write_queries =
+    ///     PostgresQueriesWrite::from_config_file(ConfigFile::load("files/config.toml")?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_config_file(config_file: ConfigFile) -> Result<Self, PgcError> {
+        let mut queries = Self::new(db::pool_of(&config_file.config)?);
+        queries.audit_log = config_file
+            .config
+            .audit_log
+            .as_deref()
+            .map(|path| Arc::new(AuditLog::new(path, &config_file.config)));
+        if let Some(dialect) = config_file.config.dialect {
+            queries = queries.with_dialect(dialect);
+        }
+        queries.config_file = Some(Arc::new(config_file));
+        Ok(queries)
+    }
+
+    /// Show what the queries report to `reporter`.
+    pub fn with_reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Where the queries show what they report.
+    pub fn reporter(&self) -> &Arc<dyn Reporter> {
+        &self.reporter
+    }
+
+    /// Record what the queries measure in `metrics`, to share it with
+    /// other query structs.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// What the queries measured, see `Metrics::snapshot`.
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// Record the operations changing the database in `audit_log`, rather
+    /// than in the `audit_log` of the config.
+    pub fn with_audit_log(mut self, audit_log: Arc<AuditLog>) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    /// Where the operations changing the database are recorded, if anywhere.
+    pub fn audit_log(&self) -> Option<&Arc<AuditLog>> {
+        self.audit_log.as_ref()
+    }
+
+    /// Run the queries as on a server of `dialect`, rather than detecting it.
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = Arc::new(OnceCell::new_with(Some(dialect)));
+        self
+    }
+
+    /// The dialect of the server, detected from `version()` on the first call
+    /// unless given, see `PostgresQueriesRead::dialect`.
+    pub async fn dialect(&self) -> Result<Dialect, PgcError> {
+        self.read_queries().dialect().await
+    }
+
+    /// Plan the statements of the destructive queries in `dry_run` rather
+    /// than running them, see `write::dry_run`.
+    pub fn with_dry_run(mut self, dry_run: Arc<DryRun>) -> Self {
+        self.dry_run = Some(dry_run);
+        self
+    }
+
+    /// Where the statements of a dry run are planned, if the queries are one.
+    pub fn dry_run(&self) -> Option<&Arc<DryRun>> {
+        self.dry_run.as_ref()
+    }
+
+    /// Stop the operations once `cancellation` is cancelled.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// The token stopping the operations once cancelled.
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// With a dry run, plan `statement` and return true, for the caller not
+    /// to run it.
+    fn planned(&self, statement: impl Into<String>) -> bool {
+        match &self.dry_run {
+            Some(dry_run) => {
+                dry_run.plan(statement.into());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The pool the queries check their connections out of.
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    /// The config the queries were built with, if any.
+    pub fn config_file(&self) -> Option<&ConfigFile> {
+        self.config_file.as_deref()
+    }
+
+    /// The settings of the connections not going through the pool, and of
+    /// `pg_dump` and `pg_restore`, failing when the queries have none.
+    fn config(&self) -> Result<Config, PgcError> {
+        match &self.config_file {
+            Some(config_file) => Ok(config_file.config.clone()),
+            None => Err(PgcError::Usage {
+                message: "❌ These queries need the settings of the connection: build them \
+                          with from_config or from_config_file"
+                    .to_string(),
+            }),
+        }
+    }
+
+    /// Run the hooks of the config for `stage`, none without a config, see
+    /// `hooks::run_hooks`.
+    async fn run_hooks(
+        &self,
+        stage: HookStage,
+        table_name: Option<&str>,
+    ) -> Result<Vec<String>, PgcError> {
+        let Some(config_file) = &self.config_file else {
+            return Ok(Vec::new());
+        };
+        if let Some(dry_run) = &self.dry_run {
+            for statement in hooks::planned_hooks(
+                &config_file.hooks,
+                &config_file.config.db_name,
+                stage,
+                table_name,
+            )? {
+                dry_run.plan(statement);
+            }
+            return Ok(Vec::new());
+        }
+        Ok(hooks::run_hooks(
+            &self.pool,
+            &config_file.hooks,
+            &config_file.config.db_name,
+            stage,
+            table_name,
+        )
+        .await?)
+    }
+
+    /// Count a restore that started at `start_time` in the metrics.
+    #[cfg(feature = "backup")]
+    fn record_restore(&self, start_time: Instant) {
+        self.metrics.increment(metrics::RESTORES, 1);
+        self.metrics.observe(
+            metrics::RESTORE_DURATION,
+            start_time.elapsed().as_secs_f64(),
+        );
+    }
+
+    /// `operation` on `target`, recorded in the audit log once done, see
+    /// `audited`. A dry run is not recorded.
+    fn audit(&self, operation: &str, target: &str) -> AuditRecord {
+        let audit_log = self.audit_log.clone().filter(|_| self.dry_run.is_none());
+        AuditRecord::start(audit_log, operation, target)
+    }
+
+    /// Run `operation`, recording it in the audit log with its outcome,
+    /// and what `summarize` tells of its result, like the rows it deleted.
+    /// A failure is recorded as well, before it is returned.
+    async fn audited<T, E, Fut>(
+        &self,
+        mut record: AuditRecord,
+        summarize: impl FnOnce(&mut AuditRecord, &T),
+        operation: Fut,
+    ) -> Result<T, E>
+    where
+        E: StdError + 'static,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let result = operation.await;
+        if let Ok(value) = &result {
+            summarize(&mut record, value);
+        }
+        record.finish(&result);
+        result
+    }
+
+    /// A connection of the pool.
+    async fn client(&self) -> Result<Object, PgcError> {
+        let client = db::checkout(&self.pool).await?;
+        self.metrics.record_pool(&self.pool);
+        Ok(client)
+    }
+
+    /// The read queries, on the same pool, reporting to the same reporter
+    /// and measured in the same metrics.
+    fn read_queries(&self) -> PostgresQueriesRead {
+        PostgresQueriesRead::new(self.pool.clone())
+            .with_reporter(self.reporter.clone())
+            .with_metrics(self.metrics.clone())
+            .with_dialect_cell(self.dialect.clone())
+            .with_cancellation(self.cancellation.clone())
+    }
+
+    /// `drop_many` of the tables of `schema`, or of the search path without
+    /// one, unaudited.
+    async fn drop_tables(
+        &self,
+        schema: Option<&str>,
+        tables: &[&str],
+        cascade: bool,
+        if_exists: bool,
+    ) -> Result<Vec<(String, DropOutcome)>, PgcError> {
+        if tables.is_empty() {
+            info!("🤗 No tables to drop");
+            return Ok(Vec::new());
+        }
+        for table in tables {
+            sql::ident(table)?;
+        }
+        let client = self.client().await?;
+
+        let names: Vec<String> = tables.iter().map(|t| t.to_string()).collect();
+        let rows = client
+            .query(
+                "SELECT to_regclass(coalesce(quote_ident($2::text) || '.', '') || quote_ident(t)) \
+                IS NOT NULL FROM unnest($1::text[]) AS t",
+                &[&names, &schema],
+            )
+            .await?;
+        let mut existing: Vec<String> = Vec::new();
+        let mut missing: Vec<String> = Vec::new();
+        for (name, row) in names.into_iter().zip(rows.iter()) {
+            if row.get::<_, bool>(0) {
+                existing.push(name);
+            } else {
+                missing.push(name);
+            }
+        }
+
+        let drop_statement = |names: &[String]| -> Result<String, PgcError> {
+            Ok(format!(
+                "DROP TABLE {}{}{}",
+                if if_exists { "IF EXISTS " } else { "" },
+                names
+                    .iter()
+                    .map(|name| match schema {
+                        Some(schema) => sql::qualified(schema, name),
+                        None => sql::ident(name),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(", "),
+                if cascade { " CASCADE" } else { "" }
+            ))
+        };
+
+        let mut outcomes: Vec<(String, DropOutcome)> = Vec::new();
+        // Missing tables are left out of the statement, so they can't make the others fail
+        for name in missing {
+            if if_exists {
+                info!("🤗 Table {} does not exist, skipping", name);
+            } else {
+                error!("❌ Table {} does not exist", name);
+            }
+            outcomes.push((name, DropOutcome::Missing));
+        }
+
+        if !existing.is_empty() && self.planned(drop_statement(&existing)?) {
+            outcomes.extend(
+                existing
+                    .into_iter()
+                    .map(|name| (name, DropOutcome::Planned)),
+            );
+        } else if !existing.is_empty() {
+            info!("🔄 Attempting to drop tables: {}", existing.join(", "));
+            match client.batch_execute(&drop_statement(&existing)?).await {
+                Ok(_) => {
+                    for name in existing {
+                        info!("✅ Dropped table: {}", name);
+                        outcomes.push((name, DropOutcome::Dropped));
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "⚠️ Dropping all tables at once failed, dropping them one by one: \
+                        {}",
+                        e
+                    );
+                    for name in existing {
+                        match client
+                            .batch_execute(&drop_statement(std::slice::from_ref(&name))?)
+                            .await
+                        {
+                            Ok(_) => {
+                                info!("✅ Dropped table: {}", name);
+                                outcomes.push((name, DropOutcome::Dropped));
+                            }
+                            Err(e) if e.code() == Some(&SqlState::UNDEFINED_TABLE) => {
+                                info!("🤗 Table {} does not exist anymore", name);
+                                outcomes.push((name, DropOutcome::Missing));
+                            }
+                            Err(e) => {
+                                outcomes.push((name, DropOutcome::Failed(e.to_string())));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Report the outcomes in the order the tables were given
+        outcomes.sort_by_key(|(name, _)| tables.iter().position(|t| t == name));
+
+        let failed: Vec<String> = outcomes
+            .iter()
+            .filter_map(|(name, outcome)| match outcome {
+                DropOutcome::Failed(e) => Some(format!("  - {}: {}", name, e)),
+                _ => None,
+            })
+            .collect();
+        if !failed.is_empty() {
+            error!(
+                "❌ Failed to drop {} tables:\n{}",
+                failed.len(),
+                failed.join("\n")
+            );
+        }
+
+        Ok(outcomes)
+    }
+}
+
+#[async_trait]
+impl DatabaseQueriesWrite for PostgresQueriesWrite {
+    /// Execute a SQL query.
+    ///
+    /// This function will attempt to execute a SQL query
+    /// in the database. If the query fails, an error message is printed
+    /// and the error returned.
+    async fn execute(
+        &self,
+        query: String,
+        success_message: Option<&str>,
+        error_message: Option<&str>,
+    ) -> Result<(), PgcError> {
+        if self.planned(query.as_str()) {
+            return Ok(());
+        }
+        let read_queries = self.read_queries();
+
+        match read_queries.execute(query).await {
+            Ok(_) => {
+                info!(
+                    "{}",
+                    success_message.unwrap_or("✅  Query executed successfully")
+                );
+                Ok(())
+            }
+            Err(e) => {
+                error!(
+                    "{}\n\n❌  Error executing query: {}",
+                    error_message.unwrap_or("Error executing query"),
+                    e
+                );
+                Err(e)
+            }
+        }
+    }
+
+    /// Drop a table in the database.
+    ///
+    /// This function will attempt to drop a table
+    /// in the database. If the table does not exist,
+    /// the function will silently exit. The `pre_drop` hooks of the
+    /// config file run first.
+    #[instrument(skip_all, fields(table = %table_name))]
+    async fn drop(&self, table_name: &str) -> Result<(), PgcError> {
+        self.audited(self.audit("drop", table_name), |_, _| {}, async {
+            let query = format!("DROP TABLE IF EXISTS {} CASCADE", sql::ident(table_name)?);
+            let read_queries = self.read_queries();
+
+            self.run_hooks(HookStage::PreDrop, Some(table_name)).await?;
+            if self.planned(query.as_str()) {
+                return Ok(());
+            }
+            info!("🔄 Attempting to drop table: {}", table_name);
+            match read_queries.execute(query).await {
+                Ok(_) => {
+                    info!("✅ {} table dropped successfully", table_name);
+                    Ok(())
+                }
+                Err(e) => {
+                    error!("❌ Failed to drop table: {}", e);
+                    Err(e)
+                }
+            }
+        })
+        .await
+    }
+
+    /// Drop several tables in one call.
+    ///
+    /// All tables are dropped with a single `DROP TABLE a, b, c` statement on
+    /// one connection. If that fails, each table is dropped on its own so the
+    /// ones that can be dropped still are, and the failures are reported
+    /// together. Returns the outcome for every requested table, telling
+    /// missing tables apart from the ones that failed to drop.
+    async fn drop_many(
+        &self,
+        tables: &[&str],
+        cascade: bool,
+        if_exists: bool,
+    ) -> Result<Vec<(String, DropOutcome)>, PgcError> {
+        self.audited(
+            self.audit("drop_many", &tables.join(", "))
+                .options(format!("cascade={} if_exists={}", cascade, if_exists)),
+            |record, outcomes: &Vec<(String, DropOutcome)>| {
+                record.set_tables(
+                    outcomes
+                        .iter()
+                        .filter(|(_, outcome)| matches!(outcome, DropOutcome::Dropped))
+                        .map(|(name, _)| name.clone())
+                        .collect(),
+                )
+            },
+            self.drop_tables(None, tables, cascade, if_exists),
+        )
+        .await
+    }
+
+    /// Drop all user tables in a schema.
+    ///
+    /// Defaults to the `public` schema when no schema is given. Tables listed
+    /// in `exclude` are preserved. With `dry_run`, nothing is dropped and the
+    /// tables that would have been dropped are printed and returned. With
+    /// `require_confirmation`, the user is asked to confirm when the process
+    /// is attached to a terminal.
+    ///
+    /// Returns the tables that were actually dropped.
+    #[instrument(skip_all, fields(schema = ?schema))]
+    async fn drop_all_tables(
+        &self,
+        schema: Option<&str>,
+        dry_run: bool,
+        exclude: &[&str],
+        require_confirmation: bool,
+    ) -> Result<Vec<String>, PgcError> {
+        self.audited(
+            self.audit("drop_all_tables", schema.unwrap_or("public"))
+                .options(format!(
+                    "dry_run={} exclude={:?} require_confirmation={}",
+                    dry_run, exclude, require_confirmation
+                )),
+            |record, dropped: &Vec<String>| record.set_tables(dropped.clone()),
+            async {
+                let read_queries = self.read_queries();
+                // let query = "DROP SCHEMA public CASCADE; CREATE SCHEMA public;";
+                let schema = custom_unwrap_or(schema, "public", "schema");
+
+                let tables: Vec<String> = read_queries
+                    .list_tables_in_schema(schema, Some(true))
+                    .await?
+                    .into_iter()
+                    .filter(|table_name| !exclude.contains(&table_name.as_str()))
+                    .collect();
+
+                if tables.is_empty() {
+                    info!("🤗 No tables to drop");
+                    return Ok(Vec::new());
+                }
+
+                info!("Found {} tables to drop", tables.len());
+                if !exclude.is_empty() {
+                    info!("🛡️  Preserving excluded tables: {}", exclude.join(", "));
+                }
+
+                if let Some(planned) = &self.dry_run {
+                    for table_name in &tables {
+                        planned.plan(format!(
+                            "DROP TABLE {} CASCADE",
+                            sql::qualified(schema, table_name)?
+                        ));
+                    }
+                    return Ok(tables);
+                }
+                if dry_run {
+                    for table_name in &tables {
+                        info!("🔍 Dry run: would drop table: {}", table_name);
+                    }
+                    return Ok(tables);
+                }
+
+                if require_confirmation && prompt::is_interactive() {
+                    let action = format!("Drop {} tables from schema {}", tables.len(), schema);
+                    if !confirm_destruction(
+                        &action,
+                        &self.config()?,
+                        schema,
+                        &tables,
+                        self.reporter.as_ref(),
+                    )
+                    .await?
+                    {
+                        return Err(PgcError::NotConfirmed {
+                            message: "❌ Dropping all tables was not confirmed, aborting"
+                                .to_string(),
+                        });
+                    }
+                }
+
+                info!("🔄 Attempting to drop all tables");
+
+                let drop_futures = tables
+                    .into_iter()
+                    .map(|table_name| {
+                        // let read_queries = read_queries.clone(); // Clone for each closure
+                        let read_queries = self.read_queries();
+
+                        let drop_query = format!(
+                            "DROP TABLE {} CASCADE",
+                            sql::qualified(schema, &table_name)?
+                        );
+
+                        debug!("🔄 Scheduling drop for table: {}", table_name);
+                        Ok(async move {
+                            match read_queries.execute(drop_query).await {
+                                Ok(_) => {
+                                    info!("✅ Dropped table: {:?}", &table_name);
+                                    Some(table_name)
+                                }
+                                Err(e) => {
+                                    error!("❌ Failed to drop table {:?}: {}", &table_name, e);
+                                    None
+                                }
+                            }
+                        })
+                    })
+                    .collect::<Result<Vec<_>, PgcError>>()?;
+
+                let dropped: Vec<String> = futures::future::join_all(drop_futures)
+                    .await
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                info!("✅ Dropped {} tables", dropped.len());
+
+                Ok(dropped)
+            },
+        )
+        .await
+    }
+
+    /// Create a schema in the database.
+    ///
+    /// The schema is only created if it does not exist yet. If `authorization`
+    /// is given, that role becomes the owner of the schema.
+    async fn create_schema(
+        &self,
+        schema_name: &str,
+        authorization: Option<&str>,
+    ) -> Result<(), PgcError> {
+        let mut query = format!("CREATE SCHEMA IF NOT EXISTS {}", sql::ident(schema_name)?);
+        if let Some(role) = authorization {
+            query.push_str(&format!(" AUTHORIZATION {}", sql::ident(role)?));
+        }
+        if self.planned(query.as_str()) {
+            return Ok(());
+        }
+        let read_queries = self.read_queries();
+
+        info!("🔄 Attempting to create schema: {}", schema_name);
+        match read_queries.execute(query).await {
+            Ok(_) => {
+                info!("✅ {} schema created successfully", schema_name);
+                Ok(())
+            }
+            Err(e) => {
+                error!("❌ Failed to create schema: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Drop a schema in the database.
+    ///
+    /// Without `cascade`, a schema that still contains tables is not dropped;
+    /// the returned error lists the tables that are blocking it instead.
+    async fn drop_schema(&self, schema_name: &str, cascade: bool) -> Result<(), PgcError> {
+        self.audited(
+            self.audit("drop_schema", schema_name)
+                .options(format!("cascade={}", cascade)),
+            |_, _| {},
+            async {
+                let read_queries = self.read_queries();
+
+                info!("🔄 Attempting to drop schema: {}", schema_name);
+                if !cascade {
+                    let tables = read_queries
+                        .list_tables_in_schema(schema_name, Some(false))
+                        .await?;
+                    if !tables.is_empty() {
+                        let err_msg = format!(
+                            "❌ Schema {} is not empty, use cascade to drop it along with its {} \
+                            tables: {}",
+                            schema_name,
+                            tables.len(),
+                            tables.join(", ")
+                        );
+                        error!("{}", err_msg);
+                        return Err(PgcError::usage(err_msg));
+                    }
+                }
+
+                let query = format!(
+                    "DROP SCHEMA IF EXISTS {}{}",
+                    sql::ident(schema_name)?,
+                    if cascade { " CASCADE" } else { "" }
+                );
+                if self.planned(query.as_str()) {
+                    return Ok(());
+                }
+                match read_queries.execute(query).await {
+                    Ok(_) => {
+                        info!("✅ {} schema dropped successfully", schema_name);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!("❌ Failed to drop schema: {}", e);
+                        Err(e)
+                    }
+                }
+            },
+        )
+        .await
+    }
+
+    /// Truncate a table in the database.
+    ///
+    /// Unlike `drop`, this keeps the table definition, its indexes and grants,
+    /// and only removes the rows. `restart_identity` resets sequences owned by
+    /// the table's columns, and `cascade` also truncates tables that reference
+    /// it through foreign keys.
+    #[instrument(skip_all, fields(table = %table_name))]
+    async fn truncate(
+        &self,
+        table_name: &str,
+        restart_identity: bool,
+        cascade: bool,
+    ) -> Result<(), PgcError> {
+        self.audited(
+            self.audit("truncate", table_name).options(format!(
+                "restart_identity={} cascade={}",
+                restart_identity, cascade
+            )),
+            |_, _| {},
+            async {
+                let query = format!(
+                    "TRUNCATE TABLE {}{}{}",
+                    sql::ident(table_name)?,
+                    if restart_identity {
+                        " RESTART IDENTITY"
+                    } else {
+                        ""
+                    },
+                    if cascade { " CASCADE" } else { "" }
+                );
+                let read_queries = self.read_queries();
+
+                if self.planned(query.as_str()) {
+                    return Ok(());
+                }
+                info!("🔄 Attempting to truncate table: {}", table_name);
+                match read_queries.execute(query).await {
+                    Ok(_) => {
+                        info!("✅ {} table truncated successfully", table_name);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!("❌ Failed to truncate table: {}", e);
+                        Err(e)
+                    }
+                }
+            },
+        )
+        .await
+    }
+
+    /// Rename a table in the database.
+    async fn rename_table(&self, old_name: &str, new_name: &str) -> Result<(), PgcError> {
+        self.audited(
+            self.audit("rename_table", old_name)
+                .options(format!("new_name={}", new_name)),
+            |_, _| {},
+            async {
+                let query = format!(
+                    "ALTER TABLE {} RENAME TO {}",
+                    sql::ident(old_name)?,
+                    sql::ident(new_name)?
+                );
+                let read_queries = self.read_queries();
+
+                if self.planned(query.as_str()) {
+                    return Ok(());
+                }
+                info!("🔄 Attempting to rename table {} to {}", old_name, new_name);
+                match read_queries.execute(query).await {
+                    Ok(_) => {
+                        info!("✅ Table {} renamed to {}", old_name, new_name);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!("❌ Failed to rename table: {}", e);
+                        Err(e)
+                    }
+                }
+            },
+        )
+        .await
+    }
+
+    /// Atomically replace `target_table` with `staging_table`.
+    ///
+    /// All steps run inside one transaction, so readers see either the old
+    /// target or the new one, never a missing table: target → a name of its
+    /// own, staging → target, drop the old target. That name is generated,
+    /// so no table of the database is dropped in its place.
+    /// The staging table's properties and geometry indexes are renamed along
+    /// with it so the next staging load can create its indexes again.
+    async fn atomic_swap(&self, staging_table: &str, target_table: &str) -> Result<(), PgcError> {
+        self.audited(
+            self.audit("atomic_swap", target_table)
+                .options(format!("staging_table={}", staging_table)),
+            |_, _| {},
+            async {
+                for name in [staging_table, target_table] {
+                    if name.len() > sql::MAX_IDENTIFIER_BYTES {
+                        return Err(PgcError::invalid_input(format!(
+                            "❌ {} is longer than the {} bytes Postgres keeps of a name",
+                            name,
+                            sql::MAX_IDENTIFIER_BYTES
+                        )));
+                    }
+                }
+                let old_table = sql::unique_name("_pgc_swap_old");
+                let statements = [
+                    format!(
+                        "ALTER TABLE IF EXISTS {} RENAME TO {}",
+                        sql::ident(target_table)?,
+                        sql::ident(&old_table)?
+                    ),
+                    format!(
+                        "ALTER TABLE {} RENAME TO {}",
+                        sql::ident(staging_table)?,
+                        sql::ident(target_table)?
+                    ),
+                    format!("DROP TABLE IF EXISTS {} CASCADE", sql::ident(&old_table)?),
+                    format!(
+                        "ALTER INDEX IF EXISTS {} RENAME TO {}",
+                        sql::ident(&format!("{}_properties_idx", staging_table))?,
+                        sql::ident(&format!("{}_properties_idx", target_table))?
+                    ),
+                    format!(
+                        "ALTER INDEX IF EXISTS {} RENAME TO {}",
+                        sql::ident(&format!("{}_geometry_idx", staging_table))?,
+                        sql::ident(&format!("{}_geometry_idx", target_table))?
+                    ),
+                ];
+                if let Some(dry_run) = &self.dry_run {
+                    for statement in statements {
+                        dry_run.plan(statement);
+                    }
+                    return Ok(());
+                }
+
+                info!(
+                    "🔄 Attempting to swap table {} into {}",
+                    staging_table, target_table
+                );
+                let mut client = self.client().await?;
+                let transaction = client.transaction().await?;
+                for statement in &statements {
+                    if let Err(e) = transaction.batch_execute(statement).await {
+                        error!("❌ Failed to swap tables, rolling back: {}", e);
+                        return Err(e.into());
+                    }
+                }
+                transaction.commit().await?;
+
+                info!(
+                    "✅ Table {} swapped into {} successfully",
+                    staging_table, target_table
+                );
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    /// Resolve version mismatch error
+    ///WARNING:  database "postgres_db" has a collation version mismatch
+    ///DETAIL:  The database was created using collation version 2.36, but the operating system provides version 2.31.
+    ///HINT:  Rebuild all objects in this database that use the default collation and run ALTER DATABASE postgres_db REFRESH COLLATION VERSION, or build PostgreSQL with the right library version.
+    ///
+    /// Defaults to the configured database when no database name is given.
+    /// The collation versions are checked before and after the refresh, and
+    /// the indexes that still need rebuilding are listed in the result.
+    #[instrument(skip_all, fields(db = ?db_name))]
+    async fn fix_collation_version(
+        &self,
+        db_name: Option<&str>,
+    ) -> Result<CollationRefresh, PgcError> {
+        self.dialect()
+            .await?
+            .require("fix_collation_version", |sql| {
+                sql.supports_collation_versions()
+            })?;
+        let read_queries = self.read_queries();
+        let configured_db_name = self.config()?.db_name;
+        let db_name = custom_unwrap_or(db_name, configured_db_name.as_str(), "db_name");
+
+        let before = read_queries.collation_check(db_name).await?;
+        if !before.mismatch {
+            info!("🤗 No collation version mismatch in {}", db_name);
+        }
+
+        let query = format!(
+            "ALTER DATABASE {} REFRESH COLLATION VERSION",
+            sql::ident(db_name)?
+        );
+        if self.planned(query.as_str()) {
+            return Ok(CollationRefresh {
+                db_name: db_name.to_string(),
+                old_version: before.recorded_version,
+                new_version: before.actual_version,
+                indexes_to_rebuild: Vec::new(),
+            });
+        }
+        if let Err(e) = read_queries.execute(query).await {
+            error!("❌ Failed to fix collation version: {}", e);
+            return Err(e);
+        }
+
+        let after = read_queries.collation_check(db_name).await?;
+        if after.mismatch {
+            return Err(format!(
+                "❌ Collation version of {} still mismatches after the refresh: recorded {:?}, \
+                actual {:?}",
+                db_name, after.recorded_version, after.actual_version
+            )
+            .into());
+        }
+
+        // Only a real version change requires rebuilding, and indexes can only be
+        // inspected in the database we are connected to
+        let indexes_to_rebuild = if before.mismatch && db_name == configured_db_name {
+            read_queries.collation_dependent_indexes().await?
+        } else {
+            Vec::new()
+        };
+        info!("✅ Collation version fixed successfully");
+        if !indexes_to_rebuild.is_empty() {
+            warn!(
+                "⚠️ {} indexes use the default collation and should be rebuilt (REINDEX):",
+                indexes_to_rebuild.len()
+            );
+            for index in &indexes_to_rebuild {
+                info!("   - {} on {}", index.index_name, index.table_name);
+            }
+        }
+
+        Ok(CollationRefresh {
+            db_name: db_name.to_string(),
+            old_version: before.recorded_version,
+            new_version: after.recorded_version,
+            indexes_to_rebuild,
+        })
+    }
+
+    /// Make sure the PostGIS extension is available in the database.
+    ///
+    /// `CREATE EXTENSION` needs elevated privileges, so it is only attempted
+    /// when the extension is actually missing. This lets roles without those
+    /// privileges work against databases where PostGIS is already installed.
+    /// If the extension is missing and cannot be created for lack of
+    /// privileges, the error says so explicitly.
+    async fn ensure_postgis_extension(&self, client: &Client) -> Result<(), PgcError> {
+        let read_queries = self.read_queries();
+        if read_queries.check_postgis_support().await? {
+            return Ok(());
+        }
+        let Some(create_postgis) = read_queries.dialect().await?.sql().create_postgis() else {
+            return Ok(());
+        };
+        if self.planned(create_postgis) {
+            return Ok(());
+        }
+
+        info!("⏳ Attempting to create the PostGIS extension");
+        match client.batch_execute(create_postgis).await {
+            Ok(_) => {
+                info!("✅ PostGIS extension created successfully");
+                Ok(())
+            }
+            Err(e) if e.code() == Some(&SqlState::INSUFFICIENT_PRIVILEGE) => {
+                let err_msg = format!(
+                    "❌ PostGIS is not installed in this database and the current role is not \
+                    allowed to create it.\n\
+                     Ask a superuser (or the database owner) to run: CREATE EXTENSION postgis;\n{}",
+                    e
+                );
+                error!("{}", err_msg);
+                Err(err_msg.into())
+            }
+            Err(e) => {
+                error!("❌ Failed to create the PostGIS extension: {}", e);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Create a table for GeoJSON features if it does not exist yet.
+    ///
+    /// The geometry column type, name column length, indexes and whether the
+    /// table is `UNLOGGED` are taken from `options`. PostGIS must already be
+    /// available, see `ensure_postgis_extension`.
+    async fn create_geo_table(
+        &self,
+        client: &Client,
+        table_name: &str,
+        options: &GeoTableOptions,
+    ) -> Result<(), PgcError> {
+        info!("⏳ Attempting to create table: {}", table_name);
+
+        // Generated columns need Postgres 12, older servers get a trigger instead
+        let mut derived_columns = String::new();
+        let mut trigger_statements = Vec::new();
+        if options.with_bbox_column || options.with_centroid_column {
+            let version: i32 = client
+                .query_one("SELECT current_setting('server_version_num')::int", &[])
+                .await?
+                .get(0);
+            let generated = version >= 120000;
+            let geometry = planar_expression("geometry", options.column_kind);
+            if options.with_bbox_column {
+                derived_columns.push_str(&format!("bbox {}", options.bbox_column_type()));
+                if generated {
+                    derived_columns.push_str(&format!(
+                        " GENERATED ALWAYS AS ({}) STORED",
+                        bbox_expression(&geometry, options.effective_srid())
+                    ));
+                }
+                derived_columns.push_str(",\n");
+            }
+            if options.with_centroid_column {
+                derived_columns.push_str(&format!("centroid {}", options.centroid_column_type()));
+                if generated {
+                    derived_columns.push_str(&format!(
+                        " GENERATED ALWAYS AS ({}) STORED",
+                        centroid_expression(&geometry)
+                    ));
+                }
+                derived_columns.push_str(",\n");
+            }
+            if !generated {
+                trigger_statements = derived_geometry_trigger(
+                    table_name,
+                    options.with_bbox_column,
+                    options.with_centroid_column,
+                    options.effective_srid(),
+                    options.column_kind,
+                )?;
+            }
+        }
+
+        let key_columns = match &options.primary_key {
+            PrimaryKey::Uuid => format!(
+                "id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+                name {} NOT NULL UNIQUE,",
+                options.name_column_type()
+            ),
+            PrimaryKey::NameAsPk => format!("name {} PRIMARY KEY,", options.name_column_type()),
+            PrimaryKey::SerialId => format!(
+                "id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+                name {} NOT NULL UNIQUE,",
+                options.name_column_type()
+            ),
+            PrimaryKey::Property(key, pg_type) => format!(
+                "{} {} PRIMARY KEY,
+                name {} NOT NULL UNIQUE,",
+                sql::ident(key)?,
+                pg_type.sql_name(),
+                options.name_column_type()
+            ),
+        };
+        let mut statements = vec![format!(
+            "CREATE {}TABLE IF NOT EXISTS {} (
+                {}
+                properties JSONB NOT NULL,
+                geometry {},
+                {}created_at TIMESTAMPTZ DEFAULT NOW()
+            );",
+            if options.unlogged { "UNLOGGED " } else { "" },
+            sql::ident(table_name)?,
+            key_columns,
+            options.geometry_column_type(),
+            derived_columns
+        )];
+        statements.extend(trigger_statements);
+        if options.with_updated_at {
+            statements.extend(updated_at_statements(table_name)?);
+        }
+        if options.with_deleted_at {
+            statements.push(format!(
+                "ALTER TABLE {} ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;",
+                sql::ident(table_name)?
+            ));
+        }
+        let dialect = self.dialect().await?.sql();
+        if options.with_gin_properties_index {
+            statements.push(dialect.json_index(
+                &format!("{}_properties_idx", table_name),
+                table_name,
+                "properties",
+            )?);
+        }
+        if options.with_gist_geometry_index {
+            statements.push(dialect.spatial_index(
+                &format!("{}_geometry_idx", table_name),
+                table_name,
+                "geometry",
+            )?);
+        }
+
+        let grants = match &options.grants {
+            Some(grants) => grants.clone(),
+            None => self
+                .config_file()
+                .map(|config_file| config_file.table_defaults.grants.clone())
+                .unwrap_or_default(),
+        };
+        if let Some(dry_run) = &self.dry_run {
+            for statement in statements {
+                dry_run.plan(statement);
+            }
+            for (role, privileges) in &grants {
+                dry_run.plan(grant_statement(table_name, role, privileges)?);
+            }
+            return Ok(());
+        }
+
+        client.batch_execute(&statements.join("\n")).await?;
+        info!("✅ Table {} created successfully", table_name);
+
+        for (role, privileges) in &grants {
+            apply_grant(client, table_name, role, privileges).await?;
+        }
+        Ok(())
+    }
+
+    /// Grant privileges on an existing table to a role.
+    ///
+    /// `privileges` is a comma separated list such as `"SELECT"` or
+    /// `"SELECT, INSERT"`, or `"ALL"`. The same grant `create_geo_table`
+    /// applies through `GeoTableOptions::grants`, for tables created before.
+    async fn grant_on_table(
+        &self,
+        table_name: &str,
+        role: &str,
+        privileges: &str,
+    ) -> Result<(), PgcError> {
+        if self.planned(grant_statement(table_name, role, privileges)?) {
+            return Ok(());
+        }
+        let client = self.client().await?;
+        apply_grant(&client, table_name, role, privileges).await
+    }
+
+    /// Back up the database with `pg_dump`, returning the path of the dump.
+    ///
+    /// The dump is written in `output_dir` as `backup_{db_name}_{timestamp}`,
+    /// with the extension of its format, see `BackupOptions`. A backup of
+    /// selected tables has them named after the database name, or counted
+    /// when there are more than three, and a schema or data only backup is
+    /// marked `_schema` or `_data`. The stderr of `pg_dump` is kept next to
+    /// the dump, in `{dump}.log`, and its last lines end the error of a
+    /// failed backup. `pg_dump` only
+    /// dumps a directory in parallel: forcing another format with more than
+    /// one job dumps with a single one.
+    ///
+    /// The exclusions of `BackupOptions` and of the `[backup]` config section
+    /// are reported with the tables and schemas they matched once backed up,
+    /// and those matching nothing with a warning.
+    #[instrument(skip_all, fields(dir = %output_dir))]
+    #[cfg(feature = "backup")]
+    async fn backup_database(
+        &self,
+        output_dir: &str,
+        options: Option<BackupOptions>,
+    ) -> Result<String, PgcError> {
+        let observer = options
+            .as_ref()
+            .and_then(|options| options.observer.clone());
+        let backup_report = |path: &String| OperationReport::Backup { path: path.clone() };
+        let operation = self.audited(
+            self.audit("backup_database", output_dir),
+            |_, _| {},
+            async {
+                self.dialect()
+                    .await?
+                    .require("backup_database", |sql| sql.supports_pg_dump())?;
+                let started = Instant::now();
+                let db_config = self.config()?;
+                let mut options = options.unwrap_or_default();
+                if let Some(config_file) = self.config_file() {
+                    backup::merge_exclusions(&mut options, &config_file.backup);
+                }
+                let backup_time = backup::backup_time(options.utc_timestamps);
+                let timestamp = backup_time.format(backup::TIMESTAMP_FORMAT).to_string();
+                let deadline = options.timeout.map(backup::Deadline::after);
+                let format = options.effective_format();
+                if options.jobs < 1 {
+                    return Err(PgcError::usage(format!(
+                        "❌ jobs must be at least 1, not {}",
+                        options.jobs
+                    )));
+                }
+                if format != DumpFormat::Directory && options.jobs > 1 {
+                    warn!(
+                        "⚠️ pg_dump only dumps in parallel in directory format, dumping {} format \
+                        with 1 job instead of {}",
+                        format.pg_dump_name(),
+                        options.jobs
+                    );
+                } else {
+                    info!("⚙️  Total number of jobs: {}", options.jobs);
+                }
+
+                let mut label = match &options.tables {
+                    Some(tables) => format!("_{}", backup::tables_label(tables)),
+                    None => String::new(),
+                };
+                if let Some(scope) = options.scope.label() {
+                    label.push_str(&format!("_{}", scope));
+                }
+                info!("🔄 Attempting to backup {} database", &db_config.db_name);
+                info!("🕒 Backup timestamp: {}", timestamp);
+
+                let docker_container_name = options.docker_container_name.as_deref();
+                let pg_dump = match docker_container_name {
+                    Some(container) => {
+                        info!("🐳 Backing up with the pg_dump of container {}", container);
+                        backup::docker_client_tool("pg_dump", container).await?
+                    }
+                    None => {
+                        backup::client_tool(
+                            "pg_dump",
+                            db_config.pg_dump_path.as_deref(),
+                            "pg_dump_path",
+                        )
+                        .await?
+                    }
+                };
+                let read_queries = self.read_queries();
+                let server = read_queries.server_info().await?;
+                if pg_dump.is_older_than(&server) {
+                    return Err(PgcError::config(format!(
+                        "❌ {} is version {}, older than the server's {}: pg_dump cannot dump a \
+                        newer server. Install pg_dump {} or newer and set `pg_dump_path` in the \
+                        config",
+                        pg_dump.path,
+                        pg_dump.version,
+                        server.version,
+                        server.major()
+                    )));
+                }
+                // What this pg_dump can do, which the extension of the dump tells
+                options.compression = options
+                    .compression
+                    .map(|spec| backup::supported_compression(spec, format, &pg_dump));
+                let file_name = backup::backup_file_name(
+                    options
+                        .filename_template
+                        .as_deref()
+                        .unwrap_or(backup::DEFAULT_FILENAME_TEMPLATE),
+                    &db_config.db_name,
+                    &label,
+                    backup_time,
+                    format,
+                )?;
+                let output_file = std::path::Path::new(output_dir)
+                    .join(format!("{}.{}", file_name, options.extension()))
+                    .display()
+                    .to_string();
+                let client = self.client().await?;
+                let exclusions = backup::exclusion_matches(&client, &options).await?;
+                // The tables a verified dump must hold
+                let expected_tables: Vec<String> = if !options.verify {
+                    Vec::new()
+                } else if let Some(tables) = &options.tables {
+                    tables.clone()
+                } else {
+                    let excluded = |table: &String| {
+                        exclusions.iter().any(|exclusion| {
+                            let name = if exclusion.schema {
+                                "public".to_string()
+                            } else {
+                                format!("public.{}", table)
+                            };
+                            exclusion.matched.contains(&name)
+                        })
+                    };
+                    read_queries
+                        .list_tables(Some(true))
+                        .await?
+                        .into_iter()
+                        .filter(|table| !excluded(table))
+                        .collect()
+                };
+                // In a container, a directory is dumped inside it and copied out, a
+                // single file is streamed back on stdout, as a plain dump to gzip is
+                let docker_output = match docker_container_name {
+                    Some(container) if format == DumpFormat::Directory => {
+                        Some(backup::DockerFile::new(container, &output_file))
+                    }
+                    _ => None,
+                };
+                let gzip_level = match options.compression {
+                    Some(CompressionSpec {
+                        method: CompressionMethod::Gzip,
+                        level,
+                    }) if format == DumpFormat::Plain => {
+                        Some(level.unwrap_or(backup::DEFAULT_GZIP_LEVEL))
+                    }
+                    _ => None,
+                };
+                let streamed = docker_output.is_none()
+                    && (docker_container_name.is_some() || gzip_level.is_some());
+                let pg_dump_file = match &docker_output {
+                    Some(docker_output) => Some(docker_output.path.as_str()),
+                    None if streamed => None,
+                    None => Some(output_file.as_str()),
+                };
+                let stdout_file = streamed.then_some(backup::StdoutFile {
+                    path: &output_file,
+                    gzip_level,
+                });
+                let command = backup::pg_dump_command(&pg_dump, &db_config, pg_dump_file, &options)
+                    .deadline(deadline)
+                    .cancellation(&self.cancellation);
+
+                info!(
+                    "💻 Executing command (pg_dump {}): {}",
+                    pg_dump.version,
+                    command.display()
+                );
+                if let Some(stdout_file) = stdout_file {
+                    match stdout_file.gzip_level {
+                        Some(level) => info!(
+                            "📥 Streaming the dump to {}, gzipped at level {}",
+                            stdout_file.path, level
+                        ),
+                        None => info!("📥 Streaming the dump to {}", stdout_file.path),
+                    }
+                }
+                info!("⏳ Running pg_dump...");
+                if let Some(observer) = &options.observer {
+                    observer.file_started(&output_file, None);
+                }
+
+                let log_path = format!("{}.log", output_file);
+                match backup::run_logged_to(&command, &log_path, stdout_file).await {
+                    Ok(run) if run.status.success() => {
+                        if let Some(observer) = &options.observer {
+                            observer.copy_finished(&output_file, None);
+                        }
+                        if let Some(docker_output) = &docker_output {
+                            docker_output.copy_out(&output_file).await?;
+                            info!(
+                                "📦 Copied {}:{} out of the container",
+                                docker_output.container, docker_output.path
+                            );
+                        }
+                        info!(
+                            "✅ Database '{}' backed up to {}",
+                            db_config.db_name, output_file
+                        );
+                        for line in backup::exclusion_report(&exclusions) {
+                            backup::log_exclusion(&line);
+                        }
+                        if options.include_globals {
+                            let globals_file = std::path::Path::new(output_dir)
+                                .join(format!("globals_{}.sql", timestamp))
+                                .display()
+                                .to_string();
+                            backup::dump_globals(
+                                &pg_dump,
+                                &db_config,
+                                &globals_file,
+                                &log_path,
+                                deadline,
+                                &self.cancellation,
+                            )
+                            .await?;
+                            info!("👥 Roles and tablespaces dumped to {}", globals_file);
+                        }
+                        // For `restore_database` to compare with the versions it restores onto
+                        let versions = backup::extension_versions(&client).await?;
+                        backup::write_extension_versions(&output_file, &versions)?;
+                        if options.verify {
+                            let verification = self.verify_backup(&output_file).await?;
+                            if verification.format != DumpFormat::Plain {
+                                let missing: Vec<&str> = expected_tables
+                                    .iter()
+                                    .filter(|table| !verification.tables.contains(table))
+                                    .map(String::as_str)
+                                    .collect();
+                                if !missing.is_empty() {
+                                    return Err(format!(
+                                        "❌ Backup {} is missing tables: {}",
+                                        output_file,
+                                        missing.join(", ")
+                                    )
+                                    .into());
+                                }
+                            }
+                            let checksum_file =
+                                backup::write_checksum(&output_file, &verification.sha256)?;
+                            info!("🔐 Checksum written to {}", checksum_file);
+                        }
+                        if options.write_latest_pointer {
+                            let pointer = backup::write_latest_pointer(
+                                output_dir,
+                                &db_config.db_name,
+                                &output_file,
+                            )?;
+                            info!("📌 {} now points to {}", pointer, output_file);
+                        }
+                        if let Some(retain) = options.retain {
+                            self.prune_backups(
+                                output_dir,
+                                retain.keep_last,
+                                retain.keep_days,
+                                options.utc_timestamps,
+                                false,
+                            )
+                            .await?;
+                        }
+                        self.metrics.increment(metrics::BACKUPS, 1);
+                        self.metrics
+                            .observe(metrics::BACKUP_DURATION, started.elapsed().as_secs_f64());
+                        Ok(output_file)
+                    }
+                    Ok(run) => {
+                        error!("❌ Failed to backup database '{}'", db_config.db_name);
+                        // Cut short, it could pass for a whole dump
+                        if run.interruption.is_some() {
+                            backup::remove_partial(&output_file)?;
+                            info!("🗑️ Removed the partial dump {}", output_file);
+                        }
+                        Err(run.failure("pg_dump", &log_path))
+                    }
+                    Err(e) => {
+                        error!(
+                            "❌ Error backing up database '{}': {}",
+                            db_config.db_name, e
+                        );
+                        if e.kind() == std::io::ErrorKind::NotFound
+                            && docker_container_name.is_none()
+                        {
+                            info!("💡 pg_dump is not installed, logical_backup does not need it");
+                        }
+                        Err(e.into())
+                    }
+                }
+            },
+        );
+        observed(observer, backup_report, operation).await
+    }
+
+    /// Back up the database with `pg_dump` into `sink`, without the dump
+    /// ever being a file of its own on this host.
+    ///
+    /// The output of `pg_dump` goes through to the sink as it is written,
+    /// gzipped first for a plain dump compressed with gzip, and `pg_dump`
+    /// waits for a slow sink. Only the custom and plain formats can be
+    /// streamed: a directory dump is refused, and the default format is
+    /// custom whatever `jobs`. Messages go to stderr, as stdout may be the
+    /// dump.
+    ///
+    /// `verify` writes the SHA-256 of the dump, computed as it was streamed,
+    /// next to a `BackupSink::File`; the other sinks only report it. The dump
+    /// is not listed back, nor `retain` applied, as it may not be readable
+    /// from here, and `include_globals` only dumps next to a file sink. The
+    /// log of `pg_dump` is `{path}.log` for a file sink, and written in the
+    /// temporary directory otherwise.
+    #[instrument(skip_all, fields(sink = %sink))]
+    #[cfg(feature = "backup")]
+    async fn stream_backup(
+        &self,
+        sink: BackupSink,
+        options: Option<BackupOptions>,
+    ) -> Result<StreamedBackup, PgcError> {
+        self.dialect()
+            .await?
+            .require("stream_backup", |sql| sql.supports_pg_dump())?;
+        let db_config = self.config()?;
+        let mut options = options.unwrap_or_default();
+        if let Some(config_file) = self.config_file() {
+            backup::merge_exclusions(&mut options, &config_file.backup);
+        }
+        let timestamp = backup::backup_time(options.utc_timestamps)
+            .format(backup::TIMESTAMP_FORMAT)
+            .to_string();
+        let deadline = options.timeout.map(backup::Deadline::after);
+        match options.format {
+            Some(DumpFormat::Directory) => {
+                return Err(
+                    "❌ A directory dump cannot be streamed, back it up with backup_database instead"
+                        .into(),
+                );
+            }
+            Some(_) => {}
+            None => options.format = Some(DumpFormat::Custom),
+        }
+        let format = options.effective_format();
+        if options.jobs > 1 {
+            warn!(
+                "⚠️ pg_dump only dumps in parallel in directory format, streaming {} format with \
+                1 job instead of {}",
+                format.pg_dump_name(),
+                options.jobs
+            );
+        }
+        info!(
+            "🔄 Attempting to stream a backup of {} database to {}",
+            &db_config.db_name, sink
+        );
+
+        let pg_dump = match options.docker_container_name.as_deref() {
+            Some(container) => {
+                info!("🐳 Backing up with the pg_dump of container {}", container);
+                backup::docker_client_tool("pg_dump", container).await?
+            }
+            None => {
+                backup::client_tool("pg_dump", db_config.pg_dump_path.as_deref(), "pg_dump_path")
+                    .await?
+            }
+        };
+        let client = self.client().await?;
+        let server = super::super::read::queries::server_info_of(&client).await?;
+        if pg_dump.is_older_than(&server) {
+            return Err(PgcError::config(format!(
+                "❌ {} is version {}, older than the server's {}: pg_dump cannot dump a newer \
+                server. Install pg_dump {} or newer and set `pg_dump_path` in the config",
+                pg_dump.path,
+                pg_dump.version,
+                server.version,
+                server.major()
+            )));
+        }
+        options.compression = options
+            .compression
+            .map(|spec| backup::supported_compression(spec, format, &pg_dump));
+        let exclusions = backup::exclusion_matches(&client, &options).await?;
+        let gzip_level = match options.compression {
+            Some(CompressionSpec {
+                method: CompressionMethod::Gzip,
+                level,
+            }) if format == DumpFormat::Plain => Some(level.unwrap_or(backup::DEFAULT_GZIP_LEVEL)),
+            _ => None,
+        };
+        let command = backup::pg_dump_command(&pg_dump, &db_config, None, &options)
+            .deadline(deadline)
+            .cancellation(&self.cancellation);
+
+        let (log_path, dump_path) = match &sink {
+            BackupSink::File(path) => (format!("{}.log", path), Some(path.clone())),
+            _ => (
+                std::env::temp_dir()
+                    .join(format!("backup_{}_{}.log", db_config.db_name, timestamp))
+                    .display()
+                    .to_string(),
+                None,
+            ),
+        };
+        let writer: Box<dyn tokio::io::AsyncWrite + Send + Unpin> = match sink {
+            BackupSink::File(path) => Box::new(tokio::fs::File::create(path).await?),
+            BackupSink::Stdout => Box::new(tokio::io::stdout()),
+            BackupSink::Writer(writer) => writer,
+        };
+        let writer = backup::HashingWriter::new(writer);
+
+        info!(
+            "💻 Executing command (pg_dump {}): {}",
+            pg_dump.version,
+            command.display()
+        );
+        if let Some(level) = gzip_level {
+            info!("🗜️ Gzipping the dump at level {}", level);
+        }
+        info!("⏳ Running pg_dump...");
+        // The checksum is of what the sink got, the gzipped dump if gzipped
+        let (run, writer) = match gzip_level {
+            Some(level) => {
+                let encoder = async_compression::tokio::write::GzipEncoder::with_quality(
+                    writer,
+                    async_compression::Level::Precise(level as i32),
+                );
+                let (run, encoder) =
+                    backup::run_logged_into(&command, &log_path, encoder, |_| true).await?;
+                (run, encoder.into_inner())
+            }
+            None => backup::run_logged_into(&command, &log_path, writer, |_| true).await?,
+        };
+        if !run.status.success() {
+            error!("❌ Failed to backup database '{}'", db_config.db_name);
+            if let (Some(_), Some(dump_path)) = (run.interruption, &dump_path) {
+                backup::remove_partial(dump_path)?;
+                info!("🗑️ Removed the partial dump {}", dump_path);
+            }
+            return Err(run.failure("pg_dump", &log_path));
+        }
+        let (sha256, size_bytes) = writer.finish();
+        info!(
+            "✅ Database '{}' streamed to the sink ({}), SHA-256 {}",
+            db_config.db_name,
+            backup::human_size(size_bytes),
+            sha256
+        );
+        for line in backup::exclusion_report(&exclusions) {
+            backup::log_exclusion(&line);
+        }
+        if let (true, Some(dump_path)) = (options.verify, &dump_path) {
+            let checksum_file = backup::write_checksum(dump_path, &sha256)?;
+            info!("🔐 Checksum written to {}", checksum_file);
+        }
+        if let Some(dump_path) = &dump_path {
+            let versions = backup::extension_versions(&client).await?;
+            backup::write_extension_versions(dump_path, &versions)?;
+        }
+        // Next to a file, there is nowhere else to write them
+        match (options.include_globals, &dump_path) {
+            (true, Some(dump_path)) => {
+                let globals_file = std::path::Path::new(dump_path)
+                    .with_file_name(format!("globals_{}.sql", timestamp))
+                    .display()
+                    .to_string();
+                backup::dump_globals(
+                    &pg_dump,
+                    &db_config,
+                    &globals_file,
+                    &log_path,
+                    deadline,
+                    &self.cancellation,
+                )
+                .await?;
+                info!("👥 Roles and tablespaces dumped to {}", globals_file);
+            }
+            (true, None) => {
+                warn!("⚠️ Roles and tablespaces are only dumped next to a file sink, skipping them")
+            }
+            _ => {}
+        }
+        Ok(StreamedBackup {
+            sha256,
+            size_bytes,
+            log_path,
+        })
+    }
+
+    /// Restore a custom or directory dump made by `backup_database`, the
+    /// schema first and then the data, see `RestoreOptions`. `dump_file` can
+    /// also be `latest`, or `{dir}/latest`, for the dump the `latest_{db}`
+    /// pointer of that directory names, or without one the newest full backup
+    /// of the configured database in it.
+    ///
+    /// The stderr of both `pg_restore` phases is appended to
+    /// `{dump}.restore.log`, and its last lines end the error of a failed
+    /// phase. `pg_restore` runs with `--verbose`, whose items move a progress
+    /// bar rather than being printed; during the data phase, the rows copied
+    /// so far into the tables being restored are read from
+    /// `pg_stat_progress_copy`.
+    #[instrument(skip_all, fields(dump = %dump_file))]
+    #[cfg(feature = "backup")]
+    async fn restore_database(
+        &self,
+        dump_file: &str,
+        options: Option<RestoreOptions>,
+    ) -> Result<(), PgcError> {
+        let observer = options
+            .as_ref()
+            .and_then(|options| options.observer.clone());
+        let restore_report = |_: &()| OperationReport::Restore {
+            dump_file: dump_file.to_string(),
+        };
+        let operation = self.audited(
+            self.audit("restore_database", dump_file)
+                .options(audit_restore(options.as_ref())),
+            |_, _| {},
+            async {
+                self.dialect()
+                    .await?
+                    .require("restore_database", |sql| sql.supports_pg_dump())?;
+                let start_time = Instant::now();
+                let db_config = self.config()?;
+                let options = options.unwrap_or_default();
+                if options.jobs < 1 {
+                    return Err(PgcError::usage(format!(
+                        "❌ jobs must be at least 1, not {}",
+                        options.jobs
+                    )));
+                }
+                // Over both phases, the second only gets what the first left
+                let deadline = options.timeout.map(backup::Deadline::after);
+                let docker_container_name = options.docker_container_name.as_deref();
+                if options.create_db && options.target_db.is_none() {
+                    return Err(PgcError::usage(
+                        "❌ `create_db` needs a `target_db` to create",
+                    ));
+                }
+
+                info!(
+                    "🔄 Attempting to restore database unto {}",
+                    options.target_db.as_deref().unwrap_or(&db_config.db_name)
+                );
+
+                // `latest`, or `{dir}/latest`, is the newest full backup of the database
+                // pg_restore can restore
+                let latest;
+                let dump_path = std::path::Path::new(dump_file);
+                let dump_file = if dump_path.file_name().is_some_and(|name| name == "latest") {
+                    let dir = dump_path
+                        .parent()
+                        .filter(|dir| !dir.as_os_str().is_empty())
+                        .unwrap_or(std::path::Path::new("."))
+                        .display()
+                        .to_string();
+                    // The pointer `write_latest_pointer` keeps, if any, over the newest name
+                    latest = match backup::read_latest_pointer(&dir, &db_config.db_name)? {
+                        Some(pointed) => pointed,
+                        None => {
+                            let full_series = format!("backup_{}", db_config.db_name);
+                            let full_backups: Vec<String> =
+                                backup::backup_files(&dir, Some(&db_config.db_name))?
+                                    .into_iter()
+                                    .filter(|backup_file| backup_file.series == full_series)
+                                    .map(|backup_file| backup_file.path.display().to_string())
+                                    .collect();
+                            self.list_backups(&dir)
+                                .await?
+                                .into_iter()
+                                .find(|backup_info| {
+                                    backup_info.valid
+                                        && backup_info.format != DumpFormat::Plain
+                                        && full_backups.contains(&backup_info.path)
+                                })
+                                .map(|backup_info| backup_info.path)
+                                .ok_or_else(|| {
+                                    format!(
+                                        "❌ No valid full backup of {} in {} to restore as latest",
+                                        db_config.db_name, dir
+                                    )
+                                })?
+                        }
+                    };
+                    info!("📌 latest is {}", latest);
+                    latest.as_str()
+                } else {
+                    dump_file
+                };
+
+                // Checked before anything is dropped
+                match backup::dump_format_of(dump_file) {
+                    Ok(DumpFormat::Plain) => {
+                        return Err(PgcError::usage(format!(
+                            "❌ {} is a plain SQL dump, restore it with psql instead",
+                            dump_file
+                        )));
+                    }
+                    Ok(format) => info!("📦 Dump format: {}", format.pg_dump_name()),
+                    Err(e) => {
+                        return Err(PgcError::io(dump_file, e));
+                    }
+                }
+                if let Some(stored) = backup::read_checksum(dump_file)? {
+                    if stored == backup::dump_checksum(std::path::Path::new(dump_file))? {
+                        info!("🔐 Checksum verified");
+                    } else if options.force {
+                        warn!(
+                            "⚠️ {} does not match {}, restoring anyway",
+                            dump_file,
+                            backup::checksum_path(dump_file)
+                        );
+                    } else {
+                        return Err(PgcError::invalid_input(format!(
+                            "❌ {} does not match {}, the dump is corrupted. Set `force` to \
+                            restore it anyway",
+                            dump_file,
+                            backup::checksum_path(dump_file)
+                        )));
+                    }
+                }
+
+                // A schema only dump has no data to restore, a data only dump is
+                // restored into the existing tables, which must not be dropped
+                let pg_restore = match docker_container_name {
+                    Some(container) => {
+                        info!(
+                            "🐳 Restoring with the pg_restore of container {}",
+                            container
+                        );
+                        backup::docker_client_tool("pg_restore", container).await?
+                    }
+                    None => {
+                        backup::client_tool(
+                            "pg_restore",
+                            db_config.pg_restore_path.as_deref(),
+                            "pg_restore_path",
+                        )
+                        .await?
+                    }
+                };
+                let read_queries = self.read_queries();
+                let server = read_queries.server_info().await?;
+                if pg_restore.is_older_than(&server) {
+                    warn!(
+                        "⚠️ {} is version {}, older than the server's {}, the restore may fail",
+                        pg_restore.path, pg_restore.version, server.version
+                    );
+                }
+                // pg_restore of a container reads a copy of the dump inside it, removed once
+                // restored
+                let docker_dump = match docker_container_name {
+                    Some(container) => {
+                        let docker_dump = backup::DockerFile::copy_in(container, dump_file).await?;
+                        info!("📦 Copied the dump to {}:{}", container, docker_dump.path);
+                        Some(docker_dump)
+                    }
+                    None => None,
+                };
+                let restore_file = docker_dump
+                    .as_ref()
+                    .map_or(dump_file, |docker_dump| docker_dump.path.as_str());
+                let toc = backup::dump_toc(&pg_restore, restore_file).await?;
+                // Both phases append their stderr to it
+                let log_path = format!(
+                    "{}.restore.log",
+                    dump_file.trim_end_matches(std::path::is_separator)
+                );
+                let has_schema = toc.iter().any(|entry| entry.desc == "TABLE");
+                let has_data = toc.iter().any(|entry| entry.desc == "TABLE DATA");
+
+                // With `target_db`, the restore goes there and the configured database is left
+                // alone
+                let restore_config = match &options.target_db {
+                    Some(target_db) => {
+                        if options.create_db {
+                            create_restore_database(&db_config, target_db, options.overwrite)
+                                .await?;
+                        }
+                        info!("🎯 Restoring into database {}", target_db);
+                        crate::read::config::Config {
+                            db_name: target_db.clone(),
+                            ..db_config.clone()
+                        }
+                    }
+                    None => db_config.clone(),
+                };
+
+                // A restore of selected tables only restores the entries of the dump belonging to
+                // them
+                let (use_list_file, restored_entries) = match &options.tables {
+                    Some(tables) => {
+                        let list_path = std::env::temp_dir()
+                            .join(format!("{}.txt", sql::unique_name("pgc_use_list")))
+                            .display()
+                            .to_string();
+                        let list_file = backup::TempFile(list_path);
+                        let listed = backup::write_table_list(
+                            &pg_restore,
+                            restore_file,
+                            tables,
+                            &list_file.0,
+                        )
+                        .await?;
+                        if listed.is_empty() {
+                            return Err(PgcError::usage(format!(
+                                "❌ {} holds none of the tables {}",
+                                dump_file,
+                                tables.join(", ")
+                            )));
+                        }
+                        info!("📋 Restoring {} entries of the dump", listed.len());
+                        let entries = toc
+                            .iter()
+                            .filter(|entry| listed.contains(&entry.dump_id))
+                            .cloned()
+                            .collect();
+                        (Some(list_file), entries)
+                    }
+                    None => (None, toc.clone()),
+                };
+                let docker_use_list = match (docker_container_name, &use_list_file) {
+                    (Some(container), Some(list_file)) => {
+                        Some(backup::DockerFile::copy_in(container, &list_file.0).await?)
+                    }
+                    _ => None,
+                };
+                let use_list = match &docker_use_list {
+                    Some(docker_use_list) => Some(docker_use_list.path.as_str()),
+                    None => use_list_file.as_ref().map(|list_file| list_file.0.as_str()),
+                };
+
+                if let Some(target_db) = &options.target_db {
+                    info!(
+                        "📋 Not dropping any table of {} first, pg_restore replaces the tables of \
+                        the dump",
+                        target_db
+                    );
+                } else if !has_schema {
+                    info!("📋 The dump holds data only, restoring it into the existing tables");
+                } else {
+                    if let Some(tables) = &options.tables {
+                        info!("📋 Only restoring tables: {}", tables.join(", "));
+                    }
+                    match options.pre_clean {
+                        PreClean::None => {
+                            info!(
+                                "📋 Not dropping any table first, pg_restore replaces the tables \
+                                 of the dump"
+                            )
+                        }
+                        PreClean::DroppedTablesInDump => {
+                            // Each table in its own schema, the schemas in the order of the dump
+                            let mut dropped: Vec<(&str, Vec<&str>)> = Vec::new();
+                            for entry in &toc {
+                                let selected = options
+                                    .tables
+                                    .as_ref()
+                                    .is_none_or(|tables| tables.contains(&entry.tag));
+                                if entry.desc != "TABLE" || !selected {
+                                    continue;
+                                }
+                                let position = dropped
+                                    .iter()
+                                    .position(|(schema, _)| *schema == entry.schema);
+                                let tables = match position {
+                                    Some(position) => &mut dropped[position].1,
+                                    None => {
+                                        dropped.push((&entry.schema, Vec::new()));
+                                        &mut dropped.last_mut().expect("just pushed").1
+                                    }
+                                };
+                                if !tables.contains(&entry.tag.as_str()) {
+                                    tables.push(&entry.tag);
+                                }
+                            }
+                            if !options.assume_yes && prompt::is_interactive() {
+                                for (schema, tables) in &dropped {
+                                    let action = format!(
+                                        "Drop the {} tables the dump recreates in schema {}",
+                                        tables.len(),
+                                        schema
+                                    );
+                                    let tables: Vec<String> =
+                                        tables.iter().map(|table| table.to_string()).collect();
+                                    if !confirm_destruction(
+                                        &action,
+                                        &restore_config,
+                                        schema,
+                                        &tables,
+                                        self.reporter.as_ref(),
+                                    )
+                                    .await?
+                                    {
+                                        return Err(PgcError::NotConfirmed {
+                                            message: "❌ Dropping the tables of the dump was not \
+                                                confirmed, aborting"
+                                                .to_string(),
+                                        });
+                                    }
+                                }
+                            }
+                            let mut failed = 0;
+                            for (schema, tables) in &dropped {
+                                failed += self
+                                    .drop_tables(Some(schema), tables, true, true)
+                                    .await?
+                                    .into_iter()
+                                    .filter(|(_, outcome)| {
+                                        matches!(outcome, DropOutcome::Failed(_))
+                                    })
+                                    .count();
+                            }
+                            if failed > 0 {
+                                return Err(format!(
+                                    "❌ Failed to drop {} tables of the dump, not restoring",
+                                    failed
+                                )
+                                .into());
+                            }
+                        }
+                        PreClean::AllTables => {
+                            // Tables in `exclude_tables` survive the restore, everything else is
+                            // wiped first
+                            let exclude_tables: Vec<&str> =
+                                options.exclude_tables.iter().map(String::as_str).collect();
+                            self.drop_all_tables(None, false, &exclude_tables, !options.assume_yes)
+                                .await?;
+                        }
+                    }
+                }
+
+                // Roles first, the schema grants to them
+                if let Some(globals_file) = &options.globals_file {
+                    let client =
+                        db::connect_as(&restore_config, &restore_config.db_name, None).await?;
+                    let applied = backup::apply_globals(&client, globals_file).await?;
+                    info!(
+                        "👥 Applied {} statements of {}, {} roles or tablespaces already existed",
+                        applied.applied, globals_file, applied.existing
+                    );
+                    if !applied.skipped.is_empty() {
+                        warn!(
+                            "⚠️ Not a superuser, skipped {} statements: {}",
+                            applied.skipped.len(),
+                            applied.skipped.join(", ")
+                        );
+                    }
+                }
+
+                // The types of the schema must exist before it is restored
+                let extensions = match &options.extensions {
+                    Some(names) => names
+                        .iter()
+                        .map(|name| backup::DumpExtension {
+                            name: name.clone(),
+                            schema: None,
+                        })
+                        .collect(),
+                    None => backup::dump_extensions(&pg_restore, restore_file).await?,
+                };
+                if !extensions.is_empty() {
+                    let client =
+                        db::connect_as(&restore_config, &restore_config.db_name, None).await?;
+                    let created = backup::create_extensions(&client, &extensions).await?;
+                    if created.is_empty() {
+                        info!(
+                            "🧩 Extensions already there: {}",
+                            extensions
+                                .iter()
+                                .map(|extension| extension.name.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        );
+                    } else {
+                        info!("🧩 Created extensions: {}", created.join(", "));
+                    }
+                    let dumped_postgis = backup::read_extension_versions(dump_file)?
+                        .into_iter()
+                        .flatten()
+                        .find(|(name, _)| name == "postgis")
+                        .map(|(_, version)| version);
+                    if let Some(dumped_postgis) = dumped_postgis {
+                        let installed: Option<String> = client
+                            .query_opt(
+                                "SELECT extversion FROM pg_extension WHERE extname = 'postgis'",
+                                &[],
+                            )
+                            .await?
+                            .map(|row| row.get(0));
+                        match installed {
+                            Some(installed) if installed != dumped_postgis => warn!(
+                                "⚠️ The dump was made with PostGIS {}, the database has PostGIS \
+                                {}: the functions the schema uses may differ",
+                                dumped_postgis, installed
+                            ),
+                            Some(_) => info!("🧩 PostGIS {} as in the dump", dumped_postgis),
+                            None => {}
+                        }
+                    }
+                }
+
+                // Detect system memory and pick the settings of its tier
+                let total_memory = sys_info::mem_info()
+                    .map(|info| info.total)
+                    .unwrap_or(16 * 1024 * 1024); // Default to 16GB if detection fails
+                let (work_mem, maintenance_work_mem) = self
+                    .config_file()
+                    .map(|config_file| config_file.restore_memory.clone())
+                    .unwrap_or_default()
+                    .settings(total_memory);
+                info!("💾 Detected memory: {}KB", total_memory);
+
+                // The server applies PGOPTIONS to the sessions of pg_restore, after those already
+                // set
+                let pg_options = std::env::var("PGOPTIONS")
+                    .ok()
+                    .into_iter()
+                    .chain(work_mem.map(|value| format!("-c work_mem={}", value)))
+                    .chain(
+                        maintenance_work_mem
+                            .map(|value| format!("-c maintenance_work_mem={}", value)),
+                    )
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                // What a session opened with the same options gets
+                let settings_client = db::connect_as(
+                    &restore_config,
+                    &restore_config.db_name,
+                    Some(pg_options.as_str()).filter(|options| !options.is_empty()),
+                )
+                .await?;
+                for setting in ["work_mem", "maintenance_work_mem"] {
+                    let value: String = settings_client
+                        .query_one(&format!("SHOW {}", setting), &[])
+                        .await?
+                        .get(0);
+                    info!("⚙️  Using {}: {}", setting, value);
+                }
+                drop(settings_client);
+                info!("⚙️  Total number of jobs: {}", &options.jobs);
+                let phase_entries = |phase: RestorePhase| -> Vec<backup::TocEntry> {
+                    restored_entries
+                        .iter()
+                        .filter(|entry| phase.restores(entry))
+                        .cloned()
+                        .collect()
+                };
+                if let Some(observer) = &options.observer {
+                    let bytes = std::fs::metadata(dump_file)
+                        .ok()
+                        .filter(|metadata| metadata.is_file())
+                        .map(|metadata| metadata.len());
+                    observer.file_started(dump_file, bytes);
+                }
+                let mut schema_duration = None;
+                // Step 1: Restore schema only
+                if has_schema {
+                    info!("📊 Step 1: Restoring schema...");
+                    let schema_command = backup::pg_restore_command(
+                        &pg_restore,
+                        &restore_config,
+                        restore_file,
+                        &options,
+                        RestorePhase::Schema,
+                        use_list,
+                    )
+                    .env("PGOPTIONS", &pg_options)
+                    .deadline(deadline)
+                    .cancellation(&self.cancellation);
+
+                    info!(
+                        "💻 Executing command (pg_restore {}): {}",
+                        pg_restore.version,
+                        schema_command.display()
+                    );
+                    let schema_start = Instant::now();
+                    let mut progress = backup::RestoreProgress::new(
+                        RestorePhase::Schema,
+                        phase_entries(RestorePhase::Schema),
+                        self.reporter.clone(),
+                    );
+                    let run = backup::run_logged_with(&schema_command, &log_path, None, |line| {
+                        progress.line(line)
+                    })
+                    .await;
+                    progress.finish();
+                    match run {
+                        Ok(run) if run.status.success() => {
+                            schema_duration = Some(schema_start.elapsed());
+                            info!(
+                                "✅ Schema restored successfully in {:.2?}",
+                                schema_start.elapsed()
+                            );
+                        }
+                        Ok(run) => return Err(run.failure("Schema restore", &log_path)),
+                        Err(e) => {
+                            return Err(PgcError::Subprocess {
+                                program: "pg_restore".to_string(),
+                                stderr_tail: Vec::new(),
+                                message: format!("❌ Schema restore failed to run: {}", e),
+                            });
+                        }
+                    }
+                } else {
+                    info!("⏭️ Step 1: No schema in the dump, skipping");
+                }
+
+                // Step 2: Restore data only
+                if !has_data {
+                    info!("⏭️ Phase 2: No data in the dump, skipping");
+                    info!(
+                        "✅ Database '{}' schema restored from {} in {:.2?}",
+                        restore_config.db_name,
+                        dump_file,
+                        start_time.elapsed()
+                    );
+                    self.record_restore(start_time);
+                    self.run_hooks(HookStage::PostRestore, None).await?;
+                    return Ok(());
+                }
+                info!("📊 Phase 2: Restoring data...");
+                let data_command = backup::pg_restore_command(
+                    &pg_restore,
+                    &restore_config,
+                    restore_file,
+                    &options,
+                    RestorePhase::Data,
+                    use_list,
+                )
+                .env("PGOPTIONS", &pg_options)
+                .deadline(deadline)
+                .cancellation(&self.cancellation);
+
+                info!(
+                    "💻 Executing command (pg_restore {}): {}",
+                    pg_restore.version,
+                    data_command.display()
+                );
+                info!("⏳ Running pg_restore...");
+                let data_start = Instant::now();
+                let mut progress = backup::RestoreProgress::new(
+                    RestorePhase::Data,
+                    phase_entries(RestorePhase::Data),
+                    self.reporter.clone(),
+                );
+                let copy_progress =
+                    backup::watch_copy_progress(self.reporter.clone(), restore_config.clone());
+                let run = backup::run_logged_with(&data_command, &log_path, None, |line| {
+                    progress.line(line)
+                })
+                .await;
+                copy_progress.abort();
+                progress.finish();
+                match run {
+                    Ok(run) if run.status.success() => {
+                        let data_duration = data_start.elapsed();
+                        if let Some(observer) = &options.observer {
+                            observer.copy_finished(dump_file, None);
+                            observer.indexing_started(&restore_config.db_name);
+                        }
+                        let post_durations = run_post_restore(
+                            self,
+                            &restore_config.db_name,
+                            options.tables.as_deref(),
+                            &options.post_restore,
+                        )
+                        .await;
+                        let duration = start_time.elapsed();
+                        info!(
+                            "✅ Database '{}' restored from {} in {:.2?}",
+                            restore_config.db_name, dump_file, duration
+                        );
+                        let mut summary = match schema_duration {
+                            Some(schema_duration) => {
+                                format!(
+                                    "Schema: {:.2?}, data: {:.2?}",
+                                    schema_duration, data_duration
+                                )
+                            }
+                            None => format!("Data: {:.2?}", data_duration),
+                        };
+                        for (step, duration) in post_durations {
+                            summary.push_str(&format!(", {}: {:.2?}", step, duration));
+                        }
+                        info!("⏱️ {}", summary);
+                        self.record_restore(start_time);
+                        self.run_hooks(HookStage::PostRestore, None).await?;
+                        Ok(())
+                    }
+                    Ok(run) => {
+                        let duration = start_time.elapsed();
+                        error!(
+                            "❌ Failed to restore database '{}' after {:.2?}",
+                            restore_config.db_name, duration
+                        );
+                        Err(run.failure("Data restore", &log_path))
+                    }
+                    Err(e) => {
+                        let duration = start_time.elapsed();
+                        error!(
+                            "❌ Error restoring database '{}' after {:.2?}: {}",
+                            restore_config.db_name, duration, e
+                        );
+                        Err(e.into())
+                    }
+                }
+            },
+        );
+        observed(observer, restore_report, operation).await
+    }
+
+    /// Delete the older backups of the configured database in `dir`, returning
+    /// the deleted paths.
+    ///
+    /// Only entries named the way `backup_database` names dumps are
+    /// considered, anything else in `dir` is never touched. Backups are sorted
+    /// by the timestamp in their name, not by modification time, and each
+    /// series (full, schema only, of given tables...) is pruned on its own.
+    /// Of each series, the `keep_last` newest backups are kept, and those
+    /// younger than `keep_days` days, their age measured in UTC with
+    /// `utc_timestamps`, as `BackupOptions::utc_timestamps` names them, and in
+    /// local time otherwise. The logs next to a deleted dump are deleted with
+    /// it. With `dry_run`, nothing is deleted.
+    #[cfg(feature = "backup")]
+    async fn prune_backups(
+        &self,
+        dir: &str,
+        keep_last: usize,
+        keep_days: Option<u32>,
+        utc_timestamps: bool,
+        dry_run: bool,
+    ) -> Result<Vec<String>, PgcError> {
+        self.audited(
+            self.audit("prune_backups", dir).options(format!(
+                "keep_last={} keep_days={:?} utc_timestamps={} dry_run={}",
+                keep_last, keep_days, utc_timestamps, dry_run
+            )),
+            |_, _| {},
+            async {
+                let db_name = self.config()?.db_name;
+                let backups = backup::backup_files(dir, Some(&db_name))?;
+                // In the clock the backups were named after
+                let cutoff = keep_days.map(|days| {
+                    backup::backup_time(utc_timestamps) - chrono::Duration::days(days as i64)
+                });
+
+                let mut kept_per_series: HashMap<&str, usize> = HashMap::new();
+                let mut removed = Vec::new();
+                // Newest first, so the first ones of a series are the ones kept
+                for backup_file in &backups {
+                    let kept = kept_per_series.entry(&backup_file.series).or_default();
+                    let recent = cutoff.is_some_and(|cutoff| backup_file.timestamp >= cutoff);
+                    if *kept < keep_last || recent {
+                        *kept += 1;
+                        continue;
+                    }
+                    let path = backup_file.path.display().to_string();
+                    if dry_run {
+                        info!("🔍 Would delete backup {}", path);
+                    } else {
+                        backup::remove_backup(backup_file)?;
+                        info!("🗑️ Deleted backup {}", path);
+                    }
+                    removed.push(path);
+                }
+                info!(
+                    "✅ {} {} of {} backups of {} in {}",
+                    if dry_run { "Would prune" } else { "Pruned" },
+                    removed.len(),
+                    backups.len(),
+                    db_name,
+                    dir
+                );
+                Ok(removed)
+            },
+        )
+        .await
+    }
+
+    /// List the dumps `backup_database` wrote in `dir`, newest first, with
+    /// their sizes.
+    ///
+    /// Each custom or directory dump is probed with `pg_restore --list`, which
+    /// also tells the database it was made of. Without `pg_restore`, these
+    /// dumps are listed as invalid.
+    #[cfg(feature = "backup")]
+    async fn list_backups(&self, dir: &str) -> Result<Vec<BackupInfo>, PgcError> {
+        info!("⏳ Attempting to list backups in {}", dir);
+        let db_config = self.config()?;
+        let pg_restore = match backup::client_tool(
+            "pg_restore",
+            db_config.pg_restore_path.as_deref(),
+            "pg_restore_path",
+        )
+        .await
+        {
+            Ok(pg_restore) => Some(pg_restore),
+            Err(e) => {
+                warn!("⚠️ {}, dumps cannot be checked", e);
+                None
+            }
+        };
+
+        let mut backups = Vec::new();
+        for backup_file in backup::backup_files(dir, None)? {
+            let path = backup_file.path.display().to_string();
+            let format = if backup_file.path.is_dir() {
+                DumpFormat::Directory
+            } else if backup::is_plain_dump(&backup_file.path) {
+                DumpFormat::Plain
+            } else {
+                DumpFormat::Custom
+            };
+            let (valid, listed_db_name) = match (format, &pg_restore) {
+                (DumpFormat::Plain, _) => (true, None),
+                (_, Some(pg_restore)) => match backup::dump_listing(pg_restore, &path).await {
+                    Ok(listing) => (true, backup::listed_db_name(&listing)),
+                    Err(_) => (false, None),
+                },
+                (_, None) => (false, None),
+            };
+            // The filename holds the database name, but followed by the
+            // labels of partial backups
+            let db_name = listed_db_name.unwrap_or_else(|| {
+                let name = backup_file.series.trim_start_matches("backup_");
+                if name.starts_with(&db_config.db_name) {
+                    db_config.db_name.clone()
+                } else {
+                    name.to_string()
+                }
+            });
+            backups.push(BackupInfo {
+                size_bytes: backup::dump_size(&backup_file.path)?,
+                path,
+                db_name,
+                timestamp: backup_file
+                    .timestamp
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string(),
+                format,
+                valid,
+            });
+        }
+
+        if backups.is_empty() {
+            info!("🤗 No backups in {}", dir);
+            return Ok(backups);
+        }
+        info!("✅ Listed {} backups", backups.len());
+        Ok(backups)
+    }
+
+    /// Check that a dump can be read and compute its SHA-256, comparing it
+    /// with the one of `{dump}.sha256` when there is one.
+    ///
+    /// The tables of custom and directory dumps are listed with
+    /// `pg_restore --list`, failing when it cannot read the dump. Plain SQL
+    /// dumps are only checksummed.
+    #[cfg(feature = "backup")]
+    async fn verify_backup(&self, dump_file: &str) -> Result<BackupVerification, PgcError> {
+        info!("🔍 Verifying backup {}", dump_file);
+        let format = backup::dump_format_of(dump_file).map_err(|e| PgcError::io(dump_file, e))?;
+
+        let mut tables = Vec::new();
+        if format != DumpFormat::Plain {
+            let db_config = self.config()?;
+            let pg_restore = backup::client_tool(
+                "pg_restore",
+                db_config.pg_restore_path.as_deref(),
+                "pg_restore_path",
+            )
+            .await?;
+            for entry in backup::dump_toc(&pg_restore, dump_file).await? {
+                if (entry.desc == "TABLE" || entry.desc == "TABLE DATA")
+                    && !tables.contains(&entry.tag)
+                {
+                    tables.push(entry.tag);
+                }
+            }
+        }
+
+        let sha256 = backup::dump_checksum(std::path::Path::new(dump_file))?;
+        let checksum_matches = backup::read_checksum(dump_file)?.map(|stored| stored == sha256);
+        match checksum_matches {
+            Some(true) => info!("🔐 Checksum matches {}", backup::checksum_path(dump_file)),
+            Some(false) => error!(
+                "❌ Checksum mismatch: {} does not match {}, the dump is corrupted",
+                dump_file,
+                backup::checksum_path(dump_file)
+            ),
+            None => info!("🔐 sha256 {}", sha256),
+        }
+        if format == DumpFormat::Plain {
+            info!("✅ Plain SQL dump {} read", dump_file);
+        } else {
+            info!("✅ Dump {} holds {} tables", dump_file, tables.len());
+        }
+        Ok(BackupVerification {
+            path: dump_file.to_string(),
+            format,
+            sha256,
+            checksum_matches,
+            tables,
+        })
+    }
+
+    /// Back up the database without `pg_dump`.
+    ///
+    /// Writes a `logical_{db_name}_{timestamp}` directory in `output_dir`,
+    /// with a gzipped CSV file per table of the current schema, a `schema.sql`
+    /// recreating the tables with their constraints, indexes and triggers,
+    /// and a `manifest.json` describing the backup. Meant for the tables this
+    /// crate creates, where `pg_dump` is not installed; it does not cover
+    /// views, other schemas, roles or privileges.
+    #[instrument(skip_all, fields(dir = %output_dir))]
+    #[cfg(feature = "backup")]
+    async fn logical_backup(&self, output_dir: &str) -> Result<LogicalBackup, PgcError> {
+        self.audited(self.audit("logical_backup", output_dir), |_, _| {}, async {
+            Ok(logical::backup(
+                &self.pool,
+                &self.config()?.db_name,
+                output_dir,
+                &self.cancellation,
+            )
+            .await?)
+        })
+        .await
+    }
+
+    /// Restore a backup made by `logical_backup` into the current database.
+    ///
+    /// The tables of the backup must not exist yet. Everything runs in one
+    /// transaction, so a failed restore leaves nothing behind.
+    #[instrument(skip_all, fields(dir = %backup_dir))]
+    #[cfg(feature = "backup")]
+    async fn logical_restore(&self, backup_dir: &str) -> Result<LogicalBackup, PgcError> {
+        self.audited(
+            self.audit("logical_restore", backup_dir),
+            |_, _| {},
+            async { Ok(logical::restore(&self.pool, backup_dir, &self.cancellation).await?) },
+        )
+        .await
+    }
+
+    /// Uploads a GeoJSON file to the database.
     ///
     /// This function takes a GeoJSON file path and an optional table name. If no table name is
-    /// provided, it will be extracted from the file name. The function creates a table if it doesn't
+    /// provided, it will be extracted from the file name. The function creates a table if it
+    /// doesn't
     /// exist and then uploads the GeoJSON data to the table using a COPY operation.
     ///
     /// # Parameters
     ///
-    /// * `geojson_path`: The path to the GeoJSON file to upload.
-    /// * `table_name`: An optional table name to use for the upload. If not provided, the table name
-    ///   will be extracted from the file name.
+    /// * `geojson_path`: The path to the GeoJSON file to upload, or to a directory whose
+    ///   `.geojson`/`.json` files are all uploaded into the same table. `-` reads the
+    ///   features from stdin, see `insert_geojson_from_reader`, and needs a `table_name`.
+    /// * `options`: The table to upload into, how, and the options of the table and of the load,
+    ///   see `InsertGeoJsonOptions`. Without a `table`, it is named after the file. By default
+    ///   the upload aborts on the first invalid feature and the table is analyzed afterwards, so
+    ///   the planner has statistics for the freshly loaded rows. Every loaded file is recorded
+    ///   in the `_imports` table unless `record_import` is turned off. The `post_ingest` hooks
+    ///   of the config file run once the files are loaded.
     ///
     /// # Returns
     ///
-    /// A `Result` indicating success or an error.
+    /// An `IngestReport` with one `FileReport` per file: features read and loaded, rows
+    /// written, skipped features, duplicates, bytes, duration and throughput.
     ///
     /// # Errors
     ///
-    /// * `Box<dyn StdError>`: If an error occurs during the upload process.
+    /// * `IngestError`: If an error occurs during the upload process. Its `report` holds the
+    ///   files loaded before the failure.
     ///
     /// # Examples
     ///
+    /// ```no_run
+    /// use postgres_connect_rust::{
+    ///     Config, DatabaseQueriesWrite, InsertGeoJsonOptions, PostgresQueriesWrite,
+    /// };
+    ///
+    /// # async fn load() -> Result<(), Box<dyn std::error::Error>> {
+    /// let options = InsertGeoJsonOptions::new().table("geo_data").srid(4326).build();
+    /// let report = PostgresQueriesWrite::from_config(Config::load("files/config.toml")?)?
+    ///     .insert_geojson("path/to/geojson.json", Some(options))
+    ///     .await?;
+    /// report.print_summary();
+    /// # Ok(())
+    /// # }
     /// ```
-    /// let queries = PostgresQueriesWrite;
-    /// let result = queries.insert_geojson("path/to/geojson.json", None);
-    /// ```
+    #[instrument(
+        skip_all,
+        fields(
+            file = %geojson_path,
+            table = options.as_ref().and_then(|o| o.table.as_deref()).unwrap_or_default()
+        )
+    )]
     async fn insert_geojson(
         &self,
         geojson_path: &str,
-        table_name: Option<&str>,
-    ) -> Result<(), Box<dyn StdError>> {
-        let (client, pool) = db::new(Some(true))
+        options: Option<InsertGeoJsonOptions>,
+    ) -> Result<IngestReport, IngestError> {
+        // Read from stdin, observed by `insert_geojson_from_reader`
+        let observer = options
+            .as_ref()
+            .and_then(|options| options.ingest_options.observer.clone())
+            .filter(|_| geojson_path != "-");
+        let operation = self.audited(
+            self.audit("insert_geojson", geojson_path)
+                .options(audit_load(options.as_ref())),
+            |record, report: &IngestReport| {
+                record.set_rows(report.files.iter().map(|file| file.rows_written).sum())
+            },
+            async {
+                let options = options.unwrap_or_default();
+                if geojson_path == "-" {
+                    return self
+                        .insert_geojson_from_reader(Box::new(tokio::io::stdin()), options)
+                        .await;
+                }
+                let table_name = custom_unwrap_or(
+                    options.table.as_deref(),
+                    std::path::Path::new(geojson_path)
+                        .file_stem() // Option<&OsStr>
+                        .and_then(|s| s.to_str()) // Option<&str>
+                        .unwrap_or("unknown"),
+                    "table_name",
+                );
+                load_geojson(
+                    self,
+                    LoadSource::Path(geojson_path),
+                    table_name,
+                    options.mode,
+                    &options.table_options,
+                    &options.ingest_options,
+                )
+                .await
+            },
+        );
+        observed(observer, ingest_report, operation).await
+    }
+
+    /// Uploads the GeoJSON FeatureCollection read from `reader` into the `table` of
+    /// `options`, as `insert_geojson` does a file.
+    ///
+    /// The stream is read once, and gzip is detected by its first bytes. It is
+    /// named `stdin` in the report and the `_imports` table, where it is
+    /// recorded with the size and SHA-256 of what was read. Skipped features
+    /// are in the warnings of the report, not in an errors sidecar file.
+    /// `skip_unchanged` does not apply: a stream is always loaded. There is no
+    /// file name to name the table after, so `table` is required.
+    #[instrument(skip_all, fields(table = options.table.as_deref().unwrap_or_default()))]
+    async fn insert_geojson_from_reader(
+        &self,
+        reader: Box<dyn AsyncRead + Send + Unpin>,
+        options: InsertGeoJsonOptions,
+    ) -> Result<IngestReport, IngestError> {
+        let observer = options.ingest_options.observer.clone();
+        observed(
+            observer,
+            ingest_report,
+            self.audited(
+                self.audit("insert_geojson_from_reader", "stream")
+                    .options(audit_load(Some(&options))),
+                |record, report: &IngestReport| {
+                    record.set_rows(report.files.iter().map(|file| file.rows_written).sum())
+                },
+                async {
+                    let Some(table_name) = options.table.as_deref() else {
+                        return Err(IngestError {
+                            report: IngestReport::default(),
+                            source: PgcError::usage(
+                                "❌ Reading GeoJSON from a stream, like stdin (-), needs a table",
+                            ),
+                        });
+                    };
+                    load_geojson(
+                        self,
+                        LoadSource::Stream(reader),
+                        table_name,
+                        options.mode,
+                        &options.table_options,
+                        &options.ingest_options,
+                    )
+                    .await
+                },
+            ),
+        )
+        .await
+    }
+
+    /// Load the GeoJSON files that appear in `dir` or its subdirectories,
+    /// until the `cancellation` of the queries is cancelled.
+    ///
+    /// A new or modified file is loaded with `insert_geojson` once it stops
+    /// changing (see `WatchOptions::settle_time`), so it is recorded in the
+    /// `_imports` table like any other load. A file that fails to load is moved
+    /// to `WatchOptions::failed_dir` rather than retried on its next change.
+    /// Cancelling stops the watch, rolling back the file being loaded; so is
+    /// a load cut short by the process being killed, with its transaction.
+    #[instrument(skip_all, fields(dir = %dir))]
+    #[cfg(feature = "ingest")]
+    async fn watch_directory(
+        &self,
+        dir: &str,
+        options: Option<WatchOptions>,
+    ) -> Result<WatchReport, PgcError> {
+        let options = options.unwrap_or_default();
+        let dir_path = convert_path(dir)?;
+        if !dir_path.is_dir() {
+            return Err(PgcError::invalid_input(format!(
+                "❌ {} is not a directory",
+                dir
+            )));
+        }
+        let failed_dir = dir_path.join(&options.failed_dir);
+        let mut report = WatchReport {
+            table: options.table_name.clone().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let (_watcher, mut events) = watch::watch(dir_path)?;
+        let mut pending = watch::PendingFiles::default();
+        if options.load_existing {
+            for path in get_all_file_paths(dir_path).await? {
+                let path = PathBuf::from(path);
+                if watch::is_watched(&path, &options.extensions, &failed_dir) {
+                    pending.touch(path);
+                }
+            }
+        }
+        info!("👀 Watching {} for new files", dir);
+
+        let mut ticks = tokio::time::interval(options.poll_interval);
+        while !self.cancellation.is_cancelled() {
+            tokio::select! {
+                _ = self.cancellation.cancelled() => break,
+                event = events.recv() => match event {
+                    Some(Ok(event)) => {
+                        for path in watch::changed_paths(&event) {
+                            if watch::is_watched(path, &options.extensions, &failed_dir) {
+                                debug!("🔄 {} changed", path.display());
+                                pending.touch(path.clone());
+                            }
+                        }
+                    }
+                    Some(Err(e)) => warn!("⚠️ Error watching {}: {}", dir, e),
+                    None => return Err(format!("❌ Stopped watching {}", dir).into()),
+                },
+                _ = ticks.tick() => {
+                    for path in pending.take_settled(options.settle_time) {
+                        if self.cancellation.is_cancelled() {
+                            break;
+                        }
+                        let file_path = path.to_string_lossy().into_owned();
+                        info!("📥 Loading {}", file_path);
+                        let insert_options = InsertGeoJsonOptions {
+                            table: options.table_name.clone(),
+                            mode: LoadMode::Append,
+                            table_options: options.table_options.clone(),
+                            ingest_options: options.ingest_options.clone(),
+                        };
+                        match self.insert_geojson(&file_path, Some(insert_options)).await
+                        {
+                            Ok(ingest_report) => report.files.extend(ingest_report.files),
+                            Err(e) => {
+                                error!("❌ Failed to load {}: {}", file_path, e);
+                                let moved = watch::move_to_failed(&path, dir_path, &failed_dir);
+                                let moved_to = match moved {
+                                    Ok(target) => {
+                                        warn!("⚠️ Moved {} to {}", file_path, target.display());
+                                        Some(target.display().to_string())
+                                    }
+                                    Err(move_error) => {
+                                        error!("{}", move_error);
+                                        None
+                                    }
+                                };
+                                report.failed.push(WatchFailure {
+                                    path: file_path,
+                                    error: e.to_string(),
+                                    moved_to,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let loaded = report.files.iter().filter(|file| !file.unchanged).count();
+        info!(
+            "🛑 Stopped watching {}: files loaded: {}, failed: {}",
+            dir,
+            loaded,
+            report.failed.len()
+        );
+        Ok(report)
+    }
+
+    /// Delete the features matching a filter.
+    ///
+    /// All matching rows are removed by a single `DELETE` statement. With
+    /// `dry_run`, nothing is deleted and the number of rows that would have
+    /// been deleted is returned instead.
+    async fn delete_features(
+        &self,
+        table_name: &str,
+        filter: &FeatureFilter,
+        dry_run: bool,
+    ) -> Result<u64, PgcError> {
+        self.audited(
+            self.audit("delete_features", table_name)
+                .options(audit_filter(filter, dry_run)),
+            |record, deleted: &u64| record.set_rows(*deleted),
+            async {
+                let client = self.client().await?;
+                let (condition, params) = filter_sql(&client, table_name, filter).await?;
+                let params: Vec<&(dyn ToSql + Sync)> = params
+                    .iter()
+                    .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+                    .collect();
+                let query = format!(
+                    "DELETE FROM {} WHERE {}",
+                    sql::ident(table_name)?,
+                    condition
+                );
+
+                if self.planned(format!("{} -- {}", query, planned_filter(filter))) || dry_run {
+                    let query = format!(
+                        "SELECT COUNT(*) FROM {} WHERE {}",
+                        sql::ident(table_name)?,
+                        condition
+                    );
+                    let row = client.query_one(&query, &params).await?;
+                    let count: i64 = row.get(0);
+                    info!(
+                        "🔍 Dry run: {} features would be deleted from {}",
+                        count, table_name
+                    );
+                    return Ok(count as u64);
+                }
+
+                info!(
+                    "🔄 Attempting to delete features from table: {}",
+                    table_name
+                );
+                match client.execute(&query, &params).await {
+                    Ok(count) => {
+                        info!("✅ Deleted {} features from {}", count, table_name);
+                        Ok(count)
+                    }
+                    Err(e) => {
+                        error!("❌ Failed to delete features: {}", e);
+                        Err(e.into())
+                    }
+                }
+            },
+        )
+        .await
+    }
+
+    /// Mark the features matching a filter as deleted, keeping their rows.
+    ///
+    /// Sets `deleted_at` on the matching rows not deleted yet, which hides them
+    /// from the read side unless `include_deleted` is asked for. The table must
+    /// have been created with `GeoTableOptions::with_deleted_at`. Returns the
+    /// number of rows marked.
+    async fn soft_delete_features(
+        &self,
+        table_name: &str,
+        filter: &FeatureFilter,
+    ) -> Result<u64, PgcError> {
+        self.audited(
+            self.audit("soft_delete_features", table_name)
+                .options(audit_filter(filter, false)),
+            |record, deleted: &u64| record.set_rows(*deleted),
+            async {
+                let client = self.client().await?;
+                if !has_column(&client, table_name, "deleted_at").await? {
+                    return Err(PgcError::usage(format!(
+                        "❌ Table {} does not support soft deletes: it has no deleted_at column, \
+                        see GeoTableOptions::with_deleted_at",
+                        table_name
+                    )));
+                }
+                let (condition, params) = filter_sql(&client, table_name, filter).await?;
+                let params: Vec<&(dyn ToSql + Sync)> = params
+                    .iter()
+                    .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+                    .collect();
+                let query = format!(
+                    "UPDATE {} SET deleted_at = NOW() WHERE deleted_at IS NULL AND ({})",
+                    sql::ident(table_name)?,
+                    condition
+                );
+                if self.planned(format!("{} -- {}", query, planned_filter(filter))) {
+                    let query = format!(
+                        "SELECT COUNT(*) FROM {} WHERE deleted_at IS NULL AND ({})",
+                        sql::ident(table_name)?,
+                        condition
+                    );
+                    let count: i64 = client.query_one(&query, &params).await?.get(0);
+                    return Ok(count as u64);
+                }
+                info!(
+                    "🔄 Attempting to soft delete features from table: {}",
+                    table_name
+                );
+                match client.execute(&query, &params).await {
+                    Ok(count) => {
+                        info!("✅ Marked {} features of {} as deleted", count, table_name);
+                        Ok(count)
+                    }
+                    Err(e) => {
+                        error!("❌ Failed to soft delete features: {}", e);
+                        Err(e.into())
+                    }
+                }
+            },
+        )
+        .await
+    }
+
+    /// Remove the rows soft deleted more than `older_than` ago for good.
+    async fn purge_deleted(&self, table_name: &str, older_than: Duration) -> Result<u64, PgcError> {
+        self.audited(
+            self.audit("purge_deleted", table_name)
+                .options(format!("older_than={:?}", older_than)),
+            |record, purged: &u64| record.set_rows(*purged),
+            async {
+                let client = self.client().await?;
+                if !has_column(&client, table_name, "deleted_at").await? {
+                    return Err(PgcError::usage(format!(
+                        "❌ Table {} does not support soft deletes: it has no deleted_at column, \
+                        see GeoTableOptions::with_deleted_at",
+                        table_name
+                    )));
+                }
+                let query = format!(
+                    "DELETE FROM {} WHERE deleted_at < NOW() - $1 * INTERVAL '1 second'",
+                    sql::ident(table_name)?
+                );
+                let seconds = older_than.as_secs_f64();
+                if self.planned(format!("{} -- $1={}", query, seconds)) {
+                    let query = format!(
+                        "SELECT COUNT(*) FROM {} WHERE deleted_at < NOW() - $1 * INTERVAL '1 \
+                        second'",
+                        sql::ident(table_name)?
+                    );
+                    let count: i64 = client.query_one(&query, &[&seconds]).await?.get(0);
+                    return Ok(count as u64);
+                }
+                info!(
+                    "🔄 Attempting to purge features of {} deleted more than {:?} ago",
+                    table_name, older_than
+                );
+                let count = client.execute(&query, &[&seconds]).await?;
+                info!("✅ Purged {} features from {}", count, table_name);
+                Ok(count)
+            },
+        )
+        .await
+    }
+
+    /// Update the properties of the features matching a filter.
+    ///
+    /// The patch is merged into (`PatchMode::Merge`) or replaces
+    /// (`PatchMode::Replace`) the `properties` of every matching row, in a
+    /// single `UPDATE`. Returns the number of affected rows; with `dry_run`,
+    /// nothing is updated and the number of matching rows is returned.
+    ///
+    /// An empty filter (no names, or an empty JSON object) is refused, since
+    /// it would touch every row in the table.
+    async fn update_properties(
+        &self,
+        table_name: &str,
+        filter: &FeatureFilter,
+        patch: Value,
+        mode: PatchMode,
+        dry_run: bool,
+    ) -> Result<u64, PgcError> {
+        self.audited(
+            self.audit("update_properties", table_name).options(format!(
+                "{} mode={:?}",
+                audit_filter(filter, dry_run),
+                mode
+            )),
+            |record, updated: &u64| record.set_rows(*updated),
+            async {
+                if filter.is_empty() {
+                    let err_msg = format!(
+                        "❌ Refusing to update properties in {} with an empty filter, it would \
+                        touch every row",
+                        table_name
+                    );
+                    error!("{}", err_msg);
+                    return Err(PgcError::usage(err_msg));
+                }
+                if !patch.is_object() {
+                    return Err(PgcError::usage(format!(
+                        "❌ Properties patch must be a JSON object, got: {}",
+                        patch
+                    )));
+                }
+
+                let client = self.client().await?;
+                let (condition, mut params) = filter_sql(&client, table_name, filter).await?;
+                let patch_param = format!("${}", params.len() + 1);
+                let assignment = match mode {
+                    PatchMode::Merge => format!("properties || {}", patch_param),
+                    PatchMode::Replace => patch_param,
+                };
+                let query = format!(
+                    "UPDATE {} SET properties = {} WHERE {}",
+                    sql::ident(table_name)?,
+                    assignment,
+                    condition
+                );
+
+                let planned = format!("{} -- {} patch={}", query, planned_filter(filter), patch);
+                if self.planned(planned) || dry_run {
+                    let params: Vec<&(dyn ToSql + Sync)> = params
+                        .iter()
+                        .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+                        .collect();
+                    let query = format!(
+                        "SELECT COUNT(*) FROM {} WHERE {}",
+                        sql::ident(table_name)?,
+                        condition
+                    );
+                    let row = client.query_one(&query, &params).await?;
+                    let count: i64 = row.get(0);
+                    info!(
+                        "🔍 Dry run: {} features would be updated in {}",
+                        count, table_name
+                    );
+                    return Ok(count as u64);
+                }
+
+                params.push(Box::new(patch));
+                let params: Vec<&(dyn ToSql + Sync)> = params
+                    .iter()
+                    .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+                    .collect();
+
+                info!(
+                    "🔄 Attempting to update properties in table: {}",
+                    table_name
+                );
+                match client.execute(&query, &params).await {
+                    Ok(count) => {
+                        info!("✅ Updated {} features in {}", count, table_name);
+                        Ok(count)
+                    }
+                    Err(e) => {
+                        error!("❌ Failed to update properties: {}", e);
+                        Err(e.into())
+                    }
+                }
+            },
+        )
+        .await
+    }
+
+    /// Copy JSON properties into real, typed columns.
+    ///
+    /// For every `(json_key, column_name, pg_type)` in `mapping`, the column is
+    /// added if missing and backfilled from `properties->>json_key`, cast to
+    /// `pg_type`. The backfill runs in batches of `BACKFILL_BATCH_SIZE` rows
+    /// so no single huge transaction is needed. Values that can't be cast are
+    /// left `NULL` and reported per key instead of aborting the whole run.
+    /// With `create_indexes`, a b-tree index is created on each new column.
+    async fn materialize_properties(
+        &self,
+        table_name: &str,
+        mapping: Vec<(String, String, PgType)>,
+        create_indexes: bool,
+    ) -> Result<Vec<MaterializedColumn>, PgcError> {
+        let client = self.client().await?;
+        let table = sql::ident(table_name)?;
+        let mut report = Vec::new();
+
+        for (json_key, column_name, pg_type) in mapping {
+            info!(
+                "🔄 Materializing property {} into column {} ({})",
+                json_key,
+                column_name,
+                pg_type.sql_name()
+            );
+            let column = sql::ident(&column_name)?;
+            let key = sql::literal(&json_key);
+            let add_column = format!(
+                "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {} {}",
+                table,
+                column,
+                pg_type.sql_name()
+            );
+
+            // Values that can't be cast are excluded from the backfill and reported
+            let value = format!("(properties->>{})", key);
+            let castable = match pg_type.castable_pattern() {
+                Some(pattern) => format!("{} ~* {}", value, sql::literal(pattern)),
+                None => "TRUE".to_string(),
+            };
+            let new_value = match pg_type {
+                PgType::Jsonb => format!("(properties->{})", key),
+                _ => format!("{}::{}", value, pg_type.sql_name()),
+            };
+            let update = format!(
+                "UPDATE {table} SET {column} = {new_value}
+                WHERE ctid = ANY(ARRAY(
+                    SELECT ctid FROM {table}
+                    WHERE {column} IS NULL AND {value} IS NOT NULL AND {castable}
+                    LIMIT {limit}
+                ))",
+                table = table,
+                column = column,
+                new_value = new_value,
+                value = value,
+                castable = castable,
+                limit = BACKFILL_BATCH_SIZE
+            );
+            let create_index = format!(
+                "CREATE INDEX IF NOT EXISTS {} ON {} ({})",
+                sql::ident(&format!("{}_{}_idx", table_name, column_name))?,
+                table,
+                column
+            );
+
+            if let Some(dry_run) = &self.dry_run {
+                dry_run.plan(add_column);
+                dry_run.plan(update);
+                if create_indexes {
+                    dry_run.plan(create_index);
+                }
+                // The column may not exist yet, the values are counted in the properties
+                let row = client
+                    .query_one(
+                        &format!(
+                            "SELECT COUNT(*) FILTER (WHERE {castable}),
+                                COUNT(*) FILTER (WHERE NOT ({castable}))
+                            FROM {table} WHERE {value} IS NOT NULL",
+                            castable = castable,
+                            table = table,
+                            value = value
+                        ),
+                        &[],
+                    )
+                    .await?;
+                report.push(MaterializedColumn {
+                    json_key,
+                    column_name,
+                    pg_type,
+                    rows_updated: row.get::<_, i64>(0) as u64,
+                    cast_failures: row.get::<_, i64>(1) as u64,
+                    failure_samples: Vec::new(),
+                });
+                continue;
+            }
+
+            client.batch_execute(&add_column).await?;
+            let mut rows_updated = 0;
+            let mut batch_failures = 0;
+            loop {
+                match client.execute(&update, &[]).await {
+                    Ok(0) => break,
+                    Ok(count) => {
+                        rows_updated += count;
+                        info!("   ⏳ {} rows backfilled", rows_updated);
+                    }
+                    Err(e) => {
+                        // e.g. an integer out of range, which the pattern can't catch
+                        error!("❌ Failed to backfill a batch of {}: {}", column_name, e);
+                        batch_failures += 1;
+                        break;
+                    }
+                }
+            }
+
+            let failed_rows = format!(
+                "FROM {} WHERE {} IS NULL AND {} IS NOT NULL",
+                table, column, value
+            );
+            let cast_failures: i64 = client
+                .query_one(&format!("SELECT COUNT(*) {}", failed_rows), &[])
+                .await?
+                .get(0);
+            let cast_failures = cast_failures as u64;
+            let failure_samples: Vec<String> = client
+                .query(
+                    &format!("SELECT DISTINCT {} {} LIMIT 5", value, failed_rows),
+                    &[],
+                )
+                .await?
+                .iter()
+                .map(|row| row.get::<_, String>(0))
+                .collect();
+            if cast_failures > 0 || batch_failures > 0 {
+                warn!(
+                    "⚠️ {} values of {} could not be cast to {}, e.g. {:?}",
+                    cast_failures,
+                    json_key,
+                    pg_type.sql_name(),
+                    failure_samples
+                );
+            }
+
+            if create_indexes {
+                client.batch_execute(&create_index).await?;
+            }
+
+            info!("✅ Materialized {} rows into {}", rows_updated, column_name);
+            report.push(MaterializedColumn {
+                json_key,
+                column_name,
+                pg_type,
+                rows_updated,
+                cast_failures,
+                failure_samples,
+            });
+        }
+
+        Ok(report)
+    }
+
+    /// Create a simplified copy of a geo table.
+    ///
+    /// The target table gets the source's columns, constraints and indexes,
+    /// and its rows with the geometry simplified by
+    /// `ST_SimplifyPreserveTopology(geometry, tolerance)`. Meant for overview
+    /// or low-zoom tables, which do not need full-resolution geometries.
+    /// Geometries that simplification reduced to nothing are left out and
+    /// counted. Everything runs in one transaction, so a failure leaves no
+    /// target table behind.
+    async fn create_simplified_table(
+        &self,
+        source_table: &str,
+        target_table: &str,
+        tolerance: f64,
+    ) -> Result<SimplifiedTable, PgcError> {
+        let mut client = self.client().await?;
+        // Generated columns are computed by the target table itself
+        let columns: Vec<String> = client
+            .query(
+                "SELECT column_name FROM information_schema.columns
+                WHERE table_schema = current_schema() AND table_name = $1 AND is_generated = 'NEVER'
+                ORDER BY ordinal_position",
+                &[&source_table],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        let select_list: Vec<String> = columns
+            .iter()
+            .map(|column| {
+                if column == "geometry" {
+                    Ok("pgc_simplified".to_string())
+                } else {
+                    sql::ident(column)
+                }
+            })
+            .collect::<Result<_, PgcError>>()?;
+        let column_list: Vec<String> = columns
+            .iter()
+            .map(|column| sql::ident(column))
+            .collect::<Result<_, _>>()?;
+        let create = format!(
+            "CREATE TABLE {} (LIKE {} INCLUDING ALL)",
+            sql::ident(target_table)?,
+            sql::ident(source_table)?
+        );
+        let insert = format!(
+            "INSERT INTO {} ({})
+            SELECT {} FROM (
+                SELECT *, ST_SimplifyPreserveTopology(geometry, $1) AS pgc_simplified FROM {}
+            ) s
+            WHERE pgc_simplified IS NULL OR NOT ST_IsEmpty(pgc_simplified)",
+            sql::ident(target_table)?,
+            column_list.join(", "),
+            select_list.join(", "),
+            sql::ident(source_table)?
+        );
+        let source_rows: i64 = client
+            .query_one(
+                &format!("SELECT COUNT(*) FROM {}", sql::ident(source_table)?),
+                &[],
+            )
+            .await?
+            .get(0);
+        if let Some(dry_run) = &self.dry_run {
+            dry_run.plan(create);
+            dry_run.plan(format!("{} -- $1={}", insert, tolerance));
+            let source_bytes: i64 = client
+                .query_one(
+                    "SELECT pg_total_relation_size(to_regclass($1))",
+                    &[&sql::ident(source_table)?],
+                )
+                .await?
+                .get(0);
+            return Ok(SimplifiedTable {
+                source_table: source_table.to_string(),
+                target_table: target_table.to_string(),
+                tolerance,
+                rows_written: source_rows as u64,
+                empty_dropped: 0,
+                source_bytes,
+                target_bytes: 0,
+            });
+        }
+
+        info!(
+            "🔄 Attempting to create simplified table {} from {} (tolerance {})",
+            target_table, source_table, tolerance
+        );
+        let transaction = client.transaction().await?;
+        transaction.batch_execute(&create).await?;
+        let rows_written = transaction.execute(&insert, &[&tolerance]).await?;
+        transaction.commit().await?;
+
+        let sizes = client
+            .query_one(
+                "SELECT pg_total_relation_size(to_regclass($1)), \
+                pg_total_relation_size(to_regclass($2))",
+                &[&sql::ident(source_table)?, &sql::ident(target_table)?],
+            )
+            .await?;
+        let report = SimplifiedTable {
+            source_table: source_table.to_string(),
+            target_table: target_table.to_string(),
+            tolerance,
+            rows_written,
+            empty_dropped: source_rows as u64 - rows_written,
+            source_bytes: sizes.get(0),
+            target_bytes: sizes.get(1),
+        };
+        if report.empty_dropped > 0 {
+            warn!(
+                "⚠️ Dropped {} geometries that simplification left empty",
+                report.empty_dropped
+            );
+        }
+        info!(
+            "✅ Created {} with {} rows, {} → {} bytes ({:.1}% smaller)",
+            target_table,
+            rows_written,
+            report.source_bytes,
+            report.target_bytes,
+            if report.source_bytes > 0 {
+                (1.0 - report.target_bytes as f64 / report.source_bytes as f64) * 100.0
+            } else {
+                0.0
+            }
+        );
+        Ok(report)
+    }
+
+    /// Create simplified overviews of a table, one per tolerance.
+    ///
+    /// Overview `i` is the materialized view `{source_table}_z{i}`, holding the
+    /// `name`, `properties` and `ST_SimplifyPreserveTopology(geometry, tolerances[i])`
+    /// of every row, with a GiST index on its geometry and a unique index on
+    /// `name` so `refresh_overviews` can refresh it without blocking readers.
+    /// Returns the names of the overviews, in order. Existing overviews are
+    /// left as they are.
+    async fn create_overview_tables(
+        &self,
+        source_table: &str,
+        tolerances: &[f64],
+    ) -> Result<Vec<String>, PgcError> {
+        if let Some(tolerance) = tolerances.iter().find(|t| !t.is_finite() || **t < 0.0) {
+            return Err(PgcError::usage(format!(
+                "❌ Invalid overview tolerance {}, tolerances must be finite and not negative",
+                tolerance
+            )));
+        }
+        let mut statements = Vec::new();
+        let mut overviews = Vec::new();
+        for (level, tolerance) in tolerances.iter().enumerate() {
+            let overview = overview_name(source_table, level);
+            // The tolerance is a checked finite float, safe to inline in the view definition
+            statements.push(format!(
+                "CREATE MATERIALIZED VIEW IF NOT EXISTS {overview} AS
+                    SELECT name, properties,
+                        ST_SimplifyPreserveTopology(geometry, {tolerance}::float8) AS geometry
+                    FROM {source};
+                CREATE UNIQUE INDEX IF NOT EXISTS {name_idx} ON {overview} (name);
+                CREATE INDEX IF NOT EXISTS {geometry_idx} ON {overview} USING GIST (geometry);",
+                overview = sql::ident(&overview)?,
+                tolerance = tolerance,
+                source = sql::ident(source_table)?,
+                name_idx = sql::ident(&format!("{}_name_idx", overview))?,
+                geometry_idx = sql::ident(&format!("{}_geometry_idx", overview))?,
+            ));
+            overviews.push(overview);
+        }
+        if let Some(dry_run) = &self.dry_run {
+            for statement in statements {
+                dry_run.plan(statement);
+            }
+            return Ok(overviews);
+        }
+
+        let mut client = self.client().await?;
+        info!(
+            "🔄 Attempting to create {} overviews of {}",
+            tolerances.len(),
+            source_table
+        );
+        let transaction = client.transaction().await?;
+        for ((overview, statement), tolerance) in overviews.iter().zip(&statements).zip(tolerances)
+        {
+            transaction.batch_execute(statement).await?;
+            info!("✅ Created overview {} (tolerance {})", overview, tolerance);
+        }
+        transaction.commit().await?;
+        Ok(overviews)
+    }
+
+    /// Refresh the overviews `create_overview_tables` created for a table.
+    ///
+    /// Each overview is refreshed `CONCURRENTLY`, so it stays readable while
+    /// it is rebuilt. Returns the names of the refreshed overviews; a table
+    /// without overviews is not an error.
+    async fn refresh_overviews(&self, source_table: &str) -> Result<Vec<String>, PgcError> {
+        let client = self.client().await?;
+        let overviews = overview_names(&client, source_table).await?;
+        if overviews.is_empty() {
+            info!("⏭️ {} has no overviews to refresh", source_table);
+            return Ok(overviews);
+        }
+        for overview in &overviews {
+            let statement = format!(
+                "REFRESH MATERIALIZED VIEW CONCURRENTLY {}",
+                sql::ident(overview)?
+            );
+            if self.planned(statement.as_str()) {
+                continue;
+            }
+            info!("🔄 Refreshing overview {}", overview);
+            client.batch_execute(&statement).await?;
+        }
+        info!(
+            "✅ Refreshed {} overviews of {}",
+            overviews.len(),
+            source_table
+        );
+        Ok(overviews)
+    }
+
+    /// Add `bbox` and `centroid` columns to an existing geo table.
+    ///
+    /// Same columns as `GeoTableOptions::with_bbox_column` and
+    /// `with_centroid_column`, but kept up to date by a trigger, so existing
+    /// rows can be backfilled in batches of `BACKFILL_BATCH_SIZE` rather than in
+    /// the single table rewrite adding a generated column would take. Returns
+    /// the number of rows backfilled.
+    async fn add_derived_geometry_columns(&self, table_name: &str) -> Result<u64, PgcError> {
+        let client = self.client().await?;
+        info!(
+            "🔄 Attempting to add bbox and centroid columns to table: {}",
+            table_name
+        );
+        let read_queries = self.read_queries();
+        let column = read_queries
+            .geo_column_info(table_name)
+            .await?
+            .into_iter()
+            .find(|column| column.column == "geometry")
+            .ok_or_else(|| {
+                PgcError::usage(format!(
+                    "❌ {} has no geometry or geography column",
+                    table_name
+                ))
+            })?;
+        let srid = column.srid.filter(|srid| *srid > 0);
+        let options = GeoTableOptions {
+            column_kind: column.kind,
+            srid,
+            ..Default::default()
+        };
+        let geometry = planar_expression("geometry", column.kind);
+        let mut statements = vec![format!(
+            "ALTER TABLE {}
+                ADD COLUMN IF NOT EXISTS bbox {},
+                ADD COLUMN IF NOT EXISTS centroid {};",
+            sql::ident(table_name)?,
+            options.bbox_column_type(),
+            options.centroid_column_type()
+        )];
+        statements.extend(derived_geometry_trigger(
+            table_name,
+            true,
+            true,
+            options.effective_srid(),
+            column.kind,
+        )?);
+        let query = format!(
+            "UPDATE {table} SET bbox = {bbox}, centroid = {centroid}
+            WHERE ctid = ANY(ARRAY(
+                SELECT ctid FROM {table}
+                WHERE geometry IS NOT NULL AND NOT ST_IsEmpty(geometry)
+                AND (bbox IS NULL OR centroid IS NULL)
+                LIMIT {limit}
+            ))",
+            table = sql::ident(table_name)?,
+            bbox = bbox_expression(&geometry, options.effective_srid()),
+            centroid = centroid_expression(&geometry),
+            limit = BACKFILL_BATCH_SIZE
+        );
+        if let Some(dry_run) = &self.dry_run {
+            for statement in statements {
+                dry_run.plan(statement);
+            }
+            dry_run.plan(query);
+            // The columns may not exist yet, every row with a geometry would be backfilled
+            let rows: i64 = client
+                .query_one(
+                    &format!(
+                        "SELECT COUNT(*) FROM {}
+                        WHERE geometry IS NOT NULL AND NOT ST_IsEmpty(geometry)",
+                        sql::ident(table_name)?
+                    ),
+                    &[],
+                )
+                .await?
+                .get(0);
+            return Ok(rows as u64);
+        }
+        client.batch_execute(&statements.join("\n")).await?;
+
+        let mut backfilled = 0;
+        loop {
+            let updated = client.execute(&query, &[]).await?;
+            if updated == 0 {
+                break;
+            }
+            backfilled += updated;
+            info!("⏳ Backfilled {} rows", backfilled);
+        }
+        info!(
+            "✅ Added bbox and centroid columns to {}, {} rows backfilled",
+            table_name, backfilled
+        );
+        Ok(backfilled)
+    }
+
+    /// Load GeoTIFF rasters with `raster2pgsql`.
+    ///
+    /// `raster_path` is a raster file or a directory of `.tif`/`.tiff` files,
+    /// all loaded into `table_name`. `raster2pgsql` is run directly (not
+    /// through a shell) with the flags of `options`, and the SQL it writes is
+    /// executed statement by statement on a database connection as it comes,
+    /// so huge rasters are never held in memory and `psql` is not needed. The
+    /// load runs in the single transaction `raster2pgsql` opens, and is rolled
+    /// back if anything fails. The raster column is then looked up in
+    /// `raster_columns` to make sure it was registered.
+    async fn insert_raster(
+        &self,
+        raster_path: &str,
+        table_name: &str,
+        options: Option<RasterOptions>,
+    ) -> Result<RasterImport, PgcError> {
+        let options = options.unwrap_or_default();
+        let column = options.column.clone().unwrap_or_else(|| "rast".to_string());
+        let files = get_raster_file_paths(raster_path).await?;
+        if files.is_empty() {
+            return Err(PgcError::invalid_input(format!(
+                "❌ No raster files found in {}",
+                raster_path
+            )));
+        }
+        let client = self.client().await?;
+        // Raster support is its own extension since PostGIS 3
+        client
+            .batch_execute("CREATE EXTENSION IF NOT EXISTS postgis_raster")
             .await
-            .expect("❌ Failed to get database client or pool");
+            .map_err(|e| format!("❌ PostGIS raster support is not available: {}", e))?;
+
+        let mut args = options.raster2pgsql_args();
+        args.extend(files.iter().cloned());
+        args.push(table_name.to_string());
+        info!(
+            "🔄 Attempting to load {} raster files into {}",
+            files.len(),
+            table_name
+        );
+        info!("💻 Executing command: raster2pgsql {}", args.join(" "));
+        let mut child = tokio::process::Command::new("raster2pgsql")
+            .args(&args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| PgcError::Subprocess {
+                program: "raster2pgsql".to_string(),
+                stderr_tail: Vec::new(),
+                message: if e.kind() == std::io::ErrorKind::NotFound {
+                    "❌ raster2pgsql was not found on PATH. It ships with PostGIS \
+                    (e.g. the `postgis` package on Debian/Ubuntu, `brew install postgis` on \
+                    macOS); \
+                    install it or add its directory to PATH"
+                        .to_string()
+                } else {
+                    format!("❌ Failed to run raster2pgsql: {}", e)
+                },
+            })?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stderr_task = tokio::spawn(async move {
+            let mut output = String::new();
+            let _ = stderr.read_to_string(&mut output).await;
+            output
+        });
+
+        // Every statement raster2pgsql writes ends a line with `;`, tiles being single-line INSERTs
+        let mut lines = tokio::io::BufReader::new(stdout).lines();
+        let mut statement = String::new();
+        let mut result: Result<(), PgcError> = Ok(());
+        let mut statements = 0;
+        while let Some(line) = lines.next_line().await? {
+            statement.push_str(&line);
+            statement.push('\n');
+            if !line.trim_end().ends_with(';') {
+                continue;
+            }
+            if let Err(e) = client.batch_execute(&statement).await {
+                result = Err(format!("❌ Failed to load raster into {}: {}", table_name, e).into());
+                break;
+            }
+            statement.clear();
+            statements += 1;
+            if statements % 1000 == 0 {
+                info!("⏳ {} statements executed", statements);
+            }
+        }
+        if result.is_err() {
+            // Stops raster2pgsql, which would block on a full pipe otherwise
+            let _ = child.kill().await;
+        }
+        let status = child.wait().await?;
+        let stderr_output = stderr_task.await.unwrap_or_default();
+        if result.is_ok() && !status.success() {
+            result = Err(PgcError::Subprocess {
+                program: "raster2pgsql".to_string(),
+                message: format!(
+                    "❌ raster2pgsql failed ({}): {}",
+                    status,
+                    stderr_output.trim()
+                ),
+                stderr_tail: stderr_output.lines().map(str::to_string).collect(),
+            });
+        }
+        if let Err(e) = result {
+            let _ = client.batch_execute("ROLLBACK").await;
+            error!("{}", e);
+            return Err(e);
+        }
 
-        let table_name = custom_unwrap_or(
+        let row = client
+            .query_opt(
+                "SELECT srid, scale_x, scale_y, blocksize_x, blocksize_y, num_bands
+                FROM raster_columns
+                WHERE r_table_schema = current_schema() AND r_table_name = $1
+                    AND r_raster_column = $2",
+                &[&table_name, &column],
+            )
+            .await?
+            .ok_or_else(|| {
+                format!(
+                    "❌ {}.{} is not registered in raster_columns after the load",
+                    table_name, column
+                )
+            })?;
+        let tiles: i64 = client
+            .query_one(
+                &format!("SELECT COUNT(*) FROM {}", sql::ident(table_name)?),
+                &[],
+            )
+            .await?
+            .get(0);
+        let import = RasterImport {
+            table_name: table_name.to_string(),
+            column,
+            files,
+            tiles,
+            srid: row.get::<_, Option<i32>>(0).filter(|srid| *srid > 0),
+            scale_x: row.get(1),
+            scale_y: row.get(2),
+            blocksize_x: row.get(3),
+            blocksize_y: row.get(4),
+            num_bands: row.get(5),
+        };
+        info!(
+            "✅ Loaded {} tiles into {} (SRID: {:?}, bands: {:?}, block size: {:?}x{:?})",
+            import.tiles,
             table_name,
-            std::path::Path::new(geojson_path)
-                .file_stem() // Option<&OsStr>
-                .and_then(|s| s.to_str()) // Option<&str>
-                .unwrap_or("unknown"),
-            "table_name",
+            import.srid,
+            import.num_bands,
+            import.blocksize_x,
+            import.blocksize_y
         );
-        // Create table if it doesn't exist
-        if let Err(e) = self.create_geo_table(&client, table_name).await {
-            // Optionally, check for specific error code if not using IF NOT EXISTS
-            eprintln!(
-                "Warning: Could not create '{}' table (may already exist):\n{}",
-                table_name, e
-            );
-            // You can proceed, unless the error is critical
+        Ok(import)
+    }
+
+    /// Collect planner statistics for a table.
+    async fn analyze(&self, table_name: &str) -> Result<(), PgcError> {
+        let query = format!("ANALYZE {}", sql::ident(table_name)?);
+        if self.planned(query.as_str()) {
+            return Ok(());
         }
-        process_and_upload_file(&client, geojson_path, table_name).await?;
+        let read_queries = self.read_queries();
 
-        Ok(())
+        info!("🔄 Attempting to analyze table: {}", table_name);
+        match read_queries.execute(query).await {
+            Ok(_) => {
+                info!("✅ {} table analyzed successfully", table_name);
+                Ok(())
+            }
+            Err(e) => {
+                error!("❌ Failed to analyze table: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Vacuum a table, optionally with `FULL` and/or `ANALYZE`.
+    ///
+    /// `VACUUM` cannot run inside a transaction block, so it is sent on its own
+    /// dedicated connection as a single simple-protocol statement, which
+    /// Postgres executes in autocommit mode. It must not be batched with other
+    /// statements or issued through a pooled client that may hold an open
+    /// transaction. Queries built with `new`, which have no settings to open
+    /// that connection with, send it through a connection of the pool.
+    async fn vacuum(&self, table_name: &str, full: bool, analyze: bool) -> Result<(), PgcError> {
+        self.dialect()
+            .await?
+            .require("vacuum", |sql| sql.supports_vacuum())?;
+        let mut vacuum_options = Vec::new();
+        if full {
+            vacuum_options.push("FULL");
+        }
+        if analyze {
+            vacuum_options.push("ANALYZE");
+        }
+        let query = if vacuum_options.is_empty() {
+            format!("VACUUM {}", sql::ident(table_name)?)
+        } else {
+            format!(
+                "VACUUM ({}) {}",
+                vacuum_options.join(", "),
+                sql::ident(table_name)?
+            )
+        };
+
+        if self.planned(query.as_str()) {
+            return Ok(());
+        }
+        info!("🔄 Attempting to vacuum table: {}", table_name);
+        let result = match &self.config_file {
+            Some(config_file) => {
+                let config = &config_file.config;
+                db::connect_as(config, &config.db_name, None)
+                    .await?
+                    .batch_execute(&query)
+                    .await
+            }
+            None => self.client().await?.batch_execute(&query).await,
+        };
+        match result {
+            Ok(_) => {
+                info!("✅ {} table vacuumed successfully", table_name);
+                Ok(())
+            }
+            Err(e) => {
+                error!("❌ Failed to vacuum table: {}", e);
+                Err(e.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "backup")]
+    #[test]
+    fn the_restored_tables_are_analyzed_by_their_quoted_names() {
+        assert_eq!(analyze_statement(None).unwrap(), "ANALYZE");
+        let tables = ["points".to_string(), "My \"Table\"".to_string()];
+        assert_eq!(
+            analyze_statement(Some(&tables)).unwrap(),
+            "ANALYZE \"points\", \"My \"\"Table\"\"\""
+        );
+        let error = analyze_statement(Some(&[String::new()])).expect_err("an empty name");
+        assert_eq!(error.kind(), ErrorKind::InvalidInput, "{}", error);
     }
 }
@@ -0,0 +1,362 @@
+use crate::read::reports::CollationDependentIndex;
+use crate::utils::error::PgcError;
+use crate::utils::sql::PgType;
+use crate::write::options::DumpFormat;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fmt;
+use tracing::info;
+
+/// What happened to one table passed to `drop_many`.
+///
+/// * `Dropped`: the table was dropped.
+/// * `Missing`: the table did not exist.
+/// * `Failed`: the table exists but could not be dropped, e.g. because other
+///   objects depend on it and `cascade` was not set.
+/// * `Planned`: the table would have been dropped, in a dry run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DropOutcome {
+    Dropped,
+    Missing,
+    Failed(String),
+    Planned,
+}
+
+/// Result of `fix_collation_version`.
+///
+/// `indexes_to_rebuild` lists the indexes that still use the default
+/// collation and should be rebuilt with `REINDEX`, since refreshing the
+/// version only updates the catalog.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollationRefresh {
+    pub db_name: String,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub indexes_to_rebuild: Vec<CollationDependentIndex>,
+}
+
+/// Result of materializing one JSON property key into a column.
+///
+/// Values that could not be cast to `pg_type` are left `NULL` in the column;
+/// they are counted in `cast_failures` with a few of them in `failure_samples`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaterializedColumn {
+    pub json_key: String,
+    pub column_name: String,
+    pub pg_type: PgType,
+    pub rows_updated: u64,
+    pub cast_failures: u64,
+    pub failure_samples: Vec<String>,
+}
+
+/// Result of `validate_geojson`, checking a file before it is loaded.
+///
+/// * `geometry_types`: number of features per geometry type.
+/// * `invalid_coordinates`: features with a coordinate that is not finite,
+///   or a longitude outside ±180 or latitude outside ±90.
+/// * `duplicate_names`: features whose name (taken from the feature id, as
+///   when loading) was already used by an earlier feature.
+/// * `mixed_type_keys`: property keys whose values have more than one JSON
+///   type, `null` aside, with the number of values per type.
+/// * `estimated_memory_bytes`, `estimated_load_seconds`: rough estimates for
+///   loading the file, derived from its size.
+///
+/// The `*_samples` fields hold the first few offending feature indexes or names.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub path: String,
+    pub file_size_bytes: u64,
+    pub total_features: u64,
+    pub geometry_types: BTreeMap<String, u64>,
+    pub null_geometries: u64,
+    pub invalid_coordinates: u64,
+    pub invalid_coordinate_samples: Vec<usize>,
+    pub duplicate_names: u64,
+    pub duplicate_name_samples: Vec<String>,
+    pub mixed_type_keys: BTreeMap<String, BTreeMap<String, u64>>,
+    pub estimated_memory_bytes: u64,
+    pub estimated_load_seconds: f64,
+}
+
+/// Result of `create_simplified_table`.
+///
+/// `empty_dropped` counts the geometries that simplification left empty and
+/// that were not copied. The sizes are `pg_total_relation_size` of the tables,
+/// indexes and TOAST included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimplifiedTable {
+    pub source_table: String,
+    pub target_table: String,
+    pub tolerance: f64,
+    pub rows_written: u64,
+    pub empty_dropped: u64,
+    pub source_bytes: i64,
+    pub target_bytes: i64,
+}
+
+/// What happened to one file of an `insert_geojson` load.
+///
+/// * `features_read`: features in the file.
+/// * `features_loaded`: features written to the table, duplicates and skipped ones aside.
+/// * `rows_written`: rows written for them, more than `features_loaded` with `explode_multi`.
+/// * `clipped`: features left out by `clip_bbox`, and with `clip_exact` those with
+///   nothing left inside the box (rows of them, with `explode_multi`).
+/// * `unchanged`: the file was not loaded because of `skip_unchanged`.
+/// * `throughput`: rows written per second.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileReport {
+    pub path: String,
+    pub bytes: u64,
+    pub features_read: u64,
+    pub features_loaded: u64,
+    pub rows_written: u64,
+    pub skipped: u64,
+    pub duplicates: u64,
+    pub clipped: u64,
+    pub unchanged: bool,
+    pub duration_ms: u64,
+    pub throughput: f64,
+    pub warnings: Vec<String>,
+}
+
+impl FileReport {
+    /// Prints the per-file summary line.
+    pub fn print_summary(&self) {
+        if self.rows_written != self.features_loaded {
+            info!(
+                "📊 Features loaded: {}, rows written: {}, skipped: {}, duplicates: {}",
+                self.features_loaded, self.rows_written, self.skipped, self.duplicates
+            );
+        } else {
+            info!(
+                "📊 Features loaded: {}, skipped: {}, duplicates: {}",
+                self.features_loaded, self.skipped, self.duplicates
+            );
+        }
+        if self.clipped > 0 {
+            info!("✂️ Outside the clip box: {}", self.clipped);
+        }
+    }
+}
+
+/// Result of `insert_geojson`, one `FileReport` per file in load order.
+///
+/// `warnings` holds the warnings of the whole load, those of the files included.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IngestReport {
+    pub table: String,
+    pub files: Vec<FileReport>,
+    pub warnings: Vec<String>,
+}
+
+impl IngestReport {
+    /// Prints the summary of the whole load.
+    pub fn print_summary(&self) {
+        let unchanged = self.files.iter().filter(|file| file.unchanged).count();
+        let rows: u64 = self.files.iter().map(|file| file.rows_written).sum();
+        info!(
+            "📊 {}: files loaded: {}, unchanged: {}, rows written: {}, warnings: {}",
+            self.table,
+            self.files.len() - unchanged,
+            unchanged,
+            rows,
+            self.warnings.len()
+        );
+    }
+}
+
+/// Error of a failed `insert_geojson`, with the report of what was done
+/// before the failure.
+#[derive(Debug)]
+pub struct IngestError {
+    pub report: IngestReport,
+    pub source: PgcError,
+}
+
+impl fmt::Display for IngestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl StdError for IngestError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// A file `watch_directory` failed to load.
+///
+/// `moved_to` is where it was moved, `None` if moving it failed as well.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchFailure {
+    pub path: String,
+    pub error: String,
+    pub moved_to: Option<String>,
+}
+
+/// Result of `watch_directory` once stopped: the files loaded, in load
+/// order, and those that failed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WatchReport {
+    pub table: String,
+    pub files: Vec<FileReport>,
+    pub failed: Vec<WatchFailure>,
+}
+
+/// Where a step of a plan got to, see `plan::run`.
+///
+/// * `Planned`: not run, as the plan was run with `dry_run`.
+/// * `Ok`: ran and succeeded.
+/// * `Failed`: ran and failed.
+/// * `Skipped`: not run, as an earlier step failed without `continue_on_error`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum StepStatus {
+    Planned,
+    Ok,
+    Failed,
+    Skipped,
+}
+
+/// One step of a plan: what it does, with the paths resolved, and how it went.
+///
+/// `detail` tells what a step that succeeded did, and why one failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StepReport {
+    pub step: usize,
+    pub description: String,
+    pub status: StepStatus,
+    pub duration_ms: u64,
+    pub detail: String,
+}
+
+/// Result of `plan::run`, one `StepReport` per step in plan order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct PlanReport {
+    pub plan: String,
+    pub steps: Vec<StepReport>,
+}
+
+impl PlanReport {
+    /// Whether a step failed, including those allowed to.
+    pub(crate) fn failed(&self) -> bool {
+        self.steps
+            .iter()
+            .any(|step| step.status == StepStatus::Failed)
+    }
+
+    /// Prints the summary of the whole run.
+    pub(crate) fn print_summary(&self) {
+        let count = |status: StepStatus| {
+            self.steps
+                .iter()
+                .filter(|step| step.status == status)
+                .count()
+        };
+        let duration_ms: u64 = self.steps.iter().map(|step| step.duration_ms).sum();
+        info!(
+            "📋 {}: ok: {}, failed: {}, skipped: {}, in {:.1}s",
+            self.plan,
+            count(StepStatus::Ok),
+            count(StepStatus::Failed),
+            count(StepStatus::Skipped),
+            duration_ms as f64 / 1000.0
+        );
+    }
+}
+
+/// Outcome of `insert_raster`, read back from `raster_columns`.
+///
+/// The SRID, scale, block size and band count are only known to
+/// `raster_columns` when the raster constraints were added.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RasterImport {
+    pub table_name: String,
+    pub column: String,
+    pub files: Vec<String>,
+    pub tiles: i64,
+    pub srid: Option<i32>,
+    pub scale_x: Option<f64>,
+    pub scale_y: Option<f64>,
+    pub blocksize_x: Option<i32>,
+    pub blocksize_y: Option<i32>,
+    pub num_bands: Option<i32>,
+}
+
+/// One table of a `logical_backup`.
+///
+/// `columns` are the columns in the data file, generated columns aside, and
+/// `file` its path relative to the backup directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogicalTable {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub file: String,
+    pub rows: u64,
+}
+
+/// A backup made by `logical_backup`, also written as its `manifest.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogicalBackup {
+    pub path: String,
+    pub db_name: String,
+    pub created_at: String,
+    pub crate_version: String,
+    pub tables: Vec<LogicalTable>,
+}
+
+/// A dump found by `list_backups`.
+///
+/// `db_name` is read from the dump when `pg_restore` can list it, and from
+/// the filename otherwise. `valid` tells whether the dump could be read:
+/// listed by `pg_restore`, or starting like a plain SQL dump.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub path: String,
+    pub db_name: String,
+    pub timestamp: String,
+    pub size_bytes: u64,
+    pub format: DumpFormat,
+    pub valid: bool,
+}
+
+/// A dump written by `stream_backup`.
+///
+/// `sha256` and `size_bytes` are those of the bytes written to the sink,
+/// computed as they went through. `log_path` is the log of `pg_dump`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamedBackup {
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub log_path: String,
+}
+
+/// What one exclusion of a backup matched in the database, see
+/// `backup::exclusion_matches`.
+///
+/// `matched` holds the `schema.table` names of the tables, or the names of
+/// the schemas when `schema` is set. `default` marks the PostGIS tables
+/// excluded by default, which match nothing without the extension.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ExclusionMatch {
+    pub pattern: String,
+    pub schema: bool,
+    pub default: bool,
+    pub matched: Vec<String>,
+}
+
+/// Result of `verify_backup`.
+///
+/// `tables` are the tables the dump holds the schema or data of.
+/// `checksum_matches` is `None` when there is no `{dump}.sha256` to compare
+/// `sha256` with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupVerification {
+    pub path: String,
+    pub format: DumpFormat,
+    pub sha256: String,
+    pub checksum_matches: Option<bool>,
+    pub tables: Vec<String>,
+}
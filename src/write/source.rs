@@ -0,0 +1,554 @@
+//! Reading a GeoJSON file a feature after another, as it is loaded, without
+//! holding more than a few of them in memory whatever the size of the file.
+//!
+//! `GeoJsonSource::open` tells a FeatureCollection from GeoJSONL, a feature
+//! per line, by the first line of the file, decompressed when it is gzipped.
+//! `scan` counts the features and reads the top level `crs` and `bbox`
+//! without parsing the features, and `features` hands them out while a
+//! thread reads ahead:
+//!
+//! ```no_run
+//! use postgres_connect_rust::GeoJsonSource;
+//!
+//! # async fn read() -> Result<(), postgres_connect_rust::PgcError> {
+//! let source = GeoJsonSource::open("data/parcels.geojsonl.gz").await?;
+//! let summary = source.scan().await?;
+//! println!("{}: {} features", source.file_stem(), summary.features);
+//! let mut features = source.features();
+//! while let Some(next) = features.next_feature().await {
+//!     let (index, feature) = next?;
+//!     println!("{}: {:?}", index, feature.id);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `insert_geojson` and `validate_geojson` read their files this way, so
+//! both take GeoJSONL too.
+
+use crate::utils::error::PgcError;
+use crate::utils::progress::ObserverHandle;
+use crate::write::utils::{feature_name, gzip_aware, read_feature_collection, GZIP_MAGIC};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, Receiver};
+use tokio::task::JoinHandle;
+
+/// The most of a file read to tell its format, its first line or a part of
+/// it: a GeoJSONL file whose first feature is longer is taken for a
+/// FeatureCollection.
+const SNIFF_BYTES: u64 = 16 * 1024 * 1024;
+
+/// The features read ahead of the load before the reading thread waits.
+const FEATURES_AHEAD: usize = 256;
+
+/// What separates the records of a GeoJSON text sequence (RFC 8142).
+const RECORD_SEPARATOR: u8 = 0x1e;
+
+/// How the features of a GeoJSON file are laid out.
+///
+/// * `FeatureCollection`: one FeatureCollection object, the whole file.
+/// * `Lines`: GeoJSONL, a Feature object per line, blank lines skipped, as
+///   are the record separators of a GeoJSON text sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GeoJsonFormat {
+    FeatureCollection,
+    Lines,
+}
+
+/// What `GeoJsonSource::scan` found in a file.
+///
+/// * `features`: the features it has.
+/// * `crs`: the top level `crs` of a FeatureCollection, as it is.
+/// * `bbox`: the top level `bbox` of a FeatureCollection.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GeoJsonSummary {
+    pub features: usize,
+    pub crs: Option<Value>,
+    pub bbox: Option<Vec<f64>>,
+}
+
+/// A GeoJSON file, plain or gzipped, whose format is known, see the module.
+#[derive(Debug, Clone)]
+pub struct GeoJsonSource {
+    path: String,
+    format: GeoJsonFormat,
+    gzipped: bool,
+    bytes: u64,
+}
+
+impl GeoJsonSource {
+    /// `path`, once its first line told its format. A file that is empty,
+    /// does not start with a JSON object, or whose first line is invalid
+    /// JSON fails here, one cut short later, when it is read.
+    pub async fn open(path: &str) -> Result<Self, PgcError> {
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || Self::open_blocking(&path)).await?
+    }
+
+    /// `open`, on the calling thread.
+    pub(crate) fn open_blocking(path: &str) -> Result<Self, PgcError> {
+        let file = File::open(path).map_err(|e| PgcError::io(path, e))?;
+        let bytes = file.metadata().map_err(|e| PgcError::io(path, e))?.len();
+        let mut reader = BufReader::new(file);
+        let gzipped = reader
+            .fill_buf()
+            .map_err(|e| PgcError::io(path, e))?
+            .starts_with(&GZIP_MAGIC);
+        let reader = gzip_aware(reader).map_err(|e| PgcError::io(path, e))?;
+        let format = sniff_format(BufReader::new(reader)).map_err(|e| e.in_geojson(path, None))?;
+        Ok(Self {
+            path: path.to_string(),
+            format,
+            gzipped,
+            bytes,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The name of the file without its extension, nor `.gz` before it:
+    /// `parcels` for `data/parcels.geojson.gz`.
+    pub fn file_stem(&self) -> &str {
+        let path = Path::new(&self.path);
+        let path = match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("gz") => {
+                path.file_stem().map(Path::new).unwrap_or(path)
+            }
+            _ => path,
+        };
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+    }
+
+    pub fn format(&self) -> GeoJsonFormat {
+        self.format
+    }
+
+    pub fn is_gzipped(&self) -> bool {
+        self.gzipped
+    }
+
+    /// The size of the file, compressed when it is.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// The features of the file counted, and its `crs` and `bbox`, in one
+    /// pass that checks the JSON but does not build the features.
+    pub async fn scan(&self) -> Result<GeoJsonSummary, PgcError> {
+        let source = self.clone();
+        tokio::task::spawn_blocking(move || source.scan_blocking()).await?
+    }
+
+    fn scan_blocking(&self) -> Result<GeoJsonSummary, PgcError> {
+        let mut summary = GeoJsonSummary::default();
+        let reader = self.reader(Arc::default())?;
+        let counted = match self.format {
+            GeoJsonFormat::FeatureCollection => {
+                let mut features = 0;
+                read_feature_collection(reader, &mut summary, |_, _: IgnoredAny| {
+                    features += 1;
+                    Ok(())
+                })
+                .map(|()| features)
+            }
+            GeoJsonFormat::Lines => {
+                let mut features = 0;
+                for_each_line(BufReader::new(reader), |_, _: IgnoredAny| {
+                    features += 1;
+                    Ok(())
+                })
+                .map(|()| features)
+            }
+        };
+        summary.features = counted.map_err(|e| e.in_geojson(&self.path, None))?;
+        Ok(summary)
+    }
+
+    /// The features of the file, read on a thread of their own a few ahead
+    /// of those taken, from the first time one is.
+    pub fn features(&self) -> GeoJsonFeatures {
+        GeoJsonFeatures {
+            inner: FeaturesInner::Read {
+                source: self.clone(),
+                receiver: None,
+                bytes_read: Arc::default(),
+            },
+        }
+    }
+
+    /// The names of the features, as `feature_name` gives them, in a pass
+    /// of their own.
+    pub(crate) async fn feature_names(
+        &self,
+        name_property: Option<String>,
+    ) -> Result<Vec<String>, PgcError> {
+        let source = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut names = Vec::new();
+            source
+                .read_blocking(Arc::default(), |idx, feature| {
+                    names.push(feature_name(&feature, idx, name_property.as_deref()));
+                    Ok(())
+                })
+                .map(|()| names)
+        })
+        .await?
+    }
+
+    /// Every feature of the file to `on_feature`, in order and on the
+    /// calling thread, the bytes of the file read so far in `bytes_read`.
+    pub(crate) fn read_blocking<F>(
+        &self,
+        bytes_read: Arc<AtomicU64>,
+        on_feature: F,
+    ) -> Result<(), PgcError>
+    where
+        F: FnMut(usize, geojson::Feature) -> Result<(), PgcError>,
+    {
+        let reader = self.reader(bytes_read)?;
+        match self.format {
+            GeoJsonFormat::FeatureCollection => {
+                read_feature_collection(reader, &mut GeoJsonSummary::default(), on_feature)
+            }
+            GeoJsonFormat::Lines => for_each_line(BufReader::new(reader), on_feature),
+        }
+        .map_err(|e| e.in_geojson(&self.path, None))
+    }
+
+    /// The file from its start, decompressed, counting the bytes read.
+    fn reader(&self, bytes_read: Arc<AtomicU64>) -> Result<Box<dyn Read>, PgcError> {
+        let file = File::open(&self.path).map_err(|e| PgcError::io(&self.path, e))?;
+        gzip_aware(CountingReader {
+            inner: file,
+            bytes_read,
+        })
+        .map_err(|e| PgcError::io(&self.path, e))
+    }
+}
+
+/// The top level `type` of a GeoJSON object.
+#[derive(Deserialize)]
+struct GeoJsonType {
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// The format of the GeoJSON `reader` reads, by its first line that is not
+/// blank. A line holding a whole Feature starts GeoJSONL; one holding the
+/// start of an object, or a whole FeatureCollection, a FeatureCollection.
+fn sniff_format<R: BufRead>(reader: R) -> Result<GeoJsonFormat, PgcError> {
+    let mut reader = reader.take(SNIFF_BYTES);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader
+            .read_until(b'\n', &mut line)
+            .map_err(|e| PgcError::GeoJson {
+                path: None,
+                feature_index: None,
+                message: format!("Failed to read GeoJSON: {}", e),
+            })?;
+        if read == 0 {
+            return Err(PgcError::GeoJson {
+                path: None,
+                feature_index: None,
+                message: "The file has no GeoJSON object".to_string(),
+            });
+        }
+        let record = line.trim_ascii();
+        match record.first() {
+            None => continue,
+            Some(&RECORD_SEPARATOR) => return Ok(GeoJsonFormat::Lines),
+            Some(b'{') => {}
+            Some(_) => {
+                return Err(PgcError::GeoJson {
+                    path: None,
+                    feature_index: None,
+                    message: "The file does not start with a GeoJSON object".to_string(),
+                })
+            }
+        }
+        return match serde_json::from_slice::<GeoJsonType>(record) {
+            Ok(object) if object.kind == "Feature" => Ok(GeoJsonFormat::Lines),
+            Ok(object) if object.kind == "FeatureCollection" => {
+                Ok(GeoJsonFormat::FeatureCollection)
+            }
+            Ok(object) => Err(PgcError::GeoJson {
+                path: None,
+                feature_index: None,
+                message: format!(
+                    "The file holds a {}, not a FeatureCollection nor a Feature per line",
+                    object.kind
+                ),
+            }),
+            // The object goes on past its first line
+            Err(e) if e.is_eof() => Ok(GeoJsonFormat::FeatureCollection),
+            Err(e) => Err(PgcError::GeoJson {
+                path: None,
+                feature_index: None,
+                message: format!("Failed to parse GeoJSON: {}", e),
+            }),
+        };
+    }
+}
+
+/// Every line of GeoJSONL `reader` that is not blank to `on_feature`, as a
+/// `T`, with its index among them.
+fn for_each_line<R, T, F>(mut reader: R, mut on_feature: F) -> Result<(), PgcError>
+where
+    R: BufRead,
+    T: serde::de::DeserializeOwned,
+    F: FnMut(usize, T) -> Result<(), PgcError>,
+{
+    let mut line = String::new();
+    let mut line_number = 0;
+    let mut index = 0;
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line).map_err(|e| PgcError::GeoJson {
+            path: None,
+            feature_index: Some(index),
+            message: format!("Failed to read line {}: {}", line_number + 1, e),
+        })?;
+        if read == 0 {
+            return Ok(());
+        }
+        line_number += 1;
+        let record = line.trim_matches(|c: char| c.is_whitespace() || c == '\u{1e}');
+        if record.is_empty() {
+            continue;
+        }
+        let feature = serde_json::from_str(record).map_err(|e| PgcError::GeoJson {
+            path: None,
+            feature_index: Some(index),
+            message: format!("Failed to parse line {}: {}", line_number, e),
+        })?;
+        on_feature(index, feature)?;
+        index += 1;
+    }
+}
+
+/// A reader counting the bytes it reads, for the progress of a load.
+pub(crate) struct CountingReader<R> {
+    inner: R,
+    bytes_read: Arc<AtomicU64>,
+}
+
+impl<R> CountingReader<R> {
+    pub(crate) fn new(inner: R, bytes_read: Arc<AtomicU64>) -> Self {
+        Self { inner, bytes_read }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buffer)?;
+        self.bytes_read.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+/// The features of a `GeoJsonSource`, or of a stream, as it is read or
+/// parsed beforehand.
+pub struct GeoJsonFeatures {
+    inner: FeaturesInner,
+}
+
+enum FeaturesInner {
+    Read {
+        source: GeoJsonSource,
+        receiver: Option<Receiver<Result<(usize, geojson::Feature), PgcError>>>,
+        bytes_read: Arc<AtomicU64>,
+    },
+    Streamed {
+        receiver: Receiver<Result<(usize, geojson::Feature), PgcError>>,
+        bytes_read: Arc<AtomicU64>,
+    },
+    Parsed {
+        features: std::vec::IntoIter<geojson::Feature>,
+        next_index: usize,
+        total: usize,
+    },
+}
+
+impl GeoJsonFeatures {
+    /// `features`, already parsed.
+    pub(crate) fn from_features(features: Vec<geojson::Feature>) -> Self {
+        let total = features.len();
+        Self {
+            inner: FeaturesInner::Parsed {
+                features: features.into_iter(),
+                next_index: 0,
+                total,
+            },
+        }
+    }
+
+    /// The features `read` hands to the callback it is given, on a blocking
+    /// thread, at most `FEATURES_AHEAD` ahead of those taken, and the bytes
+    /// it read so far to the counter it is given. The callback fails once
+    /// the features are no longer taken. The handle holds what `read`
+    /// returned, `None` when it failed: its error is then the last feature.
+    pub(crate) fn streamed<T, F>(read: F) -> (Self, JoinHandle<Option<T>>)
+    where
+        T: Send + 'static,
+        F: FnOnce(
+                Arc<AtomicU64>,
+                &mut dyn FnMut(usize, geojson::Feature) -> Result<(), PgcError>,
+            ) -> Result<T, PgcError>
+            + Send
+            + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(FEATURES_AHEAD);
+        let bytes_read = Arc::<AtomicU64>::default();
+        let thread_bytes_read = bytes_read.clone();
+        let reading = tokio::task::spawn_blocking(move || {
+            let read = read(thread_bytes_read, &mut |idx, feature| {
+                sender
+                    .blocking_send(Ok((idx, feature)))
+                    .map_err(|_| PgcError::Cancelled {
+                        message: "🛑 The features of the stream are no longer read".to_string(),
+                    })
+            });
+            match read {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    let _ = sender.blocking_send(Err(e));
+                    None
+                }
+            }
+        });
+        let features = Self {
+            inner: FeaturesInner::Streamed {
+                receiver,
+                bytes_read,
+            },
+        };
+        (features, reading)
+    }
+
+    /// The next feature and its index, `None` once there are no more, or
+    /// the error that stopped the reading.
+    pub async fn next_feature(&mut self) -> Option<Result<(usize, geojson::Feature), PgcError>> {
+        match &mut self.inner {
+            FeaturesInner::Read {
+                source,
+                receiver,
+                bytes_read,
+            } => {
+                receiver
+                    .get_or_insert_with(|| read_ahead(source.clone(), bytes_read.clone()))
+                    .recv()
+                    .await
+            }
+            FeaturesInner::Streamed { receiver, .. } => receiver.recv().await,
+            FeaturesInner::Parsed {
+                features,
+                next_index,
+                ..
+            } => features.next().map(|feature| {
+                *next_index += 1;
+                Ok((*next_index - 1, feature))
+            }),
+        }
+    }
+
+    /// The bytes of the file or stream read so far, `None` for parsed
+    /// features.
+    pub fn bytes_read(&self) -> Option<u64> {
+        match &self.inner {
+            FeaturesInner::Read { bytes_read, .. } | FeaturesInner::Streamed { bytes_read, .. } => {
+                Some(bytes_read.load(Ordering::Relaxed))
+            }
+            FeaturesInner::Parsed { .. } => None,
+        }
+    }
+
+    /// The number of features, when they are parsed.
+    pub fn total(&self) -> Option<usize> {
+        match &self.inner {
+            FeaturesInner::Read { .. } | FeaturesInner::Streamed { .. } => None,
+            FeaturesInner::Parsed { total, .. } => Some(*total),
+        }
+    }
+
+    /// `count` features are processed, with the bytes of the file or stream
+    /// read so far, or in proportion to the features when they are parsed.
+    pub(crate) fn report_progress(&self, observer: &ObserverHandle, count: u64, force: bool) {
+        match &self.inner {
+            FeaturesInner::Read { bytes_read, .. } | FeaturesInner::Streamed { bytes_read, .. } => {
+                observer.features_read(count, bytes_read.load(Ordering::Relaxed), force)
+            }
+            FeaturesInner::Parsed { total, .. } => {
+                observer.features_processed(count, *total as u64, force)
+            }
+        }
+    }
+
+    /// The names of the features not taken yet, as `feature_name` gives
+    /// them: a pass of their own over a file. A stream is read once, its
+    /// features are named once they are parsed.
+    pub(crate) async fn names(&self, name_property: Option<&str>) -> Result<Vec<String>, PgcError> {
+        match &self.inner {
+            FeaturesInner::Streamed { .. } => Err(PgcError::invalid_input(
+                "❌ The features of a stream cannot be named before they are read",
+            )),
+            FeaturesInner::Read { source, .. } => {
+                source
+                    .feature_names(name_property.map(str::to_string))
+                    .await
+            }
+            FeaturesInner::Parsed {
+                features,
+                next_index,
+                ..
+            } => Ok(features
+                .as_slice()
+                .iter()
+                .enumerate()
+                .map(|(idx, feature)| feature_name(feature, next_index + idx, name_property))
+                .collect()),
+        }
+    }
+}
+
+/// The features of `source` sent on a thread of their own, at most
+/// `FEATURES_AHEAD` ahead of those received. The thread stops when the
+/// receiver is dropped.
+fn read_ahead(
+    source: GeoJsonSource,
+    bytes_read: Arc<AtomicU64>,
+) -> Receiver<Result<(usize, geojson::Feature), PgcError>> {
+    let (sender, receiver) = mpsc::channel(FEATURES_AHEAD);
+    let thread_sender = sender.clone();
+    let spawned = std::thread::Builder::new()
+        .name("pgc-geojson".to_string())
+        .spawn(move || {
+            let read = source.read_blocking(bytes_read, |idx, feature| {
+                thread_sender
+                    .blocking_send(Ok((idx, feature)))
+                    .map_err(|_| PgcError::Cancelled {
+                        message: format!("🛑 The features of {} are no longer read", source.path),
+                    })
+            });
+            if let Err(e) = read {
+                let _ = thread_sender.blocking_send(Err(e));
+            }
+        });
+    if let Err(e) = spawned {
+        let _ = sender.try_send(Err(e.into()));
+    }
+    receiver
+}
@@ -1,71 +1,61 @@
-use anyhow::Result;
-// use flate2::bufread::GzDecoder;
-use async_compression::tokio::write::GzipDecoder;
 use bytes::BytesMut;
-use flate2::read::GzDecoder;
+#[cfg(feature = "ingest")]
+use flate2::read::MultiGzDecoder;
 use futures::stream::FuturesUnordered;
 use futures::SinkExt;
-use geojson::{GeoJson, Geometry};
-use indicatif::{ProgressBar, ProgressStyle};
+use geojson::{GeoJson, Geometry, JsonObject};
+use serde::de::{
+    DeserializeOwned, DeserializeSeed, Error as _, IgnoredAny, MapAccess, SeqAccess, Visitor,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{Deserializer, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::error::Error as StdError;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
 use tokio::fs;
 use tokio::fs::File as TokioFile;
 
-use std::fmt::Display;
+use crate::utils::error::{ErrorKind, PgcError};
+use crate::utils::sql::PgType;
+use crate::utils::{cancel, sql};
+use crate::write::dedupe::DedupeState;
+use crate::write::manifest::HashingReader;
+use crate::write::options::{
+    BoundingBox, Dedupe, GeoTableOptions, IngestOptions, InsertStrategy, OnFeatureError,
+};
+use crate::write::reports::FileReport;
+use crate::write::source::{CountingReader, GeoJsonFeatures, GeoJsonSource, GeoJsonSummary};
+use deadpool_postgres::{Object, Pool, PoolError};
+use std::fmt::{Display, Write as _};
+use std::time::{Duration, Instant};
 use tokio::io::AsyncRead;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
 use tokio_postgres::{Client, CopyInSink}; // Make sure this is imported
+use tokio_util::io::SyncIoBridge;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
 
-pub struct GeoJSONFile {
-    pub file_name: String,
-    pub json_data: Value,
-}
-
-impl GeoJSONFile {
-    pub async fn process_geojson_file(path: &str) -> Result<Self, Box<dyn StdError>> {
-        let data = tokio::fs::read_to_string(path).await?;
-
-        // Parse JSON into Value
-        let json_data: Value = serde_json::from_str(&data)?;
-
-        let file_name = Path::new(path)
-            .file_name()
-            .and_then(|s| s.to_str())
-            .ok_or_else(|| {
-                let err_msg = format!("❌ Unable to get file name from path: {}", path);
-                Box::<dyn StdError>::from(err_msg)
-            })?
-            .split(".")
-            .next()
-            .ok_or_else(|| {
-                let err_msg = format!("❌ Unable to get file name from path: {}", path);
-                Box::<dyn StdError>::from(err_msg)
-            })?
-            .to_string();
-
-        Ok(Self {
-            file_name,
-            json_data,
-        })
-    }
-}
-
-pub fn convert_path(path_str: &str) -> Result<&Path, Box<dyn StdError>> {
+pub(crate) fn convert_path(path_str: &str) -> Result<&Path, PgcError> {
     let path: &Path = Path::new(path_str);
     if path.exists() {
-        println!("Path exists!");
+        debug!("Path exists!");
         Ok(path)
     } else {
-        eprintln!("Path does not exist: {}", path_str);
-        Err(format!("Path does not exist: {}", path_str).into())
+        info!("Path does not exist: {}", path_str);
+        Err(PgcError::invalid_input(format!(
+            "Path does not exist: {}",
+            path_str
+        )))
     }
 }
 
-pub async fn get_all_file_paths(dir_path: &Path) -> Result<Vec<String>, Box<dyn StdError>> {
+pub(crate) async fn get_all_file_paths(dir_path: &Path) -> Result<Vec<String>, PgcError> {
     let mut paths = Vec::new();
     let mut entries = fs::read_dir(dir_path).await?;
 
@@ -82,13 +72,144 @@ pub async fn get_all_file_paths(dir_path: &Path) -> Result<Vec<String>, Box<dyn
     Ok(paths)
 }
 
+/// Lists the GeoJSON files to ingest for a path.
+///
+/// A file path is returned as is. For a directory, all `.geojson`, `.json`
+/// and `.geojsonl` files in it and its subdirectories are returned, sorted by
+/// path so split chunks are loaded in order.
+pub(crate) async fn get_geojson_file_paths(path: &str) -> Result<Vec<String>, PgcError> {
+    let path = convert_path(path)?;
+    if !path.is_dir() {
+        return Ok(vec![path.to_string_lossy().into_owned()]);
+    }
+    let mut paths: Vec<String> = get_all_file_paths(path)
+        .await?
+        .into_iter()
+        .filter(|file_path| {
+            Path::new(file_path)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .map(|extension| {
+                    extension.eq_ignore_ascii_case("geojson")
+                        || extension.eq_ignore_ascii_case("json")
+                        || extension.eq_ignore_ascii_case("geojsonl")
+                })
+                .unwrap_or(false)
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// The raster files to load from a path: the file itself, or the
+/// `.tif`/`.tiff` files of a directory, recursively and in sorted order.
+pub(crate) async fn get_raster_file_paths(path: &str) -> Result<Vec<String>, PgcError> {
+    let path = convert_path(path)?;
+    if !path.is_dir() {
+        return Ok(vec![path.to_string_lossy().into_owned()]);
+    }
+    let mut paths: Vec<String> = get_all_file_paths(path)
+        .await?
+        .into_iter()
+        .filter(|file_path| {
+            Path::new(file_path)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .map(|extension| {
+                    extension.eq_ignore_ascii_case("tif") || extension.eq_ignore_ascii_case("tiff")
+                })
+                .unwrap_or(false)
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FeatureWithMeta {
+pub(crate) struct FeatureWithMeta {
     pub dataset_name: String,
     pub properties: Value,
     pub geometry_wkt: String,
 }
 
+/// Selects features in a geo table.
+///
+/// * `Properties`: rows whose `properties` contain the given JSON (`properties @> $1`).
+/// * `Names`: rows whose `name` is in the list.
+/// * `BBox`: rows whose geometry bounding box intersects the box, given in the
+///   table's own coordinates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FeatureFilter {
+    Properties(Value),
+    Names(Vec<String>),
+    BBox {
+        min_x: f64,
+        min_y: f64,
+        max_x: f64,
+        max_y: f64,
+    },
+}
+
+impl FeatureFilter {
+    /// Whether this filter would match every row of the table.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            FeatureFilter::Properties(properties) => properties
+                .as_object()
+                .map(|object| object.is_empty())
+                .unwrap_or(properties.is_null()),
+            FeatureFilter::Names(names) => names.is_empty(),
+            FeatureFilter::BBox { .. } => false,
+        }
+    }
+
+    /// Builds the `WHERE` condition for this filter and its bind parameters.
+    ///
+    /// `srid` is the SRID of the table's `geometry` column, the box of `BBox`
+    /// is built in it as a constant so the spatial index can be used. Without
+    /// one, as for a column holding mixed SRIDs, the box takes the SRID of
+    /// each row, which the index can't serve.
+    pub fn to_sql(&self, srid: Option<i32>) -> (String, Vec<Box<dyn ToSql + Sync + Send>>) {
+        match self {
+            FeatureFilter::Properties(properties) => (
+                "properties @> $1".to_string(),
+                vec![Box::new(properties.clone())],
+            ),
+            FeatureFilter::Names(names) => {
+                ("name = ANY($1)".to_string(), vec![Box::new(names.clone())])
+            }
+            FeatureFilter::BBox {
+                min_x,
+                min_y,
+                max_x,
+                max_y,
+            } => (
+                format!(
+                    "geometry && ST_MakeEnvelope($1, $2, $3, $4, {})",
+                    srid.map(|srid| srid.to_string())
+                        .unwrap_or_else(|| "ST_SRID(geometry)".to_string())
+                ),
+                vec![
+                    Box::new(*min_x),
+                    Box::new(*min_y),
+                    Box::new(*max_x),
+                    Box::new(*max_y),
+                ],
+            ),
+        }
+    }
+}
+
+/// How `update_properties` applies a patch to the existing properties.
+///
+/// * `Merge`: keys of the patch are added or overwritten (`properties || patch`).
+/// * `Replace`: the properties are replaced by the patch entirely.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PatchMode {
+    Merge,
+    Replace,
+}
+
 /// Converts a GeoJSON geometry to its WKT representation.
 ///
 /// This function takes a `Geometry` object from the `geojson` crate and converts it into a
@@ -102,186 +223,1788 @@ pub struct FeatureWithMeta {
 /// # Returns
 ///
 /// A `Result` containing either the WKT representation as a `String` or an error if the conversion fails.
-pub fn geometry_to_wkt(geom: &Geometry) -> Result<String> {
-    match geom.value.clone() {
-        geojson::Value::Point(c) => Ok(format!("POINT({} {})", c[0], c[1])),
-        geojson::Value::MultiPoint(coords) => Ok(format!(
-            "MULTIPOINT({})",
-            coords
-                .into_iter()
-                .map(|p| format!("({} {})", p[0], p[1]))
-                .collect::<Vec<_>>()
-                .join(", ")
-        )),
-        geojson::Value::LineString(coords) => Ok(format!(
-            "LINESTRING({})",
-            coords
-                .into_iter()
-                .map(|p| format!("{} {}", p[0], p[1]))
-                .collect::<Vec<_>>()
-                .join(", ")
-        )),
-        geojson::Value::MultiLineString(lines) => Ok(format!(
-            "MULTILINESTRING({})",
-            lines
-                .into_iter()
-                .map(|line| format!(
-                    "({})",
-                    line.into_iter()
-                        .map(|p| format!("{} {}", p[0], p[1]))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                ))
-                .collect::<Vec<_>>()
-                .join(", ")
-        )),
-        geojson::Value::Polygon(polygons) => Ok(format!(
-            "POLYGON({})",
-            polygons
-                .into_iter()
-                .map(|ring| format!(
-                    "({})",
-                    ring.into_iter()
-                        .map(|p| format!("{} {}", p[0], p[1]))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                ))
-                .collect::<Vec<_>>()
-                .join(", ")
-        )),
-        geojson::Value::MultiPolygon(multipolygons) => Ok(format!(
-            "MULTIPOLYGON({})",
-            multipolygons
-                .into_iter()
-                .map(|poly| format!(
-                    "({})",
-                    poly.into_iter()
-                        .map(|ring| format!(
-                            "({})",
-                            ring.into_iter()
-                                .map(|p| format!("{} {}", p[0], p[1]))
-                                .collect::<Vec<_>>()
-                                .join(", ")
-                        ))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                ))
-                .collect::<Vec<_>>()
-                .join(", ")
-        )),
-        _ => anyhow::bail!("Unsupported geometry type"),
-    }
-}
-
-/// Processes a GeoJSON file and uploads it to the database.
-///
-/// This function takes a database client, an input file path, and a table name. It opens the input
-/// file, reads the GeoJSON data, and uploads it to the specified table using a COPY operation.
 ///
-/// # Parameters
+/// # Examples
 ///
-/// * `client`: A reference to a `Client` object from the `tokio_postgres` crate.
-/// * `input_file`: The path to the GeoJSON file to process.
-/// * `table_name`: The name of the table to upload the GeoJSON data to.
+/// ```
+/// use geojson::{Geometry, Value};
+/// use postgres_connect_rust::geometry_to_wkt;
 ///
-/// # Returns
+/// let point = Geometry::new(Value::Point(vec![4.9, 52.37]));
+/// assert_eq!(geometry_to_wkt(&point).unwrap(), "POINT(4.9 52.37)");
+/// ```
+pub fn geometry_to_wkt(geom: &Geometry) -> Result<String, PgcError> {
+    geometry_to_wkt_with_precision(geom, None)
+}
+
+/// Same as `geometry_to_wkt`, with the coordinates rounded to `precision`
+/// decimals when given.
 ///
-/// A `Result` indicating success or an error.
+/// Rounding can make consecutive vertices equal; the duplicates are removed.
+/// A line left with fewer than 2 distinct points or a ring with fewer than 4
+/// is an error, rather than an invalid geometry. Numbers are always written
+/// in plain decimal notation, never as e.g. `1e-7`.
+pub fn geometry_to_wkt_with_precision(
+    geom: &Geometry,
+    precision: Option<u8>,
+) -> Result<String, PgcError> {
+    let mut wkt = String::new();
+    write_wkt(geom, precision, &mut wkt)?;
+    Ok(wkt)
+}
+
+/// Appends the WKT of `geom` to `out`, as `geometry_to_wkt_with_precision`
+/// returns it, so that one buffer can be reused from a geometry to the
+/// next. The coordinates are written straight into `out`, in one pass. On an
+/// error, `out` is left as it was.
 ///
-/// # Errors
+/// ```
+/// use geojson::{Geometry, Value};
+/// use postgres_connect_rust::write_wkt;
 ///
-/// * `Box<dyn StdError>`: If an error occurs during the processing or upload process.
+/// let mut wkt = String::new();
+/// for x in [1.5, 2.0] {
+///     wkt.clear();
+///     write_wkt(&Geometry::new(Value::Point(vec![x, 0.0])), None, &mut wkt).unwrap();
+/// }
+/// assert_eq!(wkt, "POINT(2 0)");
+/// ```
+pub fn write_wkt(geom: &Geometry, precision: Option<u8>, out: &mut String) -> Result<(), PgcError> {
+    let start = out.len();
+    let written = write_wkt_value(&geom.value, precision, out);
+    if written.is_err() {
+        out.truncate(start);
+    }
+    written
+}
+
+fn write_wkt_value(
+    value: &geojson::Value,
+    precision: Option<u8>,
+    out: &mut String,
+) -> Result<(), PgcError> {
+    match value {
+        geojson::Value::Point(position) => {
+            out.push_str("POINT(");
+            let (x, y) = position_xy(position, precision)?;
+            write_position(x, y, out);
+        }
+        geojson::Value::MultiPoint(positions) => {
+            out.push_str("MULTIPOINT(");
+            write_parenthesized(positions, out, |position, out| {
+                let (x, y) = position_xy(position, precision)?;
+                write_position(x, y, out);
+                Ok(())
+            })?;
+        }
+        geojson::Value::LineString(positions) => {
+            out.push_str("LINESTRING(");
+            write_positions(positions, precision, 2, out)?;
+        }
+        geojson::Value::MultiLineString(lines) => {
+            out.push_str("MULTILINESTRING(");
+            write_parenthesized(lines, out, |line, out| {
+                write_positions(line, precision, 2, out)
+            })?;
+        }
+        geojson::Value::Polygon(rings) => {
+            out.push_str("POLYGON(");
+            write_parenthesized(rings, out, |ring, out| {
+                write_positions(ring, precision, 4, out)
+            })?;
+        }
+        geojson::Value::MultiPolygon(polygons) => {
+            out.push_str("MULTIPOLYGON(");
+            write_parenthesized(polygons, out, |rings, out| {
+                write_parenthesized(rings, out, |ring, out| {
+                    write_positions(ring, precision, 4, out)
+                })
+            })?;
+        }
+        _ => return Err(invalid_geometry("Unsupported geometry type".to_string())),
+    }
+    out.push(')');
+    Ok(())
+}
+
+/// Writes each of `items` with `write_item`, in parentheses and separated by
+/// `, `.
+fn write_parenthesized<T>(
+    items: &[T],
+    out: &mut String,
+    mut write_item: impl FnMut(&T, &mut String) -> Result<(), PgcError>,
+) -> Result<(), PgcError> {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        out.push('(');
+        write_item(item, out)?;
+        out.push(')');
+    }
+    Ok(())
+}
+
+/// The error of a geometry that cannot be written as WKT.
+fn invalid_geometry(message: String) -> PgcError {
+    PgcError::GeoJson {
+        path: None,
+        feature_index: None,
+        message,
+    }
+}
+
+/// Rounds a coordinate to `precision` decimals.
+fn round_coordinate(value: f64, precision: Option<u8>) -> f64 {
+    match precision {
+        Some(precision) => {
+            let factor = 10f64.powi(precision as i32);
+            let rounded = (value * factor).round() / factor;
+            // Avoid writing "-0"
+            if rounded == 0.0 {
+                0.0
+            } else {
+                rounded
+            }
+        }
+        None => value,
+    }
+}
+
+/// The x and y of a position, rounded to `precision` decimals. A position
+/// of fewer than 2 numbers, which GeoJSON parsers accept, is an error.
+fn position_xy(position: &[f64], precision: Option<u8>) -> Result<(f64, f64), PgcError> {
+    match position {
+        [x, y, ..] => Ok((
+            round_coordinate(*x, precision),
+            round_coordinate(*y, precision),
+        )),
+        _ => Err(invalid_geometry(format!(
+            "Position {:?} has fewer than 2 coordinates",
+            position
+        ))),
+    }
+}
+
+/// Writes a position as `x y`. `f64`'s `Display` never uses scientific notation.
+fn write_position(x: f64, y: f64, out: &mut String) {
+    // Writing to a String cannot fail
+    let _ = write!(out, "{} {}", x, y);
+}
+
+/// Writes the positions of a line or ring, dropping the consecutive
+/// duplicates left by rounding, and checks that at least `min_points` remain.
+fn write_positions(
+    positions: &[geojson::Position],
+    precision: Option<u8>,
+    min_points: usize,
+    out: &mut String,
+) -> Result<(), PgcError> {
+    let mut written = 0;
+    let mut last: Option<(f64, f64)> = None;
+    for position in positions {
+        let point = position_xy(position, precision)?;
+        if precision.is_some() && last.is_some_and(|last| same_point(last, point)) {
+            continue;
+        }
+        if written > 0 {
+            out.push_str(", ");
+        }
+        write_position(point.0, point.1, out);
+        last = Some(point);
+        written += 1;
+    }
+    if precision.is_some() && written < min_points.min(positions.len()) {
+        return Err(invalid_geometry(format!(
+            "Geometry collapses to {} distinct points when rounded to {} decimals",
+            written,
+            precision.unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
+
+/// Whether two rounded points are written the same, `NaN` included.
+fn same_point(a: (f64, f64), b: (f64, f64)) -> bool {
+    let same = |a: f64, b: f64| a == b || (a.is_nan() && b.is_nan());
+    same(a.0, b.0) && same(a.1, b.1)
+}
+
+/// Parses a GeoJSON file and returns its features.
 ///
 /// # Examples
 ///
+/// ```no_run
+/// use postgres_connect_rust::parse_geojson_features;
+///
+/// let features = parse_geojson_features("data/parcels.geojson")?;
+/// println!("{} features", features.len());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
-/// let client = Client::connect("host=localhost user=postgres password=postgres", "").await?;
-/// let result = process_file(&client, "path/to/geojson.json", "my_table").await;
-/// ```
-/// Parses a GeoJSON file and returns its features.
-pub fn parse_geojson_features(
-    input_file: &str,
-) -> Result<Vec<geojson::Feature>, Box<dyn StdError>> {
-    let file = File::open(input_file)
-        .map_err(|e| format!("❌ Failed to open input file: {}: {}", input_file, e))?;
-    let reader = BufReader::new(file);
-    let geojson: GeoJson = serde_json::from_reader(reader)
-        .map_err(|e| format!("❌ Failed to parse GeoJSON: {}", e))?;
-    match geojson {
-        GeoJson::FeatureCollection(fc) => Ok(fc.features),
-        _ => Err("GeoJSON file does not contain a FeatureCollection".into()),
+pub fn parse_geojson_features(input_file: &str) -> Result<Vec<geojson::Feature>, PgcError> {
+    let mut features = Vec::new();
+    for_each_geojson_feature(input_file, |_, feature| {
+        features.push(feature);
+        Ok(())
+    })?;
+    Ok(features)
+}
+
+/// First bytes of every gzip stream.
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// `reader`, buffered, and decompressed if it starts like a gzip stream,
+/// whatever the name of the file it reads. Without the `ingest` feature, a
+/// gzip stream is an error.
+pub(crate) fn gzip_aware<'a, R: Read + 'a>(reader: R) -> std::io::Result<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::new(reader);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        gunzip(reader)
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// The decompressed stream of `reader`, the gzip stream `gzip_aware` found.
+#[cfg(feature = "ingest")]
+fn gunzip<'a, R: BufRead + 'a>(reader: R) -> std::io::Result<Box<dyn Read + 'a>> {
+    Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+}
+
+#[cfg(not(feature = "ingest"))]
+fn gunzip<'a, R: BufRead + 'a>(_reader: R) -> std::io::Result<Box<dyn Read + 'a>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "❌ Reading gzipped GeoJSON needs the `ingest` feature",
+    ))
+}
+
+/// Streams the features of a GeoJSON file, a FeatureCollection or GeoJSONL,
+/// to `on_feature`, one at a time and in file order, along with their index.
+///
+/// Only one feature is held in memory at once. This is the parser used both
+/// for loading and validating files, so the two agree on what is parseable.
+/// An error returned by `on_feature` stops the parsing and is returned as is.
+/// Gzipped files are decompressed, see `GeoJsonSource`.
+pub(crate) fn for_each_geojson_feature<F>(input_file: &str, on_feature: F) -> Result<(), PgcError>
+where
+    F: FnMut(usize, geojson::Feature) -> Result<(), PgcError>,
+{
+    GeoJsonSource::open_blocking(input_file)?.read_blocking(Arc::default(), on_feature)
+}
+
+/// `for_each_geojson_feature` on a reader of a FeatureCollection, which is
+/// read as it is: buffering and decompressing it is left to the caller.
+pub fn for_each_geojson_feature_in<R, F>(reader: R, on_feature: F) -> Result<(), PgcError>
+where
+    R: Read,
+    F: FnMut(usize, geojson::Feature) -> Result<(), PgcError>,
+{
+    read_feature_collection(reader, &mut GeoJsonSummary::default(), on_feature)
+}
+
+/// `for_each_geojson_feature_in` with the features read as `T`, e.g.
+/// `IgnoredAny` to count them, and the `crs` and `bbox` of the collection
+/// kept in `summary`.
+pub(crate) fn read_feature_collection<R, T, F>(
+    reader: R,
+    summary: &mut GeoJsonSummary,
+    on_feature: F,
+) -> Result<(), PgcError>
+where
+    R: Read,
+    T: DeserializeOwned,
+    F: FnMut(usize, T) -> Result<(), PgcError>,
+{
+    let mut deserializer = Deserializer::from_reader(reader);
+    let mut callback_error = None;
+    let result = FeatureCollectionSeed {
+        on_feature,
+        callback_error: &mut callback_error,
+        summary,
+        feature: PhantomData,
+    }
+    .deserialize(&mut deserializer)
+    .and_then(|_| deserializer.end());
+    match (callback_error, result) {
+        (Some(e), _) => Err(e),
+        (None, Err(e)) => Err(PgcError::GeoJson {
+            path: None,
+            feature_index: None,
+            message: format!("Failed to parse GeoJSON: {}", e),
+        }),
+        (None, Ok(())) => Ok(()),
+    }
+}
+
+/// Deserializes a FeatureCollection object, handing out its features as `T`s
+/// instead of collecting them.
+struct FeatureCollectionSeed<'a, T, F> {
+    on_feature: F,
+    callback_error: &'a mut Option<PgcError>,
+    summary: &'a mut GeoJsonSummary,
+    feature: PhantomData<T>,
+}
+
+impl<'de, T, F> DeserializeSeed<'de> for FeatureCollectionSeed<'_, T, F>
+where
+    T: Deserialize<'de>,
+    F: FnMut(usize, T) -> Result<(), PgcError>,
+{
+    type Value = ();
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, T, F> Visitor<'de> for FeatureCollectionSeed<'_, T, F>
+where
+    T: Deserialize<'de>,
+    F: FnMut(usize, T) -> Result<(), PgcError>,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a GeoJSON FeatureCollection")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        let mut is_collection = false;
+        let mut has_features = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "type" => {
+                    let kind: String = map.next_value()?;
+                    is_collection = kind == "FeatureCollection";
+                    if !is_collection {
+                        break;
+                    }
+                }
+                "features" => {
+                    map.next_value_seed(FeaturesSeed {
+                        on_feature: &mut self.on_feature,
+                        callback_error: self.callback_error,
+                        feature: PhantomData,
+                    })?;
+                    has_features = true;
+                }
+                "crs" => self.summary.crs = Some(map.next_value()?),
+                "bbox" => self.summary.bbox = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        if !is_collection || !has_features {
+            return Err(A::Error::custom(
+                "GeoJSON file does not contain a FeatureCollection",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Deserializes the `features` array, passing each feature to the callback.
+struct FeaturesSeed<'a, T, F> {
+    on_feature: &'a mut F,
+    callback_error: &'a mut Option<PgcError>,
+    feature: PhantomData<T>,
+}
+
+impl<'de, T, F> DeserializeSeed<'de> for FeaturesSeed<'_, T, F>
+where
+    T: Deserialize<'de>,
+    F: FnMut(usize, T) -> Result<(), PgcError>,
+{
+    type Value = ();
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
     }
 }
 
-/// Uploads features to the database using the COPY command.
-pub async fn upload_features_copy(
-    client: &Client,
+impl<'de, T, F> Visitor<'de> for FeaturesSeed<'_, T, F>
+where
+    T: Deserialize<'de>,
+    F: FnMut(usize, T) -> Result<(), PgcError>,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an array of GeoJSON features")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut idx = 0;
+        while let Some(feature) = seq.next_element::<T>()? {
+            if let Err(e) = (self.on_feature)(idx, feature) {
+                *self.callback_error = Some(e);
+                return Err(A::Error::custom("stopped by the feature callback"));
+            }
+            idx += 1;
+        }
+        Ok(())
+    }
+}
+
+/// A feature that could not be converted for upload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct FeatureError {
+    pub index: usize,
+    pub name: String,
+    pub error: String,
+}
+
+/// At most this many skipped features are recorded in the errors sidecar file.
+const MAX_RECORDED_FEATURE_ERRORS: usize = 1000;
+
+/// Name of a feature: its `name_property` when given and the feature has
+/// it, else its id if it has one, `unknown_<index>` otherwise.
+pub(crate) fn feature_name(
+    feature: &geojson::Feature,
+    idx: usize,
+    name_property: Option<&str>,
+) -> String {
+    let property = name_property.and_then(|key| feature.property(key));
+    match property {
+        Some(Value::String(name)) => return name.clone(),
+        Some(Value::Null) | None => {}
+        Some(value) => return value.to_string(),
+    }
+    match feature.id {
+        Some(geojson::feature::Id::String(ref s)) => s.clone(),
+        Some(geojson::feature::Id::Number(ref n)) => n.to_string(),
+        None => format!("unknown_{}", idx),
+    }
+}
+
+/// Property keys that ended up with the same name after flattening or
+/// normalization, mapped to the original keys that produced them.
+pub(crate) type KeyCollisions = BTreeMap<String, BTreeSet<String>>;
+
+/// Rewrites property keys according to `flatten_properties` and `normalize_keys`.
+///
+/// Flattening turns nested objects into dotted keys, `{"admin": {"name_en": ..}}`
+/// becoming `{"admin.name_en": ..}`; arrays are kept as they are. Normalizing
+/// lowercases keys and replaces whitespace with underscores. When several keys
+/// end up the same, the first one is kept and the collision is recorded in
+/// `collisions`.
+pub(crate) fn transform_property_keys(
+    properties: JsonObject,
+    flatten: bool,
+    normalize: bool,
+    collisions: &mut KeyCollisions,
+) -> JsonObject {
+    fn visit(
+        prefix: Option<&str>,
+        properties: JsonObject,
+        flatten: bool,
+        normalize: bool,
+        sources: &mut HashMap<String, String>,
+        result: &mut JsonObject,
+        collisions: &mut KeyCollisions,
+    ) {
+        for (key, value) in properties {
+            let source = match prefix {
+                Some(prefix) => format!("{}.{}", prefix, key),
+                None => key,
+            };
+            let value = match value {
+                Value::Object(nested) if flatten && !nested.is_empty() => {
+                    visit(
+                        Some(&source),
+                        nested,
+                        flatten,
+                        normalize,
+                        sources,
+                        result,
+                        collisions,
+                    );
+                    continue;
+                }
+                value => value,
+            };
+            let key = if normalize {
+                source
+                    .chars()
+                    .map(|c| if c.is_whitespace() { '_' } else { c })
+                    .collect::<String>()
+                    .to_lowercase()
+            } else {
+                source.clone()
+            };
+            match sources.get(&key) {
+                Some(first) => {
+                    let sources = collisions.entry(key).or_default();
+                    sources.insert(first.clone());
+                    sources.insert(source);
+                }
+                None => {
+                    sources.insert(key.clone(), source);
+                    result.insert(key, value);
+                }
+            }
+        }
+    }
+
+    let mut result = JsonObject::new();
+    visit(
+        None,
+        properties,
+        flatten,
+        normalize,
+        &mut HashMap::new(),
+        &mut result,
+        collisions,
+    );
+    result
+}
+
+/// The member geometries of a Multi* geometry, or `None` for other geometries.
+fn multi_members(geometry: &Geometry) -> Option<Vec<Geometry>> {
+    match geometry.value {
+        geojson::Value::MultiPoint(ref points) => Some(
+            points
+                .iter()
+                .map(|point| Geometry::new(geojson::Value::Point(point.clone())))
+                .collect(),
+        ),
+        geojson::Value::MultiLineString(ref lines) => Some(
+            lines
+                .iter()
+                .map(|line| Geometry::new(geojson::Value::LineString(line.clone())))
+                .collect(),
+        ),
+        geojson::Value::MultiPolygon(ref polygons) => Some(
+            polygons
+                .iter()
+                .map(|polygon| Geometry::new(geojson::Value::Polygon(polygon.clone())))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// A feature converted for upload: its name, properties and WKT geometry.
+struct FeatureRow {
+    name: String,
+    properties: Value,
+    geometry: Option<String>,
+    /// Value of the primary key column, with `PrimaryKey::Property`
+    key: Option<String>,
+}
+
+impl FeatureRow {
+    fn new(
+        name: String,
+        properties: &Option<JsonObject>,
+        geometry: Option<&Geometry>,
+        precision: Option<u8>,
+    ) -> Result<Self, PgcError> {
+        let properties = serde_json::to_value(properties).map_err(|e| {
+            PgcError::invalid_input(format!("Failed to serialize properties: {}", e))
+        })?;
+        let geometry = match geometry {
+            Some(geom) => Some(
+                geometry_to_wkt_with_precision(geom, precision).map_err(|e| {
+                    PgcError::invalid_input(format!("Failed to convert geometry to WKT: {}", e))
+                })?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            name,
+            properties,
+            geometry,
+            key: None,
+        })
+    }
+
+    /// Converts a feature into the rows loaded for it.
+    ///
+    /// That is a single row, unless `explode_multi` is set and the geometry is
+    /// a MultiPoint, MultiLineString or MultiPolygon: each member geometry then
+    /// gets its own row, named `name_1`, `name_2`, … with the feature's properties.
+    /// Coordinates are rounded to `coordinate_precision` decimals, and the
+    /// value of `key_property` is taken as the primary key.
+    fn from_feature(
+        name: String,
+        feature: &geojson::Feature,
+        options: &IngestOptions,
+        key_property: Option<&str>,
+    ) -> Result<Vec<Self>, PgcError> {
+        let key = match key_property {
+            Some(key) => Some(key_value(&feature.properties, key)?),
+            None => None,
+        };
+        let members = match feature.geometry {
+            Some(ref geometry) if options.explode_multi => multi_members(geometry),
+            _ => None,
+        };
+        let mut rows = match members {
+            Some(members) if !members.is_empty() => members
+                .iter()
+                .enumerate()
+                .map(|(i, member)| {
+                    Self::new(
+                        format!("{}_{}", name, i + 1),
+                        &feature.properties,
+                        Some(member),
+                        options.coordinate_precision,
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => vec![Self::new(
+                name,
+                &feature.properties,
+                feature.geometry.as_ref(),
+                options.coordinate_precision,
+            )?],
+        };
+        for row in &mut rows {
+            row.key.clone_from(&key);
+        }
+        Ok(rows)
+    }
+
+    /// Builds the CSV line sent to COPY.
+    fn to_csv_line(&self, srid: Option<i32>) -> String {
+        feature_csv_line(
+            &self.name,
+            &self.properties,
+            self.geometry.as_deref(),
+            srid,
+            self.key.as_deref(),
+        )
+    }
+}
+
+/// The CSV line COPY loads for a feature named `name`, with its
+/// `properties`, its geometry as WKT, see `geometry_to_wkt`, tagged with
+/// `srid` when given, and the value of its primary key column, if any.
+/// Without a geometry, the field is left empty, which COPY reads as NULL.
+pub fn feature_csv_line(
+    name: &str,
+    properties: &Value,
+    wkt: Option<&str>,
+    srid: Option<i32>,
+    key: Option<&str>,
+) -> String {
+    let geometry = match wkt {
+        // Tag the geometry with the column's SRID as EWKT so typed columns accept it
+        Some(wkt) => match srid {
+            Some(srid) => escape_csv_field(&format!("SRID={};{}", srid, wkt)),
+            None => escape_csv_field(wkt),
+        },
+        // Left unquoted, the empty field is NULL
+        None => String::new(),
+    };
+    // JSON nearly always holds a comma or a quote
+    let mut line = format!(
+        "{},{},{}",
+        escape_csv_field(name),
+        quote_csv_field(&properties.to_string()),
+        geometry
+    );
+    if let Some(key) = key {
+        // Quoted, so an empty string stays one rather than becoming NULL
+        line.push_str(&format!(",\"{}\"", key.replace('"', "\"\"")));
+    }
+    line.push('\n');
+    line
+}
+
+/// The text of a property used as primary key. Strings are taken as is,
+/// other values as JSON.
+fn key_value(properties: &Option<JsonObject>, key: &str) -> Result<String, PgcError> {
+    match properties
+        .as_ref()
+        .and_then(|properties| properties.get(key))
+    {
+        Some(Value::String(value)) => Ok(value.clone()),
+        Some(Value::Null) | None => Err(PgcError::invalid_input(format!(
+            "Property '{}' used as primary key is missing",
+            key
+        ))),
+        Some(value) => Ok(value.to_string()),
+    }
+}
+
+/// Column list of the rows inserted into a geo table, e.g. `name, properties, geometry`.
+fn insert_columns(key_column: Option<(&str, PgType)>) -> Result<String, PgcError> {
+    Ok(match key_column {
+        Some((key, _)) => format!("name, properties, geometry, {}", sql::ident(key)?),
+        None => "name, properties, geometry".to_string(),
+    })
+}
+
+/// How the CSV lines of `feature_csv_line` are read by COPY, spelled out
+/// rather than left to the defaults: fields quoted with `"`, a quote in one
+/// doubled, and only an unquoted empty field read as NULL.
+const COPY_CSV_OPTIONS: &str = "FORMAT csv, QUOTE '\"', ESCAPE '\"', NULL ''";
+
+/// The `COPY` of the rows of a geo table, in CSV.
+fn copy_statement(
+    table_name: &str,
+    key_column: Option<(&str, PgType)>,
+) -> Result<String, PgcError> {
+    Ok(format!(
+        "COPY {} ({}) FROM STDIN ({})",
+        sql::ident(table_name)?,
+        insert_columns(key_column)?,
+        COPY_CSV_OPTIONS
+    ))
+}
+
+/// The statement loading the features of `input_file` into `table_name`, for
+/// a dry run: its `COPY`, or the `INSERT` of `InsertStrategy::Batched`, with
+/// the file in a comment as the rows are not read.
+pub(crate) fn planned_upload(
     table_name: &str,
-    features: Vec<geojson::Feature>,
+    table_options: &GeoTableOptions,
+    options: &IngestOptions,
     input_file: &str,
-) -> Result<(), Box<dyn StdError>> {
-    let stmt = format!(
-        "COPY {} (name, properties, geometry) FROM STDIN (FORMAT csv)",
-        table_name
-    );
-    let mut sink = Box::pin(
+) -> Result<String, PgcError> {
+    let key_column = table_options.primary_key.property_column();
+    let statement = match options.insert_strategy {
+        InsertStrategy::Copy => copy_statement(table_name, key_column)?,
+        InsertStrategy::Batched => format!(
+            "INSERT INTO {} ({}) VALUES ...",
+            sql::ident(table_name)?,
+            insert_columns(key_column)?
+        ),
+    };
+    Ok(format!("{} -- {}", statement, input_file))
+}
+
+/// Postgres refuses statements with more bind parameters than this.
+const MAX_BIND_PARAMETERS: usize = 65535;
+
+/// Bind parameters per feature in a batched `INSERT`: name, properties, geometry,
+/// and the primary key with `PrimaryKey::Property`.
+const MAX_PARAMETERS_PER_ROW: usize = 4;
+
+/// The connection an ingest runs on.
+///
+/// It is taken from a pool so that it can be replaced by a fresh one when it
+/// breaks in the middle of a load, see `IngestOptions::max_retries`.
+pub(crate) struct IngestConnection {
+    pool: Pool,
+    client: Object,
+}
+
+impl IngestConnection {
+    pub(crate) async fn new(pool: Pool) -> Result<Self, PoolError> {
+        let client = pool.get().await?;
+        Ok(Self { pool, client })
+    }
+
+    pub(crate) fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub(crate) fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    /// Replaces the connection with a fresh one from the pool.
+    pub(crate) async fn reconnect(&mut self) -> Result<(), PoolError> {
+        let client = self.pool.get().await?;
+        let broken = std::mem::replace(&mut self.client, client);
+        // Keep the broken connection out of the pool
+        drop(Object::take(broken));
+        Ok(())
+    }
+}
+
+/// Whether an error is worth retrying: the connection broke, the server is
+/// shutting down or cancelled the statement, or the transaction lost a
+/// serialization conflict or deadlock. Constraint violations, syntax errors
+/// and the like fail again however many times they are retried.
+pub(crate) fn is_transient_error(error: &(dyn StdError + 'static)) -> bool {
+    if let Some(error) = error.downcast_ref::<PgcError>() {
+        return match error {
+            PgcError::Other(error) => is_transient_error(error.as_ref()),
+            // A pool timing out has no source to tell
+            PgcError::Connection { source: None, .. } => true,
+            error => error.source().is_some_and(is_transient_error),
+        };
+    }
+    if let Some(pool_error) = error.downcast_ref::<PoolError>() {
+        return match pool_error {
+            PoolError::Backend(e) => is_transient_error(e),
+            PoolError::Timeout(_) => true,
+            _ => false,
+        };
+    }
+    let Some(error) = error.downcast_ref::<tokio_postgres::Error>() else {
+        return false;
+    };
+    if error.is_closed() {
+        return true;
+    }
+    match error.code() {
+        Some(code) => {
+            code.code().starts_with("08")
+                || [
+                    SqlState::T_R_SERIALIZATION_FAILURE,
+                    SqlState::T_R_DEADLOCK_DETECTED,
+                    SqlState::ADMIN_SHUTDOWN,
+                    SqlState::CRASH_SHUTDOWN,
+                    SqlState::CANNOT_CONNECT_NOW,
+                    SqlState::QUERY_CANCELED,
+                ]
+                .contains(code)
+        }
+        // No SQLSTATE: the error happened on the client side, e.g. reading from the socket
+        None => error
+            .source()
+            .is_some_and(|source| source.is::<std::io::Error>()),
+    }
+}
+
+/// Loads rows with multi-row `INSERT` statements, for `InsertStrategy::Batched`.
+///
+/// The statements run in a transaction that is committed every `commit_every`
+/// statements. The rows sent since the last commit are kept, so when a batch
+/// fails with a transient error the connection is replaced and the load
+/// resumes from that last commit. `finish` sends the remaining rows and
+/// commits, `abort` rolls back whatever was not committed yet.
+struct BatchedInsert<'a> {
+    connection: &'a mut IngestConnection,
+    table_name: &'a str,
+    srid: Option<i32>,
+    key_column: Option<(&'a str, PgType)>,
+    batch_size: usize,
+    commit_every: usize,
+    max_retries: u32,
+    retry_backoff: Duration,
+    /// Rows not committed yet, the first `sent` of them in the open transaction
+    rows: Vec<FeatureRow>,
+    sent: usize,
+    in_transaction: bool,
+    uncommitted_batches: usize,
+    committed_batches: usize,
+}
+
+impl<'a> BatchedInsert<'a> {
+    fn new(
+        connection: &'a mut IngestConnection,
+        table_name: &'a str,
+        srid: Option<i32>,
+        key_column: Option<(&'a str, PgType)>,
+        options: &IngestOptions,
+    ) -> Self {
+        Self {
+            connection,
+            table_name,
+            srid,
+            key_column,
+            batch_size: options
+                .batch_size
+                .clamp(1, MAX_BIND_PARAMETERS / MAX_PARAMETERS_PER_ROW),
+            commit_every: options.commit_every.max(1),
+            max_retries: options.max_retries,
+            retry_backoff: Duration::from_millis(options.retry_backoff_ms),
+            rows: Vec::new(),
+            sent: 0,
+            in_transaction: false,
+            uncommitted_batches: 0,
+            committed_batches: 0,
+        }
+    }
+
+    async fn send(&mut self, row: FeatureRow) -> Result<(), PgcError> {
+        self.rows.push(row);
+        if self.rows.len() - self.sent >= self.batch_size {
+            self.uncommitted_batches += 1;
+            let commit = self.uncommitted_batches >= self.commit_every;
+            self.flush(commit).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the pending rows, and commits them with `commit`, retrying
+    /// transient failures from the last commit on a fresh connection.
+    async fn flush(&mut self, commit: bool) -> Result<(), PgcError> {
+        let mut attempt = 0;
+        loop {
+            let error = match self.try_flush(commit).await {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+            if attempt >= self.max_retries || !is_transient_error(&error) {
+                return Err(error);
+            }
+            attempt += 1;
+            let delay = self.retry_backoff * 2u32.saturating_pow(attempt - 1);
+            warn!(
+                "⚠️ Batch {} failed with a transient error: {}. Retrying from the last commit in {:?} (attempt {} of {})",
+                self.committed_batches + self.uncommitted_batches,
+                error,
+                delay,
+                attempt,
+                self.max_retries
+            );
+            tokio::time::sleep(delay).await;
+            // The open transaction is lost with the connection, everything since the last commit is resent
+            self.in_transaction = false;
+            self.sent = 0;
+            if let Err(e) = self.connection.reconnect().await {
+                error!("❌ Failed to reconnect: {}", e);
+            }
+        }
+    }
+
+    async fn try_flush(&mut self, commit: bool) -> Result<(), PgcError> {
+        if !self.in_transaction {
+            self.connection.client().batch_execute("BEGIN").await?;
+            self.in_transaction = true;
+        }
+        while self.sent < self.rows.len() {
+            let end = (self.sent + self.batch_size).min(self.rows.len());
+            self.insert_rows(self.sent, end).await?;
+            self.sent = end;
+        }
+        if commit {
+            self.connection.client().batch_execute("COMMIT").await?;
+            self.in_transaction = false;
+            self.rows.clear();
+            self.sent = 0;
+            self.committed_batches += self.uncommitted_batches;
+            self.uncommitted_batches = 0;
+        }
+        Ok(())
+    }
+
+    /// Inserts `rows[start..end]` with a single statement.
+    async fn insert_rows(&self, start: usize, end: usize) -> Result<u64, PgcError> {
+        let rows = &self.rows[start..end];
+        let per_row = if self.key_column.is_some() { 4 } else { 3 };
+        let values: Vec<String> = (0..rows.len())
+            .map(|i| {
+                let base = i * per_row;
+                let geometry = match self.srid {
+                    Some(srid) => format!("ST_GeomFromText(${}, {})", base + 3, srid),
+                    None => format!("ST_GeomFromText(${})", base + 3),
+                };
+                match self.key_column {
+                    // Bound as text, then cast, so any key type takes a string parameter
+                    Some((_, pg_type)) => format!(
+                        "(${}, ${}, {}, ${}::text::{})",
+                        base + 1,
+                        base + 2,
+                        geometry,
+                        base + 4,
+                        pg_type.sql_name()
+                    ),
+                    None => format!("(${}, ${}, {})", base + 1, base + 2, geometry),
+                }
+            })
+            .collect();
+        let stmt = format!(
+            "INSERT INTO {} ({}) VALUES {}",
+            sql::ident(self.table_name)?,
+            insert_columns(self.key_column)?,
+            values.join(", ")
+        );
+        let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(rows.len() * per_row);
+        for row in rows {
+            params.push(&row.name);
+            params.push(&row.properties);
+            params.push(&row.geometry);
+            if self.key_column.is_some() {
+                params.push(&row.key);
+            }
+        }
+        Ok(self
+            .connection
+            .client()
+            .execute(stmt.as_str(), &params)
+            .await?)
+    }
+
+    async fn finish(&mut self) -> Result<(), PgcError> {
+        if self.rows.len() > self.sent {
+            self.uncommitted_batches += 1;
+        }
+        self.flush(true).await
+    }
+
+    async fn abort(&mut self) {
+        self.rows.clear();
+        self.sent = 0;
+        if self.in_transaction {
+            let _ = self.connection.client().batch_execute("ROLLBACK").await;
+            self.in_transaction = false;
+            self.uncommitted_batches = 0;
+        }
+    }
+}
+
+/// One worker of a `StagedCopy`: its own connection, COPYing into its own staging table.
+struct CopyWorker {
+    _client: Object,
+    staging_table: String,
+    sink: Pin<Box<CopyInSink<BytesMut>>>,
+    sent: u64,
+}
+
+/// COPY through `UNLOGGED` staging tables, for `IngestOptions::parallel_copy`
+/// and `clip_exact`.
+///
+/// Rows are dealt round-robin to the workers, so the server parses their
+/// geometries on as many cores. Each worker COPYs into its own staging table;
+/// `finish` then moves all of them into the target table with a single
+/// `INSERT ... SELECT` on the ingest connection, so the file is still loaded
+/// entirely or not at all, and checks that every row sent arrived. With
+/// `clip`, geometries are cut to the box on the way.
+struct StagedCopy<'a> {
+    connection: &'a IngestConnection,
+    table_name: &'a str,
+    srid: Option<i32>,
+    key_column: Option<(&'a str, PgType)>,
+    clip: Option<BoundingBox>,
+    workers: Vec<CopyWorker>,
+    next: usize,
+}
+
+impl<'a> StagedCopy<'a> {
+    async fn new(
+        connection: &'a IngestConnection,
+        table_name: &'a str,
+        srid: Option<i32>,
+        key_column: Option<(&'a str, PgType)>,
+        clip: Option<BoundingBox>,
+        worker_count: usize,
+    ) -> Result<StagedCopy<'a>, PgcError> {
+        let mut parallel = Self {
+            connection,
+            table_name,
+            srid,
+            key_column,
+            clip,
+            workers: Vec::with_capacity(worker_count),
+            next: 0,
+        };
+        // Named for this load alone, so that loads running at the same time
+        // never share, nor drop, each other's staging tables
+        let prefix = sql::unique_name("_pgc_parallel");
+        for i in 0..worker_count {
+            let staging_table = format!("{}_{}", prefix, i);
+            if let Err(e) = parallel.add_worker(staging_table).await {
+                parallel.abort().await;
+                return Err(e);
+            }
+        }
+        Ok(parallel)
+    }
+
+    async fn add_worker(&mut self, staging_table: String) -> Result<(), PgcError> {
+        let client = self.connection.pool().get().await?;
         client
-            .copy_in(&stmt)
-            .await
-            .expect("❌ Failed to start COPY operation"),
-    );
-    eprintln!("🔄 Processing features in {}", input_file);
-    for (idx, feature) in features.into_iter().enumerate() {
-        let name = match feature.id {
-            Some(geojson::feature::Id::String(ref s)) => s.clone(),
-            Some(geojson::feature::Id::Number(ref n)) => n.to_string(),
-            None => format!("unknown_{}", idx),
+            .batch_execute(&format!(
+                "CREATE UNLOGGED TABLE {table} (name TEXT, properties JSONB, geometry GEOMETRY, pk TEXT)",
+                table = sql::ident(&staging_table)?
+            ))
+            .await?;
+        let columns = if self.key_column.is_some() {
+            "name, properties, geometry, pk"
+        } else {
+            "name, properties, geometry"
         };
-        let properties =
-            serde_json::to_string(&feature.properties).expect("❌ Failed to serialize properties");
-        let geometry = match feature.geometry {
-            Some(ref geom) => geometry_to_wkt(geom).expect("❌ Failed to convert geometry to WKT"),
-            None => "NULL".to_string(),
+        let stmt = format!(
+            "COPY {} ({}) FROM STDIN ({})",
+            sql::ident(&staging_table)?,
+            columns,
+            COPY_CSV_OPTIONS
+        );
+        let copy = client.copy_in(&stmt).await;
+        let sink = match copy {
+            Ok(sink) => Box::pin(sink),
+            Err(e) => {
+                self.drop_staging_tables(&[staging_table]).await;
+                return Err(e.into());
+            }
         };
-        let csv_line = format!(
-            "{},{},{}\n",
-            escape_csv_field(&name),
-            escape_csv_field(&properties),
-            escape_csv_field(&geometry)
+        self.workers.push(CopyWorker {
+            _client: client,
+            staging_table,
+            sink,
+            sent: 0,
+        });
+        Ok(())
+    }
+
+    async fn send(&mut self, row: FeatureRow) -> Result<(), PgcError> {
+        let index = self.next;
+        self.next = (index + 1) % self.workers.len();
+        let worker = &mut self.workers[index];
+        let bytes = BytesMut::from(row.to_csv_line(self.srid).as_str());
+        worker.sink.send(bytes).await?;
+        worker.sent += 1;
+        Ok(())
+    }
+
+    /// Completes the COPYs, then merges the staging tables into the target table.
+    ///
+    /// Returns the number of rows dropped by the clip, having nothing left inside the box.
+    async fn finish(&mut self) -> Result<u64, PgcError> {
+        let sent: u64 = self.workers.iter().map(|worker| worker.sent).sum();
+        let closes = self
+            .workers
+            .iter_mut()
+            .map(|worker| async move { (worker.sent, worker.sink.as_mut().finish().await) });
+        for (worker_sent, result) in futures::future::join_all(closes).await {
+            let copied = result?;
+            if copied != worker_sent {
+                return Err(format!(
+                    "❌ A COPY worker received {} rows but wrote {}",
+                    worker_sent, copied
+                )
+                .into());
+            }
+        }
+
+        let selects: Vec<String> = self
+            .workers
+            .iter()
+            .map(|worker| {
+                Ok(format!(
+                    "SELECT name, properties, geometry, pk FROM {}",
+                    sql::ident(&worker.staging_table)?
+                ))
+            })
+            .collect::<Result<_, PgcError>>()?;
+        let geometry = match &self.clip {
+            Some(clip) => format!(
+                "ST_Intersection(geometry, ST_MakeEnvelope({}, {}, {}, {}, {}))",
+                clip.min_x,
+                clip.min_y,
+                clip.max_x,
+                clip.max_y,
+                self.srid.unwrap_or(0)
+            ),
+            None => "geometry".to_string(),
+        };
+        info!(
+            "🔀 Merging {} staging tables into {}",
+            self.workers.len(),
+            self.table_name
         );
-        let bytes = BytesMut::from(csv_line.as_str());
-        sink.send(bytes).await.expect("❌ Failed to send bytes");
+        let client = self.connection.client();
+        client.batch_execute("BEGIN").await?;
+        let merged = client
+            .query_one(
+                &format!(
+                    "WITH staged AS (
+                        SELECT name, properties, {geometry} AS geometry, pk FROM ({selects}) AS s
+                    ), inserted AS (
+                        INSERT INTO {table} ({columns})
+                        SELECT name, properties, geometry{key} FROM staged
+                        WHERE geometry IS NULL OR NOT ST_IsEmpty(geometry)
+                        RETURNING 1
+                    )
+                    SELECT (SELECT COUNT(*) FROM staged), (SELECT COUNT(*) FROM inserted)",
+                    geometry = geometry,
+                    selects = selects.join(" UNION ALL "),
+                    columns = insert_columns(self.key_column)?,
+                    key = match self.key_column {
+                        Some((_, pg_type)) => format!(", CAST(pk AS {})", pg_type.sql_name()),
+                        None => String::new(),
+                    },
+                    table = sql::ident(self.table_name)?
+                ),
+                &[],
+            )
+            .await
+            .map(|row| (row.get::<_, i64>(0) as u64, row.get::<_, i64>(1) as u64));
+        let result: Result<u64, PgcError> = match merged {
+            Ok((staged, inserted)) if staged == sent => {
+                client.batch_execute("COMMIT").await?;
+                Ok(staged - inserted)
+            }
+            Ok((staged, _)) => Err(format!(
+                "❌ {} rows were sent but {} reached the staging tables, the load is rolled back",
+                sent, staged
+            )
+            .into()),
+            Err(e) => Err(e.into()),
+        };
+        match &result {
+            Ok(clipped) => info!("✅ Verified {} rows in {}", sent - clipped, self.table_name),
+            Err(_) => {
+                let _ = client.batch_execute("ROLLBACK").await;
+            }
+        }
+        self.abort().await;
+        result
+    }
+
+    /// Aborts the COPYs still running and drops the staging tables.
+    async fn abort(&mut self) {
+        let workers = std::mem::take(&mut self.workers);
+        let staging_tables: Vec<String> = workers
+            .into_iter()
+            .map(|worker| worker.staging_table)
+            .collect();
+        self.drop_staging_tables(&staging_tables).await;
+    }
+
+    async fn drop_staging_tables(&self, staging_tables: &[String]) {
+        for staging_table in staging_tables {
+            let dropped = match sql::ident(staging_table) {
+                Ok(name) => self
+                    .connection
+                    .client()
+                    .batch_execute(&format!("DROP TABLE IF EXISTS {}", name))
+                    .await
+                    .map_err(PgcError::from),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = dropped {
+                warn!("⚠️ Failed to drop staging table {}: {}", staging_table, e);
+            }
+        }
+    }
+}
+
+/// Where converted rows are sent, depending on the `InsertStrategy`.
+enum RowSink<'a> {
+    Copy(Pin<Box<CopyInSink<BytesMut>>>, Option<i32>),
+    Staged(StagedCopy<'a>),
+    Batched(BatchedInsert<'a>),
+}
+
+impl RowSink<'_> {
+    async fn send(&mut self, row: FeatureRow) -> Result<(), PgcError> {
+        match self {
+            RowSink::Copy(sink, srid) => {
+                let bytes = BytesMut::from(row.to_csv_line(*srid).as_str());
+                Ok(sink.send(bytes).await?)
+            }
+            RowSink::Staged(staged) => staged.send(row).await,
+            RowSink::Batched(batched) => batched.send(row).await,
+        }
+    }
+
+    /// Completes the upload, or aborts it if that fails.
+    ///
+    /// Returns the number of rows dropped by `clip_exact`.
+    async fn finish(self) -> Result<u64, PgcError> {
+        match self {
+            RowSink::Copy(mut sink, _) => {
+                sink.close().await?;
+                Ok(0)
+            }
+            RowSink::Staged(mut staged) => staged.finish().await,
+            RowSink::Batched(mut batched) => {
+                let result = batched.finish().await;
+                if result.is_err() {
+                    batched.abort().await;
+                }
+                result.map(|()| 0)
+            }
+        }
     }
-    eprintln!("⏳ Closing copy operation...");
-    sink.close().await.expect("❌ Failed to close sink");
-    eprintln!("✅ Copy operation completed successfully!!");
+
+    /// Discards the rows sent so far (for `Batched`, those not committed yet).
+    async fn abort(self) {
+        match self {
+            // Dropping the sink without closing it aborts the COPY
+            RowSink::Copy(sink, _) => drop(sink),
+            RowSink::Staged(mut staged) => staged.abort().await,
+            RowSink::Batched(mut batched) => batched.abort().await,
+        }
+    }
+}
+
+/// Path of the sidecar file skipped features are recorded in, e.g.
+/// `data/regions.geojson` → `data/regions.errors.jsonl`.
+pub(crate) fn errors_sidecar_path(input_file: &str) -> PathBuf {
+    Path::new(input_file).with_extension("errors.jsonl")
+}
+
+/// Writes skipped features to `path`, one JSON object per line.
+fn write_feature_errors(path: &Path, feature_errors: &[FeatureError]) -> Result<(), PgcError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for feature_error in feature_errors {
+        serde_json::to_writer(&mut writer, feature_error)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
     Ok(())
 }
 
-/// Orchestrates parsing and uploading a GeoJSON file.
-pub async fn process_and_upload_file(
-    client: &Client,
+/// Uploads features to the database, using COPY or batched INSERTs depending
+/// on `options.insert_strategy`.
+///
+/// The features are taken one at a time from `features`. Without a `dedupe`
+/// strategy they are named as they come, with one their names are read from
+/// them first, a pass of its own over a file.
+///
+/// Features that cannot be converted abort the upload, or are skipped and
+/// recorded, depending on `options.on_feature_error`: in `errors_file`, or in
+/// the warnings of the report without one.
+/// Whenever the upload fails, the COPY is aborted so no rows from the file
+/// are left behind. Batched INSERTs only roll back the batches not committed yet.
+/// So does `cancellation` once cancelled, checked every `CHECK_EVERY` features.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn upload_features_copy(
+    connection: &mut IngestConnection,
+    table_name: &str,
+    mut features: GeoJsonFeatures,
+    input_file: &str,
+    errors_file: Option<&Path>,
+    table_options: &GeoTableOptions,
+    options: &IngestOptions,
+    dedupe_state: &mut DedupeState,
+    cancellation: &CancellationToken,
+) -> Result<FileReport, PgcError> {
+    let srid = table_options.effective_srid();
+    let duplicates_before = dedupe_state.duplicates;
+    let mut names = match dedupe_state.strategy {
+        Dedupe::None => None,
+        _ => {
+            let names = features.names(options.name_property.as_deref()).await?;
+            Some(
+                dedupe_state
+                    .prepare_file(connection.client(), table_name, names)
+                    .await?
+                    .into_iter(),
+            )
+        }
+    };
+
+    let exact_clip = options.clip_bbox.filter(|_| options.clip_exact);
+    let key_column = table_options.primary_key.property_column();
+    let mut sink = match options.insert_strategy {
+        InsertStrategy::Copy if options.parallel_copy > 1 || exact_clip.is_some() => {
+            let workers = options.parallel_copy.max(1);
+            let staged = StagedCopy::new(
+                connection, table_name, srid, key_column, exact_clip, workers,
+            )
+            .await?;
+            let staging = if workers > 1 {
+                format!("over {} connections", workers)
+            } else {
+                "through a staging table".to_string()
+            };
+            info!(
+                "🔄 Processing features in {} using COPY {}{}",
+                input_file,
+                staging,
+                if exact_clip.is_some() {
+                    ", clipped to the box"
+                } else {
+                    ""
+                }
+            );
+            RowSink::Staged(staged)
+        }
+        InsertStrategy::Copy => {
+            let stmt = copy_statement(table_name, key_column)?;
+            let sink = Box::pin(
+                connection
+                    .client()
+                    .copy_in(&stmt)
+                    .await
+                    .map_err(|e| PgcError::query(&stmt, e))?,
+            );
+            info!("🔄 Processing features in {} using COPY", input_file);
+            RowSink::Copy(sink, srid)
+        }
+        InsertStrategy::Batched => {
+            let batched = BatchedInsert::new(connection, table_name, srid, key_column, options);
+            info!(
+                "🔄 Processing features in {} using batched INSERTs ({} features per statement, commit every {} statements)",
+                input_file, batched.batch_size, batched.commit_every
+            );
+            RowSink::Batched(batched)
+        }
+    };
+    let observer = options.observer.as_ref();
+    let mut total: usize = 0;
+    let mut loaded: u64 = 0;
+    let mut skipped: u64 = 0;
+    let mut feature_errors: Vec<FeatureError> = Vec::new();
+    let mut loaded_names: Vec<String> = Vec::new();
+    let mut key_collisions = KeyCollisions::new();
+    let mut rows_written: u64 = 0;
+    let mut clipped: u64 = 0;
+    while let Some(next) = features.next_feature().await {
+        let (idx, mut feature) = match next {
+            Ok(next) => next,
+            Err(e) => {
+                sink.abort().await;
+                return Err(e.in_geojson(input_file, None));
+            }
+        };
+        total = idx + 1;
+        if idx.is_multiple_of(cancel::CHECK_EVERY) && cancellation.is_cancelled() {
+            sink.abort().await;
+            return Err(PgcError::Cancelled {
+                message: format!(
+                    "🛑 Loading {} was interrupted, its uncommitted rows rolled back",
+                    input_file
+                ),
+            });
+        }
+        let name = match &mut names {
+            Some(names) => names.next().flatten(),
+            None => Some(feature_name(
+                &feature,
+                idx,
+                options.name_property.as_deref(),
+            )),
+        };
+        // Duplicates dropped by the dedupe strategy
+        let Some(name) = name else {
+            continue;
+        };
+        if let Some(clip_bbox) = &options.clip_bbox {
+            let inside = feature
+                .geometry
+                .as_ref()
+                .and_then(|geometry| geometry_bbox(&geometry.value))
+                .is_some_and(|bbox| bbox.intersects(clip_bbox));
+            if !inside {
+                clipped += 1;
+                continue;
+            }
+        }
+        if options.flatten_properties || options.normalize_keys {
+            feature.properties = feature.properties.take().map(|properties| {
+                transform_property_keys(
+                    properties,
+                    options.flatten_properties,
+                    options.normalize_keys,
+                    &mut key_collisions,
+                )
+            });
+        }
+        let rows = match FeatureRow::from_feature(
+            name.clone(),
+            &feature,
+            options,
+            key_column.map(|(key, _)| key),
+        ) {
+            Ok(rows) => rows,
+            Err(e) => match options.on_feature_error {
+                OnFeatureError::Abort => {
+                    sink.abort().await;
+                    return Err(PgcError::GeoJson {
+                        path: Some(input_file.to_string()),
+                        feature_index: Some(idx),
+                        message: format!("Failed to process {}: {}", name, e),
+                    });
+                }
+                OnFeatureError::Skip => {
+                    skipped += 1;
+                    if feature_errors.len() < MAX_RECORDED_FEATURE_ERRORS {
+                        feature_errors.push(FeatureError {
+                            index: idx,
+                            name,
+                            error: e.to_string(),
+                        });
+                    }
+                    continue;
+                }
+            },
+        };
+        for row in rows {
+            if let Err(e) = sink.send(row).await {
+                sink.abort().await;
+                // Returned as it is, its variant tells a refused row from a lost connection
+                error!("❌ Failed to upload features from {}", input_file);
+                return Err(e);
+            }
+            rows_written += 1;
+        }
+        loaded += 1;
+        if names.is_some() {
+            loaded_names.push(name);
+        }
+        if let Some(observer) = observer {
+            features.report_progress(observer, idx as u64 + 1, false);
+        }
+    }
+    if let Some(observer) = observer {
+        features.report_progress(observer, total as u64, true);
+    }
+
+    let mut warnings = Vec::new();
+    for (key, sources) in &key_collisions {
+        warnings.push(format!(
+            "Property keys {:?} all became '{}', only the first one was kept",
+            sources, key
+        ));
+    }
+    if !feature_errors.is_empty() {
+        match errors_file {
+            Some(path) => {
+                write_feature_errors(path, &feature_errors)?;
+                warnings.push(format!(
+                    "Skipped {} features, errors written to {}",
+                    skipped,
+                    path.display()
+                ));
+            }
+            None => warnings.push(format!("Skipped {} features", skipped)),
+        }
+    }
+    for warning in &warnings {
+        warn!("⚠️ {}", warning);
+    }
+    // Only the count is logged, the errors themselves are for the report
+    if errors_file.is_none() {
+        warnings.extend(feature_errors.iter().map(|feature_error| {
+            format!(
+                "Skipped feature {} ({}): {}",
+                feature_error.index, feature_error.name, feature_error.error
+            )
+        }));
+    }
+    let skip_ratio = if total == 0 {
+        0.0
+    } else {
+        skipped as f64 / total as f64
+    };
+    if skip_ratio > options.max_skip_ratio {
+        sink.abort().await;
+        return Err(PgcError::invalid_input(format!(
+            "❌ Skipped {} of {} features in {} ({:.2}%), more than the allowed {:.2}%",
+            skipped,
+            total,
+            input_file,
+            skip_ratio * 100.0,
+            options.max_skip_ratio * 100.0
+        )));
+    }
+
+    info!("⏳ Closing upload...");
+    match sink.finish().await {
+        // Rows cut down to nothing by `clip_exact`
+        Ok(clipped_rows) => {
+            rows_written -= clipped_rows;
+            clipped += clipped_rows;
+            if !options.explode_multi {
+                loaded -= clipped_rows;
+            }
+        }
+        Err(e) => {
+            error!("❌ Failed to upload features from {}", input_file);
+            return Err(e);
+        }
+    }
+    info!("✅ Upload completed successfully!!");
+    if let Some(observer) = observer {
+        observer.copy_finished(input_file, Some(rows_written));
+    }
+    dedupe_state
+        .commit_file(connection.client(), loaded_names)
+        .await?;
+    let report = FileReport {
+        path: input_file.to_string(),
+        features_read: total as u64,
+        features_loaded: loaded,
+        rows_written,
+        skipped,
+        duplicates: dedupe_state.duplicates - duplicates_before,
+        clipped,
+        warnings,
+        ..Default::default()
+    };
+    report.print_summary();
+    Ok(report)
+}
+
+/// Orchestrates parsing and uploading a GeoJSON file, until `cancellation`
+/// is cancelled.
+pub(crate) async fn process_and_upload_file(
+    connection: &mut IngestConnection,
     input_file: &str,
     table_name: &str,
-) -> Result<(), Box<dyn StdError>> {
-    eprintln!(
+    table_options: &GeoTableOptions,
+    options: &IngestOptions,
+    dedupe_state: &mut DedupeState,
+    cancellation: &CancellationToken,
+) -> Result<FileReport, PgcError> {
+    info!(
         "🔄 Attempting to process file: {}, table: {}",
         input_file, table_name
     );
-    let features = parse_geojson_features(input_file)?;
-    upload_features_copy(client, table_name, features, input_file).await
+    let started = Instant::now();
+    let source = GeoJsonSource::open(input_file).await?;
+    if let Some(observer) = &options.observer {
+        observer.file_started(input_file, Some(source.bytes()));
+    }
+    let mut report = upload_features_copy(
+        connection,
+        table_name,
+        source.features(),
+        input_file,
+        Some(&errors_sidecar_path(input_file)),
+        table_options,
+        options,
+        dedupe_state,
+        cancellation,
+    )
+    .await?;
+    let elapsed = started.elapsed();
+    report.bytes = source.bytes();
+    report.duration_ms = elapsed.as_millis() as u64;
+    report.throughput = report.rows_written as f64 / elapsed.as_secs_f64().max(1e-9);
+    Ok(report)
+}
+
+/// A GeoJSON stream read by `process_and_upload_stream`: its size and
+/// SHA-256 as read, before any decompression.
+pub(crate) struct StreamDigest {
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+/// `process_and_upload_file` for a stream, named `source` in messages and
+/// errors, e.g. `stdin`. The stream is read once, front to back, on a
+/// blocking thread a few features ahead of the upload: gzip is detected by
+/// its first bytes, and there is no size to report progress against until
+/// it ends. With a `dedupe` strategy, which names every feature before the
+/// first is loaded, the whole stream is parsed first. Skipped features are
+/// recorded in the warnings of the report, there is no file to write them
+/// next to.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn process_and_upload_stream<R>(
+    connection: &mut IngestConnection,
+    reader: R,
+    source: &str,
+    table_name: &str,
+    table_options: &GeoTableOptions,
+    options: &IngestOptions,
+    dedupe_state: &mut DedupeState,
+    cancellation: &CancellationToken,
+) -> Result<(FileReport, StreamDigest), PgcError>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    info!("🔄 Attempting to process {}, table: {}", source, table_name);
+    let started = Instant::now();
+    let bridge = SyncIoBridge::new(reader);
+    let read_source = source.to_string();
+    let (mut features, reading) = GeoJsonFeatures::streamed(move |bytes_read, on_feature| {
+        let mut hashing = HashingReader::new(CountingReader::new(bridge, bytes_read));
+        let reader = gzip_aware(&mut hashing).map_err(|e| PgcError::io(&read_source, e))?;
+        for_each_geojson_feature_in(reader, on_feature)
+            .map_err(|e| e.in_geojson(&read_source, None))?;
+        let (bytes, sha256) = hashing.finish();
+        Ok(StreamDigest { bytes, sha256 })
+    });
+    if dedupe_state.strategy != Dedupe::None {
+        let mut parsed = Vec::new();
+        while let Some(next) = features.next_feature().await {
+            let (idx, feature) = next?;
+            if idx.is_multiple_of(cancel::CHECK_EVERY) && cancellation.is_cancelled() {
+                return Err(PgcError::Cancelled {
+                    message: "🛑 Reading the GeoJSON was interrupted".to_string(),
+                });
+            }
+            parsed.push(feature);
+        }
+        features = GeoJsonFeatures::from_features(parsed);
+    }
+    if let Some(observer) = &options.observer {
+        observer.file_started(source, None);
+    }
+    let mut report = upload_features_copy(
+        connection,
+        table_name,
+        features,
+        source,
+        None,
+        table_options,
+        options,
+        dedupe_state,
+        cancellation,
+    )
+    .await?;
+    // Every feature was taken, the stream was read to its end
+    let digest = reading.await?.ok_or_else(|| {
+        PgcError::from(format!("❌ {} was loaded but not read to its end", source))
+    })?;
+    let elapsed = started.elapsed();
+    report.bytes = digest.bytes;
+    report.duration_ms = elapsed.as_millis() as u64;
+    report.throughput = report.rows_written as f64 / elapsed.as_secs_f64().max(1e-9);
+    Ok((report, digest))
+}
+
+/// The bounding box of a geometry's coordinates, `None` if it has none.
+pub(crate) fn geometry_bbox(value: &geojson::Value) -> Option<BoundingBox> {
+    fn extend(bbox: &mut Option<BoundingBox>, position: &[f64]) {
+        let (Some(&x), Some(&y)) = (position.first(), position.get(1)) else {
+            return;
+        };
+        match bbox {
+            Some(bbox) => bbox.extend(x, y),
+            None => {
+                *bbox = Some(BoundingBox {
+                    min_x: x,
+                    min_y: y,
+                    max_x: x,
+                    max_y: y,
+                })
+            }
+        }
+    }
+    let mut bbox = None;
+    match value {
+        geojson::Value::Point(p) => extend(&mut bbox, p),
+        geojson::Value::MultiPoint(ps) | geojson::Value::LineString(ps) => {
+            ps.iter().for_each(|p| extend(&mut bbox, p))
+        }
+        geojson::Value::MultiLineString(ls) | geojson::Value::Polygon(ls) => {
+            ls.iter().flatten().for_each(|p| extend(&mut bbox, p))
+        }
+        geojson::Value::MultiPolygon(polygons) => polygons
+            .iter()
+            .flatten()
+            .flatten()
+            .for_each(|p| extend(&mut bbox, p)),
+        geojson::Value::GeometryCollection(geometries) => {
+            for geometry in geometries {
+                if let Some(member) = geometry_bbox(&geometry.value) {
+                    extend(&mut bbox, &[member.min_x, member.min_y]);
+                    extend(&mut bbox, &[member.max_x, member.max_y]);
+                }
+            }
+        }
+    }
+    bbox
 }
 
-/// Helper function to escape CSV fields
+/// A field of a CSV line sent to COPY, see `COPY_CSV_OPTIONS`, quoted when
+/// it would not be read back as the same text unquoted: when it holds a
+/// delimiter, a quote, a line break, even a bare `\r`, starts or ends with
+/// whitespace, is the end of data marker `\.`, or is empty, which is NULL.
 fn escape_csv_field(field: &str) -> String {
-    if field.contains(',') || field.contains('"') || field.contains('\n') {
-        // Escape quotes by doubling them and wrap in quotes
-        let escaped = field.replace('"', "\"\"");
-        format!("\"{}\"", escaped)
+    let needs_quotes = field.is_empty()
+        || field == "\\."
+        || field.contains([',', '"', '\n', '\r'])
+        || field.starts_with(char::is_whitespace)
+        || field.ends_with(char::is_whitespace);
+    if needs_quotes {
+        quote_csv_field(field)
     } else {
         field.to_string()
     }
 }
+
+/// `field` in quotes, its quotes doubled.
+fn quote_csv_field(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
 /// Returns the contained value of an `Option` if it exists, otherwise returns a default value.
 ///
 /// This function takes an `Option` and a default value. If the `Option` contains a value,
@@ -297,11 +2020,11 @@ fn escape_csv_field(field: &str) -> String {
 /// # Returns
 ///
 /// The contained value if `Some`, otherwise `default`.
-pub fn custom_unwrap_or<T: Display>(value: Option<T>, default: T, value_name: &str) -> T {
+pub(crate) fn custom_unwrap_or<T: Display>(value: Option<T>, default: T, value_name: &str) -> T {
     match value {
         Some(v) => v,
         None => {
-            eprintln!(
+            warn!(
                 "⚠️ No {} was given, using default value: {}",
                 value_name, default
             );
@@ -309,3 +2032,63 @@ pub fn custom_unwrap_or<T: Display>(value: Option<T>, default: T, value_name: &s
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_bbox_filter_is_built_in_the_srid_of_the_table() {
+        let filter = FeatureFilter::BBox {
+            min_x: 0.0,
+            min_y: 1.0,
+            max_x: 2.0,
+            max_y: 3.0,
+        };
+        let (condition, params) = filter.to_sql(Some(3857));
+        assert_eq!(
+            condition,
+            "geometry && ST_MakeEnvelope($1, $2, $3, $4, 3857)"
+        );
+        assert_eq!(params.len(), 4);
+        let (condition, _) = filter.to_sql(None);
+        assert_eq!(
+            condition,
+            "geometry && ST_MakeEnvelope($1, $2, $3, $4, ST_SRID(geometry))"
+        );
+    }
+
+    #[test]
+    fn property_and_name_filters_take_one_parameter() {
+        let filter = FeatureFilter::Names(vec!["alpha".to_string()]);
+        let (condition, params) = filter.to_sql(Some(4326));
+        assert_eq!(condition, "name = ANY($1)");
+        assert_eq!(params.len(), 1);
+        let filter = FeatureFilter::Properties(serde_json::json!({"value": 1}));
+        assert_eq!(filter.to_sql(None).0, "properties @> $1");
+        assert!(!filter.is_empty());
+        assert!(FeatureFilter::Properties(serde_json::json!({})).is_empty());
+        assert!(FeatureFilter::Names(Vec::new()).is_empty());
+    }
+
+    #[cfg(feature = "ingest")]
+    #[test]
+    fn streams_are_gunzipped_when_they_start_like_gzip() {
+        let collection = std::fs::read("tests/fixtures/points.geojson").expect("read");
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&collection).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        for input in [&collection, &gzipped] {
+            let mut hashing = HashingReader::new(input.as_slice());
+            let mut names = Vec::new();
+            for_each_geojson_feature_in(gzip_aware(&mut hashing).unwrap(), |_, feature| {
+                names.push(feature.property("name").unwrap().to_string());
+                Ok(())
+            })
+            .expect("the features of the stream");
+            assert_eq!(names, ["\"alpha\"", "\"beta\"", "\"gamma\""]);
+            // The stream as read, not decompressed
+            assert_eq!(hashing.finish().0, input.len() as u64);
+        }
+    }
+}
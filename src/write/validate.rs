@@ -0,0 +1,168 @@
+use crate::utils::error::PgcError;
+use crate::write::reports::ValidationReport;
+use crate::write::utils::{feature_name, for_each_geojson_feature};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// Offending feature indexes or names kept per check in the report.
+const MAX_SAMPLES: usize = 10;
+
+/// Memory held while loading, per byte of GeoJSON. The features of a file are
+/// parsed into memory before they are uploaded, and parsed coordinates take
+/// more room than their text.
+const MEMORY_BYTES_PER_FILE_BYTE: u64 = 3;
+
+/// GeoJSON bytes loaded per second, a conservative figure for a local COPY.
+const LOAD_FILE_BYTES_PER_SECOND: f64 = 20.0 * 1024.0 * 1024.0;
+
+/// Validate a GeoJSON file without touching the database.
+///
+/// The file is streamed with the same parser `insert_geojson` uses, so a
+/// file that validates also parses when loaded. A file that cannot be parsed
+/// at all is an error; everything else is counted in the report.
+pub fn validate_geojson(path: &str) -> Result<ValidationReport, PgcError> {
+    let started = Instant::now();
+    let file_size_bytes = std::fs::metadata(path)
+        .map_err(|e| PgcError::io(path, e))?
+        .len();
+    let mut report = ValidationReport {
+        path: path.to_string(),
+        file_size_bytes,
+        ..Default::default()
+    };
+    let mut names: HashSet<String> = HashSet::new();
+    let mut key_types: BTreeMap<String, BTreeMap<String, u64>> = BTreeMap::new();
+
+    info!("🔍 Validating {}", path);
+    for_each_geojson_feature(path, |idx, feature| {
+        report.total_features += 1;
+
+        match feature.geometry {
+            Some(ref geometry) => {
+                *report
+                    .geometry_types
+                    .entry(geometry_type_name(&geometry.value).to_string())
+                    .or_insert(0) += 1;
+                if !coordinates_valid(&geometry.value) {
+                    report.invalid_coordinates += 1;
+                    if report.invalid_coordinate_samples.len() < MAX_SAMPLES {
+                        report.invalid_coordinate_samples.push(idx);
+                    }
+                }
+            }
+            None => report.null_geometries += 1,
+        }
+
+        let name = feature_name(&feature, idx, None);
+        if !names.insert(name.clone()) {
+            report.duplicate_names += 1;
+            if report.duplicate_name_samples.len() < MAX_SAMPLES {
+                report.duplicate_name_samples.push(name);
+            }
+        }
+
+        if let Some(ref properties) = feature.properties {
+            for (key, value) in properties {
+                *key_types
+                    .entry(key.clone())
+                    .or_default()
+                    .entry(json_type_name(value).to_string())
+                    .or_insert(0) += 1;
+            }
+        }
+        Ok(())
+    })?;
+
+    report.mixed_type_keys = key_types
+        .into_iter()
+        .filter(|(_, types)| types.keys().filter(|t| *t != "null").count() > 1)
+        .collect();
+    report.estimated_memory_bytes = file_size_bytes * MEMORY_BYTES_PER_FILE_BYTE;
+    report.estimated_load_seconds = file_size_bytes as f64 / LOAD_FILE_BYTES_PER_SECOND;
+
+    print_report(&report);
+    info!("✅ Validated in {:.2?}", started.elapsed());
+    Ok(report)
+}
+
+fn print_report(report: &ValidationReport) {
+    info!("📊 Features: {}", report.total_features);
+    for (geometry_type, count) in &report.geometry_types {
+        info!("   {}: {}", geometry_type, count);
+    }
+    if report.null_geometries > 0 {
+        warn!("⚠️ Null geometries: {}", report.null_geometries);
+    }
+    if report.invalid_coordinates > 0 {
+        warn!(
+            "⚠️ Features with invalid coordinates: {} (e.g. features {:?})",
+            report.invalid_coordinates, report.invalid_coordinate_samples
+        );
+    }
+    if report.duplicate_names > 0 {
+        warn!(
+            "⚠️ Duplicate names: {} (e.g. {:?})",
+            report.duplicate_names, report.duplicate_name_samples
+        );
+    }
+    for (key, types) in &report.mixed_type_keys {
+        warn!("⚠️ Property '{}' has mixed types: {:?}", key, types);
+    }
+    info!(
+        "⏱️ Estimated load: ~{:.0} MB of memory, ~{:.1}s",
+        report.estimated_memory_bytes as f64 / (1024.0 * 1024.0),
+        report.estimated_load_seconds
+    );
+}
+
+fn geometry_type_name(value: &geojson::Value) -> &'static str {
+    match value {
+        geojson::Value::Point(_) => "Point",
+        geojson::Value::MultiPoint(_) => "MultiPoint",
+        geojson::Value::LineString(_) => "LineString",
+        geojson::Value::MultiLineString(_) => "MultiLineString",
+        geojson::Value::Polygon(_) => "Polygon",
+        geojson::Value::MultiPolygon(_) => "MultiPolygon",
+        geojson::Value::GeometryCollection(_) => "GeometryCollection",
+    }
+}
+
+fn json_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Whether every position has a finite longitude within ±180 and latitude within ±90.
+fn coordinates_valid(value: &geojson::Value) -> bool {
+    fn position_valid(position: &[f64]) -> bool {
+        position.len() >= 2
+            && position.iter().all(|c| c.is_finite())
+            && position[0].abs() <= 180.0
+            && position[1].abs() <= 90.0
+    }
+    match value {
+        geojson::Value::Point(p) => position_valid(p),
+        geojson::Value::MultiPoint(ps) | geojson::Value::LineString(ps) => {
+            ps.iter().all(|p| position_valid(p))
+        }
+        geojson::Value::MultiLineString(ls) | geojson::Value::Polygon(ls) => {
+            ls.iter().flatten().all(|p| position_valid(p))
+        }
+        geojson::Value::MultiPolygon(polygons) => polygons
+            .iter()
+            .flatten()
+            .flatten()
+            .all(|p| position_valid(p)),
+        geojson::Value::GeometryCollection(geometries) => {
+            geometries.iter().all(|g| coordinates_valid(&g.value))
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// Events of the watcher returned by `watch`.
+pub(crate) type WatchEvents = UnboundedReceiver<notify::Result<Event>>;
+
+/// Watch `dir` and its subdirectories, sending the events of the returned
+/// watcher on the returned channel. Watching stops when the watcher is dropped.
+pub(crate) fn watch(
+    dir: &Path,
+) -> Result<(RecommendedWatcher, WatchEvents), Box<dyn StdError + Send + Sync>> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = sender.send(event);
+    })?;
+    watcher
+        .watch(dir, RecursiveMode::Recursive)
+        .map_err(|e| format!("❌ Failed to watch {}: {}", dir.display(), e))?;
+    Ok((watcher, receiver))
+}
+
+/// The files created or written to by `event`, including those renamed into place.
+pub(crate) fn changed_paths(event: &Event) -> &[PathBuf] {
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(_) => &event.paths,
+        _ => &[],
+    }
+}
+
+/// Whether `path` is a file to load: one of `extensions`, and not under `failed_dir`.
+pub(crate) fn is_watched(path: &Path, extensions: &[String], failed_dir: &Path) -> bool {
+    let has_extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| {
+            extensions
+                .iter()
+                .any(|watched| extension.eq_ignore_ascii_case(watched))
+        })
+        .unwrap_or(false);
+    has_extension && !path.starts_with(failed_dir)
+}
+
+/// Size and modification time of a file, which change while it is written.
+fn file_state(path: &Path) -> Option<(u64, Option<SystemTime>)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    metadata
+        .is_file()
+        .then(|| (metadata.len(), metadata.modified().ok()))
+}
+
+/// A file waiting to settle, see `PendingFiles`.
+struct Pending {
+    state: Option<(u64, Option<SystemTime>)>,
+    since: Instant,
+}
+
+/// Files seen changing, loaded once they stop changing.
+#[derive(Default)]
+pub(crate) struct PendingFiles {
+    files: HashMap<PathBuf, Pending>,
+}
+
+impl PendingFiles {
+    /// Wait for `path` to settle, again if it was already waiting.
+    pub(crate) fn touch(&mut self, path: PathBuf) {
+        let state = file_state(&path);
+        self.files.insert(
+            path,
+            Pending {
+                state,
+                since: Instant::now(),
+            },
+        );
+    }
+
+    /// Take the files whose size and modification time have not changed for
+    /// `settle_time`, sorted by path so split chunks are loaded in order.
+    /// Files removed meanwhile are forgotten, and empty files keep waiting.
+    pub(crate) fn take_settled(&mut self, settle_time: Duration) -> Vec<PathBuf> {
+        let mut settled = Vec::new();
+        self.files.retain(|path, pending| {
+            let state = file_state(path);
+            if state.is_none() {
+                return false;
+            }
+            if state != pending.state {
+                pending.state = state;
+                pending.since = Instant::now();
+                return true;
+            }
+            let is_empty = matches!(state, Some((0, _)));
+            if is_empty || pending.since.elapsed() < settle_time {
+                return true;
+            }
+            settled.push(path.clone());
+            false
+        });
+        settled.sort();
+        settled
+    }
+}
+
+/// Move `path`, under `dir`, to the same place under `failed_dir`. A file
+/// already there is kept, and the moved one gets a timestamp suffix instead.
+pub(crate) fn move_to_failed(
+    path: &Path,
+    dir: &Path,
+    failed_dir: &Path,
+) -> Result<PathBuf, Box<dyn StdError + Send + Sync>> {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    let mut target = failed_dir.join(relative);
+    if target.exists() {
+        let stem = target
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = match target.extension() {
+            Some(extension) => format!(
+                "{}_{}.{}",
+                stem,
+                chrono::Local::now().format("%Y%m%d_%H%M%S"),
+                extension.to_string_lossy()
+            ),
+            None => format!("{}_{}", stem, chrono::Local::now().format("%Y%m%d_%H%M%S")),
+        };
+        target.set_file_name(name);
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(path, &target).map_err(|e| {
+        format!(
+            "❌ Failed to move {} to {}: {}",
+            path.display(),
+            target.display(),
+            e
+        )
+    })?;
+    Ok(target)
+}
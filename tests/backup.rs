@@ -0,0 +1,47 @@
+//! Managing the backups of a directory, which needs no database.
+#![cfg(feature = "backup")]
+
+mod common;
+
+use postgres_connect_rust::{Config, DatabaseQueriesWrite, PostgresQueriesWrite};
+
+/// Queries on a database named `db_name`, that are never connected.
+fn write_queries(db_name: &str) -> PostgresQueriesWrite {
+    PostgresQueriesWrite::from_config(Config {
+        host: "127.0.0.1".to_string(),
+        port: 1,
+        user: "postgres".to_string(),
+        password: "postgres".to_string(),
+        db_name: db_name.to_string(),
+        pg_dump_path: None,
+        pg_restore_path: None,
+        jobs: None,
+        audit_log: None,
+        dialect: None,
+    })
+    .expect("the pool should build")
+}
+
+#[tokio::test]
+async fn prune_backups_measures_the_age_of_utc_named_backups_in_utc() {
+    let dir = std::env::temp_dir().join(common::unique_name("pgc_prune"));
+    std::fs::create_dir_all(&dir).expect("create the backup directory");
+    let now = chrono::Utc::now().naive_utc();
+    let name = |age: chrono::Duration| {
+        let timestamp = (now - age).format("%Y-%m-%d_%H-%M-%S");
+        let path = dir.join(format!("backup_prunedb_{}.dump", timestamp));
+        std::fs::write(&path, b"").expect("write the backup");
+        path.display().to_string()
+    };
+    name(chrono::Duration::hours(22));
+    let old = name(chrono::Duration::hours(26));
+    name(chrono::Duration::hours(1));
+
+    let removed = write_queries("prunedb")
+        .prune_backups(dir.to_str().unwrap(), 0, Some(1), true, true)
+        .await
+        .expect("prune_backups");
+    assert_eq!(removed, vec![old], "{:?}", removed);
+    assert_eq!(std::fs::read_dir(&dir).expect("read_dir").count(), 3);
+    std::fs::remove_dir_all(dir).ok();
+}
@@ -0,0 +1,56 @@
+//! The functions of `blocking`, with the `blocking` feature, see `common`
+//! for running them against a server.
+#![cfg(feature = "blocking")]
+
+mod common;
+
+use common::TestDatabase;
+use postgres_connect_rust::{blocking, Config, ErrorKind, InsertGeoJsonOptions};
+
+#[test]
+fn blocking_functions_load_count_and_drop() {
+    if !common::enabled() {
+        return;
+    }
+    // The database is created on a runtime, which the blocking calls must not run on
+    let runtime = tokio::runtime::Runtime::new().expect("a runtime");
+    let db = runtime.block_on(TestDatabase::start());
+
+    let report = blocking::insert_geojson(
+        &db.config,
+        &common::fixture("points.geojson"),
+        Some(InsertGeoJsonOptions::new().table("points").build()),
+    )
+    .expect("insert_geojson");
+    assert_eq!(report.files[0].features_loaded, 3);
+    assert!(blocking::list_tables(&db.config)
+        .expect("list_tables")
+        .contains(&"points".to_string()));
+    assert_eq!(
+        blocking::table_row_count(&db.config, "points").expect("table_row_count"),
+        3
+    );
+    blocking::drop(&db.config, "points").expect("drop");
+    assert!(!blocking::list_tables(&db.config)
+        .expect("list_tables")
+        .contains(&"points".to_string()));
+}
+
+#[tokio::test]
+async fn blocking_functions_refuse_to_run_in_a_runtime() {
+    // Refused before connecting, so any config does
+    let config = Config {
+        host: "localhost".to_string(),
+        port: 5432,
+        user: "postgres".to_string(),
+        password: String::new(),
+        db_name: "postgres".to_string(),
+        pg_dump_path: None,
+        pg_restore_path: None,
+        jobs: None,
+        audit_log: None,
+        dialect: None,
+    };
+    let error = blocking::list_tables(&config).expect_err("called from a runtime");
+    assert_eq!(error.kind(), ErrorKind::Usage);
+}
@@ -0,0 +1,568 @@
+//! The exit codes of the `pgc` binary. The failures found before a
+//! database answers need none, the others run as the integration tests of
+//! `common` do.
+#![cfg(feature = "cli")]
+
+mod common;
+
+use postgres_connect_rust::{
+    write_starter_config, Config, DatabaseQueriesRead, DatabaseQueriesWrite, InsertGeoJsonOptions,
+};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// `pgc` with `args`, not interactive, and with no `$PGC_CONFIG`.
+fn pgc_command(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_pgc"));
+    command
+        .args(args)
+        .arg("--non-interactive")
+        .env_remove("PGC_CONFIG");
+    command
+}
+
+/// `pgc` run with `args`.
+fn pgc(args: &[&str]) -> Output {
+    pgc_command(args).output().expect("run pgc")
+}
+
+/// `pgc` run with `args`, reading `input` on stdin.
+fn pgc_with_input(args: &[&str], input: impl AsRef<[u8]>) -> Output {
+    let mut child = pgc_command(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("run pgc");
+    child
+        .stdin
+        .take()
+        .expect("the stdin of pgc")
+        .write_all(input.as_ref())
+        .expect("write to pgc");
+    child.wait_with_output().expect("wait for pgc")
+}
+
+fn exit_code(output: &Output) -> i32 {
+    output.status.code().expect("pgc exited, not killed")
+}
+
+#[test]
+fn an_invalid_command_line_exits_with_2() {
+    let output = pgc(&["list-tables", "--no-such-flag"]);
+    assert_eq!(exit_code(&output), 2, "{:?}", output);
+}
+
+#[test]
+fn a_missing_config_exits_with_3() {
+    let path = std::env::temp_dir().join(common::unique_name("pgc_missing"));
+    let output = pgc(&["--config", path.to_str().unwrap(), "list-tables"]);
+    assert_eq!(exit_code(&output), 3, "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(path.to_str().unwrap()), "{}", stderr);
+}
+
+#[test]
+fn an_unreachable_host_exits_with_4() {
+    let path =
+        std::env::temp_dir().join(format!("{}.toml", common::unique_name("pgc_unreachable")));
+    let config = Config {
+        host: "127.0.0.1".to_string(),
+        // Nothing listens on it, the connection is refused at once
+        port: 1,
+        user: "postgres".to_string(),
+        password: "postgres".to_string(),
+        db_name: "postgres".to_string(),
+        pg_dump_path: None,
+        pg_restore_path: None,
+        jobs: None,
+        audit_log: None,
+        dialect: None,
+    };
+    write_starter_config(path.to_str().unwrap(), &config, true).expect("write the config");
+    let output = pgc(&["--config", path.to_str().unwrap(), "list-tables"]);
+    assert_eq!(exit_code(&output), 4, "{:?}", output);
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn reading_stdin_needs_a_table_name() {
+    let output = pgc_with_input(&["insert-geojson", "-"], "{}");
+    assert_eq!(exit_code(&output), 2, "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--table"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn geojson_piped_in_plain_or_gzipped_is_loaded() {
+    if !common::enabled() {
+        return;
+    }
+    let db = common::TestDatabase::start().await;
+    let collection = std::fs::read(common::fixture("points.geojson")).expect("read");
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(&collection).expect("gzip");
+    let gzipped = encoder.finish().expect("gzip");
+    for (table, input) in [("piped", &collection), ("piped_gz", &gzipped)] {
+        let output = pgc_with_input(
+            &[
+                "--config",
+                db.config_path.to_str().unwrap(),
+                "insert-geojson",
+                "-",
+                "--table",
+                table,
+            ],
+            input,
+        );
+        assert_eq!(exit_code(&output), 0, "{:?}", output);
+        assert_eq!(
+            db.read_queries
+                .table_row_count(table, None)
+                .await
+                .expect("table_row_count"),
+            3
+        );
+    }
+    let imports = db
+        .read_queries
+        .execute(
+            "SELECT table_name, source_path, file_size FROM _imports ORDER BY table_name"
+                .to_string(),
+        )
+        .await
+        .expect("read _imports");
+    let imports: Vec<(String, String, i64)> = imports
+        .iter()
+        .map(|row| (row.get(0), row.get(1), row.get(2)))
+        .collect();
+    assert_eq!(
+        imports,
+        [
+            (
+                "piped".to_string(),
+                "stdin".to_string(),
+                collection.len() as i64
+            ),
+            (
+                "piped_gz".to_string(),
+                "stdin".to_string(),
+                gzipped.len() as i64
+            ),
+        ]
+    );
+}
+
+#[test]
+fn jobs_are_at_least_one() {
+    for args in [
+        ["--jobs", "0", "list-tables"],
+        ["insert-geojson", "x.geojson", "-j0"],
+        ["insert-geojson", "x.geojson", "--parallel-copy=0"],
+    ] {
+        let output = pgc(&args);
+        assert_eq!(exit_code(&output), 2, "{:?}", output);
+    }
+}
+
+#[tokio::test]
+async fn jobs_default_to_the_config_and_are_set_by_the_flags() {
+    if !common::enabled() {
+        return;
+    }
+    let db = common::TestDatabase::start().await;
+    let config = std::fs::read_to_string(&db.config_path).expect("read the config");
+    let path = std::env::temp_dir().join(format!("{}.toml", common::unique_name("pgc_jobs")));
+    std::fs::write(
+        &path,
+        config.replacen("[config]\n", "[config]\njobs = 3\n", 1),
+    )
+    .expect("write the config");
+    let cases: [(&[&str], &str); 3] = [
+        (&[], "3"),
+        (&["--jobs", "2"], "2"),
+        (&["--jobs", "2", "--parallel-copy", "1"], "1"),
+    ];
+    for (i, (flags, connections)) in cases.into_iter().enumerate() {
+        let table = format!("points_{}", i);
+        let fixture = common::fixture("points.geojson");
+        let mut args = vec![
+            "--config",
+            path.to_str().unwrap(),
+            "insert-geojson",
+            &fixture,
+            "--table",
+            &table,
+        ];
+        args.extend(flags);
+        let output = pgc(&args);
+        assert_eq!(exit_code(&output), 0, "{:?}", output);
+        let logs = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            logs.contains(&format!("COPY connections per file: {}", connections)),
+            "{:?}: {}",
+            flags,
+            logs
+        );
+    }
+    std::fs::remove_file(path).ok();
+}
+
+#[tokio::test]
+async fn a_missing_input_file_exits_with_6() {
+    if !common::enabled() {
+        return;
+    }
+    let db = common::TestDatabase::start().await;
+    let output = pgc(&[
+        "--config",
+        db.config_path.to_str().unwrap(),
+        "insert-geojson",
+        &common::fixture("missing.geojson"),
+        "--table",
+        "points",
+    ]);
+    assert_eq!(exit_code(&output), 6, "{:?}", output);
+    let tables = db
+        .read_queries
+        .list_tables(None)
+        .await
+        .expect("list_tables");
+    assert!(!tables.contains(&"points".to_string()), "{:?}", tables);
+}
+
+#[tokio::test]
+async fn an_empty_table_name_exits_with_6() {
+    if !common::enabled() {
+        return;
+    }
+    let db = common::TestDatabase::start().await;
+    let output = pgc(&[
+        "--config",
+        db.config_path.to_str().unwrap(),
+        "drop",
+        "",
+        "--yes",
+    ]);
+    assert_eq!(exit_code(&output), 6, "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("name is empty"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn the_sql_prompt_stays_read_only_after_set_config() {
+    if !common::enabled() {
+        return;
+    }
+    let db = common::TestDatabase::start().await;
+    db.write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(InsertGeoJsonOptions::new().table("points").build()),
+        )
+        .await
+        .expect("insert_geojson");
+    let output = pgc_with_input(
+        &["--config", db.config_path.to_str().unwrap(), "sql"],
+        "SELECT set_config('default_transaction_read_only', 'off', false);\n\
+         WITH d AS (DELETE FROM points RETURNING 1) SELECT count(*) FROM d;\n\
+         SHOW default_transaction_read_only;\n",
+    );
+    assert_eq!(exit_code(&output), 0, "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("read-only transaction"), "{}", stderr);
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+}
+
+#[tokio::test]
+async fn the_sql_prompt_refuses_the_writes_after_a_dollar_inside_a_name() {
+    if !common::enabled() {
+        return;
+    }
+    let db = common::TestDatabase::start().await;
+    db.write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(InsertGeoJsonOptions::new().table("points").build()),
+        )
+        .await
+        .expect("insert_geojson");
+    let output = pgc_with_input(
+        &["--config", db.config_path.to_str().unwrap(), "sql"],
+        "SELECT 1 AS a$b$; COMMIT; DELETE FROM points; SELECT 1 AS c$b$;\n",
+    );
+    assert_eq!(exit_code(&output), 0, "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("DELETE statements are refused without --allow-writes"),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+}
+
+#[tokio::test]
+async fn the_sql_prompt_runs_statements_over_lines_and_its_shortcuts() {
+    if !common::enabled() {
+        return;
+    }
+    let db = common::TestDatabase::start().await;
+    db.write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(InsertGeoJsonOptions::new().table("points").build()),
+        )
+        .await
+        .expect("insert_geojson");
+    let config = db.config_path.to_str().unwrap();
+    let output = pgc_with_input(
+        &["--config", config, "sql"],
+        "SELECT properties->>'name' AS name\n  FROM points\n  WHERE properties->>'value' = '2';\
+         SELECT 'two' AS second;\n\
+         \\dt\n\
+         \\d points\n\
+         \\d no_such_table\n\
+         DELETE FROM points;\n",
+    );
+    assert_eq!(exit_code(&output), 0, "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for printed in ["beta", "two", "points", "geometry", "properties"] {
+        assert!(stdout.contains(printed), "{}: {}", printed, stdout);
+    }
+    assert!(!stdout.contains("alpha"), "{}", stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("No table named no_such_table"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("DELETE statements are refused without --allow-writes"),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+
+    let output = pgc_with_input(
+        &["--config", config, "sql", "--allow-writes"],
+        "DELETE FROM points\nWHERE properties->>'value' = '2';\n\\q\nDELETE FROM points;\n",
+    );
+    assert_eq!(exit_code(&output), 0, "{:?}", output);
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        2
+    );
+}
+
+#[tokio::test]
+async fn run_dry_run_plans_the_statements_of_the_steps() {
+    if !common::enabled() {
+        return;
+    }
+    let db = common::TestDatabase::start().await;
+    db.write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(InsertGeoJsonOptions::new().table("points").build()),
+        )
+        .await
+        .expect("insert_geojson");
+    let plan = std::env::temp_dir().join(format!("{}.toml", common::unique_name("pgc_plan")));
+    std::fs::write(
+        &plan,
+        "[[steps]]\naction = \"truncate\"\ntable = \"points\"\n\n\
+         [[steps]]\naction = \"sql\"\nsql = \"DELETE FROM points\"\n",
+    )
+    .expect("write the plan");
+    let output = pgc(&[
+        "--config",
+        db.config_path.to_str().unwrap(),
+        "--output",
+        "json",
+        "run",
+        plan.to_str().unwrap(),
+        "--dry-run",
+    ]);
+    assert_eq!(exit_code(&output), 0, "{:?}", output);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("a JSON report");
+    let steps = report["steps"].as_array().expect("the steps");
+    assert_eq!(steps.len(), 2, "{}", report);
+    for (step, detail) in steps
+        .iter()
+        .zip(["TRUNCATE TABLE \"points\"", "DELETE FROM points"])
+    {
+        assert_eq!(step["status"], "planned", "{}", report);
+        assert_eq!(step["detail"], detail, "{}", report);
+    }
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+    std::fs::remove_file(plan).ok();
+}
+
+#[tokio::test]
+async fn run_stops_at_a_failed_step_unless_it_continues_on_error() {
+    if !common::enabled() {
+        return;
+    }
+    let db = common::TestDatabase::start().await;
+    let plan = std::env::temp_dir().join(format!("{}.toml", common::unique_name("pgc_plan")));
+    std::fs::write(
+        &plan,
+        format!(
+            "[[steps]]\naction = \"sql\"\nsql = \"SELECT * FROM no_such_table\"\n\
+             continue_on_error = true\n\n\
+             [[steps]]\naction = \"insert_geojson\"\npath = \"{}\"\ntable = \"points\"\n\n\
+             [[steps]]\naction = \"analyze\"\ntable = \"no_such_table\"\n\n\
+             [[steps]]\naction = \"drop\"\ntable = \"points\"\n",
+            common::fixture("points.geojson")
+        ),
+    )
+    .expect("write the plan");
+    let output = pgc(&[
+        "--config",
+        db.config_path.to_str().unwrap(),
+        "--output",
+        "json",
+        "run",
+        plan.to_str().unwrap(),
+        "--yes",
+    ]);
+    assert_eq!(exit_code(&output), 8, "{:?}", output);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("a JSON report");
+    let statuses: Vec<&str> = report["steps"]
+        .as_array()
+        .expect("the steps")
+        .iter()
+        .map(|step| step["status"].as_str().expect("a status"))
+        .collect();
+    assert_eq!(
+        statuses,
+        ["failed", "ok", "failed", "skipped"],
+        "{}",
+        report
+    );
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+    std::fs::remove_file(plan).ok();
+}
+
+/// The completions `pgc` gives of the command line `words`, the last one
+/// being completed, as fish asks for them.
+fn completions(words: &[&str]) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_pgc"))
+        .arg("--")
+        .arg("pgc")
+        .args(words)
+        .env("COMPLETE", "fish")
+        .env_remove("PGC_CONFIG")
+        .output()
+        .expect("run pgc");
+    assert_eq!(exit_code(&output), 0, "{:?}", output);
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.split('\t').next().unwrap_or_default().to_string())
+        .collect()
+}
+
+#[test]
+fn completions_name_the_commands_and_a_script_registers_them() {
+    assert_eq!(
+        completions(&["list-t"]),
+        ["list-tables".to_string()],
+        "the aliases are hidden"
+    );
+    for shell in ["bash", "zsh", "fish"] {
+        let output = pgc(&["completions", shell]);
+        assert_eq!(exit_code(&output), 0, "{:?}", output);
+        let script = String::from_utf8_lossy(&output.stdout);
+        assert!(script.contains("COMPLETE="), "{}: {}", shell, script);
+    }
+    // `ls` is `list-tables`
+    let path = std::env::temp_dir().join(common::unique_name("pgc_missing"));
+    let output = pgc(&["--config", path.to_str().unwrap(), "ls"]);
+    assert_eq!(exit_code(&output), 3, "{:?}", output);
+}
+
+#[test]
+fn table_names_are_not_completed_without_a_database() {
+    let path =
+        std::env::temp_dir().join(format!("{}.toml", common::unique_name("pgc_unreachable")));
+    let config = Config {
+        host: "127.0.0.1".to_string(),
+        port: 1,
+        user: "postgres".to_string(),
+        password: "postgres".to_string(),
+        db_name: "postgres".to_string(),
+        pg_dump_path: None,
+        pg_restore_path: None,
+        jobs: None,
+        audit_log: None,
+        dialect: None,
+    };
+    write_starter_config(path.to_str().unwrap(), &config, true).expect("write the config");
+    assert!(completions(&["--config", path.to_str().unwrap(), "drop", "p"]).is_empty());
+    std::fs::remove_file(path).ok();
+}
+
+#[tokio::test]
+async fn table_names_are_completed_from_the_database() {
+    if !common::enabled() {
+        return;
+    }
+    let db = common::TestDatabase::start().await;
+    for table in ["points", "parcels", "roads"] {
+        db.write_queries
+            .insert_geojson(
+                &common::fixture("points.geojson"),
+                Some(InsertGeoJsonOptions::new().table(table).build()),
+            )
+            .await
+            .expect("insert_geojson");
+    }
+    let config = db.config_path.to_str().unwrap();
+    let mut tables = completions(&["--config", config, "drop", "p"]);
+    tables.sort();
+    assert_eq!(tables, ["parcels", "points"]);
+    assert_eq!(
+        completions(&[
+            "--config",
+            config,
+            "insert-geojson",
+            "x.geojson",
+            "--table",
+            "ro"
+        ]),
+        ["roads"]
+    );
+}
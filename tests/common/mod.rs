@@ -0,0 +1,173 @@
+//! The server the integration tests run against, and a database of its own
+//! for each test, so they can run in parallel.
+//!
+//! The tests only run with `PGC_INTEGRATION=1`, and pass without doing
+//! anything otherwise, so `cargo test` stays fast and offline. Each test
+//! then starts a `postgis/postgis` container, unless `PGC_TEST_CONFIG` names
+//! the config file of a server to use instead, whose user can create
+//! databases and PostGIS.
+
+use postgres_connect_rust::{
+    connect, write_starter_config, Config, ConfigFile, PostgresQueriesRead, PostgresQueriesWrite,
+};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use testcontainers_modules::testcontainers::{ContainerAsync, ImageExt};
+
+/// The image of the containers, the official one with PostGIS.
+const IMAGE: &str = "postgis/postgis";
+const TAG: &str = "16-3.4";
+
+/// The databases created by this test binary, to name them apart.
+static DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the integration tests run, saying why not when they do not.
+pub fn enabled() -> bool {
+    let enabled = std::env::var("PGC_INTEGRATION").is_ok_and(|value| value == "1");
+    if !enabled {
+        eprintln!("Skipped: set PGC_INTEGRATION=1 to run the integration tests");
+    }
+    enabled
+}
+
+/// A name starting with `prefix`, unique across the tests and the test
+/// binaries running at the same time.
+pub fn unique_name(prefix: &str) -> String {
+    format!(
+        "{}_{}_{}",
+        prefix,
+        std::process::id(),
+        DATABASES.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// The path of a file of `tests/fixtures`.
+pub fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// A database with PostGIS for one test, and the query structs on it, built
+/// from a config file written for it as `pgc config init` does.
+pub struct TestDatabase {
+    pub config: Config,
+    pub config_path: PathBuf,
+    pub write_queries: PostgresQueriesWrite,
+    pub read_queries: PostgresQueriesRead,
+    /// The connection creating and dropping the database.
+    admin: Config,
+    /// Removed when the test ends, with its databases.
+    _container: Option<ContainerAsync<Postgres>>,
+}
+
+impl TestDatabase {
+    /// Create the database of a test, starting a container for it unless
+    /// `PGC_TEST_CONFIG` names a server.
+    pub async fn start() -> TestDatabase {
+        let (admin, container) = match std::env::var("PGC_TEST_CONFIG") {
+            Ok(path) => {
+                let admin = Config::load(&path)
+                    .unwrap_or_else(|e| panic!("PGC_TEST_CONFIG {} should load: {}", path, e));
+                (admin, None)
+            }
+            Err(_) => {
+                let container = Postgres::default()
+                    .with_name(IMAGE)
+                    .with_tag(TAG)
+                    .start()
+                    .await
+                    .expect("the PostGIS container should start");
+                let admin = Config {
+                    host: container.get_host().await.expect("host").to_string(),
+                    port: container
+                        .get_host_port_ipv4(5432)
+                        .await
+                        .expect("mapped port"),
+                    user: "postgres".to_string(),
+                    password: "postgres".to_string(),
+                    db_name: "postgres".to_string(),
+                    pg_dump_path: None,
+                    pg_restore_path: None,
+                    jobs: None,
+                    audit_log: None,
+                    dialect: None,
+                };
+                (admin, Some(container))
+            }
+        };
+
+        let config = Config {
+            db_name: unique_name("pgc_test"),
+            ..admin.clone()
+        };
+        let client = connect(&admin, &admin.db_name)
+            .await
+            .expect("the server should accept connections");
+        client
+            .batch_execute(&format!("CREATE DATABASE {}", config.db_name))
+            .await
+            .expect("the test database should be created");
+        connect(&config, &config.db_name)
+            .await
+            .expect("the test database should accept connections")
+            .batch_execute("CREATE EXTENSION IF NOT EXISTS postgis CASCADE")
+            .await
+            .expect("PostGIS should be installed");
+
+        // Read back as pgc reads its config
+        let config_path = std::env::temp_dir().join(format!("{}.toml", config.db_name));
+        write_starter_config(&config_path.display().to_string(), &config, true)
+            .expect("the config file should be written");
+        let config_file = ConfigFile::load(&config_path.display().to_string())
+            .expect("the written config file should load");
+        let write_queries =
+            PostgresQueriesWrite::from_config_file(config_file).expect("the pool should build");
+        let read_queries = PostgresQueriesRead::new(write_queries.pool().clone());
+        TestDatabase {
+            config,
+            config_path,
+            write_queries,
+            read_queries,
+            admin,
+            _container: container,
+        }
+    }
+}
+
+/// Drop the database and config file of the test when it ends, passed or
+/// failed. Dropping is not async, so the database is dropped from a runtime
+/// of its own, on another thread, before the container is removed; failing
+/// to drop it is reported rather than panicking again in a failed test.
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        self.write_queries.pool().close();
+        let admin = self.admin.clone();
+        let db_name = self.config.db_name.clone();
+        let dropped = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| e.to_string())?;
+            runtime.block_on(async {
+                let client = connect(&admin, &admin.db_name)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                client
+                    .batch_execute(&format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", db_name))
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+        })
+        .join();
+        match dropped {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!(
+                "The test database {} was not dropped: {}",
+                self.config.db_name, e
+            ),
+            Err(_) => eprintln!("The test database {} was not dropped", self.config.db_name),
+        }
+        let _ = std::fs::remove_file(&self.config_path);
+    }
+}
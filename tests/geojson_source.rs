@@ -0,0 +1,196 @@
+//! Telling the format of GeoJSON files and reading them with
+//! `GeoJsonSource`, and the errors of files that are empty, truncated or
+//! invalid. No database is needed.
+
+mod common;
+
+use postgres_connect_rust::{
+    parse_geojson_features, ErrorKind, GeoJsonFormat, GeoJsonSource, GeoJsonSummary, PgcError,
+};
+use std::path::PathBuf;
+
+/// `contents` written to a file of the temporary directory named `name`.
+fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("pgc_source_{}_{}", std::process::id(), name));
+    std::fs::write(&path, contents).expect("write the file");
+    path
+}
+
+/// The names of the features `source` hands out, or the error that
+/// stopped them, after those read before it.
+async fn feature_names(source: &GeoJsonSource) -> (Vec<String>, Option<PgcError>) {
+    let mut names = Vec::new();
+    let mut features = source.features();
+    while let Some(next) = features.next_feature().await {
+        match next {
+            Ok((idx, feature)) => {
+                assert_eq!(idx, names.len());
+                names.push(feature.property("name").expect("a name").to_string());
+            }
+            Err(e) => return (names, Some(e)),
+        }
+    }
+    (names, None)
+}
+
+#[tokio::test]
+async fn feature_collections_and_geojsonl_read_the_same_features() {
+    let collection = GeoJsonSource::open(&common::fixture("points.geojson"))
+        .await
+        .expect("open points.geojson");
+    assert_eq!(collection.format(), GeoJsonFormat::FeatureCollection);
+    assert!(!collection.is_gzipped());
+    assert_eq!(collection.file_stem(), "points");
+
+    let lines = GeoJsonSource::open(&common::fixture("points.geojsonl"))
+        .await
+        .expect("open points.geojsonl");
+    assert_eq!(lines.format(), GeoJsonFormat::Lines);
+    assert_eq!(lines.file_stem(), "points");
+
+    let expected = vec![
+        "\"alpha\"".to_string(),
+        "\"beta\"".to_string(),
+        "\"gamma\"".to_string(),
+    ];
+    for source in [&collection, &lines] {
+        let (names, error) = feature_names(source).await;
+        assert!(error.is_none(), "{:?}", error);
+        assert_eq!(names, expected, "{}", source.path());
+        assert_eq!(source.scan().await.expect("scan").features, 3);
+    }
+    let parsed = parse_geojson_features(&common::fixture("points.geojsonl"))
+        .expect("parse_geojson_features of GeoJSONL");
+    assert_eq!(parsed.len(), 3);
+}
+
+#[cfg(feature = "ingest")]
+#[tokio::test]
+async fn gzipped_geojsonl_is_read_decompressed() {
+    let source = GeoJsonSource::open(&common::fixture("points.geojsonl.gz"))
+        .await
+        .expect("open points.geojsonl.gz");
+    assert_eq!(source.format(), GeoJsonFormat::Lines);
+    assert!(source.is_gzipped());
+    assert_eq!(source.file_stem(), "points");
+    let (names, error) = feature_names(&source).await;
+    assert!(error.is_none(), "{:?}", error);
+    assert_eq!(names.len(), 3);
+
+    let compressed = std::fs::read(common::fixture("points.geojsonl.gz")).expect("read");
+    let path = temp_file("cut.geojsonl.gz", &compressed[..compressed.len() / 2]);
+    let result = match GeoJsonSource::open(path.to_str().unwrap()).await {
+        Ok(source) => source.scan().await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    let error = result.expect_err("a truncated gzip stream");
+    assert_eq!(error.kind(), ErrorKind::InvalidInput, "{}", error);
+    std::fs::remove_file(path).ok();
+}
+
+#[tokio::test]
+async fn scan_reads_the_crs_and_bbox_after_the_features() {
+    let path = temp_file(
+        "crs.geojson",
+        br#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"name": "a"}, "geometry": null},
+            {"type": "Feature", "properties": {"name": "b"}, "geometry": null}
+        ],
+        "crs": {"type": "name", "properties": {"name": "EPSG:3857"}},
+        "bbox": [1.5, 2.0, 3.5, 4.0]}"#,
+    );
+    let source = GeoJsonSource::open(path.to_str().unwrap())
+        .await
+        .expect("open");
+    assert_eq!(source.format(), GeoJsonFormat::FeatureCollection);
+    assert_eq!(
+        source.scan().await.expect("scan"),
+        GeoJsonSummary {
+            features: 2,
+            crs: Some(serde_json::json!({"type": "name", "properties": {"name": "EPSG:3857"}})),
+            bbox: Some(vec![1.5, 2.0, 3.5, 4.0]),
+        }
+    );
+    let lines = GeoJsonSource::open(&common::fixture("points.geojsonl"))
+        .await
+        .expect("open points.geojsonl");
+    let summary = lines.scan().await.expect("scan");
+    assert_eq!((summary.crs, summary.bbox), (None, None));
+    std::fs::remove_file(path).ok();
+}
+
+#[tokio::test]
+async fn files_that_are_not_geojson_fail_to_open() {
+    let cases: [(&str, &[u8], &str); 5] = [
+        ("empty.geojson", b"", "no GeoJSON object"),
+        ("blank.geojson", b"\n  \n", "no GeoJSON object"),
+        (
+            "array.geojson",
+            b"[1, 2]",
+            "does not start with a GeoJSON object",
+        ),
+        (
+            "point.geojson",
+            br#"{"type": "Point", "coordinates": [1, 2]}"#,
+            "holds a Point",
+        ),
+        (
+            "syntax.geojson",
+            b"{\"type\": FeatureCollection, \"features\": [\n]}",
+            "Failed to parse GeoJSON",
+        ),
+    ];
+    for (name, contents, message) in cases {
+        let path = temp_file(name, contents);
+        let error = GeoJsonSource::open(path.to_str().unwrap())
+            .await
+            .expect_err(name);
+        assert_eq!(error.kind(), ErrorKind::InvalidInput, "{}", error);
+        let text = error.to_string();
+        assert!(text.contains(message), "{}: {}", name, text);
+        assert!(text.contains(path.to_str().unwrap()), "{}", text);
+        std::fs::remove_file(path).ok();
+    }
+    let error = GeoJsonSource::open("tests/fixtures/missing.geojson")
+        .await
+        .expect_err("a missing file");
+    assert!(matches!(error, PgcError::Io { .. }), "{}", error);
+}
+
+#[tokio::test]
+async fn truncated_and_invalid_features_stop_the_reading_with_an_error() {
+    let collection = std::fs::read(common::fixture("points.geojson")).expect("read");
+    // Cut in the second feature
+    let path = temp_file("cut.geojson", &collection[..280]);
+    let source = GeoJsonSource::open(path.to_str().unwrap())
+        .await
+        .expect("open the start of a FeatureCollection");
+    let (names, error) = feature_names(&source).await;
+    assert_eq!(names, vec!["\"alpha\"".to_string()]);
+    let error = error.expect("an error at the cut");
+    assert_eq!(error.kind(), ErrorKind::InvalidInput, "{}", error);
+    assert!(error.to_string().contains("EOF"), "{}", error);
+    assert!(source.scan().await.is_err());
+    std::fs::remove_file(path).ok();
+
+    let path = temp_file(
+        "bad_line.geojsonl",
+        b"{\"type\": \"Feature\", \"properties\": {\"name\": \"a\"}, \"geometry\": null}\n\
+          {\"type\": \"Feature\", \"properties\": {\"name\": \n\
+          {\"type\": \"Feature\", \"properties\": {\"name\": \"c\"}, \"geometry\": null}\n",
+    );
+    let source = GeoJsonSource::open(path.to_str().unwrap())
+        .await
+        .expect("open GeoJSONL");
+    let (names, error) = feature_names(&source).await;
+    assert_eq!(names, vec!["\"a\"".to_string()]);
+    match error {
+        Some(PgcError::GeoJson {
+            path: Some(_),
+            feature_index: Some(1),
+            message,
+        }) => assert!(message.contains("line 2"), "{}", message),
+        other => panic!("not the error of the second line: {:?}", other),
+    }
+    std::fs::remove_file(path).ok();
+}
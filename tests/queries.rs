@@ -0,0 +1,1673 @@
+//! The query structs against a real PostGIS, see `common` for running them.
+
+mod common;
+
+use common::TestDatabase;
+use postgres_connect_rust::{
+    connect, parse_geojson_features, pool_of, AuditLog, ColumnKind, DatabaseQueriesRead,
+    DatabaseQueriesWrite, Dedupe, Dialect, DropOutcome, DryRun, ErrorKind, FeatureFilter,
+    GeoTableOptions, GeometryType, IngestOptions, InsertGeoJsonOptions, InsertStrategy, LoadMode,
+    ObserverEvent, OnFeatureError, OperationReport, PatchMode, PgType, PgcError,
+    PostgresQueriesRead, PostgresQueriesWrite, ProgressObserver,
+};
+#[cfg(feature = "backup")]
+use postgres_connect_rust::{BackupOptions, ProgressEvent, Reporter, RestoreOptions};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[tokio::test]
+async fn create_geo_table_lists_its_columns() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let client = connect(&db.config, &db.config.db_name)
+        .await
+        .expect("connect");
+    db.write_queries
+        .create_geo_table(&client, "parcels", &GeoTableOptions::default())
+        .await
+        .expect("create_geo_table");
+
+    let tables = db
+        .read_queries
+        .list_tables(None)
+        .await
+        .expect("list_tables");
+    assert!(tables.contains(&"parcels".to_string()), "{:?}", tables);
+    let columns: Vec<String> = db
+        .read_queries
+        .list_columns("parcels")
+        .await
+        .expect("list_columns")
+        .into_iter()
+        .map(|column| column.column_name)
+        .collect();
+    for column in ["name", "geometry", "properties"] {
+        assert!(columns.contains(&column.to_string()), "{:?}", columns);
+    }
+    assert_eq!(
+        db.read_queries
+            .table_row_count("parcels", None)
+            .await
+            .expect("table_row_count"),
+        0
+    );
+}
+
+#[tokio::test]
+async fn insert_geojson_round_trips_and_drops() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let report = db
+        .write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(InsertGeoJsonOptions::new().table("points").build()),
+        )
+        .await
+        .expect("insert_geojson");
+    assert_eq!(report.table, "points");
+    assert_eq!(report.files.len(), 1);
+    assert_eq!(report.files[0].features_loaded, 3);
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+
+    db.write_queries.drop("points").await.expect("drop");
+    let tables = db
+        .read_queries
+        .list_tables(None)
+        .await
+        .expect("list_tables");
+    assert!(!tables.contains(&"points".to_string()), "{:?}", tables);
+}
+
+#[tokio::test]
+async fn tables_named_like_sql_are_quoted() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let client = connect(&db.config, &db.config.db_name)
+        .await
+        .expect("connect");
+    db.write_queries
+        .create_geo_table(&client, "bar", &GeoTableOptions::default())
+        .await
+        .expect("create_geo_table");
+    for table in ["My Table", "select", "foo\"; DROP TABLE bar; --"] {
+        db.write_queries
+            .create_geo_table(&client, table, &GeoTableOptions::default())
+            .await
+            .expect(table);
+        let report = db
+            .write_queries
+            .insert_geojson(
+                &common::fixture("points.geojson"),
+                Some(InsertGeoJsonOptions::new().table(table).build()),
+            )
+            .await
+            .expect(table);
+        assert_eq!(report.files[0].features_loaded, 3, "{}", table);
+        assert_eq!(
+            db.read_queries
+                .table_row_count(table, None)
+                .await
+                .expect(table),
+            3,
+            "{}",
+            table
+        );
+
+        db.write_queries.drop(table).await.expect(table);
+        let tables = db
+            .read_queries
+            .list_tables(None)
+            .await
+            .expect("list_tables");
+        assert!(!tables.contains(&table.to_string()), "{:?}", tables);
+        assert!(tables.contains(&"bar".to_string()), "{:?}", tables);
+    }
+}
+
+#[tokio::test]
+async fn insert_geojson_appends_or_replaces() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    // Named after their `name`, unique in the table
+    for (fixture, mode, rows) in [
+        ("points.geojson", LoadMode::Append, 3),
+        ("more_points.geojson", LoadMode::Append, 5),
+        ("points.geojson", LoadMode::Replace, 3),
+    ] {
+        let options = InsertGeoJsonOptions::new()
+            .table("points")
+            .mode(mode)
+            .name_from_property("name")
+            .build();
+        db.write_queries
+            .insert_geojson(&common::fixture(fixture), Some(options))
+            .await
+            .expect("insert_geojson");
+        assert_eq!(
+            db.read_queries
+                .table_row_count("points", None)
+                .await
+                .expect("table_row_count"),
+            rows,
+            "after the {:?} load of {}",
+            mode,
+            fixture
+        );
+    }
+}
+
+#[tokio::test]
+async fn points_load_the_same_as_geometry_and_geography() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let mut counts = Vec::new();
+    for (table, column_kind) in [
+        ("points_geometry", ColumnKind::Geometry),
+        ("points_geography", ColumnKind::Geography),
+    ] {
+        let table_options = GeoTableOptions {
+            column_kind,
+            geometry_type: Some(GeometryType::Point),
+            srid: Some(4326),
+            ..Default::default()
+        };
+        db.write_queries
+            .insert_geojson(
+                &common::fixture("points.geojson"),
+                Some(
+                    InsertGeoJsonOptions::new()
+                        .table(table)
+                        .table_options(table_options)
+                        .build(),
+                ),
+            )
+            .await
+            .expect("insert_geojson");
+        let columns = db
+            .read_queries
+            .geo_column_info(table)
+            .await
+            .expect("geo_column_info");
+        assert_eq!(columns.len(), 1, "{:?}", columns);
+        assert_eq!(columns[0].kind, column_kind);
+        assert_eq!(columns[0].srid, Some(4326));
+        counts.push(
+            db.read_queries
+                .table_row_count(table, None)
+                .await
+                .expect("table_row_count"),
+        );
+    }
+    assert_eq!(counts, [3, 3]);
+}
+
+#[tokio::test]
+async fn a_grant_that_fails_fails_the_load() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let table_options = GeoTableOptions {
+        grants: Some(vec![("no_such_role".to_string(), "SELECT".to_string())]),
+        ..Default::default()
+    };
+    let error = db
+        .write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(
+                InsertGeoJsonOptions::new()
+                    .table("points")
+                    .table_options(table_options)
+                    .build(),
+            ),
+        )
+        .await
+        .expect_err("a grant to a missing role");
+    assert!(
+        matches!(&error.source, PgcError::Query { sql, .. } if sql.contains("no_such_role")),
+        "{:?}",
+        error.source
+    );
+    assert!(error.report.files.is_empty());
+}
+
+#[tokio::test]
+async fn insert_geojson_streams_geojsonl_and_dedupes_across_files() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    // The same features as a FeatureCollection then as GeoJSONL
+    let dir = std::env::temp_dir().join(format!("{}_geojsonl", db.config.db_name));
+    std::fs::create_dir_all(&dir).expect("create_dir_all");
+    for fixture in ["points.geojson", "points.geojsonl"] {
+        std::fs::copy(common::fixture(fixture), dir.join(fixture)).expect("copy");
+    }
+    let options = InsertGeoJsonOptions::new()
+        .table("points")
+        .name_from_property("name")
+        .dedupe(Dedupe::FirstWins)
+        .build();
+    let report = db
+        .write_queries
+        .insert_geojson(dir.to_str().unwrap(), Some(options))
+        .await
+        .expect("insert_geojson");
+    let counts: Vec<(u64, u64, u64)> = report
+        .files
+        .iter()
+        .map(|file| (file.features_read, file.features_loaded, file.duplicates))
+        .collect();
+    // The names of the second file are read before its features
+    assert_eq!(counts, vec![(3, 3, 0), (3, 0, 3)]);
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn a_stream_keeps_its_skipped_features_in_the_report() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let stream = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {"name": "a"},
+         "geometry": {"type": "Point", "coordinates": [1.0, 2.0]}},
+        {"type": "Feature", "properties": {"name": "b"},
+         "geometry": {"type": "Point", "coordinates": [1.0]}},
+        {"type": "Feature", "properties": {"name": "c"},
+         "geometry": {"type": "Point", "coordinates": [3.0, 4.0]}}
+    ]}"#;
+    // Read as it is loaded, and parsed first for a dedupe strategy
+    for (table, dedupe) in [("streamed", Dedupe::None), ("parsed", Dedupe::FirstWins)] {
+        let mut options = InsertGeoJsonOptions::new()
+            .table(table)
+            .name_from_property("name")
+            .dedupe(dedupe)
+            .on_feature_error(OnFeatureError::Skip)
+            .build();
+        options.ingest_options.max_skip_ratio = 0.5;
+        let report = db
+            .write_queries
+            .insert_geojson_from_reader(
+                Box::new(std::io::Cursor::new(stream.as_bytes().to_vec())),
+                options,
+            )
+            .await
+            .expect("insert_geojson_from_reader");
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].path, "stdin");
+        assert_eq!(report.files[0].bytes, stream.len() as u64);
+        assert_eq!(
+            (report.files[0].features_loaded, report.files[0].skipped),
+            (2, 1)
+        );
+        assert!(
+            report.warnings.iter().any(|warning| warning
+                .starts_with("stdin: Skipped feature 1 (b): ")
+                && warning.contains("fewer than 2 coordinates")),
+            "{:?}",
+            report.warnings
+        );
+        assert_eq!(
+            db.read_queries
+                .table_row_count(table, None)
+                .await
+                .expect("table_row_count"),
+            2
+        );
+    }
+    assert!(!std::path::Path::new("stdin.errors.jsonl").exists());
+}
+
+#[tokio::test]
+async fn a_row_the_server_refuses_fails_the_load_as_sql() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let fixture = common::fixture("points.geojson");
+    for (table, insert_strategy, parallel_copy) in [
+        ("points_copy", InsertStrategy::Copy, 1),
+        ("points_staged", InsertStrategy::Copy, 2),
+        ("points_batched", InsertStrategy::Batched, 1),
+    ] {
+        let load = || {
+            InsertGeoJsonOptions::new()
+                .table(table)
+                .name_from_property("name")
+                .ingest_options(IngestOptions {
+                    insert_strategy,
+                    parallel_copy,
+                    ..IngestOptions::default()
+                })
+                .build()
+        };
+        db.write_queries
+            .insert_geojson(&fixture, Some(load()))
+            .await
+            .expect("insert_geojson");
+        // The same names again, which the unique name column refuses
+        let error = db
+            .write_queries
+            .insert_geojson(&fixture, Some(load()))
+            .await
+            .expect_err("duplicate names");
+        assert!(
+            matches!(error.source, PgcError::Query { .. }),
+            "{}: {:?}",
+            table,
+            error.source
+        );
+        assert_eq!(ErrorKind::of(&error), ErrorKind::Sql, "{}", table);
+    }
+}
+
+#[tokio::test]
+async fn a_failed_replace_leaves_the_table_as_it_was() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let replace = || {
+        InsertGeoJsonOptions::new()
+            .table("points")
+            .mode(LoadMode::Replace)
+            .name_from_property("name")
+            .indexes(true, true)
+            .build()
+    };
+    db.write_queries
+        .insert_geojson(&common::fixture("points.geojson"), Some(replace()))
+        .await
+        .expect("insert_geojson");
+
+    let error = db
+        .write_queries
+        .insert_geojson(&common::fixture("missing.geojson"), Some(replace()))
+        .await
+        .expect_err("a missing file");
+    assert_eq!(ErrorKind::of(&error), ErrorKind::InvalidInput, "{}", error);
+    // Cut in its second feature, after the first was loaded
+    let cut = std::env::temp_dir().join(format!("{}_cut.geojson", db.config.db_name));
+    let collection = std::fs::read(common::fixture("more_points.geojson")).expect("read");
+    std::fs::write(&cut, &collection[..collection.len() / 2]).expect("write");
+    db.write_queries
+        .insert_geojson(cut.to_str().unwrap(), Some(replace()))
+        .await
+        .expect_err("a truncated file");
+    std::fs::remove_file(cut).ok();
+
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+    let tables = db
+        .read_queries
+        .list_tables(None)
+        .await
+        .expect("list_tables");
+    assert!(
+        !tables.iter().any(|table| table.starts_with("_pgc")),
+        "{:?}",
+        tables
+    );
+
+    // A replace that succeeds swaps the table, with its indexes
+    for fixture in ["more_points.geojson", "points.geojson"] {
+        db.write_queries
+            .insert_geojson(&common::fixture(fixture), Some(replace()))
+            .await
+            .expect("insert_geojson");
+    }
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+    let rows = db
+        .read_queries
+        .execute(
+            "SELECT count(*) FROM pg_indexes WHERE tablename = 'points' AND indexname LIKE 'points_%_idx'"
+                .to_string(),
+        )
+        .await
+        .expect("execute");
+    assert_eq!(rows[0].get::<_, i64>(0), 2);
+}
+
+#[tokio::test]
+async fn a_swap_leaves_the_other_tables_alone() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    db.write_queries
+        .execute(
+            "CREATE TABLE points_old AS SELECT 1 AS kept".to_string(),
+            None,
+            None,
+        )
+        .await
+        .expect("execute");
+    for fixture in ["points.geojson", "more_points.geojson"] {
+        db.write_queries
+            .insert_geojson(
+                &common::fixture(fixture),
+                Some(
+                    InsertGeoJsonOptions::new()
+                        .table("points")
+                        .mode(LoadMode::Replace)
+                        .build(),
+                ),
+            )
+            .await
+            .expect("insert_geojson");
+    }
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points_old", None)
+            .await
+            .expect("table_row_count"),
+        1
+    );
+    let tables = db
+        .read_queries
+        .list_tables(None)
+        .await
+        .expect("list_tables");
+    assert!(
+        !tables.iter().any(|table| table.starts_with("_pgc")),
+        "{:?}",
+        tables
+    );
+
+    let long_name = "p".repeat(64);
+    let error = db
+        .write_queries
+        .atomic_swap("points", &long_name)
+        .await
+        .expect_err("a name Postgres truncates");
+    assert_eq!(error.kind(), ErrorKind::InvalidInput, "{}", error);
+}
+
+#[tokio::test]
+async fn replace_loads_stage_in_tables_of_their_own() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    for statement in [
+        "CREATE TABLE points_staging AS SELECT 1 AS kept",
+        "CREATE VIEW points_staging_view AS SELECT * FROM points_staging",
+    ] {
+        db.write_queries
+            .execute(statement.to_string(), None, None)
+            .await
+            .expect("execute");
+    }
+    let fixture = common::fixture("points.geojson");
+    let replace = || {
+        let options = InsertGeoJsonOptions::new()
+            .table("points")
+            .mode(LoadMode::Replace)
+            .build();
+        db.write_queries.insert_geojson(&fixture, Some(options))
+    };
+    let (first, second) = tokio::join!(replace(), replace());
+    first.expect("the first insert_geojson");
+    second.expect("the second insert_geojson");
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points_staging_view", None)
+            .await
+            .expect("table_row_count"),
+        1
+    );
+
+    // Refused before anything is loaded
+    let long_name = "p".repeat(64);
+    let error = db
+        .write_queries
+        .insert_geojson(
+            &fixture,
+            Some(InsertGeoJsonOptions::new().table(&long_name).build()),
+        )
+        .await
+        .expect_err("a name Postgres truncates");
+    assert_eq!(ErrorKind::of(&error), ErrorKind::Usage, "{}", error);
+    assert!(error.report.files.is_empty());
+}
+
+#[tokio::test]
+async fn audit_log_records_failed_and_succeeded_operations() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let path = std::env::temp_dir().join(format!("{}_audit.jsonl", db.config.db_name));
+    let write_queries = db
+        .write_queries
+        .clone()
+        .with_audit_log(Arc::new(AuditLog::new(
+            &path.display().to_string(),
+            &db.config,
+        )));
+    write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(InsertGeoJsonOptions::new().table("points").build()),
+        )
+        .await
+        .expect("insert_geojson");
+    write_queries
+        .truncate("missing", false, false)
+        .await
+        .expect_err("a missing table");
+    write_queries.drop("points").await.expect("drop");
+
+    let entries: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+        .expect("the audit log")
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("a JSON line"))
+        .collect();
+    let _ = std::fs::remove_file(&path);
+    let summary: Vec<(&str, &str, &str)> = entries
+        .iter()
+        .map(|entry| {
+            (
+                entry["operation"].as_str().unwrap_or_default(),
+                entry["target"].as_str().unwrap_or_default(),
+                entry["outcome"].as_str().unwrap_or_default(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (
+                "insert_geojson",
+                common::fixture("points.geojson").as_str(),
+                "succeeded"
+            ),
+            ("truncate", "missing", "failed"),
+            ("drop", "points", "succeeded"),
+        ]
+    );
+    assert_eq!(entries[0]["rows"], 3);
+    assert_eq!(entries[1]["user"], db.config.user.as_str());
+}
+
+#[tokio::test]
+async fn dialect_is_detected_and_gates_unsupported_operations() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    assert_eq!(
+        db.read_queries.dialect().await.expect("dialect"),
+        Dialect::Postgres
+    );
+    let cockroach = db.write_queries.clone().with_dialect(Dialect::Cockroach);
+    let error = cockroach
+        .vacuum("parcels", false, true)
+        .await
+        .expect_err("no VACUUM on CockroachDB");
+    assert!(
+        matches!(
+            &error,
+            PgcError::UnsupportedOnDialect {
+                dialect: Dialect::Cockroach,
+                ..
+            }
+        ),
+        "{}",
+        error
+    );
+    assert_eq!(error.kind(), ErrorKind::Usage);
+    assert_eq!(
+        error.to_string(),
+        "❌ vacuum is not supported on CockroachDB"
+    );
+}
+
+/// Keeps the events it is told of.
+#[derive(Default)]
+struct RecordingObserver {
+    events: Mutex<Vec<ObserverEvent>>,
+}
+
+impl ProgressObserver for RecordingObserver {
+    fn on_event(&self, event: &ObserverEvent) {
+        self.events.lock().unwrap().push(event.clone());
+    }
+}
+
+#[tokio::test]
+async fn insert_geojson_tells_its_observer_how_far_it_is() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let observer = Arc::new(RecordingObserver::default());
+    db.write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(
+                InsertGeoJsonOptions::new()
+                    .table("points")
+                    .observer(observer.clone())
+                    .build(),
+            ),
+        )
+        .await
+        .expect("insert_geojson");
+
+    // Called on a thread of its own, done once told of the end
+    let mut events = Vec::new();
+    for _ in 0..100 {
+        events = observer.events.lock().unwrap().clone();
+        if matches!(events.last(), Some(ObserverEvent::Completed { .. })) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(
+        matches!(&events[0], ObserverEvent::FileStarted { path, bytes: Some(_) } if path.ends_with("points.geojson")),
+        "{:?}",
+        events
+    );
+    let processed: Vec<u64> = events
+        .iter()
+        .filter_map(|event| match event {
+            ObserverEvent::FeaturesProcessed { count, .. } => Some(*count),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(processed.last(), Some(&3), "{:?}", events);
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, ObserverEvent::CopyFinished { rows: Some(3), .. })));
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, ObserverEvent::IndexingStarted { .. })));
+    match events.last() {
+        Some(ObserverEvent::Completed {
+            report: OperationReport::Ingest(report),
+        }) => assert_eq!(report.files[0].features_loaded, 3),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn dry_run_plans_the_statements_without_changing_anything() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    db.write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(InsertGeoJsonOptions::new().table("points").build()),
+        )
+        .await
+        .expect("insert_geojson");
+    let tables_before = db
+        .read_queries
+        .list_tables(None)
+        .await
+        .expect("list_tables");
+
+    let dry_run = Arc::new(DryRun::new());
+    let planner = db.write_queries.clone().with_dry_run(dry_run.clone());
+    planner.drop("points").await.expect("drop");
+    let outcomes = planner
+        .drop_many(&["points", "missing"], true, true)
+        .await
+        .expect("drop_many");
+    assert_eq!(
+        outcomes,
+        vec![
+            ("points".to_string(), DropOutcome::Planned),
+            ("missing".to_string(), DropOutcome::Missing),
+        ]
+    );
+    planner
+        .truncate("points", true, false)
+        .await
+        .expect("truncate");
+    planner
+        .rename_table("points", "renamed")
+        .await
+        .expect("rename_table");
+    let filter = FeatureFilter::Properties(serde_json::json!({"value": 1}));
+    let deleted = planner
+        .delete_features("points", &filter, false)
+        .await
+        .expect("delete_features");
+    assert_eq!(deleted, 1);
+    let updated = planner
+        .update_properties(
+            "points",
+            &filter,
+            serde_json::json!({"value": 10}),
+            PatchMode::Merge,
+            false,
+        )
+        .await
+        .expect("update_properties");
+    assert_eq!(updated, 1);
+    let report = planner
+        .insert_geojson(
+            &common::fixture("more_points.geojson"),
+            Some(InsertGeoJsonOptions::new().table("planned").build()),
+        )
+        .await
+        .expect("insert_geojson");
+    assert_eq!(report.files[0].rows_written, 0);
+
+    let statements = dry_run.statements();
+    // The drop, then the drop of the existing tables of the two
+    assert_eq!(statements[0], "DROP TABLE IF EXISTS \"points\" CASCADE");
+    assert_eq!(statements[1], statements[0]);
+    for planned in [
+        "TRUNCATE TABLE \"points\" RESTART IDENTITY",
+        "ALTER TABLE \"points\" RENAME TO \"renamed\"",
+        "DELETE FROM \"points\" WHERE properties @> $1 -- filter=",
+        "UPDATE \"points\" SET properties = properties || $2",
+        "CREATE TABLE IF NOT EXISTS \"planned\"",
+        "COPY \"planned\" (name, properties, geometry) FROM STDIN",
+        "ANALYZE \"planned\"",
+    ] {
+        assert!(
+            statements
+                .iter()
+                .any(|statement| statement.starts_with(planned)),
+            "{} in {:#?}",
+            planned,
+            statements
+        );
+    }
+
+    assert_eq!(
+        db.read_queries
+            .list_tables(None)
+            .await
+            .expect("list_tables"),
+        tables_before
+    );
+    assert_eq!(
+        db.read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+    let rows = db
+        .read_queries
+        .execute("SELECT count(*) FROM points WHERE properties->>'value' = '1'".to_string())
+        .await
+        .expect("execute");
+    assert_eq!(rows[0].get::<_, i64>(0), 1);
+}
+
+#[tokio::test]
+async fn dry_run_plans_the_maintenance_and_replace_statements() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let table_options = GeoTableOptions {
+        with_deleted_at: true,
+        ..GeoTableOptions::default()
+    };
+    db.write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(
+                InsertGeoJsonOptions::new()
+                    .table("points")
+                    .name_from_property("name")
+                    .table_options(table_options)
+                    .build(),
+            ),
+        )
+        .await
+        .expect("insert_geojson");
+    let tables_before = db
+        .read_queries
+        .list_tables(None)
+        .await
+        .expect("list_tables");
+    let columns_before = db
+        .read_queries
+        .list_columns("points")
+        .await
+        .expect("list_columns")
+        .len();
+
+    let dry_run = Arc::new(DryRun::new());
+    let planner = db.write_queries.clone().with_dry_run(dry_run.clone());
+    planner
+        .create_schema("planned_schema", None)
+        .await
+        .expect("create_schema");
+    planner
+        .drop_schema("public", true)
+        .await
+        .expect("drop_schema");
+    planner
+        .atomic_swap("points", "swapped")
+        .await
+        .expect("atomic_swap");
+    let filter = FeatureFilter::Names(vec!["alpha".to_string()]);
+    let marked = planner
+        .soft_delete_features("points", &filter)
+        .await
+        .expect("soft_delete_features");
+    assert_eq!(marked, 1);
+    planner
+        .purge_deleted("points", Duration::from_secs(60))
+        .await
+        .expect("purge_deleted");
+    planner
+        .grant_on_table("points", "public", "SELECT")
+        .await
+        .expect("grant_on_table");
+    let materialized = planner
+        .materialize_properties(
+            "points",
+            vec![("value".to_string(), "value".to_string(), PgType::Integer)],
+            true,
+        )
+        .await
+        .expect("materialize_properties");
+    assert_eq!(materialized[0].rows_updated, 3);
+    let simplified = planner
+        .create_simplified_table("points", "points_simple", 0.1)
+        .await
+        .expect("create_simplified_table");
+    assert_eq!(simplified.rows_written, 3);
+    let overviews = planner
+        .create_overview_tables("points", &[0.1])
+        .await
+        .expect("create_overview_tables");
+    assert_eq!(overviews, vec!["points_z0".to_string()]);
+    let backfilled = planner
+        .add_derived_geometry_columns("points")
+        .await
+        .expect("add_derived_geometry_columns");
+    assert_eq!(backfilled, 3);
+    planner.vacuum("points", false, true).await.expect("vacuum");
+    planner
+        .insert_geojson(
+            &common::fixture("more_points.geojson"),
+            Some(
+                InsertGeoJsonOptions::new()
+                    .table("points")
+                    .mode(LoadMode::Replace)
+                    .build(),
+            ),
+        )
+        .await
+        .expect("insert_geojson");
+
+    let statements = dry_run.statements();
+    for planned in [
+        "CREATE SCHEMA IF NOT EXISTS \"planned_schema\"",
+        "DROP SCHEMA IF EXISTS \"public\" CASCADE",
+        "ALTER TABLE \"points\" RENAME TO \"swapped\"",
+        "UPDATE \"points\" SET deleted_at = NOW()",
+        "DELETE FROM \"points\" WHERE deleted_at <",
+        "GRANT SELECT ON TABLE \"points\" TO PUBLIC",
+        "ALTER TABLE \"points\" ADD COLUMN IF NOT EXISTS \"value\" INTEGER",
+        "CREATE INDEX IF NOT EXISTS \"points_value_idx\"",
+        "CREATE TABLE \"points_simple\" (LIKE \"points\" INCLUDING ALL)",
+        "INSERT INTO \"points_simple\"",
+        "CREATE MATERIALIZED VIEW IF NOT EXISTS \"points_z0\"",
+        "ALTER TABLE \"points\"\n",
+        "VACUUM (ANALYZE) \"points\"",
+        "COPY \"_pgc_replace_",
+        "ALTER TABLE \"_pgc_replace_",
+    ] {
+        assert!(
+            statements
+                .iter()
+                .any(|statement| statement.starts_with(planned)),
+            "{} in {:#?}",
+            planned,
+            statements
+        );
+    }
+
+    assert_eq!(
+        db.read_queries
+            .list_tables(None)
+            .await
+            .expect("list_tables"),
+        tables_before
+    );
+    assert_eq!(
+        db.read_queries
+            .list_columns("points")
+            .await
+            .expect("list_columns")
+            .len(),
+        columns_before
+    );
+    let rows = db
+        .read_queries
+        .execute("SELECT count(*) FROM points WHERE deleted_at IS NULL".to_string())
+        .await
+        .expect("execute");
+    assert_eq!(rows[0].get::<_, i64>(0), 3);
+}
+
+#[tokio::test]
+async fn copy_round_trips_line_breaks_unicode_and_null_strings() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let fixture = common::fixture("tricky_text.geojson");
+    let features = parse_geojson_features(&fixture).expect("parse_geojson_features");
+    // Straight into the table, and through the staging tables of a parallel COPY
+    for (table, parallel_copy) in [("tricky", 1), ("tricky_staged", 2)] {
+        let options = InsertGeoJsonOptions::new()
+            .table(table)
+            .ingest_options(IngestOptions {
+                parallel_copy,
+                ..IngestOptions::default()
+            })
+            .name_from_property("name")
+            .build();
+        db.write_queries
+            .insert_geojson(&fixture, Some(options))
+            .await
+            .expect("insert_geojson");
+
+        let rows = db
+            .read_queries
+            .execute(format!(
+                "SELECT name, properties, geometry IS NULL FROM {} ORDER BY created_at, name",
+                table
+            ))
+            .await
+            .expect("execute");
+        assert_eq!(rows.len(), features.len(), "{}", table);
+        for feature in &features {
+            let properties = serde_json::Value::Object(feature.properties.clone().unwrap());
+            let name = properties["name"].as_str().unwrap();
+            let row = rows
+                .iter()
+                .find(|row| row.get::<_, String>(0) == name)
+                .unwrap_or_else(|| panic!("{:?} in {}", name, table));
+            assert_eq!(row.get::<_, serde_json::Value>(1), properties, "{}", table);
+            assert_eq!(
+                row.get::<_, bool>(2),
+                feature.geometry.is_none(),
+                "{:?}",
+                name
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn parallel_copies_at_the_same_time_keep_their_own_staging_tables() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let fixture = common::fixture("points.geojson");
+    let load = |table: &'static str| {
+        let options = InsertGeoJsonOptions::new()
+            .table(table)
+            .ingest_options(IngestOptions {
+                parallel_copy: 2,
+                ..IngestOptions::default()
+            })
+            .build();
+        db.write_queries.insert_geojson(&fixture, Some(options))
+    };
+    let (first, second) = tokio::join!(load("points_first"), load("points_second"));
+    first.expect("the first insert_geojson");
+    second.expect("the second insert_geojson");
+
+    for table in ["points_first", "points_second"] {
+        assert_eq!(
+            db.read_queries
+                .table_row_count(table, None)
+                .await
+                .expect("table_row_count"),
+            3,
+            "{}",
+            table
+        );
+    }
+    let tables = db
+        .read_queries
+        .list_tables(None)
+        .await
+        .expect("list_tables");
+    assert!(
+        !tables.iter().any(|table| table.starts_with("_pgc")),
+        "{:?}",
+        tables
+    );
+}
+
+/// A FeatureCollection of `count` polygons with 15 decimals, each vertex of
+/// their rings followed by one a nanometer away, which rounding to 6
+/// decimals collapses.
+fn precise_polygons(count: usize, vertices: usize) -> String {
+    // Away from the halfway points of 6 decimals, so both round the same way
+    let on_grid = |value: f64| (value * 1e6).round() / 1e6;
+    let features: Vec<String> = (0..count)
+        .map(|i| {
+            let mut ring = Vec::new();
+            for v in 0..vertices {
+                let angle = std::f64::consts::TAU * v as f64 / vertices as f64;
+                let x = on_grid(13.0 + i as f64 * 0.01 + 0.001 * angle.cos()) + 1.234567890123e-7;
+                let y = on_grid(52.0 + 0.001 * angle.sin()) + 9.876543210987e-8;
+                ring.push(format!("[{:.15}, {:.15}]", x, y));
+                ring.push(format!("[{:.15}, {:.15}]", x + 1e-9, y - 1e-9));
+            }
+            ring.push(ring[0].clone());
+            format!(
+                concat!(
+                    r#"{{"type": "Feature", "properties": {{"name": "p{}"}}, "#,
+                    r#""geometry": {{"type": "Polygon", "coordinates": [[{}]]}}}}"#
+                ),
+                i,
+                ring.join(", ")
+            )
+        })
+        .collect();
+    format!(
+        r#"{{"type": "FeatureCollection", "features": [{}]}}"#,
+        features.join(",\n")
+    )
+}
+
+/// The positions of the single ring of each polygon of `table`, as
+/// `ST_AsText` writes them.
+async fn ring_positions(db: &TestDatabase, table: &str) -> Vec<Vec<String>> {
+    db.read_queries
+        .execute(format!(
+            "SELECT ST_AsText(geometry) FROM {} ORDER BY name",
+            table
+        ))
+        .await
+        .expect("read the polygons")
+        .iter()
+        .map(|row| {
+            let wkt: String = row.get(0);
+            let start = wkt.find("((").expect("a polygon") + 2;
+            let end = wkt.rfind("))").expect("a polygon");
+            wkt[start..end]
+                .split(',')
+                .map(|position| position.trim().to_string())
+                .collect()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn rounding_coordinates_shrinks_the_table_and_drops_the_collapsed_vertices() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    // Small enough for the geometries not to be compressed
+    let (count, vertices) = (400, 20);
+    let path = std::env::temp_dir().join(format!("{}.geojson", common::unique_name("pgc_precise")));
+    std::fs::write(&path, precise_polygons(count, vertices)).expect("write the polygons");
+    for (table, coordinate_precision) in [("precise", None), ("rounded", Some(6))] {
+        let options = InsertGeoJsonOptions::new()
+            .table(table)
+            .ingest_options(IngestOptions {
+                coordinate_precision,
+                ..Default::default()
+            })
+            .build();
+        db.write_queries
+            .insert_geojson(path.to_str().unwrap(), Some(options))
+            .await
+            .expect("insert_geojson");
+    }
+    std::fs::remove_file(&path).ok();
+
+    let sizes = db
+        .read_queries
+        .execute(
+            "SELECT (SELECT sum(pg_column_size(geometry)) FROM precise)::bigint, \
+                    (SELECT sum(pg_column_size(geometry)) FROM rounded)::bigint, \
+                    pg_total_relation_size('precise'), pg_total_relation_size('rounded')"
+                .to_string(),
+        )
+        .await
+        .expect("read the sizes");
+    let (precise, rounded): (i64, i64) = (sizes[0].get(0), sizes[0].get(1));
+    assert!(
+        rounded < precise,
+        "{} bytes of geometry, {} rounded",
+        precise,
+        rounded
+    );
+    let (precise, rounded): (i64, i64) = (sizes[0].get(2), sizes[0].get(3));
+    assert!(
+        rounded < precise,
+        "{} bytes on disk, {} rounded",
+        precise,
+        rounded
+    );
+
+    let precise = ring_positions(&db, "precise").await;
+    let rounded = ring_positions(&db, "rounded").await;
+    assert_eq!((precise.len(), rounded.len()), (count, count));
+    for (precise, rounded) in precise.iter().zip(&rounded) {
+        assert_eq!(precise.len(), 2 * vertices + 1);
+        // One of each pair kept, the ring still closed and its vertices distinct
+        assert_eq!(rounded.len(), vertices + 1, "{:?}", rounded);
+        assert_eq!(rounded.first(), rounded.last());
+        let distinct: std::collections::HashSet<&String> = rounded.iter().collect();
+        assert_eq!(distinct.len(), vertices, "{:?}", rounded);
+        for position in rounded {
+            for number in position.split_whitespace() {
+                let decimals = number.split_once('.').map_or(0, |(_, d)| d.len());
+                assert!(decimals <= 6, "{}", position);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn a_bbox_filter_is_built_in_the_srid_of_the_table() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    db.write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(
+                InsertGeoJsonOptions::new()
+                    .table("points")
+                    .srid(4326)
+                    .build(),
+            ),
+        )
+        .await
+        .expect("insert_geojson");
+    let dry_run = Arc::new(DryRun::new());
+    let planner = db.write_queries.clone().with_dry_run(dry_run.clone());
+    // Around Berlin
+    let filter = FeatureFilter::BBox {
+        min_x: 13.0,
+        min_y: 52.0,
+        max_x: 14.0,
+        max_y: 53.0,
+    };
+    planner
+        .delete_features("points", &filter, false)
+        .await
+        .expect("delete_features");
+    let statements = dry_run.take();
+    assert_eq!(statements.len(), 1, "{:?}", statements);
+    // A constant SRID, not the `ST_SRID(geometry)` of every row
+    assert!(
+        statements[0].contains("geometry && ST_MakeEnvelope($1, $2, $3, $4, 4326)"),
+        "{}",
+        statements[0]
+    );
+}
+
+#[tokio::test]
+async fn queries_built_on_one_pool_share_it() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let pool = pool_of(&db.config).expect("pool_of");
+    let write_queries = PostgresQueriesWrite::new(pool.clone());
+    let read_queries = PostgresQueriesRead::new(pool.clone());
+    write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(InsertGeoJsonOptions::new().table("points").build()),
+        )
+        .await
+        .expect("insert_geojson on an injected pool");
+    assert_eq!(
+        read_queries
+            .table_row_count("points", None)
+            .await
+            .expect("table_row_count"),
+        3
+    );
+    assert!(pool.status().size > 0, "{:?}", pool.status());
+    assert!(write_queries.config_file().is_none());
+
+    // Without settings, vacuum goes through the pool, and what needs a
+    // connection of its own fails instead of guessing one
+    write_queries
+        .vacuum("points", false, true)
+        .await
+        .expect("vacuum through the pool");
+    let error = write_queries
+        .fix_collation_version(None)
+        .await
+        .expect_err("no settings to name the database");
+    assert_eq!(error.kind(), ErrorKind::Usage, "{}", error);
+
+    db.write_queries
+        .vacuum("points", true, true)
+        .await
+        .expect("vacuum on a connection of its own");
+    pool.close();
+}
+
+/// The rows of `table` as text, in order, and its indexes: what a lossless
+/// restore brings back as it was.
+#[cfg(feature = "backup")]
+async fn table_snapshot(db: &TestDatabase, table: &str) -> (Vec<String>, Vec<String>) {
+    let rows = db
+        .read_queries
+        .execute(format!("SELECT t::text FROM {} t ORDER BY t::text", table))
+        .await
+        .expect("read the rows");
+    let indexes = db
+        .read_queries
+        .execute(format!(
+            "SELECT indexdef FROM pg_indexes WHERE tablename = '{}' ORDER BY indexdef",
+            table
+        ))
+        .await
+        .expect("read the indexes");
+    (
+        rows.iter().map(|row| row.get(0)).collect(),
+        indexes.iter().map(|row| row.get(0)).collect(),
+    )
+}
+
+#[cfg(feature = "backup")]
+#[tokio::test]
+async fn a_logical_backup_restores_the_tables_as_they_were() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    for (fixture, table) in [
+        ("tricky_text.geojson", "tricky"),
+        ("shapes.geojson", "shapes"),
+    ] {
+        db.write_queries
+            .insert_geojson(
+                &common::fixture(fixture),
+                Some(
+                    InsertGeoJsonOptions::new()
+                        .table(table)
+                        .name_from_property("name")
+                        .build(),
+                ),
+            )
+            .await
+            .expect("insert_geojson");
+    }
+    // With the table recording the imports
+    let tables = ["_imports", "shapes", "tricky"];
+    let mut before = Vec::new();
+    for table in tables {
+        before.push(table_snapshot(&db, table).await);
+    }
+
+    let dir = std::env::temp_dir().join(common::unique_name("pgc_logical"));
+    std::fs::create_dir_all(&dir).expect("create the backup directory");
+    let backup = db
+        .write_queries
+        .logical_backup(dir.to_str().unwrap())
+        .await
+        .expect("logical_backup");
+    let mut backed_up: Vec<(&str, u64)> = backup
+        .tables
+        .iter()
+        .map(|table| (table.name.as_str(), table.rows))
+        .collect();
+    backed_up.sort();
+    let expected: Vec<(&str, u64)> = tables
+        .iter()
+        .zip(&before)
+        .map(|(table, (rows, _))| (*table, rows.len() as u64))
+        .collect();
+    assert_eq!(backed_up, expected);
+
+    db.write_queries
+        .drop_many(&tables, false, false)
+        .await
+        .expect("drop_many");
+    let restored = db
+        .write_queries
+        .logical_restore(&backup.path)
+        .await
+        .expect("logical_restore");
+    assert_eq!(restored.tables.len(), tables.len());
+    let mut after = Vec::new();
+    for table in tables {
+        after.push(table_snapshot(&db, table).await);
+    }
+    assert_eq!(after, before);
+
+    // A table already there is not restored over
+    let error = db
+        .write_queries
+        .logical_restore(&backup.path)
+        .await
+        .expect_err("the tables exist");
+    assert_eq!(table_snapshot(&db, "tricky").await, before[2], "{}", error);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[cfg(feature = "backup")]
+#[tokio::test]
+async fn restore_database_restores_only_the_tables_asked_for() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    for (fixture, table) in [
+        ("points.geojson", "points"),
+        ("more_points.geojson", "more_points"),
+        ("shapes.geojson", "shapes"),
+    ] {
+        db.write_queries
+            .insert_geojson(
+                &common::fixture(fixture),
+                Some(InsertGeoJsonOptions::new().table(table).build()),
+            )
+            .await
+            .expect("insert_geojson");
+    }
+    let row_count = |table: &'static str| {
+        let read_queries = &db.read_queries;
+        async move {
+            read_queries
+                .table_row_count(table, None)
+                .await
+                .expect("table_row_count")
+        }
+    };
+    let (points, more_points) = (row_count("points").await, row_count("more_points").await);
+
+    let dir = std::env::temp_dir().join(common::unique_name("pgc_restore"));
+    std::fs::create_dir_all(&dir).expect("create the backup directory");
+    let dump_file = db
+        .write_queries
+        .backup_database(
+            dir.to_str().unwrap(),
+            Some(BackupOptions {
+                jobs: 1,
+                ..BackupOptions::default()
+            }),
+        )
+        .await
+        .expect("backup_database");
+
+    // Changed after the backup: only points is to be put back
+    db.write_queries
+        .execute("DELETE FROM points".to_string(), None, None)
+        .await
+        .expect("delete the points");
+    db.write_queries
+        .execute("DELETE FROM more_points".to_string(), None, None)
+        .await
+        .expect("delete the more points");
+    db.write_queries.drop("shapes").await.expect("drop shapes");
+
+    db.write_queries
+        .restore_database(
+            &dump_file,
+            Some(RestoreOptions {
+                jobs: 1,
+                tables: Some(vec!["points".to_string()]),
+                assume_yes: true,
+                ..RestoreOptions::default()
+            }),
+        )
+        .await
+        .expect("restore_database of points");
+    assert_eq!(row_count("points").await, points);
+    // Analyzed once restored
+    let statistics = db
+        .read_queries
+        .execute("SELECT count(*) FROM pg_stats WHERE tablename = 'points'".to_string())
+        .await
+        .expect("read the statistics");
+    assert!(statistics[0].get::<_, i64>(0) > 0);
+    assert_eq!(row_count("more_points").await, 0);
+    assert!(more_points > 0);
+    let tables = db
+        .read_queries
+        .list_tables(None)
+        .await
+        .expect("list_tables");
+    assert!(!tables.contains(&"shapes".to_string()), "{:?}", tables);
+
+    let error = db
+        .write_queries
+        .restore_database(
+            &dump_file,
+            Some(RestoreOptions {
+                jobs: 1,
+                tables: Some(vec!["no_such_table".to_string()]),
+                assume_yes: true,
+                ..RestoreOptions::default()
+            }),
+        )
+        .await
+        .expect_err("a table the dump does not hold");
+    assert_eq!(error.kind(), ErrorKind::Usage, "{}", error);
+    assert_eq!(row_count("points").await, points);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[cfg(feature = "backup")]
+#[tokio::test]
+async fn restore_database_drops_the_tables_of_the_dump_in_their_schema() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    for statement in [
+        "CREATE SCHEMA archive",
+        "CREATE TABLE archive.visits (id INTEGER PRIMARY KEY)",
+        "INSERT INTO archive.visits VALUES (1), (2)",
+    ] {
+        db.write_queries
+            .execute(statement.to_string(), None, None)
+            .await
+            .expect("execute");
+    }
+    let dir = std::env::temp_dir().join(common::unique_name("pgc_restore"));
+    std::fs::create_dir_all(&dir).expect("create the backup directory");
+    let dump_file = db
+        .write_queries
+        .backup_database(
+            dir.to_str().unwrap(),
+            Some(BackupOptions {
+                jobs: 1,
+                ..BackupOptions::default()
+            }),
+        )
+        .await
+        .expect("backup_database");
+    // Made since, pg_restore --clean cannot drop the table under it
+    db.write_queries
+        .execute(
+            "CREATE VIEW archive.recent_visits AS SELECT * FROM archive.visits".to_string(),
+            None,
+            None,
+        )
+        .await
+        .expect("create the view");
+
+    // The table is dropped first with the view, in its own schema
+    db.write_queries
+        .restore_database(
+            &dump_file,
+            Some(RestoreOptions {
+                jobs: 1,
+                assume_yes: true,
+                ..RestoreOptions::default()
+            }),
+        )
+        .await
+        .expect("restore_database");
+    let rows = db
+        .read_queries
+        .execute("SELECT count(*) FROM archive.visits".to_string())
+        .await
+        .expect("execute");
+    assert_eq!(rows[0].get::<_, i64>(0), 2);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+/// Keeps what the queries report, instead of showing it.
+#[cfg(feature = "backup")]
+#[derive(Default)]
+struct CapturingReporter {
+    messages: Mutex<Vec<String>>,
+    events: Mutex<Vec<ProgressEvent>>,
+}
+
+#[cfg(feature = "backup")]
+impl Reporter for CapturingReporter {
+    fn info(&self, message: &str) {
+        self.messages.lock().unwrap().push(message.to_string());
+    }
+    fn warn(&self, message: &str) {
+        self.messages.lock().unwrap().push(message.to_string());
+    }
+    fn table(&self, headers: &[&str], _rows: &[Vec<String>]) {
+        self.messages.lock().unwrap().push(headers.join(" "));
+    }
+    fn progress(&self, event: ProgressEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+#[cfg(feature = "backup")]
+#[tokio::test]
+async fn restore_database_reports_its_progress_to_its_reporter() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    db.write_queries
+        .insert_geojson(
+            &common::fixture("points.geojson"),
+            Some(InsertGeoJsonOptions::new().table("points").build()),
+        )
+        .await
+        .expect("insert_geojson");
+    let dir = std::env::temp_dir().join(common::unique_name("pgc_reporter"));
+    std::fs::create_dir_all(&dir).expect("create the backup directory");
+    let dump_file = db
+        .write_queries
+        .backup_database(
+            dir.to_str().unwrap(),
+            Some(BackupOptions {
+                jobs: 1,
+                ..BackupOptions::default()
+            }),
+        )
+        .await
+        .expect("backup_database");
+
+    let reporter = Arc::new(CapturingReporter::default());
+    db.write_queries
+        .clone()
+        .with_reporter(reporter.clone())
+        .restore_database(
+            &dump_file,
+            Some(RestoreOptions {
+                jobs: 1,
+                assume_yes: true,
+                ..RestoreOptions::default()
+            }),
+        )
+        .await
+        .expect("restore_database");
+    let events = reporter.events.lock().unwrap().clone();
+    for phase in ["schema", "data"] {
+        let of_phase: Vec<&ProgressEvent> = events
+            .iter()
+            .filter(|event| match event {
+                ProgressEvent::Started { phase: p, .. }
+                | ProgressEvent::Advanced { phase: p, .. }
+                | ProgressEvent::Message { phase: p, .. }
+                | ProgressEvent::Finished { phase: p } => p == phase,
+            })
+            .collect();
+        let started = of_phase.first().copied();
+        assert!(
+            matches!(started, Some(ProgressEvent::Started { total: Some(n), .. }) if *n > 0),
+            "{}: {:?}",
+            phase,
+            of_phase
+        );
+        assert!(
+            matches!(of_phase.last(), Some(ProgressEvent::Finished { .. })),
+            "{}: {:?}",
+            phase,
+            of_phase
+        );
+    }
+    assert!(
+        events.contains(&ProgressEvent::Message {
+            phase: "data".to_string(),
+            message: "TABLE DATA points".to_string(),
+        }),
+        "{:?}",
+        events
+    );
+    // Not interactive, nothing was asked
+    assert!(reporter.messages.lock().unwrap().is_empty());
+    std::fs::remove_dir_all(dir).ok();
+}
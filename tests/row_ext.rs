@@ -0,0 +1,61 @@
+//! Reading rows with `row_ext`, see `common` for running them.
+
+mod common;
+
+use common::TestDatabase;
+use postgres_connect_rust::{rows_to_structs, DatabaseQueriesRead, ErrorKind, RowExt};
+
+#[derive(Debug, PartialEq, serde::Deserialize)]
+struct Parcel {
+    name: String,
+    area: Option<f64>,
+    tags: Vec<String>,
+    properties: serde_json::Value,
+}
+
+#[tokio::test]
+async fn rows_convert_to_structs_or_fail_naming_the_column() {
+    if !common::enabled() {
+        return;
+    }
+    let db = TestDatabase::start().await;
+    let rows = db
+        .read_queries
+        .execute(
+            "SELECT 'north' AS name, 12.5::float8 AS area, ARRAY['a', 'b'] AS tags,
+                '{\"zone\": 3}'::jsonb AS properties
+            UNION ALL SELECT 'south', NULL, ARRAY[]::text[], '{}'::jsonb"
+                .to_string(),
+        )
+        .await
+        .expect("execute");
+
+    let parcels: Vec<Parcel> = rows_to_structs(&rows).expect("rows_to_structs");
+    assert_eq!(
+        parcels[0],
+        Parcel {
+            name: "north".to_string(),
+            area: Some(12.5),
+            tags: vec!["a".to_string(), "b".to_string()],
+            properties: serde_json::json!({"zone": 3}),
+        }
+    );
+    assert_eq!(parcels[1].area, None);
+    assert_eq!(rows[0].try_get_string("name").expect("name"), "north");
+
+    let error = rows[0].try_get_i64("area").expect_err("a float8 column");
+    assert_eq!(error.kind(), ErrorKind::Sql);
+    let message = error.to_string();
+    assert!(
+        message.contains("area") && message.contains("float8"),
+        "{}",
+        message
+    );
+    assert!(message.contains("i64"), "{}", message);
+    let error = rows[1].try_get_f64("area").expect_err("a NULL");
+    assert!(error.to_string().contains("area"), "{}", error);
+    let error = rows[0]
+        .try_get_string("missing")
+        .expect_err("no such column");
+    assert!(error.to_string().contains("No column missing"), "{}", error);
+}
@@ -0,0 +1,176 @@
+//! `geometry_to_wkt` against the implementation it replaced, which built
+//! the WKT from nested `Vec<String>`s: the output must not change. No
+//! database is needed.
+
+mod common;
+
+use geojson::{Geometry, Position, Value};
+use postgres_connect_rust::{
+    geometry_to_wkt, geometry_to_wkt_with_precision, parse_geojson_features, write_wkt,
+};
+
+/// The fixtures compared, every geometry type, with numbers that are
+/// negative, tiny, huge, or collapse when rounded.
+const FIXTURES: [&str; 3] = ["points.geojson", "more_points.geojson", "shapes.geojson"];
+
+/// The precisions compared, none first.
+const PRECISIONS: [Option<u8>; 5] = [None, Some(0), Some(3), Some(6), Some(9)];
+
+/// The implementation before it wrote into a buffer, as it was.
+fn previous_wkt(geom: &Geometry, precision: Option<u8>) -> Result<String, String> {
+    let rings = |rings: &[Vec<Position>]| -> Result<String, String> {
+        Ok(rings
+            .iter()
+            .map(|ring| Ok(format!("({})", previous_positions(ring, precision, 4)?)))
+            .collect::<Result<Vec<_>, String>>()?
+            .join(", "))
+    };
+    match geom.value {
+        Value::Point(ref c) => Ok(format!("POINT({})", previous_position(c, precision))),
+        Value::MultiPoint(ref coords) => Ok(format!(
+            "MULTIPOINT({})",
+            coords
+                .iter()
+                .map(|p| format!("({})", previous_position(p, precision)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        Value::LineString(ref coords) => Ok(format!(
+            "LINESTRING({})",
+            previous_positions(coords, precision, 2)?
+        )),
+        Value::MultiLineString(ref lines) => Ok(format!(
+            "MULTILINESTRING({})",
+            lines
+                .iter()
+                .map(|line| Ok(format!("({})", previous_positions(line, precision, 2)?)))
+                .collect::<Result<Vec<_>, String>>()?
+                .join(", ")
+        )),
+        Value::Polygon(ref polygon) => Ok(format!("POLYGON({})", rings(polygon)?)),
+        Value::MultiPolygon(ref multipolygons) => Ok(format!(
+            "MULTIPOLYGON({})",
+            multipolygons
+                .iter()
+                .map(|polygon| Ok(format!("({})", rings(polygon)?)))
+                .collect::<Result<Vec<_>, String>>()?
+                .join(", ")
+        )),
+        _ => Err("Unsupported geometry type".to_string()),
+    }
+}
+
+fn previous_round(value: f64, precision: Option<u8>) -> f64 {
+    match precision {
+        Some(precision) => {
+            let factor = 10f64.powi(precision as i32);
+            let rounded = (value * factor).round() / factor;
+            if rounded == 0.0 {
+                0.0
+            } else {
+                rounded
+            }
+        }
+        None => value,
+    }
+}
+
+fn previous_position(position: &[f64], precision: Option<u8>) -> String {
+    format!(
+        "{} {}",
+        previous_round(position[0], precision),
+        previous_round(position[1], precision)
+    )
+}
+
+fn previous_positions(
+    positions: &[Position],
+    precision: Option<u8>,
+    min_points: usize,
+) -> Result<String, String> {
+    let mut formatted: Vec<String> = Vec::with_capacity(positions.len());
+    for position in positions {
+        let point = previous_position(position, precision);
+        if precision.is_none() || formatted.last() != Some(&point) {
+            formatted.push(point);
+        }
+    }
+    if precision.is_some() && formatted.len() < min_points.min(positions.len()) {
+        return Err(format!(
+            "Geometry collapses to {} distinct points when rounded to {} decimals",
+            formatted.len(),
+            precision.unwrap_or_default()
+        ));
+    }
+    Ok(formatted.join(", "))
+}
+
+#[test]
+fn wkt_is_the_same_as_before_for_every_fixture() {
+    let mut compared = 0;
+    for fixture in FIXTURES {
+        let features = parse_geojson_features(&common::fixture(fixture)).expect(fixture);
+        for geometry in features
+            .iter()
+            .filter_map(|feature| feature.geometry.as_ref())
+        {
+            for precision in PRECISIONS {
+                match (
+                    geometry_to_wkt_with_precision(geometry, precision),
+                    previous_wkt(geometry, precision),
+                ) {
+                    (Ok(wkt), Ok(previous)) => assert_eq!(wkt, previous, "{:?}", precision),
+                    (Err(error), Err(previous)) => {
+                        assert!(error.to_string().contains(&previous), "{}", error)
+                    }
+                    (wkt, previous) => panic!("{:?} but before {:?}", wkt, previous),
+                }
+                compared += 1;
+            }
+        }
+    }
+    assert!(compared > 50, "{} geometries compared", compared);
+}
+
+#[test]
+fn write_wkt_appends_and_leaves_the_buffer_as_it_was_on_an_error() {
+    let line = Geometry::new(Value::LineString(vec![vec![1.0, 2.0], vec![1.0001, 2.0]]));
+    let mut wkt = "SRID=4326;".to_string();
+    write_wkt(&line, None, &mut wkt).expect("write_wkt");
+    assert_eq!(wkt, "SRID=4326;LINESTRING(1 2, 1.0001 2)");
+    assert_eq!(
+        geometry_to_wkt(&line).expect("geometry_to_wkt"),
+        "LINESTRING(1 2, 1.0001 2)"
+    );
+
+    wkt.clear();
+    wkt.push_str("SRID=4326;");
+    write_wkt(&line, Some(2), &mut wkt).expect_err("a line collapsing to a point");
+    assert_eq!(wkt, "SRID=4326;");
+}
+
+#[test]
+fn a_position_of_fewer_than_2_numbers_is_an_error() {
+    let short = vec![1.0];
+    for value in [
+        Value::Point(short.clone()),
+        Value::MultiPoint(vec![vec![0.0, 0.0], short.clone()]),
+        Value::LineString(vec![vec![0.0, 0.0], short.clone()]),
+        Value::Polygon(vec![vec![
+            vec![0.0, 0.0],
+            vec![1.0, 0.0],
+            short.clone(),
+            vec![0.0, 0.0],
+        ]]),
+    ] {
+        for precision in [None, Some(6)] {
+            let error = geometry_to_wkt_with_precision(&Geometry::new(value.clone()), precision)
+                .expect_err("a position of one number");
+            assert!(
+                error.to_string().contains("fewer than 2 coordinates"),
+                "{}",
+                error
+            );
+        }
+    }
+}